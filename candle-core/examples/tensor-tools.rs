@@ -1,4 +1,4 @@
-use candle_core::quantized::{gguf_file, k_quants, QTensor};
+use candle_core::quantized::{gguf_file, imatrix::Imatrix, k_quants, GgmlDType, GgmlType, QTensor};
 use candle_core::{Device, Result, Tensor};
 use clap::{Parser, Subcommand, ValueEnum};
 use rayon::prelude::*;
@@ -11,31 +11,54 @@ enum QuantizationMode {
 }
 
 impl QuantizationMode {
-    fn quantize(
-        &self,
-        name: &str,
-        tensor: QTensor,
-        default: fn(&Tensor) -> Result<QTensor>,
-    ) -> Result<QTensor> {
+    /// The dtype to which a tensor gets converted, `None` if the tensor is kept as is.
+    fn target_dtype(&self, name: &str, rank: usize, default: GgmlDType) -> Option<GgmlDType> {
         match self {
             Self::Llama => {
                 // Same behavior as the llama.cpp quantization.
-                let should_quantize = name.ends_with(".weight") && tensor.rank() == 2;
-                if should_quantize {
-                    let tensor = tensor.dequantize(&Device::Cpu)?;
-                    if name == "output.weight" {
-                        QTensor::quantize::<k_quants::BlockQ6K>(&tensor)
-                    } else {
-                        default(&tensor)
-                    }
+                let should_quantize = name.ends_with(".weight") && rank == 2;
+                if !should_quantize {
+                    None
+                } else if name == "output.weight" {
+                    Some(GgmlDType::Q6K)
                 } else {
-                    Ok(tensor)
+                    Some(default)
                 }
             }
         }
     }
 }
 
+/// Quantizes `tensor`, using the importance of its columns to select the block scales when
+/// available.
+fn quantize(tensor: &Tensor, dtype: GgmlDType, importance: Option<&[f32]>) -> Result<QTensor> {
+    fn q<T: GgmlType + Send + Sync + 'static>(
+        tensor: &Tensor,
+        importance: Option<&[f32]>,
+    ) -> Result<QTensor> {
+        match importance {
+            None => QTensor::quantize::<T>(tensor),
+            Some(importance) => QTensor::quantize_with_importance::<T>(tensor, importance),
+        }
+    }
+    match dtype {
+        GgmlDType::Q4_0 => q::<k_quants::BlockQ4_0>(tensor, importance),
+        GgmlDType::Q4_1 => q::<k_quants::BlockQ4_1>(tensor, importance),
+        GgmlDType::Q5_0 => q::<k_quants::BlockQ5_0>(tensor, importance),
+        GgmlDType::Q5_1 => q::<k_quants::BlockQ5_1>(tensor, importance),
+        GgmlDType::Q8_0 => q::<k_quants::BlockQ8_0>(tensor, importance),
+        GgmlDType::Q8_1 => q::<k_quants::BlockQ8_1>(tensor, importance),
+        GgmlDType::Q2K => q::<k_quants::BlockQ2K>(tensor, importance),
+        GgmlDType::Q3K => q::<k_quants::BlockQ3K>(tensor, importance),
+        GgmlDType::Q4K => q::<k_quants::BlockQ4K>(tensor, importance),
+        GgmlDType::Q5K => q::<k_quants::BlockQ5K>(tensor, importance),
+        GgmlDType::Q6K => q::<k_quants::BlockQ6K>(tensor, importance),
+        GgmlDType::Q8K => q::<k_quants::BlockQ8K>(tensor, importance),
+        GgmlDType::F16 => q::<half::f16>(tensor, importance),
+        GgmlDType::F32 => q::<f32>(tensor, importance),
+    }
+}
+
 #[derive(ValueEnum, Debug, Clone)]
 enum Quantization {
     #[value(name = "q4_0")]
@@ -60,6 +83,27 @@ enum Quantization {
     F32,
 }
 
+impl Quantization {
+    fn dtype(&self) -> GgmlDType {
+        match self {
+            Self::Q4_0 => GgmlDType::Q4_0,
+            Self::Q4_1 => GgmlDType::Q4_1,
+            Self::Q5_0 => GgmlDType::Q5_0,
+            Self::Q5_1 => GgmlDType::Q5_1,
+            Self::Q8_0 => GgmlDType::Q8_0,
+            Self::Q8_1 => GgmlDType::Q8_1,
+            Self::Q2k => GgmlDType::Q2K,
+            Self::Q3k => GgmlDType::Q3K,
+            Self::Q4k => GgmlDType::Q4K,
+            Self::Q5k => GgmlDType::Q5K,
+            Self::Q6k => GgmlDType::Q6K,
+            Self::Q8k => GgmlDType::Q8K,
+            Self::F16 => GgmlDType::F16,
+            Self::F32 => GgmlDType::F32,
+        }
+    }
+}
+
 #[derive(ValueEnum, Debug, Clone)]
 enum Format {
     Safetensors,
@@ -114,6 +158,55 @@ enum Command {
         /// Which tensor to quantize.
         #[arg(long, value_enum, default_value_t = QuantizationMode::Llama)]
         mode: QuantizationMode,
+
+        /// An importance matrix, as produced by the imatrix command, used to minimize the
+        /// quantization error on the columns that matter most.
+        #[arg(long)]
+        imatrix: Option<std::path::PathBuf>,
+    },
+
+    /// Computes an importance matrix from the activations recorded while running a model on
+    /// some calibration data.
+    Imatrix {
+        /// The activation files, in safetensors or npz format. Each entry is named after the weight
+        /// of a linear layer and contains the inputs of this layer, the last dimension being the
+        /// number of columns of the weight.
+        activation_files: Vec<std::path::PathBuf>,
+
+        /// The output file, in safetensors format.
+        #[arg(long)]
+        out_file: std::path::PathBuf,
+
+        /// An existing importance matrix that the new activations get merged into.
+        #[arg(long)]
+        merge: Option<std::path::PathBuf>,
+    },
+
+    /// Computes an importance matrix by running a quantized llama model, in gguf format, on some
+    /// calibration text. The result can be passed to the quantize command.
+    Calibrate {
+        /// The model file, in gguf format.
+        model: std::path::PathBuf,
+
+        /// The tokenizer config in json format.
+        #[arg(long)]
+        tokenizer: std::path::PathBuf,
+
+        /// The calibration text.
+        #[arg(long)]
+        text_file: std::path::PathBuf,
+
+        /// The number of tokens in each of the chunks of text processed by the model.
+        #[arg(long, default_value_t = 512)]
+        ctx_size: usize,
+
+        /// The output file, in safetensors format.
+        #[arg(long)]
+        out_file: std::path::PathBuf,
+
+        /// An existing importance matrix that the new activations get merged into.
+        #[arg(long)]
+        merge: Option<std::path::PathBuf>,
     },
 }
 
@@ -223,52 +316,134 @@ fn run_quantize(
     out_file: std::path::PathBuf,
     q: Quantization,
     qmode: QuantizationMode,
+    imatrix: Option<std::path::PathBuf>,
 ) -> Result<()> {
     // Open the out file early so as to fail directly on missing directories etc.
-    let mut out_file = std::fs::File::create(out_file)?;
+    let out_file = std::fs::File::create(out_file)?;
+    let imatrix = imatrix.map(Imatrix::load).transpose()?;
     let mut in_ = std::fs::File::open(&in_file)?;
     let content = gguf_file::Content::read(&mut in_)?;
     println!("tensors: {}", content.tensor_infos.len());
 
-    let quantize_fn = match q {
-        Quantization::Q4_0 => QTensor::quantize::<k_quants::BlockQ4_0>,
-        Quantization::Q4_1 => QTensor::quantize::<k_quants::BlockQ4_1>,
-        Quantization::Q5_0 => QTensor::quantize::<k_quants::BlockQ5_0>,
-        Quantization::Q5_1 => QTensor::quantize::<k_quants::BlockQ5_1>,
-        Quantization::Q8_0 => QTensor::quantize::<k_quants::BlockQ8_0>,
-        Quantization::Q8_1 => QTensor::quantize::<k_quants::BlockQ8_1>,
-        Quantization::Q2k => QTensor::quantize::<k_quants::BlockQ2K>,
-        Quantization::Q3k => QTensor::quantize::<k_quants::BlockQ3K>,
-        Quantization::Q4k => QTensor::quantize::<k_quants::BlockQ4K>,
-        Quantization::Q5k => QTensor::quantize::<k_quants::BlockQ5K>,
-        Quantization::Q6k => QTensor::quantize::<k_quants::BlockQ6K>,
-        Quantization::Q8k => QTensor::quantize::<k_quants::BlockQ8K>,
-        Quantization::F16 => QTensor::quantize::<half::f16>,
-        Quantization::F32 => QTensor::quantize::<f32>,
+    // The tensors are written in a stable order, the output dtypes are known upfront so that the
+    // tensor data can be streamed to the output file.
+    let mut names = content.tensor_infos.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut writer = gguf_file::GgufWriter::new(std::io::BufWriter::new(out_file));
+    for (key, value) in content.metadata.iter() {
+        writer.add_metadata(key, value.clone())
+    }
+    let mut target_dtypes = Vec::with_capacity(names.len());
+    for name in names.iter() {
+        let info = &content.tensor_infos[*name];
+        let target_dtype = qmode.target_dtype(name, info.shape.rank(), q.dtype());
+        writer.add_tensor_info(
+            name,
+            target_dtype.unwrap_or(info.ggml_dtype),
+            info.shape.clone(),
+        )?;
+        target_dtypes.push(target_dtype);
+    }
+
+    // Only a batch of tensors, processed in parallel, is kept in memory at any given time.
+    let batch_size = rayon::current_num_threads();
+    let batches = names
+        .chunks(batch_size)
+        .zip(target_dtypes.chunks(batch_size));
+    let qtensors = batches.flat_map(|(names, target_dtypes)| {
+        let batch = names
+            .par_iter()
+            .zip(target_dtypes.par_iter())
+            .map(|(name, target_dtype)| {
+                let importance = imatrix.as_ref().and_then(|imatrix| imatrix.get(name));
+                match importance {
+                    None => println!("  quantizing {name}"),
+                    Some(_) => println!("  quantizing {name} using the importance matrix"),
+                }
+                let mut in_file = std::fs::File::open(&in_file)?;
+                let tensor = content.tensor(&mut in_file, name)?;
+                match target_dtype {
+                    None => Ok(tensor),
+                    Some(dtype) => {
+                        let tensor = tensor.dequantize(&Device::Cpu)?;
+                        quantize(&tensor, *dtype, importance.as_deref())
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        batch.into_iter()
+    });
+    writer.write_tensors(qtensors)?;
+    Ok(())
+}
+
+fn run_imatrix(
+    activation_files: &[std::path::PathBuf],
+    out_file: &std::path::Path,
+    merge: Option<std::path::PathBuf>,
+) -> Result<()> {
+    let imatrix = match merge {
+        None => Imatrix::new(),
+        Some(merge) => Imatrix::load(merge)?,
+    };
+    for file in activation_files.iter() {
+        let activations = match Format::infer(file) {
+            Some(Format::Safetensors) => candle_core::safetensors::load(file, &Device::Cpu)?
+                .into_iter()
+                .collect(),
+            Some(Format::Npz) => Tensor::read_npz(file)?,
+            _ => candle_core::bail!("{file:?}: activations should be in safetensors or npz format"),
+        };
+        for (name, xs) in activations.iter() {
+            imatrix.record(name, xs)?
+        }
+    }
+    for name in imatrix.names() {
+        println!("{name}: {} rows", imatrix.count(&name))
+    }
+    imatrix.save(out_file)
+}
+
+fn run_calibrate(
+    model: &std::path::Path,
+    tokenizer: &std::path::Path,
+    text_file: &std::path::Path,
+    ctx_size: usize,
+    out_file: &std::path::Path,
+    merge: Option<std::path::PathBuf>,
+) -> anyhow::Result<()> {
+    use candle_transformers::models::quantized_llama::{ModelWeights, MAX_SEQ_LEN};
+    if ctx_size == 0 || ctx_size > MAX_SEQ_LEN {
+        anyhow::bail!("the context size should be between 1 and {MAX_SEQ_LEN}, got {ctx_size}")
+    }
+    let imatrix = match merge {
+        None => Imatrix::new(),
+        Some(merge) => Imatrix::load(merge)?,
     };
+    let imatrix = std::sync::Arc::new(imatrix);
+    let mut file = std::fs::File::open(model)?;
+    let content = gguf_file::Content::read(&mut file)?;
+    let mut model = ModelWeights::from_gguf(content, &mut file)?;
+    model.record_imatrix(&imatrix);
 
-    let qtensors = content
-        .tensor_infos
-        .par_iter()
-        .map(|(name, _)| {
-            println!("  quantizing {name}");
-            let mut in_file = std::fs::File::open(&in_file)?;
-            let tensor = content.tensor(&mut in_file, name)?;
-            let tensor = qmode.quantize(name, tensor, quantize_fn)?;
-            Ok((name, tensor))
-        })
-        .collect::<Result<Vec<_>>>()?;
-    let qtensors = qtensors
-        .iter()
-        .map(|(k, v)| (k.as_str(), v))
-        .collect::<Vec<_>>();
-
-    let metadata = content
-        .metadata
-        .iter()
-        .map(|(k, v)| (k.as_str(), v))
-        .collect::<Vec<_>>();
-    gguf_file::write(&mut out_file, metadata.as_slice(), &qtensors)?;
+    let tokenizer = tokenizers::Tokenizer::from_file(tokenizer).map_err(anyhow::Error::msg)?;
+    let text = std::fs::read_to_string(text_file)?;
+    let tokens = tokenizer
+        .encode(text, true)
+        .map_err(anyhow::Error::msg)?
+        .get_ids()
+        .to_vec();
+    let n_chunks = tokens.len().div_ceil(ctx_size);
+    for (i, chunk) in tokens.chunks(ctx_size).enumerate() {
+        println!("  chunk {}/{n_chunks}: {} tokens", i + 1, chunk.len());
+        // Each chunk starts at position 0 so that the kv cache gets reset.
+        let input = Tensor::new(chunk, &Device::Cpu)?.unsqueeze(0)?;
+        model.forward(&input, 0)?;
+    }
+    for name in imatrix.names() {
+        println!("{name}: {} rows", imatrix.count(&name))
+    }
+    imatrix.save(out_file)?;
     Ok(())
 }
 
@@ -293,7 +468,21 @@ fn main() -> anyhow::Result<()> {
             out_file,
             quantization,
             mode,
-        } => run_quantize(in_file, out_file, quantization, mode)?,
+            imatrix,
+        } => run_quantize(in_file, out_file, quantization, mode, imatrix)?,
+        Command::Imatrix {
+            activation_files,
+            out_file,
+            merge,
+        } => run_imatrix(&activation_files, &out_file, merge)?,
+        Command::Calibrate {
+            model,
+            tokenizer,
+            text_file,
+            ctx_size,
+            out_file,
+            merge,
+        } => run_calibrate(&model, &tokenizer, &text_file, ctx_size, &out_file, merge)?,
     }
     Ok(())
 }
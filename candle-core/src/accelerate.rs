@@ -161,6 +161,75 @@ mod ffi {
             _: c_long,
             _: c_ulong,
         );
+        #[link_name = "dgetrf_"]
+        pub fn dgetrf_ffi(
+            m: *const c_int,
+            n: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            ipiv: *mut c_int,
+            info: *mut c_int,
+        );
+        #[link_name = "dpotrf_"]
+        pub fn dpotrf_ffi(
+            uplo: *const c_char,
+            n: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            info: *mut c_int,
+        );
+        #[link_name = "dgeqrf_"]
+        pub fn dgeqrf_ffi(
+            m: *const c_int,
+            n: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            tau: *mut c_double,
+            work: *mut c_double,
+            lwork: *const c_int,
+            info: *mut c_int,
+        );
+        #[link_name = "dorgqr_"]
+        pub fn dorgqr_ffi(
+            m: *const c_int,
+            n: *const c_int,
+            k: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            tau: *const c_double,
+            work: *mut c_double,
+            lwork: *const c_int,
+            info: *mut c_int,
+        );
+        #[link_name = "dsyev_"]
+        pub fn dsyev_ffi(
+            jobz: *const c_char,
+            uplo: *const c_char,
+            n: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            w: *mut c_double,
+            work: *mut c_double,
+            lwork: *const c_int,
+            info: *mut c_int,
+        );
+        #[link_name = "dgesvd_"]
+        pub fn dgesvd_ffi(
+            jobu: *const c_char,
+            jobvt: *const c_char,
+            m: *const c_int,
+            n: *const c_int,
+            a: *mut c_double,
+            lda: *const c_int,
+            s: *mut c_double,
+            u: *mut c_double,
+            ldu: *const c_int,
+            vt: *mut c_double,
+            ldvt: *const c_int,
+            work: *mut c_double,
+            lwork: *const c_int,
+            info: *mut c_int,
+        );
     }
 }
 
@@ -232,6 +301,138 @@ pub unsafe fn dgemm(
     )
 }
 
+// The LAPACK routines used by the linalg module, these return the LAPACK `info` value.
+
+#[inline]
+pub unsafe fn dgetrf(m: i32, n: i32, a: &mut [f64], lda: i32, ipiv: &mut [i32]) -> i32 {
+    let mut info = 0;
+    ffi::dgetrf_ffi(&m, &n, a.as_mut_ptr(), &lda, ipiv.as_mut_ptr(), &mut info);
+    info
+}
+
+#[inline]
+pub unsafe fn dpotrf(uplo: u8, n: i32, a: &mut [f64], lda: i32) -> i32 {
+    let mut info = 0;
+    ffi::dpotrf_ffi(&(uplo as c_char), &n, a.as_mut_ptr(), &lda, &mut info);
+    info
+}
+
+#[allow(clippy::too_many_arguments)]
+#[inline]
+pub unsafe fn dgeqrf(
+    m: i32,
+    n: i32,
+    a: &mut [f64],
+    lda: i32,
+    tau: &mut [f64],
+    work: &mut [f64],
+    lwork: i32,
+) -> i32 {
+    let mut info = 0;
+    ffi::dgeqrf_ffi(
+        &m,
+        &n,
+        a.as_mut_ptr(),
+        &lda,
+        tau.as_mut_ptr(),
+        work.as_mut_ptr(),
+        &lwork,
+        &mut info,
+    );
+    info
+}
+
+#[allow(clippy::too_many_arguments)]
+#[inline]
+pub unsafe fn dorgqr(
+    m: i32,
+    n: i32,
+    k: i32,
+    a: &mut [f64],
+    lda: i32,
+    tau: &[f64],
+    work: &mut [f64],
+    lwork: i32,
+) -> i32 {
+    let mut info = 0;
+    ffi::dorgqr_ffi(
+        &m,
+        &n,
+        &k,
+        a.as_mut_ptr(),
+        &lda,
+        tau.as_ptr(),
+        work.as_mut_ptr(),
+        &lwork,
+        &mut info,
+    );
+    info
+}
+
+#[allow(clippy::too_many_arguments)]
+#[inline]
+pub unsafe fn dsyev(
+    jobz: u8,
+    uplo: u8,
+    n: i32,
+    a: &mut [f64],
+    lda: i32,
+    w: &mut [f64],
+    work: &mut [f64],
+    lwork: i32,
+) -> i32 {
+    let mut info = 0;
+    ffi::dsyev_ffi(
+        &(jobz as c_char),
+        &(uplo as c_char),
+        &n,
+        a.as_mut_ptr(),
+        &lda,
+        w.as_mut_ptr(),
+        work.as_mut_ptr(),
+        &lwork,
+        &mut info,
+    );
+    info
+}
+
+#[allow(clippy::too_many_arguments)]
+#[inline]
+pub unsafe fn dgesvd(
+    jobu: u8,
+    jobvt: u8,
+    m: i32,
+    n: i32,
+    a: &mut [f64],
+    lda: i32,
+    s: &mut [f64],
+    u: &mut [f64],
+    ldu: i32,
+    vt: &mut [f64],
+    ldvt: i32,
+    work: &mut [f64],
+    lwork: i32,
+) -> i32 {
+    let mut info = 0;
+    ffi::dgesvd_ffi(
+        &(jobu as c_char),
+        &(jobvt as c_char),
+        &m,
+        &n,
+        a.as_mut_ptr(),
+        &lda,
+        s.as_mut_ptr(),
+        u.as_mut_ptr(),
+        &ldu,
+        vt.as_mut_ptr(),
+        &ldvt,
+        work.as_mut_ptr(),
+        &lwork,
+        &mut info,
+    );
+    info
+}
+
 #[inline]
 pub fn vs_exp(a: &[f32], y: &mut [f32]) {
     let a_len = a.len();
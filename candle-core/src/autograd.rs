@@ -0,0 +1,188 @@
+//! Functional interface to the automatic differentiation.
+//!
+//! Rather than computing the gradients for all the variables as done by
+//! [`Tensor::backward`], the functions in this module return the gradients for the requested
+//! tensors only. These tensors do not have to be variables, any tensor from the op graph can be
+//! used, e.g. an intermediary activation.
+//!
+//! [`jvp`] and [`vjp`] take a function rather than an existing op graph and respectively use
+//! forward mode and reverse mode differentiation.
+use crate::{Result, Tensor, TensorId};
+use std::collections::HashSet;
+
+// Checks that the tangents (or cotangents) have the same shapes as the tensors they apply to.
+fn check_tangents(ts: &[&Tensor], tangents: &[&Tensor], op: &'static str) -> Result<()> {
+    if ts.len() != tangents.len() {
+        crate::bail!(
+            "{op}: got {} tangents for {} tensors",
+            tangents.len(),
+            ts.len()
+        )
+    }
+    for (t, tangent) in ts.iter().zip(tangents.iter()) {
+        if t.shape() != tangent.shape() {
+            Err(crate::Error::ShapeMismatchBinaryOp {
+                lhs: t.shape().clone(),
+                rhs: tangent.shape().clone(),
+                op,
+            }
+            .bt())?
+        }
+    }
+    Ok(())
+}
+
+fn grad_impl(
+    outputs: &[&Tensor],
+    inputs: &[&Tensor],
+    grad_outputs: Option<&[&Tensor]>,
+    create_graph: bool,
+) -> Result<Vec<Tensor>> {
+    let roots = match grad_outputs {
+        None => outputs
+            .iter()
+            .map(|o| Ok((*o, o.ones_like()?.contiguous()?)))
+            .collect::<Result<Vec<_>>>()?,
+        Some(grad_outputs) => {
+            check_tangents(outputs, grad_outputs, "grad")?;
+            outputs
+                .iter()
+                .zip(grad_outputs.iter())
+                .map(|(o, g)| (*o, (*g).clone()))
+                .collect::<Vec<_>>()
+        }
+    };
+    let targets = inputs.iter().map(|t| t.id()).collect::<HashSet<TensorId>>();
+    let grads = Tensor::backward_impl(&roots, Some(&targets), create_graph)?;
+    inputs
+        .iter()
+        .map(|t| match grads.get(t) {
+            Some(grad) => Ok(grad.clone()),
+            None => t.zeros_like(),
+        })
+        .collect()
+}
+
+/// Computes the gradients of the sum of `outputs` with respect to each of the `inputs`.
+///
+/// `grad_outputs` are the gradients with respect to each of the outputs, this defaults to
+/// tensors filled with ones. The returned gradients are in the same order as `inputs`, inputs
+/// that do not contribute to the outputs get a zero gradient. The returned gradients are
+/// detached from the op graph, see [`grad_with_graph`] for differentiable gradients.
+///
+/// ```rust
+/// use candle_core::{autograd, Device, Tensor, Var};
+/// let x = Var::new(&[1f32, 2., 3.], &Device::Cpu)?;
+/// let h = (x.as_tensor() * 2.)?;
+/// let y = h.sqr()?.sum_all()?;
+/// let grads = autograd::grad(&[&y], &[&h], None)?;
+/// assert_eq!(grads[0].to_vec1::<f32>()?, [4., 8., 12.]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn grad(
+    outputs: &[&Tensor],
+    inputs: &[&Tensor],
+    grad_outputs: Option<&[&Tensor]>,
+) -> Result<Vec<Tensor>> {
+    grad_impl(outputs, inputs, grad_outputs, false)
+}
+
+/// Same as [`grad`] but the returned gradients keep track of the op graph so that they can be
+/// differentiated again, e.g. for gradient penalties or Hessian-vector products.
+///
+/// ```rust
+/// use candle_core::{autograd, Device, Tensor, Var};
+/// let x = Var::new(&[1f32, 2.], &Device::Cpu)?;
+/// let x = x.as_tensor();
+/// let y = x.powf(3.)?.sum_all()?;
+/// let dy_dx = &autograd::grad_with_graph(&[&y], &[x], None)?[0];
+/// // Hessian-vector product, the hessian being diag(6.x).
+/// let v = Tensor::new(&[1f32, -1.], &Device::Cpu)?;
+/// let hvp = &autograd::grad(&[&(dy_dx * &v)?.sum_all()?], &[x], None)?[0];
+/// assert_eq!(hvp.to_vec1::<f32>()?, [6., -12.]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn grad_with_graph(
+    outputs: &[&Tensor],
+    inputs: &[&Tensor],
+    grad_outputs: Option<&[&Tensor]>,
+) -> Result<Vec<Tensor>> {
+    grad_impl(outputs, inputs, grad_outputs, true)
+}
+
+/// Evaluates `f` on `primals` and computes the Jacobian-vector product of `f` with `tangents`
+/// using forward mode differentiation. This requires a single pass through `f` whatever the
+/// number of outputs.
+///
+/// `f` gets passed copies of the primals, these are the tensors that it should be using for the
+/// tangents to be propagated. This returns the outputs of `f` and their tangents, both detached
+/// from the op graph.
+///
+/// ```rust
+/// use candle_core::{autograd, Device, Tensor};
+/// let x = Tensor::new(&[1f32, 2., 3.], &Device::Cpu)?;
+/// let v = Tensor::new(&[1f32, 0., -1.], &Device::Cpu)?;
+/// let (ys, ts) = autograd::jvp(|xs| Ok(vec![xs[0].sqr()?]), &[&x], &[&v])?;
+/// assert_eq!(ys[0].to_vec1::<f32>()?, [1., 4., 9.]);
+/// // The jacobian of x^2 is diag(2.x).
+/// assert_eq!(ts[0].to_vec1::<f32>()?, [2., 0., -6.]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn jvp<F>(f: F, primals: &[&Tensor], tangents: &[&Tensor]) -> Result<(Vec<Tensor>, Vec<Tensor>)>
+where
+    F: FnOnce(&[Tensor]) -> Result<Vec<Tensor>>,
+{
+    check_tangents(primals, tangents, "jvp")?;
+    let inputs = primals
+        .iter()
+        .map(|p| p.make_var())
+        .collect::<Result<Vec<_>>>()?;
+    let outputs = f(&inputs)?;
+    let inputs = inputs
+        .iter()
+        .zip(tangents.iter())
+        .map(|(i, t)| (i, (*t).clone()))
+        .collect::<Vec<_>>();
+    let tangents = Tensor::jvp_impl(&outputs.iter().collect::<Vec<_>>(), &inputs)?;
+    let outputs = outputs.iter().map(|o| o.detach()).collect::<Result<_>>()?;
+    let tangents = tangents.iter().map(|t| t.detach()).collect::<Result<_>>()?;
+    Ok((outputs, tangents))
+}
+
+/// Evaluates `f` on `primals` and computes the vector-Jacobian product of `f` with
+/// `cotangents`, i.e. the gradients of the outputs weighted by the cotangents with respect to
+/// each of the primals, using reverse mode differentiation.
+///
+/// As for [`jvp`], `f` gets passed copies of the primals. This returns the outputs of `f` and
+/// the gradients of the primals, both detached from the op graph.
+///
+/// ```rust
+/// use candle_core::{autograd, Device, Tensor};
+/// let x = Tensor::new(&[1f32, 2., 3.], &Device::Cpu)?;
+/// let v = Tensor::new(&[1f32, 0., -1.], &Device::Cpu)?;
+/// let (ys, gs) = autograd::vjp(|xs| Ok(vec![xs[0].sqr()?]), &[&x], &[&v])?;
+/// assert_eq!(ys[0].to_vec1::<f32>()?, [1., 4., 9.]);
+/// assert_eq!(gs[0].to_vec1::<f32>()?, [2., 0., -6.]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn vjp<F>(
+    f: F,
+    primals: &[&Tensor],
+    cotangents: &[&Tensor],
+) -> Result<(Vec<Tensor>, Vec<Tensor>)>
+where
+    F: FnOnce(&[Tensor]) -> Result<Vec<Tensor>>,
+{
+    let inputs = primals
+        .iter()
+        .map(|p| p.make_var())
+        .collect::<Result<Vec<_>>>()?;
+    let outputs = f(&inputs)?;
+    let grads = grad(
+        &outputs.iter().collect::<Vec<_>>(),
+        &inputs.iter().collect::<Vec<_>>(),
+        Some(cotangents),
+    )?;
+    let outputs = outputs.iter().map(|o| o.detach()).collect::<Result<_>>()?;
+    Ok((outputs, grads))
+}
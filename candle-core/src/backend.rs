@@ -1,5 +1,5 @@
-use crate::op::{BinaryOpT, CmpOp, ReduceOp, UnaryOpT};
-use crate::{CpuStorage, DType, Layout, Result, Shape};
+use crate::op::{BinaryOpT, CmpOp, CumulativeOp, ReduceOp, UnaryOpT};
+use crate::{CpuStorage, DType, Layout, MemoryStats, Result, Shape};
 
 pub trait BackendStorage: Sized {
     type Device: BackendDevice;
@@ -15,12 +15,30 @@ pub trait BackendStorage: Sized {
 
     fn affine(&self, _: &Layout, _: f64, _: f64) -> Result<Self>;
 
+    /// Same as `affine` but the result is written back to `self` using the same layout.
+    fn affine_inplace(&mut self, _: &Layout, _: f64, _: f64) -> Result<()>;
+
     fn powf(&self, _: &Layout, _: f64) -> Result<Self>;
 
     fn elu(&self, _: &Layout, _: f64) -> Result<Self>;
 
     fn reduce_op(&self, _: ReduceOp, _: &Layout, _: &[usize]) -> Result<Self>;
 
+    fn cumulative_op(&self, _: CumulativeOp, _: &Layout, _: usize) -> Result<Self>;
+
+    /// Returns the u32 indexes that sort the elements along the given dimension.
+    fn arg_sort(&self, _: &Layout, _: usize, _: bool) -> Result<Self>;
+
+    /// Discrete Fourier transform along the given dimension of a complex storage. The inverse
+    /// transform is normalized by the size of the dimension.
+    fn fft(&self, _: &Layout, _: usize, _: bool) -> Result<Self>;
+
+    /// Converts complex values to pairs of floats holding the real and imaginary parts.
+    fn view_as_real(&self, _: &Layout) -> Result<Self>;
+
+    /// Converts pairs of floats to complex values, the last dimension of the layout has to be 2.
+    fn view_as_complex(&self, _: &Layout) -> Result<Self>;
+
     fn cmp(&self, _: CmpOp, _: &Self, _: &Layout, _: &Layout) -> Result<Self>;
 
     fn to_dtype(&self, _: &Layout, _: DType) -> Result<Self>;
@@ -29,6 +47,10 @@ pub trait BackendStorage: Sized {
 
     fn binary_impl<B: BinaryOpT>(&self, _: &Self, _: &Layout, _: &Layout) -> Result<Self>;
 
+    /// Same as `binary_impl` but the result is written back to `self` using the lhs layout.
+    fn binary_impl_inplace<B: BinaryOpT>(&mut self, _: &Self, _: &Layout, _: &Layout)
+        -> Result<()>;
+
     fn where_cond(&self, _: &Layout, _: &Self, _: &Layout, _: &Self, _: &Layout) -> Result<Self>;
 
     fn conv1d(
@@ -39,6 +61,14 @@ pub trait BackendStorage: Sized {
         _params: &crate::conv::ParamsConv1D,
     ) -> Result<Self>;
 
+    fn conv_transpose1d(
+        &self,
+        _l: &Layout,
+        _kernel: &Self,
+        _kernel_l: &Layout,
+        _params: &crate::conv::ParamsConvTranspose1D,
+    ) -> Result<Self>;
+
     fn conv2d(
         &self,
         _l: &Layout,
@@ -55,8 +85,28 @@ pub trait BackendStorage: Sized {
         _params: &crate::conv::ParamsConvTranspose2D,
     ) -> Result<Self>;
 
+    fn conv3d(
+        &self,
+        _l: &Layout,
+        _kernel: &Self,
+        _kernel_l: &Layout,
+        _params: &crate::conv::ParamsConv3D,
+    ) -> Result<Self>;
+
     fn avg_pool2d(&self, _: &Layout, _: (usize, usize), _: (usize, usize)) -> Result<Self>;
     fn max_pool2d(&self, _: &Layout, _: (usize, usize), _: (usize, usize)) -> Result<Self>;
+    fn avg_pool3d(
+        &self,
+        _: &Layout,
+        _: (usize, usize, usize),
+        _: (usize, usize, usize),
+    ) -> Result<Self>;
+    fn max_pool3d(
+        &self,
+        _: &Layout,
+        _: (usize, usize, usize),
+        _: (usize, usize, usize),
+    ) -> Result<Self>;
     fn upsample_nearest1d(&self, _: &Layout, _: usize) -> Result<Self>;
     fn upsample_nearest2d(&self, _: &Layout, _: usize, _: usize) -> Result<Self>;
 
@@ -81,6 +131,17 @@ pub trait BackendStorage: Sized {
         _: usize,
     ) -> Result<Self>;
 
+    /// Same as `index_add` but the source values are added to `self` directly.
+    fn index_add_inplace(
+        &mut self,
+        _: &Layout,
+        _: &Self,
+        _: &Layout,
+        _: &Self,
+        _: &Layout,
+        _: usize,
+    ) -> Result<()>;
+
     fn matmul(
         &self,
         _: &Self,
@@ -90,6 +151,10 @@ pub trait BackendStorage: Sized {
     ) -> Result<Self>;
 
     fn copy_strided_src(&self, _: &mut Self, _: usize, _: &Layout) -> Result<()>;
+
+    /// Copies `self` using the src layout to `dst` using the dst layout, both layouts must have
+    /// the same shape and the dst layout cannot have overlapping elements.
+    fn copy_strided(&self, _: &mut Self, _: &Layout, _: &Layout) -> Result<()>;
 }
 
 pub trait BackendDevice: Sized + std::fmt::Debug + Clone {
@@ -111,4 +176,15 @@ pub trait BackendDevice: Sized + std::fmt::Debug + Clone {
     fn rand_uniform(&self, _: &Shape, _: DType, _: f64, _: f64) -> Result<Self::Storage>;
 
     fn rand_normal(&self, _: &Shape, _: DType, _: f64, _: f64) -> Result<Self::Storage>;
+
+    /// Sets the seed of the default random number generator of the device.
+    fn set_seed(&self, _: u64) -> Result<()>;
+
+    fn memory_stats(&self) -> Result<MemoryStats>;
+
+    /// Releases the buffers that are cached for reuse.
+    fn empty_cache(&self) -> Result<()>;
+
+    /// Sets the maximum number of bytes that can be cached, 0 disables the caching.
+    fn set_cache_limit(&self, _: usize) -> Result<()>;
 }
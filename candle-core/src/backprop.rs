@@ -1,10 +1,15 @@
-use crate::op::{BinaryOp, Op, ReduceOp, UnaryOp};
+use crate::op::{BinaryOp, CumulativeOp, Op, ReduceOp, UnaryOp};
+use crate::sparse::CooTensor;
 use crate::{Error, Result, Tensor, TensorId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // arg has been reduced to node via reduce_dims, expand it back to arg.
 // This has to handle keepdims.
-fn broadcast_back(arg: &Tensor, node: &Tensor, reduced_dims: &[usize]) -> Result<Tensor> {
+pub(crate) fn broadcast_back(
+    arg: &Tensor,
+    node: &Tensor,
+    reduced_dims: &[usize],
+) -> Result<Tensor> {
     if arg.rank() == node.rank() {
         // keepdim = true
         node.broadcast_as(arg.shape())
@@ -15,26 +20,207 @@ fn broadcast_back(arg: &Tensor, node: &Tensor, reduced_dims: &[usize]) -> Result
     }
 }
 
+// Cumulative sum starting from the end of dimension dim, this is the transpose of cumsum.
+fn reverse_cumsum(t: &Tensor, dim: usize) -> Result<Tensor> {
+    let total = t.sum_keepdim(dim)?.broadcast_as(t.shape())?;
+    (total - t.cumsum(dim)?)? + t
+}
+
+pub(crate) fn gelu_derivative(arg: &Tensor) -> Result<Tensor> {
+    // gelu(x) = 0.5 x (1 + tanh(u)) with u = sqrt(2/pi) (x + 0.044715 x^3)
+    let sqrt_two_over_pi = (2. / std::f64::consts::PI).sqrt();
+    let x_sqr = arg.sqr()?;
+    let u = ((arg * (x_sqr.affine(0.044715, 1.)?))? * sqrt_two_over_pi)?;
+    let tanh = u.tanh()?;
+    let du = (x_sqr.affine(3. * 0.044715, 1.)? * sqrt_two_over_pi)?;
+    let dtanh = (tanh.sqr()?.affine(-1., 1.)? * du)?;
+    ((tanh + 1.)? + (arg * dtanh)?)? * 0.5
+}
+
+pub(crate) fn erf_derivative(arg: &Tensor) -> Result<Tensor> {
+    // d/dx erf(x) = 2/sqrt(pi) exp(-x^2)
+    arg.sqr()?.neg()?.exp()? * std::f64::consts::FRAC_2_SQRT_PI
+}
+
+// The partial derivatives of `node = lhs op rhs` with respect to `lhs` and `rhs` for the binary
+// ops that are not handled directly.
+pub(crate) fn binary_partials(
+    op: BinaryOp,
+    lhs: &Tensor,
+    rhs: &Tensor,
+    node: &Tensor,
+) -> Result<(Tensor, Tensor)> {
+    match op {
+        BinaryOp::Pow => {
+            let d_lhs = lhs.pow(&(rhs - 1.)?)?.mul(rhs)?;
+            let d_rhs = node.mul(&lhs.log()?)?;
+            Ok((d_lhs, d_rhs))
+        }
+        BinaryOp::Atan2 => {
+            let norm = (lhs.sqr()? + rhs.sqr()?)?;
+            Ok((rhs.div(&norm)?, lhs.div(&norm)?.neg()?))
+        }
+        BinaryOp::Fmod => {
+            // fmod(x, y) = x - trunc(x / y) y
+            let trunc = ((lhs - node)? / rhs)?.round()?;
+            Ok((lhs.ones_like()?, trunc.neg()?))
+        }
+        _ => crate::bail!("no partial derivatives for {op:?}"),
+    }
+}
+
+pub(crate) fn elu_derivative(arg: &Tensor, node: &Tensor, alpha: f64) -> Result<Tensor> {
+    // d/dx elu(x) = 1 for x >= 0, alpha.exp(x) = elu(x) + alpha otherwise.
+    let positive = arg.ge(&arg.zeros_like()?)?;
+    positive.where_cond(&arg.ones_like()?, &(node + alpha)?)
+}
+
+// The source index used by the nearest neighbor upsampling for each destination index, this
+// has to match the cpu and cuda kernels.
+fn upsample_nearest_idxs(src_sz: usize, dst_sz: usize, device: &crate::Device) -> Result<Tensor> {
+    let scale = src_sz as f64 / dst_sz as f64;
+    let idxs = (0..dst_sz)
+        .map(|idx| usize::min(src_sz - 1, (idx as f64 * scale) as usize) as u32)
+        .collect::<Vec<_>>();
+    Tensor::new(idxs, device)
+}
+
+// For each output position of a 2d pooling op (in row-major order), the flattened indexes of
+// the input elements covered by its window.
+pub(crate) fn pool2d_window_idxs(
+    arg: &Tensor,
+    kernel_size: (usize, usize),
+    stride: (usize, usize),
+) -> Result<Tensor> {
+    let (_n, _c, h, w) = arg.dims4()?;
+    let h_out = (h - kernel_size.0) / stride.0 + 1;
+    let w_out = (w - kernel_size.1) / stride.1 + 1;
+    let mut idxs = Vec::with_capacity(h_out * w_out * kernel_size.0 * kernel_size.1);
+    for h_idx in 0..h_out {
+        for w_idx in 0..w_out {
+            for k_h in 0..kernel_size.0 {
+                for k_w in 0..kernel_size.1 {
+                    let src_h = h_idx * stride.0 + k_h;
+                    let src_w = w_idx * stride.1 + k_w;
+                    idxs.push((src_h * w + src_w) as u32)
+                }
+            }
+        }
+    }
+    Tensor::new(idxs, arg.device())
+}
+
+// The elements of `arg` within each window, with shape (n, c, h_out, w_out, k_h * k_w).
+pub(crate) fn pool2d_windows(
+    arg: &Tensor,
+    idxs: &Tensor,
+    h_out: usize,
+    w_out: usize,
+) -> Result<Tensor> {
+    let (n, c, _h, _w) = arg.dims4()?;
+    arg.flatten_from(2)?
+        .contiguous()?
+        .index_select(idxs, 2)?
+        .reshape((n, c, h_out, w_out, ()))
+}
+
+// A mask of shape (n, c, h_out, w_out, k_h * k_w) where a 1 means that the element is the
+// maximum of its window.
+pub(crate) fn max_pool2d_mask(arg: &Tensor, node: &Tensor, idxs: &Tensor) -> Result<Tensor> {
+    let (_n, _c, h_out, w_out) = node.dims4()?;
+    let windows = pool2d_windows(arg, idxs, h_out, w_out)?;
+    let node = node.unsqueeze(4)?.broadcast_as(windows.shape())?;
+    windows.eq(&node)?.to_dtype(arg.dtype())
+}
+
+// For each element of the output of cummax, the index along `dim` of the input element where
+// the running maximum was last reached.
+pub(crate) fn cummax_idxs(arg: &Tensor, node: &Tensor, dim: usize) -> Result<Tensor> {
+    let dims = arg.dims();
+    let mut pos_dims = vec![1; dims.len()];
+    pos_dims[dim] = dims[dim];
+    let pos = Tensor::arange(0u32, dims[dim] as u32, arg.device())?
+        .reshape(pos_dims)?
+        .broadcast_as(dims)?;
+    node.eq(arg)?
+        .where_cond(&pos, &pos.zeros_like()?)?
+        .cummax(dim)
+}
+
+// The partial derivatives of `node = arg.cumprod(dim)`, these are computed without dividing by
+// `arg` so that zero elements are supported. Returns the exclusive cumulative product `e` where
+// `e_j = x_0 ... x_{j-1}`, and `m` which has an extra dimension inserted at `dim` and where
+// `m[j, i] = x_{j+1} ... x_i` if `i >= j` and 0 otherwise, so that `d node_i / d x_j = e_j m[j, i]`.
+// `m` uses n^2 elements per lane where n is the size of `dim`.
+pub(crate) fn cumprod_partials(
+    arg: &Tensor,
+    node: &Tensor,
+    dim: usize,
+) -> Result<(Tensor, Tensor)> {
+    let n = arg.dim(dim)?;
+    let mut one_dims = arg.dims().to_vec();
+    one_dims[dim] = 1;
+    let ones = Tensor::ones(one_dims, arg.dtype(), arg.device())?;
+    let excl = Tensor::cat(&[&ones, &node.narrow(dim, 0, n.saturating_sub(1))?], dim)?
+        .narrow(dim, 0, n)?;
+
+    let mut dims = arg.dims().to_vec();
+    dims.insert(dim, n);
+    let pos = Tensor::arange(0u32, n as u32, arg.device())?;
+    let mut j_dims = vec![1; dims.len()];
+    j_dims[dim] = n;
+    let j = pos.reshape(j_dims)?.broadcast_as(dims.as_slice())?;
+    let mut i_dims = vec![1; dims.len()];
+    i_dims[dim + 1] = n;
+    let i = pos.reshape(i_dims)?.broadcast_as(dims.as_slice())?;
+    let xs = arg.unsqueeze(dim)?.broadcast_as(dims.as_slice())?;
+    let m = i
+        .gt(&j)?
+        .where_cond(&xs, &xs.ones_like()?)?
+        .cumprod(dim + 1)?;
+    let m = i.ge(&j)?.where_cond(&m, &m.zeros_like()?)?;
+    Ok((excl, m))
+}
+
+// Accumulates the per-window gradients of shape (n, c, h_out, w_out, k_h * k_w) back onto the
+// elements of `arg`.
+fn pool2d_scatter(arg: &Tensor, idxs: &Tensor, grad: &Tensor) -> Result<Tensor> {
+    let (n, c, h, w) = arg.dims4()?;
+    let grad = grad.flatten_from(2)?.contiguous()?;
+    Tensor::zeros((n, c, h * w), grad.dtype(), grad.device())?
+        .index_add(idxs, &grad, 2)?
+        .reshape((n, c, h, w))
+}
+
 impl Tensor {
     /// Return all the nodes that lead to this value in a topologically sorted vec, the first
     /// elements having dependencies on the latter ones, e.g. the first element if any is the
     /// argument.
     /// This assumes that the op graph is a DAG.
-    fn sorted_nodes(&self) -> Vec<&Tensor> {
+    /// When `targets` is set, only the nodes leading to these tensors are returned rather than the
+    /// ones leading to variables.
+    pub(crate) fn sorted_nodes<'a>(
+        roots: &[&'a Tensor],
+        targets: Option<&HashSet<TensorId>>,
+    ) -> Vec<&'a Tensor> {
         // The vec of sorted nodes is passed as an owned value rather than a mutable reference
         // to get around some lifetime limitations.
         fn walk<'a>(
             node: &'a Tensor,
             nodes: Vec<&'a Tensor>,
             already_seen: &mut HashMap<TensorId, bool>,
+            targets: Option<&HashSet<TensorId>>,
         ) -> (bool, Vec<&'a Tensor>) {
             if let Some(&tg) = already_seen.get(&node.id()) {
                 return (tg, nodes);
             }
-            let mut track_grad = false;
+            let mut track_grad = match targets {
+                None => false,
+                Some(targets) => targets.contains(&node.id()),
+            };
             let mut nodes = if node.is_variable() {
                 // Do not call recursively on the "leaf" nodes.
-                track_grad = true;
+                track_grad |= targets.is_none();
                 nodes
             } else if let Some(op) = node.op() {
                 match op {
@@ -42,11 +228,11 @@ impl Tensor {
                     | Op::ScatterAdd(t1, t2, t3, _)
                     | Op::CustomOp3(t1, t2, t3, _)
                     | Op::WhereCond(t1, t2, t3) => {
-                        let (tg, nodes) = walk(t1, nodes, already_seen);
+                        let (tg, nodes) = walk(t1, nodes, already_seen, targets);
                         track_grad |= tg;
-                        let (tg, nodes) = walk(t2, nodes, already_seen);
+                        let (tg, nodes) = walk(t2, nodes, already_seen, targets);
                         track_grad |= tg;
-                        let (tg, nodes) = walk(t3, nodes, already_seen);
+                        let (tg, nodes) = walk(t3, nodes, already_seen, targets);
                         track_grad |= tg;
                         nodes
                     }
@@ -60,6 +246,16 @@ impl Tensor {
                         kernel: rhs,
                         ..
                     }
+                    | Op::ConvTranspose1D {
+                        arg: lhs,
+                        kernel: rhs,
+                        ..
+                    }
+                    | Op::Conv3D {
+                        arg: lhs,
+                        kernel: rhs,
+                        ..
+                    }
                     | Op::ConvTranspose2D {
                         arg: lhs,
                         kernel: rhs,
@@ -69,23 +265,31 @@ impl Tensor {
                     | Op::Binary(lhs, rhs, _)
                     | Op::Gather(lhs, rhs, _)
                     | Op::IndexSelect(lhs, rhs, _)
+                    | Op::SparseIndexSelect(lhs, rhs)
                     | Op::Matmul(lhs, rhs) => {
-                        let (tg, nodes) = walk(lhs, nodes, already_seen);
+                        let (tg, nodes) = walk(lhs, nodes, already_seen, targets);
                         track_grad |= tg;
-                        let (tg, nodes) = walk(rhs, nodes, already_seen);
+                        let (tg, nodes) = walk(rhs, nodes, already_seen, targets);
                         track_grad |= tg;
                         nodes
                     }
                     Op::Cat(args, _) => args.iter().fold(nodes, |nodes, arg| {
-                        let (tg, nodes) = walk(arg, nodes, already_seen);
+                        let (tg, nodes) = walk(arg, nodes, already_seen, targets);
                         track_grad |= tg;
                         nodes
                     }),
+                    Op::Checkpoint { args, vars, .. } => {
+                        args.iter().chain(vars.iter()).fold(nodes, |nodes, arg| {
+                            let (tg, nodes) = walk(arg, nodes, already_seen, targets);
+                            track_grad |= tg;
+                            nodes
+                        })
+                    }
                     Op::Affine { arg, mul, .. } => {
                         if *mul == 0. {
                             nodes
                         } else {
-                            let (tg, nodes) = walk(arg, nodes, already_seen);
+                            let (tg, nodes) = walk(arg, nodes, already_seen, targets);
                             track_grad |= tg;
                             nodes
                         }
@@ -95,11 +299,17 @@ impl Tensor {
                     | Op::UpsampleNearest2D(node)
                     | Op::AvgPool2D { arg: node, .. }
                     | Op::MaxPool2D { arg: node, .. }
+                    | Op::AvgPool3D { arg: node, .. }
+                    | Op::MaxPool3D { arg: node, .. }
                     | Op::Copy(node)
                     | Op::Broadcast(node)
                     | Op::Cmp(node, _)
                     | Op::Reduce(node, _, _)
+                    | Op::Cumulative(node, _, _)
+                    | Op::Fft { arg: node, .. }
                     | Op::ToDType(node)
+                    | Op::ViewAsReal(node)
+                    | Op::ViewAsComplex(node)
                     | Op::ToDevice(node)
                     | Op::Transpose(node, _, _)
                     | Op::Permute(node, _)
@@ -108,7 +318,7 @@ impl Tensor {
                     | Op::Elu(node, _)
                     | Op::Powf(node, _)
                     | Op::CustomOp1(node, _) => {
-                        let (tg, nodes) = walk(node, nodes, already_seen);
+                        let (tg, nodes) = walk(node, nodes, already_seen, targets);
                         track_grad |= tg;
                         nodes
                     }
@@ -122,23 +332,75 @@ impl Tensor {
             }
             (track_grad, nodes)
         }
-        let (_tg, mut nodes) = walk(self, vec![], &mut HashMap::new());
+        let mut already_seen = HashMap::new();
+        let mut nodes = vec![];
+        for root in roots.iter() {
+            (_, nodes) = walk(root, nodes, &mut already_seen, targets);
+        }
         nodes.reverse();
         nodes
     }
 
+    /// Computes the gradients of this tensor with respect to all the variables that it depends
+    /// on. The returned gradients are detached from the op graph.
     pub fn backward(&self) -> Result<GradStore> {
-        let sorted_nodes = self.sorted_nodes();
+        Self::backward_impl(&[(self, self.ones_like()?.contiguous()?)], None, false)
+    }
+
+    /// Same as `backward` but the returned gradients keep track of the op graph used to compute
+    /// them, so that they can be differentiated again, e.g. to get Hessian-vector products or
+    /// gradient penalties. This uses more memory than `backward` as the graph of the backward
+    /// pass is kept alive with the gradients.
+    ///
+    /// ```rust
+    /// use candle_core::{Device, Var};
+    /// let x = Var::new(&[1f32, 2., 3.], &Device::Cpu)?;
+    /// let y = x.powf(3.)?.sum_all()?;
+    /// let grads = y.backward_with_graph()?;
+    /// // dy/dx = 3.x^2, its gradient is 6.x
+    /// let dy_dx = grads.get(&x).unwrap();
+    /// let grads = dy_dx.sum_all()?.backward()?;
+    /// assert_eq!(grads.get(&x).unwrap().to_vec1::<f32>()?, [6., 12., 18.]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn backward_with_graph(&self) -> Result<GradStore> {
+        Self::backward_impl(&[(self, self.ones_like()?.contiguous()?)], None, true)
+    }
+
+    /// Backpropagates the gradients of `roots` through the op graph. When `targets` is set, the
+    /// gradients are only computed for these tensors (and the nodes leading to them), otherwise
+    /// they are computed for all the variables. When `create_graph` is false, the gradients are
+    /// detached so that the backward pass itself is not tracked.
+    pub(crate) fn backward_impl(
+        roots: &[(&Tensor, Tensor)],
+        targets: Option<&HashSet<TensorId>>,
+        create_graph: bool,
+    ) -> Result<GradStore> {
+        let sorted_nodes =
+            Self::sorted_nodes(&roots.iter().map(|v| v.0).collect::<Vec<_>>(), targets);
         let mut grads = GradStore::new();
-        grads.insert(self, self.ones_like()?.contiguous()?);
+        for (root, grad) in roots.iter() {
+            let grad = match grads.remove(root) {
+                None => grad.clone(),
+                Some(prev) => prev.add(grad)?,
+            };
+            grads.insert(root, grad);
+        }
         for node in sorted_nodes.iter() {
             if node.is_variable() {
                 continue;
             }
-            let grad = grads.remove(node).unwrap();
-            // TODO: We should perform all these operations in place (or at least not track the
-            // whole graph). The only drawback would be if we wanted to support grad of grad but
-            // this is out of scope.
+            let is_target = targets.is_some_and(|t| t.contains(&node.id()));
+            let grad = if is_target {
+                grads.get(node).cloned()
+            } else {
+                grads.remove(node)
+            };
+            let grad = match grad {
+                None => continue,
+                Some(grad) if create_graph => grad,
+                Some(grad) => grad.detach()?,
+            };
             if let Some(op) = node.op() {
                 match op {
                     Op::Binary(lhs, rhs, BinaryOp::Add) => {
@@ -154,18 +416,21 @@ impl Tensor {
                         *rhs_sum_grad = rhs_sum_grad.sub(&grad)?;
                     }
                     Op::Binary(lhs, rhs, BinaryOp::Mul) => {
-                        let lhs_grad = grad.mul(rhs)?;
+                        // For complex tensors, the gradients use the conjugate of the other
+                        // operand, `conj` is a no-op on real tensors.
+                        let lhs_grad = grad.mul(&rhs.conj()?)?;
                         let lhs_sum_grad = grads.or_insert(lhs)?;
                         *lhs_sum_grad = lhs_sum_grad.add(&lhs_grad)?;
-                        let rhs_grad = grad.mul(lhs)?;
+                        let rhs_grad = grad.mul(&lhs.conj()?)?;
                         let rhs_sum_grad = grads.or_insert(rhs)?;
                         *rhs_sum_grad = rhs_sum_grad.add(&rhs_grad)?;
                     }
                     Op::Binary(lhs, rhs, BinaryOp::Div) => {
-                        let lhs_grad = grad.div(rhs)?;
+                        let rhs_conj = rhs.conj()?;
+                        let lhs_grad = grad.div(&rhs_conj)?;
                         let lhs_sum_grad = grads.or_insert(lhs)?;
                         *lhs_sum_grad = lhs_sum_grad.add(&lhs_grad)?;
-                        let rhs_grad = grad.mul(lhs)?.div(&rhs.sqr()?)?;
+                        let rhs_grad = grad.mul(&lhs.conj()?)?.div(&rhs_conj.sqr()?)?;
                         let rhs_sum_grad = grads.or_insert(rhs)?;
                         *rhs_sum_grad = rhs_sum_grad.sub(&rhs_grad)?;
                     }
@@ -184,6 +449,18 @@ impl Tensor {
                         let rhs_sum_grad = grads.or_insert(rhs)?;
                         *rhs_sum_grad = rhs_sum_grad.add(&rhs_grad)?;
                     }
+                    Op::Binary(
+                        lhs,
+                        rhs,
+                        op @ (BinaryOp::Pow | BinaryOp::Atan2 | BinaryOp::Fmod),
+                    ) => {
+                        let (d_lhs, d_rhs) = binary_partials(*op, lhs, rhs, node)?;
+                        let lhs_sum_grad = grads.or_insert(lhs)?;
+                        *lhs_sum_grad = lhs_sum_grad.add(&grad.mul(&d_lhs)?)?;
+                        let rhs_sum_grad = grads.or_insert(rhs)?;
+                        *rhs_sum_grad = rhs_sum_grad.add(&grad.mul(&d_rhs)?)?;
+                    }
+                    Op::Binary(_, _, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor) => {}
                     Op::WhereCond(pred, t, f) => {
                         let zeros = grad.zeros_like()?;
                         let t_sum_grad = grads.or_insert(t)?;
@@ -193,53 +470,155 @@ impl Tensor {
                         let f_grad = pred.where_cond(&zeros, &grad)?;
                         *f_sum_grad = f_sum_grad.add(&f_grad)?;
                     }
-                    Op::Conv1D { .. } => Err(Error::BackwardNotSupported { op: "conv1d" })?,
-                    Op::Conv2D {
+                    Op::Conv1D {
                         arg,
                         kernel,
                         padding,
                         stride,
                         dilation,
                     } => {
-                        // The output height for conv_transpose2d is:
+                        // As for conv2d, the gradient is computed on the padded input and the
+                        // padding is then removed.
+                        let l_in = arg.dim(2)?;
+                        let k_size = kernel.dim(2)?;
+                        let out_size = (grad.dim(2)? - 1) * stride + dilation * (k_size - 1) + 1;
+                        let grad_arg = grad
+                            .conv_transpose1d(kernel, 0, 0, *stride, *dilation)?
+                            .pad_with_zeros(2, 0, l_in + 2 * padding - out_size)?
+                            .narrow(2, *padding, l_in)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad_arg)?;
+
+                        let grad_kernel = arg
+                            .transpose(0, 1)?
+                            .conv1d(&grad.transpose(0, 1)?, *padding, *dilation, *stride, 1)?
+                            .transpose(0, 1)?;
+                        let sum_grad = grads.or_insert(kernel)?;
+                        let grad_kernel = grad_kernel.narrow(2, 0, k_size)?;
+                        *sum_grad = sum_grad.add(&grad_kernel)?;
+                    }
+                    &Op::Conv2D {
+                        ref arg,
+                        ref kernel,
+                        padding,
+                        padding_mode,
+                        stride,
+                        dilation,
+                    } => {
+                        if padding_mode != crate::conv::PaddingMode::Zeros {
+                            Err(Error::BackwardNotSupported {
+                                op: "conv2d with reflect or replicate padding",
+                            })?
+                        }
+                        if stride.0 != stride.1 || dilation.0 != dilation.1 {
+                            Err(Error::BackwardNotSupported {
+                                op: "conv2d with a different stride or dilation per axis",
+                            })?
+                        }
+                        let (stride, dilation) = (stride.0, dilation.0);
+                        let (pad_t, pad_b, pad_l, pad_r) = padding;
+                        let (_, _, i_h, i_w) = arg.dims4()?;
+                        let (_, _, k_h, k_w) = kernel.dims4()?;
+                        // The gradient is first computed on the padded input and the padding is
+                        // then removed. The output height for conv_transpose2d is:
                         // (i_h - 1) * stride - 2 * padding + dilation * (k_h - 1) + out_padding + 1
-                        let grad_h = grad.dim(2)?;
-                        let k_h = kernel.dim(2)?;
-                        let out_size =
-                            (grad_h - 1) * stride + dilation * (k_h - 1) + 1 - 2 * padding;
-                        let out_padding = arg.dim(2)? - out_size;
-                        let grad_arg = grad.conv_transpose2d(
-                            kernel,
-                            *padding,
-                            out_padding,
-                            *stride,
-                            *dilation,
-                        )?;
+                        let (grad_h, grad_w) = (grad.dim(2)?, grad.dim(3)?);
+                        let out_h = (grad_h - 1) * stride + dilation * (k_h - 1) + 1;
+                        let out_w = (grad_w - 1) * stride + dilation * (k_w - 1) + 1;
+                        let padded_h = i_h + pad_t + pad_b;
+                        let padded_w = i_w + pad_l + pad_r;
+                        let grad_arg = grad.conv_transpose2d(kernel, 0, 0, stride, dilation)?;
+                        let grad_arg = grad_arg
+                            .pad_with_zeros(2, 0, padded_h - out_h)?
+                            .pad_with_zeros(3, 0, padded_w - out_w)?
+                            .narrow(2, pad_t, i_h)?
+                            .narrow(3, pad_l, i_w)?;
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&grad_arg)?;
 
                         let grad_kernel = arg
                             .transpose(0, 1)?
-                            .conv2d(&grad.transpose(0, 1)?, *padding, *dilation, *stride, 1)?
+                            .conv2d_with_padding(
+                                &grad.transpose(0, 1)?,
+                                padding,
+                                crate::conv::PaddingMode::Zeros,
+                                (dilation, dilation),
+                                (stride, stride),
+                                1,
+                            )?
+                            .transpose(0, 1)?
+                            .narrow(2, 0, k_h)?
+                            .narrow(3, 0, k_w)?;
+                        let sum_grad = grads.or_insert(kernel)?;
+                        *sum_grad = sum_grad.add(&grad_kernel)?;
+                    }
+                    Op::ConvTranspose1D {
+                        arg,
+                        kernel,
+                        padding,
+                        output_padding: _,
+                        stride,
+                        dilation,
+                    } => {
+                        let grad_arg = grad.conv1d(kernel, *padding, *stride, *dilation, 1)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad_arg)?;
+
+                        let grad_kernel = grad
+                            .transpose(0, 1)?
+                            .conv1d(&arg.transpose(0, 1)?, *padding, *dilation, *stride, 1)?
+                            .transpose(0, 1)?;
+                        let sum_grad = grads.or_insert(kernel)?;
+                        let (_, _, k0) = kernel.dims3()?;
+                        let grad_kernel = grad_kernel.narrow(2, 0, k0)?;
+                        *sum_grad = sum_grad.add(&grad_kernel)?;
+                    }
+                    Op::ConvTranspose2D {
+                        arg,
+                        kernel,
+                        padding,
+                        output_padding: _,
+                        stride,
+                        dilation,
+                    } => {
+                        let grad_arg = grad.conv2d(kernel, *padding, *stride, *dilation, 1)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad_arg)?;
+
+                        let grad_kernel = grad
+                            .transpose(0, 1)?
+                            .conv2d(&arg.transpose(0, 1)?, *padding, *dilation, *stride, 1)?
                             .transpose(0, 1)?;
                         let sum_grad = grads.or_insert(kernel)?;
+                        let (_, _, k_h, k_w) = kernel.dims4()?;
+                        let grad_kernel = grad_kernel.narrow(2, 0, k_h)?.narrow(3, 0, k_w)?;
                         *sum_grad = sum_grad.add(&grad_kernel)?;
                     }
-                    Op::ConvTranspose2D { .. } => Err(Error::BackwardNotSupported {
-                        op: "conv-transpose2d",
-                    })?,
+                    Op::Conv3D { .. } => Err(Error::BackwardNotSupported { op: "conv3d" })?,
+                    Op::AvgPool3D { .. } => Err(Error::BackwardNotSupported { op: "avg-pool3d" })?,
+                    Op::MaxPool3D { .. } => Err(Error::BackwardNotSupported { op: "max-pool3d" })?,
                     Op::AvgPool2D {
                         arg,
                         kernel_size,
                         stride,
                     } => {
-                        if kernel_size != stride {
-                            crate::bail!("backward not supported for avgpool2d if ksize {kernel_size:?} != stride {stride:?}")
-                        }
                         let (_n, _c, h, w) = arg.dims4()?;
-                        let grad_arg = grad.upsample_nearest2d(h, w)?;
-                        let grad_arg =
-                            (grad_arg * (1f64 / (kernel_size.0 * kernel_size.1) as f64))?;
+                        let tiled = kernel_size == stride
+                            && h % kernel_size.0 == 0
+                            && w % kernel_size.1 == 0;
+                        let grad_arg = if tiled {
+                            let grad_arg = grad.upsample_nearest2d(h, w)?;
+                            (grad_arg * (1f64 / (kernel_size.0 * kernel_size.1) as f64))?
+                        } else {
+                            // Otherwise, each window spreads its gradient
+                            // uniformly over all the elements that it covers.
+                            let idxs = pool2d_window_idxs(arg, *kernel_size, *stride)?;
+                            let (n, c, h_out, w_out) = grad.dims4()?;
+                            let k = kernel_size.0 * kernel_size.1;
+                            let grad = (grad.unsqueeze(4)? * (1f64 / k as f64))?
+                                .broadcast_as((n, c, h_out, w_out, k))?;
+                            pool2d_scatter(arg, &idxs, &grad)?
+                        };
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&grad_arg)?;
                     }
@@ -248,27 +627,49 @@ impl Tensor {
                         kernel_size,
                         stride,
                     } => {
-                        if kernel_size != stride {
-                            crate::bail!("backward not supported for maxpool2d if ksize {kernel_size:?} != stride {stride:?}")
-                        }
                         let (_n, _c, h, w) = arg.dims4()?;
-                        // For computing the max-pool gradient, we compute a mask where a 1 means
-                        // that the element is the maximum, then we apply this mask to the
-                        // upsampled gradient (taking into account that multiple max may exist so
-                        // we scale the gradient for this case).
-                        let node_upsampled = node.upsample_nearest2d(h, w)?;
-                        let mask = arg.eq(&node_upsampled)?.to_dtype(arg.dtype())?;
-                        let avg = mask.avg_pool2d_with_stride(*kernel_size, *stride)?;
-                        let grad_arg = ((grad * avg)?.upsample_nearest2d(h, w)? * mask)?;
+                        let tiled = kernel_size == stride
+                            && h % kernel_size.0 == 0
+                            && w % kernel_size.1 == 0;
+                        let grad_arg = if tiled {
+                            // For computing the max-pool gradient, we compute a mask where a 1
+                            // means that the element is the maximum, then we apply this mask to
+                            // the upsampled gradient (taking into account that multiple max may
+                            // exist so we scale the gradient for this case).
+                            let node_upsampled = node.upsample_nearest2d(h, w)?;
+                            let mask = arg.eq(&node_upsampled)?.to_dtype(arg.dtype())?;
+                            let avg = mask.avg_pool2d_with_stride(*kernel_size, *stride)?;
+                            ((grad * avg)?.upsample_nearest2d(h, w)? * mask)?
+                        } else {
+                            // Same as above but the mask is computed per window so that
+                            // overlapping windows each route their gradient to their own max and
+                            // the elements not covered by any window get no gradient.
+                            let idxs = pool2d_window_idxs(arg, *kernel_size, *stride)?;
+                            let mask = max_pool2d_mask(arg, node, &idxs)?;
+                            let grad = grad.unsqueeze(4)?.broadcast_div(&mask.sum_keepdim(4)?)?;
+                            pool2d_scatter(arg, &idxs, &mask.broadcast_mul(&grad)?)?
+                        };
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad_arg)?;
+                    }
+                    Op::UpsampleNearest1D(arg) => {
+                        let (_n, _c, size) = arg.dims3()?;
+                        let idxs = upsample_nearest_idxs(size, grad.dim(2)?, arg.device())?;
+                        let grad_arg = arg.zeros_like()?.index_add(&idxs, &grad, 2)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad_arg)?;
+                    }
+                    Op::UpsampleNearest2D(arg) => {
+                        let (n, c, h, w) = arg.dims4()?;
+                        let (_, _, dst_h, dst_w) = grad.dims4()?;
+                        let h_idxs = upsample_nearest_idxs(h, dst_h, arg.device())?;
+                        let w_idxs = upsample_nearest_idxs(w, dst_w, arg.device())?;
+                        let zeros = Tensor::zeros((n, c, dst_h, w), grad.dtype(), grad.device())?;
+                        let grad_arg = zeros.index_add(&w_idxs, &grad, 3)?;
+                        let grad_arg = arg.zeros_like()?.index_add(&h_idxs, &grad_arg, 2)?;
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&grad_arg)?;
                     }
-                    Op::UpsampleNearest1D { .. } => Err(Error::BackwardNotSupported {
-                        op: "upsample-nearest1d",
-                    })?,
-                    Op::UpsampleNearest2D { .. } => Err(Error::BackwardNotSupported {
-                        op: "upsample-nearest2d",
-                    })?,
                     Op::Gather(arg, indexes, dim) => {
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.scatter_add(indexes, &grad, *dim)?;
@@ -293,6 +694,19 @@ impl Tensor {
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.index_add(indexes, &grad, *dim)?;
                     }
+                    // The sparse gradients are only produced by a plain backward pass, the
+                    // gradients of intermediary nodes and higher order gradients are dense.
+                    Op::SparseIndexSelect(arg, indexes)
+                        if arg.is_variable() && targets.is_none() && !create_graph =>
+                    {
+                        let indices = indexes.unsqueeze(1)?;
+                        let grad = CooTensor::new(&indices, &grad, arg.shape())?;
+                        grads.add_sparse(arg, grad)?
+                    }
+                    Op::SparseIndexSelect(arg, indexes) => {
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.index_add(indexes, &grad, 0)?;
+                    }
                     Op::Matmul(lhs, rhs) => {
                         // Skipping checks, the op went ok, we can skip
                         // the matmul size checks for now.
@@ -358,6 +772,47 @@ impl Tensor {
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&grad.broadcast_as(sum_grad.dims())?)?;
                     }
+                    &Op::Cumulative(ref arg, CumulativeOp::Sum, dim) => {
+                        let arg_grad = reverse_cumsum(&grad, dim)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    &Op::Cumulative(ref arg, CumulativeOp::Prod, dim) => {
+                        let (excl, m) = cumprod_partials(arg, node, dim)?;
+                        let grad = grad.unsqueeze(dim)?.broadcast_as(m.shape())?;
+                        let arg_grad = excl.mul(&m.mul(&grad)?.sum(dim + 1)?)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    &Op::Cumulative(ref arg, CumulativeOp::Max, dim) => {
+                        let indexes = cummax_idxs(arg, node, dim)?;
+                        let arg_grad = grad.zeros_like()?.scatter_add(&indexes, &grad, dim)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    &Op::Fft {
+                        ref arg,
+                        dim,
+                        inverse,
+                    } => {
+                        // The adjoint of the dft is `n` times the inverse dft, and vice versa.
+                        let n = arg.dim(dim)? as f64;
+                        let arg_grad = if inverse {
+                            (grad.fft_impl(dim, false)? / n)?
+                        } else {
+                            (grad.fft_impl(dim, true)? * n)?
+                        };
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    Op::ViewAsReal(arg) => {
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad.view_as_complex()?)?
+                    }
+                    Op::ViewAsComplex(arg) => {
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&grad.view_as_real()?)?
+                    }
                     Op::ToDType(arg) => {
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&grad.to_dtype(node.dtype())?)?
@@ -440,13 +895,31 @@ impl Tensor {
                         let sum_grad = grads.or_insert(arg)?;
                         *sum_grad = sum_grad.add(&arg_grad)?
                     }
-                    Op::Unary(_, UnaryOp::Gelu) => Err(Error::BackwardNotSupported { op: "gelu" })?,
+                    Op::Unary(arg, UnaryOp::Gelu) => {
+                        let arg_grad = (&grad * gelu_derivative(arg)?)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    Op::Unary(arg, UnaryOp::Erf) => {
+                        let arg_grad = (&grad * erf_derivative(arg)?)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
+                    // The rounding ops and sign are piecewise constant.
+                    Op::Unary(
+                        _,
+                        UnaryOp::Floor | UnaryOp::Ceil | UnaryOp::Round | UnaryOp::Sign,
+                    ) => {}
                     Op::Unary(arg, UnaryOp::Relu) => {
                         let sum_grad = grads.or_insert(arg)?;
                         let relu_grad = arg.ge(&arg.zeros_like()?)?.to_dtype(arg.dtype())?;
                         *sum_grad = sum_grad.add(&(&grad * relu_grad)?)?
                     }
-                    Op::Elu(..) => Err(Error::BackwardNotSupported { op: "elu" })?,
+                    Op::Elu(arg, alpha) => {
+                        let arg_grad = (&grad * elu_derivative(arg, node, *alpha)?)?;
+                        let sum_grad = grads.or_insert(arg)?;
+                        *sum_grad = sum_grad.add(&arg_grad)?
+                    }
                     Op::Powf(arg, e) => {
                         let arg_grad = (&(grad * arg.powf(e - 1.)?)? * *e)?;
                         let sum_grad = grads.or_insert(arg)?;
@@ -485,6 +958,36 @@ impl Tensor {
                             *sum_grad = sum_grad.add(&arg_grad3)?
                         }
                     }
+                    Op::Checkpoint { args, vars, f } => {
+                        // Recompute the activations and backpropagate through them, the
+                        // variables are only needed if they are part of the targets.
+                        let inputs = args
+                            .iter()
+                            .map(|arg| arg.detach())
+                            .collect::<Result<Vec<_>>>()?;
+                        let res = f(&inputs)?;
+                        let vars = vars
+                            .iter()
+                            .filter(|v| targets.is_none_or(|t| t.contains(&v.id())))
+                            .collect::<Vec<_>>();
+                        let inner_targets = inputs
+                            .iter()
+                            .chain(vars.iter().copied())
+                            .map(|t| t.id())
+                            .collect::<HashSet<_>>();
+                        let inner_grads = Self::backward_impl(
+                            &[(&res, grad.clone())],
+                            Some(&inner_targets),
+                            create_graph,
+                        )?;
+                        let args = args.iter().zip(inputs.iter());
+                        for (arg, t) in args.chain(vars.iter().map(|v| (*v, *v))) {
+                            if let Some(arg_grad) = inner_grads.get(t) {
+                                let sum_grad = grads.or_insert(arg)?;
+                                *sum_grad = sum_grad.add(arg_grad)?
+                            }
+                        }
+                    }
                     Op::Unary(arg, UnaryOp::Sqr) => {
                         let arg_grad = arg.mul(&grad)?.affine(2., 0.)?;
                         let sum_grad = grads.or_insert(arg)?;
@@ -517,15 +1020,44 @@ impl Tensor {
                 };
             }
         }
+        if !create_graph {
+            for grad in grads.0.values_mut() {
+                *grad = grad.detach()?
+            }
+        }
         Ok(grads)
     }
 }
 
-pub struct GradStore(HashMap<TensorId, Tensor>);
+pub struct GradStore(HashMap<TensorId, Tensor>, HashMap<TensorId, CooTensor>);
 
 impl GradStore {
     fn new() -> Self {
-        GradStore(HashMap::new())
+        GradStore(HashMap::new(), HashMap::new())
+    }
+
+    /// The sparse gradient of `tensor`, this is only set for the variables used through
+    /// [`Tensor::index_select_sparse_grad`]. A variable can have both a dense and a sparse
+    /// gradient when it is also used by other ops, its gradient is then the sum of both.
+    pub fn get_sparse(&self, tensor: &Tensor) -> Option<&CooTensor> {
+        self.1.get(&tensor.id())
+    }
+
+    pub fn remove_sparse(&mut self, tensor: &Tensor) -> Option<CooTensor> {
+        self.1.remove(&tensor.id())
+    }
+
+    pub fn insert_sparse(&mut self, tensor: &Tensor, grad: CooTensor) -> Option<CooTensor> {
+        self.1.insert(tensor.id(), grad)
+    }
+
+    fn add_sparse(&mut self, tensor: &Tensor, grad: CooTensor) -> Result<()> {
+        let grad = match self.1.remove(&tensor.id()) {
+            None => grad,
+            Some(prev) => prev.add(&grad)?,
+        };
+        self.1.insert(tensor.id(), grad);
+        Ok(())
     }
 
     pub fn get_id(&self, id: TensorId) -> Option<&Tensor> {
@@ -0,0 +1,54 @@
+//! Gradient checkpointing, a.k.a. activation recomputation.
+use crate::op::{BackpropOp, CheckpointFn, Op};
+use crate::{Result, Tensor};
+use std::sync::Arc;
+
+/// Applies `f` to `args` without keeping the intermediary activations of `f` alive. These
+/// activations get recomputed when the gradients are computed, trading compute for memory.
+///
+/// The tensors that `f` should differentiate through have to be passed via `args`, or be
+/// variables. `f` has to be deterministic for the recomputed activations to be the same as the
+/// original ones, e.g. dropout should not be used within `f`.
+///
+/// ```rust
+/// use candle_core::{Device, Tensor, Var};
+/// let w = Var::new(&[[1f32, 2.], [3., 4.]], &Device::Cpu)?;
+/// let x = Tensor::new(&[[1f32, -1.]], &Device::Cpu)?;
+/// let f = {
+///     let w = w.as_tensor().clone();
+///     move |xs: &[Tensor]| xs[0].matmul(&w)?.tanh()?.sqr()
+/// };
+/// let ys = candle_core::checkpoint(f, &[&x])?;
+/// let grads = ys.sum_all()?.backward()?;
+/// assert_eq!(grads.get(&w).unwrap().dims(), &[2, 2]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn checkpoint<F>(f: F, args: &[&Tensor]) -> Result<Tensor>
+where
+    F: Fn(&[Tensor]) -> Result<Tensor> + Send + Sync + 'static,
+{
+    let f: CheckpointFn = Arc::new(f);
+    let inputs = args
+        .iter()
+        .map(|arg| arg.detach())
+        .collect::<Result<Vec<_>>>()?;
+    let res = f(&inputs)?;
+    // Only the variables are retained from the op graph of `res`, the graph itself is dropped
+    // when `res` goes out of scope.
+    let vars = Tensor::sorted_nodes(&[&res], None)
+        .into_iter()
+        .filter(|node| node.is_variable())
+        .cloned()
+        .collect::<Vec<_>>();
+    let n_args = args.len();
+    let all_args = args.iter().copied().chain(vars.iter()).collect::<Vec<_>>();
+    let op = BackpropOp::new(&all_args, |mut args| {
+        let vars = args.split_off(n_args);
+        Op::Checkpoint {
+            args,
+            vars,
+            f: f.clone(),
+        }
+    });
+    Ok(res.with_op(op))
+}
@@ -0,0 +1,166 @@
+//! Complex number types that can be stored in tensors.
+//!
+//! These are thin wrappers around `num_complex::Complex` that also implement `PartialOrd` so that
+//! they can be used with the generic tensor kernels. The ordering is lexicographic: the real parts
+//! are compared first and the imaginary parts are only used to break ties.
+use num_traits::{Num, One, Zero};
+
+macro_rules! complex_type {
+    ($ty:ident, $f:ty, $doc:literal) => {
+        #[doc = $doc]
+        #[derive(Debug, Clone, Copy, Default, PartialEq)]
+        #[repr(C)]
+        pub struct $ty {
+            pub re: $f,
+            pub im: $f,
+        }
+
+        impl $ty {
+            pub const fn new(re: $f, im: $f) -> Self {
+                Self { re, im }
+            }
+
+            /// The complex conjugate.
+            pub fn conj(self) -> Self {
+                Self::new(self.re, -self.im)
+            }
+
+            /// The squared modulus `re * re + im * im`.
+            pub fn norm_sqr(self) -> $f {
+                self.re * self.re + self.im * self.im
+            }
+
+            /// The modulus, i.e. the distance to 0.
+            pub fn norm(self) -> $f {
+                self.re.hypot(self.im)
+            }
+
+            /// The modulus, returned as a complex number with a null imaginary part.
+            pub fn abs(self) -> Self {
+                Self::new(self.norm(), 0.)
+            }
+
+            pub fn exp(self) -> Self {
+                self.to_num().exp().into()
+            }
+
+            pub fn ln(self) -> Self {
+                self.to_num().ln().into()
+            }
+
+            pub fn sin(self) -> Self {
+                self.to_num().sin().into()
+            }
+
+            pub fn cos(self) -> Self {
+                self.to_num().cos().into()
+            }
+
+            pub fn tanh(self) -> Self {
+                self.to_num().tanh().into()
+            }
+
+            pub fn sqrt(self) -> Self {
+                self.to_num().sqrt().into()
+            }
+
+            pub fn recip(self) -> Self {
+                self.to_num().inv().into()
+            }
+
+            fn to_num(self) -> num_complex::Complex<$f> {
+                num_complex::Complex::new(self.re, self.im)
+            }
+        }
+
+        impl From<num_complex::Complex<$f>> for $ty {
+            fn from(v: num_complex::Complex<$f>) -> Self {
+                Self::new(v.re, v.im)
+            }
+        }
+
+        impl From<$ty> for num_complex::Complex<$f> {
+            fn from(v: $ty) -> Self {
+                v.to_num()
+            }
+        }
+
+        impl PartialOrd for $ty {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                match self.re.partial_cmp(&other.re) {
+                    Some(std::cmp::Ordering::Equal) => self.im.partial_cmp(&other.im),
+                    ord => ord,
+                }
+            }
+        }
+
+        impl std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                std::fmt::Display::fmt(&self.to_num(), f)
+            }
+        }
+
+        impl std::ops::Neg for $ty {
+            type Output = Self;
+            fn neg(self) -> Self {
+                Self::new(-self.re, -self.im)
+            }
+        }
+
+        complex_type!(@bin_op $ty, Add, add, AddAssign, add_assign);
+        complex_type!(@bin_op $ty, Sub, sub, SubAssign, sub_assign);
+        complex_type!(@bin_op $ty, Mul, mul, MulAssign, mul_assign);
+        complex_type!(@bin_op $ty, Div, div, DivAssign, div_assign);
+        complex_type!(@bin_op $ty, Rem, rem, RemAssign, rem_assign);
+
+        impl Zero for $ty {
+            fn zero() -> Self {
+                Self::new(0., 0.)
+            }
+
+            fn is_zero(&self) -> bool {
+                self.re == 0. && self.im == 0.
+            }
+        }
+
+        impl One for $ty {
+            fn one() -> Self {
+                Self::new(1., 0.)
+            }
+        }
+
+        impl Num for $ty {
+            type FromStrRadixErr = num_complex::ParseComplexError<<$f as Num>::FromStrRadixErr>;
+
+            fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+                num_complex::Complex::<$f>::from_str_radix(s, radix).map(Self::from)
+            }
+        }
+    };
+
+    (@bin_op $ty:ident, $trait:ident, $fn:ident, $assign_trait:ident, $assign_fn:ident) => {
+        impl std::ops::$trait for $ty {
+            type Output = Self;
+            fn $fn(self, rhs: Self) -> Self {
+                std::ops::$trait::$fn(self.to_num(), rhs.to_num()).into()
+            }
+        }
+
+        impl std::ops::$assign_trait for $ty {
+            fn $assign_fn(&mut self, rhs: Self) {
+                *self = std::ops::$trait::$fn(*self, rhs)
+            }
+        }
+    };
+}
+
+complex_type!(
+    C64,
+    f32,
+    "A complex number using single precision floats for both of its parts (64 bits)."
+);
+complex_type!(
+    C128,
+    f64,
+    "A complex number using double precision floats for both of its parts (128 bits)."
+);
@@ -25,6 +25,60 @@ impl ParamsConv1D {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamsConvTranspose1D {
+    pub(crate) b_size: usize,
+    pub(crate) l_in: usize,
+    pub(crate) c_out: usize,
+    pub(crate) c_in: usize,
+    pub(crate) k_size: usize,
+    pub(crate) padding: usize,
+    pub(crate) output_padding: usize,
+    pub(crate) stride: usize,
+    pub(crate) dilation: usize,
+}
+
+impl ParamsConvTranspose1D {
+    pub(crate) fn l_out(&self) -> usize {
+        (self.l_in - 1) * self.stride + self.dilation * (self.k_size - 1) + self.output_padding + 1
+            - 2 * self.padding
+    }
+
+    pub(crate) fn out_dims(&self) -> Vec<usize> {
+        let l_out = self.l_out();
+        vec![self.b_size, self.c_out, l_out]
+    }
+}
+
+/// How the values outside of the input are obtained when padding it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingMode {
+    /// Pad with zeros.
+    #[default]
+    Zeros,
+    /// Reflect the input around its edges without repeating them, e.g. `[c, b | a, b, c | b, a]`.
+    Reflect,
+    /// Repeat the values on the edges, e.g. `[a, a | a, b, c | c, c]`.
+    Replicate,
+}
+
+impl PaddingMode {
+    /// Maps an index in the padded input to an index in the input, `None` is returned for zero
+    /// padding. `pad` is the padding before the input and `size` the size of the input.
+    pub(crate) fn src_index(&self, idx: usize, pad: usize, size: usize) -> Option<usize> {
+        if idx >= pad && idx < pad + size {
+            return Some(idx - pad);
+        }
+        match self {
+            Self::Zeros => None,
+            Self::Reflect if idx < pad => Some(pad - idx),
+            Self::Reflect => Some(2 * (size - 1) + pad - idx),
+            Self::Replicate if idx < pad => Some(0),
+            Self::Replicate => Some(size - 1),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParamsConv2D {
     pub(crate) b_size: usize,
@@ -34,18 +88,40 @@ pub struct ParamsConv2D {
     pub(crate) k_w: usize,
     pub(crate) c_out: usize,
     pub(crate) c_in: usize,
-    pub(crate) padding: usize,
-    pub(crate) stride: usize,
-    pub(crate) dilation: usize,
+    pub(crate) padding_top: usize,
+    pub(crate) padding_bottom: usize,
+    pub(crate) padding_left: usize,
+    pub(crate) padding_right: usize,
+    pub(crate) padding_mode: PaddingMode,
+    pub(crate) stride_h: usize,
+    pub(crate) stride_w: usize,
+    pub(crate) dilation_h: usize,
+    pub(crate) dilation_w: usize,
 }
 
 impl ParamsConv2D {
     pub(crate) fn out_h(&self) -> usize {
-        (self.i_h + 2 * self.padding - self.dilation * (self.k_h - 1) - 1) / self.stride + 1
+        let i_h = self.i_h + self.padding_top + self.padding_bottom;
+        (i_h - self.dilation_h * (self.k_h - 1) - 1) / self.stride_h + 1
     }
 
     pub(crate) fn out_w(&self) -> usize {
-        (self.i_w + 2 * self.padding - self.dilation * (self.k_w - 1) - 1) / self.stride + 1
+        let i_w = self.i_w + self.padding_left + self.padding_right;
+        (i_w - self.dilation_w * (self.k_w - 1) - 1) / self.stride_w + 1
+    }
+
+    /// Returns the padding, stride and dilation if these are the same on all the sides and the
+    /// padding uses zeros.
+    #[cfg(feature = "cuda")]
+    pub(crate) fn symmetric(&self) -> Option<(usize, usize, usize)> {
+        let p = self.padding_top;
+        let symmetric = self.padding_mode == PaddingMode::Zeros
+            && self.padding_bottom == p
+            && self.padding_left == p
+            && self.padding_right == p
+            && self.stride_h == self.stride_w
+            && self.dilation_h == self.dilation_w;
+        symmetric.then_some((p, self.stride_h, self.dilation_h))
     }
 
     pub(crate) fn out_dims(&self) -> Vec<usize> {
@@ -84,6 +160,46 @@ impl ParamsConvTranspose2D {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamsConv3D {
+    pub(crate) b_size: usize,
+    pub(crate) i_d: usize,
+    pub(crate) i_h: usize,
+    pub(crate) i_w: usize,
+    pub(crate) k_d: usize,
+    pub(crate) k_h: usize,
+    pub(crate) k_w: usize,
+    pub(crate) c_out: usize,
+    pub(crate) c_in: usize,
+    pub(crate) padding: usize,
+    pub(crate) stride: usize,
+    pub(crate) dilation: usize,
+}
+
+impl ParamsConv3D {
+    pub(crate) fn out_d(&self) -> usize {
+        (self.i_d + 2 * self.padding - self.dilation * (self.k_d - 1) - 1) / self.stride + 1
+    }
+
+    pub(crate) fn out_h(&self) -> usize {
+        (self.i_h + 2 * self.padding - self.dilation * (self.k_h - 1) - 1) / self.stride + 1
+    }
+
+    pub(crate) fn out_w(&self) -> usize {
+        (self.i_w + 2 * self.padding - self.dilation * (self.k_w - 1) - 1) / self.stride + 1
+    }
+
+    pub(crate) fn out_dims(&self) -> Vec<usize> {
+        vec![
+            self.b_size,
+            self.c_out,
+            self.out_d(),
+            self.out_h(),
+            self.out_w(),
+        ]
+    }
+}
+
 impl Tensor {
     fn conv1d_single_group(&self, kernel: &Self, params: &ParamsConv1D) -> Result<Self> {
         let storage =
@@ -97,7 +213,13 @@ impl Tensor {
             dilation: params.dilation,
         });
         let out_dims = params.out_dims();
-        Ok(crate::tensor::from_storage(storage, out_dims, op, false))
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            out_dims,
+            op,
+            false,
+        ))
     }
 
     /// Applies a 1D convolution over the input tensor.
@@ -146,6 +268,55 @@ impl Tensor {
         }
     }
 
+    /// Applies a 1D transposed convolution over the input tensor.
+    pub fn conv_transpose1d(
+        &self,
+        kernel: &Self,
+        padding: usize,
+        output_padding: usize,
+        stride: usize,
+        dilation: usize,
+    ) -> Result<Self> {
+        let (b_size, c_in, l_in) = self.dims3()?;
+        let (c_in_k, c_out, k_size) = kernel.dims3()?;
+        if c_in != c_in_k {
+            crate::bail!("in_channel mismatch between input ({c_in}) and kernel ({c_in_k})")
+        }
+        let params = ParamsConvTranspose1D {
+            b_size,
+            l_in,
+            k_size,
+            c_out,
+            c_in,
+            padding,
+            output_padding,
+            stride,
+            dilation,
+        };
+        let storage = self.storage().conv_transpose1d(
+            self.layout(),
+            &kernel.storage(),
+            kernel.layout(),
+            &params,
+        )?;
+        let op = BackpropOp::new2(self, kernel, |arg, kernel| Op::ConvTranspose1D {
+            arg,
+            kernel,
+            padding: params.padding,
+            output_padding: params.output_padding,
+            stride: params.stride,
+            dilation: params.dilation,
+        });
+        let out_dims = params.out_dims();
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            out_dims,
+            op,
+            false,
+        ))
+    }
+
     fn conv2d_single_group(&self, kernel: &Self, params: &ParamsConv2D) -> Result<Self> {
         let storage =
             self.storage()
@@ -153,12 +324,24 @@ impl Tensor {
         let op = BackpropOp::new2(self, kernel, |arg, kernel| Op::Conv2D {
             arg,
             kernel,
-            padding: params.padding,
-            stride: params.stride,
-            dilation: params.dilation,
+            padding: (
+                params.padding_top,
+                params.padding_bottom,
+                params.padding_left,
+                params.padding_right,
+            ),
+            padding_mode: params.padding_mode,
+            stride: (params.stride_h, params.stride_w),
+            dilation: (params.dilation_h, params.dilation_w),
         });
         let out_dims = params.out_dims();
-        Ok(crate::tensor::from_storage(storage, out_dims, op, false))
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            out_dims,
+            op,
+            false,
+        ))
     }
 
     /// Applies a 2D convolution over the input tensor.
@@ -169,6 +352,29 @@ impl Tensor {
         stride: usize,
         dilation: usize,
         groups: usize,
+    ) -> Result<Self> {
+        self.conv2d_with_padding(
+            kernel,
+            (padding, padding, padding, padding),
+            PaddingMode::Zeros,
+            (stride, stride),
+            (dilation, dilation),
+            groups,
+        )
+    }
+
+    /// Applies a 2D convolution over the input tensor with a padding that can be different on
+    /// each side and strides and dilations that can be different on each axis.
+    ///
+    /// `padding` is `(top, bottom, left, right)`, `stride` and `dilation` are `(h, w)`.
+    pub fn conv2d_with_padding(
+        &self,
+        kernel: &Self,
+        padding: (usize, usize, usize, usize),
+        padding_mode: PaddingMode,
+        stride: (usize, usize),
+        dilation: (usize, usize),
+        groups: usize,
     ) -> Result<Self> {
         let (b_size, c_in, i_h, i_w) = self.dims4()?;
         let (c_out, c_in_k, k_h, k_w) = kernel.dims4()?;
@@ -177,6 +383,23 @@ impl Tensor {
                 "in_channel mismatch between input ({c_in}, groups {groups}) and kernel ({c_in_k})"
             )
         }
+        let (padding_top, padding_bottom, padding_left, padding_right) = padding;
+        match padding_mode {
+            PaddingMode::Zeros => {}
+            PaddingMode::Reflect => {
+                if padding_top.max(padding_bottom) >= i_h || padding_left.max(padding_right) >= i_w
+                {
+                    crate::bail!(
+                        "reflect padding {padding:?} has to be smaller than the input ({i_h}, {i_w})"
+                    )
+                }
+            }
+            PaddingMode::Replicate => {
+                if i_h == 0 || i_w == 0 {
+                    crate::bail!("replicate padding cannot be applied to an empty input")
+                }
+            }
+        }
         let params = ParamsConv2D {
             b_size,
             i_h,
@@ -185,9 +408,15 @@ impl Tensor {
             k_w,
             c_out: c_out / groups,
             c_in: c_in / groups,
-            padding,
-            stride,
-            dilation,
+            padding_top,
+            padding_bottom,
+            padding_left,
+            padding_right,
+            padding_mode,
+            stride_h: stride.0,
+            stride_w: stride.1,
+            dilation_h: dilation.0,
+            dilation_w: dilation.1,
         };
         if groups == 1 {
             self.conv2d_single_group(kernel, &params)
@@ -245,6 +474,80 @@ impl Tensor {
             dilation: params.dilation,
         });
         let out_dims = params.out_dims();
-        Ok(crate::tensor::from_storage(storage, out_dims, op, false))
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            out_dims,
+            op,
+            false,
+        ))
+    }
+
+    fn conv3d_single_group(&self, kernel: &Self, params: &ParamsConv3D) -> Result<Self> {
+        let storage =
+            self.storage()
+                .conv3d(self.layout(), &kernel.storage(), kernel.layout(), params)?;
+        let op = BackpropOp::new2(self, kernel, |arg, kernel| Op::Conv3D {
+            arg,
+            kernel,
+            padding: params.padding,
+            stride: params.stride,
+            dilation: params.dilation,
+        });
+        let out_dims = params.out_dims();
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            out_dims,
+            op,
+            false,
+        ))
+    }
+
+    /// Applies a 3D convolution over the input tensor.
+    ///
+    /// The input tensor should have five dimensions `(batch, c_in, d, h, w)` and the kernel
+    /// `(c_out, c_in / groups, k_d, k_h, k_w)`.
+    pub fn conv3d(
+        &self,
+        kernel: &Self,
+        padding: usize,
+        stride: usize,
+        dilation: usize,
+        groups: usize,
+    ) -> Result<Self> {
+        let (b_size, c_in, i_d, i_h, i_w) = self.dims5()?;
+        let (c_out, c_in_k, k_d, k_h, k_w) = kernel.dims5()?;
+        if c_in != c_in_k * groups {
+            crate::bail!(
+                "in_channel mismatch between input ({c_in}, groups {groups}) and kernel ({c_in_k})"
+            )
+        }
+        let params = ParamsConv3D {
+            b_size,
+            i_d,
+            i_h,
+            i_w,
+            k_d,
+            k_h,
+            k_w,
+            c_out: c_out / groups,
+            c_in: c_in / groups,
+            padding,
+            stride,
+            dilation,
+        };
+        if groups == 1 {
+            self.conv3d_single_group(kernel, &params)
+        } else {
+            let blocks = self.chunk(groups, 1)?;
+            let kernel = kernel.chunk(groups, 0)?;
+            let blocks = blocks
+                .iter()
+                .zip(&kernel)
+                .map(|(block, kernel)| block.conv3d_single_group(kernel, &params))
+                .collect::<Result<Vec<_>>>()?;
+            Tensor::cat(&blocks, 1)
+        }
     }
 }
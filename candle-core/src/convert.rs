@@ -1,4 +1,5 @@
 //! Implement conversion traits for tensors
+use crate::complex::{C128, C64};
 use crate::{DType, Device, Error, Tensor, WithDType};
 use half::{bf16, f16, slice::HalfFloatSliceExt};
 use std::convert::TryFrom;
@@ -93,8 +94,13 @@ from_tensor!(f32);
 from_tensor!(f16);
 from_tensor!(bf16);
 from_tensor!(i64);
+from_tensor!(i32);
+from_tensor!(i16);
+from_tensor!(i8);
 from_tensor!(u32);
 from_tensor!(u8);
+from_tensor!(C64);
+from_tensor!(C128);
 
 impl Tensor {
     pub fn write_bytes<W: std::io::Write>(&self, f: &mut W) -> crate::Result<()> {
@@ -125,6 +131,18 @@ impl Tensor {
                     f.write_f64::<LittleEndian>(v)?
                 }
             }
+            DType::C64 => {
+                for v in vs.to_vec1::<C64>()? {
+                    f.write_f32::<LittleEndian>(v.re)?;
+                    f.write_f32::<LittleEndian>(v.im)?
+                }
+            }
+            DType::C128 => {
+                for v in vs.to_vec1::<C128>()? {
+                    f.write_f64::<LittleEndian>(v.re)?;
+                    f.write_f64::<LittleEndian>(v.im)?
+                }
+            }
             DType::U32 => {
                 for v in vs.to_vec1::<u32>()? {
                     f.write_u32::<LittleEndian>(v)?
@@ -135,10 +153,29 @@ impl Tensor {
                     f.write_i64::<LittleEndian>(v)?
                 }
             }
+            DType::I32 => {
+                for v in vs.to_vec1::<i32>()? {
+                    f.write_i32::<LittleEndian>(v)?
+                }
+            }
+            DType::I16 => {
+                for v in vs.to_vec1::<i16>()? {
+                    f.write_i16::<LittleEndian>(v)?
+                }
+            }
+            DType::I8 => {
+                for v in vs.to_vec1::<i8>()? {
+                    f.write_i8(v)?
+                }
+            }
             DType::U8 => {
                 let vs = vs.to_vec1::<u8>()?;
                 f.write_all(&vs)?;
             }
+            DType::Bool => {
+                let vs = vs.to_dtype(DType::U8)?.to_vec1::<u8>()?;
+                f.write_all(&vs)?;
+            }
         }
         Ok(())
     }
@@ -51,6 +51,18 @@ impl Cpu<ARR> for CurrentCpu {
         _mm256_storeu_ps(mem_addr, a);
     }
 
+    unsafe fn vec_floor(a: Self::Unit) -> Self::Unit {
+        _mm256_floor_ps(a)
+    }
+
+    unsafe fn vec_ceil(a: Self::Unit) -> Self::Unit {
+        _mm256_ceil_ps(a)
+    }
+
+    unsafe fn vec_round(a: Self::Unit) -> Self::Unit {
+        _mm256_round_ps::<{ _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC }>(a)
+    }
+
     unsafe fn vec_reduce(mut x: Self::Array, y: *mut f32) {
         for i in 0..ARR / 2 {
             x[2 * i] = _mm256_add_ps(x[2 * i], x[2 * i + 1]);
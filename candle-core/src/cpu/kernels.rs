@@ -144,6 +144,39 @@ impl VecOps for u32 {
         <Self as Ord>::max(self, other)
     }
 }
+impl VecOps for i8 {
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        <Self as Ord>::min(self, other)
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        <Self as Ord>::max(self, other)
+    }
+}
+impl VecOps for i16 {
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        <Self as Ord>::min(self, other)
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        <Self as Ord>::max(self, other)
+    }
+}
+impl VecOps for i32 {
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        <Self as Ord>::min(self, other)
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        <Self as Ord>::max(self, other)
+    }
+}
 impl VecOps for i64 {
     #[inline(always)]
     fn min(self, other: Self) -> Self {
@@ -156,16 +189,81 @@ impl VecOps for i64 {
     }
 }
 
+// Complex numbers use the lexicographic ordering from their `PartialOrd` implementation.
+impl VecOps for crate::complex::C64 {
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        if self > other {
+            other
+        } else {
+            self
+        }
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        if self < other {
+            other
+        } else {
+            self
+        }
+    }
+}
+impl VecOps for crate::complex::C128 {
+    #[inline(always)]
+    fn min(self, other: Self) -> Self {
+        if self > other {
+            other
+        } else {
+            self
+        }
+    }
+
+    #[inline(always)]
+    fn max(self, other: Self) -> Self {
+        if self < other {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Element-wise floor of `xs` written to `ys`, this uses the simd rounding instructions when
+/// available.
+#[inline(always)]
+pub fn vec_floor_f32(xs: &[f32], ys: &mut [f32]) {
+    let len = xs.len().min(ys.len());
+    unsafe { super::vec_floor_f32(xs.as_ptr(), ys.as_mut_ptr(), len) }
+}
+
+/// Element-wise ceil of `xs` written to `ys`.
+#[inline(always)]
+pub fn vec_ceil_f32(xs: &[f32], ys: &mut [f32]) {
+    let len = xs.len().min(ys.len());
+    unsafe { super::vec_ceil_f32(xs.as_ptr(), ys.as_mut_ptr(), len) }
+}
+
+/// Element-wise rounding of `xs` written to `ys`, half-way cases are rounded to the nearest even
+/// integer.
+#[inline(always)]
+pub fn vec_round_f32(xs: &[f32], ys: &mut [f32]) {
+    let len = xs.len().min(ys.len());
+    unsafe { super::vec_round_f32(xs.as_ptr(), ys.as_mut_ptr(), len) }
+}
+
 #[inline(always)]
 pub fn par_for_each(n_threads: usize, func: impl Fn(usize) + Send + Sync) {
     if n_threads == 1 {
         func(0)
     } else {
-        rayon::scope(|s| {
-            for thread_idx in 0..n_threads {
-                let func = &func;
-                s.spawn(move |_| func(thread_idx));
-            }
+        crate::cpu_pool::install(|| {
+            rayon::scope(|s| {
+                for thread_idx in 0..n_threads {
+                    let func = &func;
+                    s.spawn(move |_| func(thread_idx));
+                }
+            })
         })
     }
 }
@@ -177,15 +275,17 @@ pub fn par_range(lo: usize, up: usize, n_threads: usize, func: impl Fn(usize) +
             func(i)
         }
     } else {
-        rayon::scope(|s| {
-            for thread_idx in 0..n_threads {
-                let func = &func;
-                s.spawn(move |_| {
-                    for i in (thread_idx..up).step_by(n_threads) {
-                        func(i)
-                    }
-                });
-            }
+        crate::cpu_pool::install(|| {
+            rayon::scope(|s| {
+                for thread_idx in 0..n_threads {
+                    let func = &func;
+                    s.spawn(move |_| {
+                        for i in (thread_idx..up).step_by(n_threads) {
+                            func(i)
+                        }
+                    });
+                }
+            })
         })
     }
 }
@@ -15,6 +15,10 @@ trait Cpu<const ARR: usize> {
     unsafe fn vec_reduce(x: Self::Array, y: *mut f32);
     unsafe fn from_f32(v: f32) -> Self::Unit;
     unsafe fn vec_store(mem_addr: *mut f32, a: Self::Unit);
+    unsafe fn vec_floor(a: Self::Unit) -> Self::Unit;
+    unsafe fn vec_ceil(a: Self::Unit) -> Self::Unit;
+    // Rounds half-way cases to the nearest even integer.
+    unsafe fn vec_round(a: Self::Unit) -> Self::Unit;
 }
 
 trait CpuF16<const ARR: usize> {
@@ -139,6 +143,45 @@ pub(crate) unsafe fn vec_sum(row: *const f32, b: *mut f32, k: usize) {
     }
 }
 
+macro_rules! vec_rounding {
+    ($fn_name:ident, $vec_fn:ident, $scalar_fn:ident) => {
+        #[cfg(any(
+            target_feature = "neon",
+            target_feature = "avx",
+            target_feature = "simd128"
+        ))]
+        #[inline(always)]
+        pub(crate) unsafe fn $fn_name(xs: *const f32, ys: *mut f32, k: usize) {
+            let np = k & !(CurrentCpu::EPR - 1);
+            for i in (0..np).step_by(CurrentCpu::EPR) {
+                let x = CurrentCpu::load(xs.add(i));
+                CurrentCpu::vec_store(ys.add(i), CurrentCpu::$vec_fn(x));
+            }
+
+            // leftovers
+            for i in np..k {
+                *ys.add(i) = (*xs.add(i)).$scalar_fn()
+            }
+        }
+
+        #[cfg(not(any(
+            target_feature = "neon",
+            target_feature = "avx",
+            target_feature = "simd128"
+        )))]
+        #[inline(always)]
+        pub(crate) unsafe fn $fn_name(xs: *const f32, ys: *mut f32, k: usize) {
+            for i in 0..k {
+                *ys.add(i) = (*xs.add(i)).$scalar_fn()
+            }
+        }
+    };
+}
+
+vec_rounding!(vec_floor_f32, vec_floor, floor);
+vec_rounding!(vec_ceil_f32, vec_ceil, ceil);
+vec_rounding!(vec_round_f32, vec_round, round_ties_even);
+
 #[cfg(target_feature = "avx")]
 #[inline(always)]
 pub(crate) unsafe fn vec_dot_f16(a_row: *const f16, b_row: *const f16, c: *mut f32, k: usize) {
@@ -21,6 +21,14 @@ impl CurrentCpu {
     unsafe fn reduce_one(x: float32x4_t) -> f32 {
         vgetq_lane_f32(x, 0) + vgetq_lane_f32(x, 1) + vgetq_lane_f32(x, 2) + vgetq_lane_f32(x, 3)
     }
+
+    // The vector rounding instructions are only available from armv8 onwards.
+    #[cfg(target_arch = "arm")]
+    unsafe fn map_lanes(x: float32x4_t, f: fn(f32) -> f32) -> float32x4_t {
+        let mut xs = [0f32; 4];
+        vst1q_f32(xs.as_mut_ptr(), x);
+        vld1q_f32(xs.map(f).as_ptr())
+    }
 }
 
 impl Cpu<ARR> for CurrentCpu {
@@ -62,6 +70,36 @@ impl Cpu<ARR> for CurrentCpu {
         vst1q_f32(mem_addr, a);
     }
 
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn vec_floor(a: Self::Unit) -> Self::Unit {
+        vrndmq_f32(a)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn vec_ceil(a: Self::Unit) -> Self::Unit {
+        vrndpq_f32(a)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn vec_round(a: Self::Unit) -> Self::Unit {
+        vrndnq_f32(a)
+    }
+
+    #[cfg(target_arch = "arm")]
+    unsafe fn vec_floor(a: Self::Unit) -> Self::Unit {
+        Self::map_lanes(a, f32::floor)
+    }
+
+    #[cfg(target_arch = "arm")]
+    unsafe fn vec_ceil(a: Self::Unit) -> Self::Unit {
+        Self::map_lanes(a, f32::ceil)
+    }
+
+    #[cfg(target_arch = "arm")]
+    unsafe fn vec_round(a: Self::Unit) -> Self::Unit {
+        Self::map_lanes(a, f32::round_ties_even)
+    }
+
     unsafe fn vec_reduce(mut x: Self::Array, y: *mut f32) {
         for i in 0..ARR / 2 {
             x[2 * i] = vaddq_f32(x[2 * i], x[2 * i + 1]);
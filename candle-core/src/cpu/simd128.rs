@@ -46,6 +46,18 @@ impl Cpu<ARR> for CurrentCpu {
         v128_store(mem_addr as *mut v128, a);
     }
 
+    unsafe fn vec_floor(a: Self::Unit) -> Self::Unit {
+        f32x4_floor(a)
+    }
+
+    unsafe fn vec_ceil(a: Self::Unit) -> Self::Unit {
+        f32x4_ceil(a)
+    }
+
+    unsafe fn vec_round(a: Self::Unit) -> Self::Unit {
+        f32x4_nearest(a)
+    }
+
     unsafe fn vec_reduce(mut x: Self::Array, y: *mut f32) {
         for i in 0..ARR / 2 {
             x[2 * i] = f32x4_add(x[2 * i], x[2 * i + 1]);
@@ -1,5 +1,6 @@
 use crate::backend::{BackendDevice, BackendStorage};
-use crate::op::{BinaryOpT, CmpOp, ReduceOp, UnaryOpT};
+use crate::complex::{C128, C64};
+use crate::op::{BinaryOpT, CmpOp, CumulativeOp, ReduceOp, UnaryOpT};
 use crate::{DType, Error, IntDType, Layout, Result, Shape, WithDType};
 use half::{bf16, f16};
 use rayon::prelude::*;
@@ -11,30 +12,84 @@ const USE_IM2COL_CONV2D: bool = true;
 // intercept the oom errors to avoid panicking and provide a proper error.
 #[derive(Debug, Clone)]
 pub enum CpuStorage {
+    // Booleans are stored as one byte per element holding either 0 or 1.
+    Bool(Vec<u8>),
     U8(Vec<u8>),
     U32(Vec<u32>),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
     I64(Vec<i64>),
     BF16(Vec<bf16>),
     F16(Vec<f16>),
     F32(Vec<f32>),
     F64(Vec<f64>),
+    C64(Vec<C64>),
+    C128(Vec<C128>),
 }
 
 #[derive(Debug, Clone)]
 pub struct CpuDevice;
 
+// Cached buffers are bucketed on the log2 of their capacity, a buffer with a capacity in
+// [2^k, 2^(k+1)) lands in bucket k. A request for `len` elements first looks for a large enough
+// buffer in the bucket of `len` itself and then falls back on the next bucket where all the
+// buffers are large enough. Fresh allocations use the exact requested capacity.
+fn take_cached_vec<T: WithDType>(len: usize) -> Option<Vec<T>> {
+    if len == 0 {
+        return None;
+    }
+    let bytes = len * T::DTYPE.size_in_bytes();
+    let bucket = (usize::BITS - 1 - len.leading_zeros()) as usize;
+    let mut pool = crate::memory::CPU_POOL.lock().unwrap();
+    let mut vs = match pool.remove::<Vec<T>>(bucket, bytes) {
+        Some(vs) => vs,
+        None if !len.is_power_of_two() => pool.remove::<Vec<T>>(bucket + 1, bytes)?,
+        None => return None,
+    };
+    vs.clear();
+    Some(vs)
+}
+
+fn cache_vec<T: WithDType>(pool: &mut crate::memory::MemoryPool, vs: Vec<T>) {
+    let capacity = vs.capacity();
+    if capacity == 0 {
+        return;
+    }
+    let bucket = (usize::BITS - 1 - capacity.leading_zeros()) as usize;
+    pool.insert(bucket, vs, capacity * T::DTYPE.size_in_bytes())
+}
+
+/// Returns an empty vector with a capacity of at least `len`, reusing a cached buffer if possible.
+pub fn alloc_vec<T: WithDType>(len: usize) -> Vec<T> {
+    take_cached_vec(len).unwrap_or_else(|| Vec::with_capacity(len))
+}
+
+/// Returns a vector of `len` elements set to `v`, reusing a cached buffer if possible.
+pub fn full_vec<T: WithDType>(len: usize, v: T) -> Vec<T> {
+    let mut vs = alloc_vec(len);
+    vs.resize(len, v);
+    vs
+}
+
 pub trait Map1 {
     fn f<T: WithDType>(&self, vs: &[T], layout: &Layout) -> Result<Vec<T>>;
 
     fn map(&self, vs: &CpuStorage, layout: &Layout) -> Result<CpuStorage> {
         match vs {
+            CpuStorage::Bool(vs) => Ok(CpuStorage::Bool(self.f(vs, layout)?)),
             CpuStorage::U8(vs) => Ok(CpuStorage::U8(self.f(vs, layout)?)),
             CpuStorage::U32(vs) => Ok(CpuStorage::U32(self.f(vs, layout)?)),
+            CpuStorage::I8(vs) => Ok(CpuStorage::I8(self.f(vs, layout)?)),
+            CpuStorage::I16(vs) => Ok(CpuStorage::I16(self.f(vs, layout)?)),
+            CpuStorage::I32(vs) => Ok(CpuStorage::I32(self.f(vs, layout)?)),
             CpuStorage::I64(vs) => Ok(CpuStorage::I64(self.f(vs, layout)?)),
             CpuStorage::BF16(vs) => Ok(CpuStorage::BF16(self.f(vs, layout)?)),
             CpuStorage::F16(vs) => Ok(CpuStorage::F16(self.f(vs, layout)?)),
             CpuStorage::F32(vs) => Ok(CpuStorage::F32(self.f(vs, layout)?)),
             CpuStorage::F64(vs) => Ok(CpuStorage::F64(self.f(vs, layout)?)),
+            CpuStorage::C64(vs) => Ok(CpuStorage::C64(self.f(vs, layout)?)),
+            CpuStorage::C128(vs) => Ok(CpuStorage::C128(self.f(vs, layout)?)),
         }
     }
 }
@@ -49,13 +104,19 @@ pub trait Map1Any {
 
     fn map(&self, vs: &CpuStorage, layout: &Layout) -> Result<CpuStorage> {
         match vs {
+            CpuStorage::Bool(vs) => Ok(self.f(vs, layout, CpuStorage::Bool)?),
             CpuStorage::U8(vs) => Ok(self.f(vs, layout, CpuStorage::U8)?),
             CpuStorage::U32(vs) => Ok(self.f(vs, layout, CpuStorage::U32)?),
+            CpuStorage::I8(vs) => Ok(self.f(vs, layout, CpuStorage::I8)?),
+            CpuStorage::I16(vs) => Ok(self.f(vs, layout, CpuStorage::I16)?),
+            CpuStorage::I32(vs) => Ok(self.f(vs, layout, CpuStorage::I32)?),
             CpuStorage::I64(vs) => Ok(self.f(vs, layout, CpuStorage::I64)?),
             CpuStorage::BF16(vs) => Ok(self.f(vs, layout, CpuStorage::BF16)?),
             CpuStorage::F16(vs) => Ok(self.f(vs, layout, CpuStorage::F16)?),
             CpuStorage::F32(vs) => Ok(self.f(vs, layout, CpuStorage::F32)?),
             CpuStorage::F64(vs) => Ok(self.f(vs, layout, CpuStorage::F64)?),
+            CpuStorage::C64(vs) => Ok(self.f(vs, layout, CpuStorage::C64)?),
+            CpuStorage::C128(vs) => Ok(self.f(vs, layout, CpuStorage::C128)?),
         }
     }
 }
@@ -73,13 +134,19 @@ pub trait Map2 {
         l2: &Layout,
     ) -> Result<CpuStorage> {
         match (v1, v2) {
+            (C::Bool(v1), C::Bool(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
             (C::U8(v1), C::U8(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
             (C::U32(v1), C::U32(v2)) => Ok(C::U32(self.f(v1, l1, v2, l2)?)),
+            (C::I8(v1), C::I8(v2)) => Ok(C::I8(self.f(v1, l1, v2, l2)?)),
+            (C::I16(v1), C::I16(v2)) => Ok(C::I16(self.f(v1, l1, v2, l2)?)),
+            (C::I32(v1), C::I32(v2)) => Ok(C::I32(self.f(v1, l1, v2, l2)?)),
             (C::I64(v1), C::I64(v2)) => Ok(C::I64(self.f(v1, l1, v2, l2)?)),
             (C::BF16(v1), C::BF16(v2)) => Ok(C::BF16(self.f(v1, l1, v2, l2)?)),
             (C::F16(v1), C::F16(v2)) => Ok(C::F16(self.f(v1, l1, v2, l2)?)),
             (C::F32(v1), C::F32(v2)) => Ok(C::F32(self.f(v1, l1, v2, l2)?)),
             (C::F64(v1), C::F64(v2)) => Ok(C::F64(self.f(v1, l1, v2, l2)?)),
+            (C::C64(v1), C::C64(v2)) => Ok(C::C64(self.f(v1, l1, v2, l2)?)),
+            (C::C128(v1), C::C128(v2)) => Ok(C::C128(self.f(v1, l1, v2, l2)?)),
             _ => Err(Error::DTypeMismatchBinaryOp {
                 lhs: v1.dtype(),
                 rhs: v2.dtype(),
@@ -102,13 +169,19 @@ pub trait Map2U8 {
         l2: &Layout,
     ) -> Result<CpuStorage> {
         match (v1, v2) {
-            (C::U8(v1), C::U8(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::U32(v1), C::U32(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::I64(v1), C::I64(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::BF16(v1), C::BF16(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::F16(v1), C::F16(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::F32(v1), C::F32(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
-            (C::F64(v1), C::F64(v2)) => Ok(C::U8(self.f(v1, l1, v2, l2)?)),
+            (C::Bool(v1), C::Bool(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::U8(v1), C::U8(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::U32(v1), C::U32(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::I8(v1), C::I8(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::I16(v1), C::I16(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::I32(v1), C::I32(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::I64(v1), C::I64(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::BF16(v1), C::BF16(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::F16(v1), C::F16(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::F32(v1), C::F32(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::F64(v1), C::F64(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::C64(v1), C::C64(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
+            (C::C128(v1), C::C128(v2)) => Ok(C::Bool(self.f(v1, l1, v2, l2)?)),
             _ => Err(Error::DTypeMismatchBinaryOp {
                 lhs: v1.dtype(),
                 rhs: v2.dtype(),
@@ -365,22 +438,172 @@ impl<'a> Map1 for ReduceSum<'a> {
     }
 }
 
-pub fn unary_map<T: Copy, U: Copy, F: FnMut(T) -> U>(
+struct Cumulative {
+    op: CumulativeOp,
+    dim: usize,
+}
+
+impl Map1 for Cumulative {
+    fn f<T: WithDType>(&self, src: &[T], src_l: &Layout) -> Result<Vec<T>> {
+        let mut dst: Vec<T> = match src_l.contiguous_offsets() {
+            Some((o1, o2)) => src[o1..o2].to_vec(),
+            None => src_l.strided_index().map(|i| src[i]).collect(),
+        };
+        let dims = src_l.dims();
+        let dim_size = dims[self.dim];
+        let post: usize = dims[self.dim + 1..].iter().product();
+        let pre: usize = dims[..self.dim].iter().product();
+        let f = match self.op {
+            CumulativeOp::Sum => |acc: T, v: T| acc + v,
+            CumulativeOp::Prod => |acc: T, v: T| acc * v,
+            CumulativeOp::Max => |acc: T, v: T| if v > acc { v } else { acc },
+        };
+        // The output is contiguous so the scan dimension has a stride of `post`, the accumulation
+        // is done in place moving from one slice along the dimension to the next.
+        for pre_i in 0..pre {
+            let base = pre_i * dim_size * post;
+            for d in 1..dim_size {
+                let prev = base + (d - 1) * post;
+                let cur = base + d * post;
+                for p in 0..post {
+                    dst[cur + p] = f(dst[prev + p], dst[cur + p])
+                }
+            }
+        }
+        Ok(dst)
+    }
+}
+
+// The transform is computed using double precision for all the complex dtypes.
+fn fft_<T: Copy>(
+    src: &[T],
+    src_l: &Layout,
+    dim: usize,
+    inverse: bool,
+    to_c: impl Fn(T) -> num_complex::Complex64,
+    from_c: impl Fn(num_complex::Complex64) -> T,
+) -> Vec<T> {
+    let mut dst: Vec<T> = match src_l.contiguous_offsets() {
+        Some((o1, o2)) => src[o1..o2].to_vec(),
+        None => src_l.strided_index().map(|i| src[i]).collect(),
+    };
+    let dims = src_l.dims();
+    let dim_size = dims[dim];
+    let post: usize = dims[dim + 1..].iter().product();
+    let pre: usize = dims[..dim].iter().product();
+    let mut lane = vec![num_complex::Complex64::default(); dim_size];
+    for pre_i in 0..pre {
+        for post_i in 0..post {
+            let base = pre_i * dim_size * post + post_i;
+            for (d, v) in lane.iter_mut().enumerate() {
+                *v = to_c(dst[base + d * post])
+            }
+            let lane = crate::fft::fft_vec(&lane, inverse);
+            for (d, &v) in lane.iter().enumerate() {
+                dst[base + d * post] = from_c(v)
+            }
+        }
+    }
+    dst
+}
+
+struct ArgSort {
+    dim: usize,
+    descending: bool,
+}
+
+impl ArgSort {
+    fn f<T: WithDType>(&self, src: &[T], src_l: &Layout) -> Vec<u32> {
+        let src: Vec<T> = match src_l.contiguous_offsets() {
+            Some((o1, o2)) => src[o1..o2].to_vec(),
+            None => src_l.strided_index().map(|i| src[i]).collect(),
+        };
+        let dims = src_l.dims();
+        let dim_size = dims[self.dim];
+        let post: usize = dims[self.dim + 1..].iter().product();
+        let pre: usize = dims[..self.dim].iter().product();
+        let mut dst = vec![0u32; src.len()];
+        let mut lane = Vec::with_capacity(dim_size);
+        for pre_i in 0..pre {
+            let base = pre_i * dim_size * post;
+            for p in 0..post {
+                let start = base + p;
+                lane.clear();
+                lane.extend(0..dim_size as u32);
+                // Use a stable sort so that equal elements keep their original order, nan values
+                // are considered larger than everything else so that the order stays total.
+                lane.sort_by(|&i, &j| {
+                    let vi = src[start + i as usize * post];
+                    let vj = src[start + j as usize * post];
+                    #[allow(clippy::eq_op)]
+                    let ord = vi
+                        .partial_cmp(&vj)
+                        .unwrap_or_else(|| (vi != vi).cmp(&(vj != vj)));
+                    if self.descending {
+                        ord.reverse()
+                    } else {
+                        ord
+                    }
+                });
+                for (d, &i) in lane.iter().enumerate() {
+                    dst[start + d * post] = i
+                }
+            }
+        }
+        dst
+    }
+
+    fn map(&self, vs: &CpuStorage, layout: &Layout) -> Result<CpuStorage> {
+        let dst = match vs {
+            CpuStorage::Bool(vs) => self.f(vs, layout),
+            CpuStorage::U8(vs) => self.f(vs, layout),
+            CpuStorage::U32(vs) => self.f(vs, layout),
+            CpuStorage::I8(vs) => self.f(vs, layout),
+            CpuStorage::I16(vs) => self.f(vs, layout),
+            CpuStorage::I32(vs) => self.f(vs, layout),
+            CpuStorage::I64(vs) => self.f(vs, layout),
+            CpuStorage::BF16(vs) => self.f(vs, layout),
+            CpuStorage::F16(vs) => self.f(vs, layout),
+            CpuStorage::F32(vs) => self.f(vs, layout),
+            CpuStorage::F64(vs) => self.f(vs, layout),
+            CpuStorage::C64(vs) => self.f(vs, layout),
+            CpuStorage::C128(vs) => self.f(vs, layout),
+        };
+        Ok(CpuStorage::U32(dst))
+    }
+}
+
+// Booleans are handled by the generic kernels using their u8 storage, this rejects them for the
+// ops where the result would not be a valid boolean.
+fn bail_on_bool(s: &CpuStorage, op: &'static str) -> Result<()> {
+    match s {
+        CpuStorage::Bool(_) => Err(Error::UnsupportedDTypeForOp(DType::Bool, op).bt()),
+        _ => Ok(()),
+    }
+}
+
+// Same as collect but reuses a cached buffer if possible.
+fn collect_vec<T: WithDType, I: Iterator<Item = T>>(len: usize, iter: I) -> Vec<T> {
+    let mut vs = alloc_vec(len);
+    vs.extend(iter);
+    vs
+}
+
+pub fn unary_map<T: Copy, U: WithDType, F: FnMut(T) -> U>(
     vs: &[T],
     layout: &Layout,
     mut f: F,
 ) -> Vec<U> {
     match layout.strided_blocks() {
-        crate::StridedBlocks::SingleBlock { start_offset, len } => vs
-            [start_offset..start_offset + len]
-            .iter()
-            .map(|&v| f(v))
-            .collect(),
+        crate::StridedBlocks::SingleBlock { start_offset, len } => collect_vec(
+            len,
+            vs[start_offset..start_offset + len].iter().map(|&v| f(v)),
+        ),
         crate::StridedBlocks::MultipleBlocks {
             block_start_index,
             block_len,
         } => {
-            let mut result = Vec::with_capacity(layout.shape().elem_count());
+            let mut result = alloc_vec(layout.shape().elem_count());
             // Specialize the case where block_len is one to avoid the second loop.
             if block_len == 1 {
                 for index in block_start_index {
@@ -400,7 +623,7 @@ pub fn unary_map<T: Copy, U: Copy, F: FnMut(T) -> U>(
     }
 }
 
-pub fn unary_map_vec<T: Copy, U: Copy, F: FnMut(T) -> U, FV: FnMut(&[T], &mut [U])>(
+pub fn unary_map_vec<T: Copy, U: WithDType, F: FnMut(T) -> U, FV: FnMut(&[T], &mut [U])>(
     vs: &[T],
     layout: &Layout,
     mut f: F,
@@ -408,7 +631,7 @@ pub fn unary_map_vec<T: Copy, U: Copy, F: FnMut(T) -> U, FV: FnMut(&[T], &mut [U
 ) -> Vec<U> {
     match layout.strided_blocks() {
         crate::StridedBlocks::SingleBlock { start_offset, len } => {
-            let mut ys: Vec<U> = Vec::with_capacity(len);
+            let mut ys: Vec<U> = alloc_vec(len);
             let ys_to_set = ys.spare_capacity_mut();
             let ys_to_set = unsafe { std::mem::transmute::<_, &mut [U]>(ys_to_set) };
             f_vec(&vs[start_offset..start_offset + len], ys_to_set);
@@ -423,14 +646,14 @@ pub fn unary_map_vec<T: Copy, U: Copy, F: FnMut(T) -> U, FV: FnMut(&[T], &mut [U
             let el_count = layout.shape().elem_count();
             // Specialize the case where block_len is one to avoid the second loop.
             if block_len == 1 {
-                let mut result = Vec::with_capacity(el_count);
+                let mut result = alloc_vec(el_count);
                 for index in block_start_index {
                     let v = unsafe { vs.get_unchecked(index) };
                     result.push(f(*v))
                 }
                 result
             } else {
-                let mut ys: Vec<U> = Vec::with_capacity(el_count);
+                let mut ys: Vec<U> = alloc_vec(el_count);
                 let ys_to_set = ys.spare_capacity_mut();
                 let ys_to_set = unsafe { std::mem::transmute::<_, &mut [U]>(ys_to_set) };
                 let mut dst_index = 0;
@@ -449,28 +672,31 @@ pub fn unary_map_vec<T: Copy, U: Copy, F: FnMut(T) -> U, FV: FnMut(&[T], &mut [U
 }
 
 // This function maps over two strided index sequences.
-pub fn binary_map<T: Copy, U: Copy, F: FnMut(T, T) -> U>(
+pub fn binary_map<T: Copy, U: WithDType, F: FnMut(T, T) -> U>(
     lhs_l: &Layout,
     rhs_l: &Layout,
     lhs: &[T],
     rhs: &[T],
     mut f: F,
 ) -> Vec<U> {
+    let el_count = lhs_l.shape().elem_count();
     match (lhs_l.contiguous_offsets(), rhs_l.contiguous_offsets()) {
-        (Some((o_l1, o_l2)), Some((o_r1, o_r2))) => lhs[o_l1..o_l2]
-            .iter()
-            .zip(rhs[o_r1..o_r2].iter())
-            .map(|(&l, &r)| f(l, r))
-            .collect(),
+        (Some((o_l1, o_l2)), Some((o_r1, o_r2))) => collect_vec(
+            el_count,
+            lhs[o_l1..o_l2]
+                .iter()
+                .zip(rhs[o_r1..o_r2].iter())
+                .map(|(&l, &r)| f(l, r)),
+        ),
         (Some((o_l1, o_l2)), None) => {
             // TODO: Maybe we want to avoid going through the layout twice.
             match rhs_l.offsets_b() {
                 Some(ob) => {
                     let mut i_in_block = 0;
                     let mut i_right_broadcast = 0;
-                    lhs[o_l1..o_l2]
-                        .iter()
-                        .map(|&l| {
+                    collect_vec(
+                        el_count,
+                        lhs[o_l1..o_l2].iter().map(|&l| {
                             let r = unsafe { rhs.get_unchecked(i_in_block + ob.start) };
                             i_right_broadcast += 1;
                             if i_right_broadcast >= ob.right_broadcast {
@@ -481,14 +707,16 @@ pub fn binary_map<T: Copy, U: Copy, F: FnMut(T, T) -> U>(
                                 i_in_block = 0
                             }
                             f(l, *r)
-                        })
-                        .collect()
+                        }),
+                    )
                 }
-                None => lhs_l
-                    .strided_index()
-                    .zip(rhs_l.strided_index())
-                    .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-                    .collect(),
+                None => collect_vec(
+                    el_count,
+                    lhs_l
+                        .strided_index()
+                        .zip(rhs_l.strided_index())
+                        .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+                ),
             }
         }
         (None, Some((o_r1, o_r2))) => {
@@ -497,9 +725,9 @@ pub fn binary_map<T: Copy, U: Copy, F: FnMut(T, T) -> U>(
                 Some(ob) => {
                     let mut i_in_block = 0;
                     let mut i_right_broadcast = 0;
-                    rhs[o_r1..o_r2]
-                        .iter()
-                        .map(|&r| {
+                    collect_vec(
+                        el_count,
+                        rhs[o_r1..o_r2].iter().map(|&r| {
                             let l = unsafe { lhs.get_unchecked(i_in_block + ob.start) };
                             i_right_broadcast += 1;
                             if i_right_broadcast >= ob.right_broadcast {
@@ -510,26 +738,30 @@ pub fn binary_map<T: Copy, U: Copy, F: FnMut(T, T) -> U>(
                                 i_in_block = 0
                             }
                             f(*l, r)
-                        })
-                        .collect()
+                        }),
+                    )
                 }
-                None => lhs_l
-                    .strided_index()
-                    .zip(rhs_l.strided_index())
-                    .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-                    .collect(),
+                None => collect_vec(
+                    el_count,
+                    lhs_l
+                        .strided_index()
+                        .zip(rhs_l.strided_index())
+                        .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+                ),
             }
         }
-        _ => lhs_l
-            .strided_index()
-            .zip(rhs_l.strided_index())
-            .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-            .collect(),
+        _ => collect_vec(
+            el_count,
+            lhs_l
+                .strided_index()
+                .zip(rhs_l.strided_index())
+                .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+        ),
     }
 }
 
 // Similar to binary_map but with vectorized variants.
-pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [T])>(
+pub fn binary_map_vec<T: WithDType, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [T])>(
     lhs_l: &Layout,
     rhs_l: &Layout,
     lhs: &[T],
@@ -540,7 +772,7 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
     let el_count = lhs_l.shape().elem_count();
     match (lhs_l.contiguous_offsets(), rhs_l.contiguous_offsets()) {
         (Some((o_l1, o_l2)), Some((o_r1, o_r2))) => {
-            let mut ys: Vec<T> = Vec::with_capacity(el_count);
+            let mut ys: Vec<T> = alloc_vec(el_count);
             let ys_to_set = ys.spare_capacity_mut();
             let ys_to_set = unsafe { std::mem::transmute::<_, &mut [T]>(ys_to_set) };
             f_vec(&lhs[o_l1..o_l2], &rhs[o_r1..o_r2], ys_to_set);
@@ -551,7 +783,7 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
         (Some((o_l1, o_l2)), None) => match rhs_l.offsets_b() {
             Some(ob) if ob.right_broadcast == 1 => {
                 let rhs = &rhs[ob.start..ob.start + ob.len];
-                let mut ys: Vec<T> = Vec::with_capacity(el_count);
+                let mut ys: Vec<T> = alloc_vec(el_count);
                 let ys_to_set = ys.spare_capacity_mut();
                 let ys_to_set = unsafe { std::mem::transmute::<_, &mut [T]>(ys_to_set) };
                 let mut dst_i = 0;
@@ -569,7 +801,8 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
             }
             Some(ob) => {
                 let rhs = &rhs[ob.start..ob.start + ob.len];
-                let mut ys = lhs[o_l1..o_l2].to_vec();
+                let mut ys = alloc_vec(el_count);
+                ys.extend_from_slice(&lhs[o_l1..o_l2]);
                 for idx_l in 0..ob.left_broadcast {
                     let start = idx_l * ob.len * ob.right_broadcast;
                     for (i, &r) in rhs.iter().enumerate() {
@@ -581,16 +814,18 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
                 }
                 ys
             }
-            None => lhs_l
-                .strided_index()
-                .zip(rhs_l.strided_index())
-                .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-                .collect(),
+            None => collect_vec(
+                el_count,
+                lhs_l
+                    .strided_index()
+                    .zip(rhs_l.strided_index())
+                    .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+            ),
         },
         (None, Some((o_r1, o_r2))) => match lhs_l.offsets_b() {
             Some(ob) if ob.right_broadcast == 1 => {
                 let lhs = &lhs[ob.start..ob.start + ob.len];
-                let mut ys: Vec<T> = Vec::with_capacity(el_count);
+                let mut ys: Vec<T> = alloc_vec(el_count);
                 let ys_to_set = ys.spare_capacity_mut();
                 let ys_to_set = unsafe { std::mem::transmute::<_, &mut [T]>(ys_to_set) };
                 let mut dst_i = 0;
@@ -608,7 +843,8 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
             }
             Some(ob) => {
                 let lhs = &lhs[ob.start..ob.start + ob.len];
-                let mut ys = rhs[o_r1..o_r2].to_vec();
+                let mut ys = alloc_vec(el_count);
+                ys.extend_from_slice(&rhs[o_r1..o_r2]);
                 for idx_l in 0..ob.left_broadcast {
                     let start = idx_l * ob.len * ob.right_broadcast;
                     for (i, &l) in lhs.iter().enumerate() {
@@ -620,17 +856,77 @@ pub fn binary_map_vec<T: Copy, F: FnMut(T, T) -> T, FV: FnMut(&[T], &[T], &mut [
                 }
                 ys
             }
-            None => lhs_l
+            None => collect_vec(
+                el_count,
+                lhs_l
+                    .strided_index()
+                    .zip(rhs_l.strided_index())
+                    .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+            ),
+        },
+        _ => collect_vec(
+            el_count,
+            lhs_l
                 .strided_index()
                 .zip(rhs_l.strided_index())
-                .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-                .collect(),
+                .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i])),
+        ),
+    }
+}
+
+// Same as binary_map but the results are written back to lhs.
+pub fn binary_map_inplace<T: Copy, F: FnMut(T, T) -> T>(
+    lhs_l: &Layout,
+    rhs_l: &Layout,
+    lhs: &mut [T],
+    rhs: &[T],
+    mut f: F,
+) {
+    match (lhs_l.contiguous_offsets(), rhs_l.contiguous_offsets()) {
+        (Some((o_l1, o_l2)), Some((o_r1, o_r2))) => lhs[o_l1..o_l2]
+            .iter_mut()
+            .zip(rhs[o_r1..o_r2].iter())
+            .for_each(|(l, &r)| *l = f(*l, r)),
+        (Some((o_l1, o_l2)), None) => match rhs_l.offsets_b() {
+            Some(ob) => {
+                let mut i_in_block = 0;
+                let mut i_right_broadcast = 0;
+                for l in lhs[o_l1..o_l2].iter_mut() {
+                    let r = unsafe { rhs.get_unchecked(i_in_block + ob.start) };
+                    i_right_broadcast += 1;
+                    if i_right_broadcast >= ob.right_broadcast {
+                        i_in_block += 1;
+                        i_right_broadcast = 0;
+                    }
+                    if i_in_block >= ob.len {
+                        i_in_block = 0
+                    }
+                    *l = f(*l, *r)
+                }
+            }
+            None => {
+                for (lhs_i, rhs_i) in lhs_l.strided_index().zip(rhs_l.strided_index()) {
+                    lhs[lhs_i] = f(lhs[lhs_i], rhs[rhs_i])
+                }
+            }
         },
-        _ => lhs_l
-            .strided_index()
-            .zip(rhs_l.strided_index())
-            .map(|(lhs_i, rhs_i)| f(lhs[lhs_i], rhs[rhs_i]))
-            .collect(),
+        _ => {
+            for (lhs_i, rhs_i) in lhs_l.strided_index().zip(rhs_l.strided_index()) {
+                lhs[lhs_i] = f(lhs[lhs_i], rhs[rhs_i])
+            }
+        }
+    }
+}
+
+// Same as unary_map but the results are written back to vs.
+pub fn unary_map_inplace<T: Copy, F: FnMut(T) -> T>(vs: &mut [T], layout: &Layout, mut f: F) {
+    match layout.contiguous_offsets() {
+        Some((o1, o2)) => vs[o1..o2].iter_mut().for_each(|v| *v = f(*v)),
+        None => {
+            for i in layout.strided_index() {
+                vs[i] = f(vs[i])
+            }
+        }
     }
 }
 
@@ -644,6 +940,32 @@ impl Map1 for Affine {
     }
 }
 
+impl Affine {
+    fn f_inplace<T: WithDType>(&self, vs: &mut [T], layout: &Layout) {
+        let mul = T::from_f64(self.0);
+        let add = T::from_f64(self.1);
+        unary_map_inplace(vs, layout, |v| v * mul + add)
+    }
+
+    fn map_inplace(&self, vs: &mut CpuStorage, layout: &Layout) {
+        match vs {
+            CpuStorage::Bool(vs) => self.f_inplace(vs, layout),
+            CpuStorage::U8(vs) => self.f_inplace(vs, layout),
+            CpuStorage::U32(vs) => self.f_inplace(vs, layout),
+            CpuStorage::I8(vs) => self.f_inplace(vs, layout),
+            CpuStorage::I16(vs) => self.f_inplace(vs, layout),
+            CpuStorage::I32(vs) => self.f_inplace(vs, layout),
+            CpuStorage::I64(vs) => self.f_inplace(vs, layout),
+            CpuStorage::BF16(vs) => self.f_inplace(vs, layout),
+            CpuStorage::F16(vs) => self.f_inplace(vs, layout),
+            CpuStorage::F32(vs) => self.f_inplace(vs, layout),
+            CpuStorage::F64(vs) => self.f_inplace(vs, layout),
+            CpuStorage::C64(vs) => self.f_inplace(vs, layout),
+            CpuStorage::C128(vs) => self.f_inplace(vs, layout),
+        }
+    }
+}
+
 struct AvgPool2D((usize, usize), (usize, usize));
 
 impl Map1 for AvgPool2D {
@@ -727,6 +1049,108 @@ impl Map1 for MaxPool2D {
     }
 }
 
+struct AvgPool3D((usize, usize, usize), (usize, usize, usize));
+
+impl Map1 for AvgPool3D {
+    fn f<T: WithDType>(&self, src: &[T], layout: &Layout) -> Result<Vec<T>> {
+        // https://pytorch.org/docs/stable/generated/torch.nn.AvgPool3d.html
+        let (k_d, k_h, k_w) = self.0;
+        let (s_d, s_h, s_w) = self.1;
+        let (b_sz, c, d, h, w) = layout.shape().dims5()?;
+        let stride = layout.stride();
+        let (stride_d, stride_h, stride_w) = (stride[2], stride[3], stride[4]);
+        let d_out = (d - k_d) / s_d + 1;
+        let h_out = (h - k_h) / s_h + 1;
+        let w_out = (w - k_w) / s_w + 1;
+        let src_index = layout.start_offset();
+        let mut dst = vec![T::zero(); b_sz * c * d_out * h_out * w_out];
+        let scale = 1f64 / (k_d * k_h * k_w) as f64;
+        let scale = T::from_f64(scale);
+        for b_idx in 0..b_sz {
+            let dst = &mut dst[b_idx * c * d_out * h_out * w_out..];
+            let src_index = src_index + b_idx * stride[0];
+            for c_idx in 0..c {
+                let dst = &mut dst[c_idx * d_out * h_out * w_out..];
+                let src_index = src_index + c_idx * stride[1];
+                for d_idx in 0..d_out {
+                    for h_idx in 0..h_out {
+                        for w_idx in 0..w_out {
+                            let mut sum = T::zero();
+                            for l in 0..k_d {
+                                for m in 0..k_h {
+                                    for n in 0..k_w {
+                                        let l = s_d * d_idx + l;
+                                        let m = s_h * h_idx + m;
+                                        let n = s_w * w_idx + n;
+                                        sum += src
+                                            [src_index + l * stride_d + m * stride_h + n * stride_w]
+                                    }
+                                }
+                            }
+                            dst[(d_idx * h_out + h_idx) * w_out + w_idx] = sum * scale;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(dst)
+    }
+}
+
+struct MaxPool3D((usize, usize, usize), (usize, usize, usize));
+
+impl Map1 for MaxPool3D {
+    fn f<T: WithDType>(&self, src: &[T], layout: &Layout) -> Result<Vec<T>> {
+        // https://pytorch.org/docs/stable/generated/torch.nn.MaxPool3d.html
+        let (k_d, k_h, k_w) = self.0;
+        let (s_d, s_h, s_w) = self.1;
+        let (b_sz, c, d, h, w) = layout.shape().dims5()?;
+        let stride = layout.stride();
+        let (stride_d, stride_h, stride_w) = (stride[2], stride[3], stride[4]);
+        let d_out = (d - k_d) / s_d + 1;
+        let h_out = (h - k_h) / s_h + 1;
+        let w_out = (w - k_w) / s_w + 1;
+        let src_index = layout.start_offset();
+        let mut dst = vec![T::zero(); b_sz * c * d_out * h_out * w_out];
+        for b_idx in 0..b_sz {
+            let dst = &mut dst[b_idx * c * d_out * h_out * w_out..];
+            let src_index = src_index + b_idx * stride[0];
+            for c_idx in 0..c {
+                let dst = &mut dst[c_idx * d_out * h_out * w_out..];
+                let src_index = src_index + c_idx * stride[1];
+                for d_idx in 0..d_out {
+                    for h_idx in 0..h_out {
+                        for w_idx in 0..w_out {
+                            let mut largest = src[src_index
+                                + s_d * d_idx * stride_d
+                                + s_h * h_idx * stride_h
+                                + s_w * w_idx * stride_w];
+                            for l in 0..k_d {
+                                for m in 0..k_h {
+                                    for n in 0..k_w {
+                                        let l = s_d * d_idx + l;
+                                        let m = s_h * h_idx + m;
+                                        let n = s_w * w_idx + n;
+                                        let v = src[src_index
+                                            + l * stride_d
+                                            + m * stride_h
+                                            + n * stride_w];
+                                        if largest < v {
+                                            largest = v
+                                        }
+                                    }
+                                }
+                            }
+                            dst[(d_idx * h_out + h_idx) * w_out + w_idx] = largest;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(dst)
+    }
+}
+
 struct UpsampleNearest1D(usize);
 
 impl Map1 for UpsampleNearest1D {
@@ -970,6 +1394,20 @@ impl<'a, I: IntDType> Map2 for IndexAdd<'a, I> {
         let dst_len = l1.shape().elem_count();
         let mut dst = vec![T::zero(); dst_len];
         copy_strided_src_(v1, &mut dst, 0, l1);
+        self.add_to(&mut dst, l1, src, src_l)?;
+        Ok(dst)
+    }
+}
+
+impl<'a, I: IntDType> IndexAdd<'a, I> {
+    // Adds the source values to `dst`, a contiguous buffer with the shape of `l1`.
+    fn add_to<T: WithDType>(
+        &self,
+        dst: &mut [T],
+        l1: &Layout,
+        src: &[T],
+        src_l: &Layout,
+    ) -> Result<()> {
         let src = match src_l.contiguous_offsets() {
             None => Err(Error::RequiresContiguous { op: "index-add" })?,
             Some((o1, o2)) => &src[o1..o2],
@@ -1018,7 +1456,54 @@ impl<'a, I: IntDType> Map2 for IndexAdd<'a, I> {
                 }
             }
         }
-        Ok(dst)
+        Ok(())
+    }
+
+    fn f_inplace<T: WithDType>(
+        &self,
+        dst: &mut [T],
+        l1: &Layout,
+        src: &[T],
+        src_l: &Layout,
+    ) -> Result<()> {
+        match l1.contiguous_offsets() {
+            Some((o1, o2)) => self.add_to(&mut dst[o1..o2], l1, src, src_l),
+            None => {
+                let res = self.f(dst, l1, src, src_l)?;
+                copy_strided_(&res, dst, &Layout::contiguous(l1.shape()), l1);
+                Ok(())
+            }
+        }
+    }
+
+    fn map_inplace(
+        &self,
+        dst: &mut CpuStorage,
+        l1: &Layout,
+        src: &CpuStorage,
+        src_l: &Layout,
+    ) -> Result<()> {
+        use CpuStorage as S;
+        match (dst, src) {
+            (S::U8(dst), S::U8(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::U32(dst), S::U32(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::I8(dst), S::I8(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::I16(dst), S::I16(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::I32(dst), S::I32(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::I64(dst), S::I64(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::BF16(dst), S::BF16(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::F16(dst), S::F16(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::F32(dst), S::F32(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::F64(dst), S::F64(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::C64(dst), S::C64(src)) => self.f_inplace(dst, l1, src, src_l),
+            (S::C128(dst), S::C128(src)) => self.f_inplace(dst, l1, src, src_l),
+            (dst, src) => Err(Error::DTypeMismatchBinaryOp {
+                lhs: dst.dtype(),
+                rhs: src.dtype(),
+                op: "index-add",
+            }
+            .bt()),
+        }
     }
 }
 
@@ -1060,6 +1545,17 @@ fn copy_strided_src_<T: Copy>(src: &[T], dst: &mut [T], dst_offset: usize, src_l
     }
 }
 
+fn copy_strided_<T: Copy>(src: &[T], dst: &mut [T], src_l: &Layout, dst_l: &Layout) {
+    match dst_l.contiguous_offsets() {
+        Some((o1, _)) => copy_strided_src_(src, dst, o1, src_l),
+        None => {
+            for (dst_i, src_i) in dst_l.strided_index().zip(src_l.strided_index()) {
+                dst[dst_i] = src[src_i]
+            }
+        }
+    }
+}
+
 struct Conv1D<'a>(&'a crate::conv::ParamsConv1D);
 
 impl<'a> Map2 for Conv1D<'a> {
@@ -1122,6 +1618,73 @@ impl<'a> Map2 for Conv1D<'a> {
     }
 }
 
+struct ConvTranspose1D<'a>(&'a crate::conv::ParamsConvTranspose1D);
+
+impl<'a> Map2 for ConvTranspose1D<'a> {
+    const OP: &'static str = "conv_transpose1d";
+    fn f<T: WithDType>(&self, inp: &[T], inp_l: &Layout, k: &[T], k_l: &Layout) -> Result<Vec<T>> {
+        let p = self.0;
+        let inp = &inp[inp_l.start_offset()..];
+        let (inp_s0, inp_s1, inp_s2) = crate::shape::dims3(inp_l.stride())?;
+        let k = &k[k_l.start_offset()..];
+        let (k_s0, k_s1, k_s2) = crate::shape::dims3(k_l.stride())?;
+        let l_out = p.l_out();
+
+        // Output shape: [b_size, c_out, l_out].
+        let dst = vec![T::zero(); p.b_size * p.c_out * l_out];
+        let dst_s0 = p.c_out * l_out;
+        let dst_s1 = l_out;
+
+        // TODO: Avoid making this copy if `inp` already has the appropriate layout.
+        let mut inp_cont = vec![T::zero(); p.b_size * p.c_in * p.l_in];
+        let cont_s0 = p.l_in * p.c_in;
+        let cont_s1 = p.c_in;
+        for b_idx in 0..p.b_size {
+            for l_idx in 0..p.l_in {
+                for c_idx in 0..p.c_in {
+                    let src_idx = b_idx * inp_s0 + c_idx * inp_s1 + l_idx * inp_s2;
+                    let dst_idx = b_idx * cont_s0 + l_idx * cont_s1 + c_idx;
+                    inp_cont[dst_idx] = inp[src_idx]
+                }
+            }
+        }
+
+        for k_idx in 0..p.k_size {
+            (0..p.c_out).into_par_iter().for_each(|dst_c_idx| {
+                let k_cont = (0..p.c_in)
+                    .map(|c_in_idx| k[c_in_idx * k_s0 + dst_c_idx * k_s1 + k_idx * k_s2])
+                    .collect::<Vec<_>>();
+                for b_idx in 0..p.b_size {
+                    for l_idx in 0..p.l_in {
+                        let out_idx = l_idx * p.stride + k_idx * p.dilation;
+                        if out_idx < p.padding {
+                            continue;
+                        }
+                        let out_idx = out_idx - p.padding;
+                        if out_idx < l_out {
+                            let inp_cont = &inp_cont[b_idx * cont_s0 + l_idx * cont_s1..];
+                            let dst_idx = b_idx * dst_s0 + out_idx + dst_c_idx * dst_s1;
+                            let mut d = T::zero();
+                            unsafe {
+                                T::vec_dot(inp_cont.as_ptr(), k_cont.as_ptr(), &mut d, p.c_in)
+                            }
+                            let dst_p = dst.as_ptr();
+                            // Safety: dst_idx are uniques per dst_c_idx which is used to parallelise
+                            // the different tasks so no two threads can try to write at the same
+                            // location.
+                            unsafe {
+                                let ptr = dst_p.add(dst_idx) as *mut T;
+                                *ptr += d
+                            }
+                        }
+                    }
+                }
+            })
+        }
+        Ok(dst)
+    }
+}
+
 struct Im2Col1D {
     l_k: usize,
     stride: usize,
@@ -1181,33 +1744,14 @@ impl Map1 for Im2Col1D {
     }
 }
 
-struct Im2Col {
-    h_k: usize,
-    w_k: usize,
-    stride: usize,
-    dilation: usize,
-    padding: usize,
-}
-
-impl Im2Col {
-    fn hw_out(&self, h: usize, w: usize) -> (usize, usize) {
-        let h_out = (h + 2 * self.padding - self.dilation * (self.h_k - 1) - 1) / self.stride + 1;
-        let w_out = (w + 2 * self.padding - self.dilation * (self.w_k - 1) - 1) / self.stride + 1;
-        (h_out, w_out)
-    }
-}
+struct Im2Col<'a>(&'a crate::conv::ParamsConv2D);
 
-impl Map1 for Im2Col {
+impl<'a> Map1 for Im2Col<'a> {
     fn f<T: WithDType>(&self, vs: &[T], layout: &Layout) -> Result<Vec<T>> {
-        let &Self {
-            h_k,
-            w_k,
-            stride,
-            dilation,
-            padding,
-        } = self;
+        let p = self.0;
+        let (h_k, w_k) = (p.k_h, p.k_w);
         let (b, c, h, w) = layout.shape().dims4()?;
-        let (h_out, w_out) = self.hw_out(h, w);
+        let (h_out, w_out) = (p.out_h(), p.out_w());
         let src = &vs[layout.start_offset()..];
         let mut dst = vec![T::zero(); b * h_out * w_out * c * h_k * w_k];
         let (src_s0, src_s1, src_s2, src_s3) = {
@@ -1230,19 +1774,20 @@ impl Map1 for Im2Col {
                         let dst_idx = dst_idx + c_idx * h_k * w_k;
                         let src_idx = c_idx * src_s1 + src_idx;
                         for h_k_idx in 0..h_k {
-                            let src_h = h_idx * stride + h_k_idx * dilation;
-                            if padding != 0 && (src_h < padding || src_h >= h + padding) {
-                                continue;
-                            }
-                            let src_h = src_h - padding;
+                            let src_h = h_idx * p.stride_h + h_k_idx * p.dilation_h;
+                            let src_h = match p.padding_mode.src_index(src_h, p.padding_top, h) {
+                                None => continue,
+                                Some(src_h) => src_h,
+                            };
                             let src_idx = src_idx + src_h * src_s2;
                             let dst_idx = dst_idx + h_k_idx * w_k;
                             for w_k_idx in 0..w_k {
-                                let src_w = w_idx * stride + w_k_idx * dilation;
-                                if padding != 0 && (src_w < padding || src_w >= w + padding) {
-                                    continue;
-                                }
-                                let src_w = src_w - padding;
+                                let src_w = w_idx * p.stride_w + w_k_idx * p.dilation_w;
+                                let src_w = match p.padding_mode.src_index(src_w, p.padding_left, w)
+                                {
+                                    None => continue,
+                                    Some(src_w) => src_w,
+                                };
                                 let src_idx = src_idx + src_w * src_s3;
                                 let dst_idx = dst_idx + w_k_idx;
                                 dst[dst_idx] = src[src_idx]
@@ -1305,18 +1850,20 @@ impl<'a> Map2 for Conv2D<'a> {
                         let dst_idx = dst_idx + b_idx * p.c_out * out_h * out_w;
                         for dst_h in 0..out_h {
                             let dst_idx = dst_idx + dst_h * out_w;
-                            let src_h = p.stride * dst_h + offset_h * p.dilation;
-                            if src_h < p.padding || src_h >= p.i_h + p.padding {
-                                continue;
-                            }
-                            let src_h = src_h - p.padding;
+                            let src_h = p.stride_h * dst_h + offset_h * p.dilation_h;
+                            let src_h = match p.padding_mode.src_index(src_h, p.padding_top, p.i_h)
+                            {
+                                None => continue,
+                                Some(src_h) => src_h,
+                            };
                             for dst_w in 0..out_w {
                                 let dst_idx = dst_idx + dst_w;
-                                let src_w = p.stride * dst_w + offset_w * p.dilation;
-                                if src_w < p.padding || src_w >= p.i_w + p.padding {
-                                    continue;
-                                }
-                                let src_w = src_w - p.padding;
+                                let src_w = p.stride_w * dst_w + offset_w * p.dilation_w;
+                                let src_w =
+                                    match p.padding_mode.src_index(src_w, p.padding_left, p.i_w) {
+                                        None => continue,
+                                        Some(src_w) => src_w,
+                                    };
                                 let inp_cont = &inp_cont
                                     [b_idx * cont_s0 + src_h * cont_s1 + src_w * cont_s2..];
                                 assert!(inp_cont.len() >= p.c_in);
@@ -1344,30 +1891,146 @@ impl<'a> Map2 for Conv2D<'a> {
     }
 }
 
-struct ConvTranspose2D<'a>(&'a crate::conv::ParamsConvTranspose2D);
+struct Conv3D<'a>(&'a crate::conv::ParamsConv3D);
 
-impl<'a> Map2 for ConvTranspose2D<'a> {
-    const OP: &'static str = "conv_transpose2d";
+impl<'a> Map2 for Conv3D<'a> {
+    const OP: &'static str = "conv3d";
     fn f<T: WithDType>(&self, inp: &[T], inp_l: &Layout, k: &[T], k_l: &Layout) -> Result<Vec<T>> {
         let p = self.0;
         let inp = &inp[inp_l.start_offset()..];
-        let (inp_s0, inp_s1, inp_s2, inp_s3) = crate::shape::dims4(inp_l.stride())?;
+        let (inp_s0, inp_s1, inp_s2, inp_s3, inp_s4) = crate::shape::dims5(inp_l.stride())?;
         let k = &k[k_l.start_offset()..];
-        let (k_s0, k_s1, k_s2, k_s3) = crate::shape::dims4(k_l.stride())?;
-        let (out_h, out_w) = (p.out_h(), p.out_w());
+        let (k_s0, k_s1, k_s2, k_s3, k_s4) = crate::shape::dims5(k_l.stride())?;
+        let (out_d, out_h, out_w) = (p.out_d(), p.out_h(), p.out_w());
 
-        // Output shape: [b_size, c_out, out_h, out_w].
-        let dst = vec![T::zero(); p.b_size * p.c_out * out_h * out_w];
-        let dst_s0 = p.c_out * out_h * out_w;
-        let dst_s1 = out_h * out_w;
-        let dst_s2 = out_w;
-        let dst_s3 = 1;
+        // Output shape: [b_size, c_out, out_d, out_h, out_w].
+        let dst = vec![T::zero(); p.b_size * p.c_out * out_d * out_h * out_w];
 
         // TODO: Avoid making this copy if `inp` already has the appropriate layout.
-        let mut inp_cont = vec![T::zero(); p.b_size * p.c_in * p.i_h * p.i_w];
-        let cont_s0 = p.i_h * p.i_w * p.c_in;
-        let cont_s1 = p.i_w * p.c_in;
-        let cont_s2 = p.c_in;
+        let mut inp_cont = vec![T::zero(); p.b_size * p.c_in * p.i_d * p.i_h * p.i_w];
+        let cont_s0 = p.i_d * p.i_h * p.i_w * p.c_in;
+        let cont_s1 = p.i_h * p.i_w * p.c_in;
+        let cont_s2 = p.i_w * p.c_in;
+        let cont_s3 = p.c_in;
+        for b_idx in 0..p.b_size {
+            for d_idx in 0..p.i_d {
+                for h_idx in 0..p.i_h {
+                    for w_idx in 0..p.i_w {
+                        for c_idx in 0..p.c_in {
+                            let src_idx = b_idx * inp_s0
+                                + c_idx * inp_s1
+                                + d_idx * inp_s2
+                                + h_idx * inp_s3
+                                + w_idx * inp_s4;
+                            let dst_idx = b_idx * cont_s0
+                                + d_idx * cont_s1
+                                + h_idx * cont_s2
+                                + w_idx * cont_s3
+                                + c_idx;
+                            inp_cont[dst_idx] = inp[src_idx]
+                        }
+                    }
+                }
+            }
+        }
+
+        for offset_d in 0..p.k_d {
+            for offset_h in 0..p.k_h {
+                for offset_w in 0..p.k_w {
+                    (0..p.c_out).into_par_iter().for_each(|dst_c_idx| {
+                        let dst_idx = dst_c_idx * out_d * out_h * out_w;
+                        let k_cont = (0..p.c_in)
+                            .map(|c_in_idx| {
+                                k[dst_c_idx * k_s0
+                                    + c_in_idx * k_s1
+                                    + offset_d * k_s2
+                                    + offset_h * k_s3
+                                    + offset_w * k_s4]
+                            })
+                            .collect::<Vec<_>>();
+                        for b_idx in 0..p.b_size {
+                            let dst_idx = dst_idx + b_idx * p.c_out * out_d * out_h * out_w;
+                            for dst_d in 0..out_d {
+                                let dst_idx = dst_idx + dst_d * out_h * out_w;
+                                let src_d = p.stride * dst_d + offset_d * p.dilation;
+                                if src_d < p.padding || src_d >= p.i_d + p.padding {
+                                    continue;
+                                }
+                                let src_d = src_d - p.padding;
+                                for dst_h in 0..out_h {
+                                    let dst_idx = dst_idx + dst_h * out_w;
+                                    let src_h = p.stride * dst_h + offset_h * p.dilation;
+                                    if src_h < p.padding || src_h >= p.i_h + p.padding {
+                                        continue;
+                                    }
+                                    let src_h = src_h - p.padding;
+                                    for dst_w in 0..out_w {
+                                        let dst_idx = dst_idx + dst_w;
+                                        let src_w = p.stride * dst_w + offset_w * p.dilation;
+                                        if src_w < p.padding || src_w >= p.i_w + p.padding {
+                                            continue;
+                                        }
+                                        let src_w = src_w - p.padding;
+                                        let inp_cont = &inp_cont[b_idx * cont_s0
+                                            + src_d * cont_s1
+                                            + src_h * cont_s2
+                                            + src_w * cont_s3..];
+                                        assert!(inp_cont.len() >= p.c_in);
+                                        assert!(k_cont.len() >= p.c_in);
+                                        let mut d = T::zero();
+                                        unsafe {
+                                            T::vec_dot(
+                                                inp_cont.as_ptr(),
+                                                k_cont.as_ptr(),
+                                                &mut d,
+                                                p.c_in,
+                                            )
+                                        }
+                                        let dst_p = dst.as_ptr();
+                                        // Safety: dst_idx are uniques per dst_c_idx which is used
+                                        // to parallelise the different tasks so no two threads can
+                                        // try to write at the same location.
+                                        unsafe {
+                                            let ptr = dst_p.add(dst_idx) as *mut T;
+                                            *ptr += d
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    });
+                }
+            }
+        }
+
+        Ok(dst)
+    }
+}
+
+struct ConvTranspose2D<'a>(&'a crate::conv::ParamsConvTranspose2D);
+
+impl<'a> Map2 for ConvTranspose2D<'a> {
+    const OP: &'static str = "conv_transpose2d";
+    fn f<T: WithDType>(&self, inp: &[T], inp_l: &Layout, k: &[T], k_l: &Layout) -> Result<Vec<T>> {
+        let p = self.0;
+        let inp = &inp[inp_l.start_offset()..];
+        let (inp_s0, inp_s1, inp_s2, inp_s3) = crate::shape::dims4(inp_l.stride())?;
+        let k = &k[k_l.start_offset()..];
+        let (k_s0, k_s1, k_s2, k_s3) = crate::shape::dims4(k_l.stride())?;
+        let (out_h, out_w) = (p.out_h(), p.out_w());
+
+        // Output shape: [b_size, c_out, out_h, out_w].
+        let dst = vec![T::zero(); p.b_size * p.c_out * out_h * out_w];
+        let dst_s0 = p.c_out * out_h * out_w;
+        let dst_s1 = out_h * out_w;
+        let dst_s2 = out_w;
+        let dst_s3 = 1;
+
+        // TODO: Avoid making this copy if `inp` already has the appropriate layout.
+        let mut inp_cont = vec![T::zero(); p.b_size * p.c_in * p.i_h * p.i_w];
+        let cont_s0 = p.i_h * p.i_w * p.c_in;
+        let cont_s1 = p.i_w * p.c_in;
+        let cont_s2 = p.c_in;
         for b_idx in 0..p.b_size {
             for h_idx in 0..p.i_h {
                 for w_idx in 0..p.i_w {
@@ -1498,41 +2161,44 @@ impl Map2 for MatMul {
         let dst_rs = dst_strides[0];
         let dst_cs = dst_strides[1];
 
-        let mut dst = vec![T::zero(); b * m * n];
-        let num_threads = crate::utils::get_num_threads();
-        let parallelism = if num_threads > 1 {
-            Parallelism::Rayon(num_threads)
-        } else {
-            Parallelism::None
-        };
-        for step in 0..b {
-            let lhs_p = &lhs[step * a_skip..];
-            let rhs_p = &rhs[step * b_skip..];
-            let dst_p = &mut dst[step * c_skip..];
-            unsafe {
-                gemm(
-                    /* m: usize = */ m,
-                    /* n: usize = */ n,
-                    /* k: usize = */ k,
-                    /* dst: *mut T = */ dst_p.as_mut_ptr(),
-                    /* dst_cs: isize = */ dst_cs as isize,
-                    /* dst_rs: isize = */ dst_rs as isize,
-                    /* read_dst: bool = */ false,
-                    /* lhs: *const T = */ lhs_p.as_ptr(),
-                    /* lhs_cs: isize = */ lhs_cs as isize,
-                    /* lhs_rs: isize = */ lhs_rs as isize,
-                    /* rhs: *const T = */ rhs_p.as_ptr(),
-                    /* rhs_cs: isize = */ rhs_cs as isize,
-                    /* rhs_rs: isize = */ rhs_rs as isize,
-                    /* alpha: T = */ T::zero(),
-                    /* beta: T = */ T::one(),
-                    /* conj_dst: bool = */ false,
-                    /* conj_lhs: bool = */ false,
-                    /* conj_rhs: bool = */ false,
-                    parallelism,
-                )
+        let mut dst = full_vec(b * m * n, T::zero());
+        // gemm spawns its tasks on the current rayon pool.
+        crate::cpu_pool::install(|| {
+            let num_threads = crate::utils::get_num_threads();
+            let parallelism = if num_threads > 1 {
+                Parallelism::Rayon(num_threads)
+            } else {
+                Parallelism::None
+            };
+            for step in 0..b {
+                let lhs_p = &lhs[step * a_skip..];
+                let rhs_p = &rhs[step * b_skip..];
+                let dst_p = &mut dst[step * c_skip..];
+                unsafe {
+                    gemm(
+                        /* m: usize = */ m,
+                        /* n: usize = */ n,
+                        /* k: usize = */ k,
+                        /* dst: *mut T = */ dst_p.as_mut_ptr(),
+                        /* dst_cs: isize = */ dst_cs as isize,
+                        /* dst_rs: isize = */ dst_rs as isize,
+                        /* read_dst: bool = */ false,
+                        /* lhs: *const T = */ lhs_p.as_ptr(),
+                        /* lhs_cs: isize = */ lhs_cs as isize,
+                        /* lhs_rs: isize = */ lhs_rs as isize,
+                        /* rhs: *const T = */ rhs_p.as_ptr(),
+                        /* rhs_cs: isize = */ rhs_cs as isize,
+                        /* rhs_rs: isize = */ rhs_rs as isize,
+                        /* alpha: T = */ T::zero(),
+                        /* beta: T = */ T::one(),
+                        /* conj_dst: bool = */ false,
+                        /* conj_lhs: bool = */ false,
+                        /* conj_rhs: bool = */ false,
+                        parallelism,
+                    )
+                }
             }
-        }
+        });
         Ok(dst)
     }
 
@@ -1587,7 +2253,7 @@ impl Map2 for MatMul {
             Err(self.striding_error(lhs_l, rhs_l, "non-contiguous lhs"))?
         };
 
-        let mut dst = vec![T::zero(); b * m * n];
+        let mut dst = full_vec(b * m * n, T::zero());
         match T::DTYPE {
             DType::F16 => {
                 crate::bail!("the accelerate backend does not support f16 matmul")
@@ -1690,7 +2356,7 @@ impl Map2 for MatMul {
             Err(self.striding_error(lhs_l, rhs_l, "non-contiguous lhs"))?
         };
 
-        let mut dst = vec![T::zero(); b * m * n];
+        let mut dst = full_vec(b * m * n, T::zero());
         match T::DTYPE {
             DType::F16 => {
                 for step in 0..b {
@@ -1783,6 +2449,52 @@ impl CpuStorage {
         D::cpu_storage_as_slice(self)
     }
 
+    // The memory held by the buffer, this includes its spare capacity.
+    fn capacity_in_bytes(&self) -> usize {
+        let capacity = match self {
+            Self::Bool(vs) => vs.capacity(),
+            Self::U8(vs) => vs.capacity(),
+            Self::U32(vs) => vs.capacity(),
+            Self::I8(vs) => vs.capacity(),
+            Self::I16(vs) => vs.capacity(),
+            Self::I32(vs) => vs.capacity(),
+            Self::I64(vs) => vs.capacity(),
+            Self::BF16(vs) => vs.capacity(),
+            Self::F16(vs) => vs.capacity(),
+            Self::F32(vs) => vs.capacity(),
+            Self::F64(vs) => vs.capacity(),
+            Self::C64(vs) => vs.capacity(),
+            Self::C128(vs) => vs.capacity(),
+        };
+        capacity * self.dtype().size_in_bytes()
+    }
+
+    pub(crate) fn track_alloc(&self) {
+        let bytes = self.capacity_in_bytes();
+        crate::memory::CPU_POOL.lock().unwrap().track_alloc(bytes)
+    }
+
+    // Called once no tensor uses this storage anymore, the buffer is cached for later reuse.
+    pub(crate) fn release(self) {
+        let bytes = self.capacity_in_bytes();
+        let mut pool = crate::memory::CPU_POOL.lock().unwrap();
+        pool.track_free(bytes);
+        match self {
+            Self::Bool(vs) | Self::U8(vs) => cache_vec(&mut pool, vs),
+            Self::U32(vs) => cache_vec(&mut pool, vs),
+            Self::I8(vs) => cache_vec(&mut pool, vs),
+            Self::I16(vs) => cache_vec(&mut pool, vs),
+            Self::I32(vs) => cache_vec(&mut pool, vs),
+            Self::I64(vs) => cache_vec(&mut pool, vs),
+            Self::BF16(vs) => cache_vec(&mut pool, vs),
+            Self::F16(vs) => cache_vec(&mut pool, vs),
+            Self::F32(vs) => cache_vec(&mut pool, vs),
+            Self::F64(vs) => cache_vec(&mut pool, vs),
+            Self::C64(vs) => cache_vec(&mut pool, vs),
+            Self::C128(vs) => cache_vec(&mut pool, vs),
+        }
+    }
+
     pub fn concat(storages: &[CpuStorage]) -> Result<CpuStorage> {
         let storage0 = &storages[0];
         let s = match storage0 {
@@ -1808,6 +2520,50 @@ impl CpuStorage {
                     .concat();
                 Self::U32(storages)
             }
+            Self::Bool(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::Bool(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::Bool(storages)
+            }
+            Self::I8(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::I8(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::I8(storages)
+            }
+            Self::I16(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::I16(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::I16(storages)
+            }
+            Self::I32(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::I32(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::I32(storages)
+            }
             Self::I64(_) => {
                 let storages = storages
                     .iter()
@@ -1863,6 +2619,28 @@ impl CpuStorage {
                     .concat();
                 Self::F64(storages)
             }
+            Self::C64(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::C64(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::C64(storages)
+            }
+            Self::C128(_) => {
+                let storages = storages
+                    .iter()
+                    .map(|s| match s {
+                        Self::C128(s) => Ok(s.as_slice()),
+                        _ => crate::bail!("dtype mismatch"),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                Self::C128(storages)
+            }
         };
         Ok(s)
     }
@@ -1873,13 +2651,19 @@ impl BackendStorage for CpuStorage {
 
     fn dtype(&self) -> DType {
         match self {
+            Self::Bool(_) => DType::Bool,
             Self::U8(_) => DType::U8,
             Self::U32(_) => DType::U32,
+            Self::I8(_) => DType::I8,
+            Self::I16(_) => DType::I16,
+            Self::I32(_) => DType::I32,
             Self::I64(_) => DType::I64,
             Self::BF16(_) => DType::BF16,
             Self::F16(_) => DType::F16,
             Self::F32(_) => DType::F32,
             Self::F64(_) => DType::F64,
+            Self::C64(_) => DType::C64,
+            Self::C128(_) => DType::C128,
         }
     }
 
@@ -2082,12 +2866,551 @@ impl BackendStorage for CpuStorage {
                 let data = unary_map(storage, layout, |v| v);
                 Ok(Self::F64(data))
             }
+            (Self::Bool(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::Bool(data))
+            }
+            (Self::U8(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::U32(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::I8(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::I16(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::I32(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::I64(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0));
+                Ok(Self::Bool(data))
+            }
+            (Self::BF16(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v.to_f32() != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::F16(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v.to_f32() != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::F32(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::F64(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::Bool(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::U8(data))
+            }
+            (Self::I8(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v as u8);
+                Ok(Self::U8(data))
+            }
+            (Self::I16(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v as u8);
+                Ok(Self::U8(data))
+            }
+            (Self::I32(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v as u8);
+                Ok(Self::U8(data))
+            }
+            (Self::Bool(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::I8(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::I16(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::I32(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::Bool(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::U8(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::U32(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::I8(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::I8(data))
+            }
+            (Self::I16(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::I32(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::I64(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::BF16(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::F16(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::F32(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::F64(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::Bool(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::U8(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::U32(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::I8(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::I16(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::I16(data))
+            }
+            (Self::I32(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::I64(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::BF16(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::F16(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::F32(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::F64(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::Bool(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::U8(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::U32(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::I8(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::I16(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::I32(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::I32(data))
+            }
+            (Self::I64(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::BF16(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::F16(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v.to_f32() as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::F32(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::F64(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::Bool(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::I8(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::I16(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::I32(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::Bool(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f32(v as f32));
+                Ok(Self::BF16(data))
+            }
+            (Self::I8(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f32(v as f32));
+                Ok(Self::BF16(data))
+            }
+            (Self::I16(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f32(v as f32));
+                Ok(Self::BF16(data))
+            }
+            (Self::I32(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f32(v as f32));
+                Ok(Self::BF16(data))
+            }
+            (Self::Bool(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f32(v as f32));
+                Ok(Self::F16(data))
+            }
+            (Self::I8(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f32(v as f32));
+                Ok(Self::F16(data))
+            }
+            (Self::I16(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f32(v as f32));
+                Ok(Self::F16(data))
+            }
+            (Self::I32(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f32(v as f32));
+                Ok(Self::F16(data))
+            }
+            (Self::Bool(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v as f32);
+                Ok(Self::F32(data))
+            }
+            (Self::I8(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v as f32);
+                Ok(Self::F32(data))
+            }
+            (Self::I16(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v as f32);
+                Ok(Self::F32(data))
+            }
+            (Self::I32(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v as f32);
+                Ok(Self::F32(data))
+            }
+            (Self::Bool(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v as f64);
+                Ok(Self::F64(data))
+            }
+            (Self::I8(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v as f64);
+                Ok(Self::F64(data))
+            }
+            (Self::I16(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v as f64);
+                Ok(Self::F64(data))
+            }
+            (Self::I32(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v as f64);
+                Ok(Self::F64(data))
+            }
+            (Self::Bool(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::U8(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::U32(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::I8(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::I16(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::I32(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::I64(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::BF16(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v.to_f32(), 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::F16(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v.to_f32(), 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::F32(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::F64(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v as f32, 0.));
+                Ok(Self::C64(data))
+            }
+            (Self::Bool(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::U8(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::U32(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::I8(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::I16(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::I32(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::I64(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::BF16(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v.to_f64(), 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::F16(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v.to_f64(), 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::F32(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v as f64, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::F64(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v, 0.));
+                Ok(Self::C128(data))
+            }
+            (Self::C64(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v.re != 0. || v.im != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::C64(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v.re as u8);
+                Ok(Self::U8(data))
+            }
+            (Self::C64(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v.re as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::C64(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v.re as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::C64(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v.re as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::C64(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v.re as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::C64(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v.re as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::C64(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f32(v.re));
+                Ok(Self::BF16(data))
+            }
+            (Self::C64(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f32(v.re));
+                Ok(Self::F16(data))
+            }
+            (Self::C64(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v.re);
+                Ok(Self::F32(data))
+            }
+            (Self::C64(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v.re as f64);
+                Ok(Self::F64(data))
+            }
+            (Self::C128(storage), DType::Bool) => {
+                let data = unary_map(storage, layout, |v| u8::from(v.re != 0. || v.im != 0.));
+                Ok(Self::Bool(data))
+            }
+            (Self::C128(storage), DType::U8) => {
+                let data = unary_map(storage, layout, |v| v.re as u8);
+                Ok(Self::U8(data))
+            }
+            (Self::C128(storage), DType::U32) => {
+                let data = unary_map(storage, layout, |v| v.re as u32);
+                Ok(Self::U32(data))
+            }
+            (Self::C128(storage), DType::I8) => {
+                let data = unary_map(storage, layout, |v| v.re as i8);
+                Ok(Self::I8(data))
+            }
+            (Self::C128(storage), DType::I16) => {
+                let data = unary_map(storage, layout, |v| v.re as i16);
+                Ok(Self::I16(data))
+            }
+            (Self::C128(storage), DType::I32) => {
+                let data = unary_map(storage, layout, |v| v.re as i32);
+                Ok(Self::I32(data))
+            }
+            (Self::C128(storage), DType::I64) => {
+                let data = unary_map(storage, layout, |v| v.re as i64);
+                Ok(Self::I64(data))
+            }
+            (Self::C128(storage), DType::BF16) => {
+                let data = unary_map(storage, layout, |v| bf16::from_f64(v.re));
+                Ok(Self::BF16(data))
+            }
+            (Self::C128(storage), DType::F16) => {
+                let data = unary_map(storage, layout, |v| f16::from_f64(v.re));
+                Ok(Self::F16(data))
+            }
+            (Self::C128(storage), DType::F32) => {
+                let data = unary_map(storage, layout, |v| v.re as f32);
+                Ok(Self::F32(data))
+            }
+            (Self::C128(storage), DType::F64) => {
+                let data = unary_map(storage, layout, |v| v.re);
+                Ok(Self::F64(data))
+            }
+            (Self::C64(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::C64(data))
+            }
+            (Self::C128(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::C128(data))
+            }
+            (Self::C64(storage), DType::C128) => {
+                let data = unary_map(storage, layout, |v| C128::new(v.re as f64, v.im as f64));
+                Ok(Self::C128(data))
+            }
+            (Self::C128(storage), DType::C64) => {
+                let data = unary_map(storage, layout, |v| C64::new(v.re as f32, v.im as f32));
+                Ok(Self::C64(data))
+            }
+        }
+    }
+
+    fn cumulative_op(&self, op: CumulativeOp, layout: &Layout, dim: usize) -> Result<Self> {
+        if op != CumulativeOp::Max {
+            bail_on_bool(self, op.name())?;
+        }
+        Cumulative { op, dim }.map(self, layout)
+    }
+
+    fn arg_sort(&self, layout: &Layout, dim: usize, descending: bool) -> Result<Self> {
+        ArgSort { dim, descending }.map(self, layout)
+    }
+
+    fn fft(&self, layout: &Layout, dim: usize, inverse: bool) -> Result<Self> {
+        use num_complex::Complex64;
+        match self {
+            Self::C64(storage) => {
+                let to_c = |v: C64| Complex64::new(v.re as f64, v.im as f64);
+                let from_c = |v: Complex64| C64::new(v.re as f32, v.im as f32);
+                let data = fft_(storage, layout, dim, inverse, to_c, from_c);
+                Ok(Self::C64(data))
+            }
+            Self::C128(storage) => {
+                let data = fft_(storage, layout, dim, inverse, Complex64::from, C128::from);
+                Ok(Self::C128(data))
+            }
+            _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "fft").bt()),
+        }
+    }
+
+    fn view_as_real(&self, layout: &Layout) -> Result<Self> {
+        match self {
+            Self::C64(storage) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::F32(data.iter().flat_map(|v| [v.re, v.im]).collect()))
+            }
+            Self::C128(storage) => {
+                let data = unary_map(storage, layout, |v| v);
+                Ok(Self::F64(data.iter().flat_map(|v| [v.re, v.im]).collect()))
+            }
+            _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "view_as_real").bt()),
+        }
+    }
+
+    fn view_as_complex(&self, layout: &Layout) -> Result<Self> {
+        match self {
+            Self::F32(storage) => {
+                let data = unary_map(storage, layout, |v| v);
+                let data = data.chunks_exact(2).map(|v| C64::new(v[0], v[1]));
+                Ok(Self::C64(data.collect()))
+            }
+            Self::F64(storage) => {
+                let data = unary_map(storage, layout, |v| v);
+                let data = data.chunks_exact(2).map(|v| C128::new(v[0], v[1]));
+                Ok(Self::C128(data.collect()))
+            }
+            _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "view_as_complex").bt()),
         }
     }
 
     fn reduce_op(&self, op: ReduceOp, layout: &Layout, reduce_dims: &[usize]) -> Result<Self> {
         match op {
             ReduceOp::Sum => {
+                bail_on_bool(self, "sum")?;
                 let src_dims = layout.dims();
                 let mut dst_dims = src_dims.to_vec();
                 for &dim in reduce_dims.iter() {
@@ -2146,15 +3469,23 @@ impl BackendStorage for CpuStorage {
     }
 
     fn affine(&self, layout: &Layout, mul: f64, add: f64) -> Result<Self> {
+        bail_on_bool(self, "affine")?;
         Affine(mul, add).map(self, layout)
     }
 
+    fn affine_inplace(&mut self, layout: &Layout, mul: f64, add: f64) -> Result<()> {
+        bail_on_bool(self, "affine")?;
+        Affine(mul, add).map_inplace(self, layout);
+        Ok(())
+    }
+
     fn avg_pool2d(
         &self,
         layout: &Layout,
         kernel_size: (usize, usize),
         stride: (usize, usize),
     ) -> Result<Self> {
+        bail_on_bool(self, "avg-pool2d")?;
         AvgPool2D(kernel_size, stride).map(self, layout)
     }
 
@@ -2167,6 +3498,25 @@ impl BackendStorage for CpuStorage {
         MaxPool2D(kernel_size, stride).map(self, layout)
     }
 
+    fn avg_pool3d(
+        &self,
+        layout: &Layout,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        bail_on_bool(self, "avg-pool3d")?;
+        AvgPool3D(kernel_size, stride).map(self, layout)
+    }
+
+    fn max_pool3d(
+        &self,
+        layout: &Layout,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        MaxPool3D(kernel_size, stride).map(self, layout)
+    }
+
     fn upsample_nearest1d(&self, layout: &Layout, sz: usize) -> Result<Self> {
         UpsampleNearest1D(sz).map(self, layout)
     }
@@ -2195,9 +3545,15 @@ impl BackendStorage for CpuStorage {
                 let data = unary_map(storage, layout, |v| v.powf(e));
                 Ok(Self::F64(data))
             }
-            Self::U8(_) => Err(Error::UnsupportedDTypeForOp(DType::U8, "elu").bt()),
-            Self::U32(_) => Err(Error::UnsupportedDTypeForOp(DType::U32, "elu").bt()),
-            Self::I64(_) => Err(Error::UnsupportedDTypeForOp(DType::I64, "elu").bt()),
+            Self::Bool(_)
+            | Self::U8(_)
+            | Self::U32(_)
+            | Self::I8(_)
+            | Self::I16(_)
+            | Self::I32(_)
+            | Self::I64(_)
+            | Self::C64(_)
+            | Self::C128(_) => Err(Error::UnsupportedDTypeForOp(self.dtype(), "powf").bt()),
         }
     }
 
@@ -2220,9 +3576,15 @@ impl BackendStorage for CpuStorage {
                 let data = unary_map(storage, layout, |v| elu(v, alpha));
                 Ok(Self::F64(data))
             }
-            Self::U8(_) => Err(Error::UnsupportedDTypeForOp(DType::U8, "elu").bt()),
-            Self::U32(_) => Err(Error::UnsupportedDTypeForOp(DType::U32, "elu").bt()),
-            Self::I64(_) => Err(Error::UnsupportedDTypeForOp(DType::I64, "elu").bt()),
+            Self::Bool(_)
+            | Self::U8(_)
+            | Self::U32(_)
+            | Self::I8(_)
+            | Self::I16(_)
+            | Self::I32(_)
+            | Self::I64(_)
+            | Self::C64(_)
+            | Self::C128(_) => Err(Error::UnsupportedDTypeForOp(self.dtype(), "elu").bt()),
         }
     }
 
@@ -2272,10 +3634,31 @@ impl BackendStorage for CpuStorage {
                 let data = unary_map(storage, layout, B::u32);
                 Ok(Self::U32(data))
             }
+            Self::I8(storage) => {
+                let data = unary_map(storage, layout, B::i8);
+                Ok(Self::I8(data))
+            }
+            Self::I16(storage) => {
+                let data = unary_map(storage, layout, B::i16);
+                Ok(Self::I16(data))
+            }
+            Self::I32(storage) => {
+                let data = unary_map(storage, layout, B::i32);
+                Ok(Self::I32(data))
+            }
             Self::I64(storage) => {
                 let data = unary_map(storage, layout, B::i64);
                 Ok(Self::I64(data))
             }
+            Self::C64(storage) => {
+                let data = unary_map(storage, layout, B::c64);
+                Ok(Self::C64(data))
+            }
+            Self::C128(storage) => {
+                let data = unary_map(storage, layout, B::c128);
+                Ok(Self::C128(data))
+            }
+            Self::Bool(_) => Err(Error::UnsupportedDTypeForOp(DType::Bool, B::NAME).bt()),
         }
     }
 
@@ -2326,6 +3709,30 @@ impl BackendStorage for CpuStorage {
                 };
                 Ok(Self::U32(data))
             }
+            (Self::I8(lhs), Self::I8(rhs)) => {
+                let data = if B::I8_VEC {
+                    binary_map_vec(lhs_l, rhs_l, lhs, rhs, B::i8, B::i8_vec)
+                } else {
+                    binary_map(lhs_l, rhs_l, lhs, rhs, B::i8)
+                };
+                Ok(Self::I8(data))
+            }
+            (Self::I16(lhs), Self::I16(rhs)) => {
+                let data = if B::I16_VEC {
+                    binary_map_vec(lhs_l, rhs_l, lhs, rhs, B::i16, B::i16_vec)
+                } else {
+                    binary_map(lhs_l, rhs_l, lhs, rhs, B::i16)
+                };
+                Ok(Self::I16(data))
+            }
+            (Self::I32(lhs), Self::I32(rhs)) => {
+                let data = if B::I32_VEC {
+                    binary_map_vec(lhs_l, rhs_l, lhs, rhs, B::i32, B::i32_vec)
+                } else {
+                    binary_map(lhs_l, rhs_l, lhs, rhs, B::i32)
+                };
+                Ok(Self::I32(data))
+            }
             (Self::I64(lhs), Self::I64(rhs)) => {
                 let data = if B::I64_VEC {
                     binary_map_vec(lhs_l, rhs_l, lhs, rhs, B::i64, B::i64_vec)
@@ -2342,6 +3749,21 @@ impl BackendStorage for CpuStorage {
                 };
                 Ok(Self::U8(data))
             }
+            (Self::C64(lhs), Self::C64(rhs)) => {
+                let data = binary_map(lhs_l, rhs_l, lhs, rhs, B::c64);
+                Ok(Self::C64(data))
+            }
+            (Self::C128(lhs), Self::C128(rhs)) => {
+                let data = binary_map(lhs_l, rhs_l, lhs, rhs, B::c128);
+                Ok(Self::C128(data))
+            }
+            (Self::Bool(lhs), Self::Bool(rhs)) if B::supports_dtype(DType::Bool) => {
+                let data = binary_map(lhs_l, rhs_l, lhs, rhs, B::u8);
+                Ok(Self::Bool(data))
+            }
+            (Self::Bool(_), Self::Bool(_)) => {
+                Err(Error::UnsupportedDTypeForOp(DType::Bool, B::NAME).bt())
+            }
             _ => {
                 // This should be covered by the dtype check above.
                 Err(Error::DTypeMismatchBinaryOp {
@@ -2354,15 +3776,92 @@ impl BackendStorage for CpuStorage {
         }
     }
 
+    fn binary_impl_inplace<B: BinaryOpT>(
+        &mut self,
+        rhs: &Self,
+        lhs_l: &Layout,
+        rhs_l: &Layout,
+    ) -> Result<()> {
+        let lhs_dtype = self.dtype();
+        match (self, rhs) {
+            (Self::BF16(lhs), Self::BF16(rhs)) => {
+                binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::bf16)
+            }
+            (Self::F16(lhs), Self::F16(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::f16),
+            (Self::F32(lhs), Self::F32(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::f32),
+            (Self::F64(lhs), Self::F64(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::f64),
+            (Self::U32(lhs), Self::U32(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::u32),
+            (Self::I8(lhs), Self::I8(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::i8),
+            (Self::I16(lhs), Self::I16(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::i16),
+            (Self::I32(lhs), Self::I32(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::i32),
+            (Self::I64(lhs), Self::I64(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::i64),
+            (Self::U8(lhs), Self::U8(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::u8),
+            (Self::C64(lhs), Self::C64(rhs)) => binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::c64),
+            (Self::C128(lhs), Self::C128(rhs)) => {
+                binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::c128)
+            }
+            (Self::Bool(lhs), Self::Bool(rhs)) if B::supports_dtype(DType::Bool) => {
+                binary_map_inplace(lhs_l, rhs_l, lhs, rhs, B::u8)
+            }
+            (Self::Bool(_), Self::Bool(_)) => {
+                Err(Error::UnsupportedDTypeForOp(DType::Bool, B::NAME).bt())?
+            }
+            _ => {
+                // This should be covered by the dtype check above.
+                Err(Error::DTypeMismatchBinaryOp {
+                    lhs: lhs_dtype,
+                    rhs: rhs.dtype(),
+                    op: B::NAME,
+                }
+                .bt())?
+            }
+        }
+        Ok(())
+    }
+
     fn copy_strided_src(&self, dst: &mut Self, dst_offset: usize, src_l: &Layout) -> Result<()> {
         match (self, dst) {
             (Self::U8(src), Self::U8(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::U32(src), Self::U32(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::Bool(src), Self::Bool(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::I8(src), Self::I8(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::I16(src), Self::I16(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::I32(src), Self::I32(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::I64(src), Self::I64(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::BF16(src), Self::BF16(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::F16(src), Self::F16(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::F32(src), Self::F32(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
             (Self::F64(src), Self::F64(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::C64(src), Self::C64(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (Self::C128(src), Self::C128(dst)) => copy_strided_src_(src, dst, dst_offset, src_l),
+            (_, dst) => {
+                // This should be covered by the dtype check above.
+                return Err(Error::DTypeMismatchBinaryOp {
+                    lhs: self.dtype(),
+                    rhs: dst.dtype(),
+                    op: "copy_strided",
+                }
+                .bt());
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_strided(&self, dst: &mut Self, src_l: &Layout, dst_l: &Layout) -> Result<()> {
+        match (self, dst) {
+            (Self::U8(src), Self::U8(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::U32(src), Self::U32(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::Bool(src), Self::Bool(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::I8(src), Self::I8(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::I16(src), Self::I16(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::I32(src), Self::I32(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::I64(src), Self::I64(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::BF16(src), Self::BF16(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::F16(src), Self::F16(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::F32(src), Self::F32(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::F64(src), Self::F64(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::C64(src), Self::C64(dst)) => copy_strided_(src, dst, src_l, dst_l),
+            (Self::C128(src), Self::C128(dst)) => copy_strided_(src, dst, src_l, dst_l),
             (_, dst) => {
                 // This should be covered by the dtype check above.
                 return Err(Error::DTypeMismatchBinaryOp {
@@ -2385,8 +3884,12 @@ impl BackendStorage for CpuStorage {
         f_l: &Layout,
     ) -> Result<Self> {
         match self {
+            Self::Bool(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
             Self::U8(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
             Self::U32(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
+            Self::I8(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
+            Self::I16(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
+            Self::I32(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
             Self::I64(pred) => WCond(pred, layout).map(t, t_l, f, f_l),
             _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "where-cond")),
         }
@@ -2399,8 +3902,9 @@ impl BackendStorage for CpuStorage {
         kernel_l: &Layout,
         params: &crate::conv::ParamsConv1D,
     ) -> Result<Self> {
+        bail_on_bool(self, "conv1d")?;
         if !USE_IM2COL_CONV1D {
-            return Conv1D(params).map(self, l, kernel, kernel_l);
+            return crate::cpu_pool::install(|| Conv1D(params).map(self, l, kernel, kernel_l));
         }
         let op = Im2Col1D {
             l_k: params.k_size,
@@ -2424,10 +3928,10 @@ impl BackendStorage for CpuStorage {
             // Make the kernel contiguous if not already the case.
             let mut kernel_c = self.device().zeros_impl(kernel_l.shape(), kernel.dtype())?;
             kernel.copy_strided_src(&mut kernel_c, 0, kernel_l)?;
-            let kernel_l = Layout::contiguous_with_offset((1, n, k), kernel_l.start_offset())
+            let kernel_l = Layout::contiguous((1, n, k))
                 .transpose(1, 2)?
                 .broadcast_as((b, k, n))?;
-            col.matmul(kernel, (b, m, n, k), &col_l, &kernel_l)?
+            col.matmul(&kernel_c, (b, m, n, k), &col_l, &kernel_l)?
         };
         let res_l = Layout::contiguous((b, l_out, params.c_out)).transpose(1, 2)?;
         let mut res_t = self.device().zeros_impl(res_l.shape(), res.dtype())?;
@@ -2435,6 +3939,17 @@ impl BackendStorage for CpuStorage {
         Ok(res_t)
     }
 
+    fn conv_transpose1d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConvTranspose1D,
+    ) -> Result<Self> {
+        bail_on_bool(self, "conv-transpose1d")?;
+        crate::cpu_pool::install(|| ConvTranspose1D(params).map(self, l, kernel, kernel_l))
+    }
+
     fn conv2d(
         &self,
         l: &Layout,
@@ -2442,21 +3957,15 @@ impl BackendStorage for CpuStorage {
         kernel_l: &Layout,
         params: &crate::conv::ParamsConv2D,
     ) -> Result<Self> {
+        bail_on_bool(self, "conv2d")?;
         if !USE_IM2COL_CONV2D {
-            return Conv2D(params).map(self, l, kernel, kernel_l);
+            return crate::cpu_pool::install(|| Conv2D(params).map(self, l, kernel, kernel_l));
         }
-        let op = Im2Col {
-            h_k: params.k_h,
-            w_k: params.k_w,
-            padding: params.padding,
-            stride: params.stride,
-            dilation: params.dilation,
-        };
-        let col = op.map(self, l)?;
+        let col = Im2Col(params).map(self, l)?;
         let b = params.b_size;
         let n = params.c_out;
         let (h_out, w_out) = (params.out_h(), params.out_w());
-        let k = op.h_k * op.w_k * params.c_in;
+        let k = params.k_h * params.k_w * params.c_in;
         let m = h_out * w_out;
         let col_l = Layout::contiguous((b, m, k));
         let res = if kernel_l.is_contiguous() {
@@ -2468,10 +3977,10 @@ impl BackendStorage for CpuStorage {
             // Make the kernel contiguous if not already the case.
             let mut kernel_c = self.device().zeros_impl(kernel_l.shape(), kernel.dtype())?;
             kernel.copy_strided_src(&mut kernel_c, 0, kernel_l)?;
-            let kernel_l = Layout::contiguous_with_offset((1, n, k), kernel_l.start_offset())
+            let kernel_l = Layout::contiguous((1, n, k))
                 .transpose(1, 2)?
                 .broadcast_as((b, k, n))?;
-            col.matmul(kernel, (b, m, n, k), &col_l, &kernel_l)?
+            col.matmul(&kernel_c, (b, m, n, k), &col_l, &kernel_l)?
         };
         let res_l = Layout::contiguous((b, h_out, w_out, params.c_out))
             .transpose(1, 2)?
@@ -2488,13 +3997,28 @@ impl BackendStorage for CpuStorage {
         kernel_l: &Layout,
         params: &crate::conv::ParamsConvTranspose2D,
     ) -> Result<Self> {
-        ConvTranspose2D(params).map(self, l, kernel, kernel_l)
+        bail_on_bool(self, "conv-transpose2d")?;
+        crate::cpu_pool::install(|| ConvTranspose2D(params).map(self, l, kernel, kernel_l))
+    }
+
+    fn conv3d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConv3D,
+    ) -> Result<Self> {
+        bail_on_bool(self, "conv3d")?;
+        crate::cpu_pool::install(|| Conv3D(params).map(self, l, kernel, kernel_l))
     }
 
     fn index_select(&self, ids: &Self, l: &Layout, ids_l: &Layout, dim: usize) -> Result<Self> {
         match ids {
             Self::U8(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
             Self::U32(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
+            Self::I8(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
+            Self::I16(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
+            Self::I32(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
             Self::I64(ids) => IndexSelect { ids, ids_l, dim }.map(self, l),
             _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "index-select")),
         }
@@ -2504,6 +4028,9 @@ impl BackendStorage for CpuStorage {
         match ids {
             Self::U8(ids) => Gather { ids, ids_l, dim }.map(self, l),
             Self::U32(ids) => Gather { ids, ids_l, dim }.map(self, l),
+            Self::I8(ids) => Gather { ids, ids_l, dim }.map(self, l),
+            Self::I16(ids) => Gather { ids, ids_l, dim }.map(self, l),
+            Self::I32(ids) => Gather { ids, ids_l, dim }.map(self, l),
             Self::I64(ids) => Gather { ids, ids_l, dim }.map(self, l),
             _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "gather")),
         }
@@ -2518,9 +4045,13 @@ impl BackendStorage for CpuStorage {
         src_l: &Layout,
         dim: usize,
     ) -> Result<Self> {
+        bail_on_bool(self, "scatter-add")?;
         match ids {
             Self::U8(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
             Self::U32(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
+            Self::I8(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
+            Self::I16(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
+            Self::I32(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
             Self::I64(ids) => ScatterAdd { ids, ids_l, dim }.map(self, l, src, src_l),
             _ => Err(Error::UnsupportedDTypeForOp(self.dtype(), "scatter-add")),
         }
@@ -2535,6 +4066,7 @@ impl BackendStorage for CpuStorage {
         src_l: &Layout,
         dim: usize,
     ) -> Result<Self> {
+        bail_on_bool(self, "index-add")?;
         match ids {
             Self::U8(ids) => {
                 let ids = match ids_l.contiguous_offsets() {
@@ -2550,6 +4082,27 @@ impl BackendStorage for CpuStorage {
                 };
                 IndexAdd { ids, dim }.map(self, l, src, src_l)
             }
+            Self::I8(ids) => {
+                let ids = match ids_l.contiguous_offsets() {
+                    Some((a, b)) => &ids[a..b],
+                    None => Err(Error::RequiresContiguous { op: "index-add" })?,
+                };
+                IndexAdd { ids, dim }.map(self, l, src, src_l)
+            }
+            Self::I16(ids) => {
+                let ids = match ids_l.contiguous_offsets() {
+                    Some((a, b)) => &ids[a..b],
+                    None => Err(Error::RequiresContiguous { op: "index-add" })?,
+                };
+                IndexAdd { ids, dim }.map(self, l, src, src_l)
+            }
+            Self::I32(ids) => {
+                let ids = match ids_l.contiguous_offsets() {
+                    Some((a, b)) => &ids[a..b],
+                    None => Err(Error::RequiresContiguous { op: "index-add" })?,
+                };
+                IndexAdd { ids, dim }.map(self, l, src, src_l)
+            }
             Self::I64(ids) => {
                 let ids = match ids_l.contiguous_offsets() {
                     Some((a, b)) => &ids[a..b],
@@ -2561,6 +4114,55 @@ impl BackendStorage for CpuStorage {
         }
     }
 
+    fn index_add_inplace(
+        &mut self,
+        l: &Layout,
+        ids: &Self,
+        ids_l: &Layout,
+        src: &Self,
+        src_l: &Layout,
+        dim: usize,
+    ) -> Result<()> {
+        bail_on_bool(self, "index-add")?;
+        let (o1, o2) = match ids_l.contiguous_offsets() {
+            Some(offsets) => offsets,
+            None => Err(Error::RequiresContiguous { op: "index-add" })?,
+        };
+        match ids {
+            Self::U8(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            Self::U32(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            Self::I8(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            Self::I16(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            Self::I32(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            Self::I64(ids) => IndexAdd {
+                ids: &ids[o1..o2],
+                dim,
+            }
+            .map_inplace(self, l, src, src_l),
+            _ => Err(Error::UnsupportedDTypeForOp(ids.dtype(), "index-add")),
+        }
+    }
+
     fn matmul(
         &self,
         rhs: &Self,
@@ -2568,6 +4170,7 @@ impl BackendStorage for CpuStorage {
         lhs_l: &Layout,
         rhs_l: &Layout,
     ) -> Result<Self> {
+        bail_on_bool(self, "matmul")?;
         MatMul(bmnk).map(self, lhs_l, rhs, rhs_l)
     }
 
@@ -2606,12 +4209,21 @@ impl BackendDevice for CpuDevice {
     fn rand_uniform(&self, shape: &Shape, dtype: DType, min: f64, max: f64) -> Result<CpuStorage> {
         use rand::prelude::*;
 
+        if let Some(gen) = crate::random::CPU_GENERATOR.lock().unwrap().as_mut() {
+            return gen.rand_uniform(shape, dtype, min, max);
+        }
         let elem_count = shape.elem_count();
         let mut rng = rand::thread_rng();
         match dtype {
-            DType::U8 | DType::U32 | DType::I64 => {
-                Err(Error::UnsupportedDTypeForOp(dtype, "rand_uniform").bt())
-            }
+            DType::Bool
+            | DType::U8
+            | DType::U32
+            | DType::I8
+            | DType::I16
+            | DType::I32
+            | DType::I64
+            | DType::C64
+            | DType::C128 => Err(Error::UnsupportedDTypeForOp(dtype, "rand_uniform").bt()),
             DType::BF16 => {
                 let mut data = Vec::with_capacity(elem_count);
                 let uniform =
@@ -2652,12 +4264,21 @@ impl BackendDevice for CpuDevice {
     fn rand_normal(&self, shape: &Shape, dtype: DType, mean: f64, std: f64) -> Result<CpuStorage> {
         use rand::prelude::*;
 
+        if let Some(gen) = crate::random::CPU_GENERATOR.lock().unwrap().as_mut() {
+            return gen.rand_normal(shape, dtype, mean, std);
+        }
         let elem_count = shape.elem_count();
         let mut rng = rand::thread_rng();
         match dtype {
-            DType::U8 | DType::U32 | DType::I64 => {
-                Err(Error::UnsupportedDTypeForOp(dtype, "rand_normal").bt())
-            }
+            DType::Bool
+            | DType::U8
+            | DType::U32
+            | DType::I8
+            | DType::I16
+            | DType::I32
+            | DType::I64
+            | DType::C64
+            | DType::C128 => Err(Error::UnsupportedDTypeForOp(dtype, "rand_normal").bt()),
             DType::BF16 => {
                 let mut data = Vec::with_capacity(elem_count);
                 let normal = rand_distr::Normal::new(bf16::from_f64(mean), bf16::from_f64(std))
@@ -2696,16 +4317,44 @@ impl BackendDevice for CpuDevice {
         }
     }
 
+    fn set_seed(&self, seed: u64) -> Result<()> {
+        *crate::random::CPU_GENERATOR.lock().unwrap() = Some(crate::Generator::new(seed));
+        Ok(())
+    }
+
+    fn memory_stats(&self) -> Result<crate::MemoryStats> {
+        Ok(crate::memory::CPU_POOL.lock().unwrap().stats())
+    }
+
+    fn empty_cache(&self) -> Result<()> {
+        crate::memory::CPU_POOL.lock().unwrap().empty();
+        Ok(())
+    }
+
+    fn set_cache_limit(&self, cache_limit: usize) -> Result<()> {
+        crate::memory::CPU_POOL
+            .lock()
+            .unwrap()
+            .set_cache_limit(cache_limit);
+        Ok(())
+    }
+
     fn ones_impl(&self, shape: &Shape, dtype: DType) -> Result<CpuStorage> {
         let elem_count = shape.elem_count();
         let storage = match dtype {
-            DType::U8 => CpuStorage::U8(vec![1u8; elem_count]),
-            DType::U32 => CpuStorage::U32(vec![1u32; elem_count]),
-            DType::I64 => CpuStorage::I64(vec![1i64; elem_count]),
-            DType::BF16 => CpuStorage::BF16(vec![bf16::ONE; elem_count]),
-            DType::F16 => CpuStorage::F16(vec![f16::ONE; elem_count]),
-            DType::F32 => CpuStorage::F32(vec![1f32; elem_count]),
-            DType::F64 => CpuStorage::F64(vec![1f64; elem_count]),
+            DType::Bool => CpuStorage::Bool(full_vec(elem_count, 1u8)),
+            DType::U8 => CpuStorage::U8(full_vec(elem_count, 1u8)),
+            DType::U32 => CpuStorage::U32(full_vec(elem_count, 1u32)),
+            DType::I8 => CpuStorage::I8(full_vec(elem_count, 1i8)),
+            DType::I16 => CpuStorage::I16(full_vec(elem_count, 1i16)),
+            DType::I32 => CpuStorage::I32(full_vec(elem_count, 1i32)),
+            DType::I64 => CpuStorage::I64(full_vec(elem_count, 1i64)),
+            DType::BF16 => CpuStorage::BF16(full_vec(elem_count, bf16::ONE)),
+            DType::F16 => CpuStorage::F16(full_vec(elem_count, f16::ONE)),
+            DType::F32 => CpuStorage::F32(full_vec(elem_count, 1f32)),
+            DType::F64 => CpuStorage::F64(full_vec(elem_count, 1f64)),
+            DType::C64 => CpuStorage::C64(full_vec(elem_count, C64::new(1., 0.))),
+            DType::C128 => CpuStorage::C128(full_vec(elem_count, C128::new(1., 0.))),
         };
         Ok(storage)
     }
@@ -2713,13 +4362,19 @@ impl BackendDevice for CpuDevice {
     fn zeros_impl(&self, shape: &Shape, dtype: DType) -> Result<CpuStorage> {
         let elem_count = shape.elem_count();
         let storage = match dtype {
-            DType::U8 => CpuStorage::U8(vec![0u8; elem_count]),
-            DType::U32 => CpuStorage::U32(vec![0u32; elem_count]),
-            DType::I64 => CpuStorage::I64(vec![0i64; elem_count]),
-            DType::BF16 => CpuStorage::BF16(vec![bf16::ZERO; elem_count]),
-            DType::F16 => CpuStorage::F16(vec![f16::ZERO; elem_count]),
-            DType::F32 => CpuStorage::F32(vec![0f32; elem_count]),
-            DType::F64 => CpuStorage::F64(vec![0f64; elem_count]),
+            DType::Bool => CpuStorage::Bool(full_vec(elem_count, 0u8)),
+            DType::U8 => CpuStorage::U8(full_vec(elem_count, 0u8)),
+            DType::U32 => CpuStorage::U32(full_vec(elem_count, 0u32)),
+            DType::I8 => CpuStorage::I8(full_vec(elem_count, 0i8)),
+            DType::I16 => CpuStorage::I16(full_vec(elem_count, 0i16)),
+            DType::I32 => CpuStorage::I32(full_vec(elem_count, 0i32)),
+            DType::I64 => CpuStorage::I64(full_vec(elem_count, 0i64)),
+            DType::BF16 => CpuStorage::BF16(full_vec(elem_count, bf16::ZERO)),
+            DType::F16 => CpuStorage::F16(full_vec(elem_count, f16::ZERO)),
+            DType::F32 => CpuStorage::F32(full_vec(elem_count, 0f32)),
+            DType::F64 => CpuStorage::F64(full_vec(elem_count, 0f64)),
+            DType::C64 => CpuStorage::C64(full_vec(elem_count, C64::default())),
+            DType::C128 => CpuStorage::C128(full_vec(elem_count, C128::default())),
         };
         Ok(storage)
     }
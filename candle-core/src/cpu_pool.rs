@@ -0,0 +1,142 @@
+//! Thread pools used by the parallel cpu kernels.
+//!
+//! By default the cpu kernels, e.g. matmul, convolutions or the quantized matmul, run on the
+//! global rayon pool. [`set_num_threads`] replaces this default with a dedicated pool, and a
+//! [`crate::Device`] created with [`crate::Device::cpu_with_threads`] carries its own pool that is
+//! used for all the ops applied to the tensors living on it.
+//!
+//! ```rust
+//! use candle_core::{Device, Tensor};
+//! let device = Device::cpu_with_threads(2)?;
+//! let a = Tensor::ones((64, 64), candle_core::DType::F32, &device)?;
+//! // The matmul runs on the two threads of the device pool.
+//! let b = a.matmul(&a)?;
+//! assert!(b.device().is_cpu());
+//! # Ok::<(), candle_core::Error>(())
+//! ```
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+/// A rayon thread pool that can be attached to a cpu device.
+#[derive(Clone)]
+pub struct CpuPool(Arc<rayon::ThreadPool>);
+
+impl std::fmt::Debug for CpuPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CpuPool({})", self.num_threads())
+    }
+}
+
+impl CpuPool {
+    /// Creates a new pool with `num_threads` threads, 0 uses the same default as rayon.
+    pub fn new(num_threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("candle-cpu-{i}"))
+            .build()
+            .map_err(Error::wrap)?;
+        Ok(Self(Arc::new(pool)))
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.0.current_num_threads()
+    }
+
+    /// Returns true if both values refer to the same underlying pool.
+    pub fn same_pool(&self, rhs: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &rhs.0)
+    }
+
+    /// Runs `op` on this pool, the rayon parallel iterators used within `op` are executed by the
+    /// threads of this pool.
+    pub fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        self.0.install(op)
+    }
+}
+
+// The pool set through `set_num_threads`, the global rayon pool is used when this is `None`.
+static GLOBAL: RwLock<Option<CpuPool>> = RwLock::new(None);
+
+thread_local! {
+    // The pool of the device on which the op currently being run on this thread lives.
+    static CURRENT: RefCell<Option<CpuPool>> = const { RefCell::new(None) };
+}
+
+/// Sets the number of threads used by the cpu kernels of the default [`crate::Device::Cpu`]
+/// device. Using 0 reverts to the global rayon pool, the size of which can be set via the
+/// `RAYON_NUM_THREADS` environment variable. The devices that carry their own pool are not
+/// impacted.
+pub fn set_num_threads(num_threads: usize) -> Result<()> {
+    let pool = if num_threads == 0 {
+        None
+    } else {
+        Some(CpuPool::new(num_threads)?)
+    };
+    *GLOBAL.write().unwrap() = pool;
+    Ok(())
+}
+
+/// Returns the number of threads that the cpu kernels use when run from the current thread.
+pub fn num_threads() -> usize {
+    if let Some(pool) = CURRENT.with(|c| c.borrow().clone()) {
+        return pool.num_threads();
+    }
+    if rayon::current_thread_index().is_some() {
+        return rayon::current_num_threads();
+    }
+    match GLOBAL.read().unwrap().as_ref() {
+        Some(pool) => pool.num_threads(),
+        None => rayon::current_num_threads(),
+    }
+}
+
+/// Runs `op` on the pool that applies to the current thread. This is the pool of the device
+/// of the tensors on which the current op is applied if it carries one, otherwise the pool set
+/// via [`set_num_threads`] or the global rayon pool. The parallel cpu kernels, including the ones
+/// of custom ops, should be wrapped in this function.
+pub fn install<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    if let Some(pool) = CURRENT.with(|c| c.borrow().clone()) {
+        return pool.install(op);
+    }
+    // Nested calls from within a pool stay on that pool.
+    if rayon::current_thread_index().is_some() {
+        return op();
+    }
+    let global = GLOBAL.read().unwrap().clone();
+    match global {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+/// A guard that makes a device pool the current one on this thread until it gets dropped.
+pub(crate) struct Scope {
+    active: bool,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.active {
+            CURRENT.with(|c| *c.borrow_mut() = None)
+        }
+    }
+}
+
+/// Makes `pool` current unless another pool already is, so that when an op involves tensors from
+/// multiple devices the first tensor to be accessed decides which pool is used.
+pub(crate) fn scope(pool: Option<&CpuPool>) -> Scope {
+    let active = match pool {
+        None => false,
+        Some(pool) => CURRENT.with(|c| {
+            let mut c = c.borrow_mut();
+            if c.is_some() {
+                false
+            } else {
+                *c = Some(pool.clone());
+                true
+            }
+        }),
+    };
+    Scope { active }
+}
@@ -1,12 +1,12 @@
 use crate::backend::{BackendDevice, BackendStorage};
-use crate::op::{BinaryOpT, CmpOp, ReduceOp, UnaryOpT};
+use crate::op::{BinaryOpT, CmpOp, CumulativeOp, ReduceOp, UnaryOpT};
 use crate::{CpuStorage, DType, Layout, Result, Shape, WithDType};
 pub use candle_kernels as kernels;
 pub use cudarc;
 use cudarc::cublas::{Gemm, GemmConfig, StridedBatchedConfig};
 use cudarc::driver::{
-    CudaFunction, CudaSlice, DevicePtr, DeviceRepr, DeviceSlice, LaunchAsync, LaunchConfig,
-    ValidAsZeroBits,
+    CudaFunction, CudaSlice, DevicePtr, DeviceRepr, DeviceSlice, DriverError, LaunchAsync,
+    LaunchConfig, ValidAsZeroBits,
 };
 use half::{bf16, f16};
 use std::sync::{Arc, Mutex};
@@ -84,6 +84,11 @@ pub struct CudaDevice {
     device: Arc<cudarc::driver::CudaDevice>,
     blas: Arc<cudarc::cublas::CudaBlas>,
     curand: Arc<Mutex<CudaRng>>,
+    // Once a seed has been set, the random values are generated on the host using the same
+    // stream as the cpu device and copied over.
+    generator: Arc<Mutex<Option<crate::Generator>>>,
+    // Buffers released by the tensors, these are bucketed on their exact number of elements.
+    pool: Arc<Mutex<crate::memory::MemoryPool>>,
 }
 
 impl std::fmt::Debug for CudaDevice {
@@ -119,6 +124,43 @@ impl CudaDevice {
         self.id
     }
 
+    fn take_cached<T: Send + 'static>(&self, len: usize) -> Option<CudaSlice<T>> {
+        if len == 0 {
+            return None;
+        }
+        self.pool.lock().unwrap().remove::<CudaSlice<T>>(len, 0)
+    }
+
+    /// Allocates a buffer of `len` elements, reusing a buffer of the same size released by a
+    /// tensor when possible. This shadows the allocation method of the underlying cudarc device
+    /// so that the kernels go through the cache.
+    ///
+    /// # Safety
+    /// The content of the buffer is not initialized.
+    pub unsafe fn alloc<T: DeviceRepr + Send + 'static>(
+        &self,
+        len: usize,
+    ) -> std::result::Result<CudaSlice<T>, DriverError> {
+        match self.take_cached(len) {
+            Some(slice) => Ok(slice),
+            None => self.device.alloc::<T>(len),
+        }
+    }
+
+    /// Same as [`CudaDevice::alloc`] but the buffer is filled with zeros.
+    pub fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr + Send + 'static>(
+        &self,
+        len: usize,
+    ) -> std::result::Result<CudaSlice<T>, DriverError> {
+        match self.take_cached(len) {
+            Some(mut slice) => {
+                self.device.memset_zeros(&mut slice)?;
+                Ok(slice)
+            }
+            None => self.device.alloc_zeros::<T>(len),
+        }
+    }
+
     fn const_impl(&self, v: f64, shape: &Shape, dtype: DType) -> Result<CudaStorage> {
         let elem_count = shape.elem_count();
         let cfg = LaunchConfig::for_num_elems(elem_count as u32);
@@ -139,6 +181,38 @@ impl CudaDevice {
                 unsafe { func.launch(cfg, params) }.w()?;
                 CudaStorageSlice::U32(data)
             }
+            DType::Bool => {
+                // SAFETY: Set later by running the fill kernel.
+                let data = unsafe { self.alloc::<u8>(elem_count) }.w()?;
+                let func = self.get_or_load_func("fill_u8", kernels::FILL)?;
+                let params = (&data, u8::from(v != 0.), elem_count);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::Bool(data)
+            }
+            DType::I8 => {
+                // SAFETY: Set later by running the fill kernel.
+                let data = unsafe { self.alloc::<i8>(elem_count) }.w()?;
+                let func = self.get_or_load_func("fill_i8", kernels::FILL)?;
+                let params = (&data, v as i8, elem_count);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I8(data)
+            }
+            DType::I16 => {
+                // SAFETY: Set later by running the fill kernel.
+                let data = unsafe { self.alloc::<i16>(elem_count) }.w()?;
+                let func = self.get_or_load_func("fill_i16", kernels::FILL)?;
+                let params = (&data, v as i16, elem_count);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I16(data)
+            }
+            DType::I32 => {
+                // SAFETY: Set later by running the fill kernel.
+                let data = unsafe { self.alloc::<i32>(elem_count) }.w()?;
+                let func = self.get_or_load_func("fill_i32", kernels::FILL)?;
+                let params = (&data, v as i32, elem_count);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I32(data)
+            }
             DType::I64 => {
                 // SAFETY: Set later by running the fill kernel.
                 let data = unsafe { self.alloc::<i64>(elem_count) }.w()?;
@@ -220,6 +294,8 @@ impl BackendDevice for CudaDevice {
             device,
             blas: Arc::new(blas),
             curand: Arc::new(Mutex::new(CudaRng(curand))),
+            generator: Arc::new(Mutex::new(None)),
+            pool: Arc::new(Mutex::new(crate::memory::MemoryPool::new())),
         })
     }
 
@@ -244,6 +320,22 @@ impl BackendDevice for CudaDevice {
                 let data = self.alloc_zeros::<u32>(elem_count).w()?;
                 CudaStorageSlice::U32(data)
             }
+            DType::Bool => {
+                let data = self.alloc_zeros::<u8>(elem_count).w()?;
+                CudaStorageSlice::Bool(data)
+            }
+            DType::I8 => {
+                let data = self.alloc_zeros::<i8>(elem_count).w()?;
+                CudaStorageSlice::I8(data)
+            }
+            DType::I16 => {
+                let data = self.alloc_zeros::<i16>(elem_count).w()?;
+                CudaStorageSlice::I16(data)
+            }
+            DType::I32 => {
+                let data = self.alloc_zeros::<i32>(elem_count).w()?;
+                CudaStorageSlice::I32(data)
+            }
             DType::I64 => {
                 let data = self.alloc_zeros::<i64>(elem_count).w()?;
                 CudaStorageSlice::I64(data)
@@ -272,18 +364,28 @@ impl BackendDevice for CudaDevice {
     }
 
     fn rand_uniform(&self, shape: &Shape, dtype: DType, lo: f64, up: f64) -> Result<CudaStorage> {
+        if let Some(gen) = self.generator.lock().unwrap().as_mut() {
+            let storage = gen.rand_uniform(shape, dtype, lo, up)?;
+            return self.storage_from_cpu_storage(&storage);
+        }
         let elem_count = shape.elem_count();
         let curand = self.curand.lock().unwrap();
         let slice = match dtype {
             // TODO: Add support for F16 and BF16 though this is likely to require some upstream
             // cudarc changes.
-            DType::U8 | DType::U32 | DType::I64 | DType::F16 | DType::BF16 => {
-                Err(CudaError::UnsupportedDtype {
-                    dtype,
-                    op: "rand_uniform",
-                })
-                .w()?
-            }
+            DType::Bool
+            | DType::U8
+            | DType::U32
+            | DType::I8
+            | DType::I16
+            | DType::I32
+            | DType::I64
+            | DType::F16
+            | DType::BF16 => Err(CudaError::UnsupportedDtype {
+                dtype,
+                op: "rand_uniform",
+            })
+            .w()?,
             DType::F32 => {
                 let mut data = unsafe { self.alloc::<f32>(elem_count) }.w()?;
                 curand.0.fill_with_uniform(&mut data).w()?;
@@ -308,6 +410,10 @@ impl BackendDevice for CudaDevice {
     }
 
     fn rand_normal(&self, shape: &Shape, dtype: DType, mean: f64, std: f64) -> Result<CudaStorage> {
+        if let Some(gen) = self.generator.lock().unwrap().as_mut() {
+            let storage = gen.rand_normal(shape, dtype, mean, std)?;
+            return self.storage_from_cpu_storage(&storage);
+        }
         // TODO: Add support for F16 and BF16 though this is likely to require some upstream
         // cudarc changes.
         let elem_count = shape.elem_count();
@@ -320,13 +426,19 @@ impl BackendDevice for CudaDevice {
             elem_count
         };
         let slice = match dtype {
-            DType::U8 | DType::U32 | DType::I64 | DType::F16 | DType::BF16 => {
-                Err(CudaError::UnsupportedDtype {
-                    dtype,
-                    op: "rand_normal",
-                })
-                .w()?
-            }
+            DType::Bool
+            | DType::U8
+            | DType::U32
+            | DType::I8
+            | DType::I16
+            | DType::I32
+            | DType::I64
+            | DType::F16
+            | DType::BF16 => Err(CudaError::UnsupportedDtype {
+                dtype,
+                op: "rand_normal",
+            })
+            .w()?,
             DType::F32 => {
                 let mut data = unsafe { self.alloc::<f32>(elem_count_round) }.w()?;
                 curand
@@ -347,6 +459,25 @@ impl BackendDevice for CudaDevice {
         })
     }
 
+    fn set_seed(&self, seed: u64) -> Result<()> {
+        *self.generator.lock().unwrap() = Some(crate::Generator::new(seed));
+        Ok(())
+    }
+
+    fn memory_stats(&self) -> Result<crate::MemoryStats> {
+        Ok(self.pool.lock().unwrap().stats())
+    }
+
+    fn empty_cache(&self) -> Result<()> {
+        self.pool.lock().unwrap().empty();
+        Ok(())
+    }
+
+    fn set_cache_limit(&self, cache_limit: usize) -> Result<()> {
+        self.pool.lock().unwrap().set_cache_limit(cache_limit);
+        Ok(())
+    }
+
     fn ones_impl(&self, shape: &Shape, dtype: DType) -> Result<CudaStorage> {
         self.const_impl(1., shape, dtype)
     }
@@ -361,6 +492,22 @@ impl BackendDevice for CudaDevice {
                 let data = self.htod_sync_copy(storage).w()?;
                 CudaStorageSlice::U32(data)
             }
+            CpuStorage::Bool(storage) => {
+                let data = self.htod_sync_copy(storage).w()?;
+                CudaStorageSlice::Bool(data)
+            }
+            CpuStorage::I8(storage) => {
+                let data = self.htod_sync_copy(storage).w()?;
+                CudaStorageSlice::I8(data)
+            }
+            CpuStorage::I16(storage) => {
+                let data = self.htod_sync_copy(storage).w()?;
+                CudaStorageSlice::I16(data)
+            }
+            CpuStorage::I32(storage) => {
+                let data = self.htod_sync_copy(storage).w()?;
+                CudaStorageSlice::I32(data)
+            }
             CpuStorage::I64(storage) => {
                 let data = self.htod_sync_copy(storage).w()?;
                 CudaStorageSlice::I64(data)
@@ -381,6 +528,10 @@ impl BackendDevice for CudaDevice {
                 let data = self.htod_sync_copy(storage).w()?;
                 CudaStorageSlice::F64(data)
             }
+            // TODO: add complex slices to the cuda backend.
+            CpuStorage::C64(_) | CpuStorage::C128(_) => Err(CudaError::InternalError(
+                "complex dtypes are not supported on cuda",
+            ))?,
         };
         Ok(CudaStorage {
             slice,
@@ -391,8 +542,13 @@ impl BackendDevice for CudaDevice {
 
 #[derive(Debug)]
 pub enum CudaStorageSlice {
+    // Booleans are stored as one byte per element holding either 0 or 1.
+    Bool(CudaSlice<u8>),
     U8(CudaSlice<u8>),
     U32(CudaSlice<u32>),
+    I8(CudaSlice<i8>),
+    I16(CudaSlice<i16>),
+    I32(CudaSlice<i32>),
     I64(CudaSlice<i64>),
     BF16(CudaSlice<bf16>),
     F16(CudaSlice<f16>),
@@ -411,7 +567,11 @@ pub trait Map1 {
 
     fn map(&self, s: &S, d: &CudaDevice, l: &Layout) -> Result<S> {
         let out = match s {
+            S::Bool(s) => S::Bool(self.f(s, d, l)?),
             S::U8(s) => S::U8(self.f(s, d, l)?),
+            S::I8(s) => S::I8(self.f(s, d, l)?),
+            S::I16(s) => S::I16(self.f(s, d, l)?),
+            S::I32(s) => S::I32(self.f(s, d, l)?),
             S::U32(s) => S::U32(self.f(s, d, l)?),
             S::I64(s) => S::I64(self.f(s, d, l)?),
             S::BF16(s) => S::BF16(self.f(s, d, l)?),
@@ -435,7 +595,11 @@ pub trait Map2 {
 
     fn map(&self, s1: &S, l1: &Layout, s2: &S, l2: &Layout, d: &CudaDevice) -> Result<S> {
         let out = match (s1, s2) {
+            (S::Bool(s1), S::Bool(s2)) => S::Bool(self.f(s1, l1, s2, l2, d)?),
             (S::U8(s1), S::U8(s2)) => S::U8(self.f(s1, l1, s2, l2, d)?),
+            (S::I8(s1), S::I8(s2)) => S::I8(self.f(s1, l1, s2, l2, d)?),
+            (S::I16(s1), S::I16(s2)) => S::I16(self.f(s1, l1, s2, l2, d)?),
+            (S::I32(s1), S::I32(s2)) => S::I32(self.f(s1, l1, s2, l2, d)?),
             (S::U32(s1), S::U32(s2)) => S::U32(self.f(s1, l1, s2, l2, d)?),
             (S::I64(s1), S::I64(s2)) => S::I64(self.f(s1, l1, s2, l2, d)?),
             (S::BF16(s1), S::BF16(s2)) => S::BF16(self.f(s1, l1, s2, l2, d)?),
@@ -467,7 +631,11 @@ pub trait Map2InPlace {
         d: &CudaDevice,
     ) -> Result<()> {
         match (dst, src) {
+            (S::Bool(dst), S::Bool(src)) => self.f(dst, dst_s, src, src_l, d),
             (S::U8(dst), S::U8(src)) => self.f(dst, dst_s, src, src_l, d),
+            (S::I8(dst), S::I8(src)) => self.f(dst, dst_s, src, src_l, d),
+            (S::I16(dst), S::I16(src)) => self.f(dst, dst_s, src, src_l, d),
+            (S::I32(dst), S::I32(src)) => self.f(dst, dst_s, src, src_l, d),
             (S::U32(dst), S::U32(src)) => self.f(dst, dst_s, src, src_l, d),
             (S::I64(dst), S::I64(src)) => self.f(dst, dst_s, src, src_l, d),
             (S::BF16(dst), S::BF16(src)) => self.f(dst, dst_s, src, src_l, d),
@@ -490,7 +658,11 @@ pub trait Map1Any {
 
     fn map(&self, s: &S, d: &CudaDevice, l: &Layout) -> Result<S> {
         let out = match s {
+            S::Bool(s) => self.f(s, d, l, S::Bool)?,
             S::U8(s) => self.f(s, d, l, S::U8)?,
+            S::I8(s) => self.f(s, d, l, S::I8)?,
+            S::I16(s) => self.f(s, d, l, S::I16)?,
+            S::I32(s) => self.f(s, d, l, S::I32)?,
             S::U32(s) => self.f(s, d, l, S::U32)?,
             S::I64(s) => self.f(s, d, l, S::I64)?,
             S::BF16(s) => self.f(s, d, l, S::BF16)?,
@@ -514,7 +686,11 @@ pub trait Map2Any {
 
     fn map(&self, s1: &S, l1: &Layout, s2: &S, l2: &Layout, d: &CudaDevice) -> Result<S> {
         let out = match (s1, s2) {
+            (S::Bool(s1), S::Bool(s2)) => self.f(s1, l1, s2, l2, d)?,
             (S::U8(s1), S::U8(s2)) => self.f(s1, l1, s2, l2, d)?,
+            (S::I8(s1), S::I8(s2)) => self.f(s1, l1, s2, l2, d)?,
+            (S::I16(s1), S::I16(s2)) => self.f(s1, l1, s2, l2, d)?,
+            (S::I32(s1), S::I32(s2)) => self.f(s1, l1, s2, l2, d)?,
             (S::U32(s1), S::U32(s2)) => self.f(s1, l1, s2, l2, d)?,
             (S::I64(s1), S::I64(s2)) => self.f(s1, l1, s2, l2, d)?,
             (S::BF16(s1), S::BF16(s2)) => self.f(s1, l1, s2, l2, d)?,
@@ -1130,8 +1306,18 @@ impl<'a> Map2 for Conv2D<'a> {
             crate::bail!("unexpected input shape for conv2d {dims:?}")
         };
         let ds = dev.htod_copy(ds).w()?;
+        // Only symmetric parameters are supported here, see `BackendStorage::conv2d`.
         let params = (
-            el, out_w, out_h, p.stride, p.padding, p.dilation, &ds, inp, k, &out,
+            el,
+            out_w,
+            out_h,
+            p.stride_h,
+            p.padding_top,
+            p.dilation_h,
+            &ds,
+            inp,
+            k,
+            &out,
         );
         // SAFETY: ffi.
         unsafe { func.launch(cfg, params) }.w()?;
@@ -1292,10 +1478,14 @@ impl<'a> Map2 for WhereCond<'a> {
     ) -> Result<CudaSlice<T>> {
         let ids_l = &self.1;
         let (ids, name) = match &self.0.slice {
-            CudaStorageSlice::U8(slice) => {
+            CudaStorageSlice::Bool(slice) | CudaStorageSlice::U8(slice) => {
                 let ptr = *slice.slice(ids_l.start_offset()..).device_ptr();
                 (ptr, "where_u8")
             }
+            CudaStorageSlice::I32(slice) => {
+                let ptr = *slice.slice(ids_l.start_offset()..).device_ptr();
+                (ptr, "where_i32")
+            }
             CudaStorageSlice::U32(slice) => {
                 let ptr = *slice.slice(ids_l.start_offset()..).device_ptr();
                 (ptr, "where_u32")
@@ -1305,7 +1495,7 @@ impl<'a> Map2 for WhereCond<'a> {
                 (ptr, "where_i64")
             }
             _ => Err(CudaError::UnexpectedDType {
-                msg: "where conditions should be u8/u32/i64",
+                msg: "where conditions should be bool/u8/u32/i32/i64",
                 expected: DType::U32,
                 got: self.0.dtype(),
             })
@@ -1391,7 +1581,7 @@ impl Map2Any for Cmp {
         let params = (elem_count, dims.len(), &dims_and_strides, lhs, rhs, &out);
         // SAFETY: ffi
         unsafe { func.launch(cfg, params) }.w()?;
-        Ok(S::U8(out))
+        Ok(S::Bool(out))
     }
 }
 
@@ -1449,17 +1639,72 @@ macro_rules! cuda_dtype {
 }
 cuda_dtype!(u8, U8);
 cuda_dtype!(u32, U32);
+cuda_dtype!(i8, I8);
+cuda_dtype!(i16, I16);
+cuda_dtype!(i32, I32);
 cuda_dtype!(i64, I64);
 cuda_dtype!(f16, F16);
 cuda_dtype!(bf16, BF16);
 cuda_dtype!(f32, F32);
 cuda_dtype!(f64, F64);
 
+fn cache_slice<T: Send + 'static>(
+    pool: &mut crate::memory::MemoryPool,
+    slice: CudaSlice<T>,
+    bytes: usize,
+) {
+    let len = slice.len();
+    if len > 0 {
+        pool.insert(len, slice, bytes)
+    }
+}
+
 impl CudaStorage {
     pub fn wrap_cuda_slice<T: CudaDType>(slice: CudaSlice<T>, device: CudaDevice) -> CudaStorage {
         T::wrap_cuda_slice(slice, device)
     }
 
+    fn size_in_bytes(&self) -> usize {
+        let len = match &self.slice {
+            CudaStorageSlice::Bool(s) => s.len(),
+            CudaStorageSlice::U8(s) => s.len(),
+            CudaStorageSlice::U32(s) => s.len(),
+            CudaStorageSlice::I8(s) => s.len(),
+            CudaStorageSlice::I16(s) => s.len(),
+            CudaStorageSlice::I32(s) => s.len(),
+            CudaStorageSlice::I64(s) => s.len(),
+            CudaStorageSlice::BF16(s) => s.len(),
+            CudaStorageSlice::F16(s) => s.len(),
+            CudaStorageSlice::F32(s) => s.len(),
+            CudaStorageSlice::F64(s) => s.len(),
+        };
+        len * self.dtype().size_in_bytes()
+    }
+
+    pub(crate) fn track_alloc(&self) {
+        let bytes = self.size_in_bytes();
+        self.device.pool.lock().unwrap().track_alloc(bytes)
+    }
+
+    // Called once no tensor uses this storage anymore, the buffer is cached for later reuse.
+    pub(crate) fn release(self) {
+        let bytes = self.size_in_bytes();
+        let mut pool = self.device.pool.lock().unwrap();
+        pool.track_free(bytes);
+        match self.slice {
+            CudaStorageSlice::Bool(s) | CudaStorageSlice::U8(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::U32(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::I8(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::I16(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::I32(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::I64(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::BF16(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::F16(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::F32(s) => cache_slice(&mut pool, s, bytes),
+            CudaStorageSlice::F64(s) => cache_slice(&mut pool, s, bytes),
+        }
+    }
+
     pub fn as_cuda_slice<T: CudaDType>(&self) -> Result<&CudaSlice<T>> {
         T::as_cuda_slice(self)
     }
@@ -1561,7 +1806,11 @@ impl BackendStorage for CudaStorage {
 
     fn dtype(&self) -> DType {
         match self.slice {
+            CudaStorageSlice::Bool(_) => DType::Bool,
             CudaStorageSlice::U8(_) => DType::U8,
+            CudaStorageSlice::I8(_) => DType::I8,
+            CudaStorageSlice::I16(_) => DType::I16,
+            CudaStorageSlice::I32(_) => DType::I32,
             CudaStorageSlice::U32(_) => DType::U32,
             CudaStorageSlice::I64(_) => DType::I64,
             CudaStorageSlice::BF16(_) => DType::BF16,
@@ -1576,6 +1825,12 @@ impl BackendStorage for CudaStorage {
     }
 
     fn to_dtype(&self, layout: &Layout, dtype: DType) -> Result<Self> {
+        if dtype == DType::Bool {
+            // Casting to bool has to map all the non-zero values to 1.
+            let zeros = self.device().zeros_impl(layout.shape(), self.dtype())?;
+            let zeros_l = Layout::contiguous(layout.shape());
+            return self.cmp(CmpOp::Ne, &zeros, layout, &zeros_l);
+        }
         let shape = layout.shape();
         let dims = shape.dims();
         let el = shape.elem_count();
@@ -1587,7 +1842,11 @@ impl BackendStorage for CudaStorage {
         // lifetime issue and is safe as long as self.slice does not go out of scope before inp
         // is used.
         let inp = match &self.slice {
+            CudaStorageSlice::Bool(inp) => *inp.slice(start_o..).device_ptr(),
             CudaStorageSlice::U8(inp) => *inp.slice(start_o..).device_ptr(),
+            CudaStorageSlice::I8(inp) => *inp.slice(start_o..).device_ptr(),
+            CudaStorageSlice::I16(inp) => *inp.slice(start_o..).device_ptr(),
+            CudaStorageSlice::I32(inp) => *inp.slice(start_o..).device_ptr(),
             CudaStorageSlice::U32(inp) => *inp.slice(start_o..).device_ptr(),
             CudaStorageSlice::I64(inp) => *inp.slice(start_o..).device_ptr(),
             CudaStorageSlice::BF16(inp) => *inp.slice(start_o..).device_ptr(),
@@ -1597,7 +1856,12 @@ impl BackendStorage for CudaStorage {
         };
         let inp = &inp;
 
-        let kernel_name = format!("cast_{}_{}", self.dtype().as_str(), dtype.as_str());
+        // Booleans use the u8 kernels.
+        let src_dtype = match self.dtype() {
+            DType::Bool => DType::U8,
+            dtype => dtype,
+        };
+        let kernel_name = format!("cast_{}_{}", src_dtype.as_str(), dtype.as_str());
         let func = dev.get_or_load_func(&kernel_name, kernels::CAST)?;
         let slice = match dtype {
             DType::U8 => {
@@ -1612,12 +1876,31 @@ impl BackendStorage for CudaStorage {
                 unsafe { func.launch(cfg, params) }.w()?;
                 CudaStorageSlice::U32(out)
             }
+            DType::I8 => {
+                let out = unsafe { dev.alloc::<i8>(el) }.w()?;
+                let params = (el, dims.len(), &ds, *inp, &out);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I8(out)
+            }
+            DType::I16 => {
+                let out = unsafe { dev.alloc::<i16>(el) }.w()?;
+                let params = (el, dims.len(), &ds, *inp, &out);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I16(out)
+            }
+            DType::I32 => {
+                let out = unsafe { dev.alloc::<i32>(el) }.w()?;
+                let params = (el, dims.len(), &ds, *inp, &out);
+                unsafe { func.launch(cfg, params) }.w()?;
+                CudaStorageSlice::I32(out)
+            }
             DType::I64 => {
                 let out = unsafe { dev.alloc::<i64>(el) }.w()?;
                 let params = (el, dims.len(), &ds, *inp, &out);
                 unsafe { func.launch(cfg, params) }.w()?;
                 CudaStorageSlice::I64(out)
             }
+            DType::Bool => unreachable!(),
             DType::BF16 => {
                 let out = unsafe { dev.alloc::<bf16>(el) }.w()?;
                 let params = (el, dims.len(), &ds, *inp, &out);
@@ -1655,6 +1938,12 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn affine_inplace(&mut self, layout: &Layout, mul: f64, add: f64) -> Result<()> {
+        // TODO: Have the kernel write directly to self rather than using a temporary buffer.
+        let res = self.affine(layout, mul, add)?;
+        res.copy_strided(self, &Layout::contiguous(layout.shape()), layout)
+    }
+
     fn powf(&self, layout: &Layout, e: f64) -> Result<Self> {
         let device = self.device().clone();
         let slice = Powf(e).map(&self.slice, &device, layout)?;
@@ -1673,6 +1962,38 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn cumulative_op(&self, op: CumulativeOp, layout: &Layout, dim: usize) -> Result<Self> {
+        // TODO: add a dedicated scan kernel, for now the computation is done on the cpu.
+        let cpu_storage = self.to_cpu_storage()?.cumulative_op(op, layout, dim)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
+    fn arg_sort(&self, layout: &Layout, dim: usize, descending: bool) -> Result<Self> {
+        // TODO: add a dedicated sorting kernel, for now the computation is done on the cpu.
+        let cpu_storage = self.to_cpu_storage()?.arg_sort(layout, dim, descending)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
+    fn fft(&self, _: &Layout, _: usize, _: bool) -> Result<Self> {
+        // TODO: add complex slices and use cuFFT.
+        Err(CudaError::InternalError(
+            "fft is not supported on cuda as complex dtypes are cpu only, move the tensor to the cpu first",
+        ))?
+    }
+
+    fn view_as_real(&self, layout: &Layout) -> Result<Self> {
+        // TODO: add a dedicated kernel, for now the conversion is done on the cpu.
+        let cpu_storage = self.to_cpu_storage()?.view_as_real(layout)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
+    fn view_as_complex(&self, _: &Layout) -> Result<Self> {
+        // TODO: add complex slices to the cuda backend.
+        Err(CudaError::InternalError(
+            "view_as_complex is not supported on cuda as complex dtypes are cpu only, move the tensor to the cpu first",
+        ))?
+    }
+
     fn cmp(&self, op: CmpOp, rhs: &Self, lhs_l: &Layout, rhs_l: &Layout) -> Result<Self> {
         let device = self.device().clone();
         let slice = Cmp(op).map(&self.slice, lhs_l, &rhs.slice, rhs_l, &device)?;
@@ -1696,6 +2017,17 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn binary_impl_inplace<B: BinaryOpT>(
+        &mut self,
+        rhs: &Self,
+        lhs_l: &Layout,
+        rhs_l: &Layout,
+    ) -> Result<()> {
+        // TODO: Have the kernel write directly to self rather than using a temporary buffer.
+        let res = self.binary_impl::<B>(rhs, lhs_l, rhs_l)?;
+        res.copy_strided(self, &Layout::contiguous(lhs_l.shape()), lhs_l)
+    }
+
     fn to_cpu_storage(&self) -> Result<CpuStorage> {
         match &self.slice {
             CudaStorageSlice::U8(slice) => {
@@ -1708,6 +2040,26 @@ impl BackendStorage for CudaStorage {
                 let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
                 Ok(CpuStorage::U32(cpu_storage))
             }
+            CudaStorageSlice::Bool(slice) => {
+                let dev = slice.device();
+                let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
+                Ok(CpuStorage::Bool(cpu_storage))
+            }
+            CudaStorageSlice::I8(slice) => {
+                let dev = slice.device();
+                let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
+                Ok(CpuStorage::I8(cpu_storage))
+            }
+            CudaStorageSlice::I16(slice) => {
+                let dev = slice.device();
+                let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
+                Ok(CpuStorage::I16(cpu_storage))
+            }
+            CudaStorageSlice::I32(slice) => {
+                let dev = slice.device();
+                let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
+                Ok(CpuStorage::I32(cpu_storage))
+            }
             CudaStorageSlice::I64(slice) => {
                 let dev = slice.device();
                 let cpu_storage = dev.dtoh_sync_copy(slice).w()?;
@@ -1787,10 +2139,10 @@ impl BackendStorage for CudaStorage {
             // Make the kernel contiguous if not already the case.
             let mut kernel_c = self.device().zeros_impl(kernel_l.shape(), kernel.dtype())?;
             kernel.copy_strided_src(&mut kernel_c, 0, kernel_l)?;
-            let kernel_l = Layout::contiguous_with_offset((1, n, k), kernel_l.start_offset())
+            let kernel_l = Layout::contiguous((1, n, k))
                 .transpose(1, 2)?
                 .broadcast_as((b, k, n))?;
-            col.matmul(kernel, (b, m, n, k), &col_l, &kernel_l)?
+            col.matmul(&kernel_c, (b, m, n, k), &col_l, &kernel_l)?
         };
         let res_l = Layout::contiguous((b, l_out, n)).transpose(1, 2)?;
         let mut res_t = self.device().zeros_impl(res_l.shape(), res.dtype())?;
@@ -1808,6 +2160,18 @@ impl BackendStorage for CudaStorage {
     ) -> Result<Self> {
         const USE_IM2COL_CONV2D: bool = true;
 
+        let (padding, stride, dilation) = match params.symmetric() {
+            Some(v) => v,
+            None => {
+                // TODO: support asymmetric padding, padding modes and per-axis strides in the
+                // cuda kernels, for now the computation is done on the cpu.
+                let kernel = kernel.to_cpu_storage()?;
+                let cpu_storage = self
+                    .to_cpu_storage()?
+                    .conv2d(l, &kernel, kernel_l, params)?;
+                return self.device().storage_from_cpu_storage(&cpu_storage);
+            }
+        };
         let device = self.device().clone();
         if !USE_IM2COL_CONV2D {
             let slice = Conv2D(params).map(&self.slice, l, &kernel.slice, kernel_l, &device)?;
@@ -1817,9 +2181,9 @@ impl BackendStorage for CudaStorage {
         let col = Im2Col {
             h_k: params.k_h,
             w_k: params.k_w,
-            stride: params.stride,
-            dilation: params.dilation,
-            padding: params.padding,
+            stride,
+            dilation,
+            padding,
         }
         .map(&self.slice, &device, l)?;
         let col = Self { slice: col, device };
@@ -1839,10 +2203,10 @@ impl BackendStorage for CudaStorage {
             // Make the kernel contiguous if not already the case.
             let mut kernel_c = self.device().zeros_impl(kernel_l.shape(), kernel.dtype())?;
             kernel.copy_strided_src(&mut kernel_c, 0, kernel_l)?;
-            let kernel_l = Layout::contiguous_with_offset((1, n, k), kernel_l.start_offset())
+            let kernel_l = Layout::contiguous((1, n, k))
                 .transpose(1, 2)?
                 .broadcast_as((b, k, n))?;
-            col.matmul(kernel, (b, m, n, k), &col_l, &kernel_l)?
+            col.matmul(&kernel_c, (b, m, n, k), &col_l, &kernel_l)?
         };
         let res_l = Layout::contiguous((b, h_out, w_out, n))
             .transpose(1, 2)?
@@ -1860,6 +2224,18 @@ impl BackendStorage for CudaStorage {
         kernel_l: &Layout,
         params: &crate::conv::ParamsConv2D,
     ) -> Result<Self> {
+        let cudnn_supported = params.padding_mode == crate::conv::PaddingMode::Zeros
+            && params.padding_top == params.padding_bottom
+            && params.padding_left == params.padding_right;
+        if !cudnn_supported || (!kernel_l.is_contiguous() && params.symmetric().is_none()) {
+            // TODO: support asymmetric padding and padding modes with cudnn, for now the
+            // computation is done on the cpu.
+            let kernel = kernel.to_cpu_storage()?;
+            let cpu_storage = self
+                .to_cpu_storage()?
+                .conv2d(inp_l, &kernel, kernel_l, params)?;
+            return self.device().storage_from_cpu_storage(&cpu_storage);
+        }
         let device = self.device().clone();
         if !kernel_l.is_contiguous() {
             let slice = Conv2D(params).map(&self.slice, inp_l, &kernel.slice, kernel_l, &device)?;
@@ -1915,6 +2291,36 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn conv_transpose1d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConvTranspose1D,
+    ) -> Result<Self> {
+        // TODO: add a dedicated kernel, for now the computation is done on the cpu.
+        let kernel = kernel.to_cpu_storage()?;
+        let cpu_storage = self
+            .to_cpu_storage()?
+            .conv_transpose1d(l, &kernel, kernel_l, params)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
+    fn conv3d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConv3D,
+    ) -> Result<Self> {
+        // TODO: add a dedicated kernel, for now the computation is done on the cpu.
+        let kernel = kernel.to_cpu_storage()?;
+        let cpu_storage = self
+            .to_cpu_storage()?
+            .conv3d(l, &kernel, kernel_l, params)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
     fn conv_transpose2d(
         &self,
         l: &Layout,
@@ -1954,6 +2360,28 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn avg_pool3d(
+        &self,
+        l: &Layout,
+        k: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        // TODO: add a dedicated kernel, for now the computation is done on the cpu.
+        let cpu_storage = self.to_cpu_storage()?.avg_pool3d(l, k, stride)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
+    fn max_pool3d(
+        &self,
+        l: &Layout,
+        k: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        // TODO: add a dedicated kernel, for now the computation is done on the cpu.
+        let cpu_storage = self.to_cpu_storage()?.max_pool3d(l, k, stride)?;
+        self.device().storage_from_cpu_storage(&cpu_storage)
+    }
+
     fn upsample_nearest1d(&self, _: &Layout, _out_sz: usize) -> Result<Self> {
         crate::bail!("upsample-nearest1d is not supported on cuda")
     }
@@ -2005,6 +2433,24 @@ impl BackendStorage for CudaStorage {
         Ok(acc)
     }
 
+    fn index_add_inplace(
+        &mut self,
+        l: &Layout,
+        ids: &Self,
+        ids_l: &Layout,
+        src: &Self,
+        src_l: &Layout,
+        dim: usize,
+    ) -> Result<()> {
+        if l.is_contiguous() && l.start_offset() == 0 {
+            let device = self.device().clone();
+            IndexAdd(ids, ids_l, dim).map(&mut self.slice, l.shape(), &src.slice, src_l, &device)
+        } else {
+            let res = self.index_add(l, ids, ids_l, src, src_l, dim)?;
+            res.copy_strided(self, &Layout::contiguous(l.shape()), l)
+        }
+    }
+
     fn matmul(
         &self,
         rhs: &Self,
@@ -2073,6 +2519,18 @@ impl BackendStorage for CudaStorage {
         Ok(Self { slice, device })
     }
 
+    fn copy_strided(&self, dst: &mut Self, src_l: &Layout, dst_l: &Layout) -> Result<()> {
+        if let Some((o1, _)) = dst_l.contiguous_offsets() {
+            return self.copy_strided_src(dst, o1, src_l);
+        }
+        // There is no kernel for strided destinations yet so this goes through the cpu.
+        let src = self.to_cpu_storage()?;
+        let mut cpu_dst = dst.to_cpu_storage()?;
+        src.copy_strided(&mut cpu_dst, src_l, dst_l)?;
+        *dst = dst.device().storage_from_cpu_storage(&cpu_dst)?;
+        Ok(())
+    }
+
     fn copy_strided_src(&self, dst: &mut Self, dst_offset: usize, src_l: &Layout) -> Result<()> {
         let src_shape = src_l.shape();
         let dims = src_shape.dims();
@@ -2144,6 +2602,54 @@ impl BackendStorage for CudaStorage {
                     unsafe { func.launch(cfg, params) }.w()?
                 }
             }
+            (CudaStorageSlice::Bool(src), CudaStorageSlice::Bool(dst)) => {
+                let (src, mut dst) = slice_src_and_dst(src, src_l, dst, dst_offset);
+                if src_l.is_contiguous() {
+                    dev.dtod_copy(&src, &mut dst).w()?
+                } else {
+                    let func = dev.get_or_load_func("ucopy_u8", kernels::UNARY)?;
+                    // SAFETY: Set later by running the kernel.
+                    let params = (el_count, dims.len(), &ds, &src, &mut dst);
+                    // SAFETY: ffi.
+                    unsafe { func.launch(cfg, params) }.w()?
+                }
+            }
+            (CudaStorageSlice::I8(src), CudaStorageSlice::I8(dst)) => {
+                let (src, mut dst) = slice_src_and_dst(src, src_l, dst, dst_offset);
+                if src_l.is_contiguous() {
+                    dev.dtod_copy(&src, &mut dst).w()?
+                } else {
+                    let func = dev.get_or_load_func("ucopy_i8", kernels::UNARY)?;
+                    // SAFETY: Set later by running the kernel.
+                    let params = (el_count, dims.len(), &ds, &src, &mut dst);
+                    // SAFETY: ffi.
+                    unsafe { func.launch(cfg, params) }.w()?
+                }
+            }
+            (CudaStorageSlice::I16(src), CudaStorageSlice::I16(dst)) => {
+                let (src, mut dst) = slice_src_and_dst(src, src_l, dst, dst_offset);
+                if src_l.is_contiguous() {
+                    dev.dtod_copy(&src, &mut dst).w()?
+                } else {
+                    let func = dev.get_or_load_func("ucopy_i16", kernels::UNARY)?;
+                    // SAFETY: Set later by running the kernel.
+                    let params = (el_count, dims.len(), &ds, &src, &mut dst);
+                    // SAFETY: ffi.
+                    unsafe { func.launch(cfg, params) }.w()?
+                }
+            }
+            (CudaStorageSlice::I32(src), CudaStorageSlice::I32(dst)) => {
+                let (src, mut dst) = slice_src_and_dst(src, src_l, dst, dst_offset);
+                if src_l.is_contiguous() {
+                    dev.dtod_copy(&src, &mut dst).w()?
+                } else {
+                    let func = dev.get_or_load_func("ucopy_i32", kernels::UNARY)?;
+                    // SAFETY: Set later by running the kernel.
+                    let params = (el_count, dims.len(), &ds, &src, &mut dst);
+                    // SAFETY: ffi.
+                    unsafe { func.launch(cfg, params) }.w()?
+                }
+            }
             (CudaStorageSlice::I64(src), CudaStorageSlice::I64(dst)) => {
                 let (src, mut dst) = slice_src_and_dst(src, src_l, dst, dst_offset);
                 if src_l.is_contiguous() {
@@ -46,9 +46,9 @@ pub(crate) fn launch_conv2d<
         c
     })?;
     let conv = cudnn.create_conv2d::<T>(
-        /* pad */ [params.padding as i32, params.padding as i32],
-        /* stride */ [params.stride as i32, params.stride as i32],
-        /* dilation */ [params.dilation as i32, params.dilation as i32],
+        /* pad */ [params.padding_top as i32, params.padding_left as i32],
+        /* stride */ [params.stride_h as i32, params.stride_w as i32],
+        /* dilation */ [params.dilation_h as i32, params.dilation_w as i32],
         cudarc::cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
     )?;
     let x_shape = [
@@ -13,6 +13,8 @@ pub enum DeviceLocation {
 #[derive(Debug, Clone)]
 pub enum Device {
     Cpu,
+    /// A cpu device that runs its kernels on its own thread pool rather than on the default one.
+    CpuPool(crate::CpuPool),
     Cuda(crate::CudaDevice),
 }
 
@@ -128,9 +130,25 @@ impl Device {
         Ok(Self::Cuda(crate::CudaDevice::new(ordinal)?))
     }
 
+    /// Creates a cpu device with its own pool of `num_threads` threads, the ops applied to the
+    /// tensors on this device run their parallel kernels on this pool.
+    pub fn cpu_with_threads(num_threads: usize) -> Result<Self> {
+        Ok(Self::CpuPool(crate::CpuPool::new(num_threads)?))
+    }
+
+    /// The thread pool carried by the device, `None` for the default cpu device and for cuda
+    /// devices.
+    pub fn cpu_pool(&self) -> Option<&crate::CpuPool> {
+        match self {
+            Self::CpuPool(pool) => Some(pool),
+            Self::Cpu | Self::Cuda(_) => None,
+        }
+    }
+
     pub fn same_device(&self, rhs: &Self) -> bool {
         match (self, rhs) {
             (Self::Cpu, Self::Cpu) => true,
+            (Self::CpuPool(lhs), Self::CpuPool(rhs)) => lhs.same_pool(rhs),
             (Self::Cuda(lhs), Self::Cuda(rhs)) => lhs.same_device(rhs),
             _ => false,
         }
@@ -138,21 +156,21 @@ impl Device {
 
     pub fn location(&self) -> DeviceLocation {
         match self {
-            Self::Cpu => DeviceLocation::Cpu,
+            Self::Cpu | Self::CpuPool(_) => DeviceLocation::Cpu,
             Self::Cuda(device) => device.location(),
         }
     }
 
     pub fn is_cpu(&self) -> bool {
         match self {
-            Self::Cpu => true,
+            Self::Cpu | Self::CpuPool(_) => true,
             Self::Cuda(_) => false,
         }
     }
 
     pub fn is_cuda(&self) -> bool {
         match self {
-            Self::Cpu => false,
+            Self::Cpu | Self::CpuPool(_) => false,
             Self::Cuda(_) => true,
         }
     }
@@ -165,6 +183,78 @@ impl Device {
         }
     }
 
+    /// Sets the seed of the default random number generator of the device, e.g. as used by
+    /// [`crate::Tensor::rand`] and [`crate::Tensor::randn`]. After this, the same seed always
+    /// results in the same sequence of random values whatever the device. The cpu generator is
+    /// shared by all the cpu devices.
+    pub fn set_seed(&self, seed: u64) -> Result<()> {
+        match self {
+            Self::Cpu | Self::CpuPool(_) => CpuDevice.set_seed(seed),
+            Self::Cuda(device) => device.set_seed(seed),
+        }
+    }
+
+    /// Returns the memory statistics of the device. The in use memory only accounts for the
+    /// storages of the live tensors, temporary buffers allocated while running an op are not
+    /// included. The cpu statistics are shared by all the cpu devices.
+    ///
+    /// ```rust
+    /// use candle_core::{Device, Tensor};
+    /// let device = Device::Cpu;
+    /// let t = Tensor::zeros((1024, 1024), candle_core::DType::F32, &device)?.contiguous()?;
+    /// let stats = device.memory_stats()?;
+    /// assert!(stats.peak >= stats.in_use && stats.in_use >= 4 * 1024 * 1024);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn memory_stats(&self) -> Result<crate::MemoryStats> {
+        match self {
+            Self::Cpu | Self::CpuPool(_) => CpuDevice.memory_stats(),
+            Self::Cuda(device) => device.memory_stats(),
+        }
+    }
+
+    /// Releases the buffers that the device keeps cached for reuse after the tensors using them
+    /// have been dropped.
+    pub fn empty_cache(&self) -> Result<()> {
+        match self {
+            Self::Cpu | Self::CpuPool(_) => CpuDevice.empty_cache(),
+            Self::Cuda(device) => device.empty_cache(),
+        }
+    }
+
+    /// Sets the maximum number of bytes that the device keeps cached. The default is
+    /// [`crate::memory::DEFAULT_CACHE_LIMIT`], i.e. 0, which disables the caching.
+    pub fn set_cache_limit(&self, cache_limit: usize) -> Result<()> {
+        match self {
+            Self::Cpu | Self::CpuPool(_) => CpuDevice.set_cache_limit(cache_limit),
+            Self::Cuda(device) => device.set_cache_limit(cache_limit),
+        }
+    }
+
+    pub(crate) fn rand_uniform_with_generator(
+        &self,
+        gen: &mut crate::Generator,
+        lo: f64,
+        up: f64,
+        shape: &Shape,
+        dtype: DType,
+    ) -> Result<Storage> {
+        let storage = gen.rand_uniform(shape, dtype, lo, up)?;
+        self.storage_from_cpu_storage(storage)
+    }
+
+    pub(crate) fn rand_normal_with_generator(
+        &self,
+        gen: &mut crate::Generator,
+        mean: f64,
+        std: f64,
+        shape: &Shape,
+        dtype: DType,
+    ) -> Result<Storage> {
+        let storage = gen.rand_normal(shape, dtype, mean, std)?;
+        self.storage_from_cpu_storage(storage)
+    }
+
     pub(crate) fn rand_uniform_f64(
         &self,
         lo: f64,
@@ -173,7 +263,7 @@ impl Device {
         dtype: DType,
     ) -> Result<Storage> {
         match self {
-            Device::Cpu => {
+            Device::Cpu | Device::CpuPool(_) => {
                 let storage = CpuDevice.rand_uniform(shape, dtype, lo, up)?;
                 Ok(Storage::Cpu(storage))
             }
@@ -201,7 +291,7 @@ impl Device {
         dtype: DType,
     ) -> Result<Storage> {
         match self {
-            Device::Cpu => {
+            Device::Cpu | Device::CpuPool(_) => {
                 let storage = CpuDevice.rand_normal(shape, dtype, mean, std)?;
                 Ok(Storage::Cpu(storage))
             }
@@ -223,7 +313,7 @@ impl Device {
 
     pub(crate) fn ones(&self, shape: &Shape, dtype: DType) -> Result<Storage> {
         match self {
-            Device::Cpu => {
+            Device::Cpu | Device::CpuPool(_) => {
                 let storage = CpuDevice.ones_impl(shape, dtype)?;
                 Ok(Storage::Cpu(storage))
             }
@@ -236,7 +326,7 @@ impl Device {
 
     pub(crate) fn zeros(&self, shape: &Shape, dtype: DType) -> Result<Storage> {
         match self {
-            Device::Cpu => {
+            Device::Cpu | Device::CpuPool(_) => {
                 let storage = CpuDevice.zeros_impl(shape, dtype)?;
                 Ok(Storage::Cpu(storage))
             }
@@ -249,7 +339,7 @@ impl Device {
 
     pub(crate) fn storage<A: NdArray>(&self, array: A) -> Result<Storage> {
         match self {
-            Device::Cpu => Ok(Storage::Cpu(array.to_cpu_storage())),
+            Device::Cpu | Device::CpuPool(_) => Ok(Storage::Cpu(array.to_cpu_storage())),
             Device::Cuda(device) => {
                 let storage = array.to_cpu_storage();
                 let storage = device.storage_from_cpu_storage(&storage)?;
@@ -258,9 +348,19 @@ impl Device {
         }
     }
 
+    fn storage_from_cpu_storage(&self, storage: CpuStorage) -> Result<Storage> {
+        match self {
+            Device::Cpu | Device::CpuPool(_) => Ok(Storage::Cpu(storage)),
+            Device::Cuda(device) => {
+                let storage = device.storage_from_cpu_storage(&storage)?;
+                Ok(Storage::Cuda(storage))
+            }
+        }
+    }
+
     pub(crate) fn storage_owned<S: WithDType>(&self, data: Vec<S>) -> Result<Storage> {
         match self {
-            Device::Cpu => Ok(Storage::Cpu(S::to_cpu_storage_owned(data))),
+            Device::Cpu | Device::CpuPool(_) => Ok(Storage::Cpu(S::to_cpu_storage_owned(data))),
             Device::Cuda(device) => {
                 let storage = S::to_cpu_storage_owned(data);
                 let storage = device.storage_from_cpu_storage(&storage)?;
@@ -1,6 +1,7 @@
 /// Pretty printing of tensors
 /// This implementation should be in line with the PyTorch version.
 /// https://github.com/pytorch/pytorch/blob/7b419e8513a024e172eae767e24ec1b849976b13/torch/_tensor_str.py
+use crate::complex::{C128, C64};
 use crate::{DType, Result, Tensor, WithDType};
 use half::{bf16, f16};
 
@@ -19,12 +20,12 @@ impl Tensor {
         write!(f, "Tensor[")?;
         match self.dims() {
             [] => {
-                if let Ok(v) = self.to_scalar::<T>() {
+                if let Ok(v) = self.to_dtype(T::DTYPE).and_then(|t| t.to_scalar::<T>()) {
                     write!(f, "{v}")?
                 }
             }
             [s] if *s < 10 => {
-                if let Ok(vs) = self.to_vec1::<T>() {
+                if let Ok(vs) = self.to_dtype(T::DTYPE).and_then(|t| t.to_vec1::<T>()) {
                     for (i, v) in vs.iter().enumerate() {
                         if i > 0 {
                             write!(f, ", ")?;
@@ -50,13 +51,20 @@ impl Tensor {
 impl std::fmt::Debug for Tensor {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self.dtype() {
+            // Booleans are displayed as 0 and 1.
+            DType::Bool => self.fmt_dt::<u8>(f),
             DType::U8 => self.fmt_dt::<u8>(f),
             DType::U32 => self.fmt_dt::<u32>(f),
+            DType::I8 => self.fmt_dt::<i8>(f),
+            DType::I16 => self.fmt_dt::<i16>(f),
+            DType::I32 => self.fmt_dt::<i32>(f),
             DType::I64 => self.fmt_dt::<i64>(f),
             DType::BF16 => self.fmt_dt::<bf16>(f),
             DType::F16 => self.fmt_dt::<f16>(f),
             DType::F32 => self.fmt_dt::<f32>(f),
             DType::F64 => self.fmt_dt::<f64>(f),
+            DType::C64 => self.fmt_dt::<C64>(f),
+            DType::C128 => self.fmt_dt::<C128>(f),
         }
     }
 }
@@ -378,6 +386,32 @@ where
     }
 }
 
+struct ComplexFormatter<S: WithDType> {
+    precision: usize,
+    _phantom: std::marker::PhantomData<S>,
+}
+
+impl<S: WithDType> ComplexFormatter<S> {
+    fn new(po: &PrinterOptions) -> Self {
+        Self {
+            precision: po.precision,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S> TensorFormatter for ComplexFormatter<S>
+where
+    S: WithDType + std::fmt::Display,
+{
+    type Elem = S;
+
+    fn fmt<T: std::fmt::Write>(&self, v: Self::Elem, max_w: usize, f: &mut T) -> std::fmt::Result {
+        let v = format!("{v:.prec$}", prec = self.precision);
+        write!(f, "{v:>max_w$}")
+    }
+}
+
 fn get_summarized_data(t: &Tensor, edge_items: usize) -> Result<Tensor> {
     let dims = t.dims();
     if dims.is_empty() {
@@ -435,6 +469,34 @@ impl std::fmt::Display for Tensor {
                 tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
                 writeln!(f)?;
             }
+            DType::Bool => {
+                let tf: IntFormatter<u8> = IntFormatter::new();
+                let t = self.to_dtype(DType::U8);
+                let to_display = to_display.to_dtype(DType::U8);
+                if let (Ok(t), Ok(to_display)) = (t, to_display) {
+                    let max_w = tf.max_width(&to_display);
+                    tf.fmt_tensor(&t, 1, max_w, summarize, &po, f)?;
+                    writeln!(f)?;
+                }
+            }
+            DType::I8 => {
+                let tf: IntFormatter<i8> = IntFormatter::new();
+                let max_w = tf.max_width(&to_display);
+                tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                writeln!(f)?;
+            }
+            DType::I16 => {
+                let tf: IntFormatter<i16> = IntFormatter::new();
+                let max_w = tf.max_width(&to_display);
+                tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                writeln!(f)?;
+            }
+            DType::I32 => {
+                let tf: IntFormatter<i32> = IntFormatter::new();
+                let max_w = tf.max_width(&to_display);
+                tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                writeln!(f)?;
+            }
             DType::I64 => {
                 let tf: IntFormatter<i64> = IntFormatter::new();
                 let max_w = tf.max_width(&to_display);
@@ -469,6 +531,18 @@ impl std::fmt::Display for Tensor {
                     writeln!(f)?;
                 }
             }
+            DType::C64 => {
+                let tf: ComplexFormatter<C64> = ComplexFormatter::new(&po);
+                let max_w = tf.max_width(&to_display);
+                tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                writeln!(f)?;
+            }
+            DType::C128 => {
+                let tf: ComplexFormatter<C128> = ComplexFormatter::new(&po);
+                let max_w = tf.max_width(&to_display);
+                tf.fmt_tensor(self, 1, max_w, summarize, &po, f)?;
+                writeln!(f)?;
+            }
         };
 
         let device_str = match self.device().location() {
@@ -6,10 +6,18 @@ use crate::{CpuStorage, Error, Result};
 /// The different types of elements allowed in tensors.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum DType {
+    // Boolean, stored using a single byte per element holding either 0 or 1.
+    Bool,
     // Unsigned 8 bits integer.
     U8,
     // Unsigned 32 bits integer.
     U32,
+    // Signed 8 bits integer.
+    I8,
+    // Signed 16 bits integer.
+    I16,
+    // Signed 32 bits integer.
+    I32,
     // Signed 64 bits integer.
     I64,
     // Brain floating-point using half precision (16 bits).
@@ -20,6 +28,10 @@ pub enum DType {
     F32,
     // Floating-point using double precision (64 bits).
     F64,
+    // Complex number using single precision floats for the real and imaginary parts (64 bits).
+    C64,
+    // Complex number using double precision floats for the real and imaginary parts (128 bits).
+    C128,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -29,13 +41,19 @@ impl std::str::FromStr for DType {
     type Err = DTypeParseError;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s {
+            "bool" => Ok(Self::Bool),
             "u8" => Ok(Self::U8),
             "u32" => Ok(Self::U32),
+            "i8" => Ok(Self::I8),
+            "i16" => Ok(Self::I16),
+            "i32" => Ok(Self::I32),
             "i64" => Ok(Self::I64),
             "bf16" => Ok(Self::BF16),
             "f16" => Ok(Self::F16),
             "f32" => Ok(Self::F32),
             "f64" => Ok(Self::F64),
+            "c64" => Ok(Self::C64),
+            "c128" => Ok(Self::C128),
             _ => Err(DTypeParseError),
         }
     }
@@ -45,28 +63,58 @@ impl DType {
     /// String representation for dtypes.
     pub fn as_str(&self) -> &'static str {
         match self {
+            Self::Bool => "bool",
             Self::U8 => "u8",
             Self::U32 => "u32",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
             Self::I64 => "i64",
             Self::BF16 => "bf16",
             Self::F16 => "f16",
             Self::F32 => "f32",
             Self::F64 => "f64",
+            Self::C64 => "c64",
+            Self::C128 => "c128",
         }
     }
 
     /// The size used by each element in bytes, i.e. 1 for `U8`, 4 for `F32`.
     pub fn size_in_bytes(&self) -> usize {
         match self {
+            Self::Bool => 1,
             Self::U8 => 1,
             Self::U32 => 4,
+            Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I32 => 4,
             Self::I64 => 8,
             Self::BF16 => 2,
             Self::F16 => 2,
             Self::F32 => 4,
             Self::F64 => 8,
+            Self::C64 => 8,
+            Self::C128 => 16,
         }
     }
+
+    /// Returns true for the real floating point dtypes, i.e. `BF16`, `F16`, `F32` and `F64`.
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::BF16 | Self::F16 | Self::F32 | Self::F64)
+    }
+
+    /// Returns true for the integer dtypes, booleans are not included.
+    pub fn is_int(&self) -> bool {
+        matches!(
+            self,
+            Self::U8 | Self::U32 | Self::I8 | Self::I16 | Self::I32 | Self::I64
+        )
+    }
+
+    /// Returns true for the complex number dtypes, i.e. `C64` and `C128`.
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Self::C64 | Self::C128)
+    }
 }
 
 pub trait WithDType:
@@ -137,15 +185,23 @@ macro_rules! with_dtype {
         }
     };
 }
+use crate::complex::{C128, C64};
 use half::{bf16, f16};
 
 with_dtype!(u8, U8, |v: f64| v as u8, |v: u8| v as f64);
 with_dtype!(u32, U32, |v: f64| v as u32, |v: u32| v as f64);
+with_dtype!(i8, I8, |v: f64| v as i8, |v: i8| v as f64);
+with_dtype!(i16, I16, |v: f64| v as i16, |v: i16| v as f64);
+with_dtype!(i32, I32, |v: f64| v as i32, |v: i32| v as f64);
 with_dtype!(i64, I64, |v: f64| v as i64, |v: i64| v as f64);
 with_dtype!(f16, F16, f16::from_f64, f16::to_f64);
 with_dtype!(bf16, BF16, bf16::from_f64, bf16::to_f64);
 with_dtype!(f32, F32, |v: f64| v as f32, |v: f32| v as f64);
 with_dtype!(f64, F64, |v: f64| v, |v: f64| v);
+// Converting a complex number to a float only keeps its real part.
+with_dtype!(C64, C64, |v: f64| C64::new(v as f32, 0.), |v: C64| v.re
+    as f64);
+with_dtype!(C128, C128, |v: f64| C128::new(v, 0.), |v: C128| v.re);
 
 pub trait IntDType: WithDType {
     fn is_true(&self) -> bool;
@@ -161,6 +217,33 @@ impl IntDType for i64 {
     }
 }
 
+impl IntDType for i32 {
+    fn is_true(&self) -> bool {
+        *self != 0
+    }
+    fn as_usize(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl IntDType for i16 {
+    fn is_true(&self) -> bool {
+        *self != 0
+    }
+    fn as_usize(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl IntDType for i8 {
+    fn is_true(&self) -> bool {
+        *self != 0
+    }
+    fn as_usize(&self) -> usize {
+        *self as usize
+    }
+}
+
 impl IntDType for u32 {
     fn is_true(&self) -> bool {
         *self != 0
@@ -0,0 +1,304 @@
+//! Einstein summation, the contractions are planned onto `permute`, `sum` and `matmul` so that
+//! the result is differentiable through the usual backprop.
+use crate::{Result, Tensor};
+use std::collections::HashMap;
+
+// Labels are identified by their ascii code, the dimensions covered by an ellipsis get labels
+// starting from `ELLIPSIS`, right-aligned so that they broadcast like the usual binary ops.
+type Label = usize;
+const ELLIPSIS: Label = 256;
+
+enum Item {
+    Label(Label),
+    Ellipsis,
+}
+
+fn parse_term(term: &str) -> Result<Vec<Item>> {
+    let mut items = vec![];
+    let mut rest = term;
+    while let Some(c) = rest.chars().next() {
+        if let Some(r) = rest.strip_prefix("...") {
+            if items.iter().any(|i| matches!(i, Item::Ellipsis)) {
+                crate::bail!("einsum: multiple ellipsis in term '{term}'")
+            }
+            items.push(Item::Ellipsis);
+            rest = r;
+        } else if c.is_ascii_alphabetic() {
+            items.push(Item::Label(c as Label));
+            rest = &rest[1..];
+        } else {
+            crate::bail!("einsum: unexpected character '{c}' in term '{term}'")
+        }
+    }
+    Ok(items)
+}
+
+// The number of dimensions covered by the ellipsis of an input term with the given rank.
+fn ellipsis_dims(items: &[Item], rank: usize, term: &str) -> Result<usize> {
+    let n_labels = items.iter().filter(|i| matches!(i, Item::Label(_))).count();
+    let has_ellipsis = items.len() > n_labels;
+    if rank < n_labels || (!has_ellipsis && rank != n_labels) {
+        crate::bail!("einsum: term '{term}' does not match an operand of rank {rank}")
+    }
+    Ok(rank - n_labels)
+}
+
+fn expand(items: &[Item], ell_dims: usize, max_ell_dims: usize) -> Vec<Label> {
+    let mut labels = vec![];
+    for item in items.iter() {
+        match item {
+            Item::Label(l) => labels.push(*l),
+            Item::Ellipsis => {
+                labels.extend((max_ell_dims - ell_dims..max_ell_dims).map(|i| ELLIPSIS + i))
+            }
+        }
+    }
+    labels
+}
+
+// Extracts the diagonal for the labels that appear multiple times in a single term, e.g. "ii".
+fn diagonal(mut t: Tensor, mut labels: Vec<Label>) -> Result<(Tensor, Vec<Label>)> {
+    while let Some((p, q)) = (0..labels.len())
+        .flat_map(|p| (p + 1..labels.len()).map(move |q| (p, q)))
+        .find(|&(p, q)| labels[p] == labels[q])
+    {
+        let dims = t.dims();
+        let n = dims[p];
+        if dims[q] != n {
+            crate::bail!(
+                "einsum: diagonal with mismatching sizes {n} and {}",
+                dims[q]
+            )
+        }
+        let mut mask_shape = vec![1; dims.len()];
+        mask_shape[p] = n;
+        mask_shape[q] = n;
+        let idx = Tensor::arange(0u32, n as u32, t.device())?;
+        let mask = idx
+            .unsqueeze(1)?
+            .broadcast_as((n, n))?
+            .eq(&idx.unsqueeze(0)?.broadcast_as((n, n))?)?
+            .to_dtype(t.dtype())?
+            .reshape(mask_shape)?;
+        t = t.broadcast_mul(&mask)?.sum(q)?;
+        labels.remove(q);
+    }
+    Ok((t, labels))
+}
+
+// Sums over the dimensions whose label is not part of `keep`.
+fn reduce(t: Tensor, labels: Vec<Label>, keep: &[Label]) -> Result<(Tensor, Vec<Label>)> {
+    let sum_dims: Vec<usize> = (0..labels.len())
+        .filter(|&i| !keep.contains(&labels[i]))
+        .collect();
+    if sum_dims.is_empty() {
+        return Ok((t, labels));
+    }
+    let labels = labels.into_iter().filter(|l| keep.contains(l)).collect();
+    Ok((t.sum(sum_dims)?, labels))
+}
+
+// Permutes `t` so that its labels follow `order` and broadcasts the dimensions of size one.
+fn arrange(t: &Tensor, labels: &[Label], order: &[Label], sizes: &[usize]) -> Result<Tensor> {
+    let perm: Vec<usize> = order
+        .iter()
+        .map(|l| labels.iter().position(|x| x == l).unwrap())
+        .collect();
+    let t = if perm.iter().enumerate().all(|(i, &p)| i == p) {
+        t.clone()
+    } else {
+        t.permute(perm)?
+    };
+    if t.dims() == sizes {
+        Ok(t)
+    } else {
+        t.broadcast_as(sizes)
+    }
+}
+
+// Contracts two operands with a batched matmul, the labels in `keep` are preserved.
+fn contract(
+    (a, la): (Tensor, Vec<Label>),
+    (b, lb): (Tensor, Vec<Label>),
+    keep: &[Label],
+    sizes: &HashMap<Label, usize>,
+) -> Result<(Tensor, Vec<Label>)> {
+    let batch: Vec<Label> = la
+        .iter()
+        .filter(|l| lb.contains(l) && keep.contains(l))
+        .copied()
+        .collect();
+    let k: Vec<Label> = la
+        .iter()
+        .filter(|l| lb.contains(l) && !keep.contains(l))
+        .copied()
+        .collect();
+    let m: Vec<Label> = la.iter().filter(|l| !lb.contains(l)).copied().collect();
+    let n: Vec<Label> = lb.iter().filter(|l| !la.contains(l)).copied().collect();
+    let dims = |ls: &[Label]| ls.iter().map(|l| sizes[l]).collect::<Vec<_>>();
+    let size = |ls: &[Label]| ls.iter().map(|l| sizes[l]).product::<usize>();
+    let (bs, ms, ns, ks) = (size(&batch), size(&m), size(&n), size(&k));
+
+    // The sizes of the dimensions that are only present in one operand are kept as is.
+    let a_order = [batch.as_slice(), &m, &k].concat();
+    let mut a_dims = dims(&a_order);
+    for (i, l) in a_order.iter().enumerate() {
+        if m.contains(l) {
+            a_dims[i] = a.dim(la.iter().position(|x| x == l).unwrap())?
+        }
+    }
+    let a = arrange(&a, &la, &a_order, &a_dims)?.reshape((bs, ms, ks))?;
+    let b_order = [batch.as_slice(), &n, &k].concat();
+    let mut b_dims = dims(&b_order);
+    for (i, l) in b_order.iter().enumerate() {
+        if n.contains(l) {
+            b_dims[i] = b.dim(lb.iter().position(|x| x == l).unwrap())?
+        }
+    }
+    let b = arrange(&b, &lb, &b_order, &b_dims)?.reshape((bs, ns, ks))?;
+
+    let res_dims = [
+        dims(&batch),
+        a_dims[batch.len()..batch.len() + m.len()].to_vec(),
+        b_dims[batch.len()..batch.len() + n.len()].to_vec(),
+    ]
+    .concat();
+    let res = a.matmul(&b.t()?)?.reshape(res_dims)?;
+    Ok((res, [batch, m, n].concat()))
+}
+
+impl Tensor {
+    /// Einstein summation over the operands, following the conventions of `numpy.einsum`.
+    ///
+    /// The equation has one term per operand, e.g. `"bhqd,bhkd->bhqk"`, each letter labels a
+    /// dimension of the corresponding operand. The labels that are not part of the output are
+    /// summed over. An ellipsis `...` stands for the remaining dimensions, these are broadcasted
+    /// between operands. When the `->` part is omitted, the output uses the ellipsis dimensions
+    /// followed by the labels that only appear once, in alphabetical order.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let q = Tensor::arange(0f32, 12., &Device::Cpu)?.reshape((1, 2, 3, 2))?;
+    /// let k = Tensor::arange(0f32, 8., &Device::Cpu)?.reshape((1, 2, 2, 2))?;
+    /// let attn = Tensor::einsum("bhqd,bhkd->bhqk", &[&q, &k])?;
+    /// assert_eq!(attn.dims(), &[1, 2, 3, 2]);
+    /// let expected = q.matmul(&k.t()?)?;
+    /// assert_eq!(attn.squeeze(0)?.to_vec3::<f32>()?, expected.squeeze(0)?.to_vec3::<f32>()?);
+    ///
+    /// let m = Tensor::new(&[[1f32, 2.], [3., 4.]], &Device::Cpu)?;
+    /// assert_eq!(Tensor::einsum("ii", &[&m])?.to_scalar::<f32>()?, 5.);
+    /// assert_eq!(Tensor::einsum("ij->j", &[&m])?.to_vec1::<f32>()?, &[4., 6.]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn einsum(equation: &str, operands: &[&Tensor]) -> Result<Tensor> {
+        let equation: String = equation.chars().filter(|c| !c.is_whitespace()).collect();
+        let (inputs, output) = match equation.split_once("->") {
+            Some((inputs, output)) => (inputs, Some(output)),
+            None => (equation.as_str(), None),
+        };
+        let terms: Vec<&str> = inputs.split(',').collect();
+        if terms.len() != operands.len() {
+            crate::bail!(
+                "einsum: the equation has {} terms but {} operands were provided",
+                terms.len(),
+                operands.len()
+            )
+        }
+        let items = terms
+            .iter()
+            .map(|t| parse_term(t))
+            .collect::<Result<Vec<_>>>()?;
+        let ell_dims = items
+            .iter()
+            .zip(operands.iter().zip(terms.iter()))
+            .map(|(items, (op, term))| ellipsis_dims(items, op.rank(), term))
+            .collect::<Result<Vec<_>>>()?;
+        let max_ell_dims = ell_dims.iter().copied().max().unwrap_or(0);
+        let labels: Vec<Vec<Label>> = items
+            .iter()
+            .zip(ell_dims.iter())
+            .map(|(items, &e)| expand(items, e, max_ell_dims))
+            .collect();
+
+        // The size of each label, dimensions of size one are broadcasted.
+        let mut sizes: HashMap<Label, usize> = HashMap::new();
+        for (labels, op) in labels.iter().zip(operands.iter()) {
+            for (l, &d) in labels.iter().zip(op.dims()) {
+                let size = sizes.entry(*l).or_insert(d);
+                if *size == 1 {
+                    *size = d
+                } else if d != 1 && d != *size {
+                    crate::bail!(
+                        "einsum: size mismatch for label '{}', {d} and {size}",
+                        label_name(*l)
+                    )
+                }
+            }
+        }
+
+        let output = match output {
+            Some(output) => {
+                let output = expand(&parse_term(output)?, max_ell_dims, max_ell_dims);
+                for (i, l) in output.iter().enumerate() {
+                    if !sizes.contains_key(l) {
+                        crate::bail!("einsum: unknown output label '{}'", label_name(*l))
+                    }
+                    if output[..i].contains(l) {
+                        crate::bail!("einsum: repeated output label '{}'", label_name(*l))
+                    }
+                }
+                output
+            }
+            None => {
+                let mut output: Vec<Label> = (0..max_ell_dims).map(|i| ELLIPSIS + i).collect();
+                let mut once: Vec<Label> = sizes
+                    .keys()
+                    .filter(|&&l| {
+                        l < ELLIPSIS && labels.iter().flatten().filter(|&&x| x == l).count() == 1
+                    })
+                    .copied()
+                    .collect();
+                once.sort();
+                output.extend(once);
+                output
+            }
+        };
+
+        let mut operands = operands
+            .iter()
+            .zip(labels)
+            .map(|(op, labels)| diagonal((*op).clone(), labels))
+            .collect::<Result<Vec<_>>>()?;
+        if operands.is_empty() {
+            crate::bail!("einsum: no operands")
+        }
+        let rest = operands.split_off(1);
+        // The labels needed once the operands before `rest` have been contracted.
+        let needed = |rest: &[(Tensor, Vec<Label>)]| -> Vec<Label> {
+            let rest = rest.iter().flat_map(|(_, l)| l.iter());
+            output.iter().chain(rest).copied().collect()
+        };
+        let (t, l) = operands.remove(0);
+        let mut acc = reduce(t, l, &needed(&rest))?;
+        for (i, (t, l)) in rest.iter().enumerate() {
+            let keep = needed(&rest[i + 1..]);
+            let rhs_keep = [keep.as_slice(), &acc.1].concat();
+            let rhs = reduce(t.clone(), l.clone(), &rhs_keep)?;
+            acc = contract(acc, rhs, &keep, &sizes)?;
+        }
+        let (t, labels) = acc;
+        let dims: Vec<usize> = output
+            .iter()
+            .map(|l| t.dim(labels.iter().position(|x| x == l).unwrap()))
+            .collect::<Result<_>>()?;
+        arrange(&t, &labels, &output, &dims)
+    }
+}
+
+fn label_name(l: Label) -> String {
+    if l < ELLIPSIS {
+        (l as u8 as char).to_string()
+    } else {
+        "...".to_string()
+    }
+}
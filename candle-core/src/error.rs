@@ -132,6 +132,9 @@ pub enum Error {
     #[error("cannot set variable {msg}")]
     CannotSetVar { msg: &'static str },
 
+    #[error("in-place {op} {msg}")]
+    InplaceOp { op: &'static str, msg: &'static str },
+
     // Box indirection to avoid large variant.
     #[error("{0:?}")]
     MatMulUnexpectedStriding(Box<MatMulUnexpectedStriding>),
@@ -145,6 +148,9 @@ pub enum Error {
     #[error("backward is not supported for {op}")]
     BackwardNotSupported { op: &'static str },
 
+    #[error("jvp is not supported for {op}")]
+    JvpNotSupported { op: &'static str },
+
     // === Other Errors ===
     #[error("the candle crate has not been built with cuda support")]
     NotCompiledWithCudaSupport,
@@ -0,0 +1,245 @@
+//! Discrete Fourier transforms and short-time Fourier transforms.
+//!
+//! The transforms operate on complex tensors, real inputs are converted to complex ones when
+//! needed. The forward transforms are not normalized whereas the inverse ones are scaled by
+//! `1/n`, this is the same convention as numpy and PyTorch.
+use crate::op::{BackpropOp, Op};
+use crate::shape::Dim;
+use crate::{DType, Error, Result, Tensor, D};
+use num_complex::Complex64;
+
+// Recursive radix-2 fft, odd sizes fall back to a naive dft. `sign` is -1 for the forward
+// transform and 1 for the inverse one.
+fn fft_rec(xs: &[Complex64], sign: f64) -> Vec<Complex64> {
+    let n = xs.len();
+    if n <= 1 {
+        return xs.to_vec();
+    }
+    if n % 2 == 1 {
+        return dft(xs, sign);
+    }
+    let even: Vec<_> = xs.iter().step_by(2).copied().collect();
+    let odd: Vec<_> = xs.iter().skip(1).step_by(2).copied().collect();
+    let even = fft_rec(&even, sign);
+    let odd = fft_rec(&odd, sign);
+    let mut out = vec![Complex64::default(); n];
+    for k in 0..n / 2 {
+        let theta = sign * 2. * std::f64::consts::PI * k as f64 / n as f64;
+        let t = Complex64::from_polar(1., theta) * odd[k];
+        out[k] = even[k] + t;
+        out[k + n / 2] = even[k] - t;
+    }
+    out
+}
+
+fn dft(xs: &[Complex64], sign: f64) -> Vec<Complex64> {
+    let n = xs.len();
+    (0..n)
+        .map(|k| {
+            xs.iter()
+                .enumerate()
+                .map(|(j, &x)| {
+                    // Reduce the product modulo n to keep the angle accurate for large sizes.
+                    let kj = (k * j) % n;
+                    let theta = sign * 2. * std::f64::consts::PI * kj as f64 / n as f64;
+                    x * Complex64::from_polar(1., theta)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Computes the discrete Fourier transform of `xs`, or its inverse when `inverse` is set.
+pub(crate) fn fft_vec(xs: &[Complex64], inverse: bool) -> Vec<Complex64> {
+    if inverse {
+        let n = xs.len() as f64;
+        fft_rec(xs, 1.).into_iter().map(|v| v / n).collect()
+    } else {
+        fft_rec(xs, -1.)
+    }
+}
+
+impl Tensor {
+    pub(crate) fn fft_impl(&self, dim: usize, inverse: bool) -> Result<Self> {
+        let storage = self.storage().fft(self.layout(), dim, inverse)?;
+        let op = BackpropOp::new1(self, |arg| Op::Fft { arg, dim, inverse });
+        Ok(crate::tensor::from_storage(
+            storage,
+            self.device(),
+            self.shape().clone(),
+            op,
+            false,
+        ))
+    }
+
+    // Converts real tensors to complex ones, f64 tensors use `C128` and all the other real
+    // dtypes use `C64`.
+    fn to_complex(&self) -> Result<Self> {
+        match self.dtype() {
+            DType::C64 | DType::C128 => Ok(self.clone()),
+            DType::F64 => Self::complex(self, &self.zeros_like()?),
+            _ => {
+                let re = self.to_dtype(DType::F32)?;
+                Self::complex(&re, &re.zeros_like()?)
+            }
+        }
+    }
+
+    /// The discrete Fourier transform along dimension `dim`. Real tensors are converted to
+    /// complex ones first.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[1f32, 2., 3., 4.], &Device::Cpu)?;
+    /// let a = a.fft(0)?.view_as_real()?;
+    /// assert_eq!(a.to_vec2::<f32>()?, &[[10., 0.], [-2., 2.], [-2., 0.], [-2., -2.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn fft<D: Dim>(&self, dim: D) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "fft")?;
+        self.to_complex()?.fft_impl(dim, false)
+    }
+
+    /// The inverse discrete Fourier transform along dimension `dim`, normalized by `1/n`.
+    pub fn ifft<D: Dim>(&self, dim: D) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "ifft")?;
+        self.to_complex()?.fft_impl(dim, true)
+    }
+
+    /// The discrete Fourier transform of a real tensor along dimension `dim`. As the result is
+    /// hermitian symmetric, only the first `n / 2 + 1` frequencies are returned.
+    pub fn rfft<D: Dim>(&self, dim: D) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "rfft")?;
+        if self.dtype().is_complex() {
+            Err(Error::UnsupportedDTypeForOp(self.dtype(), "rfft").bt())?
+        }
+        let n = self.dim(dim)?;
+        self.fft(dim)?.narrow(dim, 0, n / 2 + 1)
+    }
+
+    /// The inverse of `rfft`, `n` is the size of the real output along dimension `dim`. The input
+    /// is truncated or zero-padded to `n / 2 + 1` frequencies.
+    pub fn irfft<D: Dim>(&self, dim: D, n: usize) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "irfft")?;
+        if n == 0 {
+            crate::bail!("irfft requires a positive output size")
+        }
+        let xs = self.to_complex()?;
+        let n_freqs = n / 2 + 1;
+        let xs_freqs = xs.dim(dim)?;
+        let xs = if xs_freqs >= n_freqs {
+            xs.narrow(dim, 0, n_freqs)?
+        } else {
+            let mut pad_dims = xs.dims().to_vec();
+            pad_dims[dim] = n_freqs - xs_freqs;
+            let pad = Tensor::zeros(pad_dims, xs.dtype(), xs.device())?;
+            Tensor::cat(&[&xs, &pad], dim)?
+        };
+        // Rebuild the negative frequencies using the hermitian symmetry.
+        let n_neg = n - n_freqs;
+        let xs = if n_neg == 0 {
+            xs
+        } else {
+            let ids: Vec<u32> = (1..=n_neg as u32).rev().collect();
+            let ids = Tensor::new(ids.as_slice(), xs.device())?;
+            let neg = xs.conj()?.index_select(&ids, dim)?;
+            Tensor::cat(&[&xs, &neg], dim)?
+        };
+        xs.fft_impl(dim, true)?.real()
+    }
+
+    /// The short-time Fourier transform over the last dimension.
+    ///
+    /// The signal is split in frames of `n_fft` samples separated by `hop_length` samples, each
+    /// frame is multiplied by `window` (a tensor of size `n_fft`) when provided. For real inputs
+    /// the result has shape `(.., n_fft / 2 + 1, n_frames)`, for complex inputs all the `n_fft`
+    /// frequencies are returned. No padding is applied to the input.
+    pub fn stft(&self, n_fft: usize, hop_length: usize, window: Option<&Tensor>) -> Result<Self> {
+        let frames = self.frame(n_fft, hop_length)?;
+        let frames = match window {
+            None => frames,
+            Some(window) => frames.broadcast_mul(&window.to_dtype(frames.dtype())?)?,
+        };
+        let xs = if frames.dtype().is_complex() {
+            frames.fft(D::Minus1)?
+        } else {
+            frames.rfft(D::Minus1)?
+        };
+        xs.transpose(D::Minus2, D::Minus1)
+    }
+
+    // Splits the last dimension in frames, the result has shape `(.., n_frames, n_fft)`.
+    fn frame(&self, n_fft: usize, hop_length: usize) -> Result<Self> {
+        let t = self.dim(D::Minus1)?;
+        if n_fft == 0 || hop_length == 0 || t < n_fft {
+            crate::bail!(
+                "stft: invalid n_fft {n_fft} or hop_length {hop_length} for a signal of size {t}"
+            )
+        }
+        let n_frames = 1 + (t - n_fft) / hop_length;
+        let ids: Vec<u32> = (0..n_frames)
+            .flat_map(|f| (0..n_fft).map(move |j| (f * hop_length + j) as u32))
+            .collect();
+        let ids = Tensor::new(ids.as_slice(), self.device())?;
+        let mut dims = self.dims().to_vec();
+        dims.pop();
+        dims.push(n_frames);
+        dims.push(n_fft);
+        self.index_select(&ids, D::Minus1)?.reshape(dims)
+    }
+
+    /// The inverse of `stft` for real signals. The input has shape `(.., n_fft / 2 + 1,
+    /// n_frames)`, frames are recovered with `irfft` and combined with overlap-add. The result
+    /// is truncated or zero-padded to `length` when provided.
+    pub fn istft(
+        &self,
+        n_fft: usize,
+        hop_length: usize,
+        window: Option<&Tensor>,
+        length: Option<usize>,
+    ) -> Result<Self> {
+        let frames = self
+            .transpose(D::Minus2, D::Minus1)?
+            .irfft(D::Minus1, n_fft)?;
+        let dtype = frames.dtype();
+        let device = frames.device();
+        let window = match window {
+            None => Tensor::ones(n_fft, dtype, device)?,
+            Some(window) => window.to_dtype(dtype)?,
+        };
+        let frames = frames.broadcast_mul(&window)?;
+        let n_frames = frames.dim(D::Minus2)?;
+        let out_len = n_fft + hop_length * (n_frames - 1);
+        let ids: Vec<u32> = (0..n_frames)
+            .flat_map(|f| (0..n_fft).map(move |j| (f * hop_length + j) as u32))
+            .collect();
+        let ids = Tensor::new(ids.as_slice(), device)?;
+
+        let mut dims = frames.dims().to_vec();
+        dims.truncate(dims.len() - 2);
+        let mut flat_dims = dims.clone();
+        flat_dims.push(n_frames * n_fft);
+        let frames = frames.reshape(flat_dims)?;
+        dims.push(out_len);
+        let rank = dims.len();
+        let ys = Tensor::zeros(dims, dtype, device)?.index_add(&ids, &frames, rank - 1)?;
+
+        // Normalize by the sum of the squared windows, the clamping avoids dividing by zero on
+        // the edges.
+        let envelope = window.sqr()?.repeat(n_frames)?;
+        let envelope = Tensor::zeros(out_len, dtype, device)?
+            .index_add(&ids, &envelope, 0)?
+            .maximum(1e-11)?;
+        let ys = ys.broadcast_div(&envelope)?;
+        match length {
+            None => Ok(ys),
+            Some(length) if length <= out_len => ys.narrow(D::Minus1, 0, length),
+            Some(length) => {
+                let mut pad_dims = ys.dims().to_vec();
+                pad_dims[rank - 1] = length - out_len;
+                let pad = Tensor::zeros(pad_dims, dtype, device)?;
+                Tensor::cat(&[&ys, &pad], D::Minus1)
+            }
+        }
+    }
+}
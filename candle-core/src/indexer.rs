@@ -1,4 +1,4 @@
-use crate::{Error, Tensor};
+use crate::{DType, Error, Tensor};
 use std::ops::{
     Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive,
 };
@@ -24,13 +24,68 @@ impl Tensor {
     ///
     /// # Ok::<(), candle_core::Error>(())
     /// ```
+    ///
+    /// Similar to numpy, negative indexes count from the end, slices can have a step, new axes
+    /// can be inserted and an ellipsis stands for all the dimensions that are not indexed.
+    ///
+    /// ```
+    /// # use candle_core::{Tensor, Device, IndexOp, Ellipsis, NewAxis, step};
+    /// let a = Tensor::arange(0u32, 12, &Device::Cpu)?.reshape((3, 4))?;
+    ///
+    /// // a[-1]
+    /// assert_eq!(a.i(-1)?.to_vec1::<u32>()?, [8, 9, 10, 11]);
+    /// // a[..., ::2]
+    /// assert_eq!(a.i((Ellipsis, step(.., 2)))?.to_vec2::<u32>()?, [[0, 2], [4, 6], [8, 10]]);
+    /// // a[::-1, -1]
+    /// assert_eq!(a.i((step(.., -1), -1))?.to_vec1::<u32>()?, [11, 7, 3]);
+    /// // a[None, 1:]
+    /// assert_eq!(a.i((NewAxis, 1..))?.dims(), &[1, 2, 4]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    ///
+    /// Indexing with a tensor selects the elements at the given indexes, the indexed dimension
+    /// gets replaced by the dimensions of the index tensor. Boolean tensors are used as masks
+    /// instead, all the dimensions covered by the mask get replaced by a single dimension holding
+    /// the selected elements.
+    ///
+    /// ```
+    /// # use candle_core::{Tensor, Device, IndexOp};
+    /// let a = Tensor::arange(0u32, 12, &Device::Cpu)?.reshape((3, 4))?;
+    ///
+    /// let idxs = Tensor::new(&[[2u32, 0], [1, 1]], &Device::Cpu)?;
+    /// assert_eq!(a.i((.., &idxs))?.dims(), &[3, 2, 2]);
+    ///
+    /// let mask = a.ge(9u32)?;
+    /// assert_eq!(a.i(&mask)?.to_vec1::<u32>()?, [9, 10, 11]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
     fn index(&self, indexers: &[TensorIndexer]) -> Result<Self, Error> {
+        let n_ellipsis = indexers
+            .iter()
+            .filter(|i| matches!(i, TensorIndexer::Ellipsis))
+            .count();
+        if n_ellipsis > 1 {
+            crate::bail!("an index can only have a single ellipsis")
+        }
+        let indexed_dims: usize = indexers.iter().map(|i| i.indexed_dims()).sum();
+        if indexed_dims > self.rank() {
+            crate::bail!(
+                "too many indexes for a tensor of rank {}, got {indexed_dims}",
+                self.rank()
+            )
+        }
         let mut x = self.clone();
-        let dims = self.shape().dims();
         let mut current_dim = 0;
-        for (i, indexer) in indexers.iter().enumerate() {
+        for indexer in indexers.iter() {
             x = match indexer {
                 TensorIndexer::Select(n) => x.narrow(current_dim, *n, 1)?.squeeze(current_dim)?,
+                TensorIndexer::SelectFromEnd(n) => {
+                    let len = x.dim(current_dim)?;
+                    if *n == 0 || *n > len {
+                        crate::bail!("index -{n} is out of range for dimension of size {len}")
+                    }
+                    x.narrow(current_dim, len - n, 1)?.squeeze(current_dim)?
+                }
                 TensorIndexer::Narrow(left_bound, right_bound) => {
                     let start = match left_bound {
                         Bound::Included(n) => *n,
@@ -40,25 +95,192 @@ impl Tensor {
                     let stop = match right_bound {
                         Bound::Included(n) => *n + 1,
                         Bound::Excluded(n) => *n,
-                        Bound::Unbounded => dims[i],
+                        Bound::Unbounded => x.dim(current_dim)?,
                     };
                     let out = x.narrow(current_dim, start, stop.saturating_sub(start))?;
                     current_dim += 1;
                     out
                 }
+                &TensorIndexer::Slice { start, stop, step } => {
+                    let len = x.dim(current_dim)?;
+                    let out = match slice_indexes(start, stop, step, len)? {
+                        SliceIndexes::Range(start, len) => x.narrow(current_dim, start, len)?,
+                        SliceIndexes::Indexes(indexes) => {
+                            let indexes = Tensor::new(indexes, x.device())?;
+                            x.contiguous()?.index_select(&indexes, current_dim)?
+                        }
+                    };
+                    current_dim += 1;
+                    out
+                }
+                TensorIndexer::IndexSelect(mask) if mask.dtype() == DType::Bool => {
+                    let out = mask_select(&x, mask, current_dim)?;
+                    current_dim += 1;
+                    out
+                }
                 TensorIndexer::IndexSelect(indexes) => {
-                    if indexes.rank() != 1 {
-                        crate::bail!("multi-dimensional tensor indexing is not supported")
-                    }
-                    let out = x.index_select(&indexes.to_device(x.device())?, current_dim)?;
+                    let len = x.dim(current_dim)?;
+                    let indexes = indexes.to_device(x.device())?;
+                    let is_signed = matches!(
+                        indexes.dtype(),
+                        DType::I8 | DType::I16 | DType::I32 | DType::I64
+                    );
+                    let indexes = if is_signed {
+                        // Negative indexes count from the end.
+                        let len = Tensor::new(len as i64, x.device())?
+                            .to_dtype(indexes.dtype())?
+                            .broadcast_as(indexes.shape())?;
+                        indexes
+                            .lt(&indexes.zeros_like()?)?
+                            .where_cond(&(&indexes + len)?, &indexes)?
+                    } else {
+                        indexes
+                    };
+                    let mut dims = x.dims()[..current_dim].to_vec();
+                    dims.extend_from_slice(indexes.dims());
+                    dims.extend_from_slice(&x.dims()[current_dim + 1..]);
+                    let out = x
+                        .contiguous()?
+                        .index_select(&indexes.flatten_all()?, current_dim)?
+                        .reshape(dims)?;
+                    current_dim += indexes.rank();
+                    out
+                }
+                TensorIndexer::NewAxis => {
+                    let out = x.unsqueeze(current_dim)?;
                     current_dim += 1;
                     out
                 }
+                TensorIndexer::Ellipsis => {
+                    current_dim += self.rank() - indexed_dims;
+                    x
+                }
                 TensorIndexer::Err(e) => crate::bail!("indexing error {e:?}"),
             };
         }
         Ok(x)
     }
+
+    /// Returns a copy of this tensor where the elements selected by `index` are replaced with the
+    /// values from `src`, `src` being broadcasted to the shape of `self.i(index)`. All the
+    /// indexes supported by `.i()` can be used. When an element is selected multiple times,
+    /// e.g. via a tensor index with repeated values, the corresponding values of `src` are summed.
+    ///
+    /// ```rust
+    /// # use candle_core::{Tensor, Device, IndexOp, step};
+    /// let a = Tensor::zeros((2, 4), candle_core::DType::F32, &Device::Cpu)?;
+    /// let src = Tensor::new(&[1f32, 2.], &Device::Cpu)?;
+    /// // a[:, ::2] = src
+    /// let a = a.slice_assign((.., step(.., 2)), &src)?;
+    /// assert_eq!(a.to_vec2::<f32>()?, [[1., 0., 2., 0.], [1., 0., 2., 0.]]);
+    /// // a[a > 1] = -1
+    /// let a = a.slice_assign(&a.gt(1f32)?, &Tensor::new(-1f32, &Device::Cpu)?)?;
+    /// assert_eq!(a.to_vec2::<f32>()?, [[1., 0., -1., 0.], [1., 0., -1., 0.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn slice_assign<I>(&self, index: I, src: &Tensor) -> Result<Self, Error>
+    where
+        Tensor: IndexOp<I>,
+    {
+        let elem_count = self.elem_count();
+        if elem_count > u32::MAX as usize {
+            crate::bail!("slice_assign is not supported for tensors with more than 2^32 elements")
+        }
+        // Apply the indexing to the positions of the elements to find which ones are selected.
+        let positions = Tensor::arange(0u32, elem_count as u32, self.device())?
+            .reshape(self.shape())?
+            .i(index)?;
+        let src = src
+            .to_dtype(self.dtype())?
+            .broadcast_as(positions.shape())?
+            .flatten_all()?
+            .contiguous()?;
+        let positions = positions.flatten_all()?.contiguous()?;
+        let flat = self.flatten_all()?;
+        let selected = Tensor::zeros(elem_count, DType::U32, self.device())?.index_add(
+            &positions,
+            &positions.ones_like()?.contiguous()?,
+            0,
+        )?;
+        let values = flat.zeros_like()?.index_add(&positions, &src, 0)?;
+        selected
+            .gt(&selected.zeros_like()?)?
+            .where_cond(&values, &flat)?
+            .reshape(self.shape())
+    }
+}
+
+enum SliceIndexes {
+    // A contiguous range given by its start and length.
+    Range(usize, usize),
+    Indexes(Vec<u32>),
+}
+
+// Resolves a python-like slice on a dimension of size `len`, out of range bounds are clamped.
+fn slice_indexes(
+    start: Option<isize>,
+    stop: Option<isize>,
+    step: isize,
+    len: usize,
+) -> Result<SliceIndexes, Error> {
+    let ilen = len as isize;
+    let resolve = |v: isize, lo: isize, hi: isize| {
+        let v = if v < 0 { v + ilen } else { v };
+        v.clamp(lo, hi)
+    };
+    let indexes: Vec<u32> = if step > 0 {
+        let start = start.map_or(0, |v| resolve(v, 0, ilen));
+        let stop = stop.map_or(ilen, |v| resolve(v, 0, ilen));
+        if step == 1 {
+            let len = (stop - start).max(0) as usize;
+            return Ok(SliceIndexes::Range(start as usize, len));
+        }
+        (start..stop)
+            .step_by(step as usize)
+            .map(|v| v as u32)
+            .collect()
+    } else if step < 0 {
+        let start = start.map_or(ilen - 1, |v| resolve(v, -1, ilen - 1));
+        let stop = stop.map_or(-1, |v| resolve(v, -1, ilen - 1));
+        (stop + 1..=start)
+            .rev()
+            .step_by(step.unsigned_abs())
+            .map(|v| v as u32)
+            .collect()
+    } else {
+        crate::bail!("slice step cannot be zero")
+    };
+    Ok(SliceIndexes::Indexes(indexes))
+}
+
+// Selects the elements of `x` where `mask` is true, the mask covering the dimensions starting
+// at `dim`.
+fn mask_select(x: &Tensor, mask: &Tensor, dim: usize) -> Result<Tensor, Error> {
+    let mask_rank = mask.rank();
+    let dims = x.dims();
+    if dims.len() < dim + mask_rank || &dims[dim..dim + mask_rank] != mask.dims() {
+        crate::bail!(
+            "mask of shape {:?} does not match the dimensions of {:?} starting at {dim}",
+            mask.shape(),
+            x.shape()
+        )
+    }
+    let indexes = mask
+        .flatten_all()?
+        .to_dtype(DType::U8)?
+        .to_vec1::<u8>()?
+        .into_iter()
+        .enumerate()
+        .filter(|(_, v)| *v != 0)
+        .map(|(i, _)| i as u32)
+        .collect::<Vec<_>>();
+    let x = if mask_rank == 0 {
+        x.unsqueeze(dim)?
+    } else {
+        x.flatten(dim, dim + mask_rank - 1)?
+    };
+    let indexes = Tensor::new(indexes, x.device())?;
+    x.contiguous()?.index_select(&indexes, dim)
 }
 
 #[derive(Debug)]
@@ -66,19 +288,109 @@ impl Tensor {
 pub enum TensorIndexer {
     /// This selects the elemnts for which an index has some specific value.
     Select(usize),
+    /// Same as `Select` but the index counts from the end, 1 being the last element.
+    SelectFromEnd(usize),
     /// This is a regular slice, purely indexing a chunk of the tensor
     Narrow(Bound<usize>, Bound<usize>),
-    /// Indexing via a 1d tensor
+    /// A python-like slice, negative bounds count from the end and out of range bounds are
+    /// clamped. A negative step iterates in reverse order.
+    Slice {
+        start: Option<isize>,
+        stop: Option<isize>,
+        step: isize,
+    },
+    /// Indexing via a tensor, or selecting the elements where a boolean tensor is true
     IndexSelect(Tensor),
+    /// Inserts a new dimension of size 1
+    NewAxis,
+    /// Stands for all the dimensions that are not indexed
+    Ellipsis,
     Err(Error),
 }
 
+impl TensorIndexer {
+    // The number of dimensions of the indexed tensor that this indexer applies to.
+    fn indexed_dims(&self) -> usize {
+        match self {
+            Self::Select(_) | Self::SelectFromEnd(_) | Self::Narrow(_, _) | Self::Slice { .. } => 1,
+            Self::IndexSelect(t) if t.dtype() == DType::Bool => t.rank(),
+            Self::IndexSelect(_) => 1,
+            Self::NewAxis | Self::Ellipsis | Self::Err(_) => 0,
+        }
+    }
+}
+
+/// Marker used to insert a new dimension of size 1 when indexing, similar to `None` in numpy.
+#[derive(Debug, Clone, Copy)]
+pub struct NewAxis;
+
+/// Marker for all the dimensions that are not explicitly indexed, similar to `...` in numpy.
+#[derive(Debug, Clone, Copy)]
+pub struct Ellipsis;
+
+impl From<NewAxis> for TensorIndexer {
+    fn from(_: NewAxis) -> Self {
+        TensorIndexer::NewAxis
+    }
+}
+
+impl From<Ellipsis> for TensorIndexer {
+    fn from(_: Ellipsis) -> Self {
+        TensorIndexer::Ellipsis
+    }
+}
+
+/// Applies a step to a range index, e.g. `step(.., 2)` is the equivalent of `::2` in numpy and
+/// `step(.., -1)` reverses the order of the elements.
+pub fn step<R: Into<TensorIndexer>>(range: R, step: isize) -> TensorIndexer {
+    let (start, stop) = match range.into() {
+        TensorIndexer::Narrow(start, stop) => {
+            let start = match start {
+                Bound::Included(n) => Some(n as isize),
+                Bound::Excluded(n) => Some(n as isize + 1),
+                Bound::Unbounded => None,
+            };
+            let stop = match stop {
+                Bound::Included(n) => Some(n as isize + 1),
+                Bound::Excluded(n) => Some(n as isize),
+                Bound::Unbounded => None,
+            };
+            (start, stop)
+        }
+        TensorIndexer::Slice { start, stop, .. } => (start, stop),
+        TensorIndexer::Err(e) => return TensorIndexer::Err(e),
+        indexer => {
+            let msg = format!("a step can only be applied to a range, got {indexer:?}");
+            return TensorIndexer::Err(Error::Msg(msg).bt());
+        }
+    };
+    TensorIndexer::Slice { start, stop, step }
+}
+
 impl From<usize> for TensorIndexer {
     fn from(index: usize) -> Self {
         TensorIndexer::Select(index)
     }
 }
 
+macro_rules! impl_from_signed {
+    ($t:ty) => {
+        impl From<$t> for TensorIndexer {
+            fn from(index: $t) -> Self {
+                if index >= 0 {
+                    TensorIndexer::Select(index as usize)
+                } else {
+                    TensorIndexer::SelectFromEnd(index.unsigned_abs() as usize)
+                }
+            }
+        }
+    };
+}
+
+impl_from_signed!(i32);
+impl_from_signed!(i64);
+impl_from_signed!(isize);
+
 impl From<&[u32]> for TensorIndexer {
     fn from(index: &[u32]) -> Self {
         match Tensor::new(index, &crate::Device::Cpu) {
@@ -128,6 +440,56 @@ macro_rules! impl_from_range {
     };
 }
 
+// Ranges with signed bounds behave as the unsigned ones when the bounds are non-negative and
+// as python slices otherwise.
+macro_rules! impl_from_signed_range {
+    ($range_type:ty) => {
+        impl From<$range_type> for TensorIndexer {
+            fn from(range: $range_type) -> Self {
+                use std::ops::Bound::*;
+
+                let start = match range.start_bound() {
+                    Included(idx) => Some(*idx as isize),
+                    Excluded(idx) => Some(*idx as isize + 1),
+                    Unbounded => None,
+                };
+                let stop = match range.end_bound() {
+                    // An inclusive -1 end bound covers the last element.
+                    Included(-1) => None,
+                    Included(idx) => Some(*idx as isize + 1),
+                    Excluded(idx) => Some(*idx as isize),
+                    Unbounded => None,
+                };
+                if start.unwrap_or(0) >= 0 && stop.unwrap_or(0) >= 0 {
+                    let start = start.map_or(Unbounded, |v| Included(v as usize));
+                    let stop = stop.map_or(Unbounded, |v| Excluded(v as usize));
+                    TensorIndexer::Narrow(start, stop)
+                } else {
+                    TensorIndexer::Slice {
+                        start,
+                        stop,
+                        step: 1,
+                    }
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_from_signed_ranges {
+    ($t:ty) => {
+        impl_from_signed_range!(Range<$t>);
+        impl_from_signed_range!(RangeFrom<$t>);
+        impl_from_signed_range!(RangeInclusive<$t>);
+        impl_from_signed_range!(RangeTo<$t>);
+        impl_from_signed_range!(RangeToInclusive<$t>);
+    };
+}
+
+impl_from_signed_ranges!(i32);
+impl_from_signed_ranges!(i64);
+impl_from_signed_ranges!(isize);
+
 impl_from_range!(Range<usize>);
 impl_from_range!(RangeFrom<usize>);
 impl_from_range!(RangeFull);
@@ -0,0 +1,386 @@
+//! Forward mode automatic differentiation.
+//!
+//! The tangents of some input tensors are propagated through the same op graph as the one used
+//! by the backpropagation, following the nodes from the inputs to the outputs.
+use crate::backprop::{
+    binary_partials, broadcast_back, cummax_idxs, cumprod_partials, elu_derivative, erf_derivative,
+    gelu_derivative, max_pool2d_mask, pool2d_window_idxs, pool2d_windows,
+};
+use crate::op::{BinaryOp, CumulativeOp, Op, ReduceOp, UnaryOp};
+use crate::{Error, Result, Tensor, TensorId};
+use std::collections::{HashMap, HashSet};
+
+// Adds two tangents where `None` stands for a zero tangent.
+fn add_tangents(lhs: Option<Tensor>, rhs: Option<Tensor>) -> Result<Option<Tensor>> {
+    let t = match (lhs, rhs) {
+        (None, None) => None,
+        (Some(t), None) | (None, Some(t)) => Some(t),
+        (Some(lhs), Some(rhs)) => Some(lhs.add(&rhs)?),
+    };
+    Ok(t)
+}
+
+struct TangentStore(HashMap<TensorId, Tensor>);
+
+impl TangentStore {
+    fn get(&self, tensor: &Tensor) -> Option<&Tensor> {
+        self.0.get(&tensor.id())
+    }
+
+    // Same as `get` but returns a zero tangent rather than `None`.
+    fn get_or_zeros(&self, tensor: &Tensor) -> Result<Tensor> {
+        match self.get(tensor) {
+            Some(t) => Ok(t.clone()),
+            None => tensor.zeros_like(),
+        }
+    }
+}
+
+impl Tensor {
+    /// Propagates the tangents of `inputs` through the op graph up to `outputs` and returns the
+    /// tangents of the outputs. Outputs that do not depend on the inputs get a zero tangent.
+    pub(crate) fn jvp_impl(
+        outputs: &[&Tensor],
+        inputs: &[(&Tensor, Tensor)],
+    ) -> Result<Vec<Tensor>> {
+        let targets = inputs.iter().map(|(t, _)| t.id()).collect::<HashSet<_>>();
+        let mut sorted_nodes = Self::sorted_nodes(outputs, Some(&targets));
+        // The nodes are sorted from the outputs to the inputs, reverse this so that the
+        // tangents of the arguments are always available before processing a node.
+        sorted_nodes.reverse();
+        let mut tangents = TangentStore(
+            inputs
+                .iter()
+                .map(|(t, tangent)| (t.id(), tangent.clone()))
+                .collect(),
+        );
+        for node in sorted_nodes.iter() {
+            if targets.contains(&node.id()) {
+                continue;
+            }
+            if let Some(op) = node.op() {
+                if let Some(tangent) = node_tangent(node, op, &tangents)? {
+                    tangents.0.insert(node.id(), tangent);
+                }
+            }
+        }
+        outputs.iter().map(|o| tangents.get_or_zeros(o)).collect()
+    }
+}
+
+// Returns the tangent of `node` given the tangents of the arguments of `op`, `None` is used when
+// the tangent is zero.
+fn node_tangent(node: &Tensor, op: &Op, tangents: &TangentStore) -> Result<Option<Tensor>> {
+    // Unary ops have a zero tangent if their argument has one.
+    macro_rules! tangent {
+        ($arg:expr) => {
+            match tangents.get($arg) {
+                None => return Ok(None),
+                Some(t) => t,
+            }
+        };
+    }
+    let tangent = match op {
+        Op::Binary(lhs, rhs, BinaryOp::Add) => {
+            add_tangents(tangents.get(lhs).cloned(), tangents.get(rhs).cloned())?
+        }
+        Op::Binary(lhs, rhs, BinaryOp::Sub) => {
+            let rhs = tangents.get(rhs).map(|t| t.neg()).transpose()?;
+            add_tangents(tangents.get(lhs).cloned(), rhs)?
+        }
+        Op::Binary(lhs, rhs, BinaryOp::Mul) => {
+            let t_lhs = tangents.get(lhs).map(|t| t.mul(rhs)).transpose()?;
+            let t_rhs = tangents.get(rhs).map(|t| lhs.mul(t)).transpose()?;
+            add_tangents(t_lhs, t_rhs)?
+        }
+        Op::Binary(lhs, rhs, BinaryOp::Div) => {
+            let t_lhs = tangents.get(lhs).map(|t| t.div(rhs)).transpose()?;
+            let t_rhs = match tangents.get(rhs) {
+                None => None,
+                Some(t) => Some(lhs.mul(t)?.div(&rhs.sqr()?)?.neg()?),
+            };
+            add_tangents(t_lhs, t_rhs)?
+        }
+        Op::Binary(lhs, rhs, BinaryOp::Minimum) | Op::Binary(lhs, rhs, BinaryOp::Maximum) => {
+            // Same as for the backpropagation, ties get half of each tangent.
+            let mask_lhs = node.eq(lhs)?.to_dtype(node.dtype())?;
+            let mask_rhs = node.eq(rhs)?.to_dtype(node.dtype())?;
+            let t_lhs = match tangents.get(lhs) {
+                None => None,
+                Some(t) => Some(mask_lhs.mul(t)?.div(&(&mask_rhs + 1.)?)?),
+            };
+            let t_rhs = match tangents.get(rhs) {
+                None => None,
+                Some(t) => Some(mask_rhs.mul(t)?.div(&(&mask_lhs + 1.)?)?),
+            };
+            add_tangents(t_lhs, t_rhs)?
+        }
+        Op::Binary(lhs, rhs, op @ (BinaryOp::Pow | BinaryOp::Atan2 | BinaryOp::Fmod)) => {
+            if tangents.get(lhs).is_none() && tangents.get(rhs).is_none() {
+                return Ok(None);
+            }
+            let (d_lhs, d_rhs) = binary_partials(*op, lhs, rhs, node)?;
+            let t_lhs = tangents.get(lhs).map(|t| t.mul(&d_lhs)).transpose()?;
+            let t_rhs = tangents.get(rhs).map(|t| t.mul(&d_rhs)).transpose()?;
+            add_tangents(t_lhs, t_rhs)?
+        }
+        Op::Binary(_, _, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor) => None,
+        Op::WhereCond(pred, on_true, on_false) => {
+            if tangents.get(on_true).is_none() && tangents.get(on_false).is_none() {
+                return Ok(None);
+            }
+            let t_true = tangents.get_or_zeros(on_true)?;
+            let t_false = tangents.get_or_zeros(on_false)?;
+            Some(pred.where_cond(&t_true, &t_false)?)
+        }
+        Op::Conv1D {
+            arg,
+            kernel,
+            padding,
+            stride,
+            dilation,
+        } => {
+            let (p, s, d) = (*padding, *stride, *dilation);
+            let t_arg = tangents.get(arg).map(|t| t.conv1d(kernel, p, s, d, 1));
+            let t_kernel = tangents.get(kernel).map(|t| arg.conv1d(t, p, s, d, 1));
+            add_tangents(t_arg.transpose()?, t_kernel.transpose()?)?
+        }
+        Op::ConvTranspose1D {
+            arg,
+            kernel,
+            padding,
+            output_padding,
+            stride,
+            dilation,
+        } => {
+            let (p, op, s, d) = (*padding, *output_padding, *stride, *dilation);
+            let t_arg = tangents
+                .get(arg)
+                .map(|t| t.conv_transpose1d(kernel, p, op, s, d));
+            let t_kernel = tangents
+                .get(kernel)
+                .map(|t| arg.conv_transpose1d(t, p, op, s, d));
+            add_tangents(t_arg.transpose()?, t_kernel.transpose()?)?
+        }
+        Op::Conv2D {
+            arg,
+            kernel,
+            padding,
+            padding_mode,
+            stride,
+            dilation,
+        } => {
+            let conv = |arg: &Tensor, kernel: &Tensor| {
+                arg.conv2d_with_padding(kernel, *padding, *padding_mode, *stride, *dilation, 1)
+            };
+            let t_arg = tangents.get(arg).map(|t| conv(t, kernel));
+            let t_kernel = tangents.get(kernel).map(|t| conv(arg, t));
+            add_tangents(t_arg.transpose()?, t_kernel.transpose()?)?
+        }
+        Op::ConvTranspose2D {
+            arg,
+            kernel,
+            padding,
+            output_padding,
+            stride,
+            dilation,
+        } => {
+            let (p, op, s, d) = (*padding, *output_padding, *stride, *dilation);
+            let t_arg = tangents
+                .get(arg)
+                .map(|t| t.conv_transpose2d(kernel, p, op, s, d));
+            let t_kernel = tangents
+                .get(kernel)
+                .map(|t| arg.conv_transpose2d(t, p, op, s, d));
+            add_tangents(t_arg.transpose()?, t_kernel.transpose()?)?
+        }
+        Op::Conv3D {
+            arg,
+            kernel,
+            padding,
+            stride,
+            dilation,
+        } => {
+            let (p, s, d) = (*padding, *stride, *dilation);
+            let t_arg = tangents.get(arg).map(|t| t.conv3d(kernel, p, s, d, 1));
+            let t_kernel = tangents.get(kernel).map(|t| arg.conv3d(t, p, s, d, 1));
+            add_tangents(t_arg.transpose()?, t_kernel.transpose()?)?
+        }
+        Op::AvgPool2D {
+            arg,
+            kernel_size,
+            stride,
+        } => Some(tangent!(arg).avg_pool2d_with_stride(*kernel_size, *stride)?),
+        Op::AvgPool3D {
+            arg,
+            kernel_size,
+            stride,
+        } => Some(tangent!(arg).avg_pool3d_with_stride(*kernel_size, *stride)?),
+        Op::MaxPool2D {
+            arg,
+            kernel_size,
+            stride,
+        } => {
+            // The tangent of each window is the one of its max, averaged over ties.
+            let t = tangent!(arg);
+            let (_n, _c, h_out, w_out) = node.dims4()?;
+            let idxs = pool2d_window_idxs(arg, *kernel_size, *stride)?;
+            let mask = max_pool2d_mask(arg, node, &idxs)?;
+            let t = pool2d_windows(t, &idxs, h_out, w_out)?;
+            Some((t * &mask)?.sum(4)?.div(&mask.sum(4)?)?)
+        }
+        Op::MaxPool3D { .. } => Err(Error::JvpNotSupported { op: "max-pool3d" })?,
+        Op::UpsampleNearest1D(arg) => Some(tangent!(arg).upsample_nearest1d(node.dim(2)?)?),
+        Op::UpsampleNearest2D(arg) => {
+            let (_n, _c, h, w) = node.dims4()?;
+            Some(tangent!(arg).upsample_nearest2d(h, w)?)
+        }
+        Op::Gather(arg, indexes, dim) => Some(tangent!(arg).gather(indexes, *dim)?),
+        Op::ScatterAdd(init, indexes, src, dim) => {
+            if tangents.get(init).is_none() && tangents.get(src).is_none() {
+                return Ok(None);
+            }
+            let t_init = tangents.get_or_zeros(init)?;
+            let t_src = tangents.get_or_zeros(src)?;
+            Some(t_init.scatter_add(indexes, &t_src, *dim)?)
+        }
+        Op::IndexAdd(init, indexes, src, dim) => {
+            if tangents.get(init).is_none() && tangents.get(src).is_none() {
+                return Ok(None);
+            }
+            let t_init = tangents.get_or_zeros(init)?;
+            let t_src = tangents.get_or_zeros(src)?;
+            Some(t_init.index_add(indexes, &t_src, *dim)?)
+        }
+        Op::IndexSelect(arg, indexes, dim) => Some(tangent!(arg).index_select(indexes, *dim)?),
+        Op::SparseIndexSelect(arg, indexes) => Some(tangent!(arg).index_select(indexes, 0)?),
+        Op::Matmul(lhs, rhs) => {
+            let t_lhs = tangents.get(lhs).map(|t| t.matmul(rhs)).transpose()?;
+            let t_rhs = tangents.get(rhs).map(|t| lhs.matmul(t)).transpose()?;
+            add_tangents(t_lhs, t_rhs)?
+        }
+        Op::Cat(args, dim) => {
+            if args.iter().all(|arg| tangents.get(arg).is_none()) {
+                return Ok(None);
+            }
+            let args = args
+                .iter()
+                .map(|arg| tangents.get_or_zeros(arg))
+                .collect::<Result<Vec<_>>>()?;
+            Some(Tensor::cat(&args, *dim)?)
+        }
+        Op::Affine { arg, mul, .. } => Some(tangent!(arg).affine(*mul, 0.)?),
+        Op::ToDType(arg) => Some(tangent!(arg).to_dtype(node.dtype())?),
+        Op::ViewAsReal(arg) => Some(tangent!(arg).view_as_real()?),
+        Op::ViewAsComplex(arg) => Some(tangent!(arg).view_as_complex()?),
+        Op::Copy(arg) => Some(tangent!(arg).clone()),
+        Op::Broadcast(arg) => Some(tangent!(arg).broadcast_as(node.shape())?),
+        &Op::Narrow(ref arg, dim, start_idx, len) => {
+            Some(tangent!(arg).narrow(dim, start_idx, len)?)
+        }
+        Op::Reshape(arg) => Some(tangent!(arg).reshape(node.shape())?),
+        Op::ToDevice(arg) => Some(tangent!(arg).to_device(node.device())?),
+        Op::Transpose(arg, dim1, dim2) => Some(tangent!(arg).transpose(*dim1, *dim2)?),
+        Op::Permute(arg, dims) => Some(tangent!(arg).permute(dims.clone())?),
+        Op::Cmp(_, _) | Op::Reduce(_, ReduceOp::ArgMin | ReduceOp::ArgMax, _) => None,
+        Op::Reduce(arg, ReduceOp::Sum, reduced_dims) => {
+            let sum_dims = sum_dims(arg, reduced_dims);
+            let t = tangent!(arg).sum_keepdim(sum_dims)?;
+            Some(t.reshape(node.shape())?)
+        }
+        Op::Reduce(arg, ReduceOp::Max | ReduceOp::Min, reduced_dims) => {
+            // Consistent with the backpropagation, all the elements reaching the extremum
+            // contribute to the tangent.
+            let t = tangent!(arg);
+            let mask = broadcast_back(arg, node, reduced_dims)?
+                .eq(arg)?
+                .to_dtype(t.dtype())?;
+            let t = t.mul(&mask)?.sum_keepdim(sum_dims(arg, reduced_dims))?;
+            Some(t.reshape(node.shape())?)
+        }
+        &Op::Cumulative(ref arg, CumulativeOp::Sum, dim) => Some(tangent!(arg).cumsum(dim)?),
+        &Op::Cumulative(ref arg, CumulativeOp::Prod, dim) => {
+            let (excl, m) = cumprod_partials(arg, node, dim)?;
+            let t = tangent!(arg).mul(&excl)?.unsqueeze(dim + 1)?;
+            Some(m.mul(&t.broadcast_as(m.shape())?)?.sum(dim)?)
+        }
+        &Op::Cumulative(ref arg, CumulativeOp::Max, dim) => {
+            let t = tangent!(arg);
+            Some(t.gather(&cummax_idxs(arg, node, dim)?, dim)?)
+        }
+        &Op::Fft {
+            ref arg,
+            dim,
+            inverse,
+        } => Some(tangent!(arg).fft_impl(dim, inverse)?),
+        Op::Unary(arg, UnaryOp::Exp) => Some(tangent!(arg).mul(node)?),
+        Op::Unary(arg, UnaryOp::Log) => Some(tangent!(arg).div(arg)?),
+        Op::Unary(arg, UnaryOp::Sin) => Some(tangent!(arg).mul(&arg.cos()?)?),
+        Op::Unary(arg, UnaryOp::Cos) => Some(tangent!(arg).mul(&arg.sin()?)?.neg()?),
+        Op::Unary(arg, UnaryOp::Tanh) => {
+            let dtanh = node.sqr()?.affine(-1., 1.)?;
+            Some(tangent!(arg).mul(&dtanh)?)
+        }
+        Op::Unary(arg, UnaryOp::Abs) => {
+            let ones = arg.ones_like()?;
+            let sign = arg
+                .ge(&arg.zeros_like()?)?
+                .where_cond(&ones, &ones.neg()?)?;
+            Some(tangent!(arg).mul(&sign)?)
+        }
+        Op::Unary(arg, UnaryOp::Neg) => Some(tangent!(arg).neg()?),
+        Op::Unary(arg, UnaryOp::Recip) => Some(tangent!(arg).div(&arg.sqr()?)?.neg()?),
+        Op::Unary(arg, UnaryOp::Sqr) => Some(tangent!(arg).mul(arg)?.affine(2., 0.)?),
+        Op::Unary(arg, UnaryOp::Sqrt) => Some(tangent!(arg).div(node)?.affine(0.5, 0.)?),
+        Op::Unary(arg, UnaryOp::Gelu) => Some(tangent!(arg).mul(&gelu_derivative(arg)?)?),
+        Op::Unary(arg, UnaryOp::Erf) => Some(tangent!(arg).mul(&erf_derivative(arg)?)?),
+        Op::Unary(_, UnaryOp::Floor | UnaryOp::Ceil | UnaryOp::Round | UnaryOp::Sign) => None,
+        Op::Unary(arg, UnaryOp::Relu) => {
+            let relu_grad = arg.ge(&arg.zeros_like()?)?.to_dtype(arg.dtype())?;
+            Some(tangent!(arg).mul(&relu_grad)?)
+        }
+        Op::Elu(arg, alpha) => Some(tangent!(arg).mul(&elu_derivative(arg, node, *alpha)?)?),
+        Op::Powf(arg, e) => Some((tangent!(arg).mul(&arg.powf(e - 1.)?)? * *e)?),
+        Op::CustomOp1(arg, c) => Some(c.jvp(arg, node, tangent!(arg))?),
+        Op::CustomOp2(arg1, arg2, c) => {
+            let t1 = tangents.get_or_zeros(arg1)?;
+            let t2 = tangents.get_or_zeros(arg2)?;
+            Some(c.jvp(arg1, arg2, node, &t1, &t2)?)
+        }
+        Op::CustomOp3(arg1, arg2, arg3, c) => {
+            let t1 = tangents.get_or_zeros(arg1)?;
+            let t2 = tangents.get_or_zeros(arg2)?;
+            let t3 = tangents.get_or_zeros(arg3)?;
+            Some(c.jvp(arg1, arg2, arg3, node, &t1, &t2, &t3)?)
+        }
+        Op::Checkpoint { args, vars, f } => {
+            // Recompute the activations using the same inputs as the forward pass.
+            let inputs = args
+                .iter()
+                .map(|arg| arg.detach())
+                .collect::<Result<Vec<_>>>()?;
+            let seeds = args
+                .iter()
+                .zip(inputs.iter())
+                .chain(vars.iter().map(|v| (v, v)))
+                .filter_map(|(arg, t)| tangents.get(arg).map(|tangent| (t, tangent.clone())))
+                .collect::<Vec<_>>();
+            if seeds.is_empty() {
+                return Ok(None);
+            }
+            let res = f(&inputs)?;
+            Some(Tensor::jvp_impl(&[&res], &seeds)?.remove(0))
+        }
+    };
+    Ok(tangent)
+}
+
+// The dimensions that have been reduced given the reduced shape with `keepdim=true`.
+fn sum_dims(arg: &Tensor, reduced_dims: &[usize]) -> Vec<usize> {
+    arg.dims()
+        .iter()
+        .zip(reduced_dims.iter())
+        .enumerate()
+        .filter_map(|(i, (d, r))| if d != r { Some(i) } else { None })
+        .collect()
+}
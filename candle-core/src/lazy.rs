@@ -0,0 +1,335 @@
+//! Lazy evaluation and fusion of elementwise ops on the cpu.
+//!
+//! When the lazy mode is enabled on the current thread, the unary, binary and affine ops applied
+//! to cpu float tensors do not compute their result right away. The resulting tensor instead
+//! records the op and its value is only computed once its storage is needed, e.g. when it is used
+//! by a non-elementwise op, when converting it to a vec, or when calling [`Tensor::realize`].
+//! Chains of pending ops are fused into a single strided loop so that the intermediary results
+//! never get materialized.
+//!
+//! ```rust
+//! use candle_core::{Device, Tensor};
+//! let x = Tensor::new(&[-1f32, 0., 1., 2.], &Device::Cpu)?;
+//! let y = {
+//!     let _lazy = candle_core::lazy::enable();
+//!     // silu(x) * x, evaluated in a single pass.
+//!     let silu = (&x / (x.neg()?.exp()? + 1.)?)?;
+//!     (silu * &x)?
+//! };
+//! assert!(!y.is_realized());
+//! let y = y.realize()?;
+//! assert!(y.is_realized());
+//! # Ok::<(), candle_core::Error>(())
+//! ```
+use crate::cpu_backend::alloc_vec;
+use crate::op::{BinaryOp, BinaryOpT, Op, UnaryOp, UnaryOpT};
+use crate::tensor::Tensor_;
+use crate::{CpuStorage, DType, Device, Layout, Shape, Storage, StridedIndex, Tensor, WithDType};
+use half::{bf16, f16};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Mutex, Weak};
+
+// The number of elements processed at once by the fused loop, the intermediary values for a
+// chunk are kept in small scratch buffers.
+const CHUNK_SIZE: usize = 1024;
+
+thread_local! {
+    static LAZY: Cell<bool> = const { Cell::new(false) };
+}
+
+// The tensors that have been recorded and may not have been realized yet, these get computed
+// before any storage is modified in place.
+static PENDING: Mutex<Vec<Weak<Tensor_>>> = Mutex::new(Vec::new());
+
+/// A guard returned by [`enable`], the previous mode is restored when it gets dropped.
+pub struct LazyGuard {
+    prev: bool,
+}
+
+impl Drop for LazyGuard {
+    fn drop(&mut self) {
+        LAZY.with(|lazy| lazy.set(self.prev))
+    }
+}
+
+/// Enables the lazy mode on the current thread until the returned guard is dropped.
+pub fn enable() -> LazyGuard {
+    let prev = LAZY.with(|lazy| lazy.replace(true));
+    LazyGuard { prev }
+}
+
+/// Returns true if the lazy mode is enabled on the current thread.
+pub fn is_enabled() -> bool {
+    LAZY.with(|lazy| lazy.get())
+}
+
+// Whether an elementwise op producing a tensor with this dtype on this device should be recorded
+// rather than executed.
+pub(crate) fn should_record(device: &Device, dtype: DType) -> bool {
+    let float = matches!(dtype, DType::BF16 | DType::F16 | DType::F32 | DType::F64);
+    float && device.is_cpu() && is_enabled()
+}
+
+pub(crate) fn register(tensor: Weak<Tensor_>) {
+    let mut pending = PENDING.lock().unwrap();
+    if pending.len() == pending.capacity() {
+        // Forget about the tensors that have been dropped or realized since the last resize.
+        pending.retain(|t| t.upgrade().is_some_and(|t| t.pending_op().is_some()))
+    }
+    pending.push(tensor)
+}
+
+/// Computes all the pending tensors, including the ones recorded on other threads.
+pub fn flush() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    for tensor in pending.iter().filter_map(|t| t.upgrade()) {
+        tensor.realize_pending()
+    }
+}
+
+trait FusedDType: WithDType {
+    fn unary<U: UnaryOpT>(xs: &[Self], ys: &mut [Self]);
+    fn binary<B: BinaryOpT>(lhs: &[Self], rhs: &[Self], ys: &mut [Self]);
+}
+
+macro_rules! fused_dtype {
+    ($ty:ty, $f:ident, $f_vec:ident, $vec:ident) => {
+        impl FusedDType for $ty {
+            fn unary<U: UnaryOpT>(xs: &[Self], ys: &mut [Self]) {
+                if U::$vec {
+                    U::$f_vec(xs, ys)
+                } else {
+                    for (x, y) in xs.iter().zip(ys.iter_mut()) {
+                        *y = U::$f(*x)
+                    }
+                }
+            }
+
+            fn binary<B: BinaryOpT>(lhs: &[Self], rhs: &[Self], ys: &mut [Self]) {
+                if B::$vec {
+                    B::$f_vec(lhs, rhs, ys)
+                } else {
+                    for ((l, r), y) in lhs.iter().zip(rhs.iter()).zip(ys.iter_mut()) {
+                        *y = B::$f(*l, *r)
+                    }
+                }
+            }
+        }
+    };
+}
+fused_dtype!(bf16, bf16, bf16_vec, BF16_VEC);
+fused_dtype!(f16, f16, f16_vec, F16_VEC);
+fused_dtype!(f32, f32, f32_vec, F32_VEC);
+fused_dtype!(f64, f64, f64_vec, F64_VEC);
+
+fn unary<T: FusedDType>(op: UnaryOp, xs: &[T], ys: &mut [T]) {
+    use crate::op;
+    match op {
+        UnaryOp::Exp => T::unary::<op::Exp>(xs, ys),
+        UnaryOp::Log => T::unary::<op::Log>(xs, ys),
+        UnaryOp::Sin => T::unary::<op::Sin>(xs, ys),
+        UnaryOp::Cos => T::unary::<op::Cos>(xs, ys),
+        UnaryOp::Abs => T::unary::<op::Abs>(xs, ys),
+        UnaryOp::Neg => T::unary::<op::Neg>(xs, ys),
+        UnaryOp::Recip => T::unary::<op::Recip>(xs, ys),
+        UnaryOp::Sqr => T::unary::<op::Sqr>(xs, ys),
+        UnaryOp::Sqrt => T::unary::<op::Sqrt>(xs, ys),
+        UnaryOp::Gelu => T::unary::<op::Gelu>(xs, ys),
+        UnaryOp::Relu => T::unary::<op::Relu>(xs, ys),
+        UnaryOp::Tanh => T::unary::<op::Tanh>(xs, ys),
+        UnaryOp::Erf => T::unary::<op::Erf>(xs, ys),
+        UnaryOp::Floor => T::unary::<op::Floor>(xs, ys),
+        UnaryOp::Ceil => T::unary::<op::Ceil>(xs, ys),
+        UnaryOp::Round => T::unary::<op::Round>(xs, ys),
+        UnaryOp::Sign => T::unary::<op::Sign>(xs, ys),
+    }
+}
+
+fn binary<T: FusedDType>(op: BinaryOp, lhs: &[T], rhs: &[T], ys: &mut [T]) {
+    use crate::op;
+    match op {
+        BinaryOp::Add => T::binary::<op::Add>(lhs, rhs, ys),
+        BinaryOp::Mul => T::binary::<op::Mul>(lhs, rhs, ys),
+        BinaryOp::Sub => T::binary::<op::Sub>(lhs, rhs, ys),
+        BinaryOp::Div => T::binary::<op::Div>(lhs, rhs, ys),
+        BinaryOp::Maximum => T::binary::<op::Maximum>(lhs, rhs, ys),
+        BinaryOp::Minimum => T::binary::<op::Minimum>(lhs, rhs, ys),
+        BinaryOp::Pow => T::binary::<op::Pow>(lhs, rhs, ys),
+        BinaryOp::Atan2 => T::binary::<op::Atan2>(lhs, rhs, ys),
+        BinaryOp::Fmod => T::binary::<op::Fmod>(lhs, rhs, ys),
+        BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+            unreachable!("bitwise ops are not recorded as they do not apply to floats")
+        }
+    }
+}
+
+// Each instruction writes to its own register, the arguments always refer to registers of
+// previous instructions.
+enum Instr {
+    Load(usize),
+    Unary(UnaryOp, usize),
+    Binary(BinaryOp, usize, usize),
+    Affine(usize, f64, f64),
+}
+
+// The fused loop for a pending tensor, the leaves are the realized tensors that it reads from.
+#[derive(Default)]
+struct Program {
+    instrs: Vec<Instr>,
+    leaves: Vec<Tensor>,
+    registers: HashMap<crate::TensorId, usize>,
+}
+
+impl Program {
+    fn push(&mut self, instr: Instr) -> usize {
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+
+    // The instruction computing `op` from the registers of its arguments.
+    fn instr(&self, op: &Op) -> Instr {
+        let register = |arg: &Tensor| self.registers[&arg.id()];
+        match op {
+            Op::Unary(arg, op) => Instr::Unary(*op, register(arg)),
+            Op::Binary(lhs, rhs, op) => Instr::Binary(*op, register(lhs), register(rhs)),
+            Op::Affine { arg, mul, add } => Instr::Affine(register(arg), *mul, *add),
+            // Only elementwise ops are recorded by the lazy mode.
+            _ => unreachable!("unexpected pending op"),
+        }
+    }
+
+    // Pending arguments are inlined in the loop, an argument used multiple times is only
+    // evaluated once. The graph is walked with an explicit stack rather than recursively so that
+    // long chains of pending ops cannot overflow the call stack, the instruction for `root` comes
+    // last.
+    fn compile(&mut self, root: &Op) {
+        // Each entry holds a pending op, the tensor that it computes or `None` for the root, and
+        // whether its arguments have already been pushed on the stack.
+        let mut stack: Vec<(Op, Option<Tensor>, bool)> = vec![(root.clone(), None, false)];
+        while let Some((op, tensor, args_pushed)) = stack.pop() {
+            if let Some(tensor) = &tensor {
+                if self.registers.contains_key(&tensor.id()) {
+                    continue;
+                }
+            }
+            if args_pushed {
+                let register = self.push(self.instr(&op));
+                if let Some(tensor) = tensor {
+                    self.registers.insert(tensor.id(), register);
+                }
+                continue;
+            }
+            let args = match &op {
+                Op::Unary(arg, _) | Op::Affine { arg, .. } => vec![arg.clone()],
+                Op::Binary(lhs, rhs, _) => vec![lhs.clone(), rhs.clone()],
+                _ => unreachable!("unexpected pending op"),
+            };
+            stack.push((op, tensor, true));
+            for arg in args.into_iter().rev() {
+                if self.registers.contains_key(&arg.id()) {
+                    continue;
+                }
+                match arg.pending_op() {
+                    Some(arg_op) => stack.push((arg_op, Some(arg), false)),
+                    None => {
+                        self.leaves.push(arg.clone());
+                        let register = self.push(Instr::Load(self.leaves.len() - 1));
+                        self.registers.insert(arg.id(), register);
+                    }
+                }
+            }
+        }
+    }
+
+    fn run<T: FusedDType>(&self, el_count: usize) -> Vec<T> {
+        let storages = self.leaves.iter().map(|l| l.storage()).collect::<Vec<_>>();
+        let mut leaves = self
+            .leaves
+            .iter()
+            .zip(storages.iter())
+            .map(|(leaf, storage)| {
+                let data = match &**storage {
+                    Storage::Cpu(storage) => storage.as_slice::<T>(),
+                    Storage::Cuda(_) => unreachable!("lazy ops only run on the cpu"),
+                };
+                // The dtype has been checked when recording the ops.
+                Leaf::new(data.expect("unexpected dtype in lazy op"), leaf.layout())
+            })
+            .collect::<Vec<_>>();
+        let mut registers = vec![vec![T::zero(); CHUNK_SIZE]; self.instrs.len()];
+        let mut out = alloc_vec(el_count);
+        for start in (0..el_count).step_by(CHUNK_SIZE) {
+            let len = usize::min(CHUNK_SIZE, el_count - start);
+            for (dst, instr) in self.instrs.iter().enumerate() {
+                let (srcs, dst) = registers.split_at_mut(dst);
+                let dst = &mut dst[0][..len];
+                match instr {
+                    Instr::Load(leaf) => leaves[*leaf].load(dst),
+                    Instr::Unary(op, arg) => unary(*op, &srcs[*arg][..len], dst),
+                    Instr::Binary(op, lhs, rhs) => {
+                        binary(*op, &srcs[*lhs][..len], &srcs[*rhs][..len], dst)
+                    }
+                    Instr::Affine(arg, mul, add) => {
+                        let (mul, add) = (T::from_f64(*mul), T::from_f64(*add));
+                        for (x, y) in srcs[*arg][..len].iter().zip(dst.iter_mut()) {
+                            *y = *x * mul + add
+                        }
+                    }
+                }
+            }
+            out.extend_from_slice(&registers[self.instrs.len() - 1][..len])
+        }
+        out
+    }
+}
+
+enum LeafIndex<'a> {
+    Contiguous(usize),
+    Strided(StridedIndex<'a>),
+}
+
+// Reads the values of a leaf in the order of its layout, chunk after chunk.
+struct Leaf<'a, T> {
+    data: &'a [T],
+    index: LeafIndex<'a>,
+}
+
+impl<'a, T: Copy> Leaf<'a, T> {
+    fn new(data: &'a [T], layout: &'a Layout) -> Self {
+        let index = match layout.contiguous_offsets() {
+            Some((o1, _)) => LeafIndex::Contiguous(o1),
+            None => LeafIndex::Strided(layout.strided_index()),
+        };
+        Self { data, index }
+    }
+
+    fn load(&mut self, dst: &mut [T]) {
+        match &mut self.index {
+            LeafIndex::Contiguous(offset) => {
+                dst.copy_from_slice(&self.data[*offset..*offset + dst.len()]);
+                *offset += dst.len()
+            }
+            LeafIndex::Strided(index) => {
+                for (d, i) in dst.iter_mut().zip(index) {
+                    *d = self.data[i]
+                }
+            }
+        }
+    }
+}
+
+// Computes the value of a pending tensor with the given shape and dtype.
+pub(crate) fn eval(op: &Op, shape: &Shape, dtype: DType) -> Storage {
+    let mut program = Program::default();
+    program.compile(op);
+    let el_count = shape.elem_count();
+    let storage = match dtype {
+        DType::BF16 => CpuStorage::BF16(program.run(el_count)),
+        DType::F16 => CpuStorage::F16(program.run(el_count)),
+        DType::F32 => CpuStorage::F32(program.run(el_count)),
+        DType::F64 => CpuStorage::F64(program.run(el_count)),
+        dtype => unreachable!("unexpected dtype for lazy op {dtype:?}"),
+    };
+    Storage::Cpu(storage)
+}
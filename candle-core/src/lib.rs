@@ -35,12 +35,16 @@
 
 #[cfg(feature = "accelerate")]
 mod accelerate;
+pub mod autograd;
 pub mod backend;
 pub mod backprop;
+mod checkpoint;
+pub mod complex;
 mod conv;
 mod convert;
 pub mod cpu;
 pub mod cpu_backend;
+pub mod cpu_pool;
 #[cfg(feature = "cuda")]
 pub mod cuda_backend;
 #[cfg(feature = "cudnn")]
@@ -49,18 +53,26 @@ mod device;
 pub mod display;
 mod dtype;
 mod dummy_cuda_backend;
+mod einsum;
 pub mod error;
+pub mod fft;
 mod indexer;
+mod jvp;
 pub mod layout;
+pub mod lazy;
+pub mod linalg;
+pub mod memory;
 #[cfg(feature = "mkl")]
 mod mkl;
 pub mod npy;
 mod op;
 pub mod pickle;
 pub mod quantized;
+pub mod random;
 pub mod safetensors;
 pub mod scalar;
 pub mod shape;
+pub mod sparse;
 mod storage;
 mod strided_index;
 mod tensor;
@@ -68,13 +80,18 @@ pub mod test_utils;
 pub mod utils;
 mod variable;
 
+pub use checkpoint::checkpoint;
+pub use conv::PaddingMode;
 pub use cpu_backend::CpuStorage;
+pub use cpu_pool::{set_num_threads, CpuPool};
 pub use device::{Device, DeviceLocation};
 pub use dtype::{DType, FloatDType, IntDType, WithDType};
 pub use error::{Error, Result};
-pub use indexer::IndexOp;
+pub use indexer::{step, Ellipsis, IndexOp, NewAxis, TensorIndexer};
 pub use layout::Layout;
+pub use memory::MemoryStats;
 pub use op::{CustomOp1, CustomOp2, CustomOp3};
+pub use random::Generator;
 pub use shape::{Shape, D};
 pub use storage::Storage;
 pub use strided_index::{StridedBlocks, StridedIndex};
@@ -109,6 +126,22 @@ impl ToUsize2 for (usize, usize) {
     }
 }
 
+pub trait ToUsize3 {
+    fn to_usize3(self) -> (usize, usize, usize);
+}
+
+impl ToUsize3 for usize {
+    fn to_usize3(self) -> (usize, usize, usize) {
+        (self, self, self)
+    }
+}
+
+impl ToUsize3 for (usize, usize, usize) {
+    fn to_usize3(self) -> (usize, usize, usize) {
+        self
+    }
+}
+
 // A simple trait defining a module with forward method using a single argument.
 pub trait Module: std::fmt::Debug {
     fn forward(&self, xs: &Tensor) -> Result<Tensor>;
@@ -0,0 +1,842 @@
+//! Linear algebra: determinants, inverses, linear systems and matrix decompositions.
+//!
+//! The functions operate on the last two dimensions of their arguments, the leading dimensions
+//! are batch dimensions. Only f32 and f64 tensors on the cpu are supported, the computations are
+//! carried in f64 and rely on LAPACK when the `mkl` or `accelerate` feature is enabled.
+//!
+//! All the functions support backpropagation. The gradients are only well defined when the
+//! matrices to invert are not singular and, for `svd` and `eigh`, when the singular values or
+//! eigenvalues are distinct.
+//!
+//! ```rust
+//! use candle_core::{linalg, Device, Tensor};
+//! let a = Tensor::new(&[[4f64, 2.], [2., 3.]], &Device::Cpu)?;
+//! let l = linalg::cholesky(&a)?;
+//! assert_eq!(l.to_vec2::<f64>()?, &[[2., 0.], [1., 2f64.sqrt()]]);
+//! let x = linalg::solve(&a, &Tensor::new(&[2f64, 1.], &Device::Cpu)?)?;
+//! assert_eq!(x.to_vec1::<f64>()?, &[0.5, 0.]);
+//! # Ok::<(), candle_core::Error>(())
+//! ```
+use crate::backend::BackendStorage;
+use crate::op::{CustomOp1, CustomOp2};
+use crate::{CpuStorage, DType, Error, Layout, Result, Shape, Tensor, D};
+
+#[cfg(feature = "mkl")]
+use crate::mkl as lapack;
+
+#[cfg(all(feature = "accelerate", not(feature = "mkl")))]
+use crate::accelerate as lapack;
+
+// The maximum number of sweeps for the jacobi based svd and eigh.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+const MAX_SWEEPS: usize = 64;
+
+// Returns the `cols x rows` row-major transpose of a `rows x cols` row-major matrix.
+fn transpose(a: &[f64], rows: usize, cols: usize) -> Vec<f64> {
+    let mut t = vec![0.; rows * cols];
+    for i in 0..rows {
+        for j in 0..cols {
+            t[j * rows + i] = a[i * cols + j]
+        }
+    }
+    t
+}
+
+fn identity(n: usize) -> Vec<f64> {
+    let mut id = vec![0.; n * n];
+    for i in 0..n {
+        id[i * n + i] = 1.
+    }
+    id
+}
+
+// An LU factorization with partial pivoting, `lu` holds both the unit lower triangular factor
+// and the upper triangular one. Row `i` of the factorized matrix is row `perm[i]` of the input.
+struct Lu {
+    lu: Vec<f64>,
+    perm: Vec<usize>,
+    sign: f64,
+    n: usize,
+}
+
+impl Lu {
+    fn det(&self) -> f64 {
+        let n = self.n;
+        self.sign * (0..n).map(|i| self.lu[i * n + i]).product::<f64>()
+    }
+
+    fn is_singular(&self) -> bool {
+        (0..self.n).any(|i| self.lu[i * self.n + i] == 0.)
+    }
+
+    // Solves `a x = b` where `b` is a `n x k` matrix.
+    fn solve(&self, b: &[f64], k: usize) -> Vec<f64> {
+        let (n, lu) = (self.n, &self.lu);
+        let mut x: Vec<f64> = self
+            .perm
+            .iter()
+            .flat_map(|&r| b[r * k..(r + 1) * k].iter().copied())
+            .collect();
+        for i in 0..n {
+            for j in 0..i {
+                let l = lu[i * n + j];
+                for c in 0..k {
+                    x[i * k + c] -= l * x[j * k + c]
+                }
+            }
+        }
+        for i in (0..n).rev() {
+            for j in i + 1..n {
+                let u = lu[i * n + j];
+                for c in 0..k {
+                    x[i * k + c] -= u * x[j * k + c]
+                }
+            }
+            let d = lu[i * n + i];
+            for c in 0..k {
+                x[i * k + c] /= d
+            }
+        }
+        x
+    }
+}
+
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn lu_f64(mut a: Vec<f64>, n: usize) -> Lu {
+    let mut perm: Vec<usize> = (0..n).collect();
+    let mut sign = 1.;
+    for j in 0..n {
+        let mut p = j;
+        for i in j + 1..n {
+            if a[i * n + j].abs() > a[p * n + j].abs() {
+                p = i
+            }
+        }
+        if p != j {
+            for c in 0..n {
+                a.swap(j * n + c, p * n + c)
+            }
+            perm.swap(j, p);
+            sign = -sign
+        }
+        let d = a[j * n + j];
+        if d == 0. {
+            continue;
+        }
+        for i in j + 1..n {
+            let l = a[i * n + j] / d;
+            a[i * n + j] = l;
+            for c in j + 1..n {
+                a[i * n + c] -= l * a[j * n + c]
+            }
+        }
+    }
+    Lu {
+        lu: a,
+        perm,
+        sign,
+        n,
+    }
+}
+
+// Cholesky-Banachiewicz, only the lower triangle of `a` is used.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn cholesky_f64(a: &[f64], n: usize) -> Result<Vec<f64>> {
+    let mut l = vec![0.; n * n];
+    for i in 0..n {
+        for j in 0..=i {
+            let s: f64 = (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum();
+            if i == j {
+                let d = a[i * n + i] - s;
+                if d <= 0. || d.is_nan() {
+                    crate::bail!("linalg-cholesky: the matrix is not positive-definite")
+                }
+                l[i * n + i] = d.sqrt()
+            } else {
+                l[i * n + j] = (a[i * n + j] - s) / l[j * n + j]
+            }
+        }
+    }
+    Ok(l)
+}
+
+// Householder QR, returns the `m x k` matrix `q` and the `k x n` matrix `r`.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn qr_f64(a: &[f64], m: usize, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let k = m.min(n);
+    let mut r = a.to_vec();
+    let mut reflectors = Vec::with_capacity(k);
+    for j in 0..k {
+        let mut v: Vec<f64> = (j..m).map(|i| r[i * n + j]).collect();
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let alpha = if v[0] > 0. { -norm } else { norm };
+        v[0] -= alpha;
+        let v_norm2 = v.iter().map(|x| x * x).sum::<f64>();
+        if v_norm2 > 0. {
+            for c in j..n {
+                let dot: f64 = (j..m).map(|i| v[i - j] * r[i * n + c]).sum();
+                let f = 2. * dot / v_norm2;
+                for i in j..m {
+                    r[i * n + c] -= f * v[i - j]
+                }
+            }
+        }
+        reflectors.push((v, v_norm2))
+    }
+    // Apply the reflectors in reverse order to the first `k` columns of the identity.
+    let mut q = vec![0.; m * k];
+    for i in 0..k {
+        q[i * k + i] = 1.
+    }
+    for (j, (v, v_norm2)) in reflectors.iter().enumerate().rev() {
+        if *v_norm2 == 0. {
+            continue;
+        }
+        for c in 0..k {
+            let dot: f64 = (j..m).map(|i| v[i - j] * q[i * k + c]).sum();
+            let f = 2. * dot / v_norm2;
+            for i in j..m {
+                q[i * k + c] -= f * v[i - j]
+            }
+        }
+    }
+    let mut r = r[..k * n].to_vec();
+    for i in 0..k {
+        for j in 0..i.min(n) {
+            r[i * n + j] = 0.
+        }
+    }
+    (q, r)
+}
+
+// Applies a givens rotation to the columns `p` and `q` of a `rows x cols` matrix.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+#[allow(clippy::too_many_arguments)]
+fn rotate_cols(a: &mut [f64], rows: usize, cols: usize, p: usize, q: usize, c: f64, s: f64) {
+    for i in 0..rows {
+        let (x, y) = (a[i * cols + p], a[i * cols + q]);
+        a[i * cols + p] = c * x - s * y;
+        a[i * cols + q] = s * x + c * y;
+    }
+}
+
+// The rotation `(c, s)` that cancels the off-diagonal term of the 2x2 symmetric matrix
+// `[[app, apq], [apq, aqq]]`.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn jacobi_rotation(app: f64, aqq: f64, apq: f64) -> (f64, f64) {
+    let theta = (aqq - app) / (2. * apq);
+    let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+    let c = 1. / (t * t + 1.).sqrt();
+    (c, c * t)
+}
+
+// One-sided jacobi svd, returns `u`, `s` and `vt` with the singular values in decreasing order.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn svd_f64(a: &[f64], m: usize, n: usize) -> Result<(Vec<f64>, Vec<f64>, Vec<f64>)> {
+    if m < n {
+        let (u, s, vt) = svd_f64(&transpose(a, m, n), n, m)?;
+        return Ok((transpose(&vt, m, m), s, transpose(&u, n, m)));
+    }
+    // Orthogonalize the columns of `a`, the rotations get accumulated in `v`.
+    let mut w = a.to_vec();
+    let mut v = identity(n);
+    for _sweep in 0..MAX_SWEEPS {
+        let mut converged = true;
+        for p in 0..n {
+            for q in p + 1..n {
+                let (mut alpha, mut beta, mut gamma) = (0., 0., 0.);
+                for i in 0..m {
+                    let (x, y) = (w[i * n + p], w[i * n + q]);
+                    alpha += x * x;
+                    beta += y * y;
+                    gamma += x * y;
+                }
+                if gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                converged = false;
+                let (c, s) = jacobi_rotation(alpha, beta, gamma);
+                rotate_cols(&mut w, m, n, p, q, c, s);
+                rotate_cols(&mut v, n, n, p, q, c, s);
+            }
+        }
+        if converged {
+            break;
+        }
+    }
+    let norms: Vec<f64> = (0..n)
+        .map(|j| (0..m).map(|i| w[i * n + j].powi(2)).sum::<f64>().sqrt())
+        .collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+    let tol = norms.iter().fold(0f64, |acc, &v| acc.max(v)) * m as f64 * f64::EPSILON;
+    let (mut u, mut s, mut vt) = (vec![0.; m * n], Vec::with_capacity(n), vec![0.; n * n]);
+    for (dst, &j) in order.iter().enumerate() {
+        s.push(norms[j]);
+        for i in 0..n {
+            vt[dst * n + i] = v[i * n + j]
+        }
+        if norms[j] > tol {
+            for i in 0..m {
+                u[i * n + dst] = w[i * n + j] / norms[j]
+            }
+            continue;
+        }
+        // The left singular vector is arbitrary for a null singular value, complete `u` with a
+        // canonical basis vector orthogonal to the previous columns.
+        for e in 0..m {
+            let mut col = vec![0.; m];
+            col[e] = 1.;
+            for c in 0..dst {
+                let dot: f64 = (0..m).map(|i| u[i * n + c] * col[i]).sum();
+                for (i, col) in col.iter_mut().enumerate() {
+                    *col -= dot * u[i * n + c]
+                }
+            }
+            let norm = col.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 0.5 {
+                for (i, col) in col.iter().enumerate() {
+                    u[i * n + dst] = col / norm
+                }
+                break;
+            }
+        }
+    }
+    Ok((u, s, vt))
+}
+
+// Cyclic jacobi eigenvalue algorithm, only the lower triangle of `a` is used. The eigenvalues
+// are returned in increasing order, the eigenvectors are the columns of the second matrix.
+#[cfg(not(any(feature = "mkl", feature = "accelerate")))]
+fn eigh_f64(a: &[f64], n: usize) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut a = a.to_vec();
+    for i in 0..n {
+        for j in i + 1..n {
+            a[i * n + j] = a[j * n + i]
+        }
+    }
+    let norm2: f64 = a.iter().map(|x| x * x).sum();
+    let mut v = identity(n);
+    for _sweep in 0..MAX_SWEEPS {
+        let off2: f64 = (0..n * n)
+            .filter(|i| i / n != i % n)
+            .map(|i| a[i] * a[i])
+            .sum();
+        if off2 <= f64::EPSILON * f64::EPSILON * norm2 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0. {
+                    continue;
+                }
+                let (c, s) = jacobi_rotation(a[p * n + p], a[q * n + q], apq);
+                rotate_cols(&mut a, n, n, p, q, c, s);
+                for j in 0..n {
+                    let (x, y) = (a[p * n + j], a[q * n + j]);
+                    a[p * n + j] = c * x - s * y;
+                    a[q * n + j] = s * x + c * y;
+                }
+                rotate_cols(&mut v, n, n, p, q, c, s);
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a[i * n + i].total_cmp(&a[j * n + j]));
+    let w = order.iter().map(|&j| a[j * n + j]).collect();
+    let mut vs = vec![0.; n * n];
+    for (dst, &j) in order.iter().enumerate() {
+        for i in 0..n {
+            vs[i * n + dst] = v[i * n + j]
+        }
+    }
+    Ok((w, vs))
+}
+
+// Runs a LAPACK routine twice, first to query the optimal workspace size. Note that LAPACK uses
+// column-major matrices so the row-major data of a matrix is seen by LAPACK as its transpose.
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn with_workspace(mut f: impl FnMut(&mut [f64], i32) -> i32) -> i32 {
+    let mut size = [0f64];
+    f(&mut size, -1);
+    let mut work = vec![0f64; (size[0] as usize).max(1)];
+    let lwork = work.len() as i32;
+    f(&mut work, lwork)
+}
+
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn lu_f64(a: Vec<f64>, n: usize) -> Lu {
+    let mut lu = transpose(&a, n, n);
+    let mut ipiv = vec![0i32; n];
+    // A positive info reports an exactly singular factor, this is checked by the callers.
+    unsafe { lapack::dgetrf(n as i32, n as i32, &mut lu, n as i32, &mut ipiv) };
+    let mut perm: Vec<usize> = (0..n).collect();
+    let mut sign = 1.;
+    for (i, &p) in ipiv.iter().enumerate() {
+        let p = p as usize - 1;
+        if p != i {
+            perm.swap(i, p);
+            sign = -sign
+        }
+    }
+    Lu {
+        lu: transpose(&lu, n, n),
+        perm,
+        sign,
+        n,
+    }
+}
+
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn cholesky_f64(a: &[f64], n: usize) -> Result<Vec<f64>> {
+    // The upper factor of the column-major matrix is the lower factor of the row-major one.
+    let mut l = a.to_vec();
+    let info = unsafe { lapack::dpotrf(b'U', n as i32, &mut l, n as i32) };
+    if info > 0 {
+        crate::bail!("linalg-cholesky: the matrix is not positive-definite")
+    }
+    for i in 0..n {
+        for j in i + 1..n {
+            l[i * n + j] = 0.
+        }
+    }
+    Ok(l)
+}
+
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn qr_f64(a: &[f64], m: usize, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let k = m.min(n);
+    let (m_, n_, k_) = (m as i32, n as i32, k as i32);
+    let mut qr = transpose(a, m, n);
+    let mut tau = vec![0.; k];
+    with_workspace(|work, lwork| unsafe {
+        lapack::dgeqrf(m_, n_, &mut qr, m_, &mut tau, work, lwork)
+    });
+    let mut r = vec![0.; k * n];
+    for i in 0..k {
+        for j in i..n {
+            r[i * n + j] = qr[j * m + i]
+        }
+    }
+    with_workspace(|work, lwork| unsafe {
+        lapack::dorgqr(m_, k_, k_, &mut qr, m_, &tau, work, lwork)
+    });
+    (transpose(&qr[..m * k], k, m), r)
+}
+
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn svd_f64(a: &[f64], m: usize, n: usize) -> Result<(Vec<f64>, Vec<f64>, Vec<f64>)> {
+    let k = m.min(n);
+    let (m_, n_, k_) = (m as i32, n as i32, k as i32);
+    let mut a = transpose(a, m, n);
+    let (mut u, mut s, mut vt) = (vec![0.; m * k], vec![0.; k], vec![0.; k * n]);
+    let info = with_workspace(|work, lwork| unsafe {
+        lapack::dgesvd(
+            b'S', b'S', m_, n_, &mut a, m_, &mut s, &mut u, m_, &mut vt, k_, work, lwork,
+        )
+    });
+    if info > 0 {
+        crate::bail!("linalg-svd: the algorithm did not converge")
+    }
+    Ok((transpose(&u, k, m), s, transpose(&vt, n, k)))
+}
+
+#[cfg(any(feature = "mkl", feature = "accelerate"))]
+fn eigh_f64(a: &[f64], n: usize) -> Result<(Vec<f64>, Vec<f64>)> {
+    // The upper triangle of the column-major matrix is the lower triangle of the row-major one.
+    let n_ = n as i32;
+    let mut v = a.to_vec();
+    let mut w = vec![0.; n];
+    let info = with_workspace(|work, lwork| unsafe {
+        lapack::dsyev(b'V', b'U', n_, &mut v, n_, &mut w, work, lwork)
+    });
+    if info > 0 {
+        crate::bail!("linalg-eigh: the algorithm did not converge")
+    }
+    Ok((w, transpose(&v, n, n)))
+}
+
+// Reads the values of a f32 or f64 storage as f64, following the layout.
+fn read_f64(storage: &CpuStorage, layout: &Layout) -> Result<Vec<f64>> {
+    let vs = match storage {
+        CpuStorage::F32(vs) => layout.strided_index().map(|i| vs[i] as f64).collect(),
+        CpuStorage::F64(vs) => layout.strided_index().map(|i| vs[i]).collect(),
+        storage => Err(Error::UnsupportedDTypeForOp(storage.dtype(), "linalg").bt())?,
+    };
+    Ok(vs)
+}
+
+fn write_f64(vs: Vec<f64>, dtype: DType) -> CpuStorage {
+    match dtype {
+        DType::F32 => CpuStorage::F32(vs.into_iter().map(|v| v as f32).collect()),
+        _ => CpuStorage::F64(vs),
+    }
+}
+
+// Splits a shape into its batch dimensions and the dimensions of the matrices.
+fn matrix_dims(shape: &Shape, op: &'static str) -> Result<(Vec<usize>, usize, usize)> {
+    let dims = shape.dims();
+    if dims.len() < 2 || dims[dims.len() - 2] == 0 || dims[dims.len() - 1] == 0 {
+        crate::bail!("{op} expects non-empty matrices, got shape {shape:?}")
+    }
+    let (batch, mn) = dims.split_at(dims.len() - 2);
+    Ok((batch.to_vec(), mn[0], mn[1]))
+}
+
+fn check_matrices(a: &Tensor, op: &'static str, square: bool) -> Result<(Vec<usize>, usize)> {
+    if !matches!(a.dtype(), DType::F32 | DType::F64) {
+        Err(Error::UnsupportedDTypeForOp(a.dtype(), op).bt())?
+    }
+    let (batch, m, n) = matrix_dims(a.shape(), op)?;
+    if square && m != n {
+        crate::bail!("{op} expects square matrices, got shape {:?}", a.shape())
+    }
+    Ok((batch, n))
+}
+
+// A `n x n` matrix with `f(row, col)` as values.
+fn matrix_fn(a: &Tensor, n: usize, f: impl Fn(usize, usize) -> f64) -> Result<Tensor> {
+    let vs: Vec<f64> = (0..n * n).map(|i| f(i / n, i % n)).collect();
+    Tensor::from_vec(vs, (n, n), a.device())?.to_dtype(a.dtype())
+}
+
+// Builds diagonal matrices from a batch of vectors.
+fn diag_embed(v: &Tensor) -> Result<Tensor> {
+    let eye = matrix_fn(v, v.dim(D::Minus1)?, |i, j| (i == j) as u8 as f64)?;
+    v.unsqueeze(D::Minus1)?.broadcast_mul(&eye)
+}
+
+// The matrices with `1 / (v[j] - v[i])` as values, or zero when `v[i] == v[j]`.
+fn inv_gaps(v: &Tensor) -> Result<Tensor> {
+    let gaps = v
+        .unsqueeze(D::Minus2)?
+        .broadcast_sub(&v.unsqueeze(D::Minus1)?)?;
+    gaps.eq(0.)?.where_cond(&gaps.zeros_like()?, &gaps.recip()?)
+}
+
+// The lower triangle of `a` with a halved diagonal.
+fn tril_half_diag(a: &Tensor) -> Result<Tensor> {
+    let mask = matrix_fn(a, a.dim(D::Minus1)?, |i, j| match i.cmp(&j) {
+        std::cmp::Ordering::Greater => 1.,
+        std::cmp::Ordering::Equal => 0.5,
+        std::cmp::Ordering::Less => 0.,
+    })?;
+    a.broadcast_mul(&mask)
+}
+
+fn cholesky_bwd(l: &Tensor, grad_l: &Tensor) -> Result<Tensor> {
+    let lt = l.t()?;
+    let phi = tril_half_diag(&lt.matmul(grad_l)?)?;
+    // l^-T phi l^-1, symmetrized as only the lower triangle of the input is used.
+    let s = solve(&lt, &solve(&lt, &phi)?.t()?)?.t()?;
+    (&s + s.t()?)? * 0.5
+}
+
+fn qr_bwd(q: &Tensor, r: &Tensor, grad_q: &Tensor, grad_r: &Tensor) -> Result<Tensor> {
+    let (m, n) = (q.dim(D::Minus2)?, r.dim(D::Minus1)?);
+    if m < n {
+        crate::bail!("linalg-qr: backward is only supported for matrices with at least as many rows as columns")
+    }
+    // The lower triangle of r grad_r^T - grad_q^T q, copied to the upper triangle.
+    let m = (r.matmul(&grad_r.t()?)? - grad_q.t()?.matmul(q)?)?;
+    let tril = tril_half_diag(&m)?;
+    let grad = (grad_q + q.matmul(&(&tril + tril.t()?)?)?)?;
+    // grad r^-T
+    solve(r, &grad.t()?)?.t()
+}
+
+fn svd_bwd(
+    (u, s, vt): (&Tensor, &Tensor, &Tensor),
+    (grad_u, grad_s, grad_vt): (&Tensor, &Tensor, &Tensor),
+) -> Result<Tensor> {
+    let (m, n) = (u.dim(D::Minus2)?, vt.dim(D::Minus1)?);
+    let (v, grad_v) = (vt.t()?, grad_vt.t()?);
+    let skew = |x: Tensor| x.sub(&x.t()?);
+    let ut_grad_u = skew(u.t()?.matmul(grad_u)?)?;
+    let vt_grad_v = skew(vt.matmul(&grad_v)?)?;
+    let s_row = s.unsqueeze(D::Minus2)?;
+    let s_col = s.unsqueeze(D::Minus1)?;
+    let inner = (ut_grad_u.broadcast_mul(&s_row)? + vt_grad_v.broadcast_mul(&s_col)?)?;
+    let inner = (inner.mul(&inv_gaps(&s.sqr()?)?)? + diag_embed(grad_s)?)?;
+    if m > n {
+        let grad_u_s = grad_u.broadcast_div(&s_row)?;
+        let grad_u_s = (&grad_u_s - u.matmul(&u.t()?.matmul(&grad_u_s)?)?)?;
+        (u.matmul(&inner)? + grad_u_s)?.matmul(vt)
+    } else if m < n {
+        let grad_v_s = grad_v.broadcast_div(&s_row)?.t()?;
+        let grad_v_s = (&grad_v_s - grad_v_s.matmul(&v)?.matmul(vt)?)?;
+        u.matmul(&(inner.matmul(vt)? + grad_v_s)?)
+    } else {
+        u.matmul(&inner)?.matmul(vt)
+    }
+}
+
+fn eigh_bwd(w: &Tensor, v: &Tensor, grad_w: &Tensor, grad_v: &Tensor) -> Result<Tensor> {
+    let inner = v.t()?.matmul(grad_v)?.mul(&inv_gaps(w)?)?;
+    let inner = (inner + diag_embed(grad_w)?)?;
+    v.matmul(&inner)?.matmul(&v.t()?)
+}
+
+// The decompositions are implemented as custom ops, their results get packed together along the
+// last dimension of the op output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decomposition {
+    Det,
+    Inv,
+    Cholesky,
+    Qr,
+    Svd,
+    Eigh,
+}
+
+impl Decomposition {
+    // The shapes of the results for a single `m x n` matrix.
+    fn results(&self, m: usize, n: usize) -> Vec<Vec<usize>> {
+        let k = m.min(n);
+        match self {
+            Self::Det => vec![vec![]],
+            Self::Inv | Self::Cholesky => vec![vec![n, n]],
+            Self::Qr => vec![vec![m, k], vec![k, n]],
+            Self::Svd => vec![vec![m, k], vec![k], vec![k, n]],
+            Self::Eigh => vec![vec![n], vec![n, n]],
+        }
+    }
+
+    fn run(&self, a: &[f64], m: usize, n: usize) -> Result<Vec<f64>> {
+        let out = match self {
+            Self::Det => vec![lu_f64(a.to_vec(), n).det()],
+            Self::Inv => {
+                let lu = lu_f64(a.to_vec(), n);
+                if lu.is_singular() {
+                    crate::bail!("linalg-inv: the matrix is singular")
+                }
+                lu.solve(&identity(n), n)
+            }
+            Self::Cholesky => cholesky_f64(a, n)?,
+            Self::Qr => {
+                let (mut q, r) = qr_f64(a, m, n);
+                q.extend(r);
+                q
+            }
+            Self::Svd => {
+                let (mut u, s, vt) = svd_f64(a, m, n)?;
+                u.extend(s);
+                u.extend(vt);
+                u
+            }
+            Self::Eigh => {
+                let (mut w, v) = eigh_f64(a, n)?;
+                w.extend(v);
+                w
+            }
+        };
+        Ok(out)
+    }
+
+    fn apply(self, a: &Tensor) -> Result<Vec<Tensor>> {
+        let (batch, m, n) = matrix_dims(a.shape(), self.name())?;
+        let packed = a.apply_op1(self)?;
+        self.unpack(&packed, &batch, m, n)
+    }
+
+    fn unpack(&self, packed: &Tensor, batch: &[usize], m: usize, n: usize) -> Result<Vec<Tensor>> {
+        let mut offset = 0;
+        self.results(m, n)
+            .into_iter()
+            .map(|dims| {
+                let len = dims.iter().product();
+                let t = packed.narrow(D::Minus1, offset, len)?;
+                offset += len;
+                t.reshape([batch, &dims].concat())
+            })
+            .collect()
+    }
+}
+
+impl CustomOp1 for Decomposition {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Det => "linalg-det",
+            Self::Inv => "linalg-inv",
+            Self::Cholesky => "linalg-cholesky",
+            Self::Qr => "linalg-qr",
+            Self::Svd => "linalg-svd",
+            Self::Eigh => "linalg-eigh",
+        }
+    }
+
+    fn cpu_fwd(&self, storage: &CpuStorage, layout: &Layout) -> Result<(CpuStorage, Shape)> {
+        let (mut dims, m, n) = matrix_dims(layout.shape(), self.name())?;
+        let a = read_f64(storage, layout)?;
+        let mut out = vec![];
+        for a in a.chunks(m * n) {
+            out.extend(self.run(a, m, n)?)
+        }
+        dims.push(
+            self.results(m, n)
+                .iter()
+                .map(|d| d.iter().product::<usize>())
+                .sum(),
+        );
+        Ok((write_f64(out, storage.dtype()), Shape::from(dims)))
+    }
+
+    fn bwd(&self, arg: &Tensor, res: &Tensor, grad_res: &Tensor) -> Result<Option<Tensor>> {
+        let (batch, m, n) = matrix_dims(arg.shape(), self.name())?;
+        let res = self.unpack(res, &batch, m, n)?;
+        let grad = self.unpack(grad_res, &batch, m, n)?;
+        let grad_arg = match self {
+            Self::Det => {
+                // d det(a) = det(a) tr(a^-1 da)
+                let grad = (&grad[0] * &res[0])?;
+                let grad = grad.unsqueeze(D::Minus1)?.unsqueeze(D::Minus1)?;
+                inv(arg)?.t()?.broadcast_mul(&grad)?
+            }
+            Self::Inv => {
+                let inv_t = res[0].t()?;
+                inv_t.matmul(&grad[0])?.matmul(&inv_t)?.neg()?
+            }
+            Self::Cholesky => cholesky_bwd(&res[0], &grad[0])?,
+            Self::Qr => qr_bwd(&res[0], &res[1], &grad[0], &grad[1])?,
+            Self::Svd => svd_bwd((&res[0], &res[1], &res[2]), (&grad[0], &grad[1], &grad[2]))?,
+            Self::Eigh => eigh_bwd(&res[0], &res[1], &grad[0], &grad[1])?,
+        };
+        Ok(Some(grad_arg))
+    }
+}
+
+struct Solve;
+
+impl CustomOp2 for Solve {
+    fn name(&self) -> &'static str {
+        "linalg-solve"
+    }
+
+    fn cpu_fwd(
+        &self,
+        s1: &CpuStorage,
+        l1: &Layout,
+        s2: &CpuStorage,
+        l2: &Layout,
+    ) -> Result<(CpuStorage, Shape)> {
+        let (_, n, _) = matrix_dims(l1.shape(), self.name())?;
+        let (_, _, k) = matrix_dims(l2.shape(), self.name())?;
+        let (a, b) = (read_f64(s1, l1)?, read_f64(s2, l2)?);
+        let mut out = Vec::with_capacity(b.len());
+        for (a, b) in a.chunks(n * n).zip(b.chunks(n * k)) {
+            let lu = lu_f64(a.to_vec(), n);
+            if lu.is_singular() {
+                crate::bail!("linalg-solve: the matrix is singular")
+            }
+            out.extend(lu.solve(b, k))
+        }
+        Ok((write_f64(out, s1.dtype()), l2.shape().clone()))
+    }
+
+    fn bwd(
+        &self,
+        a: &Tensor,
+        _b: &Tensor,
+        x: &Tensor,
+        grad_x: &Tensor,
+    ) -> Result<(Option<Tensor>, Option<Tensor>)> {
+        let grad_b = solve(&a.t()?, grad_x)?;
+        let grad_a = grad_b.matmul(&x.t()?)?.neg()?;
+        Ok((Some(grad_a), Some(grad_b)))
+    }
+}
+
+/// The determinants of the square matrices `a`, the result only has the batch dimensions.
+///
+/// ```rust
+/// use candle_core::{linalg, Device, Tensor};
+/// let a = Tensor::new(&[[[1f32, 2.], [3., 4.]], [[2., 0.], [0., 3.]]], &Device::Cpu)?;
+/// assert_eq!(linalg::det(&a)?.to_vec1::<f32>()?, &[-2., 6.]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn det(a: &Tensor) -> Result<Tensor> {
+    check_matrices(a, "linalg-det", true)?;
+    let mut det = Decomposition::Det.apply(a)?;
+    Ok(det.remove(0))
+}
+
+/// The inverses of the square matrices `a`, an error is returned for singular matrices.
+pub fn inv(a: &Tensor) -> Result<Tensor> {
+    check_matrices(a, "linalg-inv", true)?;
+    let mut inv = Decomposition::Inv.apply(a)?;
+    Ok(inv.remove(0))
+}
+
+/// Solves the linear systems `a x = b` where `a` has shape `(.., n, n)` and `b` has shape
+/// `(.., n, k)`. When `b` has one dimension less than `a`, it is a vector of size `n` per batch
+/// element and so is the result.
+pub fn solve(a: &Tensor, b: &Tensor) -> Result<Tensor> {
+    let (batch, n) = check_matrices(a, "linalg-solve", true)?;
+    if a.dtype() != b.dtype() {
+        Err(Error::DTypeMismatchBinaryOp {
+            lhs: a.dtype(),
+            rhs: b.dtype(),
+            op: "linalg-solve",
+        }
+        .bt())?
+    }
+    if b.rank() + 1 == a.rank() {
+        return solve(a, &b.unsqueeze(D::Minus1)?)?.squeeze(D::Minus1);
+    }
+    let (b_batch, b_n, _) = matrix_dims(b.shape(), "linalg-solve")?;
+    if b_batch != batch || b_n != n {
+        Err(Error::ShapeMismatchBinaryOp {
+            lhs: a.shape().clone(),
+            rhs: b.shape().clone(),
+            op: "linalg-solve",
+        }
+        .bt())?
+    }
+    a.apply_op2(b, Solve)
+}
+
+/// The lower triangular matrices `l` such that `a = l l^T` for symmetric positive-definite
+/// matrices `a`. Only the lower triangle of `a` is used.
+pub fn cholesky(a: &Tensor) -> Result<Tensor> {
+    check_matrices(a, "linalg-cholesky", true)?;
+    let mut l = Decomposition::Cholesky.apply(a)?;
+    Ok(l.remove(0))
+}
+
+/// The reduced QR decomposition of `a` with shape `(.., m, n)`. This returns `q` with
+/// orthonormal columns and shape `(.., m, k)` and the upper triangular `r` with shape `(.., k, n)`
+/// where `k = min(m, n)`. The backward pass requires `m >= n`.
+pub fn qr(a: &Tensor) -> Result<(Tensor, Tensor)> {
+    check_matrices(a, "linalg-qr", false)?;
+    let qr = Decomposition::Qr.apply(a)?;
+    Ok((qr[0].clone(), qr[1].clone()))
+}
+
+/// The reduced singular value decomposition of `a` with shape `(.., m, n)`, i.e.
+/// `a = u diag(s) vt`. With `k = min(m, n)`, `u` has shape `(.., m, k)`, the singular values `s`
+/// have shape `(.., k)` and are sorted in decreasing order, and `vt` has shape `(.., k, n)`.
+///
+/// ```rust
+/// use candle_core::{linalg, Device, Tensor};
+/// let a = Tensor::new(&[[3f64, 0.], [0., -4.], [0., 0.]], &Device::Cpu)?;
+/// let (u, s, vt) = linalg::svd(&a)?;
+/// assert_eq!(s.to_vec1::<f64>()?, &[4., 3.]);
+/// let a2 = u.broadcast_mul(&s.unsqueeze(0)?)?.matmul(&vt)?;
+/// assert_eq!(a2.to_vec2::<f64>()?, a.to_vec2::<f64>()?);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub fn svd(a: &Tensor) -> Result<(Tensor, Tensor, Tensor)> {
+    check_matrices(a, "linalg-svd", false)?;
+    let usv = Decomposition::Svd.apply(a)?;
+    Ok((usv[0].clone(), usv[1].clone(), usv[2].clone()))
+}
+
+/// The eigenvalues and eigenvectors of the symmetric matrices `a`, only the lower triangle of `a`
+/// is used. The eigenvalues are returned in increasing order with shape `(.., n)`, the
+/// eigenvectors are the columns of the second result.
+pub fn eigh(a: &Tensor) -> Result<(Tensor, Tensor)> {
+    check_matrices(a, "linalg-eigh", true)?;
+    let wv = Decomposition::Eigh.apply(a)?;
+    Ok((wv[0].clone(), wv[1].clone()))
+}
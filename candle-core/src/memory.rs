@@ -0,0 +1,101 @@
+//! Memory statistics and caching of the buffers released by tensors.
+//!
+//! When the last tensor using a storage is dropped, the underlying buffer is handed back to its
+//! device. Once a cache limit has been set with [`crate::Device::set_cache_limit`], the device
+//! keeps these buffers in size buckets and reuses them for later allocations of a compatible size,
+//! which avoids going through the system allocator (or the cuda driver) on every op. The cached
+//! buffers can be released with [`crate::Device::empty_cache`].
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// The pool shared by all the cpu devices.
+pub(crate) static CPU_POOL: Mutex<MemoryPool> = Mutex::new(MemoryPool::new());
+
+/// The default upper bound on the number of bytes that a device keeps cached. Caching is disabled
+/// by default, it can be enabled with [`crate::Device::set_cache_limit`].
+pub const DEFAULT_CACHE_LIMIT: usize = 0;
+
+/// Memory usage of a device, all the values are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    /// The memory used by the storages of the live tensors, including the spare capacity of their
+    /// buffers.
+    pub in_use: usize,
+    /// The highest value reached by `in_use`.
+    pub peak: usize,
+    /// The memory held by released buffers that are kept around for reuse.
+    pub cached: usize,
+}
+
+type Buffer = (Box<dyn Any + Send>, usize);
+
+// The buffers are keyed on their concrete type, e.g. `Vec<f32>`, and on a size bucket that is
+// chosen by the backend.
+pub(crate) struct MemoryPool {
+    buffers: BTreeMap<(TypeId, usize), Vec<Buffer>>,
+    stats: MemoryStats,
+    cache_limit: usize,
+}
+
+impl MemoryPool {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buffers: BTreeMap::new(),
+            stats: MemoryStats {
+                in_use: 0,
+                peak: 0,
+                cached: 0,
+            },
+            cache_limit: DEFAULT_CACHE_LIMIT,
+        }
+    }
+
+    pub(crate) fn track_alloc(&mut self, bytes: usize) {
+        self.stats.in_use += bytes;
+        self.stats.peak = usize::max(self.stats.peak, self.stats.in_use);
+    }
+
+    pub(crate) fn track_free(&mut self, bytes: usize) {
+        self.stats.in_use = self.stats.in_use.saturating_sub(bytes);
+    }
+
+    /// Keeps `buffer` for later reuse, the buffer is dropped if this would go over the cache
+    /// limit.
+    pub(crate) fn insert<B: Any + Send>(&mut self, bucket: usize, buffer: B, bytes: usize) {
+        if self.stats.cached + bytes > self.cache_limit {
+            return;
+        }
+        self.stats.cached += bytes;
+        self.buffers
+            .entry((TypeId::of::<B>(), bucket))
+            .or_default()
+            .push((Box::new(buffer), bytes))
+    }
+
+    /// Retrieves a cached buffer of type `B` holding at least `min_bytes` from the given bucket.
+    pub(crate) fn remove<B: Any + Send>(&mut self, bucket: usize, min_bytes: usize) -> Option<B> {
+        let buffers = self.buffers.get_mut(&(TypeId::of::<B>(), bucket))?;
+        let index = buffers.iter().rposition(|(_, bytes)| *bytes >= min_bytes)?;
+        let (buffer, bytes) = buffers.swap_remove(index);
+        self.stats.cached -= bytes;
+        // The key ensures that the downcast always succeeds.
+        buffer.downcast::<B>().ok().map(|b| *b)
+    }
+
+    pub(crate) fn empty(&mut self) {
+        self.buffers.clear();
+        self.stats.cached = 0;
+    }
+
+    pub(crate) fn set_cache_limit(&mut self, cache_limit: usize) {
+        self.cache_limit = cache_limit;
+        if self.stats.cached > cache_limit {
+            self.empty()
+        }
+    }
+
+    pub(crate) fn stats(&self) -> MemoryStats {
+        self.stats
+    }
+}
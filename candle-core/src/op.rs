@@ -1,5 +1,6 @@
 #![allow(clippy::redundant_closure_call)]
-use crate::{CpuStorage, CudaStorage, Layout, Result, Shape, Tensor};
+use crate::complex::{C128, C64};
+use crate::{CpuStorage, CudaStorage, DType, Layout, Result, Shape, Tensor};
 use half::{bf16, f16};
 use num_traits::float::Float;
 
@@ -34,6 +35,25 @@ impl ReduceOp {
     }
 }
 
+// Cumulative ops scan the input along a single dimension and return a tensor with the same
+// shape and type as the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CumulativeOp {
+    Sum,
+    Prod,
+    Max,
+}
+
+impl CumulativeOp {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Sum => "cumsum",
+            Self::Prod => "cumprod",
+            Self::Max => "cummax",
+        }
+    }
+}
+
 // These ops return the same type as their input type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinaryOp {
@@ -43,6 +63,12 @@ pub enum BinaryOp {
     Div,
     Maximum,
     Minimum,
+    Pow,
+    Atan2,
+    Fmod,
+    BitAnd,
+    BitOr,
+    BitXor,
 }
 
 // Unary ops with no argument
@@ -60,6 +86,11 @@ pub enum UnaryOp {
     Gelu,
     Relu,
     Tanh,
+    Erf,
+    Floor,
+    Ceil,
+    Round,
+    Sign,
 }
 
 #[derive(Clone)]
@@ -69,10 +100,18 @@ pub enum Op {
     Cmp(Tensor, CmpOp),
     // The third argument is the reduced shape with `keepdim=true`.
     Reduce(Tensor, ReduceOp, Vec<usize>),
+    Cumulative(Tensor, CumulativeOp, usize),
+    Fft {
+        arg: Tensor,
+        dim: usize,
+        inverse: bool,
+    },
     Matmul(Tensor, Tensor),
     Gather(Tensor, Tensor, usize),
     ScatterAdd(Tensor, Tensor, Tensor, usize),
     IndexSelect(Tensor, Tensor, usize),
+    // Selection on the first dimension where the gradient of a variable argument is sparse.
+    SparseIndexSelect(Tensor, Tensor),
     IndexAdd(Tensor, Tensor, Tensor, usize),
     WhereCond(Tensor, Tensor, Tensor),
 
@@ -86,14 +125,27 @@ pub enum Op {
     },
 
     #[allow(dead_code)]
-    Conv2D {
+    ConvTranspose1D {
         arg: Tensor,
         kernel: Tensor,
         padding: usize,
+        output_padding: usize,
         stride: usize,
         dilation: usize,
     },
 
+    #[allow(dead_code)]
+    Conv2D {
+        arg: Tensor,
+        kernel: Tensor,
+        // (top, bottom, left, right)
+        padding: (usize, usize, usize, usize),
+        padding_mode: crate::conv::PaddingMode,
+        // (h, w)
+        stride: (usize, usize),
+        dilation: (usize, usize),
+    },
+
     #[allow(dead_code)]
     ConvTranspose2D {
         arg: Tensor,
@@ -104,6 +156,15 @@ pub enum Op {
         dilation: usize,
     },
 
+    #[allow(dead_code)]
+    Conv3D {
+        arg: Tensor,
+        kernel: Tensor,
+        padding: usize,
+        stride: usize,
+        dilation: usize,
+    },
+
     AvgPool2D {
         arg: Tensor,
         kernel_size: (usize, usize),
@@ -116,6 +177,20 @@ pub enum Op {
         stride: (usize, usize),
     },
 
+    #[allow(dead_code)]
+    AvgPool3D {
+        arg: Tensor,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    },
+
+    #[allow(dead_code)]
+    MaxPool3D {
+        arg: Tensor,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    },
+
     UpsampleNearest1D(Tensor),
     UpsampleNearest2D(Tensor),
 
@@ -128,6 +203,8 @@ pub enum Op {
         add: f64,
     },
     ToDType(Tensor),
+    ViewAsReal(Tensor),
+    ViewAsComplex(Tensor),
     Copy(Tensor),
     Broadcast(Tensor),
     Narrow(Tensor, usize, usize, usize),
@@ -149,8 +226,17 @@ pub enum Op {
         Tensor,
         std::sync::Arc<Box<dyn CustomOp3 + Send + Sync>>,
     ),
+    // The activations computed by `f` are not kept around, they get recomputed from `args`
+    // when differentiating. `vars` are the variables used by `f` that are not part of `args`.
+    Checkpoint {
+        args: Vec<Tensor>,
+        vars: Vec<Tensor>,
+        f: CheckpointFn,
+    },
 }
 
+pub(crate) type CheckpointFn = std::sync::Arc<dyn Fn(&[Tensor]) -> Result<Tensor> + Send + Sync>;
+
 /// Unary ops that can be defined in user-land.
 pub trait CustomOp1 {
     // Box<dyn> does not support const yet, so use a function to get the name.
@@ -174,6 +260,13 @@ pub trait CustomOp1 {
     fn bwd(&self, _arg: &Tensor, _res: &Tensor, _grad_res: &Tensor) -> Result<Option<Tensor>> {
         Err(crate::Error::BackwardNotSupported { op: self.name() })
     }
+
+    /// The forward mode counterpart of `bwd`, this takes as argument the argument `arg` used in
+    /// the forward pass, the result `res` and the tangent of the argument `tangent`. The function
+    /// should return the tangent of the result.
+    fn jvp(&self, _arg: &Tensor, _res: &Tensor, _tangent: &Tensor) -> Result<Tensor> {
+        Err(crate::Error::JvpNotSupported { op: self.name() })
+    }
 }
 
 pub trait CustomOp2 {
@@ -212,6 +305,19 @@ pub trait CustomOp2 {
     ) -> Result<(Option<Tensor>, Option<Tensor>)> {
         Err(crate::Error::BackwardNotSupported { op: self.name() })
     }
+
+    /// The tangent of the result given the tangents of both arguments, tangents for arguments
+    /// that do not depend on the jvp inputs are filled with zeros.
+    fn jvp(
+        &self,
+        _arg1: &Tensor,
+        _arg2: &Tensor,
+        _res: &Tensor,
+        _tangent1: &Tensor,
+        _tangent2: &Tensor,
+    ) -> Result<Tensor> {
+        Err(crate::Error::JvpNotSupported { op: self.name() })
+    }
 }
 
 pub trait CustomOp3 {
@@ -255,6 +361,22 @@ pub trait CustomOp3 {
     ) -> Result<(Option<Tensor>, Option<Tensor>, Option<Tensor>)> {
         Err(crate::Error::BackwardNotSupported { op: self.name() })
     }
+
+    /// The tangent of the result given the tangents of the three arguments, tangents for
+    /// arguments that do not depend on the jvp inputs are filled with zeros.
+    #[allow(clippy::too_many_arguments)]
+    fn jvp(
+        &self,
+        _arg1: &Tensor,
+        _arg2: &Tensor,
+        _arg3: &Tensor,
+        _res: &Tensor,
+        _tangent1: &Tensor,
+        _tangent2: &Tensor,
+        _tangent3: &Tensor,
+    ) -> Result<Tensor> {
+        Err(crate::Error::JvpNotSupported { op: self.name() })
+    }
 }
 
 pub trait UnaryOpT {
@@ -267,7 +389,18 @@ pub trait UnaryOpT {
     fn f64(v1: f64) -> f64;
     fn u8(v1: u8) -> u8;
     fn u32(v1: u32) -> u32;
+    fn i8(v1: i8) -> i8;
+    fn i16(v1: i16) -> i16;
+    fn i32(v1: i32) -> i32;
     fn i64(v1: i64) -> i64;
+    fn c64(v1: C64) -> C64;
+    fn c128(v1: C128) -> C128;
+
+    /// Whether the op is defined for `dtype`, storages using other dtypes are rejected with an
+    /// error before reaching the backends.
+    fn supports_dtype(_dtype: DType) -> bool {
+        true
+    }
 
     // There is no very good way to represent optional function in traits so we go for an explicit
     // boolean flag to mark the function as existing.
@@ -291,7 +424,19 @@ pub trait BinaryOpT {
     fn f64(v1: f64, v2: f64) -> f64;
     fn u8(v1: u8, v2: u8) -> u8;
     fn u32(v1: u32, v2: u32) -> u32;
+    fn i8(v1: i8, v2: i8) -> i8;
+    fn i16(v1: i16, v2: i16) -> i16;
+    fn i32(v1: i32, v2: i32) -> i32;
     fn i64(v1: i64, v2: i64) -> i64;
+    fn c64(v1: C64, v2: C64) -> C64;
+    fn c128(v1: C128, v2: C128) -> C128;
+
+    /// Whether the op is defined for `dtype`, see [`UnaryOpT::supports_dtype`]. Booleans are
+    /// rejected by the cpu backend unless supported here, in which case the `u8` function is
+    /// applied to their 0/1 values.
+    fn supports_dtype(_dtype: DType) -> bool {
+        true
+    }
 
     const BF16_VEC: bool = false;
     fn bf16_vec(_xs1: &[bf16], _xs2: &[bf16], _ys: &mut [bf16]) {}
@@ -305,6 +450,12 @@ pub trait BinaryOpT {
     fn u8_vec(_xs1: &[u8], _xs2: &[u8], _ys: &mut [u8]) {}
     const U32_VEC: bool = false;
     fn u32_vec(_xs1: &[u32], _xs2: &[u32], _ys: &mut [u32]) {}
+    const I8_VEC: bool = false;
+    fn i8_vec(_xs1: &[i8], _xs2: &[i8], _ys: &mut [i8]) {}
+    const I16_VEC: bool = false;
+    fn i16_vec(_xs1: &[i16], _xs2: &[i16], _ys: &mut [i16]) {}
+    const I32_VEC: bool = false;
+    fn i32_vec(_xs1: &[i32], _xs2: &[i32], _ys: &mut [i32]) {}
     const I64_VEC: bool = false;
     fn i64_vec(_xs1: &[i64], _xs2: &[i64], _ys: &mut [i64]) {}
 }
@@ -327,6 +478,17 @@ pub(crate) struct Sqrt;
 pub(crate) struct Gelu;
 pub(crate) struct Relu;
 pub(crate) struct Tanh;
+pub(crate) struct Erf;
+pub(crate) struct Floor;
+pub(crate) struct Ceil;
+pub(crate) struct Round;
+pub(crate) struct Sign;
+pub(crate) struct Pow;
+pub(crate) struct Atan2;
+pub(crate) struct Fmod;
+pub(crate) struct BitAnd;
+pub(crate) struct BitOr;
+pub(crate) struct BitXor;
 
 macro_rules! bin_op {
     ($op:ident, $name: literal, $e: expr, $f32_vec: ident, $f64_vec: ident) => {
@@ -359,9 +521,29 @@ macro_rules! bin_op {
                 $e(v1, v2)
             }
             #[inline(always)]
+            fn i8(v1: i8, v2: i8) -> i8 {
+                $e(v1, v2)
+            }
+            #[inline(always)]
+            fn i16(v1: i16, v2: i16) -> i16 {
+                $e(v1, v2)
+            }
+            #[inline(always)]
+            fn i32(v1: i32, v2: i32) -> i32 {
+                $e(v1, v2)
+            }
+            #[inline(always)]
             fn i64(v1: i64, v2: i64) -> i64 {
                 $e(v1, v2)
             }
+            #[inline(always)]
+            fn c64(v1: C64, v2: C64) -> C64 {
+                $e(v1, v2)
+            }
+            #[inline(always)]
+            fn c128(v1: C128, v2: C128) -> C128 {
+                $e(v1, v2)
+            }
 
             #[cfg(feature = "mkl")]
             const F32_VEC: bool = true;
@@ -415,13 +597,138 @@ bin_op!(
     vd_max
 );
 
+// Binary ops that are only defined on real numbers, the half precision floats are evaluated in
+// f32. The functions for the dtypes that are not supported are never called.
+macro_rules! real_bin_op {
+    ($op:ident, $name:literal, $a:ident, $b:ident, float: $fe:expr) => {
+        real_bin_op!(
+            @impl $op, $name, $a, $b, $fe,
+            unreachable!(concat!("no integer function for ", $name)),
+            |dtype: DType| dtype.is_float()
+        );
+    };
+    ($op:ident, $name:literal, $a:ident, $b:ident, int: $ie:expr) => {
+        real_bin_op!(
+            @impl $op, $name, $a, $b,
+            unreachable!(concat!("no float function for ", $name)),
+            $ie,
+            |dtype: DType| dtype.is_int() || dtype == DType::Bool
+        );
+    };
+    ($op:ident, $name:literal, $a:ident, $b:ident, float: $fe:expr, int: $ie:expr) => {
+        real_bin_op!(
+            @impl $op, $name, $a, $b, $fe, $ie,
+            |dtype: DType| dtype.is_float() || dtype.is_int()
+        );
+    };
+    (@impl $op:ident, $name:literal, $a:ident, $b:ident, $fe:expr, $ie:expr, $supports:expr) => {
+        #[allow(unused_variables)]
+        impl BinaryOpT for $op {
+            const NAME: &'static str = $name;
+            const KERNEL: &'static str = concat!("b", $name);
+            const V: Self = $op;
+            fn supports_dtype(dtype: DType) -> bool {
+                $supports(dtype)
+            }
+            #[inline(always)]
+            fn bf16($a: bf16, $b: bf16) -> bf16 {
+                bf16::from_f32(Self::f32($a.to_f32(), $b.to_f32()))
+            }
+            #[inline(always)]
+            fn f16($a: f16, $b: f16) -> f16 {
+                f16::from_f32(Self::f32($a.to_f32(), $b.to_f32()))
+            }
+            #[inline(always)]
+            fn f32($a: f32, $b: f32) -> f32 {
+                $fe
+            }
+            #[inline(always)]
+            fn f64($a: f64, $b: f64) -> f64 {
+                $fe
+            }
+            #[inline(always)]
+            fn u8($a: u8, $b: u8) -> u8 {
+                $ie
+            }
+            #[inline(always)]
+            fn u32($a: u32, $b: u32) -> u32 {
+                $ie
+            }
+            #[inline(always)]
+            fn i8($a: i8, $b: i8) -> i8 {
+                $ie
+            }
+            #[inline(always)]
+            fn i16($a: i16, $b: i16) -> i16 {
+                $ie
+            }
+            #[inline(always)]
+            fn i32($a: i32, $b: i32) -> i32 {
+                $ie
+            }
+            #[inline(always)]
+            fn i64($a: i64, $b: i64) -> i64 {
+                $ie
+            }
+            #[inline(always)]
+            fn c64(_: C64, _: C64) -> C64 {
+                unreachable!(concat!("no ", $name, " function for c64"))
+            }
+            #[inline(always)]
+            fn c128(_: C128, _: C128) -> C128 {
+                unreachable!(concat!("no ", $name, " function for c128"))
+            }
+        }
+    };
+}
+
+real_bin_op!(Pow, "pow", v1, v2, float: v1.powf(v2));
+real_bin_op!(Atan2, "atan2", v1, v2, float: v1.atan2(v2));
+// The result has the sign of the dividend, integer remainders by zero are set to zero.
+real_bin_op!(Fmod, "fmod", v1, v2, float: v1 % v2, int: v1.checked_rem(v2).unwrap_or(0));
+real_bin_op!(BitAnd, "bitand", v1, v2, int: v1 & v2);
+real_bin_op!(BitOr, "bitor", v1, v2, int: v1 | v2);
+real_bin_op!(BitXor, "bitxor", v1, v2, int: v1 ^ v2);
+
+// Unary ops defined on floats and complex numbers, `$ie` is used for the signed integer dtypes
+// when provided. Optional vectorized f32/f64 functions can be provided for mkl and accelerate.
 #[allow(clippy::redundant_closure_call)]
 macro_rules! unary_op {
-    ($op: ident, $name: literal, $a: ident, $e: expr) => {
+    ($op:ident, $name:literal, $a:ident, $e:expr) => {
+        unary_op!(@impl $op, $name, $a, $e, [], []);
+    };
+    ($op:ident, $name:literal, $a:ident, $e:expr, int: $ie:expr) => {
+        unary_op!(@impl $op, $name, $a, $e, [$ie], []);
+    };
+    ($op:ident, $name:literal, $a:ident, $e:expr, $f32_vec:ident, $f64_vec:ident) => {
+        unary_op!(@impl $op, $name, $a, $e, [], [$f32_vec, $f64_vec]);
+    };
+    ($op:ident, $name:literal, $a:ident, $e:expr, $f32_vec:ident, $f64_vec:ident, int: $ie:expr) => {
+        unary_op!(@impl $op, $name, $a, $e, [$ie], [$f32_vec, $f64_vec]);
+    };
+    (@has_int []) => {
+        false
+    };
+    (@has_int [$ie:expr]) => {
+        true
+    };
+    (@int $name:literal, $ty:literal, []) => {
+        unreachable!(concat!("no ", $name, " function for ", $ty))
+    };
+    (@int $name:literal, $ty:literal, [$ie:expr]) => {
+        $ie
+    };
+    (@impl $op:ident, $name:literal, $a:ident, $e:expr, [$($ie:expr)?],
+        [$($f32_vec:ident, $f64_vec:ident)?]) => {
+        #[allow(unused_variables)]
         impl UnaryOpT for $op {
             const NAME: &'static str = $name;
             const KERNEL: &'static str = concat!("u", $name);
             const V: Self = $op;
+            fn supports_dtype(dtype: DType) -> bool {
+                let signed_int = matches!(dtype, DType::I8 | DType::I16 | DType::I32 | DType::I64);
+                dtype.is_float() || dtype.is_complex() || (unary_op!(@has_int [$($ie)?]) && signed_int)
+            }
             #[inline(always)]
             fn bf16($a: bf16) -> bf16 {
                 $e
@@ -440,102 +747,240 @@ macro_rules! unary_op {
             }
             #[inline(always)]
             fn u8(_: u8) -> u8 {
-                todo!("no unary function for u8")
+                unreachable!(concat!("no ", $name, " function for u8"))
             }
             #[inline(always)]
             fn u32(_: u32) -> u32 {
-                todo!("no unary function for u32")
+                unreachable!(concat!("no ", $name, " function for u32"))
+            }
+            #[inline(always)]
+            fn i8($a: i8) -> i8 {
+                unary_op!(@int $name, "i8", [$($ie)?])
+            }
+            #[inline(always)]
+            fn i16($a: i16) -> i16 {
+                unary_op!(@int $name, "i16", [$($ie)?])
+            }
+            #[inline(always)]
+            fn i32($a: i32) -> i32 {
+                unary_op!(@int $name, "i32", [$($ie)?])
             }
             #[inline(always)]
-            fn i64(_: i64) -> i64 {
-                todo!("no unary function for i64")
+            fn i64($a: i64) -> i64 {
+                unary_op!(@int $name, "i64", [$($ie)?])
             }
+            #[inline(always)]
+            fn c64($a: C64) -> C64 {
+                $e
+            }
+            #[inline(always)]
+            fn c128($a: C128) -> C128 {
+                $e
+            }
+
+            $(
+                #[cfg(feature = "mkl")]
+                const F32_VEC: bool = true;
+                #[cfg(feature = "mkl")]
+                const F64_VEC: bool = true;
+                #[cfg(feature = "mkl")]
+                #[inline(always)]
+                fn f32_vec(xs: &[f32], ys: &mut [f32]) {
+                    crate::mkl::$f32_vec(xs, ys)
+                }
+                #[cfg(feature = "mkl")]
+                #[inline(always)]
+                fn f64_vec(xs: &[f64], ys: &mut [f64]) {
+                    crate::mkl::$f64_vec(xs, ys)
+                }
+
+                #[cfg(feature = "accelerate")]
+                const F32_VEC: bool = true;
+                #[cfg(feature = "accelerate")]
+                const F64_VEC: bool = true;
+                #[cfg(feature = "accelerate")]
+                #[inline(always)]
+                fn f32_vec(xs: &[f32], ys: &mut [f32]) {
+                    crate::accelerate::$f32_vec(xs, ys)
+                }
+                #[cfg(feature = "accelerate")]
+                #[inline(always)]
+                fn f64_vec(xs: &[f64], ys: &mut [f64]) {
+                    crate::accelerate::$f64_vec(xs, ys)
+                }
+            )?
         }
     };
+}
 
-    ($op: ident, $name: literal, $a: ident, $e: expr, $f32_vec:ident, $f64_vec:ident) => {
+unary_op!(Exp, "exp", v, v.exp(), vs_exp, vd_exp);
+unary_op!(Log, "log", v, v.ln(), vs_ln, vd_ln);
+unary_op!(Sin, "sin", v, v.sin(), vs_sin, vd_sin);
+unary_op!(Cos, "cos", v, v.cos(), vs_cos, vd_cos);
+unary_op!(Tanh, "tanh", v, v.tanh(), vs_tanh, vd_tanh);
+// Integer overflows wrap around, e.g. the absolute value of `i32::MIN` is `i32::MIN`.
+unary_op!(Abs, "abs", v, v.abs(), int: v.wrapping_abs());
+unary_op!(Neg, "neg", v, -v, int: v.wrapping_neg());
+unary_op!(Recip, "recip", v, v.recip());
+unary_op!(Sqr, "sqr", v, v * v, vs_sqr, vd_sqr, int: v.wrapping_mul(v));
+unary_op!(Sqrt, "sqrt", v, v.sqrt(), vs_sqrt, vd_sqrt);
+
+// Unary ops on real numbers, `$ie` is used for the integer dtypes and the half precision floats
+// are evaluated in f32. An optional vectorized f32 function can be provided.
+macro_rules! real_unary_op {
+    ($op:ident, $name:literal, $a:ident, float: $fe:expr, int: $ie:expr $(, $f32_vec:path)?) => {
         impl UnaryOpT for $op {
             const NAME: &'static str = $name;
             const KERNEL: &'static str = concat!("u", $name);
             const V: Self = $op;
+            fn supports_dtype(dtype: DType) -> bool {
+                dtype.is_float() || dtype.is_int()
+            }
             #[inline(always)]
             fn bf16($a: bf16) -> bf16 {
-                $e
+                bf16::from_f32(Self::f32($a.to_f32()))
             }
             #[inline(always)]
             fn f16($a: f16) -> f16 {
-                $e
+                f16::from_f32(Self::f32($a.to_f32()))
             }
             #[inline(always)]
             fn f32($a: f32) -> f32 {
-                $e
+                $fe
             }
             #[inline(always)]
             fn f64($a: f64) -> f64 {
-                $e
+                $fe
             }
             #[inline(always)]
-            fn u8(_: u8) -> u8 {
-                todo!("no unary function for u8")
+            fn u8($a: u8) -> u8 {
+                $ie
             }
             #[inline(always)]
-            fn u32(_: u32) -> u32 {
-                todo!("no unary function for u32")
+            fn u32($a: u32) -> u32 {
+                $ie
             }
             #[inline(always)]
-            fn i64(_: i64) -> i64 {
-                todo!("no unary function for i64")
+            fn i8($a: i8) -> i8 {
+                $ie
             }
-
-            #[cfg(feature = "mkl")]
-            const F32_VEC: bool = true;
-            #[cfg(feature = "mkl")]
-            const F64_VEC: bool = true;
-            #[cfg(feature = "mkl")]
             #[inline(always)]
-            fn f32_vec(xs: &[f32], ys: &mut [f32]) {
-                crate::mkl::$f32_vec(xs, ys)
+            fn i16($a: i16) -> i16 {
+                $ie
             }
-            #[cfg(feature = "mkl")]
             #[inline(always)]
-            fn f64_vec(xs: &[f64], ys: &mut [f64]) {
-                crate::mkl::$f64_vec(xs, ys)
+            fn i32($a: i32) -> i32 {
+                $ie
             }
-
-            #[cfg(feature = "accelerate")]
-            const F32_VEC: bool = true;
-            #[cfg(feature = "accelerate")]
-            const F64_VEC: bool = true;
-            #[cfg(feature = "accelerate")]
             #[inline(always)]
-            fn f32_vec(xs: &[f32], ys: &mut [f32]) {
-                crate::accelerate::$f32_vec(xs, ys)
+            fn i64($a: i64) -> i64 {
+                $ie
+            }
+            #[inline(always)]
+            fn c64(_: C64) -> C64 {
+                unreachable!(concat!("no ", $name, " function for c64"))
             }
-            #[cfg(feature = "accelerate")]
             #[inline(always)]
-            fn f64_vec(xs: &[f64], ys: &mut [f64]) {
-                crate::accelerate::$f64_vec(xs, ys)
+            fn c128(_: C128) -> C128 {
+                unreachable!(concat!("no ", $name, " function for c128"))
             }
+
+            $(
+                const F32_VEC: bool = true;
+                #[inline(always)]
+                fn f32_vec(xs: &[f32], ys: &mut [f32]) {
+                    $f32_vec(xs, ys)
+                }
+            )?
         }
     };
 }
 
-unary_op!(Exp, "exp", v, v.exp(), vs_exp, vd_exp);
-unary_op!(Log, "log", v, v.ln(), vs_ln, vd_ln);
-unary_op!(Sin, "sin", v, v.sin(), vs_sin, vd_sin);
-unary_op!(Cos, "cos", v, v.cos(), vs_cos, vd_cos);
-unary_op!(Tanh, "tanh", v, v.tanh(), vs_tanh, vd_tanh);
-unary_op!(Abs, "abs", v, v.abs());
-unary_op!(Neg, "neg", v, -v);
-unary_op!(Recip, "recip", v, v.recip());
-unary_op!(Sqr, "sqr", v, v * v, vs_sqr, vd_sqr);
-unary_op!(Sqrt, "sqrt", v, v.sqrt(), vs_sqrt, vd_sqrt);
+real_unary_op!(Floor, "floor", v, float: v.floor(), int: v, crate::cpu::kernels::vec_floor_f32);
+real_unary_op!(Ceil, "ceil", v, float: v.ceil(), int: v, crate::cpu::kernels::vec_ceil_f32);
+// Half-way cases are rounded to the nearest even integer.
+real_unary_op!(
+    Round,
+    "round",
+    v,
+    float: v.round_ties_even(),
+    int: v,
+    crate::cpu::kernels::vec_round_f32
+);
+// The sign of zeros and NaNs is the value itself.
+real_unary_op!(
+    Sign,
+    "sign",
+    v,
+    float: if v.is_nan() || v == 0. { v } else { v.signum() },
+    int: v.cmp(&0) as i8 as _
+);
+
+impl UnaryOpT for Erf {
+    const NAME: &'static str = "erf";
+    const KERNEL: &'static str = "uerf";
+    const V: Self = Erf;
+    fn supports_dtype(dtype: DType) -> bool {
+        dtype.is_float()
+    }
+    #[inline(always)]
+    fn bf16(v: bf16) -> bf16 {
+        bf16::from_f32(libm::erff(v.to_f32()))
+    }
+    #[inline(always)]
+    fn f16(v: f16) -> f16 {
+        f16::from_f32(libm::erff(v.to_f32()))
+    }
+    #[inline(always)]
+    fn f32(v: f32) -> f32 {
+        libm::erff(v)
+    }
+    #[inline(always)]
+    fn f64(v: f64) -> f64 {
+        libm::erf(v)
+    }
+    #[inline(always)]
+    fn u8(_: u8) -> u8 {
+        unreachable!("no erf function for u8")
+    }
+    #[inline(always)]
+    fn u32(_: u32) -> u32 {
+        unreachable!("no erf function for u32")
+    }
+    #[inline(always)]
+    fn i8(_: i8) -> i8 {
+        unreachable!("no erf function for i8")
+    }
+    #[inline(always)]
+    fn i16(_: i16) -> i16 {
+        unreachable!("no erf function for i16")
+    }
+    #[inline(always)]
+    fn i32(_: i32) -> i32 {
+        unreachable!("no erf function for i32")
+    }
+    #[inline(always)]
+    fn i64(_: i64) -> i64 {
+        unreachable!("no erf function for i64")
+    }
+    #[inline(always)]
+    fn c64(_: C64) -> C64 {
+        unreachable!("no erf function for c64")
+    }
+    #[inline(always)]
+    fn c128(_: C128) -> C128 {
+        unreachable!("no erf function for c128")
+    }
+}
 
 /// `gelu` operation
 /// <https://en.wikipedia.org/wiki/Activation_function#Comparison_of_activation_functions>
 impl UnaryOpT for Gelu {
     const NAME: &'static str = "gelu";
     const V: Self = Gelu;
+    fn supports_dtype(dtype: DType) -> bool {
+        !dtype.is_complex()
+    }
     #[inline(always)]
     fn bf16(v: bf16) -> bf16 {
         bf16::from_f32_const(0.5)
@@ -579,9 +1024,29 @@ impl UnaryOpT for Gelu {
         0
     }
     #[inline(always)]
+    fn i8(_: i8) -> i8 {
+        0
+    }
+    #[inline(always)]
+    fn i16(_: i16) -> i16 {
+        0
+    }
+    #[inline(always)]
+    fn i32(_: i32) -> i32 {
+        0
+    }
+    #[inline(always)]
     fn i64(_: i64) -> i64 {
         0
     }
+    #[inline(always)]
+    fn c64(_: C64) -> C64 {
+        unreachable!("no gelu function for c64")
+    }
+    #[inline(always)]
+    fn c128(_: C128) -> C128 {
+        unreachable!("no gelu function for c128")
+    }
     const KERNEL: &'static str = "ugelu";
 
     #[cfg(feature = "mkl")]
@@ -625,6 +1090,9 @@ impl UnaryOpT for Relu {
     const NAME: &'static str = "relu";
     const KERNEL: &'static str = "urelu";
     const V: Self = Relu;
+    fn supports_dtype(dtype: DType) -> bool {
+        !dtype.is_complex()
+    }
     #[inline(always)]
     fn bf16(v: bf16) -> bf16 {
         v.max(bf16::ZERO)
@@ -650,8 +1118,28 @@ impl UnaryOpT for Relu {
         v
     }
     #[inline(always)]
+    fn i8(v: i8) -> i8 {
+        v.max(0)
+    }
+    #[inline(always)]
+    fn i16(v: i16) -> i16 {
+        v.max(0)
+    }
+    #[inline(always)]
+    fn i32(v: i32) -> i32 {
+        v.max(0)
+    }
+    #[inline(always)]
     fn i64(v: i64) -> i64 {
-        v
+        v.max(0)
+    }
+    #[inline(always)]
+    fn c64(_: C64) -> C64 {
+        unreachable!("no relu function for c64")
+    }
+    #[inline(always)]
+    fn c128(_: C128) -> C128 {
+        unreachable!("no relu function for c128")
     }
 }
 
@@ -2,13 +2,19 @@
 //!
 //! Spec: https://github.com/philpax/ggml/blob/gguf-spec/docs/gguf.md
 
-use super::{GgmlDType, QTensor};
-use crate::Result;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use super::{k_quants, GgmlDType, GgmlType, QTensor, QuantizedType};
+use crate::{Error, Result};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
 
 pub const DEFAULT_ALIGNMENT: u64 = 32;
 
+// The metadata key used to specify a custom alignment for the tensor data.
+const ALIGNMENT_KEY: &str = "general.alignment";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Magic {
     Gguf,
@@ -29,19 +35,44 @@ impl TryFrom<u32> for Magic {
 pub enum VersionedMagic {
     GgufV1,
     GgufV2,
+    GgufV3,
+}
+
+/// The byte order used for the values of a GGUF file, including its tensor data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
 }
 
 impl VersionedMagic {
-    fn read<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+    fn read<R: std::io::Read>(reader: &mut R) -> Result<(Self, Endianness)> {
+        // The magic is always stored as little endian, the byte order of the version is the one
+        // used by the rest of the file. Big endian files are detected by their version having
+        // its lower bytes set to zero.
         let magic = reader.read_u32::<LittleEndian>()?;
         let magic = Magic::try_from(magic)?;
         let version = reader.read_u32::<LittleEndian>()?;
-        let versioned_magic = match (magic, version) {
-            (Magic::Gguf, 1) => Self::GgufV1,
-            (Magic::Gguf, 2) => Self::GgufV2,
-            _ => crate::bail!("ggml: unsupported magic/version {magic:?}/{version}"),
+        let (version, endianness) = if version & 0xffff == 0 {
+            (version.swap_bytes(), Endianness::Big)
+        } else {
+            (version, Endianness::Little)
+        };
+        let versioned_magic = match (magic, version, endianness) {
+            (Magic::Gguf, 1, Endianness::Little) => Self::GgufV1,
+            (Magic::Gguf, 2, _) => Self::GgufV2,
+            (Magic::Gguf, 3, _) => Self::GgufV3,
+            _ => crate::bail!("ggml: unsupported magic/version {magic:?}/{version} {endianness:?}"),
+        };
+        Ok((versioned_magic, endianness))
+    }
+
+    fn read_len<B: ByteOrder, R: std::io::Read>(&self, reader: &mut R) -> Result<usize> {
+        let len = match self {
+            Self::GgufV1 => reader.read_u32::<B>()? as usize,
+            Self::GgufV2 | Self::GgufV3 => reader.read_u64::<B>()? as usize,
         };
-        Ok(versioned_magic)
+        Ok(len)
     }
 }
 
@@ -53,34 +84,88 @@ pub struct TensorInfo {
 }
 
 impl TensorInfo {
+    fn size_in_bytes(&self) -> usize {
+        self.shape.elem_count() / self.ggml_dtype.blck_size() * self.ggml_dtype.type_size()
+    }
+
+    fn read_raw<R: std::io::Seek + std::io::Read>(
+        &self,
+        reader: &mut R,
+        tensor_data_offset: u64,
+    ) -> Result<Vec<u8>> {
+        let offset = match tensor_data_offset.checked_add(self.offset) {
+            Some(offset) => offset,
+            None => crate::bail!("tensor data offset {} is too large", self.offset),
+        };
+        let mut raw_data = vec![0u8; self.size_in_bytes()];
+        reader.seek(std::io::SeekFrom::Start(offset))?;
+        reader.read_exact(&mut raw_data)?;
+        Ok(raw_data)
+    }
+
+    /// Reads the tensor data, assuming that it is stored as little endian.
     pub fn read<R: std::io::Seek + std::io::Read>(
         &self,
         reader: &mut R,
         tensor_data_offset: u64,
     ) -> Result<QTensor> {
-        let tensor_elems = self.shape.elem_count();
-        let size_in_bytes =
-            tensor_elems * self.ggml_dtype.type_size() / self.ggml_dtype.blck_size();
-        let mut raw_data = vec![0u8; size_in_bytes];
-        reader.seek(std::io::SeekFrom::Start(tensor_data_offset + self.offset))?;
-        reader.read_exact(&mut raw_data)?;
+        let raw_data = self.read_raw(reader, tensor_data_offset)?;
         super::ggml_file::qtensor_from_ggml(self.ggml_dtype, &raw_data, self.shape.dims().to_vec())
     }
 }
 
+// The offset and size of each multi-byte value within a block, these are the values that have to
+// be byte swapped for big endian data.
+fn block_values(dtype: GgmlDType) -> Vec<(usize, usize)> {
+    use k_quants::*;
+    use std::mem::offset_of;
+    match dtype {
+        GgmlDType::F32 => vec![(0, 4)],
+        GgmlDType::F16 | GgmlDType::Q4_0 | GgmlDType::Q5_0 | GgmlDType::Q8_0 => vec![(0, 2)],
+        GgmlDType::Q4_1 | GgmlDType::Q5_1 | GgmlDType::Q8_1 | GgmlDType::Q4K | GgmlDType::Q5K => {
+            vec![(0, 2), (2, 2)]
+        }
+        GgmlDType::Q2K => vec![
+            (offset_of!(BlockQ2K, d), 2),
+            (offset_of!(BlockQ2K, dmin), 2),
+        ],
+        GgmlDType::Q3K => vec![(offset_of!(BlockQ3K, d), 2)],
+        GgmlDType::Q6K => vec![(offset_of!(BlockQ6K, d), 2)],
+        GgmlDType::Q8K => {
+            let bsums = offset_of!(BlockQ8K, bsums);
+            let mut values = vec![(offset_of!(BlockQ8K, d), 4)];
+            values.extend((0..QK_K / 16).map(|i| (bsums + 2 * i, 2)));
+            values
+        }
+    }
+}
+
+// Converts big endian tensor data in place to little endian.
+fn swap_bytes(dtype: GgmlDType, data: &mut [u8]) {
+    let values = block_values(dtype);
+    for block in data.chunks_exact_mut(dtype.type_size()) {
+        for &(offset, size) in values.iter() {
+            block[offset..offset + size].reverse()
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Content {
     pub magic: VersionedMagic,
+    pub endianness: Endianness,
     pub metadata: HashMap<String, Value>,
     pub tensor_infos: HashMap<String, TensorInfo>,
     pub tensor_data_offset: u64,
+    /// The alignment of the tensor data, as specified by the `general.alignment` metadata.
+    pub alignment: u64,
 }
 
-fn read_string<R: std::io::Read>(reader: &mut R, magic: &VersionedMagic) -> Result<String> {
-    let len = match magic {
-        VersionedMagic::GgufV1 => reader.read_u32::<LittleEndian>()? as usize,
-        VersionedMagic::GgufV2 => reader.read_u64::<LittleEndian>()? as usize,
-    };
+fn read_string<B: ByteOrder, R: std::io::Read>(
+    reader: &mut R,
+    magic: &VersionedMagic,
+) -> Result<String> {
+    let len = magic.read_len::<B, _>(reader)?;
     let mut v = vec![0u8; len];
     reader.read_exact(&mut v)?;
     // GGUF strings are supposed to be non-null terminated but in practice this happens.
@@ -252,7 +337,7 @@ impl Value {
         }
     }
 
-    fn read<R: std::io::Read>(
+    fn read<B: ByteOrder, R: std::io::Read>(
         reader: &mut R,
         value_type: ValueType,
         magic: &VersionedMagic,
@@ -260,30 +345,27 @@ impl Value {
         let v = match value_type {
             ValueType::U8 => Self::U8(reader.read_u8()?),
             ValueType::I8 => Self::I8(reader.read_i8()?),
-            ValueType::U16 => Self::U16(reader.read_u16::<LittleEndian>()?),
-            ValueType::I16 => Self::I16(reader.read_i16::<LittleEndian>()?),
-            ValueType::U32 => Self::U32(reader.read_u32::<LittleEndian>()?),
-            ValueType::I32 => Self::I32(reader.read_i32::<LittleEndian>()?),
-            ValueType::U64 => Self::U64(reader.read_u64::<LittleEndian>()?),
-            ValueType::I64 => Self::I64(reader.read_i64::<LittleEndian>()?),
-            ValueType::F32 => Self::F32(reader.read_f32::<LittleEndian>()?),
-            ValueType::F64 => Self::F64(reader.read_f64::<LittleEndian>()?),
+            ValueType::U16 => Self::U16(reader.read_u16::<B>()?),
+            ValueType::I16 => Self::I16(reader.read_i16::<B>()?),
+            ValueType::U32 => Self::U32(reader.read_u32::<B>()?),
+            ValueType::I32 => Self::I32(reader.read_i32::<B>()?),
+            ValueType::U64 => Self::U64(reader.read_u64::<B>()?),
+            ValueType::I64 => Self::I64(reader.read_i64::<B>()?),
+            ValueType::F32 => Self::F32(reader.read_f32::<B>()?),
+            ValueType::F64 => Self::F64(reader.read_f64::<B>()?),
             ValueType::Bool => match reader.read_u8()? {
                 0 => Self::Bool(false),
                 1 => Self::Bool(true),
                 b => crate::bail!("unexpected bool value {b}"),
             },
-            ValueType::String => Self::String(read_string(reader, magic)?),
+            ValueType::String => Self::String(read_string::<B, _>(reader, magic)?),
             ValueType::Array => {
-                let value_type = reader.read_u32::<LittleEndian>()?;
+                let value_type = reader.read_u32::<B>()?;
                 let value_type = ValueType::from_u32(value_type)?;
-                let len = match magic {
-                    VersionedMagic::GgufV1 => reader.read_u32::<LittleEndian>()? as usize,
-                    VersionedMagic::GgufV2 => reader.read_u64::<LittleEndian>()? as usize,
-                };
+                let len = magic.read_len::<B, _>(reader)?;
                 let mut vs = Vec::with_capacity(len);
                 for _ in 0..len {
-                    vs.push(Value::read(reader, value_type, magic)?)
+                    vs.push(Value::read::<B, _>(reader, value_type, magic)?)
                 }
                 Self::Array(vs)
             }
@@ -370,49 +452,77 @@ impl ValueType {
     }
 }
 
+// Returns the alignment specified by the `general.alignment` metadata, or the default one.
+fn alignment(value: Option<&Value>) -> Result<u64> {
+    let alignment = match value {
+        None => return Ok(DEFAULT_ALIGNMENT),
+        Some(Value::U8(v)) => *v as u64,
+        Some(Value::U16(v)) => *v as u64,
+        Some(Value::U32(v)) => *v as u64,
+        Some(Value::U64(v)) => *v,
+        Some(Value::I8(v)) if *v >= 0 => *v as u64,
+        Some(Value::I16(v)) if *v >= 0 => *v as u64,
+        Some(Value::I32(v)) if *v >= 0 => *v as u64,
+        Some(Value::I64(v)) if *v >= 0 => *v as u64,
+        Some(v) => crate::bail!("unexpected value for {ALIGNMENT_KEY}: {v:?}"),
+    };
+    if !alignment.is_power_of_two() {
+        crate::bail!("{ALIGNMENT_KEY} has to be a power of two, got {alignment}")
+    }
+    Ok(alignment)
+}
+
 impl Content {
     pub fn read<R: std::io::Seek + std::io::Read>(reader: &mut R) -> Result<Self> {
-        let magic = VersionedMagic::read(reader)?;
+        let (magic, endianness) = VersionedMagic::read(reader)?;
+        match endianness {
+            Endianness::Little => Self::read_impl::<LittleEndian, _>(reader, magic, endianness),
+            Endianness::Big => Self::read_impl::<BigEndian, _>(reader, magic, endianness),
+        }
+    }
 
-        let tensor_count = match magic {
-            VersionedMagic::GgufV1 => reader.read_u32::<LittleEndian>()? as usize,
-            VersionedMagic::GgufV2 => reader.read_u64::<LittleEndian>()? as usize,
-        };
-        let metadata_kv_count = match magic {
-            VersionedMagic::GgufV1 => reader.read_u32::<LittleEndian>()? as usize,
-            VersionedMagic::GgufV2 => reader.read_u64::<LittleEndian>()? as usize,
-        };
+    fn read_impl<B: ByteOrder, R: std::io::Seek + std::io::Read>(
+        reader: &mut R,
+        magic: VersionedMagic,
+        endianness: Endianness,
+    ) -> Result<Self> {
+        let tensor_count = magic.read_len::<B, _>(reader)?;
+        let metadata_kv_count = magic.read_len::<B, _>(reader)?;
 
         let mut metadata = HashMap::new();
         for _idx in 0..metadata_kv_count {
-            let key = read_string(reader, &magic)?;
-            let value_type = reader.read_u32::<LittleEndian>()?;
+            let key = read_string::<B, _>(reader, &magic)?;
+            let value_type = reader.read_u32::<B>()?;
             let value_type = ValueType::from_u32(value_type)?;
-            let value = Value::read(reader, value_type, &magic)?;
+            let value = Value::read::<B, _>(reader, value_type, &magic)?;
             metadata.insert(key, value);
         }
+        let alignment = alignment(metadata.get(ALIGNMENT_KEY))?;
         let mut tensor_infos = HashMap::new();
         for _idx in 0..tensor_count {
-            let tensor_name = read_string(reader, &magic)?;
-            let n_dimensions = reader.read_u32::<LittleEndian>()?;
+            let tensor_name = read_string::<B, _>(reader, &magic)?;
+            let n_dimensions = reader.read_u32::<B>()?;
 
             let mut dimensions: Vec<usize> = match magic {
                 VersionedMagic::GgufV1 => {
                     let mut dimensions = vec![0; n_dimensions as usize];
-                    reader.read_u32_into::<LittleEndian>(&mut dimensions)?;
+                    reader.read_u32_into::<B>(&mut dimensions)?;
                     dimensions.into_iter().map(|c| c as usize).collect()
                 }
-                VersionedMagic::GgufV2 => {
+                VersionedMagic::GgufV2 | VersionedMagic::GgufV3 => {
                     let mut dimensions = vec![0; n_dimensions as usize];
-                    reader.read_u64_into::<LittleEndian>(&mut dimensions)?;
+                    reader.read_u64_into::<B>(&mut dimensions)?;
                     dimensions.into_iter().map(|c| c as usize).collect()
                 }
             };
 
             dimensions.reverse();
-            let ggml_dtype = reader.read_u32::<LittleEndian>()?;
+            let ggml_dtype = reader.read_u32::<B>()?;
             let ggml_dtype = GgmlDType::from_u32(ggml_dtype)?;
-            let offset = reader.read_u64::<LittleEndian>()?;
+            let offset = reader.read_u64::<B>()?;
+            if offset % alignment != 0 {
+                crate::bail!("tensor {tensor_name} offset {offset} is not aligned to {alignment}")
+            }
             tensor_infos.insert(
                 tensor_name,
                 TensorInfo {
@@ -423,21 +533,14 @@ impl Content {
             );
         }
         let position = reader.stream_position()?;
-        let alignment = match metadata.get("general.alignment") {
-            Some(Value::U8(v)) => *v as u64,
-            Some(Value::U16(v)) => *v as u64,
-            Some(Value::U32(v)) => *v as u64,
-            Some(Value::I8(v)) if *v >= 0 => *v as u64,
-            Some(Value::I16(v)) if *v >= 0 => *v as u64,
-            Some(Value::I32(v)) if *v >= 0 => *v as u64,
-            _ => DEFAULT_ALIGNMENT,
-        };
-        let tensor_data_offset = (position + alignment - 1) / alignment * alignment;
+        let tensor_data_offset = position.div_ceil(alignment) * alignment;
         Ok(Self {
             magic,
+            endianness,
             metadata,
             tensor_infos,
             tensor_data_offset,
+            alignment,
         })
     }
 
@@ -450,7 +553,212 @@ impl Content {
             Some(tensor_info) => tensor_info,
             None => crate::bail!("cannot find tensor-infor for {name}"),
         };
-        tensor_info.read(reader, self.tensor_data_offset)
+        let mut raw_data = tensor_info.read_raw(reader, self.tensor_data_offset)?;
+        if self.endianness == Endianness::Big {
+            swap_bytes(tensor_info.ggml_dtype, &mut raw_data)
+        }
+        let dims = tensor_info.shape.dims().to_vec();
+        super::ggml_file::qtensor_from_ggml(tensor_info.ggml_dtype, &raw_data, dims)
+    }
+}
+
+// Blocks that are read directly from a memory mapped file.
+struct MmapedBlocks<T> {
+    mmap: Arc<memmap2::Mmap>,
+    offset: usize,
+    n_blocks: usize,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: GgmlType> MmapedBlocks<T> {
+    fn new(mmap: Arc<memmap2::Mmap>, offset: usize, size_in_bytes: usize) -> Result<Self> {
+        match offset.checked_add(size_in_bytes) {
+            Some(end) if end <= mmap.len() => {}
+            _ => crate::bail!(
+                "tensor data at {offset} with {size_in_bytes} bytes is out of the file bounds {}",
+                mmap.len()
+            ),
+        }
+        let ptr = mmap[offset..].as_ptr();
+        if !(ptr as *const T).is_aligned() {
+            crate::bail!("tensor data at {offset} is not aligned for {:?}", T::DTYPE)
+        }
+        Ok(Self {
+            mmap,
+            offset,
+            n_blocks: size_in_bytes / std::mem::size_of::<T>(),
+            _phantom: std::marker::PhantomData,
+        })
+    }
+
+    fn as_slice(&self) -> &[T] {
+        // Safety: the bounds and alignment have been checked on creation and the blocks only
+        // contain immediate values, for which any bit pattern is valid.
+        let ptr = self.mmap[self.offset..].as_ptr() as *const T;
+        unsafe { std::slice::from_raw_parts(ptr, self.n_blocks) }
+    }
+}
+
+impl<T: GgmlType> QuantizedType for MmapedBlocks<T> {
+    fn dtype(&self) -> GgmlDType {
+        T::DTYPE
+    }
+
+    fn matmul_t(&self, mkn: (usize, usize, usize), lhs: &[f32], dst: &mut [f32]) -> Result<()> {
+        k_quants::matmul(mkn, lhs, self.as_slice(), dst)
+    }
+
+    fn to_float(&self, ys: &mut [f32]) -> Result<()> {
+        T::to_float(self.as_slice(), ys)
+    }
+
+    fn storage_size_in_bytes(&self) -> usize {
+        self.n_blocks * std::mem::size_of::<T>()
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.as_slice().as_ptr() as *const u8
+    }
+}
+
+fn mmaped_data(
+    mmap: &Arc<memmap2::Mmap>,
+    offset: usize,
+    ggml_dtype: GgmlDType,
+    elem_count: usize,
+    endianness: Endianness,
+) -> Result<Box<dyn QuantizedType>> {
+    fn boxed<T: GgmlType + 'static>(
+        mmap: &Arc<memmap2::Mmap>,
+        offset: usize,
+        size_in_bytes: usize,
+    ) -> Result<Box<dyn QuantizedType>> {
+        let blocks = MmapedBlocks::<T>::new(mmap.clone(), offset, size_in_bytes)?;
+        Ok(Box::new(blocks))
+    }
+    let size_in_bytes = elem_count / ggml_dtype.blck_size() * ggml_dtype.type_size();
+    if endianness == Endianness::Big {
+        // Big endian data cannot be used in place, it gets converted to an in-memory copy.
+        let raw_data = offset
+            .checked_add(size_in_bytes)
+            .and_then(|end| mmap.get(offset..end));
+        let mut raw_data = match raw_data {
+            Some(raw_data) => raw_data.to_vec(),
+            None => crate::bail!(
+                "tensor data at {offset} with {size_in_bytes} bytes is out of the file bounds {}",
+                mmap.len()
+            ),
+        };
+        swap_bytes(ggml_dtype, &mut raw_data);
+        let qtensor = super::ggml_file::qtensor_from_ggml(ggml_dtype, &raw_data, vec![elem_count])?;
+        return Ok(qtensor.data);
+    }
+    match ggml_dtype {
+        GgmlDType::F32 => boxed::<f32>(mmap, offset, size_in_bytes),
+        GgmlDType::F16 => boxed::<half::f16>(mmap, offset, size_in_bytes),
+        GgmlDType::Q4_0 => boxed::<k_quants::BlockQ4_0>(mmap, offset, size_in_bytes),
+        GgmlDType::Q4_1 => boxed::<k_quants::BlockQ4_1>(mmap, offset, size_in_bytes),
+        GgmlDType::Q5_0 => boxed::<k_quants::BlockQ5_0>(mmap, offset, size_in_bytes),
+        GgmlDType::Q5_1 => boxed::<k_quants::BlockQ5_1>(mmap, offset, size_in_bytes),
+        GgmlDType::Q8_0 => boxed::<k_quants::BlockQ8_0>(mmap, offset, size_in_bytes),
+        GgmlDType::Q8_1 => boxed::<k_quants::BlockQ8_1>(mmap, offset, size_in_bytes),
+        GgmlDType::Q2K => boxed::<k_quants::BlockQ2K>(mmap, offset, size_in_bytes),
+        GgmlDType::Q3K => boxed::<k_quants::BlockQ3K>(mmap, offset, size_in_bytes),
+        GgmlDType::Q4K => boxed::<k_quants::BlockQ4K>(mmap, offset, size_in_bytes),
+        GgmlDType::Q5K => boxed::<k_quants::BlockQ5K>(mmap, offset, size_in_bytes),
+        GgmlDType::Q6K => boxed::<k_quants::BlockQ6K>(mmap, offset, size_in_bytes),
+        GgmlDType::Q8K => boxed::<k_quants::BlockQ8K>(mmap, offset, size_in_bytes),
+    }
+}
+
+/// A memory mapped GGUF file, the tensors that are retrieved from it directly use the block data
+/// from the mapping rather than copying it. The tensor data has to be aligned for the block
+/// types, which is the case for files using the default alignment of [`DEFAULT_ALIGNMENT`] bytes
+/// such as the ones produced by [`write`]. The data of big endian files gets copied as it has to
+/// be converted.
+pub struct MmapedContent {
+    path: std::path::PathBuf,
+    content: Content,
+    mmap: Arc<memmap2::Mmap>,
+}
+
+impl MmapedContent {
+    /// Memory maps the file at path `p` and reads its header.
+    ///
+    /// # Safety
+    ///
+    /// The unsafe is inherited from [`memmap2::MmapOptions`].
+    pub unsafe fn new<P: AsRef<Path>>(p: P) -> Result<Self> {
+        let p = p.as_ref();
+        let file = std::fs::File::open(p).map_err(|e| Error::from(e).with_path(p))?;
+        let mmap = memmap2::MmapOptions::new()
+            .map(&file)
+            .map_err(|e| Error::from(e).with_path(p))?;
+        let content =
+            Content::read(&mut std::io::Cursor::new(&mmap[..])).map_err(|e| e.with_path(p))?;
+        Ok(Self {
+            path: p.to_path_buf(),
+            content,
+            mmap: Arc::new(mmap),
+        })
+    }
+
+    pub fn content(&self) -> &Content {
+        &self.content
+    }
+
+    fn tensor_info(&self, name: &str) -> Result<(&TensorInfo, usize)> {
+        match self.content.tensor_infos.get(name) {
+            Some(info) => {
+                let offset = self
+                    .content
+                    .tensor_data_offset
+                    .checked_add(info.offset)
+                    .and_then(|offset| usize::try_from(offset).ok());
+                match offset {
+                    Some(offset) => Ok((info, offset)),
+                    None => Err(Error::Msg(format!(
+                        "tensor data offset {} for {name} is too large",
+                        info.offset
+                    ))
+                    .bt()
+                    .with_path(&self.path)),
+                }
+            }
+            None => Err(Error::Msg(format!("cannot find tensor-info for {name}"))
+                .bt()
+                .with_path(&self.path)),
+        }
+    }
+
+    /// Returns the tensor `name`, its data is borrowed from the memory mapped file.
+    pub fn tensor(&self, name: &str) -> Result<QTensor> {
+        let (info, offset) = self.tensor_info(name)?;
+        let endianness = self.content.endianness;
+        let elem_count = info.shape.elem_count();
+        let data = mmaped_data(&self.mmap, offset, info.ggml_dtype, elem_count, endianness)?;
+        QTensor::from_data(data, info.shape.clone())
+    }
+
+    /// Returns the tensor `name` without touching its data, the data only gets paged in from the
+    /// memory mapped file when first used, e.g. on the first [`super::QMatMul::forward`].
+    pub fn lazy_tensor(&self, name: &str) -> Result<QTensor> {
+        let (info, offset) = self.tensor_info(name)?;
+        let mmap = self.mmap.clone();
+        let ggml_dtype = info.ggml_dtype;
+        let elem_count = info.shape.elem_count();
+        let endianness = self.content.endianness;
+        QTensor::lazy(info.shape.clone(), ggml_dtype, move || {
+            let data = mmaped_data(&mmap, offset, ggml_dtype, elem_count, endianness)?;
+            // Read the whole tensor ahead rather than faulting the pages in one at a time.
+            #[cfg(unix)]
+            mmap.advise_range(
+                memmap2::Advice::WillNeed,
+                offset,
+                data.storage_size_in_bytes(),
+            )?;
+            Ok(data)
+        })
     }
 }
 
@@ -461,53 +769,188 @@ fn write_string<W: std::io::Write>(w: &mut W, str: &str) -> Result<()> {
     Ok(())
 }
 
+/// Writes a GGUF file with the given metadata and tensors, see [`GgufWriter`] to write the
+/// tensors without having all of them in memory at once.
 pub fn write<W: std::io::Seek + std::io::Write>(
     w: &mut W,
     metadata: &[(&str, &Value)],
     tensors: &[(&str, &QTensor)],
 ) -> Result<()> {
-    w.write_u32::<LittleEndian>(0x46554747)?;
-    w.write_u32::<LittleEndian>(2)?; // version 2.
-    w.write_u64::<LittleEndian>(tensors.len() as u64)?;
-    w.write_u64::<LittleEndian>(metadata.len() as u64)?;
-    for (name, value) in metadata.iter() {
-        write_string(w, name)?;
-        w.write_u32::<LittleEndian>(value.value_type().to_u32())?;
-        value.write(w)?;
-    }
-    let mut offset = 0usize;
-    let mut offsets = Vec::with_capacity(tensors.len());
+    let mut writer = GgufWriter::new(w);
+    for (key, value) in metadata.iter() {
+        writer.add_metadata(key, (*value).clone())
+    }
     for (name, tensor) in tensors.iter() {
-        write_string(w, name)?;
-        let dims = tensor.shape().dims();
-        w.write_u32::<LittleEndian>(dims.len() as u32)?;
-        for &dim in dims.iter().rev() {
-            w.write_u64::<LittleEndian>(dim as u64)?;
-        }
-        w.write_u32::<LittleEndian>(tensor.dtype().to_u32())?;
-        w.write_u64::<LittleEndian>(offset as u64)?;
-        offsets.push(offset);
-        let size_in_bytes = tensor.storage_size_in_bytes();
-        let padding = 31 - (31 + size_in_bytes) % 32;
-        offset += size_in_bytes + padding;
-    }
-    let pos = w.stream_position()? as usize;
-    let padding = 31 - (31 + pos) % 32;
-    w.write_all(&vec![0u8; padding])?;
-    let tensor_start_pos = w.stream_position()? as usize;
-    for (offset, (_name, tensor)) in offsets.iter().zip(tensors.iter()) {
-        let pos = w.stream_position()? as usize;
-        if tensor_start_pos + offset != pos {
-            crate::bail!(
-                "internal error, unexpected current position {tensor_start_pos} {offset} {pos}"
-            )
-        }
-        let data_ptr = tensor.as_ptr();
-        let size_in_bytes = tensor.storage_size_in_bytes();
-        let data = unsafe { std::slice::from_raw_parts(data_ptr, size_in_bytes) };
-        w.write_all(data)?;
-        let padding = 31 - (31 + size_in_bytes) % 32;
-        w.write_all(&vec![0u8; padding])?;
+        writer.add_tensor_info(name, tensor.dtype(), tensor.shape().clone())?
     }
+    writer.write_tensors(tensors.iter().map(|(_, tensor)| Ok(*tensor)))?;
     Ok(())
 }
+
+// Keeps track of the number of bytes written so far.
+struct PosWriter<W> {
+    w: W,
+    pos: u64,
+}
+
+impl<W: std::io::Write> std::io::Write for PosWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.w.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W: std::io::Write> PosWriter<W> {
+    fn pad(&mut self, alignment: u64) -> Result<()> {
+        let padding = (alignment - self.pos % alignment) % alignment;
+        self.write_all(&vec![0u8; padding as usize])?;
+        Ok(())
+    }
+}
+
+/// A streaming writer for GGUF v3 files. The metadata and the dtype and shape of all the tensors
+/// have to be specified first, the tensor data is then written one tensor at a time so that only a
+/// single tensor has to be in memory.
+///
+/// The tensor data is aligned on [`DEFAULT_ALIGNMENT`] bytes unless a custom alignment is set via
+/// the `general.alignment` metadata.
+///
+/// ```rust
+/// use candle_core::quantized::{gguf_file, k_quants, GgmlDType, QTensor};
+/// use candle_core::{Device, Tensor};
+/// let mut writer = gguf_file::GgufWriter::new(std::io::Cursor::new(vec![]));
+/// writer.add_metadata("general.alignment", gguf_file::Value::U32(64));
+/// for name in ["a", "b"] {
+///     writer.add_tensor_info(name, GgmlDType::Q8_0, (4, 32))?;
+/// }
+/// let tensors = ["a", "b"].into_iter().map(|_| {
+///     let t = Tensor::ones((4, 32), candle_core::DType::F32, &Device::Cpu)?;
+///     QTensor::quantize::<k_quants::BlockQ8_0>(&t)
+/// });
+/// let mut file = writer.write_tensors(tensors)?;
+/// file.set_position(0);
+/// let content = gguf_file::Content::read(&mut file)?;
+/// assert_eq!(content.alignment, 64);
+/// assert_eq!(content.tensor_infos["b"].offset, 192);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+pub struct GgufWriter<W: std::io::Write> {
+    w: W,
+    metadata: Vec<(String, Value)>,
+    tensor_infos: Vec<(String, GgmlDType, crate::Shape)>,
+}
+
+impl<W: std::io::Write> GgufWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            w,
+            metadata: vec![],
+            tensor_infos: vec![],
+        }
+    }
+
+    /// Adds a metadata value, this replaces the existing value if the key is already present.
+    pub fn add_metadata(&mut self, key: &str, value: Value) {
+        match self.metadata.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.metadata.push((key.to_string(), value)),
+        }
+    }
+
+    /// Declares the next tensor to be written.
+    pub fn add_tensor_info<S: Into<crate::Shape>>(
+        &mut self,
+        name: &str,
+        dtype: GgmlDType,
+        shape: S,
+    ) -> Result<()> {
+        let shape = shape.into();
+        super::check_dtype_shape(dtype, &shape)?;
+        if self.tensor_infos.iter().any(|(n, _, _)| n == name) {
+            crate::bail!("duplicate tensor name {name}")
+        }
+        self.tensor_infos.push((name.to_string(), dtype, shape));
+        Ok(())
+    }
+
+    /// Writes the file header followed by the tensors. These are consumed one at a time from the
+    /// iterator and have to be in the same order as the tensor infos and to match their dtypes
+    /// and shapes. Returns the underlying writer.
+    pub fn write_tensors<I, T>(self, tensors: I) -> Result<W>
+    where
+        I: IntoIterator<Item = Result<T>>,
+        T: std::borrow::Borrow<QTensor>,
+    {
+        let alignment = alignment(
+            self.metadata
+                .iter()
+                .find(|(k, _)| k == ALIGNMENT_KEY)
+                .map(|(_, v)| v),
+        )?;
+        let mut w = PosWriter { w: self.w, pos: 0 };
+        w.write_u32::<LittleEndian>(0x46554747)?;
+        w.write_u32::<LittleEndian>(3)?; // version 3.
+        w.write_u64::<LittleEndian>(self.tensor_infos.len() as u64)?;
+        w.write_u64::<LittleEndian>(self.metadata.len() as u64)?;
+        for (key, value) in self.metadata.iter() {
+            write_string(&mut w, key)?;
+            w.write_u32::<LittleEndian>(value.value_type().to_u32())?;
+            value.write(&mut w)?;
+        }
+        let mut offset = 0u64;
+        let mut offsets = Vec::with_capacity(self.tensor_infos.len());
+        for (name, dtype, shape) in self.tensor_infos.iter() {
+            write_string(&mut w, name)?;
+            let dims = shape.dims();
+            w.write_u32::<LittleEndian>(dims.len() as u32)?;
+            for &dim in dims.iter().rev() {
+                w.write_u64::<LittleEndian>(dim as u64)?;
+            }
+            w.write_u32::<LittleEndian>(dtype.to_u32())?;
+            w.write_u64::<LittleEndian>(offset)?;
+            offsets.push(offset);
+            let size_in_bytes = (shape.elem_count() / dtype.blck_size() * dtype.type_size()) as u64;
+            offset += size_in_bytes.div_ceil(alignment) * alignment;
+        }
+        w.pad(alignment)?;
+        let tensor_start_pos = w.pos;
+
+        let mut tensors = tensors.into_iter();
+        for (idx, (name, dtype, shape)) in self.tensor_infos.iter().enumerate() {
+            let tensor = match tensors.next() {
+                Some(tensor) => tensor?,
+                None => crate::bail!("missing tensor data for {name}, got {idx} tensors"),
+            };
+            let tensor = tensor.borrow();
+            if tensor.dtype() != *dtype || tensor.shape() != shape {
+                crate::bail!(
+                    "tensor {name} is expected to be {dtype:?} {shape:?}, got {:?} {:?}",
+                    tensor.dtype(),
+                    tensor.shape()
+                )
+            }
+            if tensor_start_pos + offsets[idx] != w.pos {
+                crate::bail!(
+                    "internal error, unexpected current position {tensor_start_pos} {} {}",
+                    offsets[idx],
+                    w.pos
+                )
+            }
+            tensor.load()?;
+            let data_ptr = tensor.as_ptr();
+            let size_in_bytes = tensor.storage_size_in_bytes();
+            let data = unsafe { std::slice::from_raw_parts(data_ptr, size_in_bytes) };
+            w.write_all(data)?;
+            w.pad(alignment)?;
+        }
+        if tensors.next().is_some() {
+            crate::bail!("more tensors than tensor infos {}", self.tensor_infos.len())
+        }
+        Ok(w.w)
+    }
+}
@@ -0,0 +1,249 @@
+//! Support for the 4-bit GPTQ and AWQ checkpoints.
+//!
+//! These checkpoints store each quantized linear layer as a set of tensors: `qweight` with the
+//! 4-bit values packed in `i32`, `qzeros` with the packed zero points and `scales`, both having
+//! one row per group of input features, and for GPTQ an optional `g_idx` that maps each input
+//! feature to its group. [`PackedWeight`] converts these to a [`QTensor`] that can be used with
+//! [`super::QMatMul`], or to a dense tensor.
+//!
+//! When the groups are contiguous ranges of input features with a size that is a multiple of 32,
+//! which is the case unless GPTQ was run with activation reordering, the 4-bit values are repacked
+//! as is in the `Q4_1` format. This is close to but not exactly the original weight as the `Q4_1`
+//! blocks store the scale and the offset `-scale * zero` as `f16`, so the offset gets rounded.
+//! Otherwise the weights are requantized using `Q8_0`.
+use super::{k_quants, QTensor};
+use crate::{DType, Device, Result, Tensor};
+use std::collections::HashMap;
+
+// The 4-bit values of AWQ are interleaved in each i32, this maps the column index within a
+// packed value to the position of its nibble.
+const AWQ_REVERSE_ORDER: [usize; 8] = [0, 4, 1, 5, 2, 6, 3, 7];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantMethod {
+    /// GPTQ checkpoints, the zero points are stored with an offset of one.
+    Gptq,
+    /// GPTQ checkpoints using the v2 format where the zero points are stored as is.
+    GptqV2,
+    Awq,
+}
+
+/// The packed tensors of a GPTQ or AWQ quantized linear layer.
+#[derive(Debug, Clone)]
+pub struct PackedWeight {
+    pub qweight: Tensor,
+    pub qzeros: Tensor,
+    pub scales: Tensor,
+    pub g_idx: Option<Tensor>,
+}
+
+// The unpacked values, the weight has shape (out_dim, in_dim) and the zero points and scales have
+// shape (n_groups, out_dim).
+struct Unpacked {
+    out_dim: usize,
+    in_dim: usize,
+    q: Vec<u8>,
+    zeros: Vec<f32>,
+    scales: Vec<f32>,
+    groups: Vec<usize>,
+}
+
+impl Unpacked {
+    fn value(&self, row: usize, col: usize) -> f32 {
+        let g = self.groups[col] * self.out_dim + row;
+        self.scales[g] * (self.q[row * self.in_dim + col] as f32 - self.zeros[g])
+    }
+
+    fn dequantize(&self) -> Vec<f32> {
+        let mut dst = Vec::with_capacity(self.out_dim * self.in_dim);
+        for row in 0..self.out_dim {
+            dst.extend((0..self.in_dim).map(|col| self.value(row, col)))
+        }
+        dst
+    }
+
+    // Returns true if each block of input features of the Q4_1 format belongs to a single group.
+    fn blocks_have_single_group(&self) -> bool {
+        const QK: usize = k_quants::QK4_1;
+        self.in_dim.is_multiple_of(QK)
+            && self
+                .groups
+                .chunks_exact(QK)
+                .all(|b| b.iter().all(|&g| g == b[0]))
+    }
+
+    fn to_q4_1(&self) -> Vec<k_quants::BlockQ4_1> {
+        const QK: usize = k_quants::QK4_1;
+        let mut blocks = Vec::with_capacity(self.out_dim * self.in_dim / QK);
+        for row in 0..self.out_dim {
+            for start in (0..self.in_dim).step_by(QK) {
+                // w = s * (q - z) = d * q + m with d = s and m = - s * z.
+                let g = self.groups[start] * self.out_dim + row;
+                let (s, z) = (self.scales[g], self.zeros[g]);
+                let q = &self.q[row * self.in_dim + start..row * self.in_dim + start + QK];
+                let mut qs = [0u8; QK / 2];
+                for (j, qs) in qs.iter_mut().enumerate() {
+                    *qs = q[j] | (q[j + QK / 2] << 4)
+                }
+                blocks.push(k_quants::BlockQ4_1 {
+                    d: half::f16::from_f32(s),
+                    m: half::f16::from_f32(-s * z),
+                    qs,
+                })
+            }
+        }
+        blocks
+    }
+}
+
+fn i32_values(t: &Tensor, name: &str) -> Result<Vec<Vec<u32>>> {
+    if t.dtype() != DType::I32 {
+        crate::bail!("{name} is expected to be i32, got {:?}", t.dtype())
+    }
+    let t = t.to_device(&Device::Cpu)?.to_vec2::<i32>()?;
+    Ok(t.into_iter()
+        .map(|r| r.into_iter().map(|v| v as u32).collect())
+        .collect())
+}
+
+impl PackedWeight {
+    /// Retrieves the packed tensors of the layer `prefix`, e.g. `model.layers.0.self_attn.q_proj`,
+    /// from the tensors of a checkpoint.
+    pub fn from_tensors(tensors: &HashMap<String, Tensor>, prefix: &str) -> Result<Self> {
+        let get = |name: &str| {
+            let name = format!("{prefix}.{name}");
+            match tensors.get(&name) {
+                Some(t) => Ok(t.clone()),
+                None => Err(crate::Error::CannotFindTensor { path: name }.bt()),
+            }
+        };
+        Ok(Self {
+            qweight: get("qweight")?,
+            qzeros: get("qzeros")?,
+            scales: get("scales")?,
+            g_idx: tensors.get(&format!("{prefix}.g_idx")).cloned(),
+        })
+    }
+
+    /// The shape `(out_dim, in_dim)` of the weight.
+    pub fn dims(&self, method: QuantMethod) -> Result<(usize, usize)> {
+        let (d0, d1) = self.qweight.dims2()?;
+        match method {
+            QuantMethod::Gptq | QuantMethod::GptqV2 => Ok((d1, d0 * 8)),
+            QuantMethod::Awq => Ok((d1 * 8, d0)),
+        }
+    }
+
+    fn unpack(&self, method: QuantMethod) -> Result<Unpacked> {
+        let (out_dim, in_dim) = self.dims(method)?;
+        let scales = self.scales.to_dtype(DType::F32)?;
+        let (n_groups, scales_out) = scales.dims2()?;
+        if scales_out != out_dim || n_groups == 0 {
+            crate::bail!(
+                "unexpected scales shape {:?} for a weight ({out_dim}, {in_dim})",
+                scales.shape()
+            )
+        }
+        let qzeros_shape = self.qzeros.dims2()?;
+        if !out_dim.is_multiple_of(8) || qzeros_shape != (n_groups, out_dim / 8) {
+            crate::bail!(
+                "unexpected qzeros shape {:?} for {n_groups} groups and {out_dim} outputs",
+                self.qzeros.shape()
+            )
+        }
+        let qweight = i32_values(&self.qweight, "qweight")?;
+        let qzeros = i32_values(&self.qzeros, "qzeros")?;
+        let scales = scales.flatten_all()?.to_vec1::<f32>()?;
+
+        let mut q = vec![0u8; out_dim * in_dim];
+        let mut zeros = vec![0f32; n_groups * out_dim];
+        let groups = match method {
+            QuantMethod::Gptq | QuantMethod::GptqV2 => {
+                // The values are packed along the input features for the weight and along the
+                // output features for the zero points.
+                for (i, qweight) in qweight.iter().enumerate() {
+                    for (row, &v) in qweight.iter().enumerate() {
+                        for j in 0..8 {
+                            q[row * in_dim + i * 8 + j] = ((v >> (4 * j)) & 0xF) as u8
+                        }
+                    }
+                }
+                let offset = if method == QuantMethod::Gptq { 1. } else { 0. };
+                for (g, qzeros) in qzeros.iter().enumerate() {
+                    for (i, &v) in qzeros.iter().enumerate() {
+                        for j in 0..8 {
+                            let z = ((v >> (4 * j)) & 0xF) as f32;
+                            zeros[g * out_dim + i * 8 + j] = z + offset
+                        }
+                    }
+                }
+                match &self.g_idx {
+                    Some(g_idx) => {
+                        let g_idx = g_idx.to_dtype(DType::I64)?.to_vec1::<i64>()?;
+                        if g_idx.len() != in_dim {
+                            crate::bail!("g_idx has {} entries, expected {in_dim}", g_idx.len())
+                        }
+                        if let Some(g) = g_idx.iter().find(|&&g| g < 0 || g >= n_groups as i64) {
+                            crate::bail!("invalid group {g} in g_idx, {n_groups} groups")
+                        }
+                        g_idx.into_iter().map(|g| g as usize).collect()
+                    }
+                    None => sequential_groups(in_dim, n_groups)?,
+                }
+            }
+            QuantMethod::Awq => {
+                // Both the weight and zero points are packed along the output features.
+                for (col, qweight) in qweight.iter().enumerate() {
+                    for (i, &v) in qweight.iter().enumerate() {
+                        for (j, &pos) in AWQ_REVERSE_ORDER.iter().enumerate() {
+                            q[(i * 8 + j) * in_dim + col] = ((v >> (4 * pos)) & 0xF) as u8
+                        }
+                    }
+                }
+                for (g, qzeros) in qzeros.iter().enumerate() {
+                    for (i, &v) in qzeros.iter().enumerate() {
+                        for (j, &pos) in AWQ_REVERSE_ORDER.iter().enumerate() {
+                            zeros[g * out_dim + i * 8 + j] = ((v >> (4 * pos)) & 0xF) as f32
+                        }
+                    }
+                }
+                sequential_groups(in_dim, n_groups)?
+            }
+        };
+        Ok(Unpacked {
+            out_dim,
+            in_dim,
+            q,
+            zeros,
+            scales,
+            groups,
+        })
+    }
+
+    /// Converts the packed weight to a quantized tensor of shape `(out_dim, in_dim)`.
+    pub fn to_qtensor(&self, method: QuantMethod) -> Result<QTensor> {
+        let unpacked = self.unpack(method)?;
+        let shape = (unpacked.out_dim, unpacked.in_dim);
+        if unpacked.blocks_have_single_group() {
+            QTensor::new(unpacked.to_q4_1(), shape)
+        } else {
+            let w = Tensor::from_vec(unpacked.dequantize(), shape, &Device::Cpu)?;
+            QTensor::quantize::<k_quants::BlockQ8_0>(&w)
+        }
+    }
+
+    /// Returns the dense weight with shape `(out_dim, in_dim)`.
+    pub fn dequantize(&self, method: QuantMethod, device: &Device) -> Result<Tensor> {
+        let unpacked = self.unpack(method)?;
+        let shape = (unpacked.out_dim, unpacked.in_dim);
+        Tensor::from_vec(unpacked.dequantize(), shape, device)
+    }
+}
+
+fn sequential_groups(in_dim: usize, n_groups: usize) -> Result<Vec<usize>> {
+    if !in_dim.is_multiple_of(n_groups) {
+        crate::bail!("{in_dim} input features cannot be split in {n_groups} groups")
+    }
+    let group_size = in_dim / n_groups;
+    Ok((0..in_dim).map(|i| i / group_size).collect())
+}
@@ -0,0 +1,157 @@
+//! Importance matrices for calibration aware quantization.
+//!
+//! An importance matrix records, for each linear layer of a model, the mean of the squared
+//! activations that flow into each column of its weight while running the model on some sample
+//! data. The columns that see large activations contribute most to the output of the layer so
+//! the quantization error on these gets minimized first, see
+//! [`super::QTensor::quantize_with_importance`].
+//!
+//! The inputs of a [`super::QMatMul`] are recorded automatically once an importance matrix is
+//! attached to it with [`super::QMatMul::with_imatrix`], the `calibrate` command of the
+//! `tensor-tools` example uses this to compute the importance matrix of a llama model.
+//!
+//! ```rust
+//! use candle_core::quantized::{imatrix::Imatrix, k_quants, QTensor};
+//! use candle_core::{Device, Tensor};
+//! let device = Device::Cpu;
+//! let w = Tensor::randn(0f32, 1., (8, 256), &device)?;
+//! let imatrix = Imatrix::new();
+//! for _ in 0..4 {
+//!     // The input of the linear layer `w`, recorded when running the model on sample data.
+//!     let xs = Tensor::randn(0f32, 1., (2, 5, 256), &device)?;
+//!     imatrix.record("w", &xs)?;
+//!     let _ys = xs.broadcast_matmul(&w.t()?)?;
+//! }
+//! let importance = imatrix.get("w").unwrap();
+//! let qw = QTensor::quantize_with_importance::<k_quants::BlockQ3K>(&w, &importance)?;
+//! assert_eq!(qw.shape().dims(), [8, 256]);
+//! # Ok::<(), candle_core::Error>(())
+//! ```
+use crate::{DType, Device, Result, Tensor};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// The suffix of the entries holding the number of recorded rows in the saved files, the counts are
+// stored as i64 as they can exceed the range of u32 on large calibration sets.
+const COUNT_SUFFIX: &str = ".count";
+
+#[derive(Debug, Clone)]
+struct Entry {
+    sum_x2: Vec<f64>,
+    count: usize,
+}
+
+/// Accumulates the activation statistics of the linear layers of a model. The recording only
+/// requires a shared reference so that it can be done from within the forward pass of a model.
+#[derive(Debug, Default)]
+pub struct Imatrix {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Imatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the activations `xs`, of shape `(.., k)`, that are the input of the linear layer
+    /// the weight of which is named `name` and has `k` columns.
+    pub fn record(&self, name: &str, xs: &Tensor) -> Result<()> {
+        let (count, k) = match xs.dims() {
+            [] => crate::bail!("imatrix: cannot record a scalar for {name}"),
+            dims => (xs.elem_count() / dims[dims.len() - 1], dims[dims.len() - 1]),
+        };
+        let sum_x2 = xs
+            .to_dtype(DType::F32)?
+            .reshape((count, k))?
+            .sqr()?
+            .sum(0)?
+            .to_vec1::<f32>()?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(name.to_string()).or_insert_with(|| Entry {
+            sum_x2: vec![0.; k],
+            count: 0,
+        });
+        if entry.sum_x2.len() != k {
+            crate::bail!(
+                "imatrix: {name} recorded with {} columns, got {:?}",
+                entry.sum_x2.len(),
+                xs.shape()
+            )
+        }
+        for (s, v) in entry.sum_x2.iter_mut().zip(sum_x2.iter()) {
+            *s += *v as f64
+        }
+        entry.count += count;
+        Ok(())
+    }
+
+    /// The importance of each column of `name`, i.e. the mean of the squared activations.
+    pub fn get(&self, name: &str) -> Option<Vec<f32>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(name)?;
+        let count = entry.count.max(1) as f64;
+        Some(entry.sum_x2.iter().map(|v| (v / count) as f32).collect())
+    }
+
+    /// The number of rows that have been recorded for `name`.
+    pub fn count(&self, name: &str) -> usize {
+        let entries = self.entries.lock().unwrap();
+        entries.get(name).map_or(0, |e| e.count)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        let mut names = entries.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Saves the importance values to a safetensors file, together with the number of recorded
+    /// rows so that the statistics can be merged with further recordings after loading.
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let mut tensors = HashMap::new();
+        for name in self.names() {
+            let importance = self.get(&name).unwrap();
+            let count = self.count(&name) as i64;
+            tensors.insert(name.clone(), Tensor::new(importance, &Device::Cpu)?);
+            let count = Tensor::new(&[count], &Device::Cpu)?;
+            tensors.insert(format!("{name}{COUNT_SUFFIX}"), count);
+        }
+        crate::safetensors::save(&tensors, path)
+    }
+
+    /// Loads the importance values saved with [`Imatrix::save`].
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let tensors = crate::safetensors::load(path, &Device::Cpu)?;
+        let mut entries = HashMap::new();
+        for (name, importance) in tensors.iter() {
+            if name.ends_with(COUNT_SUFFIX) {
+                continue;
+            }
+            let count = match tensors.get(&format!("{name}{COUNT_SUFFIX}")) {
+                None => 1,
+                Some(count) => {
+                    let count = count
+                        .to_dtype(DType::I64)?
+                        .flatten_all()?
+                        .to_vec1::<i64>()?;
+                    match count.as_slice() {
+                        [count] if *count >= 0 => *count as usize,
+                        _ => crate::bail!("imatrix: invalid count {count:?} for {name}"),
+                    }
+                }
+            };
+            let sum_x2 = importance
+                .to_dtype(DType::F64)?
+                .flatten_all()?
+                .to_vec1::<f64>()?
+                .into_iter()
+                .map(|v| v * count as f64)
+                .collect();
+            entries.insert(name.to_string(), Entry { sum_x2, count });
+        }
+        Ok(Self {
+            entries: Mutex::new(entries),
+        })
+    }
+}
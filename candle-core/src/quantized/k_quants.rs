@@ -1,6 +1,7 @@
 use super::utils::{
-    get_scale_min_k4, group_for_dequantization, group_for_quantization, make_q3_quants,
-    make_qkx1_quants, make_qx_quants, nearest_int,
+    check_imatrix, get_scale_min_k4, group_for_dequantization, group_for_quantization_imatrix,
+    importance_weights, make_q3_quants, make_qkx1_quants, make_qkx2_quants, make_qx_quants,
+    make_qx_quants_weighted, nearest_int,
 };
 use super::GgmlDType;
 use crate::Result;
@@ -31,6 +32,15 @@ pub trait GgmlType: Sized + Clone + Send + Sync {
     fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()>;
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()>;
 
+    /// Same as `from_float` but the block scales are selected so as to minimize the quantization
+    /// error weighted by the importance of each column. `imatrix` contains one value per column
+    /// and `xs` is made of rows of `imatrix.len()` elements. This is only used by the k-quants,
+    /// the other types ignore the importance values.
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        check_imatrix::<Self>(xs, imatrix)?;
+        Self::from_float(xs, ys)
+    }
+
     /// Dot product used as a building block for quantized mat-mul.
     /// n is the number of elements to be considered.
     fn vec_dot(n: usize, xs: &[Self], ys: &[Self::VecDotType]) -> Result<f32>;
@@ -732,17 +742,69 @@ impl GgmlType for BlockQ2K {
         Ok(sumf)
     }
 
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L279
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, None)
+    }
+
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, Some(imatrix))
+    }
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L354
+    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
+        for (block, y) in group_for_dequantization(xs, ys)? {
+            let d = block.d.to_f32();
+            let min = block.dmin.to_f32();
+
+            let mut is = 0;
+
+            for (y_block, qs) in y.chunks_exact_mut(128).zip(block.qs.chunks_exact(32)) {
+                // Step by 32 over q.
+                let mut shift = 0;
+                let mut y_block_index = 0;
+                for _j in 0..4 {
+                    let sc = block.scales[is];
+                    is += 1;
+                    let dl = d * (sc & 0xF) as f32;
+                    let ml = min * (sc >> 4) as f32;
+                    for q in &qs[..16] {
+                        let y = dl * ((q >> shift) & 3) as f32 - ml;
+                        y_block[y_block_index] = y;
+                        y_block_index += 1;
+                    }
+
+                    let sc = block.scales[is];
+                    is += 1;
+                    let dl = d * (sc & 0xF) as f32;
+                    let ml = min * (sc >> 4) as f32;
+                    for q in &qs[16..] {
+                        let y = dl * ((q >> shift) & 3) as f32 - ml;
+                        y_block[y_block_index] = y;
+                        y_block_index += 1;
+                    }
+
+                    shift += 2;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockQ2K {
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L279
+    fn quantize_weighted(xs: &[f32], ys: &mut [Self], imatrix: Option<&[f32]>) -> Result<()> {
         const Q4SCALE: f32 = 15.0;
 
-        for (block, x) in group_for_quantization(xs, ys)? {
+        for (block, x, weights) in group_for_quantization_imatrix(xs, ys, imatrix)? {
             //calculate scales and mins
             let mut mins: [f32; QK_K / 16] = [0.0; QK_K / 16];
             let mut scales: [f32; QK_K / 16] = [0.0; QK_K / 16];
 
             for (j, x_scale_slice) in x.chunks(16).enumerate() {
-                (scales[j], mins[j]) = make_qkx1_quants(3, 5, x_scale_slice);
+                (scales[j], mins[j]) = match &weights {
+                    None => make_qkx1_quants(3, 5, x_scale_slice),
+                    Some(w) => make_qkx2_quants(3, x_scale_slice, &w[16 * j..16 * (j + 1)]),
+                };
             }
             // get max scale and max min and ensure they are >= 0.0
             let max_scale = scales.iter().fold(0.0, |max, &val| val.max(max));
@@ -797,45 +859,6 @@ impl GgmlType for BlockQ2K {
         }
         Ok(())
     }
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L354
-    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
-        for (block, y) in group_for_dequantization(xs, ys)? {
-            let d = block.d.to_f32();
-            let min = block.dmin.to_f32();
-
-            let mut is = 0;
-
-            for (y_block, qs) in y.chunks_exact_mut(128).zip(block.qs.chunks_exact(32)) {
-                // Step by 32 over q.
-                let mut shift = 0;
-                let mut y_block_index = 0;
-                for _j in 0..4 {
-                    let sc = block.scales[is];
-                    is += 1;
-                    let dl = d * (sc & 0xF) as f32;
-                    let ml = min * (sc >> 4) as f32;
-                    for q in &qs[..16] {
-                        let y = dl * ((q >> shift) & 3) as f32 - ml;
-                        y_block[y_block_index] = y;
-                        y_block_index += 1;
-                    }
-
-                    let sc = block.scales[is];
-                    is += 1;
-                    let dl = d * (sc & 0xF) as f32;
-                    let ml = min * (sc >> 4) as f32;
-                    for q in &qs[16..] {
-                        let y = dl * ((q >> shift) & 3) as f32 - ml;
-                        y_block[y_block_index] = y;
-                        y_block_index += 1;
-                    }
-
-                    shift += 2;
-                }
-            }
-        }
-        Ok(())
-    }
 }
 
 impl GgmlType for BlockQ3K {
@@ -972,10 +995,81 @@ impl GgmlType for BlockQ3K {
     }
 
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()> {
-        for (block, x) in group_for_quantization(xs, ys)? {
+        Self::quantize_weighted(xs, ys, None)
+    }
+
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, Some(imatrix))
+    }
+
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L533
+    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
+        const KMASK1: u32 = 0x03030303;
+        const KMASK2: u32 = 0x0f0f0f0f;
+
+        for (block, y) in group_for_dequantization(xs, ys)? {
+            //Reconstruct the scales
+            let mut aux = [0; 4];
+            LittleEndian::read_u32_into(&block.scales, &mut aux[0..3]);
+
+            let tmp = aux[2];
+            aux[2] = ((aux[0] >> 4) & KMASK2) | (((tmp >> 4) & KMASK1) << 4);
+            aux[3] = ((aux[1] >> 4) & KMASK2) | (((tmp >> 6) & KMASK1) << 4);
+            aux[0] = (aux[0] & KMASK2) | (((tmp) & KMASK1) << 4);
+            aux[1] = (aux[1] & KMASK2) | (((tmp >> 2) & KMASK1) << 4);
+
+            //Transfer the scales into an i8 array
+            let scales: &mut [i8] =
+                unsafe { std::slice::from_raw_parts_mut(aux.as_mut_ptr() as *mut i8, 16) };
+
+            let d_all = block.d.to_f32();
+            let mut m = 1;
+            let mut is = 0;
+            let mut dl;
+
+            // Dequantize both 128 long blocks
+            // 32 qs values per 128 long block
+            // Each 16 elements get a scale
+            for (y, qs) in y.chunks_exact_mut(128).zip(block.qs.chunks_exact(32)) {
+                let mut shift = 0;
+                for shift_scoped_y in y.chunks_exact_mut(32) {
+                    for (scale_index, scale_scoped_y) in
+                        shift_scoped_y.chunks_exact_mut(16).enumerate()
+                    {
+                        dl = d_all * (scales[is] as f32 - 32.0);
+                        for (i, inner_y) in scale_scoped_y.iter_mut().enumerate() {
+                            let new_y = dl
+                                * (((qs[i + 16 * scale_index] >> shift) & 3) as i8
+                                    - if (block.hmask[i + 16 * scale_index] & m) == 0 {
+                                        4
+                                    } else {
+                                        0
+                                    }) as f32;
+                            *inner_y = new_y;
+                        }
+                        // 16 block finished => advance scale index
+                        is += 1;
+                    }
+                    // 32 block finished => increase shift and m
+                    shift += 2;
+                    m <<= 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockQ3K {
+    fn quantize_weighted(xs: &[f32], ys: &mut [Self], imatrix: Option<&[f32]>) -> Result<()> {
+        for (block, x, weights) in group_for_quantization_imatrix(xs, ys, imatrix)? {
             let mut scales: [f32; QK_K / 16] = [0.0; QK_K / 16];
             for (j, x_scale_slice) in x.chunks_exact(16).enumerate() {
-                scales[j] = make_q3_quants(x_scale_slice, 4, true);
+                scales[j] = match &weights {
+                    None => make_q3_quants(x_scale_slice, 4, true),
+                    Some(w) => make_qx_quants_weighted(4, x_scale_slice, &w[16 * j..16 * (j + 1)]),
+                };
             }
 
             // Get max scale by absolute value.
@@ -1053,64 +1147,6 @@ impl GgmlType for BlockQ3K {
 
         Ok(())
     }
-
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L533
-    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
-        const KMASK1: u32 = 0x03030303;
-        const KMASK2: u32 = 0x0f0f0f0f;
-
-        for (block, y) in group_for_dequantization(xs, ys)? {
-            //Reconstruct the scales
-            let mut aux = [0; 4];
-            LittleEndian::read_u32_into(&block.scales, &mut aux[0..3]);
-
-            let tmp = aux[2];
-            aux[2] = ((aux[0] >> 4) & KMASK2) | (((tmp >> 4) & KMASK1) << 4);
-            aux[3] = ((aux[1] >> 4) & KMASK2) | (((tmp >> 6) & KMASK1) << 4);
-            aux[0] = (aux[0] & KMASK2) | (((tmp) & KMASK1) << 4);
-            aux[1] = (aux[1] & KMASK2) | (((tmp >> 2) & KMASK1) << 4);
-
-            //Transfer the scales into an i8 array
-            let scales: &mut [i8] =
-                unsafe { std::slice::from_raw_parts_mut(aux.as_mut_ptr() as *mut i8, 16) };
-
-            let d_all = block.d.to_f32();
-            let mut m = 1;
-            let mut is = 0;
-            let mut dl;
-
-            // Dequantize both 128 long blocks
-            // 32 qs values per 128 long block
-            // Each 16 elements get a scale
-            for (y, qs) in y.chunks_exact_mut(128).zip(block.qs.chunks_exact(32)) {
-                let mut shift = 0;
-                for shift_scoped_y in y.chunks_exact_mut(32) {
-                    for (scale_index, scale_scoped_y) in
-                        shift_scoped_y.chunks_exact_mut(16).enumerate()
-                    {
-                        dl = d_all * (scales[is] as f32 - 32.0);
-                        for (i, inner_y) in scale_scoped_y.iter_mut().enumerate() {
-                            let new_y = dl
-                                * (((qs[i + 16 * scale_index] >> shift) & 3) as i8
-                                    - if (block.hmask[i + 16 * scale_index] & m) == 0 {
-                                        4
-                                    } else {
-                                        0
-                                    }) as f32;
-                            *inner_y = new_y;
-                        }
-                        // 16 block finished => advance scale index
-                        is += 1;
-                    }
-                    // 32 block finished => increase shift and m
-                    shift += 2;
-                    m <<= 1;
-                }
-            }
-        }
-
-        Ok(())
-    }
 }
 
 impl GgmlType for BlockQ4K {
@@ -1207,12 +1243,55 @@ impl GgmlType for BlockQ4K {
     }
 
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()> {
-        for (block, x) in group_for_quantization(xs, ys)? {
+        Self::quantize_weighted(xs, ys, None)
+    }
+
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, Some(imatrix))
+    }
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L735
+    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
+        for (block, y) in group_for_dequantization(xs, ys)? {
+            let d = block.d.to_f32();
+            let min = block.dmin.to_f32();
+            let q = &block.qs;
+            let mut is = 0;
+            let mut ys_index = 0;
+
+            for j in (0..QK_K).step_by(64) {
+                let q = &q[j / 2..j / 2 + 32];
+                let (sc, m) = get_scale_min_k4(is, &block.scales);
+                let d1 = d * sc as f32;
+                let m1 = min * m as f32;
+                let (sc, m) = get_scale_min_k4(is + 1, &block.scales);
+                let d2 = d * sc as f32;
+                let m2 = min * m as f32;
+                for q in q {
+                    y[ys_index] = d1 * (q & 0xF) as f32 - m1;
+                    ys_index += 1;
+                }
+                for q in q {
+                    y[ys_index] = d2 * (q >> 4) as f32 - m2;
+                    ys_index += 1;
+                }
+                is += 2;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockQ4K {
+    fn quantize_weighted(xs: &[f32], ys: &mut [Self], imatrix: Option<&[f32]>) -> Result<()> {
+        for (block, x, weights) in group_for_quantization_imatrix(xs, ys, imatrix)? {
             let mut mins: [f32; QK_K / 32] = [0.0; QK_K / 32];
             let mut scales: [f32; QK_K / 32] = [0.0; QK_K / 32];
 
             for (j, x_scale_slice) in x.chunks_exact(32).enumerate() {
-                (scales[j], mins[j]) = make_qkx1_quants(15, 5, x_scale_slice);
+                (scales[j], mins[j]) = match &weights {
+                    None => make_qkx1_quants(15, 5, x_scale_slice),
+                    Some(w) => make_qkx2_quants(15, x_scale_slice, &w[32 * j..32 * (j + 1)]),
+                };
             }
 
             // get max scale and max min and ensure they are >= 0.0
@@ -1266,36 +1345,6 @@ impl GgmlType for BlockQ4K {
         }
         Ok(())
     }
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L735
-    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
-        for (block, y) in group_for_dequantization(xs, ys)? {
-            let d = block.d.to_f32();
-            let min = block.dmin.to_f32();
-            let q = &block.qs;
-            let mut is = 0;
-            let mut ys_index = 0;
-
-            for j in (0..QK_K).step_by(64) {
-                let q = &q[j / 2..j / 2 + 32];
-                let (sc, m) = get_scale_min_k4(is, &block.scales);
-                let d1 = d * sc as f32;
-                let m1 = min * m as f32;
-                let (sc, m) = get_scale_min_k4(is + 1, &block.scales);
-                let d2 = d * sc as f32;
-                let m2 = min * m as f32;
-                for q in q {
-                    y[ys_index] = d1 * (q & 0xF) as f32 - m1;
-                    ys_index += 1;
-                }
-                for q in q {
-                    y[ys_index] = d2 * (q >> 4) as f32 - m2;
-                    ys_index += 1;
-                }
-                is += 2;
-            }
-        }
-        Ok(())
-    }
 }
 
 // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L928
@@ -1399,14 +1448,65 @@ impl GgmlType for BlockQ5K {
         Ok(sumf + sums.iter().sum::<f32>())
     }
 
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L793
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()> {
-        for (block, x) in group_for_quantization(xs, ys)? {
+        Self::quantize_weighted(xs, ys, None)
+    }
+
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, Some(imatrix))
+    }
+
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L928
+    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
+        for (block, y) in group_for_dequantization(xs, ys)? {
+            let d = block.d.to_f32();
+            let min = block.dmin.to_f32();
+            let ql = &block.qs;
+            let qh = &block.qh;
+            let mut is = 0;
+            let mut u1 = 1;
+            let mut u2 = 2;
+            let mut ys_index = 0;
+
+            for j in (0..QK_K).step_by(64) {
+                let ql = &ql[j / 2..j / 2 + 32];
+                let (sc, m) = get_scale_min_k4(is, &block.scales);
+                let d1 = d * sc as f32;
+                let m1 = min * m as f32;
+                let (sc, m) = get_scale_min_k4(is + 1, &block.scales);
+                let d2 = d * sc as f32;
+                let m2 = min * m as f32;
+                for (ql, qh) in ql.iter().zip(qh) {
+                    let to_add = if qh & u1 != 0 { 16 } else { 1 };
+                    y[ys_index] = d1 * ((ql & 0xF) + to_add) as f32 - m1;
+                    ys_index += 1;
+                }
+                for (ql, qh) in ql.iter().zip(qh) {
+                    let to_add = if qh & u2 != 0 { 16 } else { 1 };
+                    y[ys_index] = d2 * ((ql >> 4) + to_add) as f32 - m2;
+                    ys_index += 1;
+                }
+                is += 2;
+                u1 <<= 2;
+                u2 <<= 2;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockQ5K {
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L793
+    fn quantize_weighted(xs: &[f32], ys: &mut [Self], imatrix: Option<&[f32]>) -> Result<()> {
+        for (block, x, weights) in group_for_quantization_imatrix(xs, ys, imatrix)? {
             let mut mins: [f32; QK_K / 32] = [0.0; QK_K / 32];
             let mut scales: [f32; QK_K / 32] = [0.0; QK_K / 32];
 
             for (j, x_scale_slice) in x.chunks_exact(32).enumerate() {
-                (scales[j], mins[j]) = make_qkx1_quants(31, 5, x_scale_slice);
+                (scales[j], mins[j]) = match &weights {
+                    None => make_qkx1_quants(31, 5, x_scale_slice),
+                    Some(w) => make_qkx2_quants(31, x_scale_slice, &w[32 * j..32 * (j + 1)]),
+                };
             }
 
             // get max scale and max min and ensure they are >= 0.0
@@ -1476,44 +1576,6 @@ impl GgmlType for BlockQ5K {
 
         Ok(())
     }
-
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L928
-    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
-        for (block, y) in group_for_dequantization(xs, ys)? {
-            let d = block.d.to_f32();
-            let min = block.dmin.to_f32();
-            let ql = &block.qs;
-            let qh = &block.qh;
-            let mut is = 0;
-            let mut u1 = 1;
-            let mut u2 = 2;
-            let mut ys_index = 0;
-
-            for j in (0..QK_K).step_by(64) {
-                let ql = &ql[j / 2..j / 2 + 32];
-                let (sc, m) = get_scale_min_k4(is, &block.scales);
-                let d1 = d * sc as f32;
-                let m1 = min * m as f32;
-                let (sc, m) = get_scale_min_k4(is + 1, &block.scales);
-                let d2 = d * sc as f32;
-                let m2 = min * m as f32;
-                for (ql, qh) in ql.iter().zip(qh) {
-                    let to_add = if qh & u1 != 0 { 16 } else { 1 };
-                    y[ys_index] = d1 * ((ql & 0xF) + to_add) as f32 - m1;
-                    ys_index += 1;
-                }
-                for (ql, qh) in ql.iter().zip(qh) {
-                    let to_add = if qh & u2 != 0 { 16 } else { 1 };
-                    y[ys_index] = d2 * ((ql >> 4) + to_add) as f32 - m2;
-                    ys_index += 1;
-                }
-                is += 2;
-                u1 <<= 2;
-                u2 <<= 2;
-            }
-        }
-        Ok(())
-    }
 }
 
 impl GgmlType for BlockQ6K {
@@ -1587,6 +1649,49 @@ impl GgmlType for BlockQ6K {
     }
 
     fn from_float(xs: &[f32], ys: &mut [Self]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, None)
+    }
+
+    fn from_float_imatrix(xs: &[f32], ys: &mut [Self], imatrix: &[f32]) -> Result<()> {
+        Self::quantize_weighted(xs, ys, Some(imatrix))
+    }
+
+    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L1067
+    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
+        let k = ys.len();
+        if k % QK_K != 0 {
+            crate::bail!("dequantize_row_q6k: {k} is not divisible by {QK_K}")
+        }
+        for (idx_x, x) in xs.iter().enumerate() {
+            let d = x.d.to_f32();
+            let ql = &x.ql;
+            let qh = &x.qh;
+            let sc = &x.scales;
+            for n in (0..QK_K).step_by(128) {
+                let idx = n / 128;
+                let ys = &mut ys[idx_x * QK_K + n..];
+                let sc = &sc[8 * idx..];
+                let ql = &ql[64 * idx..];
+                let qh = &qh[32 * idx..];
+                for l in 0..32 {
+                    let is = l / 16;
+                    let q1 = ((ql[l] & 0xF) | ((qh[l] & 3) << 4)) as i8 - 32;
+                    let q2 = ((ql[l + 32] & 0xF) | (((qh[l] >> 2) & 3) << 4)) as i8 - 32;
+                    let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i8 - 32;
+                    let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i8 - 32;
+                    ys[l] = d * sc[is] as f32 * q1 as f32;
+                    ys[l + 32] = d * sc[is + 2] as f32 * q2 as f32;
+                    ys[l + 64] = d * sc[is + 4] as f32 * q3 as f32;
+                    ys[l + 96] = d * sc[is + 6] as f32 * q4 as f32;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlockQ6K {
+    fn quantize_weighted(xs: &[f32], ys: &mut [Self], imatrix: Option<&[f32]>) -> Result<()> {
         if xs.len() != ys.len() * Self::BLCK_SIZE {
             crate::bail!(
                 "quantize_row_q6k: size mismatch {} {} {}",
@@ -1595,16 +1700,30 @@ impl GgmlType for BlockQ6K {
                 Self::BLCK_SIZE
             )
         }
+        if let Some(imatrix) = imatrix {
+            check_imatrix::<Self>(xs, imatrix)?;
+        }
         let mut l = [0i8; QK_K];
         let mut scales = [0f32; QK_K / 16];
         let mut x = xs.as_ptr();
         let l = l.as_mut_ptr();
         unsafe {
-            for y in ys.iter_mut() {
+            for (i, y) in ys.iter_mut().enumerate() {
+                let weights = imatrix.map(|imatrix| {
+                    let x = std::slice::from_raw_parts(x, QK_K);
+                    let start = (i * QK_K) % imatrix.len();
+                    importance_weights(x, &imatrix[start..start + QK_K])
+                });
                 let mut max_scale = 0f32;
                 let mut max_abs_scale = 0f32;
                 for (ib, scale_) in scales.iter_mut().enumerate() {
-                    let scale = make_qx_quants(16, 32, x.add(16 * ib), l.add(16 * ib), 1);
+                    let scale = match &weights {
+                        None => make_qx_quants(16, 32, x.add(16 * ib), l.add(16 * ib), 1),
+                        Some(w) => {
+                            let x = std::slice::from_raw_parts(x.add(16 * ib), 16);
+                            make_qx_quants_weighted(32, x, &w[16 * ib..16 * (ib + 1)])
+                        }
+                    };
                     *scale_ = scale;
                     let abs_scale = scale.abs();
                     if abs_scale > max_abs_scale {
@@ -1657,39 +1776,6 @@ impl GgmlType for BlockQ6K {
         }
         Ok(())
     }
-
-    // https://github.com/ggerganov/llama.cpp/blob/8183159cf3def112f6d1fe94815fce70e1bffa12/k_quants.c#L1067
-    fn to_float(xs: &[Self], ys: &mut [f32]) -> Result<()> {
-        let k = ys.len();
-        if k % QK_K != 0 {
-            crate::bail!("dequantize_row_q6k: {k} is not divisible by {QK_K}")
-        }
-        for (idx_x, x) in xs.iter().enumerate() {
-            let d = x.d.to_f32();
-            let ql = &x.ql;
-            let qh = &x.qh;
-            let sc = &x.scales;
-            for n in (0..QK_K).step_by(128) {
-                let idx = n / 128;
-                let ys = &mut ys[idx_x * QK_K + n..];
-                let sc = &sc[8 * idx..];
-                let ql = &ql[64 * idx..];
-                let qh = &qh[32 * idx..];
-                for l in 0..32 {
-                    let is = l / 16;
-                    let q1 = ((ql[l] & 0xF) | ((qh[l] & 3) << 4)) as i8 - 32;
-                    let q2 = ((ql[l + 32] & 0xF) | (((qh[l] >> 2) & 3) << 4)) as i8 - 32;
-                    let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i8 - 32;
-                    let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i8 - 32;
-                    ys[l] = d * sc[is] as f32 * q1 as f32;
-                    ys[l + 32] = d * sc[is + 2] as f32 * q2 as f32;
-                    ys[l + 64] = d * sc[is + 4] as f32 * q3 as f32;
-                    ys[l + 96] = d * sc[is + 6] as f32 * q4 as f32;
-                }
-            }
-        }
-        Ok(())
-    }
 }
 
 impl GgmlType for BlockQ8K {
@@ -1778,24 +1864,27 @@ pub fn matmul<T: GgmlType>(
     }
     let lhs_b = lhs_b.as_slice();
 
-    for row_idx in 0..m {
-        let lhs_row = &lhs_b[row_idx * k_in_lhs_blocks..(row_idx + 1) * k_in_lhs_blocks];
-        let dst_row = &mut dst[row_idx * n..(row_idx + 1) * n];
-
-        let result: Result<Vec<_>> = dst_row
-            .into_par_iter()
-            .enumerate()
-            .with_min_len(128)
-            .with_max_len(512)
-            .map(|(col_idx, dst)| {
-                let rhs_col = &rhs_t[col_idx * k_in_rhs_blocks..(col_idx + 1) * k_in_rhs_blocks];
-                T::vec_dot(k, rhs_col, lhs_row).map(|value| *dst = value)
-            })
-            .collect();
-
-        result?;
-    }
-    Ok(())
+    crate::cpu_pool::install(|| {
+        for row_idx in 0..m {
+            let lhs_row = &lhs_b[row_idx * k_in_lhs_blocks..(row_idx + 1) * k_in_lhs_blocks];
+            let dst_row = &mut dst[row_idx * n..(row_idx + 1) * n];
+
+            let result: Result<Vec<_>> = dst_row
+                .into_par_iter()
+                .enumerate()
+                .with_min_len(128)
+                .with_max_len(512)
+                .map(|(col_idx, dst)| {
+                    let rhs_col =
+                        &rhs_t[col_idx * k_in_rhs_blocks..(col_idx + 1) * k_in_rhs_blocks];
+                    T::vec_dot(k, rhs_col, lhs_row).map(|value| *dst = value)
+                })
+                .collect();
+
+            result?;
+        }
+        Ok(())
+    })
 }
 
 impl GgmlType for f32 {
@@ -4,6 +4,8 @@ use crate::{Device, Result, Shape, Tensor};
 pub mod avx;
 pub mod ggml_file;
 pub mod gguf_file;
+pub mod gptq;
+pub mod imatrix;
 pub mod k_quants;
 #[cfg(target_feature = "neon")]
 pub mod neon;
@@ -14,6 +16,9 @@ pub use k_quants::GgmlType;
 pub struct QTensor {
     data: Box<dyn QuantizedType>,
     shape: Shape,
+    // The dequantized weights used by the cuda matmul, one per device and dtype. These are
+    // computed on the first forward pass on a device and kept around for the later ones.
+    cuda_weights: std::sync::Mutex<Vec<std::sync::Arc<crate::CudaStorage>>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -120,6 +125,10 @@ pub trait QuantizedType: Send + Sync {
     fn to_float(&self, ys: &mut [f32]) -> Result<()>;
     fn storage_size_in_bytes(&self) -> usize;
     fn as_ptr(&self) -> *const u8;
+    /// Makes the data available in memory, this is only needed for lazily loaded data.
+    fn load(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl<T: k_quants::GgmlType + Send + Sync> QuantizedType for Vec<T> {
@@ -144,6 +153,66 @@ impl<T: k_quants::GgmlType + Send + Sync> QuantizedType for Vec<T> {
     }
 }
 
+type Loader = Box<dyn Fn() -> Result<Box<dyn QuantizedType>> + Send + Sync>;
+
+// Data that only gets loaded the first time that it is used.
+struct LazyData {
+    dtype: GgmlDType,
+    size_in_bytes: usize,
+    loader: Loader,
+    data: std::sync::OnceLock<Box<dyn QuantizedType>>,
+}
+
+impl LazyData {
+    fn get(&self) -> Result<&dyn QuantizedType> {
+        if let Some(data) = self.data.get() {
+            return Ok(data.as_ref());
+        }
+        let data = (self.loader)()?;
+        if data.dtype() != self.dtype || data.storage_size_in_bytes() != self.size_in_bytes {
+            crate::bail!(
+                "lazy qtensor loaded {:?} {}, expected {:?} {}",
+                data.dtype(),
+                data.storage_size_in_bytes(),
+                self.dtype,
+                self.size_in_bytes
+            )
+        }
+        // If another thread has loaded the data in the meantime, its version is used.
+        Ok(self.data.get_or_init(|| data).as_ref())
+    }
+}
+
+impl QuantizedType for LazyData {
+    fn dtype(&self) -> GgmlDType {
+        self.dtype
+    }
+
+    fn matmul_t(&self, mkn: (usize, usize, usize), lhs: &[f32], dst: &mut [f32]) -> Result<()> {
+        self.get()?.matmul_t(mkn, lhs, dst)
+    }
+
+    fn to_float(&self, ys: &mut [f32]) -> Result<()> {
+        self.get()?.to_float(ys)
+    }
+
+    fn storage_size_in_bytes(&self) -> usize {
+        self.size_in_bytes
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        // The consumers of the data call `QTensor::load` first so the loading cannot fail here.
+        match self.get() {
+            Ok(data) => data.as_ptr(),
+            Err(err) => unreachable!("lazy qtensor used before being loaded: {err}"),
+        }
+    }
+
+    fn load(&self) -> Result<()> {
+        self.get()?.load()
+    }
+}
+
 impl std::fmt::Debug for QTensor {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "QTensor[{:?}; {:?}]", self.shape, self.dtype())
@@ -151,14 +220,18 @@ impl std::fmt::Debug for QTensor {
 }
 
 fn check_shape<T: k_quants::GgmlType>(shape: &Shape) -> Result<()> {
+    check_dtype_shape(T::DTYPE, shape)
+}
+
+fn check_dtype_shape(dtype: GgmlDType, shape: &Shape) -> Result<()> {
     let dims = shape.dims();
     if dims.is_empty() {
         crate::bail!("scalar tensor cannot be quantized {shape:?}")
     }
-    if dims[dims.len() - 1] % T::BLCK_SIZE != 0 {
+    if dims[dims.len() - 1] % dtype.blck_size() != 0 {
         crate::bail!(
             "quantized tensor must have their last dim divisible by block size {shape:?} {}",
-            T::BLCK_SIZE
+            dtype.blck_size()
         )
     }
     Ok(())
@@ -174,9 +247,59 @@ impl QTensor {
         Ok(Self {
             data: Box::new(data),
             shape,
+            cuda_weights: Default::default(),
+        })
+    }
+
+    /// Creates a tensor with some data boxed behind the [`QuantizedType`] trait, e.g. blocks that
+    /// live in a memory mapped file.
+    pub fn from_data<S: Into<Shape>>(data: Box<dyn QuantizedType>, shape: S) -> Result<Self> {
+        let shape = shape.into();
+        check_dtype_shape(data.dtype(), &shape)?;
+        let size_in_bytes =
+            shape.elem_count() / data.dtype().blck_size() * data.dtype().type_size();
+        if data.storage_size_in_bytes() != size_in_bytes {
+            crate::bail!(
+                "unexpected data size {} for {shape:?} {:?}",
+                data.storage_size_in_bytes(),
+                data.dtype()
+            )
+        }
+        Ok(Self {
+            data,
+            shape,
+            cuda_weights: Default::default(),
+        })
+    }
+
+    /// Creates a tensor the data of which only gets loaded by calling `loader` the first time
+    /// that it is needed, e.g. on the first [`QMatMul::forward`]. The loader has to return data
+    /// with the given dtype and shape.
+    pub fn lazy<S, F>(shape: S, dtype: GgmlDType, loader: F) -> Result<Self>
+    where
+        S: Into<Shape>,
+        F: Fn() -> Result<Box<dyn QuantizedType>> + Send + Sync + 'static,
+    {
+        let shape = shape.into();
+        check_dtype_shape(dtype, &shape)?;
+        let data = LazyData {
+            dtype,
+            size_in_bytes: shape.elem_count() / dtype.blck_size() * dtype.type_size(),
+            loader: Box::new(loader),
+            data: std::sync::OnceLock::new(),
+        };
+        Ok(Self {
+            data: Box::new(data),
+            shape,
+            cuda_weights: Default::default(),
         })
     }
 
+    /// Loads the data of a lazy tensor, this is a no-op for the other tensors.
+    pub fn load(&self) -> Result<()> {
+        self.data.load()
+    }
+
     pub fn quantize<T: k_quants::GgmlType + Send + Sync + 'static>(src: &Tensor) -> Result<Self> {
         let shape = src.shape();
         check_shape::<T>(shape)?;
@@ -195,6 +318,37 @@ impl QTensor {
         Ok(Self {
             data: Box::new(data),
             shape: shape.clone(),
+            cuda_weights: Default::default(),
+        })
+    }
+
+    /// Quantizes `src` selecting the block scales that minimize the quantization error weighted
+    /// by `importance`, which has one value per column of `src`, i.e. per element of its last
+    /// dimension. The importance of the weight of a linear layer is usually computed from the
+    /// activations flowing into it, see [`imatrix::Imatrix`].
+    pub fn quantize_with_importance<T: k_quants::GgmlType + Send + Sync + 'static>(
+        src: &Tensor,
+        importance: &[f32],
+    ) -> Result<Self> {
+        let shape = src.shape();
+        check_shape::<T>(shape)?;
+        let n_per_row = shape.dims()[shape.rank() - 1];
+        if importance.len() != n_per_row {
+            crate::bail!(
+                "importance has {} values but tensor {shape:?} has {n_per_row} columns",
+                importance.len()
+            )
+        }
+        let src = src
+            .to_dtype(crate::DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        let mut data = vec![T::zeros(); src.len() / T::BLCK_SIZE];
+        T::from_float_imatrix(&src, &mut data, importance)?;
+        Ok(Self {
+            data: Box::new(data),
+            shape: shape.clone(),
+            cuda_weights: Default::default(),
         })
     }
 
@@ -211,12 +365,14 @@ impl QTensor {
     }
 
     pub fn dequantize(&self, device: &Device) -> Result<Tensor> {
+        self.load()?;
         let mut f32_data = vec![0f32; self.shape.elem_count()];
         self.data.to_float(&mut f32_data)?;
         Tensor::from_vec(f32_data, &self.shape, device)
     }
 
     pub fn matmul_t(&self, mkn: (usize, usize, usize), lhs: &[f32], dst: &mut [f32]) -> Result<()> {
+        self.load()?;
         self.data.matmul_t(mkn, lhs, dst)
     }
 
@@ -224,41 +380,55 @@ impl QTensor {
         self.data.storage_size_in_bytes()
     }
 
+    /// A pointer to the raw data, lazy tensors have to be loaded with [`QTensor::load`] first.
     pub fn as_ptr(&self) -> *const u8 {
         self.data.as_ptr()
     }
 }
 
-#[derive(Debug)]
-pub struct QMatMul(std::sync::Arc<QTensor>);
+/// A matmul with a quantized right hand side: `forward(xs)` computes `xs @ w.t()` where `w` is the
+/// quantized tensor of shape `(n, k)`. The input can use arbitrary strides and have a
+/// `f32`, `f16` or `bf16` dtype, the result uses the same dtype as the input.
+///
+/// The quantized weights are frozen, the backward pass only computes the gradient of the input
+/// using the dequantized weights. This is enough to train adapters, e.g. LoRA, on top of a
+/// quantized model.
+#[derive(Debug, Clone)]
+pub struct QMatMul {
+    qtensor: std::sync::Arc<QTensor>,
+    imatrix: Option<(String, std::sync::Arc<imatrix::Imatrix>)>,
+}
 
 impl QMatMul {
     pub fn from_arc(qtensor: std::sync::Arc<QTensor>) -> Self {
-        Self(qtensor)
+        Self {
+            qtensor,
+            imatrix: None,
+        }
     }
 
     pub fn from_qtensor(qtensor: QTensor) -> Self {
-        Self(std::sync::Arc::new(qtensor))
+        Self::from_arc(std::sync::Arc::new(qtensor))
     }
 
     pub fn inner(&self) -> &std::sync::Arc<QTensor> {
-        &self.0
+        &self.qtensor
     }
-}
 
-impl crate::CustomOp1 for QTensor {
-    fn name(&self) -> &'static str {
-        "qmatmul"
+    /// Records the inputs of `forward` in `imatrix` under `name`, the name of the weight, so that
+    /// the importance of its columns can be computed while running the model on some calibration
+    /// data.
+    pub fn with_imatrix(self, name: &str, imatrix: std::sync::Arc<imatrix::Imatrix>) -> Self {
+        Self {
+            imatrix: Some((name.to_string(), imatrix)),
+            ..self
+        }
     }
+}
 
-    fn cpu_fwd(
-        &self,
-        storage: &crate::CpuStorage,
-        layout: &crate::Layout,
-    ) -> Result<(crate::CpuStorage, Shape)> {
-        if !layout.is_contiguous() {
-            crate::bail!("input tensor is not contiguous {layout:?}")
-        }
+impl QTensor {
+    // Returns (m, k, n) and the shape of the result for the input layout.
+    fn matmul_dims(&self, layout: &crate::Layout) -> Result<((usize, usize, usize), Shape)> {
         let src_shape = layout.shape();
         // self is transposed so n is first then k.
         let (n, k) = self.shape.dims2()?;
@@ -272,21 +442,153 @@ impl crate::CustomOp1 for QTensor {
         }
         dst_shape.push(n);
         let dst_shape = Shape::from(dst_shape);
-        let storage = storage.as_slice::<f32>()?;
-        let storage =
-            &storage[layout.start_offset()..layout.start_offset() + src_shape.elem_count()];
-        let mut dst_storage = vec![0f32; dst_shape.elem_count()];
-        self.matmul_t(
-            (dst_shape.elem_count() / n, k, n),
-            storage,
-            &mut dst_storage,
-        )?;
-        Ok((crate::CpuStorage::F32(dst_storage), dst_shape))
+        Ok(((dst_shape.elem_count() / n, k, n), dst_shape))
+    }
+
+    // The dequantized weights with shape (n, k) converted to the dtype of `xs`, used by the
+    // backward and forward mode autodiff.
+    fn dequantize_like(&self, xs: &Tensor) -> Result<Tensor> {
+        self.dequantize(xs.device())?.to_dtype(xs.dtype())
+    }
+}
+
+impl QTensor {
+    // There are no quantized cuda kernels yet, so the weights are dequantized on the host and
+    // uploaded once per device and dtype, the matmul then uses a gemm on these.
+    fn cuda_weights(
+        &self,
+        dev: &crate::CudaDevice,
+        dtype: crate::DType,
+        (n, k): (usize, usize),
+    ) -> Result<std::sync::Arc<crate::CudaStorage>> {
+        use crate::backend::{BackendDevice, BackendStorage};
+        let mut cuda_weights = self.cuda_weights.lock().unwrap();
+        let cached = cuda_weights
+            .iter()
+            .find(|w| w.dtype() == dtype && w.device().same_device(dev));
+        if let Some(w) = cached {
+            return Ok(w.clone());
+        }
+        let mut w = vec![0f32; n * k];
+        self.data.to_float(&mut w)?;
+        let w = dev.storage_from_cpu_storage(&crate::CpuStorage::F32(w))?;
+        let w = std::sync::Arc::new(w.to_dtype(&crate::Layout::contiguous((n, k)), dtype)?);
+        cuda_weights.push(w.clone());
+        Ok(w)
+    }
+}
+
+impl crate::CustomOp1 for QTensor {
+    fn name(&self) -> &'static str {
+        "qmatmul"
+    }
+
+    fn cpu_fwd(
+        &self,
+        storage: &crate::CpuStorage,
+        layout: &crate::Layout,
+    ) -> Result<(crate::CpuStorage, Shape)> {
+        use crate::backend::BackendStorage;
+        use crate::cpu_backend::unary_map;
+        use crate::CpuStorage;
+        self.load()?;
+        let (mkn, dst_shape) = self.matmul_dims(layout)?;
+        let mut dst = vec![0f32; dst_shape.elem_count()];
+        let dst = match storage {
+            CpuStorage::F32(vs) => {
+                match layout.contiguous_offsets() {
+                    Some((o1, o2)) => self.matmul_t(mkn, &vs[o1..o2], &mut dst)?,
+                    None => self.matmul_t(mkn, &unary_map(vs, layout, |v| v), &mut dst)?,
+                }
+                CpuStorage::F32(dst)
+            }
+            CpuStorage::F16(vs) => {
+                self.matmul_t(mkn, &unary_map(vs, layout, |v| v.to_f32()), &mut dst)?;
+                CpuStorage::F16(dst.into_iter().map(half::f16::from_f32).collect())
+            }
+            CpuStorage::BF16(vs) => {
+                self.matmul_t(mkn, &unary_map(vs, layout, |v| v.to_f32()), &mut dst)?;
+                CpuStorage::BF16(dst.into_iter().map(half::bf16::from_f32).collect())
+            }
+            _ => crate::bail!("unsupported dtype for qmatmul {:?}", storage.dtype()),
+        };
+        Ok((dst, dst_shape))
+    }
+
+    fn cuda_fwd(
+        &self,
+        storage: &crate::CudaStorage,
+        layout: &crate::Layout,
+    ) -> Result<(crate::CudaStorage, Shape)> {
+        use crate::backend::{BackendDevice, BackendStorage};
+        use crate::{DType, Layout};
+        let dtype = storage.dtype();
+        if !matches!(dtype, DType::F32 | DType::F16 | DType::BF16) {
+            crate::bail!("unsupported dtype for qmatmul {dtype:?}")
+        }
+        self.load()?;
+        let ((m, k, n), dst_shape) = self.matmul_dims(layout)?;
+        let dev = storage.device();
+        let w = self.cuda_weights(dev, dtype, (n, k))?;
+        let w_l = Layout::new(Shape::from((k, n)), vec![1, k], 0);
+        let dst = if layout.is_contiguous() {
+            let lhs_l = Layout::contiguous_with_offset((m, k), layout.start_offset());
+            storage.matmul(&w, (1, m, n, k), &lhs_l, &w_l)?
+        } else {
+            let mut lhs = dev.zeros_impl(layout.shape(), dtype)?;
+            storage.copy_strided_src(&mut lhs, 0, layout)?;
+            lhs.matmul(&w, (1, m, n, k), &Layout::contiguous((m, k)), &w_l)?
+        };
+        Ok((dst, dst_shape))
+    }
+
+    fn bwd(&self, _arg: &Tensor, _res: &Tensor, grad_res: &Tensor) -> Result<Option<Tensor>> {
+        // d(xs @ w.t()) / dxs = grad @ w, the quantized weights are treated as constants.
+        let w = self.dequantize_like(grad_res)?;
+        Ok(Some(grad_res.broadcast_matmul(&w)?))
+    }
+
+    fn jvp(&self, _arg: &Tensor, _res: &Tensor, tangent: &Tensor) -> Result<Tensor> {
+        let w = self.dequantize_like(tangent)?;
+        tangent.broadcast_matmul(&w.t()?)
+    }
+}
+
+impl crate::CustomOp1 for QMatMul {
+    fn name(&self) -> &'static str {
+        self.qtensor.name()
+    }
+
+    fn cpu_fwd(
+        &self,
+        storage: &crate::CpuStorage,
+        layout: &crate::Layout,
+    ) -> Result<(crate::CpuStorage, Shape)> {
+        self.qtensor.cpu_fwd(storage, layout)
+    }
+
+    fn cuda_fwd(
+        &self,
+        storage: &crate::CudaStorage,
+        layout: &crate::Layout,
+    ) -> Result<(crate::CudaStorage, Shape)> {
+        self.qtensor.cuda_fwd(storage, layout)
+    }
+
+    fn bwd(&self, arg: &Tensor, res: &Tensor, grad_res: &Tensor) -> Result<Option<Tensor>> {
+        self.qtensor.bwd(arg, res, grad_res)
+    }
+
+    fn jvp(&self, arg: &Tensor, res: &Tensor, tangent: &Tensor) -> Result<Tensor> {
+        self.qtensor.jvp(arg, res, tangent)
     }
 }
 
 impl QMatMul {
     pub fn forward(&self, xs: &Tensor) -> Result<Tensor> {
-        xs.apply_op1_no_bwd(self.0.as_ref())
+        if let Some((name, imatrix)) = &self.imatrix {
+            imatrix.record(name, xs)?
+        }
+        xs.apply_op1(self.clone())
     }
 }
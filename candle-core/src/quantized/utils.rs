@@ -324,3 +324,158 @@ pub(super) fn make_q3_quants(x: &[f32], nmax: i32, do_rmse: bool) -> f32 {
     }
     1.0 / iscale
 }
+
+/// Checks that `imatrix` holds one non-negative importance per column for `xs`, the rows of
+/// which have `imatrix.len()` elements each.
+pub(super) fn check_imatrix<T: super::k_quants::GgmlType>(
+    xs: &[f32],
+    imatrix: &[f32],
+) -> Result<()> {
+    let dtype = T::DTYPE;
+    let n_per_row = imatrix.len();
+    if n_per_row == 0
+        || !n_per_row.is_multiple_of(T::BLCK_SIZE)
+        || !xs.len().is_multiple_of(n_per_row)
+    {
+        crate::bail!(
+            "quantize {dtype:?}: importance matrix of len {n_per_row} is incompatible with {} elements and block size {}",
+            xs.len(),
+            T::BLCK_SIZE
+        )
+    }
+    if let Some(v) = imatrix.iter().find(|v| !v.is_finite() || **v < 0.) {
+        crate::bail!("quantize {dtype:?}: invalid importance value {v}")
+    }
+    Ok(())
+}
+
+/// The weight of each element of the block `x` given the importance of its columns `imp`. As in
+/// llama.cpp the magnitude of the values is taken into account so that large weights stay accurate
+/// and a small offset ensures that columns with no recorded activation are not ignored entirely.
+pub(super) fn importance_weights(x: &[f32], imp: &[f32]) -> Vec<f32> {
+    let sigma2 = x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32;
+    x.iter()
+        .zip(imp.iter())
+        .map(|(x, imp)| (imp + 1e-6 * (1. + sigma2)) * (sigma2 + x * x).sqrt())
+        .collect()
+}
+
+/// Same as [`group_for_quantization`] but also returns the importance weights of the elements of
+/// each block when an importance matrix is provided.
+#[allow(clippy::type_complexity)]
+pub(super) fn group_for_quantization_imatrix<'a, 'b, T: super::k_quants::GgmlType>(
+    xs: &'b [f32],
+    ys: &'a mut [T],
+    imatrix: Option<&[f32]>,
+) -> Result<Vec<(&'a mut T, &'b [f32], Option<Vec<f32>>)>> {
+    if let Some(imatrix) = imatrix {
+        check_imatrix::<T>(xs, imatrix)?;
+    }
+    let blocks = group_for_quantization(xs, ys)?;
+    let blocks = blocks
+        .into_iter()
+        .enumerate()
+        .map(|(i, (block, x))| {
+            let weights = imatrix.map(|imatrix| {
+                let start = (i * T::BLCK_SIZE) % imatrix.len();
+                importance_weights(x, &imatrix[start..start + T::BLCK_SIZE])
+            });
+            (block, x, weights)
+        })
+        .collect();
+    Ok(blocks)
+}
+
+/// Weighted version of [`make_qkx1_quants`]: returns the scale and min, the min being negated,
+/// that minimize the weighted squared error when quantizing `x` to `[0, nmax]`. A grid of
+/// candidate scales around the min-max one is explored, each candidate being refined with a
+/// weighted least squares fit.
+pub(super) fn make_qkx2_quants(nmax: i32, x: &[f32], weights: &[f32]) -> (f32, f32) {
+    const RMIN: f32 = -1.0;
+    const RDELTA: f32 = 0.1;
+    const NSTEP: usize = 20;
+
+    let min = x.iter().copied().fold(x[0], f32::min).min(0.);
+    let max = x.iter().copied().fold(x[0], f32::max);
+    if max <= min {
+        return (0.0, -min);
+    }
+    let sum_w = weights.iter().sum::<f32>();
+    let sum_x = x.iter().zip(weights).map(|(x, w)| w * x).sum::<f32>();
+    let error = |scale: f32, min: f32| -> f32 {
+        let iscale = if scale > 0. { 1. / scale } else { 0. };
+        x.iter()
+            .zip(weights)
+            .map(|(&x, &w)| {
+                let l = nearest_int(iscale * (x - min)).clamp(0, nmax) as f32;
+                let diff = scale * l + min - x;
+                w * diff * diff
+            })
+            .sum()
+    };
+
+    let mut best_scale = (max - min) / nmax as f32;
+    let mut best_min = min;
+    let mut best_error = error(best_scale, best_min);
+    for step in 0..=NSTEP {
+        let iscale = (RMIN + RDELTA * step as f32 + nmax as f32) / (max - min);
+        let (mut sum_l, mut sum_l2, mut sum_xl) = (0f32, 0f32, 0f32);
+        for (&x, &w) in x.iter().zip(weights) {
+            let l = nearest_int(iscale * (x - min)).clamp(0, nmax) as f32;
+            sum_l += w * l;
+            sum_l2 += w * l * l;
+            sum_xl += w * l * x;
+        }
+        let det = sum_w * sum_l2 - sum_l * sum_l;
+        if det <= 0. {
+            continue;
+        }
+        let mut this_scale = (sum_w * sum_xl - sum_x * sum_l) / det;
+        let mut this_min = (sum_l2 * sum_x - sum_l * sum_xl) / det;
+        if this_min > 0. {
+            this_min = 0.;
+            this_scale = sum_xl / sum_l2;
+        }
+        if this_scale <= 0. {
+            continue;
+        }
+        let this_error = error(this_scale, this_min);
+        if this_error < best_error {
+            best_error = this_error;
+            best_scale = this_scale;
+            best_min = this_min;
+        }
+    }
+    (best_scale, -best_min)
+}
+
+/// Weighted counterpart of the symmetric quantization used by [`make_qx_quants`] and
+/// [`make_q3_quants`]: returns the scale minimizing the weighted squared error when quantizing
+/// `x` to `[-nmax, nmax - 1]`, the scale has the opposite sign of the largest value.
+pub(super) fn make_qx_quants_weighted(nmax: i32, x: &[f32], weights: &[f32]) -> f32 {
+    let max = x
+        .iter()
+        .copied()
+        .fold(0f32, |m, v| if v.abs() > m.abs() { v } else { m });
+    if max == 0. {
+        return 0.;
+    }
+    let mut best_scale = -max / nmax as f32;
+    let mut best = 0f32;
+    for step in -9..=9 {
+        let iscale = -(nmax as f32 + 0.1 * step as f32) / max;
+        let (mut sum_lx, mut sum_l2) = (0f32, 0f32);
+        for (&x, &w) in x.iter().zip(weights) {
+            let l = nearest_int(iscale * x).clamp(-nmax, nmax - 1) as f32;
+            sum_lx += w * x * l;
+            sum_l2 += w * l * l;
+        }
+        // The weighted error for the optimal scale sum_lx / sum_l2 is minimized when
+        // sum_lx^2 / sum_l2 is maximized.
+        if sum_l2 > 0. && sum_lx * sum_lx > best * sum_l2 {
+            best = sum_lx * sum_lx / sum_l2;
+            best_scale = sum_lx / sum_l2;
+        }
+    }
+    best_scale
+}
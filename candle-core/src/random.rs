@@ -0,0 +1,167 @@
+//! Seedable random number generation.
+//!
+//! The random values are produced by a counter-based generator (Philox4x32-10): the value of the
+//! element at index `i` of a draw only depends on the seed and on `offset + i`, where `offset`
+//! counts the number of elements generated so far. The same stream is used on all the devices so
+//! that a given seed results in the same values on cpu and on cuda.
+use crate::{CpuStorage, DType, Error, Result, Shape, WithDType};
+use std::sync::Mutex;
+
+// The generator used by `Device::Cpu` once a seed has been set, before that the thread rng is
+// used.
+pub(crate) static CPU_GENERATOR: Mutex<Option<Generator>> = Mutex::new(None);
+
+const PHILOX_M0: u32 = 0xD251_1F53;
+const PHILOX_M1: u32 = 0xCD9E_8D57;
+const PHILOX_W0: u32 = 0x9E37_79B9;
+const PHILOX_W1: u32 = 0xBB67_AE85;
+
+fn philox4x32_10(ctr: [u32; 4], key: [u32; 2]) -> [u32; 4] {
+    let (mut ctr, mut key) = (ctr, key);
+    for round in 0..10 {
+        let p0 = PHILOX_M0 as u64 * ctr[0] as u64;
+        let p1 = PHILOX_M1 as u64 * ctr[2] as u64;
+        ctr = [
+            (p1 >> 32) as u32 ^ ctr[1] ^ key[0],
+            p1 as u32,
+            (p0 >> 32) as u32 ^ ctr[3] ^ key[1],
+            p0 as u32,
+        ];
+        if round < 9 {
+            key = [
+                key[0].wrapping_add(PHILOX_W0),
+                key[1].wrapping_add(PHILOX_W1),
+            ];
+        }
+    }
+    ctr
+}
+
+// A value uniformly distributed in [0, 1) using 53 bits of randomness.
+fn to_unit_f64(hi: u32, lo: u32) -> f64 {
+    let v = ((hi as u64) << 32 | lo as u64) >> 11;
+    v as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// A random number generator with an explicit state, this can be passed to the random tensor
+/// constructors such as [`crate::Tensor::rand_with_generator`] to get reproducible values.
+///
+/// ```rust
+/// use candle_core::{Device, Generator, Tensor};
+/// let mut gen = Generator::new(42);
+/// let a = Tensor::rand_with_generator(0f32, 1f32, 4, &Device::Cpu, &mut gen)?;
+/// let b = Tensor::rand_with_generator(0f32, 1f32, 4, &Device::Cpu, &mut gen)?;
+/// assert_ne!(a.to_vec1::<f32>()?, b.to_vec1::<f32>()?);
+///
+/// let mut gen = Generator::new(42);
+/// let c = Tensor::rand_with_generator(0f32, 1f32, 4, &Device::Cpu, &mut gen)?;
+/// assert_eq!(a.to_vec1::<f32>()?, c.to_vec1::<f32>()?);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generator {
+    seed: u64,
+    offset: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, offset: 0 }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The number of elements generated so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Moves the generator within its stream, e.g. to replay some draws.
+    pub fn set_offset(&mut self, offset: u64) {
+        self.offset = offset
+    }
+
+    // Two uniform values in [0, 1) for each of the next `elem_count` elements.
+    fn sample<T: WithDType>(&mut self, elem_count: usize, f: impl Fn(f64, f64) -> f64) -> Vec<T> {
+        let key = [self.seed as u32, (self.seed >> 32) as u32];
+        let data = (0..elem_count as u64)
+            .map(|idx| {
+                let ctr = self.offset.wrapping_add(idx);
+                let r = philox4x32_10([ctr as u32, (ctr >> 32) as u32, 0, 0], key);
+                T::from_f64(f(to_unit_f64(r[0], r[1]), to_unit_f64(r[2], r[3])))
+            })
+            .collect();
+        self.offset = self.offset.wrapping_add(elem_count as u64);
+        data
+    }
+
+    fn sample_storage(
+        &mut self,
+        shape: &Shape,
+        dtype: DType,
+        op: &'static str,
+        f: impl Fn(f64, f64) -> f64,
+    ) -> Result<CpuStorage> {
+        let elem_count = shape.elem_count();
+        let storage = match dtype {
+            DType::BF16 => CpuStorage::BF16(self.sample(elem_count, f)),
+            DType::F16 => CpuStorage::F16(self.sample(elem_count, f)),
+            DType::F32 => CpuStorage::F32(self.sample(elem_count, f)),
+            DType::F64 => CpuStorage::F64(self.sample(elem_count, f)),
+            DType::Bool
+            | DType::U8
+            | DType::U32
+            | DType::I8
+            | DType::I16
+            | DType::I32
+            | DType::I64
+            | DType::C64
+            | DType::C128 => Err(Error::UnsupportedDTypeForOp(dtype, op).bt())?,
+        };
+        Ok(storage)
+    }
+
+    pub(crate) fn rand_uniform(
+        &mut self,
+        shape: &Shape,
+        dtype: DType,
+        lo: f64,
+        up: f64,
+    ) -> Result<CpuStorage> {
+        self.sample_storage(shape, dtype, "rand_uniform", |u, _| lo + (up - lo) * u)
+    }
+
+    pub(crate) fn rand_normal(
+        &mut self,
+        shape: &Shape,
+        dtype: DType,
+        mean: f64,
+        std: f64,
+    ) -> Result<CpuStorage> {
+        // Box-Muller transform, 1 - u1 is used as u1 can be 0.
+        self.sample_storage(shape, dtype, "rand_normal", |u1, u2| {
+            let r = (-2. * (1. - u1).ln()).sqrt();
+            mean + std * r * (2. * std::f64::consts::PI * u2).cos()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn philox_known_answers() {
+        // Test vectors from the Random123 distribution.
+        assert_eq!(
+            philox4x32_10([0, 0, 0, 0], [0, 0]),
+            [0x6627e8d5, 0xe169c58d, 0xbc57ac4c, 0x9b00dbd8]
+        );
+        assert_eq!(
+            philox4x32_10([u32::MAX; 4], [u32::MAX; 2]),
+            [0x408f276d, 0x41c83b0e, 0xa20bc7c6, 0x6d5451fd]
+        );
+    }
+}
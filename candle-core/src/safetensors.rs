@@ -8,13 +8,21 @@ use std::path::Path;
 impl From<DType> for st::Dtype {
     fn from(value: DType) -> Self {
         match value {
+            DType::Bool => st::Dtype::BOOL,
             DType::U8 => st::Dtype::U8,
             DType::U32 => st::Dtype::U32,
+            DType::I8 => st::Dtype::I8,
+            DType::I16 => st::Dtype::I16,
+            DType::I32 => st::Dtype::I32,
             DType::I64 => st::Dtype::I64,
             DType::BF16 => st::Dtype::BF16,
             DType::F16 => st::Dtype::F16,
             DType::F32 => st::Dtype::F32,
             DType::F64 => st::Dtype::F64,
+            // Complex tensors are rejected by `save` and `save_safetensors`.
+            DType::C64 | DType::C128 => {
+                panic!("complex tensors are not supported by safetensors")
+            }
         }
     }
 }
@@ -23,8 +31,12 @@ impl TryFrom<st::Dtype> for DType {
     type Error = Error;
     fn try_from(value: st::Dtype) -> Result<Self> {
         match value {
+            st::Dtype::BOOL => Ok(DType::Bool),
             st::Dtype::U8 => Ok(DType::U8),
             st::Dtype::U32 => Ok(DType::U32),
+            st::Dtype::I8 => Ok(DType::I8),
+            st::Dtype::I16 => Ok(DType::I16),
+            st::Dtype::I32 => Ok(DType::I32),
             st::Dtype::I64 => Ok(DType::I64),
             st::Dtype::BF16 => Ok(DType::BF16),
             st::Dtype::F16 => Ok(DType::F16),
@@ -79,6 +91,7 @@ impl st::View for &Tensor {
 
 impl Tensor {
     pub fn save_safetensors<P: AsRef<Path>>(&self, name: &str, filename: P) -> Result<()> {
+        bail_on_complex(self)?;
         let data = [(name, self.clone())];
         Ok(st::serialize_to_file(data, &None, filename.as_ref())?)
     }
@@ -185,29 +198,42 @@ impl Tensor {
         device: &Device,
     ) -> Result<Self> {
         match dtype {
+            DType::Bool => convert_slice::<u8>(data, shape, device)?.to_dtype(DType::Bool),
             DType::U8 => convert_slice::<u8>(data, shape, device),
             DType::U32 => convert_slice::<u32>(data, shape, device),
+            DType::I8 => convert_slice::<i8>(data, shape, device),
+            DType::I16 => convert_slice::<i16>(data, shape, device),
+            DType::I32 => convert_slice::<i32>(data, shape, device),
             DType::I64 => convert_slice::<i64>(data, shape, device),
             DType::BF16 => convert_slice::<half::bf16>(data, shape, device),
             DType::F16 => convert_slice::<half::f16>(data, shape, device),
             DType::F32 => convert_slice::<f32>(data, shape, device),
             DType::F64 => convert_slice::<f64>(data, shape, device),
+            DType::C64 | DType::C128 => {
+                // Complex numbers are stored as pairs of floats, (real, imaginary).
+                let shape = [shape, &[2]].concat();
+                let t = match dtype {
+                    DType::C64 => convert_slice::<f32>(data, &shape, device)?,
+                    _ => convert_slice::<f64>(data, &shape, device)?,
+                };
+                t.view_as_complex()
+            }
         }
     }
 }
 
 fn convert(view: &st::TensorView<'_>, device: &Device) -> Result<Tensor> {
     match view.dtype() {
+        st::Dtype::BOOL => convert_::<u8>(view, device)?.to_dtype(DType::Bool),
         st::Dtype::U8 => convert_::<u8>(view, device),
+        st::Dtype::I8 => convert_::<i8>(view, device),
+        st::Dtype::I16 => convert_::<i16>(view, device),
         st::Dtype::U16 => {
             let conv = |x| Ok(u32::from(x));
             convert_with_cast_::<u16, u32, _>(view, device, conv)
         }
         st::Dtype::U32 => convert_::<u32>(view, device),
-        st::Dtype::I32 => {
-            let conv = |x| Ok(i64::from(x));
-            convert_with_cast_::<i32, i64, _>(view, device, conv)
-        }
+        st::Dtype::I32 => convert_::<i32>(view, device),
         st::Dtype::I64 => convert_::<i64>(view, device),
         st::Dtype::BF16 => convert_::<half::bf16>(view, device),
         st::Dtype::F16 => convert_::<half::f16>(view, device),
@@ -221,13 +247,20 @@ fn convert_back(tensor: &Tensor) -> Result<Vec<u8>> {
     // TODO: This makes an unnecessary copy when the tensor is on the cpu.
     let tensor = tensor.flatten_all()?;
     match tensor.dtype() {
+        DType::Bool => Ok(convert_back_::<u8>(tensor.to_dtype(DType::U8)?.to_vec1()?)),
         DType::U8 => Ok(convert_back_::<u8>(tensor.to_vec1()?)),
         DType::U32 => Ok(convert_back_::<u32>(tensor.to_vec1()?)),
+        DType::I8 => Ok(convert_back_::<i8>(tensor.to_vec1()?)),
+        DType::I16 => Ok(convert_back_::<i16>(tensor.to_vec1()?)),
+        DType::I32 => Ok(convert_back_::<i32>(tensor.to_vec1()?)),
         DType::I64 => Ok(convert_back_::<i64>(tensor.to_vec1()?)),
         DType::F16 => Ok(convert_back_::<half::f16>(tensor.to_vec1()?)),
         DType::BF16 => Ok(convert_back_::<half::bf16>(tensor.to_vec1()?)),
         DType::F32 => Ok(convert_back_::<f32>(tensor.to_vec1()?)),
         DType::F64 => Ok(convert_back_::<f64>(tensor.to_vec1()?)),
+        dtype @ (DType::C64 | DType::C128) => {
+            Err(Error::UnsupportedDTypeForOp(dtype, "safetensors").bt())
+        }
     }
 }
 
@@ -248,9 +281,22 @@ pub fn save<K: AsRef<str> + Ord + std::fmt::Display, P: AsRef<Path>>(
     tensors: &HashMap<K, Tensor>,
     filename: P,
 ) -> Result<()> {
+    for tensor in tensors.values() {
+        bail_on_complex(tensor)?
+    }
     Ok(st::serialize_to_file(tensors, &None, filename.as_ref())?)
 }
 
+// The safetensors format has no complex dtypes, `Tensor::view_as_real` can be used to store such
+// tensors.
+fn bail_on_complex(tensor: &Tensor) -> Result<()> {
+    let dtype = tensor.dtype();
+    if dtype.is_complex() {
+        Err(Error::UnsupportedDTypeForOp(dtype, "safetensors").bt())?
+    }
+    Ok(())
+}
+
 pub struct MmapedFile {
     path: std::path::PathBuf,
     inner: memmap2::Mmap,
@@ -310,4 +356,25 @@ mod tests {
         assert_eq!(bytes, b"x\0\0\0\0\0\0\0{\"t\":{\"dtype\":\"F32\",\"shape\":[2,2],\"data_offsets\":[0,16]},\"u\":{\"dtype\":\"F32\",\"shape\":[1,2],\"data_offsets\":[16,24]}}      \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0");
         std::fs::remove_file("multi.safetensors").unwrap();
     }
+
+    #[test]
+    fn save_load_int_and_bool_tensors() {
+        let t = Tensor::new(&[-1i32, 0, 7], &Device::Cpu).unwrap();
+        let b = t.to_dtype(DType::Bool).unwrap();
+        let i8 = t.to_dtype(DType::I8).unwrap();
+        let map: HashMap<_, _> = [("t", t), ("b", b), ("i8", i8)].into_iter().collect();
+        save(&map, "int_bool.safetensors").unwrap();
+
+        let weights = load("int_bool.safetensors", &Device::Cpu).unwrap();
+        let t = weights.get("t").unwrap();
+        assert_eq!(t.dtype(), DType::I32);
+        assert_eq!(t.to_vec1::<i32>().unwrap(), &[-1, 0, 7]);
+        let b = weights.get("b").unwrap();
+        assert_eq!(b.dtype(), DType::Bool);
+        let b = b.to_dtype(DType::U8).unwrap().to_vec1::<u8>().unwrap();
+        assert_eq!(b, &[1, 0, 1]);
+        let i8 = weights.get("i8").unwrap();
+        assert_eq!(i8.to_vec1::<i8>().unwrap(), &[-1, 0, 7]);
+        std::fs::remove_file("int_bool.safetensors").unwrap();
+    }
 }
@@ -0,0 +1,428 @@
+//! Sparse tensors in the coordinate (COO) and compressed sparse row (CSR) formats.
+//!
+//! A [`CooTensor`] stores the indices of its non-zero entries together with their values. The
+//! leading dimensions are sparse while the trailing ones can be dense, e.g. the gradient of an
+//! embedding table only has a few non-zero rows and each of these rows is a dense vector. A
+//! [`CsrTensor`] is a sparse matrix where the column indices are grouped by rows, this is the
+//! usual format for sparse-dense matrix multiplications.
+//!
+//! The conversions and products are expressed with `index_select` and `index_add` so that they
+//! run on all the devices and are differentiable with respect to the values and the dense
+//! operands.
+use crate::{DType, Device, Result, Shape, Tensor};
+use std::collections::BTreeMap;
+
+/// A sparse tensor in the coordinate format.
+///
+/// `indices` is a `u32` tensor of shape `(nnz, sparse_dims)` and `values` has shape
+/// `(nnz, dense_dims...)`, so that the full tensor has `sparse_dims + dense_dims.len()`
+/// dimensions. The indices can contain duplicates, the corresponding values are summed.
+///
+/// ```rust
+/// use candle_core::{sparse::CooTensor, Device, Tensor};
+/// let indices = Tensor::new(&[[0u32, 1], [2, 0]], &Device::Cpu)?;
+/// let values = Tensor::new(&[3f32, 4.], &Device::Cpu)?;
+/// let coo = CooTensor::new(&indices, &values, (3, 2))?;
+/// assert_eq!(coo.to_dense()?.to_vec2::<f32>()?, &[[0., 3.], [0., 0.], [4., 0.]]);
+///
+/// let rhs = Tensor::new(&[[1f32, 2.], [3., 4.]], &Device::Cpu)?;
+/// assert_eq!(coo.matmul(&rhs)?.to_vec2::<f32>()?, &[[9., 12.], [0., 0.], [4., 8.]]);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CooTensor {
+    indices: Tensor,
+    values: Tensor,
+    shape: Shape,
+}
+
+/// A sparse matrix in the compressed sparse row format.
+///
+/// The column indices and values of the non-zero entries of row `i` are at positions
+/// `row_offsets[i]..row_offsets[i + 1]` of `col_indices` and `values`.
+///
+/// ```rust
+/// use candle_core::{sparse::CsrTensor, Device, Tensor};
+/// let dense = Tensor::new(&[[0f32, 2.], [1., 0.], [0., 0.]], &Device::Cpu)?;
+/// let csr = CsrTensor::from_dense(&dense)?;
+/// assert_eq!(csr.row_offsets().to_vec1::<u32>()?, &[0, 1, 2, 2]);
+/// assert_eq!(csr.col_indices().to_vec1::<u32>()?, &[1, 0]);
+/// assert_eq!(csr.to_dense()?.to_vec2::<f32>()?, dense.to_vec2::<f32>()?);
+/// # Ok::<(), candle_core::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CsrTensor {
+    row_offsets: Tensor,
+    col_indices: Tensor,
+    values: Tensor,
+    shape: (usize, usize),
+}
+
+fn check_indices(indices: &Tensor) -> Result<Tensor> {
+    match indices.dtype() {
+        DType::U8 | DType::U32 | DType::I64 => indices.to_dtype(DType::I64),
+        dtype => crate::bail!("sparse indices should be u8, u32 or i64, got {dtype:?}"),
+    }
+}
+
+// Checks that the `i64` indices of shape `(nnz, dims.len())` are within `dims` and converts
+// them to `u32`.
+fn check_bounds(indices: &Tensor, dims: &[usize]) -> Result<Tensor> {
+    if let Some(d) = dims.iter().find(|&&d| d > u32::MAX as usize + 1) {
+        crate::bail!("sparse dimensions are limited to 2^32 elements, got {d}")
+    }
+    if indices.elem_count() > 0 {
+        let min = indices.min(0)?.to_vec1::<i64>()?;
+        let max = indices.max(0)?.to_vec1::<i64>()?;
+        for (i, &d) in dims.iter().enumerate() {
+            if min[i] < 0 || max[i] >= d as i64 {
+                crate::bail!(
+                    "sparse indices in [{}, {}] are out of bounds for dimension {i} of size {d}",
+                    min[i],
+                    max[i]
+                )
+            }
+        }
+    }
+    indices.to_dtype(DType::U32)?.contiguous()
+}
+
+// Converts the flattened positions over the sparse dimensions back to indices.
+fn unravel(linear: &[i64], sparse_dims: &[usize], device: &Device) -> Result<Tensor> {
+    let mut indices = Vec::with_capacity(linear.len() * sparse_dims.len());
+    for &l in linear.iter() {
+        let start = indices.len();
+        let mut l = l as usize;
+        for &d in sparse_dims.iter().rev() {
+            indices.push((l % d) as u32);
+            l /= d;
+        }
+        indices[start..].reverse();
+    }
+    Tensor::from_vec(indices, (linear.len(), sparse_dims.len()), device)
+}
+
+impl CooTensor {
+    /// Creates a sparse tensor from the indices of the non-zero entries and their values, see
+    /// [`CooTensor`] for the expected shapes.
+    pub fn new<S: Into<Shape>>(indices: &Tensor, values: &Tensor, shape: S) -> Result<Self> {
+        let shape = shape.into();
+        let indices = check_indices(indices)?;
+        let (nnz, sparse_dims) = indices.dims2()?;
+        let values_dims = values.dims();
+        if values_dims.is_empty()
+            || values_dims[0] != nnz
+            || sparse_dims + values_dims.len() - 1 != shape.rank()
+            || values_dims[1..] != shape.dims()[sparse_dims..]
+        {
+            crate::bail!(
+                "sparse values with shape {:?} do not match {nnz} indices for a tensor of shape {shape:?}",
+                values.shape()
+            )
+        }
+        if sparse_dims == 0 {
+            crate::bail!("sparse tensors should have at least one sparse dimension")
+        }
+        let indices = check_bounds(&indices, &shape.dims()[..sparse_dims])?;
+        let values = values.contiguous()?;
+        Ok(Self {
+            indices,
+            values,
+            shape,
+        })
+    }
+
+    /// Creates a sparse tensor from the non-zero entries of `t`, the first `sparse_dims`
+    /// dimensions are sparse and an entry is kept if any of its dense values is non-zero.
+    pub fn from_dense(t: &Tensor, sparse_dims: usize) -> Result<Self> {
+        let dims = t.dims();
+        if sparse_dims == 0 || sparse_dims > dims.len() {
+            crate::bail!("invalid number of sparse dims {sparse_dims} for shape {dims:?}")
+        }
+        let n = dims[..sparse_dims].iter().product::<usize>();
+        let dense_dims = &dims[sparse_dims..];
+        let flat = t.reshape((n, ()))?;
+        let non_zero = flat
+            .ne(0.)?
+            .to_dtype(DType::U32)?
+            .sum(1)?
+            .to_vec1::<u32>()?;
+        let linear: Vec<i64> = (0..n as i64)
+            .filter(|&i| non_zero[i as usize] > 0)
+            .collect();
+        let indices = unravel(&linear, &dims[..sparse_dims], t.device())?;
+        let rows = Tensor::new(linear.as_slice(), t.device())?;
+        let values_dims = [&[linear.len()], dense_dims].concat();
+        let values = flat.index_select(&rows, 0)?.reshape(values_dims)?;
+        Self::new(&indices, &values, dims)
+    }
+
+    /// The indices of the non-zero entries, a `u32` tensor of shape `(nnz, sparse_dims)`.
+    pub fn indices(&self) -> &Tensor {
+        &self.indices
+    }
+
+    /// The values of the non-zero entries, with shape `(nnz, dense_dims...)`.
+    pub fn values(&self) -> &Tensor {
+        &self.values
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    pub fn dims(&self) -> &[usize] {
+        self.shape.dims()
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.values.dtype()
+    }
+
+    pub fn device(&self) -> &Device {
+        self.values.device()
+    }
+
+    /// The number of stored entries, including duplicates.
+    pub fn nnz(&self) -> usize {
+        self.values.dim(0).unwrap_or(0)
+    }
+
+    /// The number of sparse dimensions.
+    pub fn sparse_dims(&self) -> usize {
+        self.indices.dim(1).unwrap_or(0)
+    }
+
+    // The position of each entry when flattening the sparse dimensions, as `i64` as the number
+    // of positions can exceed the range of `u32`.
+    fn linear_indices(&self) -> Result<Tensor> {
+        let sparse_dims = &self.dims()[..self.sparse_dims()];
+        let indices = self.indices.to_dtype(DType::I64)?;
+        if sparse_dims.len() == 1 {
+            return indices.flatten_all();
+        }
+        let mut strides = vec![1i64; sparse_dims.len()];
+        for i in (0..sparse_dims.len() - 1).rev() {
+            strides[i] = strides[i + 1] * sparse_dims[i + 1] as i64
+        }
+        let strides = Tensor::new(strides, self.device())?.unsqueeze(0)?;
+        indices.broadcast_mul(&strides)?.sum(1)
+    }
+
+    /// Converts to a dense tensor, the values of duplicate indices are summed.
+    pub fn to_dense(&self) -> Result<Tensor> {
+        let sparse_dims = self.sparse_dims();
+        let n = self.dims()[..sparse_dims].iter().product::<usize>();
+        let flat_dims = [&[n], &self.dims()[sparse_dims..]].concat();
+        Tensor::zeros(flat_dims, self.dtype(), self.device())?
+            .index_add(&self.linear_indices()?, &self.values, 0)?
+            .reshape(self.shape())
+    }
+
+    /// Returns an equivalent tensor where the indices are unique and sorted in lexicographic
+    /// order.
+    pub fn coalesce(&self) -> Result<Self> {
+        let linear = self.linear_indices()?.to_vec1::<i64>()?;
+        let mut slots = BTreeMap::new();
+        for &l in linear.iter() {
+            slots.insert(l, 0u32);
+        }
+        for (i, slot) in slots.values_mut().enumerate() {
+            *slot = i as u32
+        }
+        let positions: Vec<u32> = linear.iter().map(|l| slots[l]).collect();
+        let positions = Tensor::new(positions, self.device())?;
+        let unique: Vec<i64> = slots.into_keys().collect();
+        let sparse_dims = &self.dims()[..self.sparse_dims()];
+        let indices = unravel(&unique, sparse_dims, self.device())?;
+        let values_dims = [&[unique.len()], &self.values.dims()[1..]].concat();
+        let values = Tensor::zeros(values_dims, self.dtype(), self.device())?.index_add(
+            &positions,
+            &self.values,
+            0,
+        )?;
+        Self::new(&indices, &values, self.shape())
+    }
+
+    /// Concatenates the entries of two sparse tensors with the same shape, the result represents
+    /// their sum.
+    pub fn add(&self, rhs: &Self) -> Result<Self> {
+        if self.shape != rhs.shape || self.sparse_dims() != rhs.sparse_dims() {
+            Err(crate::Error::ShapeMismatchBinaryOp {
+                lhs: self.shape.clone(),
+                rhs: rhs.shape.clone(),
+                op: "sparse-add",
+            }
+            .bt())?
+        }
+        let indices = Tensor::cat(&[&self.indices, &rhs.indices], 0)?;
+        let values = Tensor::cat(&[&self.values, &rhs.values], 0)?;
+        Self::new(&indices, &values, self.shape())
+    }
+
+    /// Converts a sparse matrix to the CSR format.
+    pub fn to_csr(&self) -> Result<CsrTensor> {
+        let (rows, cols) = self.matrix_dims("to-csr")?;
+        let coo = self.coalesce()?;
+        let row_indices = coo
+            .indices
+            .narrow(1, 0, 1)?
+            .flatten_all()?
+            .to_vec1::<u32>()?;
+        let mut row_offsets = vec![0u32; rows + 1];
+        for &r in row_indices.iter() {
+            row_offsets[r as usize + 1] += 1
+        }
+        for r in 0..rows {
+            row_offsets[r + 1] += row_offsets[r]
+        }
+        let row_offsets = Tensor::new(row_offsets, self.device())?;
+        let col_indices = coo.indices.narrow(1, 1, 1)?.flatten_all()?;
+        CsrTensor::new(&row_offsets, &col_indices, &coo.values, (rows, cols))
+    }
+
+    fn matrix_dims(&self, op: &'static str) -> Result<(usize, usize)> {
+        match (self.dims(), self.sparse_dims()) {
+            (&[rows, cols], 2) => Ok((rows, cols)),
+            _ => crate::bail!(
+                "{op} expects a sparse matrix, got shape {:?} with {} sparse dims",
+                self.shape,
+                self.sparse_dims()
+            ),
+        }
+    }
+
+    /// Multiplies this sparse matrix by the dense matrix `rhs`, the result is dense.
+    pub fn matmul(&self, rhs: &Tensor) -> Result<Tensor> {
+        let (rows, cols) = self.matrix_dims("sparse-matmul")?;
+        let row_indices = self.indices.narrow(1, 0, 1)?.flatten_all()?;
+        let col_indices = self.indices.narrow(1, 1, 1)?.flatten_all()?;
+        spmm((rows, cols), &row_indices, &col_indices, &self.values, rhs)
+    }
+}
+
+// Sparse-dense matrix product, each non-zero entry scales a row of `rhs` that gets added to the
+// row of the output.
+fn spmm(
+    (rows, cols): (usize, usize),
+    row_indices: &Tensor,
+    col_indices: &Tensor,
+    values: &Tensor,
+    rhs: &Tensor,
+) -> Result<Tensor> {
+    let (k, n) = rhs.dims2()?;
+    if k != cols {
+        Err(crate::Error::ShapeMismatchBinaryOp {
+            lhs: (rows, cols).into(),
+            rhs: rhs.shape().clone(),
+            op: "sparse-matmul",
+        }
+        .bt())?
+    }
+    let src = rhs
+        .index_select(col_indices, 0)?
+        .broadcast_mul(&values.unsqueeze(1)?)?;
+    Tensor::zeros((rows, n), rhs.dtype(), rhs.device())?.index_add(row_indices, &src, 0)
+}
+
+impl CsrTensor {
+    /// Creates a sparse matrix with `rows` rows and `cols` columns, `row_offsets` has `rows + 1`
+    /// non-decreasing elements going from 0 to the number of values and `col_indices` has the
+    /// same number of elements as `values`.
+    pub fn new(
+        row_offsets: &Tensor,
+        col_indices: &Tensor,
+        values: &Tensor,
+        (rows, cols): (usize, usize),
+    ) -> Result<Self> {
+        let row_offsets = check_indices(row_offsets)?;
+        let col_indices = check_indices(col_indices)?;
+        let nnz = values.dims1()?;
+        if row_offsets.dims1()? != rows + 1 || col_indices.dims1()? != nnz {
+            crate::bail!(
+                "csr tensor with {rows} rows and {nnz} values got {} offsets and {} column indices",
+                row_offsets.elem_count(),
+                col_indices.elem_count()
+            )
+        }
+        let offsets = row_offsets.to_vec1::<i64>()?;
+        if offsets[0] != 0 || offsets[rows] != nnz as i64 || offsets.windows(2).any(|o| o[0] > o[1])
+        {
+            crate::bail!("csr row offsets should be non-decreasing, from 0 to {nnz}")
+        }
+        let row_offsets = check_bounds(&row_offsets.unsqueeze(1)?, &[nnz + 1])?.flatten_all()?;
+        let col_indices = check_bounds(&col_indices.unsqueeze(1)?, &[cols])?.flatten_all()?;
+        Ok(Self {
+            row_offsets,
+            col_indices,
+            values: values.contiguous()?,
+            shape: (rows, cols),
+        })
+    }
+
+    /// Creates a sparse matrix from the non-zero entries of a dense matrix.
+    pub fn from_dense(t: &Tensor) -> Result<Self> {
+        t.dims2()?;
+        CooTensor::from_dense(t, 2)?.to_csr()
+    }
+
+    pub fn row_offsets(&self) -> &Tensor {
+        &self.row_offsets
+    }
+
+    pub fn col_indices(&self) -> &Tensor {
+        &self.col_indices
+    }
+
+    pub fn values(&self) -> &Tensor {
+        &self.values
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.values.dtype()
+    }
+
+    pub fn device(&self) -> &Device {
+        self.values.device()
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.elem_count()
+    }
+
+    // The row of each stored entry.
+    fn row_indices(&self) -> Result<Tensor> {
+        let offsets = self.row_offsets.to_vec1::<u32>()?;
+        let mut rows = Vec::with_capacity(self.nnz());
+        for (row, o) in offsets.windows(2).enumerate() {
+            rows.extend(std::iter::repeat_n(row as u32, (o[1] - o[0]) as usize))
+        }
+        Tensor::new(rows, self.device())
+    }
+
+    /// Converts to the coordinate format.
+    pub fn to_coo(&self) -> Result<CooTensor> {
+        let indices = Tensor::stack(&[&self.row_indices()?, &self.col_indices], 1)?;
+        CooTensor::new(&indices, &self.values, self.shape)
+    }
+
+    pub fn to_dense(&self) -> Result<Tensor> {
+        self.to_coo()?.to_dense()
+    }
+
+    /// Multiplies this sparse matrix by the dense matrix `rhs`, the result is dense.
+    pub fn matmul(&self, rhs: &Tensor) -> Result<Tensor> {
+        let row_indices = self.row_indices()?;
+        spmm(
+            self.shape,
+            &row_indices,
+            &self.col_indices,
+            &self.values,
+            rhs,
+        )
+    }
+}
@@ -1,5 +1,5 @@
 use crate::backend::BackendStorage;
-use crate::op::{self, CmpOp, CustomOp1, CustomOp2, CustomOp3, ReduceOp};
+use crate::op::{self, CmpOp, CumulativeOp, CustomOp1, CustomOp2, CustomOp3, ReduceOp};
 use crate::{CpuStorage, CudaStorage, DType, Device, Error, Layout, Result, Shape};
 
 // We do not want to implement Clone on Storage as cloning may fail because of
@@ -35,6 +35,22 @@ impl Storage {
         }
     }
 
+    // Accounts for a storage that has just been allocated in the memory stats of its device.
+    pub(crate) fn track_alloc(&self) {
+        match self {
+            Self::Cpu(storage) => storage.track_alloc(),
+            Self::Cuda(storage) => storage.track_alloc(),
+        }
+    }
+
+    // Hands the storage back to its device once it is not used anymore.
+    pub(crate) fn release(self) {
+        match self {
+            Self::Cpu(storage) => storage.release(),
+            Self::Cuda(storage) => storage.release(),
+        }
+    }
+
     pub(crate) fn same_device(&self, rhs: &Self, op: &'static str) -> Result<()> {
         let lhs = self.device().location();
         let rhs = rhs.device().location();
@@ -68,6 +84,13 @@ impl Storage {
         }
     }
 
+    pub(crate) fn affine_inplace(&mut self, layout: &Layout, mul: f64, add: f64) -> Result<()> {
+        match self {
+            Storage::Cpu(storage) => storage.affine_inplace(layout, mul, add),
+            Self::Cuda(storage) => storage.affine_inplace(layout, mul, add),
+        }
+    }
+
     pub(crate) fn powf(&self, layout: &Layout, alpha: f64) -> Result<Self> {
         match self {
             Storage::Cpu(storage) => {
@@ -138,6 +161,76 @@ impl Storage {
         }
     }
 
+    pub(crate) fn cumulative_op(
+        &self,
+        op: CumulativeOp,
+        layout: &Layout,
+        dim: usize,
+    ) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.cumulative_op(op, layout, dim)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.cumulative_op(op, layout, dim)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
+    pub(crate) fn arg_sort(&self, layout: &Layout, dim: usize, descending: bool) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.arg_sort(layout, dim, descending)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.arg_sort(layout, dim, descending)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
+    pub(crate) fn fft(&self, layout: &Layout, dim: usize, inverse: bool) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.fft(layout, dim, inverse)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.fft(layout, dim, inverse)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
+    pub(crate) fn view_as_real(&self, layout: &Layout) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.view_as_real(layout)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.view_as_real(layout)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
+    pub(crate) fn view_as_complex(&self, layout: &Layout) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.view_as_complex(layout)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.view_as_complex(layout)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
     pub(crate) fn to_dtype(&self, layout: &Layout, dtype: DType) -> Result<Self> {
         match self {
             Storage::Cpu(storage) => {
@@ -210,6 +303,9 @@ impl Storage {
     }
 
     pub(crate) fn unary_impl<B: op::UnaryOpT>(&self, layout: &Layout) -> Result<Self> {
+        if !B::supports_dtype(self.dtype()) {
+            Err(Error::UnsupportedDTypeForOp(self.dtype(), B::NAME).bt())?
+        }
         match self {
             Storage::Cpu(storage) => {
                 let storage = storage.unary_impl::<B>(layout)?;
@@ -230,6 +326,9 @@ impl Storage {
     ) -> Result<Self> {
         self.same_device(rhs, B::NAME)?;
         self.same_dtype(rhs, B::NAME)?;
+        if !B::supports_dtype(self.dtype()) {
+            Err(Error::UnsupportedDTypeForOp(self.dtype(), B::NAME).bt())?
+        }
         match (self, rhs) {
             (Storage::Cpu(lhs), Storage::Cpu(rhs)) => {
                 let storage = lhs.binary_impl::<B>(rhs, lhs_layout, rhs_layout)?;
@@ -252,6 +351,37 @@ impl Storage {
         }
     }
 
+    pub(crate) fn binary_impl_inplace<B: op::BinaryOpT>(
+        &mut self,
+        rhs: &Self,
+        lhs_layout: &Layout,
+        rhs_layout: &Layout,
+    ) -> Result<()> {
+        self.same_device(rhs, B::NAME)?;
+        self.same_dtype(rhs, B::NAME)?;
+        if !B::supports_dtype(self.dtype()) {
+            Err(Error::UnsupportedDTypeForOp(self.dtype(), B::NAME).bt())?
+        }
+        match (self, rhs) {
+            (Storage::Cpu(lhs), Storage::Cpu(rhs)) => {
+                lhs.binary_impl_inplace::<B>(rhs, lhs_layout, rhs_layout)
+            }
+            (Self::Cuda(lhs), Self::Cuda(rhs)) => {
+                lhs.binary_impl_inplace::<B>(rhs, lhs_layout, rhs_layout)
+            }
+            (lhs, rhs) => {
+                // Should not happen because of the same device check above but we're defensive
+                // anyway.
+                Err(Error::DeviceMismatchBinaryOp {
+                    lhs: lhs.device().location(),
+                    rhs: rhs.device().location(),
+                    op: B::NAME,
+                }
+                .bt())
+            }
+        }
+    }
+
     pub(crate) fn conv1d(
         &self,
         l: &Layout,
@@ -279,6 +409,33 @@ impl Storage {
         }
     }
 
+    pub(crate) fn conv_transpose1d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConvTranspose1D,
+    ) -> Result<Self> {
+        self.same_device(kernel, "conv_transpose1d")?;
+        self.same_dtype(kernel, "conv_transpose1d")?;
+        match (self, &kernel) {
+            (Storage::Cpu(inp), Storage::Cpu(kernel)) => {
+                let s = inp.conv_transpose1d(l, kernel, kernel_l, params)?;
+                Ok(Self::Cpu(s))
+            }
+            (Storage::Cuda(inp), Storage::Cuda(kernel)) => {
+                let s = inp.conv_transpose1d(l, kernel, kernel_l, params)?;
+                Ok(Self::Cuda(s))
+            }
+            (lhs, rhs) => Err(Error::DeviceMismatchBinaryOp {
+                lhs: lhs.device().location(),
+                rhs: rhs.device().location(),
+                op: "conv_transpose1d",
+            }
+            .bt()),
+        }
+    }
+
     pub(crate) fn conv2d(
         &self,
         l: &Layout,
@@ -333,6 +490,33 @@ impl Storage {
         }
     }
 
+    pub(crate) fn conv3d(
+        &self,
+        l: &Layout,
+        kernel: &Self,
+        kernel_l: &Layout,
+        params: &crate::conv::ParamsConv3D,
+    ) -> Result<Self> {
+        self.same_device(kernel, "conv3d")?;
+        self.same_dtype(kernel, "conv3d")?;
+        match (self, &kernel) {
+            (Storage::Cpu(inp), Storage::Cpu(kernel)) => {
+                let s = inp.conv3d(l, kernel, kernel_l, params)?;
+                Ok(Self::Cpu(s))
+            }
+            (Storage::Cuda(inp), Storage::Cuda(kernel)) => {
+                let s = inp.conv3d(l, kernel, kernel_l, params)?;
+                Ok(Self::Cuda(s))
+            }
+            (lhs, rhs) => Err(Error::DeviceMismatchBinaryOp {
+                lhs: lhs.device().location(),
+                rhs: rhs.device().location(),
+                op: "conv3d",
+            }
+            .bt()),
+        }
+    }
+
     pub(crate) fn avg_pool2d(
         &self,
         layout: &Layout,
@@ -369,6 +553,42 @@ impl Storage {
         }
     }
 
+    pub(crate) fn avg_pool3d(
+        &self,
+        layout: &Layout,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.avg_pool3d(layout, kernel_size, stride)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.avg_pool3d(layout, kernel_size, stride)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
+    pub(crate) fn max_pool3d(
+        &self,
+        layout: &Layout,
+        kernel_size: (usize, usize, usize),
+        stride: (usize, usize, usize),
+    ) -> Result<Self> {
+        match self {
+            Storage::Cpu(storage) => {
+                let storage = storage.max_pool3d(layout, kernel_size, stride)?;
+                Ok(Self::Cpu(storage))
+            }
+            Self::Cuda(storage) => {
+                let storage = storage.max_pool3d(layout, kernel_size, stride)?;
+                Ok(Self::Cuda(storage))
+            }
+        }
+    }
+
     pub(crate) fn upsample_nearest1d(&self, layout: &Layout, sz: usize) -> Result<Self> {
         match self {
             Storage::Cpu(storage) => {
@@ -493,6 +713,29 @@ impl Storage {
         }
     }
 
+    pub(crate) fn index_add_inplace(
+        &mut self,
+        l: &Layout,
+        indexes: &Self,
+        indexes_l: &Layout,
+        source: &Self,
+        source_l: &Layout,
+        d: usize,
+    ) -> Result<()> {
+        self.same_device(indexes, "index-add")?;
+        self.same_device(source, "index-add")?;
+        self.same_dtype(source, "index-add")?;
+        match (self, indexes, source) {
+            (Self::Cpu(s), Self::Cpu(indexes), Self::Cpu(source)) => {
+                s.index_add_inplace(l, indexes, indexes_l, source, source_l, d)
+            }
+            (Self::Cuda(s), Self::Cuda(indexes), Self::Cuda(source)) => {
+                s.index_add_inplace(l, indexes, indexes_l, source, source_l, d)
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub(crate) fn index_select(
         &self,
         rhs: &Self,
@@ -564,4 +807,24 @@ impl Storage {
             .bt()),
         }
     }
+
+    // Both self and dst can be strided, the two layouts must have the same shape.
+    pub(crate) fn copy_strided(
+        &self,
+        dst: &mut Self,
+        src_l: &Layout,
+        dst_l: &Layout,
+    ) -> Result<()> {
+        self.same_dtype(dst, "copy")?;
+        match (self, dst) {
+            (Self::Cpu(src), Self::Cpu(dst)) => src.copy_strided(dst, src_l, dst_l),
+            (Self::Cuda(src), Self::Cuda(dst)) => src.copy_strided(dst, src_l, dst_l),
+            (lhs, rhs) => Err(Error::DeviceMismatchBinaryOp {
+                lhs: lhs.device().location(),
+                rhs: rhs.device().location(),
+                op: "copy",
+            }
+            .bt()),
+        }
+    }
 }
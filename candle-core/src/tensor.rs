@@ -2,12 +2,13 @@
 #![allow(clippy::redundant_closure_call)]
 use crate::backend::{BackendDevice, BackendStorage};
 use crate::op::{
-    BackpropOp, BinaryOp, CmpOp, CustomOp1, CustomOp2, CustomOp3, Op, ReduceOp, UnaryOp,
+    BackpropOp, BinaryOp, CmpOp, CumulativeOp, CustomOp1, CustomOp2, CustomOp3, Op, ReduceOp,
+    UnaryOp,
 };
 use crate::scalar::TensorOrScalar;
 use crate::shape::{Dim, Dims};
 use crate::{storage::Storage, DType, Device, Error, Layout, Result, Shape};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 /// Unique identifier for tensors.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -36,10 +37,13 @@ pub struct Tensor_ {
     // Ideally, we would use Arc<Storage> for tensors on which we don't plan on modifying the data
     // and Arc<Mutex<Storage>> for tensors where the data could be modified, e.g. variables but
     // that's tricky to encode in the current setup.
-    storage: Arc<RwLock<Storage>>,
+    storage: Arc<StorageCell>,
     layout: Layout,
     op: BackpropOp,
     is_variable: bool,
+    // The elementwise op recorded in lazy mode, the storage only gets computed once it is needed,
+    // see the `lazy` module.
+    pending: Mutex<Option<Op>>,
     dtype: DType,
     device: Device,
 }
@@ -78,29 +82,41 @@ impl std::ops::Deref for Tensor {
 }
 
 macro_rules! unary_op {
-    ($fn_name:ident, $op_name:ident) => {
+    ($(#[$attr:meta])* $fn_name:ident, $op_name:ident) => {
+        $(#[$attr])*
         pub fn $fn_name(&self) -> Result<Self> {
             let shape = self.shape();
+            let op = BackpropOp::new1(self, |s| Op::Unary(s, UnaryOp::$op_name));
+            let supported = <crate::op::$op_name as crate::op::UnaryOpT>::supports_dtype(self.dtype());
+            if supported && crate::lazy::should_record(self.device(), self.dtype()) {
+                let pending = Op::Unary(self.clone(), UnaryOp::$op_name);
+                return Ok(self.lazy_op(pending, shape.clone(), op));
+            }
             let storage = self
                 .storage()
                 .unary_impl::<crate::op::$op_name>(self.layout())?;
-            let op = BackpropOp::new1(self, |s| Op::Unary(s, UnaryOp::$op_name));
-            Ok(from_storage(storage, shape.clone(), op, false))
+            Ok(from_storage(storage, self.device(), shape.clone(), op, false))
         }
     };
 }
 
 macro_rules! binary_op {
-    ($fn_name:ident, $op_name:ident) => {
+    ($(#[$attr:meta])* $fn_name:ident, $op_name:ident) => {
+        $(#[$attr])*
         pub fn $fn_name(&self, rhs: &Self) -> Result<Self> {
             let shape = self.same_shape_binary_op(rhs, stringify!($fn_name))?;
+            let op = BackpropOp::new2(self, rhs, |t1, t2| Op::Binary(t1, t2, BinaryOp::$op_name));
+            let supported = <crate::op::$op_name as crate::op::BinaryOpT>::supports_dtype(self.dtype());
+            if supported && self.can_record_binary(rhs) {
+                let pending = Op::Binary(self.clone(), rhs.clone(), BinaryOp::$op_name);
+                return Ok(self.lazy_op(pending, shape.clone(), op));
+            }
             let storage = self.storage().binary_impl::<crate::op::$op_name>(
                 &*rhs.storage(),
                 self.layout(),
                 rhs.layout(),
             )?;
-            let op = BackpropOp::new2(self, rhs, |t1, t2| Op::Binary(t1, t2, BinaryOp::$op_name));
-            Ok(from_storage(storage, shape.clone(), op, false))
+            Ok(from_storage(storage, self.device(), shape.clone(), op, false))
         }
     };
 }
@@ -116,13 +132,43 @@ macro_rules! binary_op_scalar {
                     .broadcast_as(self.shape())?,
             };
             let shape = self.same_shape_binary_op(&rhs, stringify!($fn_name))?;
+            let op = BackpropOp::new2(self, &rhs, |t1, t2| Op::Binary(t1, t2, BinaryOp::$op_name));
+            let supported =
+                <crate::op::$op_name as crate::op::BinaryOpT>::supports_dtype(self.dtype());
+            if supported && self.can_record_binary(&rhs) {
+                let pending = Op::Binary(self.clone(), rhs.clone(), BinaryOp::$op_name);
+                return Ok(self.lazy_op(pending, shape.clone(), op));
+            }
             let storage = self.storage().binary_impl::<crate::op::$op_name>(
                 &*rhs.storage(),
                 self.layout(),
                 rhs.layout(),
             )?;
-            let op = BackpropOp::new2(self, &rhs, |t1, t2| Op::Binary(t1, t2, BinaryOp::$op_name));
-            Ok(from_storage(storage, shape.clone(), op, false))
+            Ok(from_storage(
+                storage,
+                self.device(),
+                shape.clone(),
+                op,
+                false,
+            ))
+        }
+    };
+}
+
+macro_rules! binary_op_inplace {
+    (
+        $(#[$attr:meta])* $fn_name:ident,
+        $(#[$out_attr:meta])* $out_fn_name:ident,
+        $op_name:ident
+    ) => {
+        $(#[$attr])*
+        pub fn $fn_name(&self, rhs: &Self) -> Result<()> {
+            self.binary_inplace::<crate::op::$op_name>(rhs, stringify!($fn_name))
+        }
+
+        $(#[$out_attr])*
+        pub fn $out_fn_name(&self, rhs: &Self, out: &Self) -> Result<()> {
+            self.binary_out::<crate::op::$op_name>(rhs, out, stringify!($out_fn_name))
         }
     };
 }
@@ -148,21 +194,114 @@ macro_rules! broadcast_binary_op {
     };
 }
 
+// Dropping a long chain of pending ops recursively could overflow the stack, the chain is unlinked
+// iteratively instead: the pending ops of the arguments that are only referenced by the dropped
+// op are taken out before these arguments get dropped.
+impl Drop for Tensor_ {
+    fn drop(&mut self) {
+        let pending = match self.pending.get_mut() {
+            Ok(pending) => pending.take(),
+            Err(err) => err.into_inner().take(),
+        };
+        let mut ops: Vec<Op> = pending.into_iter().collect();
+        while let Some(op) = ops.pop() {
+            let args = match op {
+                Op::Unary(arg, _) | Op::Affine { arg, .. } => vec![arg],
+                Op::Binary(lhs, rhs, _) => vec![lhs, rhs],
+                _ => vec![],
+            };
+            for arg in args {
+                if let Some(mut arg) = Arc::into_inner(arg.0) {
+                    if let Ok(Some(op)) = arg.pending.get_mut().map(|p| p.take()) {
+                        ops.push(op)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Tensor_ {
+    pub(crate) fn pending_op(&self) -> Option<Op> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    // The lock on the pending op is held until the storage has been written so that concurrent
+    // accesses cannot observe the placeholder storage.
+    pub(crate) fn realize_pending(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(op) = pending.take() {
+            let storage = crate::lazy::eval(&op, self.layout.shape(), self.dtype);
+            storage.track_alloc();
+            *self.storage.write().unwrap() = storage;
+        }
+    }
+}
+
+pub(crate) struct StorageGuard<'a> {
+    storage: std::sync::RwLockReadGuard<'a, Storage>,
+    _scope: crate::cpu_pool::Scope,
+}
+
+impl std::ops::Deref for StorageGuard<'_> {
+    type Target = Storage;
+
+    fn deref(&self) -> &Storage {
+        &self.storage
+    }
+}
+
+// The storage shared by a tensor and its views. It gets dropped together with the last tensor
+// using it, at which point the buffer is released so that it can be reused.
+pub(crate) struct StorageCell(RwLock<Storage>);
+
+impl std::ops::Deref for StorageCell {
+    type Target = RwLock<Storage>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for StorageCell {
+    fn drop(&mut self) {
+        let storage = match self.0.get_mut() {
+            Ok(storage) => storage,
+            Err(err) => err.into_inner(),
+        };
+        let empty = Storage::Cpu(crate::CpuStorage::U8(vec![]));
+        std::mem::replace(storage, empty).release()
+    }
+}
+
+// Wraps a newly allocated storage, the memory stats of its device account for it until the last
+// tensor using it is dropped.
+fn new_storage(storage: Storage) -> Arc<StorageCell> {
+    storage.track_alloc();
+    Arc::new(StorageCell(RwLock::new(storage)))
+}
+
 /// Creates a fresh tensor structure based on a storage and a shape, this uses contiguous strides.
 pub(crate) fn from_storage<S: Into<Shape>>(
     storage: Storage,
+    device: &Device,
     shape: S,
     op: BackpropOp,
     is_variable: bool,
 ) -> Tensor {
     let dtype = storage.dtype();
-    let device = storage.device();
+    // The storage only knows about its location, cpu devices may also carry a thread pool.
+    let device = match device {
+        Device::CpuPool(_) if matches!(storage, Storage::Cpu(_)) => device.clone(),
+        _ => storage.device(),
+    };
     let tensor_ = Tensor_ {
         id: TensorId::new(),
-        storage: Arc::new(RwLock::new(storage)),
+        storage: new_storage(storage),
         layout: Layout::contiguous(shape),
         op,
         is_variable,
+        pending: Mutex::new(None),
         dtype,
         device,
     };
@@ -180,10 +319,11 @@ impl Tensor {
         if is_variable {
             let shape = shape.into();
             let storage = device.ones(&shape, dtype)?;
-            Ok(from_storage(storage, shape, none, is_variable))
+            Ok(from_storage(storage, device, shape, none, is_variable))
         } else {
             let storage = device.ones(&crate::shape::SCALAR, dtype)?;
-            from_storage(storage, crate::shape::SCALAR, none, is_variable).broadcast_as(shape)
+            from_storage(storage, device, crate::shape::SCALAR, none, is_variable)
+                .broadcast_as(shape)
         }
     }
 
@@ -225,10 +365,11 @@ impl Tensor {
         if is_variable {
             let shape = shape.into();
             let storage = device.zeros(&shape, dtype)?;
-            Ok(from_storage(storage, shape, none, is_variable))
+            Ok(from_storage(storage, device, shape, none, is_variable))
         } else {
             let storage = device.zeros(&crate::shape::SCALAR, dtype)?;
-            from_storage(storage, crate::shape::SCALAR, none, is_variable).broadcast_as(shape)
+            from_storage(storage, device, crate::shape::SCALAR, none, is_variable)
+                .broadcast_as(shape)
         }
     }
 
@@ -269,7 +410,7 @@ impl Tensor {
         let s = s.into();
         let storage = device.rand_uniform(lo, up, &s)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, s, none, is_variable))
+        Ok(from_storage(storage, device, s, none, is_variable))
     }
 
     pub(crate) fn rand_f64_impl<S: Into<Shape>>(
@@ -283,7 +424,7 @@ impl Tensor {
         let s = s.into();
         let storage = device.rand_uniform_f64(lo, up, &s, dtype)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, s, none, is_variable))
+        Ok(from_storage(storage, device, s, none, is_variable))
     }
 
     /// Creates a new tensor initialized with values sampled uniformly between `lo` and `up`.
@@ -300,6 +441,21 @@ impl Tensor {
         Tensor::rand_f64_impl(lo, up, self.shape(), self.dtype(), self.device(), false)
     }
 
+    /// Same as [`Tensor::rand`] but the values are sampled using `gen` rather than the default
+    /// generator of the device. The values only depend on the state of `gen`, not on the device.
+    pub fn rand_with_generator<S: Into<Shape>, T: crate::FloatDType>(
+        lo: T,
+        up: T,
+        s: S,
+        device: &Device,
+        gen: &mut crate::Generator,
+    ) -> Result<Self> {
+        let s = s.into();
+        let storage =
+            device.rand_uniform_with_generator(gen, lo.to_f64(), up.to_f64(), &s, T::DTYPE)?;
+        Ok(from_storage(storage, device, s, BackpropOp::none(), false))
+    }
+
     pub(crate) fn randn_impl<S: Into<Shape>, T: crate::FloatDType>(
         mean: T,
         std: T,
@@ -310,7 +466,7 @@ impl Tensor {
         let s = s.into();
         let storage = device.rand_normal(mean, std, &s)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, s, none, is_variable))
+        Ok(from_storage(storage, device, s, none, is_variable))
     }
 
     pub(crate) fn randn_f64_impl<S: Into<Shape>>(
@@ -324,7 +480,7 @@ impl Tensor {
         let s = s.into();
         let storage = device.rand_normal_f64(mean, std, &s, dtype)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, s, none, is_variable))
+        Ok(from_storage(storage, device, s, none, is_variable))
     }
 
     pub fn randn_like(&self, mean: f64, stdev: f64) -> Result<Self> {
@@ -349,6 +505,21 @@ impl Tensor {
         Self::randn_impl(mean, std, s, device, false)
     }
 
+    /// Same as [`Tensor::randn`] but the values are sampled using `gen` rather than the default
+    /// generator of the device. The values only depend on the state of `gen`, not on the device.
+    pub fn randn_with_generator<S: Into<Shape>, T: crate::FloatDType>(
+        mean: T,
+        std: T,
+        s: S,
+        device: &Device,
+        gen: &mut crate::Generator,
+    ) -> Result<Self> {
+        let s = s.into();
+        let storage =
+            device.rand_normal_with_generator(gen, mean.to_f64(), std.to_f64(), &s, T::DTYPE)?;
+        Ok(from_storage(storage, device, s, BackpropOp::none(), false))
+    }
+
     pub(crate) fn new_impl<A: crate::device::NdArray>(
         array: A,
         shape: Shape,
@@ -362,7 +533,7 @@ impl Tensor {
         }
         let storage = device.storage(array)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, shape, none, is_variable))
+        Ok(from_storage(storage, device, shape, none, is_variable))
     }
 
     /// Creates a new tensor on the specified device using the content and shape of the input.
@@ -418,7 +589,7 @@ impl Tensor {
         }
         let storage = device.storage_owned(data)?;
         let none = BackpropOp::none();
-        Ok(from_storage(storage, shape, none, is_variable))
+        Ok(from_storage(storage, device, shape, none, is_variable))
     }
 
     /// Creates a new tensor initialized with values from the input vector. The number of elements
@@ -463,12 +634,61 @@ impl Tensor {
         self.is_variable || self.op.is_some()
     }
 
-    // TODO: Also make an inplace version or a pre-allocated? This could be tricky
-    // if this can create cycles in the compute graph.
     binary_op!(add, Add);
     binary_op!(mul, Mul);
     binary_op!(sub, Sub);
     binary_op!(div, Div);
+    binary_op_inplace!(
+        /// Adds `rhs` to `self` in place, avoiding the allocation of a new storage. `rhs` is
+        /// broadcasted to the shape of `self`.
+        ///
+        /// The storage of `self` is modified so all the tensors sharing this storage, e.g. views
+        /// obtained via `narrow` or `reshape`, see the change. In-place ops are not part of the
+        /// computation graph and return an error if `self` or `rhs` track gradients.
+        ///
+        /// ```rust
+        /// use candle_core::{Tensor, Device};
+        /// let a = Tensor::new(&[[0f32, 1.], [2., 3.]], &Device::Cpu)?;
+        /// a.add_(&Tensor::new(&[10f32, 20.], &Device::Cpu)?)?;
+        /// assert_eq!(a.to_vec2::<f32>()?, &[[10.0, 21.0], [12.0, 23.0]]);
+        /// # Ok::<(), candle_core::Error>(())
+        /// ```
+        add_,
+        /// Writes `self + rhs` to the preallocated tensor `out` which must have the same shape as
+        /// `self` and `rhs`. The same restrictions as for [`Tensor::add_`] apply to `out`.
+        ///
+        /// ```rust
+        /// use candle_core::{Tensor, Device, DType};
+        /// let a = Tensor::new(&[0f32, 1., 2.], &Device::Cpu)?;
+        /// let out = Tensor::zeros(3, DType::F32, &Device::Cpu)?.contiguous()?;
+        /// a.add_out(&a, &out)?;
+        /// assert_eq!(out.to_vec1::<f32>()?, &[0.0, 2.0, 4.0]);
+        /// # Ok::<(), candle_core::Error>(())
+        /// ```
+        add_out,
+        Add
+    );
+    binary_op_inplace!(
+        /// Multiplies `self` by `rhs` in place, see [`Tensor::add_`].
+        mul_,
+        /// Writes `self * rhs` to the preallocated tensor `out`, see [`Tensor::add_out`].
+        mul_out,
+        Mul
+    );
+    binary_op_inplace!(
+        /// Subtracts `rhs` from `self` in place, see [`Tensor::add_`].
+        sub_,
+        /// Writes `self - rhs` to the preallocated tensor `out`, see [`Tensor::add_out`].
+        sub_out,
+        Sub
+    );
+    binary_op_inplace!(
+        /// Divides `self` by `rhs` in place, see [`Tensor::add_`].
+        div_,
+        /// Writes `self / rhs` to the preallocated tensor `out`, see [`Tensor::add_out`].
+        div_out,
+        Div
+    );
     binary_op_scalar!(maximum, Maximum);
     binary_op_scalar!(minimum, Minimum);
     broadcast_binary_op!(broadcast_add, add);
@@ -477,6 +697,40 @@ impl Tensor {
     broadcast_binary_op!(broadcast_div, div);
     broadcast_binary_op!(broadcast_maximum, maximum);
     broadcast_binary_op!(broadcast_minimum, minimum);
+    binary_op!(
+        /// Element-wise power `self^rhs` for float tensors.
+        pow,
+        Pow
+    );
+    binary_op!(
+        /// Element-wise four-quadrant arctangent of `self / rhs` for float tensors.
+        atan2,
+        Atan2
+    );
+    binary_op!(
+        /// Element-wise remainder of the truncated division of `self` by `rhs`, the result has
+        /// the sign of `self`. Integer remainders by zero are set to zero.
+        fmod,
+        Fmod
+    );
+    binary_op!(
+        /// Element-wise bitwise and for integer and boolean tensors.
+        bitwise_and,
+        BitAnd
+    );
+    binary_op!(
+        /// Element-wise bitwise or for integer and boolean tensors.
+        bitwise_or,
+        BitOr
+    );
+    binary_op!(
+        /// Element-wise bitwise xor for integer and boolean tensors.
+        bitwise_xor,
+        BitXor
+    );
+    broadcast_binary_op!(broadcast_pow, pow);
+    broadcast_binary_op!(broadcast_atan2, atan2);
+    broadcast_binary_op!(broadcast_fmod, fmod);
 
     unary_op!(recip, Recip);
     unary_op!(neg, Neg);
@@ -490,6 +744,24 @@ impl Tensor {
     unary_op!(sqrt, Sqrt);
     unary_op!(gelu, Gelu);
     unary_op!(relu, Relu);
+    unary_op!(
+        /// Element-wise error function for float tensors.
+        erf,
+        Erf
+    );
+    unary_op!(floor, Floor);
+    unary_op!(ceil, Ceil);
+    unary_op!(
+        /// Element-wise rounding to the nearest integer, half-way cases are rounded to the
+        /// nearest even integer.
+        round,
+        Round
+    );
+    unary_op!(
+        /// Element-wise sign, i.e. -1, 0 or 1. NaNs are returned unchanged.
+        sign,
+        Sign
+    );
 
     /// Retrieves the single scalar value hold in the tensor. If the tensor contains multiple
     /// dimensions, an error is returned instead.
@@ -548,23 +820,207 @@ impl Tensor {
     /// # Ok::<(), candle_core::Error>(())
     /// ```
     pub fn affine(&self, mul: f64, add: f64) -> Result<Self> {
-        let storage = self.storage().affine(self.layout(), mul, add)?;
         let op = BackpropOp::new1(self, |arg| Op::Affine { arg, mul, add });
-        Ok(from_storage(storage, self.shape(), op, false))
+        if crate::lazy::should_record(self.device(), self.dtype()) {
+            let arg = self.clone();
+            return Ok(self.lazy_op(Op::Affine { arg, mul, add }, self.shape().clone(), op));
+        }
+        let storage = self.storage().affine(self.layout(), mul, add)?;
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
+    }
+
+    // In-place ops would create cycles in the computation graph so they are only available on
+    // tensors that do not track gradients.
+    fn check_inplace(&self, op: &'static str) -> Result<()> {
+        if self.track_op() {
+            let msg = "is not supported on tensors that track gradients";
+            Err(Error::InplaceOp { op, msg }.bt())?
+        }
+        let layout = self.layout();
+        if layout
+            .dims()
+            .iter()
+            .zip(layout.stride())
+            .any(|(&d, &s)| d > 1 && s == 0)
+        {
+            let msg = "cannot write to a broadcasted tensor";
+            Err(Error::InplaceOp { op, msg }.bt())?
+        }
+        Ok(())
+    }
+
+    // Broadcasts the source of an in-place op to the shape of the destination.
+    fn inplace_src(&self, src: &Self, op: &'static str) -> Result<Self> {
+        if src.track_op() {
+            let msg = "cannot read from a tensor that tracks gradients";
+            Err(Error::InplaceOp { op, msg }.bt())?
+        }
+        if src.shape() == self.shape() {
+            Ok(src.clone())
+        } else {
+            src.broadcast_as(self.shape())
+        }
+    }
+
+    fn binary_inplace<B: crate::op::BinaryOpT>(&self, rhs: &Self, op: &'static str) -> Result<()> {
+        self.check_inplace(op)?;
+        let rhs = self.inplace_src(rhs, op)?;
+        if self.same_storage(&rhs) {
+            // The values of rhs could be overwritten before being read, use a copy instead.
+            return self.binary_inplace::<B>(&rhs.copy()?, op);
+        }
+        let (mut storage, layout) = self.storage_mut_and_layout();
+        let rhs_storage = rhs.storage();
+        storage.binary_impl_inplace::<B>(&rhs_storage, layout, rhs.layout())
+    }
+
+    fn binary_out<B: crate::op::BinaryOpT>(
+        &self,
+        rhs: &Self,
+        out: &Self,
+        op: &'static str,
+    ) -> Result<()> {
+        self.same_shape_binary_op(rhs, op)?;
+        out.check_inplace(op)?;
+        out.same_shape_binary_op(self, op)?;
+        let rhs = out.inplace_src(rhs, op)?;
+        // rhs is copied as its values would be overwritten when copying self to out.
+        let rhs = if out.same_storage(&rhs) {
+            rhs.copy()?
+        } else {
+            rhs
+        };
+        if !out.same_storage(self) || out.layout() != self.layout() {
+            out.copy_from(self)?
+        }
+        out.binary_inplace::<B>(&rhs, op)
+    }
+
+    /// In-place version of [`Tensor::affine`], see [`Tensor::add_`].
+    pub fn affine_(&self, mul: f64, add: f64) -> Result<()> {
+        self.check_inplace("affine_")?;
+        let (mut storage, layout) = self.storage_mut_and_layout();
+        storage.affine_inplace(layout, mul, add)
+    }
+
+    /// Writes the result of [`Tensor::affine`] to the preallocated tensor `out`, see
+    /// [`Tensor::add_out`].
+    pub fn affine_out(&self, mul: f64, add: f64, out: &Self) -> Result<()> {
+        out.check_inplace("affine_out")?;
+        out.same_shape_binary_op(self, "affine_out")?;
+        if !out.same_storage(self) || out.layout() != self.layout() {
+            out.copy_from(self)?
+        }
+        out.affine_(mul, add)
+    }
+
+    /// Copies the values of `src` to `self` in place, `src` is broadcasted to the shape of
+    /// `self` and must have the same dtype. `self` can be a strided view of a larger tensor, e.g.
+    /// to update a slice of a preallocated cache, see [`Tensor::add_`] for the restrictions.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device, DType};
+    /// let cache = Tensor::zeros((2, 3), DType::F32, &Device::Cpu)?.contiguous()?;
+    /// let src = Tensor::new(&[[1f32], [2.]], &Device::Cpu)?;
+    /// cache.narrow(1, 1, 1)?.copy_from(&src)?;
+    /// assert_eq!(cache.to_vec2::<f32>()?, &[[0.0, 1.0, 0.0], [0.0, 2.0, 0.0]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn copy_from(&self, src: &Self) -> Result<()> {
+        self.check_inplace("copy_from")?;
+        let src = self.inplace_src(src, "copy_from")?;
+        if self.same_storage(&src) {
+            if self.layout() == src.layout() {
+                return Ok(());
+            }
+            return self.copy_from(&src.copy()?);
+        }
+        let (mut storage, layout) = self.storage_mut_and_layout();
+        let src_storage = src.storage();
+        src_storage.copy_strided(&mut storage, src.layout(), layout)
+    }
+
+    /// In-place version of [`Tensor::index_add`], the values of `source` are added to the
+    /// slices of `self` selected by `indexes` on dimension `dim`. See [`Tensor::add_`] for the
+    /// restrictions on in-place ops.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let table = Tensor::new(&[[0f32, 1.], [2., 3.], [4., 5.]], &Device::Cpu)?;
+    /// let ids = Tensor::new(&[2u32, 0], &Device::Cpu)?;
+    /// table.index_add_(&ids, &Tensor::new(&[[1f32, 1.], [-1., -1.]], &Device::Cpu)?, 0)?;
+    /// assert_eq!(table.to_vec2::<f32>()?, &[[-1.0, 0.0], [2.0, 3.0], [5.0, 6.0]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn index_add_<D: Dim>(&self, indexes: &Self, source: &Self, dim: D) -> Result<()> {
+        self.check_inplace("index_add_")?;
+        if source.track_op() {
+            let msg = "cannot read from a tensor that tracks gradients";
+            Err(Error::InplaceOp {
+                op: "index_add_",
+                msg,
+            }
+            .bt())?
+        }
+        self.index_add_inplace(indexes, source, dim)
+    }
+
+    // Also used to update variables, the checks on gradient tracking are left to the caller.
+    pub(crate) fn index_add_inplace<D: Dim>(
+        &self,
+        indexes: &Self,
+        source: &Self,
+        dim: D,
+    ) -> Result<()> {
+        let dim = dim.to_index(self.shape(), "index-add")?;
+        self.check_index_add(indexes, source, dim)?;
+        if self.same_storage(source) || self.same_storage(indexes) {
+            let (indexes, source) = (indexes.copy()?, source.copy()?);
+            return self.index_add_inplace(&indexes, &source, dim);
+        }
+        let (mut storage, layout) = self.storage_mut_and_layout();
+        let indexes_storage = indexes.storage();
+        let source_storage = source.storage();
+        storage.index_add_inplace(
+            layout,
+            &indexes_storage,
+            indexes.layout(),
+            &source_storage,
+            source.layout(),
+            dim,
+        )
     }
 
     /// Applies the Exponential Linear Unit (ELU) function on each element of the input tensor.
     pub fn elu(&self, alpha: f64) -> Result<Self> {
         let storage = self.storage().elu(self.layout(), alpha)?;
         let op = BackpropOp::new1(self, |t| Op::Elu(t, alpha));
-        Ok(from_storage(storage, self.shape(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
     }
 
     /// Raise the tensor to some float exponent `e`.
     pub fn powf(&self, e: f64) -> Result<Self> {
         let storage = self.storage().powf(self.layout(), e)?;
         let op = BackpropOp::new1(self, |t| Op::Powf(t, e));
-        Ok(from_storage(storage, self.shape(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
     }
 
     fn check_dim(&self, dim: usize, op: &'static str) -> Result<()> {
@@ -628,10 +1084,11 @@ impl Tensor {
             let layout = self.layout().narrow(dim, start, len)?;
             let tensor_ = Tensor_ {
                 id: TensorId::new(),
-                storage: self.storage.clone(),
+                storage: self.shared_storage(),
                 layout,
                 op,
                 is_variable: false,
+                pending: Mutex::new(None),
                 dtype: self.dtype,
                 device: self.device.clone(),
             };
@@ -667,7 +1124,7 @@ impl Tensor {
         let mut dims = self.dims().to_vec();
         dims[dim] = 1;
         let op = BackpropOp::new1(self, |arg| Op::Reduce(arg, op, dims.to_vec()));
-        let res = from_storage(storage, dims, op, false);
+        let res = from_storage(storage, self.device(), dims, op, false);
         if keepdim {
             Ok(res)
         } else {
@@ -685,7 +1142,7 @@ impl Tensor {
             dims[sum_dim] = 1
         }
         let op = BackpropOp::new1(self, |a| Op::Reduce(a, ReduceOp::Sum, dims.to_vec()));
-        let sum = from_storage(storage, dims, op, false);
+        let sum = from_storage(storage, self.device(), dims, op, false);
         if keepdim {
             Ok(sum)
         } else {
@@ -795,6 +1252,127 @@ impl Tensor {
         self.reduce_impl(dim, false, ReduceOp::ArgMin)
     }
 
+    fn cumulative_impl<D: Dim>(&self, dim: D, op: CumulativeOp) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), op.name())?;
+        let storage = self.storage().cumulative_op(op, self.layout(), dim)?;
+        let op = BackpropOp::new1(self, |arg| Op::Cumulative(arg, op, dim));
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
+    }
+
+    /// Returns the cumulative sum of the elements along the selected dimension. The resulting
+    /// tensor has the same shape as the input tensor.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[[0f32, 1., 2.], [3., 4., 5.]], &Device::Cpu)?;
+    /// let s = a.cumsum(1)?;
+    /// assert_eq!(s.to_vec2::<f32>()?, &[[0., 1., 3.], [3., 7., 12.]]);
+    /// let s = a.cumsum(0)?;
+    /// assert_eq!(s.to_vec2::<f32>()?, &[[0., 1., 2.], [3., 5., 7.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn cumsum<D: Dim>(&self, dim: D) -> Result<Self> {
+        self.cumulative_impl(dim, CumulativeOp::Sum)
+    }
+
+    /// Returns the cumulative product of the elements along the selected dimension. The
+    /// resulting tensor has the same shape as the input tensor.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[[1f32, 2., 3.], [4., 5., 6.]], &Device::Cpu)?;
+    /// let s = a.cumprod(1)?;
+    /// assert_eq!(s.to_vec2::<f32>()?, &[[1., 2., 6.], [4., 20., 120.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn cumprod<D: Dim>(&self, dim: D) -> Result<Self> {
+        self.cumulative_impl(dim, CumulativeOp::Prod)
+    }
+
+    /// Returns the cumulative maximum of the elements along the selected dimension, i.e. the
+    /// maximum of all the elements up to and including the current index. The resulting tensor
+    /// has the same shape as the input tensor.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[[1f32, 3., 2.], [4., 0., 6.]], &Device::Cpu)?;
+    /// let s = a.cummax(1)?;
+    /// assert_eq!(s.to_vec2::<f32>()?, &[[1., 3., 3.], [4., 4., 6.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn cummax<D: Dim>(&self, dim: D) -> Result<Self> {
+        self.cumulative_impl(dim, CumulativeOp::Max)
+    }
+
+    /// Returns the indexes that sort the tensor along the selected dimension, in ascending order
+    /// unless `descending` is set. The resulting tensor has the same shape as the input and uses
+    /// the `u32` dtype. The sort is stable so equal elements keep their relative order.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[[3f32, 1., 4.], [1., 5., 9.]], &Device::Cpu)?;
+    /// let i = a.arg_sort(1, false)?;
+    /// assert_eq!(i.to_vec2::<u32>()?, &[[1, 0, 2], [0, 1, 2]]);
+    /// let i = a.arg_sort(1, true)?;
+    /// assert_eq!(i.to_vec2::<u32>()?, &[[2, 0, 1], [2, 1, 0]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn arg_sort<D: Dim>(&self, dim: D, descending: bool) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "arg-sort")?;
+        let storage = self.storage().arg_sort(self.layout(), dim, descending)?;
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            BackpropOp::none(),
+            false,
+        ))
+    }
+
+    /// Sorts the tensor along the selected dimension, in ascending order unless `descending` is
+    /// set. This returns both the sorted values and the `u32` indexes of these values in the
+    /// original tensor. The values are gathered from the input so gradients flow back through
+    /// the indexes.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[3f32, 1., 4., 1., 5.], &Device::Cpu)?;
+    /// let (values, indexes) = a.sort(0, false)?;
+    /// assert_eq!(values.to_vec1::<f32>()?, &[1., 1., 3., 4., 5.]);
+    /// assert_eq!(indexes.to_vec1::<u32>()?, &[1, 3, 0, 2, 4]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn sort<D: Dim>(&self, dim: D, descending: bool) -> Result<(Self, Self)> {
+        let dim = dim.to_index(self.shape(), "sort")?;
+        let indexes = self.arg_sort(dim, descending)?;
+        let values = self.contiguous()?.gather(&indexes, dim)?;
+        Ok((values, indexes))
+    }
+
+    /// Returns the `k` largest elements along the selected dimension in descending order,
+    /// together with their `u32` indexes in the original tensor.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[[3f32, 1., 4., 1., 5.], [2., 7., 1., 8., 2.]], &Device::Cpu)?;
+    /// let (values, indexes) = a.topk(2, 1)?;
+    /// assert_eq!(values.to_vec2::<f32>()?, &[[5., 4.], [8., 7.]]);
+    /// assert_eq!(indexes.to_vec2::<u32>()?, &[[4, 2], [3, 1]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn topk<D: Dim>(&self, k: usize, dim: D) -> Result<(Self, Self)> {
+        let dim = dim.to_index(self.shape(), "topk")?;
+        let indexes = self.arg_sort(dim, true)?.narrow(dim, 0, k)?.contiguous()?;
+        let values = self.contiguous()?.gather(&indexes, dim)?;
+        Ok((values, indexes))
+    }
+
     /// Element-wise comparison between two tensors, e.g. equality, greater than, ... The actual
     /// comparison operation is specified by the `op` argument.
     ///
@@ -812,7 +1390,13 @@ impl Tensor {
             .storage()
             .cmp(op, &rhs.storage(), self.layout(), rhs.layout())?;
         let op = BackpropOp::new1(self, |a| Op::Cmp(a, op));
-        Ok(from_storage(storage, shape.dims(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            shape.dims(),
+            op,
+            false,
+        ))
     }
 
     /// Element-wise equality.
@@ -825,6 +1409,36 @@ impl Tensor {
         self.cmp(rhs, CmpOp::Ne)
     }
 
+    // Non-zero values are true, boolean tensors are returned unchanged.
+    fn to_bool(&self) -> Result<Self> {
+        if self.dtype() == DType::Bool {
+            Ok(self.clone())
+        } else {
+            self.ne(0f64)
+        }
+    }
+
+    /// Element-wise logical and, non-zero values are considered as true. The result uses the
+    /// `Bool` dtype.
+    pub fn logical_and(&self, rhs: &Self) -> Result<Self> {
+        self.to_bool()?.bitwise_and(&rhs.to_bool()?)
+    }
+
+    /// Element-wise logical or, see [`Tensor::logical_and`].
+    pub fn logical_or(&self, rhs: &Self) -> Result<Self> {
+        self.to_bool()?.bitwise_or(&rhs.to_bool()?)
+    }
+
+    /// Element-wise logical xor, see [`Tensor::logical_and`].
+    pub fn logical_xor(&self, rhs: &Self) -> Result<Self> {
+        self.to_bool()?.bitwise_xor(&rhs.to_bool()?)
+    }
+
+    /// Element-wise logical not, the result is true for the zero values.
+    pub fn logical_not(&self) -> Result<Self> {
+        self.to_bool()?.eq(0f64)
+    }
+
     /// Element-wise comparison with lower-than, the returned tensor uses value 1 where `self <
     /// rhs` and 0 otherwise.
     pub fn lt<T: TensorOrScalar>(&self, rhs: T) -> Result<Self> {
@@ -864,7 +1478,13 @@ impl Tensor {
         let storage = self
             .storage()
             .upsample_nearest1d(self.layout(), target_size)?;
-        Ok(from_storage(storage, (n, c, target_size), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, target_size),
+            op,
+            false,
+        ))
     }
 
     /// Alias for `interpolate1d`.
@@ -883,7 +1503,13 @@ impl Tensor {
         let storage = self
             .storage()
             .upsample_nearest2d(self.layout(), target_h, target_w)?;
-        Ok(from_storage(storage, (n, c, target_h, target_w), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, target_h, target_w),
+            op,
+            false,
+        ))
     }
 
     /// Alias for `interpolate2d`.
@@ -923,7 +1549,13 @@ impl Tensor {
         let storage = self
             .storage()
             .avg_pool2d(self.layout(), kernel_size, stride)?;
-        Ok(from_storage(storage, (n, c, h_out, w_out), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, h_out, w_out),
+            op,
+            false,
+        ))
     }
 
     /// 2D max pooling over an input tensor with multiple channels.
@@ -958,7 +1590,93 @@ impl Tensor {
         let storage = self
             .storage()
             .max_pool2d(self.layout(), kernel_size, stride)?;
-        Ok(from_storage(storage, (n, c, h_out, w_out), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, h_out, w_out),
+            op,
+            false,
+        ))
+    }
+
+    /// 3D average pooling over an input tensor with multiple channels.
+    ///
+    /// The input tensor should have five dimensions, `(batch, channels, d, h, w)`, the returned
+    /// tensor also has five dimensions, `(batch, channels, d', h', w')`. The pooling is performed
+    /// on the three last dimensions using a kernel of size `sz`.
+    pub fn avg_pool3d<T: crate::ToUsize3>(&self, sz: T) -> Result<Self> {
+        let sz = sz.to_usize3();
+        self.avg_pool3d_with_stride(sz, sz)
+    }
+
+    /// Same as `avg_pool3d` but with a `stride` that can be set to a value different from the
+    /// kernel size.
+    pub fn avg_pool3d_with_stride<T: crate::ToUsize3>(
+        &self,
+        kernel_size: T,
+        stride: T,
+    ) -> Result<Self> {
+        let kernel_size = kernel_size.to_usize3();
+        let stride = stride.to_usize3();
+        let (n, c, d, h, w) = self.dims5()?;
+        let d_out = (d - kernel_size.0) / stride.0 + 1;
+        let h_out = (h - kernel_size.1) / stride.1 + 1;
+        let w_out = (w - kernel_size.2) / stride.2 + 1;
+        let op = BackpropOp::new1(self, |arg| Op::AvgPool3D {
+            arg,
+            kernel_size,
+            stride,
+        });
+        let storage = self
+            .storage()
+            .avg_pool3d(self.layout(), kernel_size, stride)?;
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, d_out, h_out, w_out),
+            op,
+            false,
+        ))
+    }
+
+    /// 3D max pooling over an input tensor with multiple channels.
+    ///
+    /// The input tensor should have five dimensions, `(batch, channels, d, h, w)`, the returned
+    /// tensor also has five dimensions, `(batch, channels, d', h', w')`. The pooling is performed
+    /// on the three last dimensions using a kernel of size `sz`.
+    pub fn max_pool3d<T: crate::ToUsize3>(&self, sz: T) -> Result<Self> {
+        let sz = sz.to_usize3();
+        self.max_pool3d_with_stride(sz, sz)
+    }
+
+    /// Same as `max_pool3d` but with a `stride` that can be set to a value different from the
+    /// kernel size.
+    pub fn max_pool3d_with_stride<T: crate::ToUsize3>(
+        &self,
+        kernel_size: T,
+        stride: T,
+    ) -> Result<Self> {
+        let kernel_size = kernel_size.to_usize3();
+        let stride = stride.to_usize3();
+        let (n, c, d, h, w) = self.dims5()?;
+        let d_out = (d - kernel_size.0) / stride.0 + 1;
+        let h_out = (h - kernel_size.1) / stride.1 + 1;
+        let w_out = (w - kernel_size.2) / stride.2 + 1;
+        let op = BackpropOp::new1(self, |arg| Op::MaxPool3D {
+            arg,
+            kernel_size,
+            stride,
+        });
+        let storage = self
+            .storage()
+            .max_pool3d(self.layout(), kernel_size, stride)?;
+        Ok(from_storage(
+            storage,
+            self.device(),
+            (n, c, d_out, h_out, w_out),
+            op,
+            false,
+        ))
     }
 
     /// Returns the matrix-multiplication of the input tensor with the other provided tensor.
@@ -1008,7 +1726,7 @@ impl Tensor {
             rhs.layout(),
         )?;
         let op = BackpropOp::new2(self, rhs, Op::Matmul);
-        Ok(from_storage(storage, c_shape, op, false))
+        Ok(from_storage(storage, self.device(), c_shape, op, false))
     }
 
     /// Matrix-multiplication with broadcasting support.
@@ -1047,7 +1765,7 @@ impl Tensor {
             on_false.layout(),
         )?;
         let op = BackpropOp::new3(self, on_true, on_false, Op::WhereCond);
-        Ok(from_storage(storage, shape, op, false))
+        Ok(from_storage(storage, self.device(), shape, op, false))
     }
 
     /// Returns a tensor with the values from the `self` tensor at the index corresponding to the
@@ -1122,12 +1840,16 @@ impl Tensor {
         let op = BackpropOp::new3(self, indexes, source, |t1, t2, t3| {
             Op::ScatterAdd(t1, t2, t3, dim)
         });
-        Ok(from_storage(storage, self.shape(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
     }
 
-    /// Accumulate element from `source` at indexes `indexes` and add them to `self`.
-    pub fn index_add<D: Dim>(&self, indexes: &Self, source: &Self, dim: D) -> Result<Self> {
-        let dim = dim.to_index(self.shape(), "index-add")?;
+    fn check_index_add(&self, indexes: &Self, source: &Self, dim: usize) -> Result<()> {
         let source_dims = source.dims();
         let self_dims = self.dims();
         let mismatch = if source_dims.len() != self_dims.len() {
@@ -1160,6 +1882,13 @@ impl Tensor {
                 rhs: source.shape().clone(),
             })?
         }
+        Ok(())
+    }
+
+    /// Accumulate element from `source` at indexes `indexes` and add them to `self`.
+    pub fn index_add<D: Dim>(&self, indexes: &Self, source: &Self, dim: D) -> Result<Self> {
+        let dim = dim.to_index(self.shape(), "index-add")?;
+        self.check_index_add(indexes, source, dim)?;
         let storage = self.storage().index_add(
             self.layout(),
             &indexes.storage(),
@@ -1171,7 +1900,13 @@ impl Tensor {
         let op = BackpropOp::new3(self, indexes, source, |t1, t2, t3| {
             Op::IndexAdd(t1, t2, t3, dim)
         });
-        Ok(from_storage(storage, self.shape(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            self.shape(),
+            op,
+            false,
+        ))
     }
 
     /// Gather values across the target dimension.
@@ -1212,7 +1947,13 @@ impl Tensor {
             self.storage()
                 .gather(self.layout(), &indexes.storage(), indexes.layout(), dim)?;
         let op = BackpropOp::new2(self, indexes, |t1, t2| Op::Gather(t1, t2, dim));
-        Ok(from_storage(storage, indexes.shape(), op, false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            indexes.shape(),
+            op,
+            false,
+        ))
     }
 
     /// Select values for the input tensor at the target indexes across the specified dimension.
@@ -1242,7 +1983,29 @@ impl Tensor {
         let mut dims = self.dims().to_vec();
         dims[dim] = indexes_len;
         let op = BackpropOp::new2(self, indexes, |t1, t2| Op::IndexSelect(t1, t2, dim));
-        Ok(from_storage(storage, dims, op, false))
+        Ok(from_storage(storage, self.device(), dims, op, false))
+    }
+
+    /// Same as `index_select` on the first dimension but when `self` is a variable, its gradient
+    /// is a [`crate::sparse::CooTensor`] holding only the selected rows rather than a dense
+    /// tensor, see [`crate::backprop::GradStore::get_sparse`]. This avoids materializing dense
+    /// gradients for large embedding tables.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Var, Device};
+    /// let table = Var::new(&[[0f32, 1.], [2., 3.], [4., 5.]], &Device::Cpu)?;
+    /// let ids = Tensor::new(&[2u32, 2, 0], &Device::Cpu)?;
+    /// let grads = table.index_select_sparse_grad(&ids)?.sum_all()?.backward()?;
+    /// assert!(grads.get(&table).is_none());
+    /// let grad = grads.get_sparse(&table).unwrap();
+    /// assert_eq!(grad.nnz(), 3);
+    /// assert_eq!(grad.to_dense()?.to_vec2::<f32>()?, &[[1., 1.], [0., 0.], [2., 2.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn index_select_sparse_grad(&self, indexes: &Self) -> Result<Self> {
+        let ys = self.detach()?.index_select(indexes, 0)?;
+        let op = BackpropOp::new2(self, indexes, Op::SparseIndexSelect);
+        Ok(ys.with_op(op))
     }
 
     /// Returns an iterator over position of the elements in the storage when ranging over the
@@ -1544,10 +2307,11 @@ impl Tensor {
         let op = BackpropOp::new1(self, |t| Op::Transpose(t, dim1, dim2));
         let tensor_ = Tensor_ {
             id: TensorId::new(),
-            storage: self.storage.clone(),
+            storage: self.shared_storage(),
             layout: self.layout.transpose(dim1, dim2)?,
             op,
             is_variable: false,
+            pending: Mutex::new(None),
             dtype: self.dtype,
             device: self.device.clone(),
         };
@@ -1580,10 +2344,11 @@ impl Tensor {
         let op = BackpropOp::new1(self, |t| Op::Permute(t, dims.clone()));
         let tensor_ = Tensor_ {
             id: TensorId::new(),
-            storage: self.storage.clone(),
+            storage: self.shared_storage(),
             layout: self.layout.permute(&dims)?,
             op,
             is_variable: false,
+            pending: Mutex::new(None),
             dtype: self.dtype,
             device: self.device.clone(),
         };
@@ -1606,10 +2371,11 @@ impl Tensor {
         let op = BackpropOp::new1(self, Op::Copy);
         let tensor_ = Tensor_ {
             id: TensorId::new(),
-            storage: Arc::new(RwLock::new(self.storage().try_clone(self.layout())?)),
+            storage: new_storage(self.storage().try_clone(self.layout())?),
             layout: self.layout.clone(),
             op,
             is_variable: false,
+            pending: Mutex::new(None),
             dtype: self.dtype,
             device: self.device.clone(),
         };
@@ -1621,16 +2387,32 @@ impl Tensor {
     pub fn detach(&self) -> Result<Tensor> {
         let tensor_ = Tensor_ {
             id: TensorId::new(),
-            storage: self.storage.clone(),
+            storage: self.shared_storage(),
             layout: self.layout.clone(),
             op: BackpropOp::none(),
             is_variable: false,
+            pending: Mutex::new(None),
             dtype: self.dtype,
             device: self.device.clone(),
         };
         Ok(Tensor(Arc::new(tensor_)))
     }
 
+    // Returns a new tensor sharing the storage of this one but with a different op.
+    pub(crate) fn with_op(&self, op: BackpropOp) -> Tensor {
+        let tensor_ = Tensor_ {
+            id: TensorId::new(),
+            storage: self.shared_storage(),
+            layout: self.layout.clone(),
+            op,
+            is_variable: false,
+            pending: Mutex::new(None),
+            dtype: self.dtype,
+            device: self.device.clone(),
+        };
+        Tensor(Arc::new(tensor_))
+    }
+
     /// If the target device is the same as the tensor device, only a shallow copy is performed.
     pub fn to_device(&self, device: &Device) -> Result<Tensor> {
         if self.device().same_device(device) {
@@ -1638,24 +2420,28 @@ impl Tensor {
         } else {
             let storage = match (&*self.storage(), device) {
                 (Storage::Cpu(storage), Device::Cuda(cuda)) => {
-                    Storage::Cuda(cuda.storage_from_cpu_storage(storage)?)
+                    new_storage(Storage::Cuda(cuda.storage_from_cpu_storage(storage)?))
+                }
+                (Storage::Cuda(storage), Device::Cpu | Device::CpuPool(_)) => {
+                    new_storage(Storage::Cpu(storage.to_cpu_storage()?))
                 }
-                (Storage::Cuda(storage), Device::Cpu) => Storage::Cpu(storage.to_cpu_storage()?),
                 (Storage::Cuda(storage), Device::Cuda(cuda)) => {
                     // TODO: Avoid passing through the cpu storage here, especially if the gpu ids
                     // are the same.
                     let cpu_storage = storage.to_cpu_storage()?;
-                    Storage::Cuda(cuda.storage_from_cpu_storage(&cpu_storage)?)
+                    new_storage(Storage::Cuda(cuda.storage_from_cpu_storage(&cpu_storage)?))
                 }
-                (Storage::Cpu(storage), Device::Cpu) => Storage::Cpu(storage.clone()),
+                // Cpu devices only differ by their thread pool so the data can be shared.
+                (Storage::Cpu(_), Device::Cpu | Device::CpuPool(_)) => self.shared_storage(),
             };
             let op = BackpropOp::new1(self, Op::ToDevice);
             let tensor_ = Tensor_ {
                 id: TensorId::new(),
-                storage: Arc::new(RwLock::new(storage)),
+                storage,
                 layout: self.layout.clone(),
                 op,
                 is_variable: false,
+                pending: Mutex::new(None),
                 dtype: self.dtype,
                 device: device.clone(),
             };
@@ -1682,10 +2468,11 @@ impl Tensor {
     pub fn broadcast_as<S: Into<Shape>>(&self, shape: S) -> Result<Self> {
         let tensor_ = Tensor_ {
             id: TensorId::new(),
-            storage: self.storage.clone(),
+            storage: self.shared_storage(),
             layout: self.layout.broadcast_as(shape)?,
             op: BackpropOp::new1(self, Op::Broadcast),
             is_variable: false,
+            pending: Mutex::new(None),
             dtype: self.dtype,
             device: self.device.clone(),
         };
@@ -1714,7 +2501,88 @@ impl Tensor {
             let shape = self.shape();
             let storage = self.storage().to_dtype(self.layout(), dtype)?;
             let op = BackpropOp::new1(self, Op::ToDType);
-            Ok(from_storage(storage, shape.clone(), op, false))
+            Ok(from_storage(
+                storage,
+                self.device(),
+                shape.clone(),
+                op,
+                false,
+            ))
+        }
+    }
+
+    /// Returns a real tensor with an additional trailing dimension of size 2 holding the real and
+    /// imaginary parts of this complex tensor. Contrary to PyTorch, this copies the data.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let re = Tensor::new(&[1f32, 2.], &Device::Cpu)?;
+    /// let im = Tensor::new(&[3f32, 4.], &Device::Cpu)?;
+    /// let c = Tensor::complex(&re, &im)?;
+    /// let c = c.view_as_real()?;
+    /// assert_eq!(c.to_vec2::<f32>()?, &[[1., 3.], [2., 4.]]);
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn view_as_real(&self) -> Result<Self> {
+        let mut dims = self.dims().to_vec();
+        dims.push(2);
+        let storage = self.storage().view_as_real(self.layout())?;
+        let op = BackpropOp::new1(self, Op::ViewAsReal);
+        Ok(from_storage(storage, self.device(), dims, op, false))
+    }
+
+    /// Builds a complex tensor from a real tensor which last dimension has size 2 and holds the
+    /// real and imaginary parts. This is the inverse of `view_as_real`.
+    pub fn view_as_complex(&self) -> Result<Self> {
+        let mut dims = self.dims().to_vec();
+        match dims.pop() {
+            Some(2) => {}
+            _ => Err(Error::UnexpectedShape {
+                msg: "view_as_complex expects the last dimension to have size 2".to_string(),
+                expected: Shape::from(2),
+                got: self.shape().clone(),
+            }
+            .bt())?,
+        }
+        let storage = self.storage().view_as_complex(self.layout())?;
+        let op = BackpropOp::new1(self, Op::ViewAsComplex);
+        Ok(from_storage(storage, self.device(), dims, op, false))
+    }
+
+    /// Creates a complex tensor from two real tensors with the same shape holding respectively the
+    /// real and imaginary parts.
+    pub fn complex(re: &Self, im: &Self) -> Result<Self> {
+        Self::stack(&[re, im], re.rank())?.view_as_complex()
+    }
+
+    /// The real part of a complex tensor, real tensors are returned unchanged.
+    pub fn real(&self) -> Result<Self> {
+        if self.dtype().is_complex() {
+            self.view_as_real()?
+                .narrow(self.rank(), 0, 1)?
+                .squeeze(self.rank())
+        } else {
+            Ok(self.clone())
+        }
+    }
+
+    /// The imaginary part of a complex tensor.
+    pub fn imag(&self) -> Result<Self> {
+        if self.dtype().is_complex() {
+            self.view_as_real()?
+                .narrow(self.rank(), 1, 1)?
+                .squeeze(self.rank())
+        } else {
+            Err(Error::UnsupportedDTypeForOp(self.dtype(), "imag").bt())
+        }
+    }
+
+    /// The complex conjugate of a complex tensor, real tensors are returned unchanged.
+    pub fn conj(&self) -> Result<Self> {
+        if self.dtype().is_complex() {
+            Self::complex(&self.real()?, &self.imag()?.neg()?)
+        } else {
+            Ok(self.clone())
         }
     }
 
@@ -1729,7 +2597,13 @@ impl Tensor {
             self.storage()
                 .copy_strided_src(&mut storage, 0, self.layout())?;
             let op = BackpropOp::new1(self, Op::Copy);
-            Ok(from_storage(storage, shape.clone(), op, false))
+            Ok(from_storage(
+                storage,
+                self.device(),
+                shape.clone(),
+                op,
+                false,
+            ))
         }
     }
 
@@ -1740,7 +2614,13 @@ impl Tensor {
         let mut storage = self.device().zeros(&shape, self.dtype())?;
         self.storage()
             .copy_strided_src(&mut storage, 0, self.layout())?;
-        Ok(from_storage(storage, shape, BackpropOp::none(), true))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            shape,
+            BackpropOp::none(),
+            true,
+        ))
     }
 
     /// Reshape returns a tensor with the target shape provided that the number of elements of the
@@ -1781,10 +2661,11 @@ impl Tensor {
         if self.is_contiguous() {
             let tensor_ = Tensor_ {
                 id: TensorId::new(),
-                storage: self.storage.clone(),
+                storage: self.shared_storage(),
                 layout: Layout::contiguous_with_offset(shape, self.layout.start_offset()),
                 op,
                 is_variable: false,
+                pending: Mutex::new(None),
                 dtype: self.dtype,
                 device: self.device.clone(),
             };
@@ -1793,7 +2674,7 @@ impl Tensor {
             let mut storage = self.device().zeros(&shape, self.dtype())?;
             self.storage()
                 .copy_strided_src(&mut storage, 0, self.layout())?;
-            Ok(from_storage(storage, shape, op, false))
+            Ok(from_storage(storage, self.device(), shape, op, false))
         }
     }
 
@@ -1988,7 +2869,7 @@ impl Tensor {
             arg.storage()
                 .copy_strided_src(&mut storage, offset, arg.layout())?;
         }
-        Ok(from_storage(storage, shape, op, false))
+        Ok(from_storage(storage, device, shape, op, false))
     }
 
     /// Pad the input tensor using 0s along dimension `dim`. This adds `left` elements before the
@@ -2024,8 +2905,15 @@ impl Tensor {
         m.forward(self)
     }
 
-    pub(crate) fn storage(&self) -> std::sync::RwLockReadGuard<'_, Storage> {
-        self.storage.read().unwrap()
+    // While the returned guard is alive, the cpu kernels run on the thread pool of the tensor
+    // device if it has one.
+    pub(crate) fn storage(&self) -> StorageGuard<'_> {
+        let scope = crate::cpu_pool::scope(self.device.cpu_pool());
+        self.realize_pending();
+        StorageGuard {
+            storage: self.storage.read().unwrap(),
+            _scope: scope,
+        }
     }
 
     // If we extend the visibility of this function to be usable outside of this crate, we should
@@ -2033,26 +2921,94 @@ impl Tensor {
     pub(crate) fn storage_mut_and_layout(
         &self,
     ) -> (std::sync::RwLockWriteGuard<'_, Storage>, &Layout) {
+        // Pending tensors may read from this storage so they have to be computed before it gets
+        // modified.
+        crate::lazy::flush();
+        self.realize_pending();
         let storage = self.storage.write().unwrap();
         (storage, &self.layout)
     }
 
     /// The storage used by this tensor, together with the layout to use to access it safely.
     pub fn storage_and_layout(&self) -> (std::sync::RwLockReadGuard<'_, Storage>, &Layout) {
+        self.realize_pending();
         let storage = self.storage.read().unwrap();
         (storage, &self.layout)
     }
 
+    // The storage to use for a view on this tensor, a pending tensor is computed first so that
+    // the view does not share the placeholder storage.
+    fn shared_storage(&self) -> Arc<StorageCell> {
+        self.realize_pending();
+        self.storage.clone()
+    }
+
+    fn can_record_binary(&self, rhs: &Self) -> bool {
+        crate::lazy::should_record(self.device(), self.dtype())
+            && rhs.device().is_cpu()
+            && rhs.dtype() == self.dtype()
+    }
+
+    // Creates a tensor holding the elementwise op `pending` rather than its result. The storage
+    // is a placeholder until the tensor gets realized.
+    fn lazy_op(&self, pending: Op, shape: Shape, op: BackpropOp) -> Self {
+        let tensor_ = Tensor_ {
+            id: TensorId::new(),
+            storage: Arc::new(StorageCell(RwLock::new(Storage::Cpu(
+                crate::CpuStorage::F32(vec![]),
+            )))),
+            layout: Layout::contiguous(shape),
+            op,
+            is_variable: false,
+            pending: Mutex::new(Some(pending)),
+            dtype: self.dtype,
+            device: self.device.clone(),
+        };
+        let tensor = Tensor(Arc::new(tensor_));
+        crate::lazy::register(Arc::downgrade(&tensor.0));
+        tensor
+    }
+
+    /// Computes the value of a tensor created in lazy mode, see the [`crate::lazy`] module. The
+    /// returned tensor shares the storage of `self` and this is a no-op on realized tensors.
+    ///
+    /// ```rust
+    /// use candle_core::{Tensor, Device};
+    /// let a = Tensor::new(&[0f32, 1., 2.], &Device::Cpu)?;
+    /// let b = {
+    ///     let _lazy = candle_core::lazy::enable();
+    ///     a.exp()?.affine(2., 1.)?
+    /// };
+    /// assert!(!b.is_realized());
+    /// let b = b.realize()?;
+    /// assert!(b.is_realized());
+    /// # Ok::<(), candle_core::Error>(())
+    /// ```
+    pub fn realize(&self) -> Result<Self> {
+        self.realize_pending();
+        Ok(self.clone())
+    }
+
+    /// Returns false if the value of this tensor has been recorded in lazy mode and has not been
+    /// computed yet.
+    pub fn is_realized(&self) -> bool {
+        self.pending.lock().unwrap().is_none()
+    }
+
     pub(crate) fn same_storage(&self, rhs: &Self) -> bool {
-        let lhs: &RwLock<Storage> = self.storage.as_ref();
-        let rhs: &RwLock<Storage> = rhs.storage.as_ref();
-        std::ptr::eq(lhs, rhs)
+        Arc::ptr_eq(&self.storage, &rhs.storage)
     }
 
     /// Applies a unary custom op without backward support
     pub fn apply_op1_no_bwd<C: CustomOp1>(&self, c: &C) -> Result<Self> {
         let (storage, shape) = self.storage().apply_op1(self.layout(), c)?;
-        Ok(from_storage(storage, shape, BackpropOp::none(), false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            shape,
+            BackpropOp::none(),
+            false,
+        ))
     }
 
     /// Applies a binary custom op without backward support
@@ -2060,7 +3016,13 @@ impl Tensor {
         let (storage, shape) =
             self.storage()
                 .apply_op2(self.layout(), &rhs.storage(), rhs.layout(), c)?;
-        Ok(from_storage(storage, shape, BackpropOp::none(), false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            shape,
+            BackpropOp::none(),
+            false,
+        ))
     }
 
     /// Applies a ternary custom op without backward support
@@ -2073,7 +3035,13 @@ impl Tensor {
             t3.layout(),
             c,
         )?;
-        Ok(from_storage(storage, shape, BackpropOp::none(), false))
+        Ok(from_storage(
+            storage,
+            self.device(),
+            shape,
+            BackpropOp::none(),
+            false,
+        ))
     }
 
     /// Applies a unary custom op.
@@ -2082,7 +3050,7 @@ impl Tensor {
             .storage()
             .apply_op1(self.layout(), c.as_ref().as_ref())?;
         let op = BackpropOp::new1(self, |s| Op::CustomOp1(s, c.clone()));
-        Ok(from_storage(storage, shape, op, false))
+        Ok(from_storage(storage, self.device(), shape, op, false))
     }
 
     pub fn apply_op1<C: 'static + CustomOp1 + Send + Sync>(&self, c: C) -> Result<Self> {
@@ -2102,7 +3070,7 @@ impl Tensor {
             c.as_ref().as_ref(),
         )?;
         let op = BackpropOp::new2(self, rhs, |t1, t2| Op::CustomOp2(t1, t2, c.clone()));
-        Ok(from_storage(storage, shape, op, false))
+        Ok(from_storage(storage, self.device(), shape, op, false))
     }
 
     pub fn apply_op2<C: 'static + CustomOp2 + Send + Sync>(&self, r: &Self, c: C) -> Result<Self> {
@@ -2127,7 +3095,7 @@ impl Tensor {
         let op = BackpropOp::new3(self, t2, t3, |t1, t2, t3| {
             Op::CustomOp3(t1, t2, t3, c.clone())
         });
-        Ok(from_storage(storage, shape, op, false))
+        Ok(from_storage(storage, self.device(), shape, op, false))
     }
 
     pub fn apply_op3<C: 'static + CustomOp3 + Send + Sync>(
@@ -1,14 +1,6 @@
-use std::str::FromStr;
-
+/// Returns the number of threads used by the cpu kernels, see [`crate::cpu_pool`].
 pub fn get_num_threads() -> usize {
-    // Respond to the same environment variable as rayon.
-    match std::env::var("RAYON_NUM_THREADS")
-        .ok()
-        .and_then(|s| usize::from_str(&s).ok())
-    {
-        Some(x) if x > 0 => x,
-        Some(_) | None => num_cpus::get(),
-    }
+    crate::cpu_pool::num_threads()
 }
 
 pub fn has_accelerate() -> bool {
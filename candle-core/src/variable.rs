@@ -140,4 +140,16 @@ impl Var {
         src.copy_strided_src(&mut dst, layout.start_offset(), src_l)?;
         Ok(())
     }
+
+    /// Adds the values of `source` to the slices of the variable selected by `indexes` on
+    /// dimension `dim`, in place. This is used by the optimizers to only update the rows touched
+    /// by a sparse gradient, as for `set` the update is not part of the computation graph.
+    pub fn index_add_<D: crate::shape::Dim>(
+        &self,
+        indexes: &Tensor,
+        source: &Tensor,
+        dim: D,
+    ) -> Result<()> {
+        self.0.index_add_inplace(indexes, source, dim)
+    }
 }
@@ -482,8 +482,144 @@ fn conv2d_grad(dev: &Device) -> Result<()> {
     Ok(())
 }
 
+fn conv_transpose1d(dev: &Device) -> Result<()> {
+    let t = Tensor::new(&[1f32, 2., 3.], dev)?.reshape((1, 1, 3))?;
+    let w = Tensor::new(&[1f32, 1.], dev)?.reshape((1, 1, 2))?;
+    let res = t.conv_transpose1d(&w, 0, 0, 1, 1)?;
+    assert_eq!(res.to_vec3::<f32>()?, [[[1., 3., 5., 3.]]]);
+    let res = t.conv_transpose1d(&w, 0, 0, 2, 1)?;
+    assert_eq!(res.to_vec3::<f32>()?, [[[1., 1., 2., 2., 3., 3.]]]);
+
+    let res = t.conv_transpose1d(&w, 1, 1, 2, 1)?;
+    assert_eq!(res.to_vec3::<f32>()?, [[[1., 2., 2., 3., 3.]]]);
+
+    // Compare with conv_transpose2d using a height of 1.
+    let t = Tensor::randn(0f32, 1., (2, 4, 5), dev)?;
+    let w = Tensor::randn(0f32, 1., (4, 3, 3), dev)?;
+    let res = t.conv_transpose1d(&w, 0, 0, 2, 2)?;
+    let res2 = t
+        .unsqueeze(2)?
+        .conv_transpose2d(&w.unsqueeze(2)?, 0, 0, 2, 2)?
+        .squeeze(2)?;
+    assert_eq!(res.dims(), [2, 3, 13]);
+    let diff = (res - res2)?.abs()?.flatten_all()?.max(0)?;
+    assert!(diff.to_vec0::<f32>()? < 1e-4);
+    Ok(())
+}
+
+fn conv3d(dev: &Device) -> Result<()> {
+    let t = Tensor::ones((1, 1, 3, 3, 3), candle_core::DType::F32, dev)?;
+    let w = Tensor::ones((1, 1, 2, 2, 2), candle_core::DType::F32, dev)?;
+    let res = t.conv3d(&w, 0, 1, 1, 1)?;
+    assert_eq!(res.dims(), [1, 1, 2, 2, 2]);
+    assert_eq!(res.flatten_all()?.to_vec1::<f32>()?, [8.; 8]);
+    let res = t.conv3d(&w, 1, 2, 1, 1)?;
+    assert_eq!(res.dims(), [1, 1, 2, 2, 2]);
+    assert_eq!(
+        res.flatten_all()?.to_vec1::<f32>()?,
+        [1., 2., 2., 4., 2., 4., 4., 8.]
+    );
+
+    // Compare with conv2d using a depth of 1.
+    let t = Tensor::randn(0f32, 1., (2, 4, 1, 5, 6), dev)?;
+    let w = Tensor::randn(0f32, 1., (6, 2, 1, 3, 3), dev)?;
+    let res = t.conv3d(&w, 0, 1, 1, 2)?;
+    let res2 = t.squeeze(2)?.conv2d(&w.squeeze(2)?, 0, 1, 1, 2)?;
+    assert_eq!(res.dims(), [2, 6, 1, 3, 4]);
+    let diff = (res.squeeze(2)? - res2)?.abs()?.flatten_all()?.max(0)?;
+    assert!(diff.to_vec0::<f32>()? < 1e-4);
+    Ok(())
+}
+
+fn conv2d_padding(dev: &Device) -> Result<()> {
+    use candle_core::PaddingMode;
+
+    fn max_diff(a: &Tensor, b: &Tensor) -> Result<f32> {
+        Ok((a - b)?.abs()?.flatten_all()?.max(0)?.to_vec0::<f32>()?)
+    }
+
+    let t = Tensor::randn(0f32, 1., (1, 2, 5, 6), dev)?;
+    let w = Tensor::randn(0f32, 1., (3, 2, 3, 2), dev)?;
+
+    // Asymmetric zero padding.
+    let res = t.conv2d_with_padding(&w, (1, 2, 0, 3), PaddingMode::Zeros, (1, 1), (1, 1), 1)?;
+    let padded = t.pad_with_zeros(2, 1, 2)?.pad_with_zeros(3, 0, 3)?;
+    let res2 = padded.conv2d(&w, 0, 1, 1, 1)?;
+    assert_eq!(res.dims(), [1, 3, 6, 8]);
+    assert!(max_diff(&res, &res2)? < 1e-4);
+
+    // Reflect and replicate padding, compared against an explicitly padded input.
+    let t1 = Tensor::new(&[[[[1f32, 2., 3.], [4., 5., 6.]]]], dev)?;
+    let w1 = Tensor::new(&[[[[1f32]]]], dev)?;
+    let res = t1.conv2d_with_padding(&w1, (1, 0, 2, 1), PaddingMode::Reflect, (1, 1), (1, 1), 1)?;
+    assert_eq!(
+        res.i((0, 0))?.to_vec2::<f32>()?,
+        [
+            [6., 5., 4., 5., 6., 5.],
+            [3., 2., 1., 2., 3., 2.],
+            [6., 5., 4., 5., 6., 5.]
+        ]
+    );
+    let res =
+        t1.conv2d_with_padding(&w1, (1, 0, 2, 1), PaddingMode::Replicate, (1, 1), (1, 1), 1)?;
+    assert_eq!(
+        res.i((0, 0))?.to_vec2::<f32>()?,
+        [
+            [1., 1., 1., 2., 3., 3.],
+            [1., 1., 1., 2., 3., 3.],
+            [4., 4., 4., 5., 6., 6.]
+        ]
+    );
+    let h_idx = Tensor::new(&[2u32, 1, 0, 1, 2, 3, 4, 3], dev)?;
+    let w_idx = Tensor::new(&[1u32, 0, 1, 2, 3, 4, 5, 4], dev)?;
+    let padded = t.index_select(&h_idx, 2)?.index_select(&w_idx, 3)?;
+    let res = t.conv2d_with_padding(&w, (2, 1, 1, 1), PaddingMode::Reflect, (1, 1), (1, 1), 1)?;
+    let res2 = padded.conv2d(&w, 0, 1, 1, 1)?;
+    assert_eq!(res.dims(), [1, 3, 6, 7]);
+    assert!(max_diff(&res, &res2)? < 1e-4);
+    let h_idx = Tensor::new(&[0u32, 0, 0, 1, 2, 3, 4, 4], dev)?;
+    let w_idx = Tensor::new(&[0u32, 0, 1, 2, 3, 4, 5, 5], dev)?;
+    let padded = t.index_select(&h_idx, 2)?.index_select(&w_idx, 3)?;
+    let res = t.conv2d_with_padding(&w, (2, 1, 1, 1), PaddingMode::Replicate, (1, 1), (1, 1), 1)?;
+    let res2 = padded.conv2d(&w, 0, 1, 1, 1)?;
+    assert!(max_diff(&res, &res2)? < 1e-4);
+
+    // Per-axis stride, compared against a unit stride conv with every other row selected.
+    let res = t.conv2d_with_padding(&w, (1, 1, 1, 1), PaddingMode::Zeros, (2, 1), (1, 1), 1)?;
+    let full = t.conv2d(&w, 1, 1, 1, 1)?.contiguous()?;
+    let rows = Tensor::new(&[0u32, 2, 4], dev)?;
+    assert_eq!(res.dims(), [1, 3, 3, 7]);
+    assert!(max_diff(&res, &full.index_select(&rows, 2)?)? < 1e-4);
+
+    // Per-axis dilation, compared against a kernel with explicit holes along the width.
+    let res = t.conv2d_with_padding(&w, (0, 0, 0, 0), PaddingMode::Zeros, (1, 1), (1, 2), 1)?;
+    let w_dilated = w
+        .pad_with_zeros(3, 0, 1)?
+        .contiguous()?
+        .index_select(&Tensor::new(&[0u32, 2, 1], dev)?, 3)?;
+    let res2 = t.conv2d(&w_dilated, 0, 1, 1, 1)?;
+    assert_eq!(res.dims(), [1, 3, 3, 4]);
+    assert!(max_diff(&res, &res2)? < 1e-4);
+
+    // Gradients through asymmetric zero padding.
+    let t = candle_core::Var::from_tensor(&t)?;
+    let w = candle_core::Var::from_tensor(&w)?;
+    let res = t.conv2d_with_padding(&w, (1, 2, 0, 3), PaddingMode::Zeros, (1, 1), (1, 1), 1)?;
+    let grads = res.sqr()?.sum_all()?.backward()?;
+    let padded = t.pad_with_zeros(2, 1, 2)?.pad_with_zeros(3, 0, 3)?;
+    let res2 = padded.conv2d(&w, 0, 1, 1, 1)?;
+    let grads2 = res2.sqr()?.sum_all()?.backward()?;
+    for v in [&t, &w] {
+        let g = grads.get(v).expect("no grad");
+        let g2 = grads2.get(v).expect("no grad");
+        assert!(max_diff(g, g2)? < 1e-3);
+    }
+    Ok(())
+}
+
 test_device!(conv1d, conv1d_cpu, conv1d_gpu);
 test_device!(conv1d_small, conv1d_small_cpu, conv1d_small_gpu);
+test_device!(conv_transpose1d, conv_transpose1d_cpu, conv_transpose1d_gpu);
 test_device!(conv2d, conv2d_cpu, conv2d_gpu);
 test_device!(
     conv2d_non_square,
@@ -493,3 +629,5 @@ test_device!(
 test_device!(conv2d_small, conv2d_small_cpu, conv2d_small_gpu);
 test_device!(conv2d_smaller, conv2d_smaller_cpu, conv2d_smaller_gpu);
 test_device!(conv2d_grad, conv2d_grad_cpu, conv2d_grad_gpu);
+test_device!(conv2d_padding, conv2d_padding_cpu, conv2d_padding_gpu);
+test_device!(conv3d, conv3d_cpu, conv3d_gpu);
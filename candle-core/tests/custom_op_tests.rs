@@ -97,6 +97,12 @@ impl CustomOp1 for EluWithBackward {
         let bwd = arg.apply_op1(EluBackward { alpha })?;
         Ok(Some(grad_res.mul(&bwd)?))
     }
+
+    fn jvp(&self, arg: &Tensor, _res: &Tensor, tangent: &Tensor) -> Result<Tensor> {
+        let alpha = self.0.alpha;
+        let bwd = arg.apply_op1(EluBackward { alpha })?;
+        tangent.mul(&bwd)
+    }
 }
 
 #[test]
@@ -112,3 +118,26 @@ fn custom_op1_with_backward() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn custom_op1_with_jvp() -> Result<()> {
+    let cpu = &Device::Cpu;
+    let t = Tensor::new(&[-2f32, 0f32, 2f32], cpu)?;
+    let v = Tensor::new(&[1f32, 1f32, -3f32], cpu)?;
+    let (ys, ts) = candle_core::autograd::jvp(
+        |xs| Ok(vec![xs[0].apply_op1(EluWithBackward::new(2.))?]),
+        &[&t],
+        &[&v],
+    )?;
+    assert_eq!(to_vec1_round(&ys[0], 4)?, &[-1.7293, 0.0, 2.0]);
+    assert_eq!(to_vec1_round(&ts[0], 4)?, [0.2707, 1.0, -3.0]);
+
+    // Custom ops without a jvp hook cannot be used in forward mode.
+    let res = candle_core::autograd::jvp(
+        |xs| Ok(vec![xs[0].apply_op1(Elu { alpha: 1. })?]),
+        &[&t],
+        &[&v],
+    );
+    assert!(matches!(res, Err(Error::JvpNotSupported { .. })));
+    Ok(())
+}
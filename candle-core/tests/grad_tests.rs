@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use candle_core::{test_device, test_utils, Device, Shape, Tensor, Var};
+use candle_core::{autograd, test_device, test_utils, DType, Device, IndexOp, Shape, Tensor, Var};
 
 fn simple_grad(device: &Device) -> Result<()> {
     let x = Var::new(&[3f32, 1., 4.], device)?;
@@ -221,9 +221,507 @@ fn binary_grad(device: &Device) -> Result<()> {
     Ok(())
 }
 
+fn cumulative_grad(device: &Device) -> Result<()> {
+    let x = Var::new(&[3f32, 1., 4., 2.], device)?;
+    let x = x.as_tensor();
+    let w = Tensor::new(&[1f32, 2., 3., 4.], device)?;
+
+    let y = x.cumsum(0)?.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(x).context("no grad for x")?;
+    assert_eq!(y.to_scalar::<f32>()?, 75.);
+    assert_eq!(grad_x.to_vec1::<f32>()?, [10., 9., 7., 4.]);
+
+    let y = x.cumprod(0)?.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(x).context("no grad for x")?;
+    // y = 1.x0 + 2.x0.x1 + 3.x0.x1.x2 + 4.x0.x1.x2.x3
+    assert_eq!(y.to_scalar::<f32>()?, 141.);
+    assert_eq!(grad_x.to_vec1::<f32>()?, [47., 138., 33., 48.]);
+
+    // Zero elements do not result in nan gradients.
+    let x0 = Var::new(&[3f32, 0., 4., 2.], device)?;
+    let y = x0.cumprod(0)?.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x0).context("no grad for x")?;
+    assert_eq!(y.to_scalar::<f32>()?, 3.);
+    assert_eq!(grad_x.to_vec1::<f32>()?, [1., 138., 0., 0.]);
+    let x0 = Var::new(&[[1f32, 0., 2.], [0., 0., 3.]], device)?;
+    let grads = x0.cumprod(1)?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x0).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec2::<f32>()?, [[1., 3., 0.], [1., 0., 0.]]);
+
+    let y = x.cummax(0)?.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(x).context("no grad for x")?;
+    // cummax = [3, 3, 4, 4]
+    assert_eq!(y.to_scalar::<f32>()?, 37.);
+    assert_eq!(grad_x.to_vec1::<f32>()?, [3., 0., 7., 0.]);
+
+    // Check the gradient along a non-leading dimension of a 2d tensor.
+    let x = Var::new(&[[1f32, 2., 3.], [4., 5., 6.]], device)?;
+    let y = x.cumsum(1)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec2::<f32>()?, [[3., 2., 1.], [3., 2., 1.]]);
+    Ok(())
+}
+
+fn sort_grad(device: &Device) -> Result<()> {
+    let x = Var::new(&[[3f32, 1., 4.], [1., 5., 9.]], device)?;
+    let w = Tensor::new(&[[1f32, 2., 3.], [4., 5., 6.]], device)?;
+    let (values, _indexes) = x.sort(1, false)?;
+    let y = values.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec2::<f32>()?, [[2., 1., 3.], [4., 5., 6.]]);
+
+    let (values, _indexes) = x.topk(2, 1)?;
+    let w = Tensor::new(&[[1f32, 2.], [3., 4.]], device)?;
+    let y = values.mul(&w)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec2::<f32>()?, [[2., 0., 1.], [0., 4., 3.]]);
+    Ok(())
+}
+
+fn fft_grad(device: &Device) -> Result<()> {
+    // Parseval: the sum of the squared moduli of the dft is `n` times the squared norm.
+    let x = Var::new(&[1f32, 2., -1., 0.5], device)?;
+    let y = x.fft(0)?.view_as_real()?.sqr()?.sum_all()?;
+    assert_eq!(y.to_scalar::<f32>()?, 25.);
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec1::<f32>()?, [8., 16., -8., 4.]);
+
+    let y = x.rfft(0)?.irfft(0, 4)?.mul(&x)?.sum_all()?;
+    let grads = y.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(test_utils::to_vec1_round(grad_x, 4)?, [2., 4., -2., 1.]);
+    Ok(())
+}
+
+/// Checks the gradient of `f` at `x` against central finite differences. The output of `f` is
+/// contracted with a fixed random tensor so that every output element contributes to the loss.
+fn check_grad_fd<F>(x: &Tensor, f: F) -> Result<()>
+where
+    F: Fn(&Tensor) -> candle_core::Result<Tensor>,
+{
+    let eps = 1e-5;
+    let x = x.to_dtype(DType::F64)?;
+    let weights = Tensor::randn(0f64, 1., f(&x)?.shape(), x.device())?;
+    let loss = |x: &Tensor| -> Result<f64> { Ok(f(x)?.mul(&weights)?.sum_all()?.to_scalar()?) };
+    let var = Var::from_tensor(&x)?;
+    let grads = f(&var)?.mul(&weights)?.sum_all()?.backward()?;
+    let grad = grads
+        .get(&var)
+        .context("no grad")?
+        .flatten_all()?
+        .to_vec1::<f64>()?;
+    let xs = x.flatten_all()?.to_vec1::<f64>()?;
+    for (i, g) in grad.iter().enumerate() {
+        let mut xs_plus = xs.clone();
+        xs_plus[i] += eps;
+        let mut xs_minus = xs.clone();
+        xs_minus[i] -= eps;
+        let plus = loss(&Tensor::from_vec(xs_plus, x.shape(), x.device())?)?;
+        let minus = loss(&Tensor::from_vec(xs_minus, x.shape(), x.device())?)?;
+        let fd = (plus - minus) / (2. * eps);
+        assert!(
+            (fd - g).abs() < 1e-5 * (1. + g.abs()),
+            "gradient mismatch at {i}: backprop {g}, finite differences {fd}"
+        );
+    }
+    Ok(())
+}
+
+fn conv_fd_grad(device: &Device) -> Result<()> {
+    let x = Tensor::randn(0f64, 1., (2, 4, 7), device)?;
+    let w = Tensor::randn(0f64, 1., (3, 4, 3), device)?;
+    for (padding, stride, dilation) in [(0, 1, 1), (1, 2, 1), (2, 1, 2), (1, 3, 2)] {
+        check_grad_fd(&x, |x| x.conv1d(&w, padding, stride, dilation, 1))?;
+        check_grad_fd(&w, |w| x.conv1d(w, padding, stride, dilation, 1))?;
+    }
+    let w = Tensor::randn(0f64, 1., (4, 2, 3), device)?;
+    check_grad_fd(&x, |x| x.conv1d(&w, 1, 1, 1, 2))?;
+    check_grad_fd(&w, |w| x.conv1d(w, 1, 1, 1, 2))?;
+
+    let x = Tensor::randn(0f64, 1., (2, 3, 3, 4), device)?;
+    let w = Tensor::randn(0f64, 1., (3, 2, 3, 2), device)?;
+    for (padding, out_padding, stride, dilation) in [(0, 0, 1, 1), (1, 1, 2, 1), (1, 0, 2, 2)] {
+        check_grad_fd(&x, |x| {
+            x.conv_transpose2d(&w, padding, out_padding, stride, dilation)
+        })?;
+        check_grad_fd(&w, |w| {
+            x.conv_transpose2d(w, padding, out_padding, stride, dilation)
+        })?;
+    }
+
+    let x = Tensor::randn(0f64, 1., (2, 3, 4), device)?;
+    let w = Tensor::randn(0f64, 1., (3, 2, 3), device)?;
+    check_grad_fd(&x, |x| x.conv_transpose1d(&w, 1, 1, 2, 2))?;
+    check_grad_fd(&w, |w| x.conv_transpose1d(w, 1, 1, 2, 2))?;
+    Ok(())
+}
+
+fn activation_fd_grad(device: &Device) -> Result<()> {
+    let x = Tensor::randn(0f64, 2., (3, 5), device)?;
+    check_grad_fd(&x, |x| x.gelu())?;
+    check_grad_fd(&x, |x| x.elu(0.7))?;
+
+    let x = Var::new(&[-2f32, -0.5, 0., 1., 3.], device)?;
+    let grads = x.elu(1.)?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(
+        test_utils::to_vec1_round(grad_x, 4)?,
+        [0.1353, 0.6065, 1., 1., 1.]
+    );
+    let grads = x.gelu()?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(
+        test_utils::to_vec1_round(grad_x, 4)?,
+        [-0.0861, 0.1326, 0.5, 1.083, 1.0116]
+    );
+    Ok(())
+}
+
+fn elementwise_fd_grad(device: &Device) -> Result<()> {
+    let x = Tensor::randn(0f64, 1., (3, 5), device)?;
+    let y = Tensor::randn(0f64, 1., (3, 5), device)?;
+    check_grad_fd(&x, |x| x.erf())?;
+    // A positive base keeps the gradient with respect to the exponent defined.
+    let base = (x.abs()? + 0.5)?;
+    check_grad_fd(&base, |b| b.pow(&y))?;
+    check_grad_fd(&y, |y| base.pow(y))?;
+    check_grad_fd(&x, |x| x.atan2(&y))?;
+    check_grad_fd(&y, |y| x.atan2(y))?;
+    // Small divisors would make the finite differences cross the discontinuities of fmod.
+    let y = (&y.abs()? + 0.5)?.mul(&y.sign()?)?;
+    check_grad_fd(&x, |x| x.fmod(&y))?;
+    check_grad_fd(&y, |y| x.fmod(y))?;
+
+    // The rounding ops are piecewise constant.
+    let x = Var::new(&[-1.5f32, 0.3, 2.5], device)?;
+    let y = (x.floor()? + x.ceil()? + x.round()? + x.sign()?)?;
+    let grads = (y + x.as_tensor())?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(grad_x.to_vec1::<f32>()?, [1., 1., 1.]);
+
+    // The forward mode matches the backward mode, i.e. <u, J.v> = <J^T.u, v>.
+    let x = (Tensor::randn(0f64, 1., (2, 4), device)?.abs()? + 0.5)?;
+    let y = Tensor::randn(0f64, 1., (2, 4), device)?;
+    let (tx, ty) = (x.randn_like(0., 1.)?, y.randn_like(0., 1.)?);
+    let f = |xs: &[Tensor]| {
+        let (x, y) = (&xs[0], &xs[1]);
+        let z = (x.pow(y)? + x.atan2(y)?.erf()? + y.fmod(x)? + x.round()?)?;
+        Ok(vec![z])
+    };
+    let (ys, ts) = autograd::jvp(f, &[&x, &y], &[&tx, &ty])?;
+    let u = ys[0].randn_like(0., 1.)?;
+    let (_, gs) = autograd::vjp(f, &[&x, &y], &[&u])?;
+    let lhs = u.mul(&ts[0])?.sum_all()?.to_scalar::<f64>()?;
+    let rhs = (gs[0].mul(&tx)?.sum_all()? + gs[1].mul(&ty)?.sum_all()?)?;
+    let rhs = rhs.to_scalar::<f64>()?;
+    assert!((lhs - rhs).abs() < 1e-8 * (1. + lhs.abs()), "{lhs} {rhs}");
+    Ok(())
+}
+
+fn pool_upsample_fd_grad(device: &Device) -> Result<()> {
+    let x = Tensor::randn(0f64, 1., (2, 2, 5, 6), device)?;
+    for (k, s) in [
+        ((2, 2), (2, 2)),
+        ((3, 3), (1, 1)),
+        ((3, 2), (2, 1)),
+        ((2, 3), (1, 2)),
+    ] {
+        check_grad_fd(&x, |x| x.avg_pool2d_with_stride(k, s))?;
+        check_grad_fd(&x, |x| x.max_pool2d_with_stride(k, s))?;
+    }
+    check_grad_fd(&x, |x| x.upsample_nearest2d(7, 13))?;
+    check_grad_fd(&x, |x| x.upsample_nearest2d(3, 4))?;
+    let x = Tensor::randn(0f64, 1., (2, 3, 5), device)?;
+    check_grad_fd(&x, |x| x.upsample_nearest1d(12))?;
+    check_grad_fd(&x, |x| x.upsample_nearest1d(3))?;
+
+    // With overlapping windows, an element that is the max of several windows accumulates
+    // the gradient of each of them.
+    let x = Var::new(&[[[[1f32, 2., 1.], [0., 5., 0.], [1., 2., 1.]]]], device)?;
+    let grads = x.max_pool2d_with_stride(2, 1)?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(
+        grad_x.i((0, 0))?.to_vec2::<f32>()?,
+        [[0., 0., 0.], [0., 4., 0.], [0., 0., 0.]]
+    );
+    let grads = x.avg_pool2d_with_stride(2, 1)?.sum_all()?.backward()?;
+    let grad_x = grads.get(&x).context("no grad for x")?;
+    assert_eq!(
+        grad_x.i((0, 0))?.to_vec2::<f32>()?,
+        [[0.25, 0.5, 0.25], [0.5, 1., 0.5], [0.25, 0.5, 0.25]]
+    );
+    Ok(())
+}
+
+fn second_order_grad(device: &Device) -> Result<()> {
+    let x = Var::new(&[1f32, 2., 3.], device)?;
+    let y = x.powf(3.)?.sum_all()?;
+    // The gradients returned by `backward` are detached.
+    let grads = y.backward()?;
+    let dy_dx = grads.get(&x).context("no grad for x")?;
+    assert_eq!(dy_dx.to_vec1::<f32>()?, [3., 12., 27.]);
+    assert!(dy_dx.sum_all()?.backward()?.get(&x).is_none());
+
+    let grads = y.backward_with_graph()?;
+    let dy_dx = grads.get(&x).context("no grad for x")?;
+    assert_eq!(dy_dx.to_vec1::<f32>()?, [3., 12., 27.]);
+    let grads = dy_dx.sum_all()?.backward()?;
+    let d2y_dx2 = grads.get(&x).context("no grad for x")?;
+    assert_eq!(d2y_dx2.to_vec1::<f32>()?, [6., 12., 18.]);
+
+    // Hessian-vector product, the hessian of sum(sin(x) * exp(x)) is diagonal.
+    let x = Var::new(&[0.5f32, -1., 2.], device)?;
+    let v = Tensor::new(&[1f32, 2., -1.], device)?;
+    let y = (x.sin()? * x.exp()?)?.sum_all()?;
+    let dy_dx = &autograd::grad_with_graph(&[&y], &[&x], None)?[0];
+    let hvp = &autograd::grad(&[&(dy_dx * &v)?.sum_all()?], &[&x], None)?[0];
+    // d2/dx2 sin(x).exp(x) = 2.cos(x).exp(x)
+    let expected = ((x.cos()? * x.exp()?)? * 2.)?.mul(&v)?;
+    assert_eq!(
+        test_utils::to_vec1_round(hvp, 4)?,
+        test_utils::to_vec1_round(&expected, 4)?
+    );
+
+    // Gradient penalty: with y = sum(w.x^2), |dy/dx|^2 = 4.sum(w^2.x^2) and its gradient with
+    // respect to w is 8.w.x^2.
+    let x = Var::new(&[1f32, 2.], device)?;
+    let w = Var::new(&[3f32, -1.], device)?;
+    let y = (w.as_tensor() * x.sqr()?)?.sum_all()?;
+    let dy_dx = &autograd::grad_with_graph(&[&y], &[&x], None)?[0];
+    let penalty = dy_dx.sqr()?.sum_all()?;
+    assert_eq!(penalty.to_scalar::<f32>()?, 52.);
+    let grads = penalty.backward()?;
+    let grad_w = grads.get(&w).context("no grad for w")?;
+    assert_eq!(grad_w.to_vec1::<f32>()?, [24., -32.]);
+    Ok(())
+}
+
+fn autograd_grad(device: &Device) -> Result<()> {
+    let x = Var::new(&[1f32, -2., 3.], device)?;
+    let w = Var::new(&[2f32, 1., 0.5], device)?;
+    let h = (x.as_tensor() * w.as_tensor())?;
+    let y1 = (&h * 3.)?;
+    let y2 = h.sqr()?.sum_all()?;
+    let unused = Tensor::new(&[1f32], device)?;
+
+    // Gradients are only returned for the requested tensors, intermediary ones included.
+    let grads = autograd::grad(&[&y2], &[&h, &x, &unused], None)?;
+    assert_eq!(grads.len(), 3);
+    assert_eq!(grads[0].to_vec1::<f32>()?, [4., -4., 3.]);
+    assert_eq!(grads[1].to_vec1::<f32>()?, [8., -4., 1.5]);
+    assert_eq!(grads[2].to_vec1::<f32>()?, [0.]);
+
+    // Multiple outputs with explicit output gradients, these get summed.
+    let g1 = Tensor::new(&[1f32, 0., -1.], device)?;
+    let g2 = Tensor::new(2f32, device)?;
+    let grads = autograd::grad(&[&y1, &y2], &[&w], Some(&[&g1, &g2]))?;
+    assert_eq!(grads[0].to_vec1::<f32>()?, [11., 16., 9.]);
+    assert!(autograd::grad(&[&y1], &[&w], Some(&[&g2])).is_err());
+    Ok(())
+}
+
+fn jvp_grad(device: &Device) -> Result<()> {
+    // Forward mode on a function with two inputs and two outputs.
+    let x = Tensor::new(&[1f32, 2., 3.], device)?;
+    let w = Tensor::new(&[0.5f32, -1., 2.], device)?;
+    let tx = Tensor::new(&[1f32, 0., -1.], device)?;
+    let tw = Tensor::new(&[0f32, 2., 1.], device)?;
+    let f = |xs: &[Tensor]| {
+        let h = (&xs[0] * &xs[1])?;
+        Ok(vec![h.sqr()?, (h.exp()? + &xs[0])?.sum_all()?])
+    };
+    let (ys, ts) = autograd::jvp(f, &[&x, &w], &[&tx, &tw])?;
+    assert_eq!(ys[0].to_vec1::<f32>()?, [0.25, 4., 36.]);
+    // dh = tx.w + x.tw = [0.5, 4., 1.], d(h^2) = 2.h.dh
+    assert_eq!(ts[0].to_vec1::<f32>()?, [0.5, -16., 12.]);
+    // d(sum(exp(h) + x)) = sum(exp(h).dh + tx)
+    let expected = 0.5 * 0.5f32.exp() + 4. * (-2f32).exp() + 6f32.exp();
+    assert!((ts[1].to_scalar::<f32>()? - expected).abs() < 1e-4);
+
+    // Zero elements do not result in nan tangents for cumprod.
+    let x0 = Tensor::new(&[3f32, 0., 4., 2.], device)?;
+    let f0 = |xs: &[Tensor]| Ok(vec![xs[0].cumprod(0)?]);
+    let (_, ts0) = autograd::jvp(f0, &[&x0], &[&x0.ones_like()?])?;
+    assert_eq!(ts0[0].to_vec1::<f32>()?, [1., 3., 12., 24.]);
+
+    // Tangents must match the primals.
+    assert!(autograd::jvp(f, &[&x, &w], &[&tx]).is_err());
+    assert!(autograd::jvp(f, &[&x, &w], &[&tx, &tw.i(..2)?]).is_err());
+
+    // The jvp and vjp are consistent with each other, i.e. <u, J.v> = <J^T.u, v>.
+    let x = Tensor::randn(0f64, 1., (2, 3, 6, 6), device)?;
+    let k = Tensor::randn(0f64, 1., (4, 3, 3, 3), device)?;
+    let tx = Tensor::randn(0f64, 1., (2, 3, 6, 6), device)?;
+    let tk = Tensor::randn(0f64, 1., (4, 3, 3, 3), device)?;
+    let f = |xs: &[Tensor]| {
+        let h = xs[0].conv2d(&xs[1], 1, 1, 1, 1)?.gelu()?;
+        let h = h.max_pool2d_with_stride(3, 2)?.tanh()?;
+        let h = Tensor::cat(&[&h, &h.sqr()?], 1)?;
+        let w = xs[1].reshape((4, 27))?;
+        let g = (w.matmul(&w.t()?)? * 0.1)?.exp()?;
+        let g = g.broadcast_div(&g.sum_keepdim(1)?)?;
+        Ok(vec![h.sum(3)?, g])
+    };
+    let (ys, ts) = autograd::jvp(f, &[&x, &k], &[&tx, &tk])?;
+    let us = ys
+        .iter()
+        .map(|y| y.randn_like(0., 1.))
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let (_, gs) = autograd::vjp(f, &[&x, &k], &us.iter().collect::<Vec<_>>())?;
+    let lhs = (us[0].mul(&ts[0])?.sum_all()? + us[1].mul(&ts[1])?.sum_all()?)?;
+    let rhs = (gs[0].mul(&tx)?.sum_all()? + gs[1].mul(&tk)?.sum_all()?)?;
+    let (lhs, rhs) = (lhs.to_scalar::<f64>()?, rhs.to_scalar::<f64>()?);
+    assert!((lhs - rhs).abs() < 1e-8 * (1. + lhs.abs()), "{lhs} {rhs}");
+    Ok(())
+}
+
+fn checkpoint_grad(device: &Device) -> Result<()> {
+    let x = Var::new(&[[1f32, -2., 0.5], [0.3, 2., -1.]], device)?;
+    let w = Var::new(&[[0.5f32, -1.], [2., 0.1], [-0.3, 1.]], device)?;
+    let block = {
+        let w = w.as_tensor().clone();
+        move |xs: &[Tensor]| xs[0].matmul(&w)?.tanh()?.sqr()?.exp()
+    };
+    let loss = |ys: &Tensor| (ys * 2.)?.sum_all();
+
+    let ys = block(&[x.as_tensor().clone()])?;
+    let grads = loss(&ys)?.backward()?;
+    let ys_c = candle_core::checkpoint(block.clone(), &[&x])?;
+    assert_eq!(ys.to_vec2::<f32>()?, ys_c.to_vec2::<f32>()?);
+    let grads_c = loss(&ys_c)?.backward()?;
+    for v in [&x, &w] {
+        let g = grads.get(v).unwrap();
+        let g_c = grads_c.get(v).unwrap();
+        assert_eq!(g.to_vec2::<f32>()?, g_c.to_vec2::<f32>()?);
+    }
+
+    // The variables captured by the closure get their gradients even when no argument
+    // requires some, and the functional interface can target the checkpoint arguments.
+    let xs = x.as_tensor().detach()?;
+    let ys_c = candle_core::checkpoint(block.clone(), &[&xs])?;
+    let grads_c = loss(&ys_c)?.backward()?;
+    let g_w = grads.get(&w).unwrap().to_vec2::<f32>()?;
+    assert_eq!(grads_c.get(&w).unwrap().to_vec2::<f32>()?, g_w);
+    let g = autograd::grad(&[&loss(&ys_c)?], &[&xs, &w], None)?;
+    let g_x = grads.get(&x).unwrap().to_vec2::<f32>()?;
+    assert_eq!(g[0].to_vec2::<f32>()?, g_x);
+    assert_eq!(g[1].to_vec2::<f32>()?, g_w);
+
+    // Forward mode goes through the recomputation too.
+    let v = Tensor::new(&[[1f32, 0., -1.], [0.5, 0.5, 0.]], device)?;
+    let (_, ts) = autograd::jvp(|xs| Ok(vec![block(xs)?]), &[&xs], &[&v])?;
+    let f = block.clone();
+    let (_, ts_c) = autograd::jvp(
+        move |xs| Ok(vec![candle_core::checkpoint(f, &[&xs[0]])?]),
+        &[&xs],
+        &[&v],
+    )?;
+    assert_eq!(ts[0].to_vec2::<f32>()?, ts_c[0].to_vec2::<f32>()?);
+    Ok(())
+}
+
+fn einsum_fd_grad(device: &Device) -> Result<()> {
+    let q = Tensor::randn(0f64, 1., (2, 3, 4), device)?;
+    let k = Tensor::randn(0f64, 1., (2, 5, 4), device)?;
+    check_grad_fd(&q, |q| Tensor::einsum("bqd,bkd->bqk", &[q, &k]))?;
+    check_grad_fd(&k, |k| Tensor::einsum("bqd,bkd->bqk", &[&q, k]))?;
+    let m = Tensor::randn(0f64, 1., (3, 3), device)?;
+    check_grad_fd(&m, |m| Tensor::einsum("ii,ij->j", &[m, m]))?;
+    check_grad_fd(&q, |q| Tensor::einsum("...d,d->...", &[q, &k.i((0, 0))?]))?;
+    Ok(())
+}
+
+// The linalg functions only run on the cpu. The inputs are deterministic so that the
+// decompositions are smooth around them, and the losses only depend on the squares of the
+// singular vectors and eigenvectors as their signs are arbitrary.
+#[test]
+fn linalg_fd_grad() -> Result<()> {
+    use candle_core::linalg;
+    let device = &Device::Cpu;
+    let x = Tensor::arange(1f64, 19., device)?
+        .sqr()?
+        .reshape((2, 3, 3))?
+        .sin()?;
+    let eye = Tensor::new(&[[1f64, 0., 0.], [0., 1., 0.], [0., 0., 1.]], device)?;
+    let a = x.broadcast_add(&(&eye * 2.)?)?;
+    check_grad_fd(&a, linalg::det)?;
+    check_grad_fd(&a, linalg::inv)?;
+    let b = Tensor::arange(0f64, 12., device)?
+        .reshape((2, 3, 2))?
+        .cos()?;
+    check_grad_fd(&a, |a| linalg::solve(a, &b))?;
+    check_grad_fd(&b, |b| linalg::solve(&a, b))?;
+    check_grad_fd(&b.i((.., .., 0))?, |b| linalg::solve(&a, b))?;
+    let spd = |x: &Tensor| x.matmul(&x.t()?)?.broadcast_add(&eye);
+    check_grad_fd(&x, |x| linalg::cholesky(&spd(x)?))?;
+    check_grad_fd(&x, |x| {
+        let (w, v) = linalg::eigh(&(x + x.t()?)?)?;
+        Tensor::cat(&[w.unsqueeze(2)?, v.sqr()?], 2)
+    })?;
+    let tall = Tensor::arange(0f64, 24., device)?
+        .sqr()?
+        .reshape((2, 4, 3))?
+        .cos()?;
+    for x in [&tall, &tall.t()?, &x] {
+        check_grad_fd(x, |x| {
+            let (u, s, vt) = linalg::svd(x)?;
+            let u = u.sqr()?.flatten_from(1)?;
+            let vt = vt.sqr()?.flatten_from(1)?;
+            Tensor::cat(&[u, s, vt], 1)
+        })?;
+    }
+    for x in [&tall, &x] {
+        check_grad_fd(x, |x| {
+            let (q, r) = linalg::qr(x)?;
+            Tensor::cat(&[q.flatten_from(1)?, r.flatten_from(1)?], 1)
+        })?;
+    }
+    Ok(())
+}
+
 test_device!(simple_grad, simple_grad_cpu, simple_grad_gpu);
 test_device!(sum_grad, sum_grad_cpu, sum_grad_gpu);
 test_device!(matmul_grad, matmul_grad_cpu, matmul_grad_gpu);
 test_device!(grad_descent, grad_descent_cpu, grad_descent_gpu);
 test_device!(unary_grad, unary_grad_cpu, unary_grad_gpu);
 test_device!(binary_grad, binary_grad_cpu, binary_grad_gpu);
+test_device!(cumulative_grad, cumulative_grad_cpu, cumulative_grad_gpu);
+test_device!(sort_grad, sort_grad_cpu, sort_grad_gpu);
+// Complex dtypes are only supported on the cpu.
+#[test]
+fn fft_grad_cpu() -> Result<()> {
+    fft_grad(&Device::Cpu)
+}
+test_device!(conv_fd_grad, conv_fd_grad_cpu, conv_fd_grad_gpu);
+test_device!(
+    activation_fd_grad,
+    activation_fd_grad_cpu,
+    activation_fd_grad_gpu
+);
+test_device!(
+    elementwise_fd_grad,
+    elementwise_fd_grad_cpu,
+    elementwise_fd_grad_gpu
+);
+test_device!(
+    pool_upsample_fd_grad,
+    pool_upsample_fd_grad_cpu,
+    pool_upsample_fd_grad_gpu
+);
+test_device!(
+    second_order_grad,
+    second_order_grad_cpu,
+    second_order_grad_gpu
+);
+test_device!(autograd_grad, autograd_grad_cpu, autograd_grad_gpu);
+test_device!(jvp_grad, jvp_grad_cpu, jvp_grad_gpu);
+test_device!(checkpoint_grad, checkpoint_grad_cpu, checkpoint_grad_gpu);
+test_device!(einsum_fd_grad, einsum_fd_grad_cpu, einsum_fd_grad_gpu);
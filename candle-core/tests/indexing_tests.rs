@@ -1,5 +1,5 @@
 use anyhow::Result;
-use candle_core::{Device, IndexOp, Tensor};
+use candle_core::{step, DType, Device, Ellipsis, IndexOp, NewAxis, Tensor, Var};
 
 #[test]
 fn integer_index() -> Result<()> {
@@ -91,3 +91,137 @@ fn index_3d() -> Result<()> {
     assert_eq!(tensor.i((1, .., 3))?.to_vec1::<u32>()?, &[15, 19, 23]);
     Ok(())
 }
+
+#[test]
+fn negative_and_step_index() -> Result<()> {
+    let tensor = Tensor::arange(0u32, 12, &Device::Cpu)?.reshape((3, 4))?;
+    assert_eq!(tensor.i(-1)?.to_vec1::<u32>()?, &[8, 9, 10, 11]);
+    assert_eq!(tensor.i((-2, -3))?.to_scalar::<u32>()?, 5);
+    assert!(tensor.i(-4).is_err());
+    assert_eq!(
+        tensor.i((.., -2..))?.to_vec2::<u32>()?,
+        &[[2, 3], [6, 7], [10, 11]]
+    );
+    #[allow(clippy::reversed_empty_ranges)]
+    let result = tensor.i((0, 1..-1))?;
+    assert_eq!(result.to_vec1::<u32>()?, &[1, 2]);
+    assert_eq!(tensor.i((0, ..=-2))?.to_vec1::<u32>()?, &[0, 1, 2]);
+    assert_eq!(tensor.i((0, ..=-1))?.to_vec1::<u32>()?, &[0, 1, 2, 3]);
+    // Out of range python slices are clamped.
+    assert_eq!(tensor.i((0, -10..2))?.to_vec1::<u32>()?, &[0, 1]);
+
+    assert_eq!(tensor.i((0, step(.., 2)))?.to_vec1::<u32>()?, &[0, 2]);
+    assert_eq!(tensor.i((0, step(1.., 2)))?.to_vec1::<u32>()?, &[1, 3]);
+    assert_eq!(tensor.i((0, step(.., 3)))?.to_vec1::<u32>()?, &[0, 3]);
+    assert_eq!(
+        tensor.i((0, step(.., -1)))?.to_vec1::<u32>()?,
+        &[3, 2, 1, 0]
+    );
+    assert_eq!(tensor.i((0, step(-2.., -2)))?.to_vec1::<u32>()?, &[2, 0]);
+    assert_eq!(tensor.i((0, step(..0, -1)))?.to_vec1::<u32>()?, &[3, 2, 1]);
+    assert_eq!(
+        tensor.i((step(.., -2), step(1..=3, 2)))?.to_vec2::<u32>()?,
+        &[[9, 11], [1, 3]]
+    );
+    assert!(tensor.i(step(.., 0)).is_err());
+    assert!(tensor.i(step(1, 2)).is_err());
+    Ok(())
+}
+
+#[test]
+fn new_axis_and_ellipsis() -> Result<()> {
+    let tensor = Tensor::arange(0u32, 24, &Device::Cpu)?.reshape((2, 3, 4))?;
+    assert_eq!(tensor.i(NewAxis)?.dims(), &[1, 2, 3, 4]);
+    assert_eq!(tensor.i((.., NewAxis, 1))?.dims(), &[2, 1, 4]);
+    assert_eq!(tensor.i((Ellipsis, NewAxis))?.dims(), &[2, 3, 4, 1]);
+    assert_eq!(
+        tensor.i((Ellipsis, -1))?.to_vec2::<u32>()?,
+        &[[3, 7, 11], [15, 19, 23]]
+    );
+    assert_eq!(tensor.i((1, Ellipsis, 2))?.to_vec1::<u32>()?, &[14, 18, 22]);
+    assert_eq!(tensor.i((1, Ellipsis, 1, 2))?.to_scalar::<u32>()?, 18);
+    assert_eq!(tensor.i((Ellipsis, step(.., 2)))?.dims(), &[2, 3, 2]);
+    assert!(tensor.i((Ellipsis, 0, Ellipsis)).is_err());
+    assert!(tensor.i((0, 0, 0, 0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn tensor_index() -> Result<()> {
+    let dev = &Device::Cpu;
+    let tensor = Tensor::arange(0u32, 12, dev)?.reshape((3, 4))?;
+    let idxs = Tensor::new(&[[2u32, 0], [1, 1]], dev)?;
+    let result = tensor.i(&idxs)?;
+    assert_eq!(result.dims(), &[2, 2, 4]);
+    assert_eq!(result.i((0, 0))?.to_vec1::<u32>()?, &[8, 9, 10, 11]);
+    let result = tensor.i((1.., &idxs))?;
+    assert_eq!(result.dims(), &[2, 2, 2]);
+    assert_eq!(result.i(1)?.to_vec2::<u32>()?, &[[10, 8], [9, 9]]);
+    // Signed indexes can be negative.
+    let idxs = Tensor::new(&[-1i64, 0, -4], dev)?;
+    assert_eq!(tensor.i((0, &idxs))?.to_vec1::<u32>()?, &[3, 0, 0]);
+    // A scalar tensor index drops the dimension.
+    let idx = Tensor::new(2u32, dev)?;
+    assert_eq!(tensor.i((.., &idx))?.to_vec1::<u32>()?, &[2, 6, 10]);
+    // The indexed tensor does not have to be contiguous.
+    assert_eq!(tensor.t()?.i(&idx)?.to_vec1::<u32>()?, &[2, 6, 10]);
+    Ok(())
+}
+
+#[test]
+fn mask_index() -> Result<()> {
+    let dev = &Device::Cpu;
+    let tensor = Tensor::arange(0f32, 12., dev)?.reshape((3, 4))?;
+    let mask = tensor
+        .ge(5f32)?
+        .where_cond(&tensor.lt(9f32)?, &tensor.lt(0f32)?)?;
+    assert_eq!(tensor.i(&mask)?.to_vec1::<f32>()?, &[5., 6., 7., 8.]);
+    let rows = Tensor::new(&[1u8, 0, 1], dev)?.to_dtype(DType::Bool)?;
+    assert_eq!(tensor.i(&rows)?.dims(), &[2, 4]);
+    assert_eq!(tensor.i((&rows, -1))?.to_vec1::<f32>()?, &[3., 11.]);
+    let cols = tensor.i(0)?.gt(1f32)?;
+    assert_eq!(
+        tensor.i((.., &cols))?.to_vec2::<f32>()?,
+        &[[2., 3.], [6., 7.], [10., 11.]]
+    );
+    assert!(tensor.i((.., &rows)).is_err());
+    Ok(())
+}
+
+#[test]
+fn slice_assign() -> Result<()> {
+    let dev = &Device::Cpu;
+    let tensor = Tensor::zeros((3, 4), DType::F32, dev)?;
+    let src = Tensor::new(&[[1f32, 2.], [3., 4.]], dev)?;
+    let result = tensor.slice_assign((1.., step(.., 2)), &src)?;
+    assert_eq!(
+        result.to_vec2::<f32>()?,
+        &[[0., 0., 0., 0.], [1., 0., 2., 0.], [3., 0., 4., 0.]]
+    );
+    let result = result.slice_assign((Ellipsis, -1), &Tensor::new(5f32, dev)?)?;
+    assert_eq!(result.i((.., 3))?.to_vec1::<f32>()?, &[5., 5., 5.]);
+    let result = result.slice_assign(&result.eq(0f32)?, &Tensor::new(-1f32, dev)?)?;
+    assert_eq!(
+        result.to_vec2::<f32>()?,
+        &[[-1., -1., -1., 5.], [1., -1., 2., 5.], [3., -1., 4., 5.]]
+    );
+    let idxs = Tensor::new(&[2u32, 0], dev)?;
+    let result = result.slice_assign((&idxs, 0), &Tensor::new(&[7f32, 8.], dev)?)?;
+    assert_eq!(result.i((.., 0))?.to_vec1::<f32>()?, &[8., 1., 7.]);
+    assert!(result.slice_assign(0, &src).is_err());
+
+    // The gradient flows to both the destination and the assigned values.
+    let dst = Var::new(&[1f32, 2., 3., 4.], dev)?;
+    let src = Var::new(&[10f32, 20.], dev)?;
+    let result = dst.slice_assign(step(1.., 2), &src)?;
+    assert_eq!(result.to_vec1::<f32>()?, &[1., 10., 3., 20.]);
+    let grads = (result * Tensor::new(&[1f32, 2., 3., 4.], dev)?)?
+        .sum_all()?
+        .backward()?;
+    assert_eq!(
+        grads.get(&dst).unwrap().to_vec1::<f32>()?,
+        &[1., 0., 3., 0.]
+    );
+    assert_eq!(grads.get(&src).unwrap().to_vec1::<f32>()?, &[2., 4.]);
+    Ok(())
+}
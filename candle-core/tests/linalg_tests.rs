@@ -0,0 +1,158 @@
+use anyhow::Result;
+use candle_core::{linalg, DType, Device, Tensor, D};
+
+fn max_diff(a: &Tensor, b: &Tensor) -> Result<f64> {
+    let diff = (a - b)?.abs()?.flatten_all()?.max(0)?;
+    Ok(diff.to_dtype(DType::F64)?.to_scalar::<f64>()?)
+}
+
+fn eye(n: usize) -> Result<Tensor> {
+    let vs: Vec<f64> = (0..n * n).map(|i| (i / n == i % n) as u8 as f64).collect();
+    Ok(Tensor::from_vec(vs, (n, n), &Device::Cpu)?)
+}
+
+#[test]
+fn det_inv_solve() -> Result<()> {
+    let device = &Device::Cpu;
+    let a = Tensor::new(&[[2f64, 1., 0.], [1., 3., 1.], [0., 1., 4.]], device)?;
+    assert!((linalg::det(&a)?.to_scalar::<f64>()? - 18.).abs() < 1e-12);
+    assert!(max_diff(&a.matmul(&linalg::inv(&a)?)?, &eye(3)?)? < 1e-12);
+
+    let a = Tensor::arange(1f64, 19., device)?
+        .sqr()?
+        .sin()?
+        .reshape((2, 3, 3))?;
+    let b = Tensor::arange(0f64, 12., device)?.reshape((2, 3, 2))?;
+    let x = linalg::solve(&a, &b)?;
+    assert_eq!(x.dims(), [2, 3, 2]);
+    assert!(max_diff(&a.matmul(&x)?, &b)? < 1e-12);
+    let x = linalg::solve(&a, &b.narrow(2, 0, 1)?.squeeze(2)?)?;
+    assert_eq!(x.dims(), [2, 3]);
+    assert!(max_diff(&a.matmul(&x.unsqueeze(2)?)?, &b.narrow(2, 0, 1)?)? < 1e-12);
+    let det = linalg::det(&a)?.to_vec1::<f64>()?;
+    let det_inv = linalg::det(&linalg::inv(&a)?)?.to_vec1::<f64>()?;
+    for (d, d_inv) in det.iter().zip(det_inv.iter()) {
+        assert!((d * d_inv - 1.).abs() < 1e-12)
+    }
+
+    // f32 inputs give f32 outputs.
+    let a32 = a.to_dtype(DType::F32)?;
+    assert_eq!(linalg::inv(&a32)?.dtype(), DType::F32);
+    assert!(max_diff(&linalg::inv(&a32)?, &linalg::inv(&a)?.to_dtype(DType::F32)?)? < 1e-5);
+
+    let singular = Tensor::new(&[[1f32, 2.], [2., 4.]], device)?;
+    assert_eq!(linalg::det(&singular)?.to_scalar::<f32>()?, 0.);
+    assert!(linalg::inv(&singular).is_err());
+    assert!(linalg::solve(&singular, &Tensor::new(&[1f32, 1.], device)?).is_err());
+    assert!(linalg::det(&Tensor::zeros((2, 3), DType::F32, device)?).is_err());
+    assert!(linalg::det(&Tensor::zeros((2, 2), DType::U32, device)?).is_err());
+    Ok(())
+}
+
+#[test]
+fn cholesky() -> Result<()> {
+    let device = &Device::Cpu;
+    let x = Tensor::arange(0f64, 18., device)?
+        .sqr()?
+        .sin()?
+        .reshape((2, 3, 3))?;
+    let a = x.matmul(&x.t()?)?.broadcast_add(&eye(3)?)?;
+    let l = linalg::cholesky(&a)?;
+    assert!(max_diff(&l.matmul(&l.t()?)?, &a)? < 1e-12);
+    let l = l.to_vec3::<f64>()?;
+    assert!(l
+        .iter()
+        .all(|l| l[0][1] == 0. && l[0][2] == 0. && l[1][2] == 0.));
+
+    let not_pd = Tensor::new(&[[1f64, 2.], [2., 1.]], device)?;
+    assert!(linalg::cholesky(&not_pd).is_err());
+    Ok(())
+}
+
+#[test]
+fn qr() -> Result<()> {
+    let device = &Device::Cpu;
+    let a = Tensor::arange(0f64, 24., device)?
+        .sqr()?
+        .cos()?
+        .reshape((2, 4, 3))?;
+    for a in [a.clone(), a.t()?] {
+        let (q, r) = linalg::qr(&a)?;
+        let (_, m, n) = a.dims3()?;
+        let k = m.min(n);
+        assert_eq!(q.dims(), [2, m, k]);
+        assert_eq!(r.dims(), [2, k, n]);
+        assert!(max_diff(&q.matmul(&r)?, &a)? < 1e-12);
+        assert!(
+            max_diff(
+                &q.t()?.matmul(&q)?,
+                &eye(k)?.unsqueeze(0)?.repeat((2, 1, 1))?
+            )? < 1e-12
+        );
+        for r in r.to_vec3::<f64>()? {
+            for (i, row) in r.iter().enumerate() {
+                assert!(row[..i].iter().all(|&v| v == 0.))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn svd() -> Result<()> {
+    let device = &Device::Cpu;
+    let a = Tensor::arange(0f64, 24., device)?
+        .sqr()?
+        .cos()?
+        .reshape((2, 4, 3))?;
+    for a in [a.clone(), a.t()?, a.narrow(1, 0, 3)?] {
+        let (u, s, vt) = linalg::svd(&a)?;
+        let k = s.dim(D::Minus1)?;
+        let us = u.broadcast_mul(&s.unsqueeze(1)?)?;
+        assert!(max_diff(&us.matmul(&vt)?, &a)? < 1e-12);
+        let eye = eye(k)?.unsqueeze(0)?.repeat((2, 1, 1))?;
+        assert!(max_diff(&u.t()?.matmul(&u)?, &eye)? < 1e-12);
+        assert!(max_diff(&vt.matmul(&vt.t()?)?, &eye)? < 1e-12);
+        for s in s.to_vec2::<f64>()? {
+            assert!(s.windows(2).all(|w| w[0] >= w[1]))
+        }
+    }
+
+    // Rank deficient matrices still get orthonormal singular vectors.
+    let a = Tensor::new(&[[1f64, 2.], [2., 4.], [3., 6.]], device)?;
+    let (u, s, vt) = linalg::svd(&a)?;
+    let s = s.to_vec1::<f64>()?;
+    assert!((s[0] - 70f64.sqrt()).abs() < 1e-12 && s[1].abs() < 1e-12);
+    assert!(max_diff(&u.t()?.matmul(&u)?, &eye(2)?)? < 1e-12);
+    assert!(max_diff(&vt.matmul(&vt.t()?)?, &eye(2)?)? < 1e-12);
+    Ok(())
+}
+
+#[test]
+fn eigh() -> Result<()> {
+    let device = &Device::Cpu;
+    let x = Tensor::arange(0f64, 32., device)?
+        .sqr()?
+        .sin()?
+        .reshape((2, 4, 4))?;
+    let a = (&x + x.t()?)?;
+    let (w, v) = linalg::eigh(&a)?;
+    assert_eq!(w.dims(), [2, 4]);
+    let vw = v.broadcast_mul(&w.unsqueeze(1)?)?;
+    assert!(max_diff(&a.matmul(&v)?, &vw)? < 1e-12);
+    assert!(
+        max_diff(
+            &v.t()?.matmul(&v)?,
+            &eye(4)?.unsqueeze(0)?.repeat((2, 1, 1))?
+        )? < 1e-12
+    );
+    for w in w.to_vec2::<f64>()? {
+        assert!(w.windows(2).all(|w| w[0] <= w[1]))
+    }
+
+    // Only the lower triangle is used.
+    let a = Tensor::new(&[[2f32, 100.], [1., 2.]], device)?;
+    let (w, _) = linalg::eigh(&a)?;
+    assert_eq!(w.to_vec1::<f32>()?, &[1., 3.]);
+    Ok(())
+}
@@ -0,0 +1,71 @@
+// The cpu memory pool is global so these checks live in their own test binary.
+use candle_core::{Device, Result, Tensor};
+
+const BYTES: usize = 65536 * 4;
+
+#[test]
+fn memory_stats() -> Result<()> {
+    let device = Device::Cpu;
+    // The caching is opt-in.
+    let x = Tensor::arange(0f32, 60000., &device)?;
+    drop(x.affine(2., 1.)?);
+    assert_eq!(device.memory_stats()?.cached, 0);
+    device.set_cache_limit(256 * 1024 * 1024)?;
+
+    let start = device.memory_stats()?;
+    // Fresh allocations are not rounded up.
+    let y = x.affine(2., 1.)?;
+    assert_eq!(device.memory_stats()?.in_use, start.in_use + 60000 * 4);
+    drop(y);
+    device.empty_cache()?;
+    assert_eq!(start.cached, 0);
+
+    let a = Tensor::arange(0f32, 65536., &device)?;
+    let b = (&a + 1.)?;
+    let stats = device.memory_stats()?;
+    assert_eq!(stats.in_use, start.in_use + 2 * BYTES);
+    assert!(stats.peak >= stats.in_use);
+
+    // Views share the storage so the memory is only released with the last of them.
+    let view = a.narrow(0, 0, 10)?;
+    drop(a);
+    assert_eq!(device.memory_stats()?.in_use, start.in_use + 2 * BYTES);
+    drop(view);
+    drop(b);
+    let stats = device.memory_stats()?;
+    assert_eq!(stats.in_use, start.in_use);
+    assert!(stats.peak >= start.in_use + 2 * BYTES);
+    assert!(stats.cached >= 2 * BYTES);
+
+    // New buffers of a similar size come from the cache.
+    let c = x.affine(2., 1.)?;
+    assert_eq!(device.memory_stats()?.cached, stats.cached - BYTES);
+    assert_eq!(c.get(10)?.to_scalar::<f32>()?, 21.);
+
+    device.empty_cache()?;
+    assert_eq!(device.memory_stats()?.cached, 0);
+    device.set_cache_limit(0)?;
+    drop(c);
+    assert_eq!(device.memory_stats()?.cached, 0);
+    device.set_cache_limit(256 * 1024 * 1024)?;
+
+    // Buffers with a length that is not a power of two are reused for the same length, the spare
+    // capacity of a reused buffer is counted as in use.
+    let d = x.affine(2., 1.)?;
+    drop(d);
+    assert_eq!(device.memory_stats()?.cached, 60000 * 4);
+    let d = x.affine(2., 1.)?;
+    assert_eq!(device.memory_stats()?.cached, 0);
+    assert_eq!(d.get(10)?.to_scalar::<f32>()?, 21.);
+    drop(d);
+    let e = Tensor::arange(0f32, 50000., &device)?;
+    let in_use = device.memory_stats()?.in_use;
+    let f = e.affine(1., 0.)?;
+    assert_eq!(device.memory_stats()?.in_use, in_use + 60000 * 4);
+    drop(f);
+    drop(e);
+
+    device.set_cache_limit(candle_core::memory::DEFAULT_CACHE_LIMIT)?;
+    assert_eq!(device.memory_stats()?.cached, 0);
+    Ok(())
+}
@@ -98,6 +98,28 @@ fn upsample_nearest2d(dev: &Device) -> Result<()> {
     Ok(())
 }
 
+fn pool3d(dev: &Device) -> Result<()> {
+    let t = Tensor::arange(0f32, 64., dev)?.reshape((1, 1, 4, 4, 4))?;
+    let pool = t.max_pool3d(2)?;
+    assert_eq!(pool.dims(), [1, 1, 2, 2, 2]);
+    assert_eq!(
+        pool.flatten_all()?.to_vec1::<f32>()?,
+        [21., 23., 29., 31., 53., 55., 61., 63.]
+    );
+    let pool = t.avg_pool3d(2)?;
+    assert_eq!(
+        pool.flatten_all()?.to_vec1::<f32>()?,
+        [10.5, 12.5, 18.5, 20.5, 42.5, 44.5, 50.5, 52.5]
+    );
+    let pool = t.avg_pool3d_with_stride((1, 2, 4), (1, 2, 4))?;
+    assert_eq!(pool.dims(), [1, 1, 4, 2, 1]);
+    assert_eq!(
+        pool.flatten_all()?.to_vec1::<f32>()?,
+        [3.5, 11.5, 19.5, 27.5, 35.5, 43.5, 51.5, 59.5]
+    );
+    Ok(())
+}
+
 test_device!(avg_pool2d, avg_pool2d_cpu, avg_pool2d_gpu);
 test_device!(
     avg_pool2d_pytorch,
@@ -110,3 +132,4 @@ test_device!(
     upsample_nearest2d_cpu,
     upsample_nearest2d_gpu
 );
+test_device!(pool3d, pool3d_cpu, pool3d_gpu);
@@ -1,7 +1,7 @@
 use candle_core::{
     quantized::{self, GgmlDType},
     test_utils::to_vec2_round,
-    Device, Result, Tensor,
+    DType, Device, Result, Tensor,
 };
 use quantized::{k_quants, GgmlType};
 use rand::prelude::*;
@@ -687,3 +687,495 @@ fn quantized_matmul_q6k() -> Result<()> {
     ggml_matmul_error_test::<BlockQ6K>()?;
     Ok(())
 }
+
+#[test]
+fn gguf_mmap() -> Result<()> {
+    use quantized::gguf_file;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let cpu = &Device::Cpu;
+    let (lhs, rhs, _) = get_random_tensors(3, 256, 5, cpu)?;
+    let q4k = Arc::new(quantized::QTensor::quantize::<k_quants::BlockQ4K>(&rhs)?);
+    let q8_0 = Arc::new(quantized::QTensor::quantize::<k_quants::BlockQ8_0>(&rhs)?);
+    let f32 = Arc::new(quantized::QTensor::quantize::<f32>(&rhs.narrow(1, 0, 3)?)?);
+    let path = std::env::temp_dir().join(format!("candle-mmap-{}.gguf", std::process::id()));
+    let mut file = std::fs::File::create(&path)?;
+    let tensors = [("q4k", &q4k), ("q8_0", &q8_0), ("f32", &f32)];
+    let to_write = tensors.map(|(name, t)| (name, t.as_ref()));
+    gguf_file::write(&mut file, &[], &to_write)?;
+    drop(file);
+
+    let content = unsafe { gguf_file::MmapedContent::new(&path)? };
+    assert_eq!(content.content().tensor_infos.len(), 3);
+    for (name, expected) in tensors {
+        for qtensor in [content.tensor(name)?, content.lazy_tensor(name)?] {
+            assert_eq!(qtensor.shape(), expected.shape());
+            assert_eq!(qtensor.dtype(), expected.dtype());
+            let diff = (qtensor.dequantize(cpu)? - expected.dequantize(cpu)?)?;
+            assert_eq!(diff.abs()?.sum_all()?.to_scalar::<f32>()?, 0.);
+            if name != "f32" {
+                let mm = quantized::QMatMul::from_qtensor(qtensor).forward(&lhs)?;
+                let expected = quantized::QMatMul::from_arc(expected.clone()).forward(&lhs)?;
+                assert_eq!(mm.to_vec2::<f32>()?, expected.to_vec2::<f32>()?);
+            }
+        }
+    }
+    assert!(content.tensor("missing").is_err());
+    assert!(content.lazy_tensor("missing").is_err());
+    drop(content);
+    std::fs::remove_file(&path)?;
+
+    // Lazy tensors only get loaded once, on the first forward.
+    let loads = Arc::new(AtomicUsize::new(0));
+    let blocks = {
+        let loads = loads.clone();
+        let src = rhs.flatten_all()?.to_vec1::<f32>()?;
+        move || {
+            loads.fetch_add(1, Ordering::SeqCst);
+            let mut data = vec![k_quants::BlockQ4K::zeros(); 5];
+            k_quants::BlockQ4K::from_float(&src, &mut data)?;
+            Ok(Box::new(data) as Box<dyn quantized::QuantizedType>)
+        }
+    };
+    let lazy = quantized::QTensor::lazy((5, 256), GgmlDType::Q4K, blocks)?;
+    assert_eq!(lazy.storage_size_in_bytes(), q4k.storage_size_in_bytes());
+    let mm = quantized::QMatMul::from_qtensor(lazy);
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+    let ys1 = mm.forward(&lhs)?;
+    let ys2 = mm.forward(&lhs)?;
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+    assert_eq!(ys1.to_vec2::<f32>()?, ys2.to_vec2::<f32>()?);
+    let expected = quantized::QMatMul::from_arc(q4k).forward(&lhs)?;
+    assert_eq!(ys1.to_vec2::<f32>()?, expected.to_vec2::<f32>()?);
+    assert!(quantized::QTensor::lazy((5, 100), GgmlDType::Q4K, || unreachable!()).is_err());
+    let wrong = quantized::QTensor::lazy((2, 256), GgmlDType::Q4K, || {
+        Ok(Box::new(vec![0f32; 512]) as Box<dyn quantized::QuantizedType>)
+    })?;
+    assert!(wrong.dequantize(cpu).is_err());
+    let failing = quantized::QTensor::lazy((5, 256), GgmlDType::Q4K, || {
+        candle_core::bail!("cannot read the data")
+    })?;
+    assert!(failing.load().is_err());
+    assert!(quantized::QMatMul::from_qtensor(failing)
+        .forward(&lhs)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn gguf_v3_alignment_and_endianness() -> Result<()> {
+    use byteorder::{BigEndian, WriteBytesExt};
+    use quantized::gguf_file::{self, Endianness, GgufWriter, Value, VersionedMagic};
+
+    let cpu = &Device::Cpu;
+    let (_, rhs, _) = get_random_tensors(1, 64, 3, cpu)?;
+    let q8_0 = quantized::QTensor::quantize::<k_quants::BlockQ8_0>(&rhs)?;
+    let f32 = quantized::QTensor::quantize::<f32>(&rhs.narrow(1, 0, 5)?)?;
+
+    // Custom alignment, the tensors are only produced when being written.
+    let mut writer = GgufWriter::new(std::io::Cursor::new(vec![]));
+    writer.add_metadata("general.alignment", Value::U32(128));
+    writer.add_metadata("general.name", Value::String("test".to_string()));
+    writer.add_tensor_info("f32", GgmlDType::F32, (3, 5))?;
+    writer.add_tensor_info("q8_0", GgmlDType::Q8_0, (3, 64))?;
+    assert!(writer
+        .add_tensor_info("q8_0", GgmlDType::Q8_0, (3, 64))
+        .is_err());
+    let tensors = [&f32, &q8_0].into_iter().map(|t| match t.dtype() {
+        GgmlDType::F32 => quantized::QTensor::quantize::<f32>(&t.dequantize(cpu)?),
+        _ => quantized::QTensor::quantize::<k_quants::BlockQ8_0>(&rhs),
+    });
+    let mut file = writer.write_tensors(tensors)?;
+    file.set_position(0);
+    let content = gguf_file::Content::read(&mut file)?;
+    assert_eq!(content.magic, VersionedMagic::GgufV3);
+    assert_eq!(content.endianness, Endianness::Little);
+    assert_eq!(content.alignment, 128);
+    assert_eq!(content.tensor_data_offset % 128, 0);
+    assert_eq!(content.tensor_infos["f32"].offset, 0);
+    assert_eq!(content.tensor_infos["q8_0"].offset, 128);
+    for (name, expected) in [("f32", &f32), ("q8_0", &q8_0)] {
+        let t = content.tensor(&mut file, name)?;
+        assert_eq!(t.dtype(), expected.dtype());
+        let diff = (t.dequantize(cpu)? - expected.dequantize(cpu)?)?;
+        assert_eq!(diff.abs()?.sum_all()?.to_scalar::<f32>()?, 0.);
+    }
+
+    // The tensors have to match their infos.
+    let writer = || -> Result<GgufWriter<std::io::Cursor<Vec<u8>>>> {
+        let mut writer = GgufWriter::new(std::io::Cursor::new(vec![]));
+        writer.add_tensor_info("f32", GgmlDType::F32, (3, 5))?;
+        Ok(writer)
+    };
+    assert!(writer()?.write_tensors([Ok(&q8_0)]).is_err());
+    assert!(writer()?.write_tensors([Ok(&f32), Ok(&f32)]).is_err());
+    assert!(writer()?
+        .write_tensors::<_, &quantized::QTensor>([])
+        .is_err());
+    assert!(writer()?.write_tensors([Ok(&f32)]).is_ok());
+    let mut writer = writer()?;
+    writer.add_metadata("general.alignment", Value::U32(24));
+    assert!(writer.write_tensors([Ok(&f32)]).is_err());
+
+    // A big endian file with a single Q8_0 tensor.
+    let mut be = vec![];
+    be.extend_from_slice(b"GGUF");
+    be.write_u32::<BigEndian>(3)?;
+    be.write_u64::<BigEndian>(1)?;
+    be.write_u64::<BigEndian>(1)?;
+    be.write_u64::<BigEndian>(7)?;
+    be.extend_from_slice(b"general");
+    be.write_u32::<BigEndian>(4)?; // u32
+    be.write_u32::<BigEndian>(42)?;
+    be.write_u64::<BigEndian>(4)?;
+    be.extend_from_slice(b"q8_0");
+    be.write_u32::<BigEndian>(2)?;
+    be.write_u64::<BigEndian>(64)?;
+    be.write_u64::<BigEndian>(3)?;
+    be.write_u32::<BigEndian>(8)?; // Q8_0
+    let offset_pos = be.len();
+    be.write_u64::<BigEndian>(0)?;
+    be.resize(be.len().div_ceil(32) * 32, 0);
+    let data =
+        unsafe { std::slice::from_raw_parts(q8_0.as_ptr(), q8_0.storage_size_in_bytes()).to_vec() };
+    for block in data.chunks(34) {
+        // The f16 scale is the only multi-byte value of a Q8_0 block.
+        be.extend_from_slice(&[block[1], block[0]]);
+        be.extend_from_slice(&block[2..]);
+    }
+    let content = gguf_file::Content::read(&mut std::io::Cursor::new(&be))?;
+    assert_eq!(content.endianness, Endianness::Big);
+    assert_eq!(content.metadata["general"].to_u32()?, 42);
+    assert_eq!(content.tensor_infos["q8_0"].shape.dims(), [3, 64]);
+    let t = content.tensor(&mut std::io::Cursor::new(&be), "q8_0")?;
+    let diff = (t.dequantize(cpu)? - q8_0.dequantize(cpu)?)?;
+    assert_eq!(diff.abs()?.sum_all()?.to_scalar::<f32>()?, 0.);
+
+    // Memory mapped big endian files get converted.
+    let path = std::env::temp_dir().join(format!("candle-be-{}.gguf", std::process::id()));
+    std::fs::write(&path, &be)?;
+    let content = unsafe { gguf_file::MmapedContent::new(&path)? };
+    for t in [content.tensor("q8_0")?, content.lazy_tensor("q8_0")?] {
+        let diff = (t.dequantize(cpu)? - q8_0.dequantize(cpu)?)?;
+        assert_eq!(diff.abs()?.sum_all()?.to_scalar::<f32>()?, 0.);
+    }
+    drop(content);
+
+    // Offsets that overflow when adding the tensor size are rejected.
+    let mut bad = be.clone();
+    let offset = (u64::MAX - 127).to_be_bytes();
+    bad[offset_pos..offset_pos + 8].copy_from_slice(&offset);
+    std::fs::write(&path, &bad)?;
+    let content = unsafe { gguf_file::MmapedContent::new(&path)? };
+    assert!(content.tensor("q8_0").is_err());
+    assert!(content
+        .lazy_tensor("q8_0")
+        .and_then(|t| t.dequantize(cpu))
+        .is_err());
+    drop(content);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn qmatmul_strided_half_and_backward() -> Result<()> {
+    use candle_core::{DType, Var};
+    let cpu = &Device::Cpu;
+    let (b, m, k, n) = (2, 3, 64, 4);
+    let rhs = (0..(k * n))
+        .map(|v| (v % 17) as f32 / 8. - 1.)
+        .collect::<Vec<_>>();
+    let rhs = Tensor::from_vec(rhs, (n, k), cpu)?;
+    let qtensor = quantized::QTensor::quantize::<k_quants::BlockQ8_0>(&rhs)?;
+    let w = qtensor.dequantize(cpu)?;
+    let matmul = quantized::QMatMul::from_qtensor(qtensor);
+
+    let lhs = (0..(b * m * k)).map(|v| (v % 13) as f32 / 4. - 1.5);
+    let lhs = Tensor::from_iter(lhs, cpu)?.reshape((b, k, m))?;
+    // A non-contiguous input gives the same results as its contiguous version.
+    let xs = lhs.transpose(1, 2)?;
+    assert!(!xs.is_contiguous());
+    let res = matmul.forward(&xs)?;
+    assert_eq!(res.dims(), [b, m, n]);
+    let expected = matmul.forward(&xs.contiguous()?)?;
+    assert_eq!(res.to_vec3::<f32>()?, expected.to_vec3::<f32>()?);
+    let diff = (&res - xs.broadcast_matmul(&w.t()?)?)?.abs()?;
+    assert!(diff.flatten_all()?.max(0)?.to_scalar::<f32>()? < 0.1);
+
+    // Half precision activations produce results with the same dtype.
+    for dtype in [DType::F16, DType::BF16] {
+        let xs = xs.to_dtype(dtype)?;
+        let res = matmul.forward(&xs)?;
+        assert_eq!(res.dtype(), dtype);
+        let expected = matmul.forward(&xs.to_dtype(DType::F32)?)?;
+        let diff = (res.to_dtype(DType::F32)? - expected)?.abs()?;
+        assert!(diff.flatten_all()?.max(0)?.to_scalar::<f32>()? < 0.5);
+    }
+    assert!(matmul.forward(&xs.to_dtype(DType::F64)?).is_err());
+
+    // The backward pass uses the dequantized weights.
+    let xs = Var::from_tensor(&xs)?;
+    let ys = matmul.forward(xs.as_tensor())?;
+    let grads = (&ys * &ys)?.sum_all()?.backward()?;
+    let grad = grads.get(&xs).unwrap();
+    let expected = (ys * 2.)?.broadcast_matmul(&w)?;
+    assert_eq!(grad.dims(), [b, m, k]);
+    let diff = (grad - expected)?.abs()?;
+    assert!(diff.flatten_all()?.max(0)?.to_scalar::<f32>()? < 1e-3);
+    Ok(())
+}
+
+fn weighted_error(src: &Tensor, q: &quantized::QTensor, importance: &[f32]) -> Result<f32> {
+    let importance = Tensor::new(importance, &Device::Cpu)?;
+    let diff = (src - q.dequantize(&Device::Cpu)?)?.sqr()?;
+    diff.broadcast_mul(&importance)?
+        .sum_all()?
+        .to_scalar::<f32>()
+}
+
+fn quantize_with_importance<T: GgmlType + Send + Sync + 'static>() -> Result<()> {
+    let (n, k) = (16, 512);
+    let mut rng = StdRng::seed_from_u64(42);
+    let src = (0..n * k).map(|_| rng.gen::<f32>() * 2. - 1.);
+    let src = Tensor::from_iter(src, &Device::Cpu)?.reshape((n, k))?;
+    // A few columns see activations much larger than the others.
+    let importance = (0..k)
+        .map(|i| if i % 7 == 0 { 100. } else { 0.01 })
+        .collect::<Vec<_>>();
+    let plain = quantized::QTensor::quantize::<T>(&src)?;
+    let weighted = quantized::QTensor::quantize_with_importance::<T>(&src, &importance)?;
+    assert_eq!(weighted.dtype(), T::DTYPE);
+    assert_eq!(weighted.shape(), src.shape());
+    let plain_err = weighted_error(&src, &plain, &importance)?;
+    let weighted_err = weighted_error(&src, &weighted, &importance)?;
+    assert!(
+        weighted_err < plain_err,
+        "{:?}: {weighted_err} >= {plain_err}",
+        T::DTYPE
+    );
+    // Uniform importance values stay close to the plain quantization.
+    let uniform = quantized::QTensor::quantize_with_importance::<T>(&src, &vec![1.; k])?;
+    let ones = vec![1.; k];
+    let plain_err = weighted_error(&src, &plain, &ones)?;
+    let uniform_err = weighted_error(&src, &uniform, &ones)?;
+    assert!(
+        uniform_err < plain_err * 1.1,
+        "{:?}: {uniform_err} {plain_err}",
+        T::DTYPE
+    );
+
+    assert!(quantized::QTensor::quantize_with_importance::<T>(&src, &importance[1..]).is_err());
+    let mut negative = importance.clone();
+    negative[3] = -1.;
+    assert!(quantized::QTensor::quantize_with_importance::<T>(&src, &negative).is_err());
+    Ok(())
+}
+
+#[test]
+fn quantize_kquants_with_importance() -> Result<()> {
+    quantize_with_importance::<k_quants::BlockQ2K>()?;
+    quantize_with_importance::<k_quants::BlockQ3K>()?;
+    quantize_with_importance::<k_quants::BlockQ4K>()?;
+    quantize_with_importance::<k_quants::BlockQ5K>()?;
+    quantize_with_importance::<k_quants::BlockQ6K>()?;
+    Ok(())
+}
+
+#[test]
+fn imatrix_record() -> Result<()> {
+    use quantized::imatrix::Imatrix;
+    let cpu = &Device::Cpu;
+    let imatrix = Imatrix::new();
+    let xs = Tensor::new(&[[[1f32, 2., 0.], [3., 0., -1.]]], cpu)?;
+    imatrix.record("a", &xs)?;
+    imatrix.record(
+        "a",
+        &Tensor::new(&[[2f32, 2., 2.]], cpu)?.to_dtype(DType::F16)?,
+    )?;
+    imatrix.record("b", &Tensor::new(&[4f32, 0.], cpu)?)?;
+    assert!(imatrix
+        .record("a", &Tensor::zeros((2, 2), DType::F32, cpu)?)
+        .is_err());
+    assert_eq!(imatrix.names(), ["a", "b"]);
+    assert_eq!(imatrix.count("a"), 3);
+    assert_eq!(imatrix.get("a").unwrap(), [14. / 3., 8. / 3., 5. / 3.]);
+    assert_eq!(imatrix.get("b").unwrap(), [16., 0.]);
+    assert!(imatrix.get("c").is_none());
+
+    // The counts are preserved when saving so that further recordings are merged correctly.
+    let path = std::env::temp_dir().join(format!("candle-imatrix-{}.st", std::process::id()));
+    imatrix.save(&path)?;
+    let counts = candle_core::safetensors::load(&path, cpu)?;
+    assert_eq!(counts["a.count"].dtype(), DType::I64);
+    let loaded = Imatrix::load(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(loaded.names(), ["a", "b"]);
+    loaded.record("b", &Tensor::new(&[2f32, 3.], cpu)?)?;
+    assert_eq!(loaded.count("b"), 2);
+    assert_eq!(loaded.get("b").unwrap(), [10., 4.5]);
+    assert_eq!(loaded.get("a"), imatrix.get("a"));
+
+    // The inputs of a matmul are recorded when running a model.
+    let imatrix = std::sync::Arc::new(Imatrix::new());
+    let w = Tensor::randn(0f32, 1., (4, 32), cpu)?;
+    let mm = quantized::QMatMul::from_qtensor(quantized::QTensor::quantize::<f32>(&w)?)
+        .with_imatrix("w", imatrix.clone());
+    let xs = Tensor::ones((2, 3, 32), DType::F32, cpu)?;
+    mm.forward(&xs)?;
+    mm.forward(&(xs * 2.)?)?;
+    assert_eq!(imatrix.count("w"), 12);
+    assert_eq!(imatrix.get("w").unwrap(), [2.5; 32]);
+    Ok(())
+}
+
+// Random 4-bit values, zero points and scales for a weight of shape (out_dim, in_dim) together
+// with the matching dense weight.
+#[allow(clippy::type_complexity)]
+fn packed_weight_values(
+    out_dim: usize,
+    in_dim: usize,
+    groups: &[usize],
+    n_groups: usize,
+) -> Result<(Vec<Vec<u32>>, Vec<Vec<u32>>, Tensor, Tensor)> {
+    let mut rng = StdRng::seed_from_u64(299792458);
+    let q = (0..out_dim)
+        .map(|_| (0..in_dim).map(|_| rng.gen_range(0..16)).collect())
+        .collect::<Vec<Vec<u32>>>();
+    let z = (0..n_groups)
+        .map(|_| (0..out_dim).map(|_| rng.gen_range(1..16)).collect())
+        .collect::<Vec<Vec<u32>>>();
+    let scales = (0..n_groups * out_dim).map(|_| rng.gen_range(0.001f32..0.1));
+    let scales = Tensor::from_iter(scales, &Device::Cpu)?
+        .reshape((n_groups, out_dim))?
+        .to_dtype(DType::F16)?;
+    let s = scales.to_dtype(DType::F32)?.to_vec2::<f32>()?;
+    let w = (0..out_dim)
+        .flat_map(|r| {
+            let (q, z, s) = (&q, &z, &s);
+            (0..in_dim).map(move |c| {
+                let g = groups[c];
+                s[g][r] * (q[r][c] as f32 - z[g][r] as f32)
+            })
+        })
+        .collect::<Vec<_>>();
+    let w = Tensor::from_vec(w, (out_dim, in_dim), &Device::Cpu)?;
+    Ok((q, z, scales, w))
+}
+
+// Packs 8 consecutive values per i32, `nibbles[k]` being the position of the k-th value.
+fn pack_i32(values: &[Vec<u32>], nibbles: &[usize; 8]) -> Result<Tensor> {
+    let packed = values
+        .iter()
+        .map(|r| {
+            r.chunks(8)
+                .map(|c| {
+                    let v = c
+                        .iter()
+                        .zip(nibbles)
+                        .fold(0u32, |a, (v, p)| a | (v << (4 * p)));
+                    v as i32
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    Tensor::new(packed, &Device::Cpu)
+}
+
+fn max_diff(a: &Tensor, b: &Tensor) -> Result<f32> {
+    (a - b)?.abs()?.flatten_all()?.max(0)?.to_scalar::<f32>()
+}
+
+#[test]
+fn gptq_awq_repack() -> Result<()> {
+    use quantized::gptq::{PackedWeight, QuantMethod};
+    let cpu = &Device::Cpu;
+    let transpose = |v: &[Vec<u32>]| -> Vec<Vec<u32>> {
+        (0..v[0].len())
+            .map(|j| v.iter().map(|r| r[j]).collect())
+            .collect()
+    };
+    let (out_dim, in_dim, n_groups) = (16, 128, 2);
+    let seq = (0..in_dim).map(|i| i / 64).collect::<Vec<_>>();
+    let (q, z, scales, w) = packed_weight_values(out_dim, in_dim, &seq, n_groups)?;
+
+    // GPTQ packs the weight along the input features, the zero points are offset by one.
+    let gptq_order = [0, 1, 2, 3, 4, 5, 6, 7];
+    let z_gptq = z
+        .iter()
+        .map(|r| r.iter().map(|v| v - 1).collect())
+        .collect::<Vec<_>>();
+    let gptq = PackedWeight {
+        qweight: pack_i32(&q, &gptq_order)?.t()?.contiguous()?,
+        qzeros: pack_i32(&z_gptq, &gptq_order)?,
+        scales: scales.clone(),
+        g_idx: None,
+    };
+    assert_eq!(gptq.dims(QuantMethod::Gptq)?, (out_dim, in_dim));
+    assert!(max_diff(&gptq.dequantize(QuantMethod::Gptq, cpu)?, &w)? < 1e-6);
+    let qtensor = gptq.to_qtensor(QuantMethod::Gptq)?;
+    assert_eq!(qtensor.dtype(), GgmlDType::Q4_1);
+    assert_eq!(qtensor.shape().dims(), [out_dim, in_dim]);
+    assert!(max_diff(&qtensor.dequantize(cpu)?, &w)? < 1e-3);
+    // The v2 format has no offset.
+    let gptq_v2 = PackedWeight {
+        qzeros: pack_i32(&z, &gptq_order)?,
+        ..gptq.clone()
+    };
+    assert!(max_diff(&gptq_v2.dequantize(QuantMethod::GptqV2, cpu)?, &w)? < 1e-6);
+
+    // With activation reordering the groups are interleaved and cannot be represented in Q4_1.
+    let act_order = (0..in_dim).map(|i| i % 2).collect::<Vec<_>>();
+    let (q, z, scales, w) = packed_weight_values(out_dim, in_dim, &act_order, n_groups)?;
+    let z_gptq = z
+        .iter()
+        .map(|r| r.iter().map(|v| v - 1).collect())
+        .collect::<Vec<_>>();
+    let g_idx = act_order.iter().map(|&g| g as i32).collect::<Vec<_>>();
+    let gptq = PackedWeight {
+        qweight: pack_i32(&q, &gptq_order)?.t()?.contiguous()?,
+        qzeros: pack_i32(&z_gptq, &gptq_order)?,
+        scales: scales.clone(),
+        g_idx: Some(Tensor::new(g_idx, cpu)?),
+    };
+    assert!(max_diff(&gptq.dequantize(QuantMethod::Gptq, cpu)?, &w)? < 1e-6);
+    let qtensor = gptq.to_qtensor(QuantMethod::Gptq)?;
+    assert_eq!(qtensor.dtype(), GgmlDType::Q8_0);
+    assert!(max_diff(&qtensor.dequantize(cpu)?, &w)? < 1e-2);
+
+    // AWQ packs both the weight and zero points along the output features, interleaved.
+    let (q, z, scales, w) = packed_weight_values(out_dim, in_dim, &seq, n_groups)?;
+    // As in AutoAWQ, nibble i holds the value of the column ORDER[i].
+    const ORDER: [usize; 8] = [0, 2, 4, 6, 1, 3, 5, 7];
+    let mut awq_order = [0; 8];
+    for (nibble, &col) in ORDER.iter().enumerate() {
+        awq_order[col] = nibble
+    }
+    let awq = PackedWeight {
+        qweight: pack_i32(&transpose(&q), &awq_order)?,
+        qzeros: pack_i32(&z, &awq_order)?,
+        scales,
+        g_idx: None,
+    };
+    assert_eq!(awq.dims(QuantMethod::Awq)?, (out_dim, in_dim));
+    assert!(max_diff(&awq.dequantize(QuantMethod::Awq, cpu)?, &w)? < 1e-6);
+    let qtensor = awq.to_qtensor(QuantMethod::Awq)?;
+    assert_eq!(qtensor.dtype(), GgmlDType::Q4_1);
+    assert!(max_diff(&qtensor.dequantize(cpu)?, &w)? < 1e-3);
+
+    // The repacked weight can be used directly in a quantized matmul.
+    let xs = Tensor::randn(0f32, 1., (3, in_dim), cpu)?;
+    let ys = quantized::QMatMul::from_qtensor(qtensor).forward(&xs)?;
+    // The lhs gets quantized to 8 bits by the matmul.
+    let expected = xs.matmul(&w.t()?)?;
+    let scale = expected.abs()?.flatten_all()?.max(0)?.to_scalar::<f32>()?;
+    assert!(max_diff(&ys, &expected)? < 0.02 * scale);
+
+    let bad = PackedWeight {
+        qweight: awq.qweight.to_dtype(DType::F32)?,
+        ..awq.clone()
+    };
+    assert!(bad.to_qtensor(QuantMethod::Awq).is_err());
+    assert!(awq.to_qtensor(QuantMethod::Gptq).is_err());
+    Ok(())
+}
@@ -0,0 +1,32 @@
+// These tests are in their own file as they set the seed of the default cpu generator, which is
+// shared with all the other tests of the same binary.
+use candle_core::{Device, Generator, Result, Tensor};
+
+#[test]
+fn set_seed() -> Result<()> {
+    let device = &Device::Cpu;
+    device.set_seed(42)?;
+    let a = Tensor::rand(0f32, 1f32, (3, 4), device)?;
+    let b = Tensor::randn(0f32, 1f32, (3, 4), device)?;
+    device.set_seed(42)?;
+    let a2 = Tensor::rand(0f32, 1f32, (3, 4), device)?;
+    let b2 = Tensor::randn(0f32, 1f32, (3, 4), device)?;
+    assert_eq!(a.to_vec2::<f32>()?, a2.to_vec2::<f32>()?);
+    assert_eq!(b.to_vec2::<f32>()?, b2.to_vec2::<f32>()?);
+    assert_ne!(a.to_vec2::<f32>()?, b.to_vec2::<f32>()?);
+
+    // The default generator uses the same stream as an explicit one.
+    let mut gen = Generator::new(42);
+    let a3 = Tensor::rand_with_generator(0f32, 1f32, (3, 4), device, &mut gen)?;
+    let b3 = Tensor::randn_with_generator(0f32, 1f32, (3, 4), device, &mut gen)?;
+    assert_eq!(a.to_vec2::<f32>()?, a3.to_vec2::<f32>()?);
+    assert_eq!(b.to_vec2::<f32>()?, b3.to_vec2::<f32>()?);
+
+    if candle_core::utils::cuda_is_available() {
+        let cuda = Device::new_cuda(0)?;
+        cuda.set_seed(42)?;
+        let a4 = Tensor::rand(0f32, 1f32, (3, 4), &cuda)?;
+        assert_eq!(a.to_vec2::<f32>()?, a4.to_vec2::<f32>()?);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use candle_core::sparse::{CooTensor, CsrTensor};
+use candle_core::{test_device, DType, Device, Tensor, Var};
+
+fn coo(device: &Device) -> Result<()> {
+    let dense = Tensor::new(&[[0f32, 1., 0.], [0., 0., 0.], [2., 0., 3.]], device)?;
+    let coo = CooTensor::from_dense(&dense, 2)?;
+    assert_eq!(coo.nnz(), 3);
+    assert_eq!(coo.indices().to_vec2::<u32>()?, &[[0, 1], [2, 0], [2, 2]]);
+    assert_eq!(coo.values().to_vec1::<f32>()?, &[1., 2., 3.]);
+    assert_eq!(coo.to_dense()?.to_vec2::<f32>()?, dense.to_vec2::<f32>()?);
+
+    // Rows with dense values, duplicates are summed when coalescing.
+    let indices = Tensor::new(&[[3u32], [0], [3]], device)?;
+    let values = Tensor::new(&[[1f32, 2.], [3., 4.], [5., 6.]], device)?;
+    let rows = CooTensor::new(&indices, &values, (4, 2))?;
+    assert_eq!(rows.sparse_dims(), 1);
+    let expected = [[3f32, 4.], [0., 0.], [0., 0.], [6., 8.]];
+    assert_eq!(rows.to_dense()?.to_vec2::<f32>()?, expected);
+    let coalesced = rows.coalesce()?;
+    assert_eq!(coalesced.nnz(), 2);
+    assert_eq!(coalesced.indices().to_vec2::<u32>()?, &[[0], [3]]);
+    assert_eq!(coalesced.to_dense()?.to_vec2::<f32>()?, expected);
+    let sum = rows.add(&coalesced)?;
+    assert_eq!(sum.nnz(), 5);
+    let doubled = (rows.to_dense()? * 2.)?;
+    assert_eq!(sum.to_dense()?.to_vec2::<f32>()?, doubled.to_vec2::<f32>()?);
+    let rows3 = CooTensor::from_dense(&dense.reshape((3, 3, 1))?, 1)?;
+    assert_eq!(rows3.indices().to_vec2::<u32>()?, &[[0], [2]]);
+
+    assert!(CooTensor::new(&indices, &values, (4, 3)).is_err());
+    assert!(CooTensor::new(&indices, &values.narrow(0, 0, 2)?, (4, 2)).is_err());
+    assert!(CooTensor::new(&indices.to_dtype(DType::F32)?, &values, (4, 2)).is_err());
+    // Out of bounds indices are rejected.
+    assert!(CooTensor::new(&indices, &values, (3, 2)).is_err());
+    let negative = Tensor::new(&[[1i64], [-1], [0]], device)?;
+    assert!(CooTensor::new(&negative, &values, (4, 2)).is_err());
+
+    // The positions over the sparse dimensions can exceed the range of u32.
+    let indices = Tensor::new(&[[70000u32, 1], [0, 69999], [70000, 1]], device)?;
+    let values = Tensor::new(&[1f32, 2., 3.], device)?;
+    let large = CooTensor::new(&indices, &values, (70001, 70000))?.coalesce()?;
+    assert_eq!(large.indices().to_vec2::<u32>()?, &[[0, 69999], [70000, 1]]);
+    assert_eq!(large.values().to_vec1::<f32>()?, &[2., 4.]);
+    Ok(())
+}
+
+fn csr(device: &Device) -> Result<()> {
+    let dense = Tensor::new(&[[0f32, 1., 0.], [0., 0., 0.], [2., 0., 3.]], device)?;
+    let csr = CsrTensor::from_dense(&dense)?;
+    assert_eq!(csr.row_offsets().to_vec1::<u32>()?, &[0, 1, 1, 3]);
+    assert_eq!(csr.col_indices().to_vec1::<u32>()?, &[1, 0, 2]);
+    assert_eq!(csr.to_dense()?.to_vec2::<f32>()?, dense.to_vec2::<f32>()?);
+    let coo = csr.to_coo()?;
+    let (offsets, cols) = (csr.row_offsets(), csr.col_indices());
+    let values = csr.values();
+    assert!(CsrTensor::new(offsets, cols, values, (3, 2)).is_err());
+    let bad_offsets = Tensor::new(&[0u32, 2, 1, 3], device)?;
+    assert!(CsrTensor::new(&bad_offsets, cols, values, (3, 3)).is_err());
+    let bad_offsets = Tensor::new(&[1u32, 1, 1, 3], device)?;
+    assert!(CsrTensor::new(&bad_offsets, cols, values, (3, 3)).is_err());
+    let bad_offsets = Tensor::new(&[0u32, 1, 1, 2], device)?;
+    assert!(CsrTensor::new(&bad_offsets, cols, values, (3, 3)).is_err());
+    assert_eq!(coo.indices().to_vec2::<u32>()?, &[[0, 1], [2, 0], [2, 2]]);
+
+    // Sparse-dense products match the dense matmul.
+    let rhs = Tensor::arange(0f32, 6., device)?.reshape((3, 2))?;
+    let expected = dense.matmul(&rhs)?.to_vec2::<f32>()?;
+    assert_eq!(csr.matmul(&rhs)?.to_vec2::<f32>()?, expected);
+    assert_eq!(coo.matmul(&rhs)?.to_vec2::<f32>()?, expected);
+    assert!(csr.matmul(&rhs.t()?).is_err());
+
+    // The products are differentiable with respect to the dense operand and the values.
+    let rhs = Var::from_tensor(&rhs)?;
+    let values = Var::from_tensor(csr.values())?;
+    let csr = CsrTensor::new(csr.row_offsets(), csr.col_indices(), &values, (3, 3))?;
+    let grads = csr.matmul(&rhs)?.sum_all()?.backward()?;
+    let grad_rhs = grads.get(&rhs).context("no grad for rhs")?;
+    let dense_grad = dense.sum_keepdim(0)?.t()?.broadcast_as((3, 2))?;
+    assert_eq!(grad_rhs.to_vec2::<f32>()?, dense_grad.to_vec2::<f32>()?);
+    let grad_values = grads.get(&values).context("no grad for values")?;
+    assert_eq!(grad_values.to_vec1::<f32>()?, &[5., 1., 9.]);
+    Ok(())
+}
+
+fn sparse_grad(device: &Device) -> Result<()> {
+    let table = Var::new(&[[0f32, 1.], [2., 3.], [4., 5.], [6., 7.]], device)?;
+    let ids = Tensor::new(&[3u32, 1, 3], device)?;
+    let ys = table.index_select_sparse_grad(&ids)?;
+    assert_eq!(
+        ys.to_vec2::<f32>()?,
+        table.index_select(&ids, 0)?.to_vec2::<f32>()?
+    );
+    let w = Tensor::new(&[[1f32, 2.], [3., 4.], [5., 6.]], device)?;
+    let loss = (ys * &w)?.sum_all()?;
+    let grads = loss.backward()?;
+    assert!(grads.get(&table).is_none());
+    let grad = grads.get_sparse(&table).context("no sparse grad")?;
+    assert_eq!(grad.nnz(), 3);
+    let dense_grads = (table.index_select(&ids, 0)? * &w)?.sum_all()?.backward()?;
+    let dense_grad = dense_grads.get(&table).context("no grad")?;
+    assert_eq!(
+        grad.to_dense()?.to_vec2::<f32>()?,
+        dense_grad.to_vec2::<f32>()?
+    );
+
+    // Uses that require dense gradients fall back to index_add.
+    let loss = (table.index_select_sparse_grad(&ids)? * &w)?.sum_all()?;
+    let g = candle_core::autograd::grad(&[&loss], &[&table], None)?;
+    assert_eq!(g[0].to_vec2::<f32>()?, dense_grad.to_vec2::<f32>()?);
+
+    // A variable used by both kinds of ops gets both gradients.
+    let loss = (table.index_select_sparse_grad(&ids)?.sum_all()? + table.sum_all()?)?;
+    let grads = loss.backward()?;
+    assert_eq!(grads.get(&table).context("no grad")?.dims(), &[4, 2]);
+    assert_eq!(grads.get_sparse(&table).context("no grad")?.nnz(), 3);
+    Ok(())
+}
+
+fn index_add_inplace(device: &Device) -> Result<()> {
+    let t = Tensor::zeros((3, 2), DType::F32, device)?.contiguous()?;
+    let ids = Tensor::new(&[1u32, 1, 2], device)?;
+    let src = Tensor::new(&[[1f32, 2.], [3., 4.], [5., 6.]], device)?;
+    t.index_add_(&ids, &src, 0)?;
+    assert_eq!(t.to_vec2::<f32>()?, &[[0., 0.], [4., 6.], [5., 6.]]);
+    // Strided destination.
+    let tt = t.t()?;
+    tt.index_add_(
+        &Tensor::new(&[0u32], device)?,
+        &Tensor::new(&[[1f32], [1.]], device)?,
+        1,
+    )?;
+    assert_eq!(t.to_vec2::<f32>()?, &[[1., 1.], [4., 6.], [5., 6.]]);
+    assert!(t.index_add_(&ids, &src.narrow(0, 0, 2)?, 0).is_err());
+    let v = Var::new(&[1f32, 2.], device)?;
+    assert!(v.as_tensor().index_add_(&ids, &src, 0).is_err());
+    v.index_add_(
+        &Tensor::new(&[1u32], device)?,
+        &Tensor::new(&[3f32], device)?,
+        0,
+    )?;
+    assert_eq!(v.to_vec1::<f32>()?, &[1., 5.]);
+    Ok(())
+}
+
+test_device!(coo, coo_cpu, coo_gpu);
+test_device!(csr, csr_cpu, csr_gpu);
+test_device!(sparse_grad, sparse_grad_cpu, sparse_grad_gpu);
+test_device!(
+    index_add_inplace,
+    index_add_inplace_cpu,
+    index_add_inplace_gpu
+);
@@ -1,4 +1,4 @@
-use candle_core::{test_device, DType, Device, IndexOp, Result, Tensor};
+use candle_core::{test_device, test_utils, DType, Device, Generator, IndexOp, Result, Tensor};
 
 fn zeros(device: &Device) -> Result<()> {
     let tensor = Tensor::zeros((5, 2), DType::F32, device)?;
@@ -565,12 +565,136 @@ fn embeddings(device: &Device) -> Result<()> {
 fn cmp(device: &Device) -> Result<()> {
     let t1 = Tensor::new(&[[0f32, 1f32], [2f32, 3f32], [4f32, 5f32]], device)?;
     let t2 = Tensor::new(&[[1f32, 0f32], [3f32, 3f32], [4f32, 7f32]], device)?;
-    assert_eq!(t1.eq(&t2)?.to_vec2::<u8>()?, &[[0, 0], [0, 1], [1, 0]]);
-    assert_eq!(t1.ne(&t2)?.to_vec2::<u8>()?, &[[1, 1], [1, 0], [0, 1]]);
-    assert_eq!(t1.le(&t2)?.to_vec2::<u8>()?, &[[1, 0], [1, 1], [1, 1]]);
-    assert_eq!(t1.lt(&t2)?.to_vec2::<u8>()?, &[[1, 0], [1, 0], [0, 1]]);
-    assert_eq!(t1.gt(&t2)?.to_vec2::<u8>()?, &[[0, 1], [0, 0], [0, 0]]);
-    assert_eq!(t1.ge(&t2)?.to_vec2::<u8>()?, &[[0, 1], [0, 1], [1, 0]]);
+    assert_eq!(t1.eq(&t2)?.dtype(), DType::Bool);
+    let to_u8 = |t: Tensor| t.to_dtype(DType::U8)?.to_vec2::<u8>();
+    assert_eq!(to_u8(t1.eq(&t2)?)?, &[[0, 0], [0, 1], [1, 0]]);
+    assert_eq!(to_u8(t1.ne(&t2)?)?, &[[1, 1], [1, 0], [0, 1]]);
+    assert_eq!(to_u8(t1.le(&t2)?)?, &[[1, 0], [1, 1], [1, 1]]);
+    assert_eq!(to_u8(t1.lt(&t2)?)?, &[[1, 0], [1, 0], [0, 1]]);
+    assert_eq!(to_u8(t1.gt(&t2)?)?, &[[0, 1], [0, 0], [0, 0]]);
+    assert_eq!(to_u8(t1.ge(&t2)?)?, &[[0, 1], [0, 1], [1, 0]]);
+    Ok(())
+}
+
+fn int_and_bool_dtypes(device: &Device) -> Result<()> {
+    let t = Tensor::new(&[-3i32, 0, 2, 130], device)?;
+    assert_eq!(t.dtype(), DType::I32);
+    assert_eq!((&t + &t)?.to_vec1::<i32>()?, &[-6, 0, 4, 260]);
+    assert_eq!(t.to_dtype(DType::I8)?.to_vec1::<i8>()?, &[-3, 0, 2, -126]);
+    assert_eq!(t.to_dtype(DType::I16)?.to_vec1::<i16>()?, &[-3, 0, 2, 130]);
+    assert_eq!(
+        t.to_dtype(DType::F32)?.to_vec1::<f32>()?,
+        &[-3., 0., 2., 130.]
+    );
+    let b = t.to_dtype(DType::Bool)?;
+    assert_eq!(b.dtype(), DType::Bool);
+    assert_eq!(b.to_dtype(DType::U8)?.to_vec1::<u8>()?, &[1, 0, 1, 1]);
+    assert!(b.to_vec1::<u8>().is_err());
+    let on_true = Tensor::new(&[1f32, 2., 3., 4.], device)?;
+    let on_false = on_true.neg()?;
+    let res = b.where_cond(&on_true, &on_false)?;
+    assert_eq!(res.to_vec1::<f32>()?, &[1., -2., 3., 4.]);
+    let res = t.gt(0i32)?.where_cond(&on_true, &on_false)?;
+    assert_eq!(res.to_vec1::<f32>()?, &[-1., -2., 3., 4.]);
+
+    assert_eq!(t.neg()?.to_vec1::<i32>()?, &[3, 0, -2, -130]);
+    assert_eq!(t.abs()?.to_vec1::<i32>()?, &[3, 0, 2, 130]);
+    assert_eq!(t.sqr()?.to_vec1::<i32>()?, &[9, 0, 4, 16900]);
+    assert_eq!(t.relu()?.to_vec1::<i32>()?, &[0, 0, 2, 130]);
+    let t64 = t.to_dtype(DType::I64)?;
+    assert_eq!(t64.relu()?.to_vec1::<i64>()?, &[0, 0, 2, 130]);
+    assert!(t.exp().is_err());
+    assert!(t.sqrt().is_err());
+    assert!(t.to_dtype(DType::U32)?.neg().is_err());
+    Ok(())
+}
+
+fn extended_ops(device: &Device) -> Result<()> {
+    let x = Tensor::new(&[-1.5f32, -0.5, 0.5, 1.5, 2.5, 2.7], device)?;
+    assert_eq!(x.floor()?.to_vec1::<f32>()?, [-2., -1., 0., 1., 2., 2.]);
+    assert_eq!(x.ceil()?.to_vec1::<f32>()?, [-1., 0., 1., 2., 3., 3.]);
+    assert_eq!(x.round()?.to_vec1::<f32>()?, [-2., 0., 0., 2., 2., 3.]);
+    assert_eq!(x.sign()?.to_vec1::<f32>()?, [-1., -1., 1., 1., 1., 1.]);
+    let x16 = x.to_dtype(DType::F16)?;
+    assert_eq!(
+        x16.round()?.to_dtype(DType::F32)?.to_vec1::<f32>()?,
+        [-2., 0., 0., 2., 2., 3.]
+    );
+    let sign = Tensor::new(&[0f64, -3., f64::NAN], device)?
+        .sign()?
+        .to_vec1::<f64>()?;
+    assert_eq!(sign[..2], [0., -1.]);
+    assert!(sign[2].is_nan());
+
+    // The vectorized rounding handles the leftover elements.
+    let x = (Tensor::arange(0f32, 19., device)? * 0.75)?.affine(1., -7.)?;
+    for (f32_res, f64_res) in [
+        (x.floor()?, x.to_dtype(DType::F64)?.floor()?),
+        (x.ceil()?, x.to_dtype(DType::F64)?.ceil()?),
+        (x.round()?, x.to_dtype(DType::F64)?.round()?),
+    ] {
+        let f64_res = f64_res.to_dtype(DType::F32)?;
+        assert_eq!(f32_res.to_vec1::<f32>()?, f64_res.to_vec1::<f32>()?);
+    }
+
+    let x = Tensor::new(&[0f32, 0.5, -1., 3.], device)?;
+    assert_eq!(
+        test_utils::to_vec1_round(&x.erf()?, 4)?,
+        [0., 0.5205, -0.8427, 1.]
+    );
+
+    let i = Tensor::new(&[-5i64, 0, 7], device)?;
+    assert_eq!(i.sign()?.to_vec1::<i64>()?, [-1, 0, 1]);
+    assert_eq!(i.floor()?.to_vec1::<i64>()?, [-5, 0, 7]);
+    let u = Tensor::new(&[3u32, 0], device)?;
+    assert_eq!(u.sign()?.to_vec1::<u32>()?, [1, 0]);
+    assert!(i.erf().is_err());
+
+    let lhs = Tensor::new(&[2f32, 3., 1., -1.], device)?;
+    let rhs = Tensor::new(&[3f32, 0.5, -1., -1.], device)?;
+    assert_eq!(
+        test_utils::to_vec1_round(&lhs.pow(&rhs)?, 4)?,
+        [8., 1.7321, 1., -1.]
+    );
+    assert_eq!(
+        test_utils::to_vec1_round(&lhs.atan2(&rhs)?, 4)?,
+        [0.588, 1.4056, 2.3562, -2.3562]
+    );
+    let lhs = Tensor::new(&[5.5f32, -5.5, 7.], device)?;
+    let rhs = Tensor::new(&[2f32, 2., -3.], device)?;
+    assert_eq!(lhs.fmod(&rhs)?.to_vec1::<f32>()?, [1.5, -1.5, 1.]);
+    let rhs = Tensor::new(&[2f32], device)?;
+    assert_eq!(lhs.broadcast_fmod(&rhs)?.to_vec1::<f32>()?, [1.5, -1.5, 1.]);
+    assert_eq!(
+        lhs.broadcast_pow(&rhs)?.to_vec1::<f32>()?,
+        [30.25, 30.25, 49.]
+    );
+    let lhs = Tensor::new(&[7i64, -7, 5], device)?;
+    let rhs = Tensor::new(&[3i64, 3, 0], device)?;
+    assert_eq!(lhs.fmod(&rhs)?.to_vec1::<i64>()?, [1, -1, 0]);
+    assert!(lhs.pow(&rhs).is_err());
+
+    let lhs = Tensor::new(&[0b1100u8, 0b1010], device)?;
+    let rhs = Tensor::new(&[0b1010u8, 0b0110], device)?;
+    assert_eq!(lhs.bitwise_and(&rhs)?.to_vec1::<u8>()?, [0b1000, 0b0010]);
+    assert_eq!(lhs.bitwise_or(&rhs)?.to_vec1::<u8>()?, [0b1110, 0b1110]);
+    assert_eq!(lhs.bitwise_xor(&rhs)?.to_vec1::<u8>()?, [0b0110, 0b1100]);
+    let lhs = Tensor::new(&[-1i32, 6], device)?;
+    let rhs = Tensor::new(&[5i32, 3], device)?;
+    assert_eq!(lhs.bitwise_and(&rhs)?.to_vec1::<i32>()?, [5, 2]);
+    assert!(x.bitwise_and(&x).is_err());
+
+    let a = Tensor::new(&[0f32, 1., 2., 0.], device)?;
+    let b = Tensor::new(&[1u32, 0, 3, 0], device)?;
+    let to_u8 = |t: Tensor| -> Result<Vec<u8>> {
+        assert_eq!(t.dtype(), DType::Bool);
+        t.to_dtype(DType::U8)?.to_vec1::<u8>()
+    };
+    assert_eq!(to_u8(a.logical_and(&b)?)?, [0, 0, 1, 0]);
+    assert_eq!(to_u8(a.logical_or(&b)?)?, [1, 1, 1, 0]);
+    assert_eq!(to_u8(a.logical_xor(&b)?)?, [1, 1, 0, 0]);
+    assert_eq!(to_u8(a.logical_not()?)?, [1, 0, 0, 1]);
+    assert_eq!(to_u8(a.gt(0f32)?.logical_not()?)?, [1, 0, 0, 1]);
     Ok(())
 }
 
@@ -888,6 +1012,125 @@ fn broadcasting(device: &Device) -> Result<()> {
     Ok(())
 }
 
+fn cumulative(device: &Device) -> Result<()> {
+    let data = &[[3u32, 1, 4, 1, 5], [2, 1, 7, 8, 2]];
+    let tensor = Tensor::new(data, device)?;
+    assert_eq!(
+        tensor.cumsum(1)?.to_vec2::<u32>()?,
+        &[[3, 4, 8, 9, 14], [2, 3, 10, 18, 20]]
+    );
+    assert_eq!(
+        tensor.cumsum(0)?.to_vec2::<u32>()?,
+        &[[3, 1, 4, 1, 5], [5, 2, 11, 9, 7]]
+    );
+    assert_eq!(
+        tensor.cumprod(1)?.to_vec2::<u32>()?,
+        &[[3, 3, 12, 12, 60], [2, 2, 14, 112, 224]]
+    );
+    assert_eq!(
+        tensor.cummax(1)?.to_vec2::<u32>()?,
+        &[[3, 3, 4, 4, 5], [2, 2, 7, 8, 8]]
+    );
+    // Non-contiguous inputs.
+    let tensor = Tensor::new(data, device)?.t()?;
+    assert_eq!(
+        tensor.cumsum(0)?.to_vec2::<u32>()?,
+        &[[3, 2], [4, 3], [8, 10], [9, 18], [14, 20]]
+    );
+    assert_eq!(
+        tensor.cummax(1)?.to_vec2::<u32>()?,
+        &[[3, 3], [1, 1], [4, 7], [1, 8], [5, 5]]
+    );
+
+    let tensor = Tensor::arange(0f32, 24., device)?.reshape((2, 3, 4))?;
+    let cumsum = tensor.cumsum(1)?;
+    assert_eq!(cumsum.dims(), &[2, 3, 4]);
+    assert_eq!(
+        cumsum.to_vec3::<f32>()?,
+        &[
+            [[0., 1., 2., 3.], [4., 6., 8., 10.], [12., 15., 18., 21.]],
+            [
+                [12., 13., 14., 15.],
+                [28., 30., 32., 34.],
+                [48., 51., 54., 57.]
+            ]
+        ]
+    );
+    let tensor = Tensor::new(&[-1f64, 3., -2., 5., 4.], device)?;
+    assert_eq!(tensor.cummax(0)?.to_vec1::<f64>()?, &[-1., 3., 3., 5., 5.]);
+    assert_eq!(
+        tensor.cumprod(0)?.to_vec1::<f64>()?,
+        &[-1., -3., 6., 30., 120.]
+    );
+    Ok(())
+}
+
+fn sort(device: &Device) -> Result<()> {
+    let data = &[[3f32, 1., 4., 1., 5.], [2., 1., 7., 8., 2.]];
+    let tensor = Tensor::new(data, device)?;
+    let (values, indexes) = tensor.sort(1, false)?;
+    assert_eq!(
+        values.to_vec2::<f32>()?,
+        &[[1., 1., 3., 4., 5.], [1., 2., 2., 7., 8.]]
+    );
+    assert_eq!(
+        indexes.to_vec2::<u32>()?,
+        &[[1, 3, 0, 2, 4], [1, 0, 4, 2, 3]]
+    );
+    let (values, indexes) = tensor.sort(1, true)?;
+    assert_eq!(
+        values.to_vec2::<f32>()?,
+        &[[5., 4., 3., 1., 1.], [8., 7., 2., 2., 1.]]
+    );
+    assert_eq!(
+        indexes.to_vec2::<u32>()?,
+        &[[4, 2, 0, 1, 3], [3, 2, 0, 4, 1]]
+    );
+    let (values, indexes) = tensor.sort(0, false)?;
+    assert_eq!(
+        values.to_vec2::<f32>()?,
+        &[[2., 1., 4., 1., 2.], [3., 1., 7., 8., 5.]]
+    );
+    assert_eq!(
+        indexes.to_vec2::<u32>()?,
+        &[[1, 0, 0, 0, 1], [0, 1, 1, 1, 0]]
+    );
+
+    // Non-contiguous inputs.
+    let tensor = tensor.t()?;
+    let (values, indexes) = tensor.sort(0, true)?;
+    assert_eq!(
+        values.to_vec2::<f32>()?,
+        &[[5., 8.], [4., 7.], [3., 2.], [1., 2.], [1., 1.]]
+    );
+    assert_eq!(
+        indexes.to_vec2::<u32>()?,
+        &[[4, 3], [2, 2], [0, 0], [1, 4], [3, 1]]
+    );
+    assert_eq!(
+        tensor.arg_sort(1, false)?.to_vec2::<u32>()?,
+        &[[1, 0], [0, 1], [0, 1], [0, 1], [1, 0]]
+    );
+
+    let tensor = Tensor::new(&[3i64, -1, 4, -1, 5, 9, -2, 6], device)?;
+    let (values, indexes) = tensor.topk(3, 0)?;
+    assert_eq!(values.to_vec1::<i64>()?, &[9, 6, 5]);
+    assert_eq!(indexes.to_vec1::<u32>()?, &[5, 7, 4]);
+
+    // Nan values are sorted after all the other values.
+    let nan = f32::NAN;
+    let tensor = Tensor::new(&[nan, 1., nan, -3., 2., nan, 0., 5., nan, -1.], device)?;
+    assert_eq!(
+        tensor.arg_sort(0, false)?.to_vec1::<u32>()?,
+        &[3, 9, 6, 1, 4, 7, 0, 2, 5, 8]
+    );
+    assert_eq!(
+        tensor.arg_sort(0, true)?.to_vec1::<u32>()?,
+        &[0, 2, 5, 8, 7, 4, 1, 6, 9, 3]
+    );
+    Ok(())
+}
+
 fn randn(device: &Device) -> Result<()> {
     let tensor = Tensor::randn(0f32, 1f32, (5, 3), device)?;
     assert_eq!(tensor.dims(), [5, 3]);
@@ -896,6 +1139,273 @@ fn randn(device: &Device) -> Result<()> {
     Ok(())
 }
 
+fn rand_generator(device: &Device) -> Result<()> {
+    // The values only depend on the generator state and are the same on all devices.
+    let mut gen = Generator::new(299792458);
+    let u = Tensor::rand_with_generator(-1f32, 3f32, 10000, device, &mut gen)?;
+    let n = Tensor::randn_with_generator(1f64, 2f64, 10000, device, &mut gen)?;
+    assert_eq!(gen.offset(), 20000);
+    let mut gen = Generator::new(299792458);
+    let u_cpu = Tensor::rand_with_generator(-1f32, 3f32, 10000, &Device::Cpu, &mut gen)?;
+    let n_cpu = Tensor::randn_with_generator(1f64, 2f64, 10000, &Device::Cpu, &mut gen)?;
+    assert_eq!(u.to_vec1::<f32>()?, u_cpu.to_vec1::<f32>()?);
+    assert_eq!(n.to_vec1::<f64>()?, n_cpu.to_vec1::<f64>()?);
+
+    let u = u.to_vec1::<f32>()?;
+    assert!(u.iter().all(|&v| (-1. ..3.).contains(&v)));
+    let mean = u.iter().sum::<f32>() / 10000.;
+    assert!((mean - 1.).abs() < 0.05, "{mean}");
+    let mean = n.mean_all()?.to_scalar::<f64>()?;
+    let std = n.broadcast_sub(&n.mean_all()?)?.sqr()?.mean_all()?.sqrt()?;
+    let std = std.to_scalar::<f64>()?;
+    assert!((mean - 1.).abs() < 0.05, "{mean}");
+    assert!((std - 2.).abs() < 0.05, "{std}");
+
+    // Replaying a draw.
+    gen.set_offset(10000);
+    let n2 = Tensor::randn_with_generator(1f64, 2f64, 10000, device, &mut gen)?;
+    assert_eq!(n2.to_vec1::<f64>()?, n_cpu.to_vec1::<f64>()?);
+    let mut gen = Generator::new(1);
+    let t = Tensor::rand_with_generator(0f64, 1f64, 10000, device, &mut gen)?;
+    assert_ne!(t.to_vec1::<f64>()?, n_cpu.to_vec1::<f64>()?);
+    Ok(())
+}
+
+fn inplace_ops(device: &Device) -> Result<()> {
+    let t = Tensor::new(&[[1f32, 2., 3.], [4., 5., 6.]], device)?;
+    let view = t.reshape(6)?;
+    t.add_(&Tensor::new(&[1f32, 1., 1.], device)?)?;
+    t.mul_(&Tensor::new(&[[2f32], [3.]], device)?)?;
+    assert_eq!(t.to_vec2::<f32>()?, &[[4., 6., 8.], [15., 18., 21.]]);
+    // Views share the storage.
+    assert_eq!(view.to_vec1::<f32>()?, &[4., 6., 8., 15., 18., 21.]);
+    t.affine_(0.5, -1.)?;
+    t.sub_(&t)?;
+    assert_eq!(t.to_vec2::<f32>()?, &[[0., 0., 0.], [0., 0., 0.]]);
+
+    // Strided destination.
+    let cache = Tensor::zeros((2, 4), DType::F32, device)?.contiguous()?;
+    let tt = Tensor::new(&[[1f32, 2.], [3., 4.]], device)?.t()?;
+    cache.narrow(1, 1, 2)?.copy_from(&tt)?;
+    cache
+        .narrow(1, 3, 1)?
+        .copy_from(&Tensor::new(7f32, device)?)?;
+    cache
+        .narrow(1, 1, 1)?
+        .div_(&Tensor::new(&[[2f32], [4.]], device)?)?;
+    assert_eq!(
+        cache.to_vec2::<f32>()?,
+        &[[0., 0.5, 3., 7.], [0., 0.5, 4., 7.]]
+    );
+
+    // Output buffers, including outputs aliasing the inputs.
+    let a = Tensor::new(&[1f32, 2., 3.], device)?;
+    let b = Tensor::new(&[4f32, 5., 6.], device)?;
+    let out = Tensor::zeros(3, DType::F32, device)?.contiguous()?;
+    a.sub_out(&b, &out)?;
+    assert_eq!(out.to_vec1::<f32>()?, &[-3., -3., -3.]);
+    a.affine_out(2., 1., &out)?;
+    assert_eq!(out.to_vec1::<f32>()?, &[3., 5., 7.]);
+    a.div_out(&b, &b)?;
+    assert_eq!(b.to_vec1::<f32>()?, &[0.25, 0.4, 0.5]);
+    a.mul_out(&a, &a)?;
+    assert_eq!(a.to_vec1::<f32>()?, &[1., 4., 9.]);
+
+    // Broadcasted destinations and tensors tracking gradients are rejected.
+    let z = Tensor::zeros(3, DType::F32, device)?;
+    assert!(z.add_(&a).is_err());
+    let v = candle_core::Var::new(&[1f32, 2., 3.], device)?;
+    assert!(v.add_(&a).is_err());
+    assert!(a.add_(&v).is_err());
+    assert!(v.exp()?.copy_from(&a).is_err());
+    assert!(a.add_(&Tensor::new(&[1u32, 2, 3], device)?).is_err());
+    Ok(())
+}
+
+fn fft(device: &Device) -> Result<()> {
+    let t = Tensor::new(&[[1f32, 2., 3., 4.], [0., 1., 0., -1.]], device)?;
+    let f = t.fft(1)?;
+    assert_eq!(f.dtype(), DType::C64);
+    // Activations are not defined on complex values.
+    assert!(f.gelu().is_err());
+    assert!(f.relu().is_err());
+    assert_eq!(
+        test_utils::to_vec3_round(&f.view_as_real()?, 4)?,
+        &[
+            [[10., 0.], [-2., 2.], [-2., 0.], [-2., -2.]],
+            [[0., 0.], [0., -2.], [0., 0.], [0., 2.]]
+        ]
+    );
+    let inv = f.ifft(1)?;
+    assert_eq!(
+        test_utils::to_vec2_round(&inv.real()?, 4)?,
+        t.to_vec2::<f32>()?
+    );
+    assert_eq!(test_utils::to_vec2_round(&inv.imag()?, 4)?, &[[0.; 4]; 2]);
+
+    // Odd sizes and the real transforms.
+    let t = Tensor::new(&[1f64, -2., 3., 0.5, 2.], device)?;
+    let f = t.rfft(0)?;
+    assert_eq!(f.dims(), &[3]);
+    assert_eq!(f.dtype(), DType::C128);
+    let f_full = t.fft(0)?.narrow(0, 0, 3)?;
+    let diff = (f.view_as_real()? - f_full.view_as_real()?)?.abs()?;
+    assert!(diff.flatten_all()?.max(0)?.to_scalar::<f64>()? < 1e-10);
+    let inv = f.irfft(0, 5)?;
+    let diff = (inv - &t)?.abs()?;
+    assert!(diff.flatten_all()?.max(0)?.to_scalar::<f64>()? < 1e-10);
+
+    // stft followed by istft reconstructs the signal with a hann window.
+    let n_fft = 8;
+    let signal: Vec<f64> = (0..40).map(|i| (i as f64 * 0.3).sin()).collect();
+    let signal = Tensor::new(signal.as_slice(), device)?;
+    let window: Vec<f64> = (0..n_fft)
+        .map(|i| 0.5 * (1. - (2. * std::f64::consts::PI * i as f64 / n_fft as f64).cos()))
+        .collect();
+    let window = Tensor::new(window.as_slice(), device)?;
+    let spec = signal.stft(n_fft, 2, Some(&window))?;
+    assert_eq!(spec.dims(), &[5, 17]);
+    let rec = spec.istft(n_fft, 2, Some(&window), Some(40))?;
+    assert_eq!(rec.dims(), &[40]);
+    // The first and last samples are not covered by the window.
+    let diff = (rec.narrow(0, 2, 36)? - signal.narrow(0, 2, 36)?)?.abs()?;
+    assert!(diff.flatten_all()?.max(0)?.to_scalar::<f64>()? < 1e-10);
+    Ok(())
+}
+
+fn lazy(device: &Device) -> Result<()> {
+    // The existing tests give the same results when the elementwise ops are recorded.
+    {
+        let _lazy = candle_core::lazy::enable();
+        add_mul(device)?;
+        binary_op(device)?;
+        clamp(device)?;
+        sum(device)?;
+        cat(device)?;
+        matmul(device)?;
+        broadcasting(device)?;
+        cumulative(device)?;
+        inplace_ops(device)?;
+        fft(device)?;
+    }
+    assert!(!candle_core::lazy::is_enabled());
+
+    // A fused chain reading from strided and broadcasted inputs.
+    let x = Tensor::arange(-6f32, 6., device)?.reshape((3, 4))?.t()?;
+    let y = Tensor::new(&[0.5f32, -1., 2.], device)?;
+    let f = |x: &Tensor, y: &Tensor| -> Result<Tensor> {
+        let silu = (x / (x.neg()?.exp()? + 1.)?)?;
+        let z = silu.broadcast_mul(y)?.affine(2., -1.)?;
+        (&z + z.sqr()?.sqrt()?)?.tanh()
+    };
+    let eager = f(&x, &y)?;
+    let lazy = {
+        let _lazy = candle_core::lazy::enable();
+        f(&x, &y)?
+    };
+    assert_eq!(lazy.is_realized(), !device.is_cpu());
+    assert_eq!(lazy.to_vec2::<f32>()?, eager.to_vec2::<f32>()?);
+    assert!(lazy.is_realized());
+    for dtype in [DType::F16, DType::F64] {
+        let (x, y) = (x.to_dtype(dtype)?, y.to_dtype(dtype)?);
+        let lazy = {
+            let _lazy = candle_core::lazy::enable();
+            f(&x, &y)?.realize()?
+        };
+        assert_eq!(
+            lazy.to_dtype(DType::F32)?.to_vec2::<f32>()?,
+            f(&x, &y)?.to_dtype(DType::F32)?.to_vec2::<f32>()?
+        );
+    }
+
+    // Pending tensors are computed before their inputs get modified in place.
+    let a = Tensor::new(&[1f32, 2., 3.], device)?;
+    let (b, view) = {
+        let _lazy = candle_core::lazy::enable();
+        let b = (&a * 2.)?;
+        let view = (&b + 1.)?.reshape((3, 1))?;
+        (b, view)
+    };
+    a.add_(&a)?;
+    assert_eq!(b.to_vec1::<f32>()?, &[2., 4., 6.]);
+    assert_eq!(view.to_vec2::<f32>()?, &[[3.], [5.], [7.]]);
+
+    // Long chains of pending ops are compiled without recursing on each op.
+    let chain = {
+        let _lazy = candle_core::lazy::enable();
+        let mut chain = a.clone();
+        for _ in 0..50000 {
+            chain = (chain + 1.)?
+        }
+        chain.realize()?
+    };
+    assert_eq!(chain.to_vec1::<f32>()?, &[50002., 50004., 50006.]);
+    Ok(())
+}
+
+fn einsum(device: &Device) -> Result<()> {
+    let q = Tensor::arange(0f32, 24., device)?.reshape((1, 2, 3, 4))?;
+    let k = Tensor::arange(-8f32, 16., device)?.reshape((1, 2, 3, 4))?;
+    let attn = Tensor::einsum("bhqd,bhkd->bhqk", &[&q, &k])?;
+    assert_eq!(
+        attn.flatten_all()?.to_vec1::<f32>()?,
+        q.matmul(&k.t()?)?.flatten_all()?.to_vec1::<f32>()?
+    );
+    // Relative positions as in the sam image encoder.
+    let rel = Tensor::arange(0f32, 48., device)?.reshape((3, 4, 4))?;
+    let r = Tensor::einsum(
+        "bhwc,hkc->bhwk",
+        &[&q.i(0)?.unsqueeze(0)?, &rel.narrow(0, 0, 2)?],
+    )?;
+    assert_eq!(r.dims(), &[1, 2, 3, 4]);
+    assert_eq!(
+        r.i((0, 1, 2))?.to_vec1::<f32>()?,
+        rel.i(1)?
+            .matmul(&q.i((0, 1, 2))?.unsqueeze(1)?)?
+            .squeeze(1)?
+            .to_vec1::<f32>()?
+    );
+
+    // Transposes, traces, outer products and implicit outputs.
+    let m = Tensor::new(&[[1f32, 2., 3.], [4., 5., 6.]], device)?;
+    let t = Tensor::einsum("ij->ji", &[&m])?;
+    assert_eq!(t.to_vec2::<f32>()?, m.t()?.to_vec2::<f32>()?);
+    assert_eq!(
+        Tensor::einsum("ij", &[&m])?.to_vec2::<f32>()?,
+        m.to_vec2::<f32>()?
+    );
+    assert_eq!(Tensor::einsum("ji", &[&m])?.dims(), &[3, 2]);
+    assert_eq!(Tensor::einsum("ij->", &[&m])?.to_scalar::<f32>()?, 21.);
+    let sq = Tensor::new(&[[1f32, 2.], [3., 4.]], device)?;
+    assert_eq!(
+        Tensor::einsum("ii->i", &[&sq])?.to_vec1::<f32>()?,
+        &[1., 4.]
+    );
+    assert_eq!(Tensor::einsum("ii", &[&sq])?.to_scalar::<f32>()?, 5.);
+    let a = Tensor::new(&[1f32, 2.], device)?;
+    let b = Tensor::new(&[3f32, 4., 5.], device)?;
+    assert_eq!(
+        Tensor::einsum("i,j", &[&a, &b])?.to_vec2::<f32>()?,
+        &[[3., 4., 5.], [6., 8., 10.]]
+    );
+    assert_eq!(Tensor::einsum("i,i->", &[&a, &a])?.to_scalar::<f32>()?, 5.);
+
+    // Three operands and broadcasted ellipsis dimensions.
+    let x = Tensor::einsum("i,ij,j->", &[&a, &m, &b])?;
+    assert_eq!(x.to_scalar::<f32>()?, 150.);
+    let bm = Tensor::einsum("...ij,jk->...ik", &[&q, &m.t()?.pad_with_zeros(0, 0, 1)?])?;
+    assert_eq!(bm.dims(), &[1, 2, 3, 2]);
+    let bias = Tensor::new(&[[1f32], [2.]], device)?;
+    let r = Tensor::einsum("...i,...i->...", &[&m, &bias])?;
+    assert_eq!(r.to_vec1::<f32>()?, &[6., 30.]);
+
+    assert!(Tensor::einsum("ij,jk->ik", &[&m, &m]).is_err());
+    assert!(Tensor::einsum("ij->k", &[&m]).is_err());
+    assert!(Tensor::einsum("ijk", &[&m]).is_err());
+    assert!(Tensor::einsum("ij,jk", &[&m]).is_err());
+    Ok(())
+}
+
 test_device!(zeros, zeros_cpu, zeros_gpu);
 test_device!(add_mul, add_mul_cpu, add_mul_gpu);
 test_device!(tensor_2d, tensor_2d_cpu, tensor_2d_gpu);
@@ -919,7 +1429,32 @@ test_device!(index_add, index_add_cpu, index_add_gpu);
 test_device!(gather, gather_cpu, gather_gpu);
 test_device!(scatter_add, scatter_add_cpu, scatter_add_gpu);
 test_device!(randn, randn_cpu, randn_gpu);
+test_device!(rand_generator, rand_generator_cpu, rand_generator_gpu);
 test_device!(clamp, clamp_cpu, clamp_gpu);
+test_device!(cumulative, cumulative_cpu, cumulative_gpu);
+test_device!(sort, sort_cpu, sort_gpu);
+// Complex dtypes are only supported on the cpu.
+#[test]
+fn fft_cpu() -> Result<()> {
+    fft(&Device::Cpu)
+}
+
+#[cfg(feature = "cuda")]
+#[test]
+fn fft_gpu() -> Result<()> {
+    let t = Tensor::new(&[1f32, 2., 3., 4.], &Device::new_cuda(0)?)?;
+    assert!(t.fft(0).is_err());
+    Ok(())
+}
+test_device!(inplace_ops, inplace_ops_cpu, inplace_ops_gpu);
+test_device!(lazy, lazy_cpu, lazy_gpu);
+test_device!(einsum, einsum_cpu, einsum_gpu);
+test_device!(extended_ops, extended_ops_cpu, extended_ops_gpu);
+test_device!(
+    int_and_bool_dtypes,
+    int_and_bool_dtypes_cpu,
+    int_and_bool_dtypes_gpu
+);
 
 // There was originally a bug on the CPU implementation for randn
 // https://github.com/huggingface/candle/issues/381
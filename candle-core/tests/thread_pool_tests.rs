@@ -0,0 +1,65 @@
+use candle_core::{CpuStorage, CustomOp1, DType, Device, Layout, Result, Shape, Tensor};
+
+// Reports the number of threads of the pool on which the cpu kernels run.
+struct NumThreads;
+
+impl CustomOp1 for NumThreads {
+    fn name(&self) -> &'static str {
+        "num-threads"
+    }
+
+    fn cpu_fwd(&self, _: &CpuStorage, _: &Layout) -> Result<(CpuStorage, Shape)> {
+        let n = candle_core::cpu_pool::install(rayon::current_num_threads);
+        Ok((CpuStorage::U32(vec![n as u32]), Shape::from(())))
+    }
+}
+
+fn num_threads(t: &Tensor) -> Result<u32> {
+    t.apply_op1_no_bwd(&NumThreads)?.to_scalar::<u32>()
+}
+
+#[test]
+fn device_pool() -> Result<()> {
+    let device = Device::cpu_with_threads(3)?;
+    assert!(device.is_cpu());
+    assert!(device.same_device(&device.clone()));
+    assert!(!device.same_device(&Device::Cpu));
+    assert!(!device.same_device(&Device::cpu_with_threads(3)?));
+
+    let a = Tensor::arange(0f32, 64., &device)?.reshape((8, 8))?;
+    assert_eq!(num_threads(&a)?, 3);
+    let b = a.matmul(&a.t()?)?.exp()?.sum_keepdim(1)?;
+    assert_eq!(num_threads(&b)?, 3);
+    assert_eq!(b.device().cpu_pool().map(|p| p.num_threads()), Some(3));
+
+    // The results match the ones from the default device.
+    let a_cpu = a.to_device(&Device::Cpu)?;
+    assert!(a_cpu.device().cpu_pool().is_none());
+    let b_cpu = a_cpu.matmul(&a_cpu.t()?)?.exp()?.sum_keepdim(1)?;
+    assert_eq!(b.to_vec2::<f32>()?, b_cpu.to_vec2::<f32>()?);
+
+    // Tensors from different cpu devices can be mixed, the first operand pool gets used.
+    let c = (&a + &a_cpu)?;
+    assert!(c.device().same_device(&device));
+    assert_eq!(c.to_vec2::<f32>()?, (&a_cpu * 2.)?.to_vec2::<f32>()?);
+    let c = Tensor::cat(&[&a, &a_cpu], 0)?;
+    assert_eq!(c.dims(), [16, 8]);
+    Ok(())
+}
+
+#[test]
+fn set_num_threads() -> Result<()> {
+    let t = Tensor::zeros(4, DType::F32, &Device::Cpu)?;
+    candle_core::set_num_threads(2)?;
+    assert_eq!(num_threads(&t)?, 2);
+    assert_eq!(candle_core::utils::get_num_threads(), 2);
+    let a = Tensor::arange(0f32, 16., &Device::Cpu)?.reshape((4, 4))?;
+    let b = a.matmul(&a)?;
+    // Devices with their own pool are not impacted.
+    let pool = Device::cpu_with_threads(1)?;
+    assert_eq!(num_threads(&t.to_device(&pool)?)?, 1);
+    candle_core::set_num_threads(0)?;
+    assert_eq!(num_threads(&t)? as usize, rayon::current_num_threads());
+    assert_eq!(b.to_vec2::<f32>()?, a.matmul(&a)?.to_vec2::<f32>()?);
+    Ok(())
+}
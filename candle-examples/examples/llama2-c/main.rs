@@ -84,6 +84,11 @@ pub struct TrainingCmd {
 
     #[arg(long, default_value_t = 0.001)]
     learning_rate: f64,
+
+    /// Recompute the activations of each block during the backward pass rather than keeping
+    /// them in memory.
+    #[arg(long)]
+    checkpoint: bool,
 }
 
 #[derive(Subcommand, Debug, Clone)]
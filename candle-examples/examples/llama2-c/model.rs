@@ -278,7 +278,7 @@ impl Block {
 
 pub struct Llama {
     wte: Embedding,
-    blocks: Vec<Block>,
+    blocks: Vec<Arc<Block>>,
     ln_f: RmsNorm,
     lm_head: Linear,
     pub config: Config,
@@ -296,12 +296,29 @@ impl Llama {
         logits.to_dtype(DType::F32)
     }
 
+    /// Same as `forward` but the activations within each block are not kept for the backward
+    /// pass, they get recomputed instead. This reduces the memory used when training.
+    pub fn forward_checkpointed(&self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let mut x = self.wte.forward(x)?;
+        for (block_idx, block) in self.blocks.iter().enumerate() {
+            let block = block.clone();
+            let f = move |xs: &[Tensor]| block.forward(&xs[0], index_pos, block_idx);
+            x = candle::checkpoint(f, &[&x])?;
+        }
+        let x = self.ln_f.forward(&x)?;
+        let logits = self.lm_head.forward(&x)?;
+        logits.to_dtype(DType::F32)
+    }
+
     pub fn load(vb: VarBuilder, cache: &Cache, cfg: Config) -> Result<Self> {
         let wte = embedding(cfg.vocab_size, cfg.dim, vb.pp("model.embed_tokens"))?;
         let lm_head = linear(cfg.dim, cfg.vocab_size, vb.pp("lm_head"))?;
         let ln_f = rms_norm(cfg.dim, cfg.norm_eps, vb.pp("model.norm"))?;
         let blocks: Vec<_> = (0..cfg.n_layers)
-            .map(|i| Block::load(vb.pp(&format!("model.layers.{i}")), cache, &cfg).unwrap())
+            .map(|i| {
+                let block = Block::load(vb.pp(&format!("model.layers.{i}")), cache, &cfg);
+                Arc::new(block.unwrap())
+            })
             .collect();
         Ok(Self {
             wte,
@@ -7,6 +7,7 @@ extern crate accelerate_src;
 
 use clap::{Parser, ValueEnum};
 use rand::prelude::*;
+use std::sync::Arc;
 
 use candle::{DType, Result, Tensor, D};
 use candle_nn::{loss, ops, Conv2d, Linear, Module, Optimizer, VarBuilder, VarMap};
@@ -61,40 +62,50 @@ impl Model for Mlp {
 
 #[derive(Debug)]
 struct ConvNet {
-    conv1: Conv2d,
-    conv2: Conv2d,
+    conv1: Arc<Conv2d>,
+    conv2: Arc<Conv2d>,
     fc1: Linear,
     fc2: Linear,
     dropout: candle_nn::Dropout,
+    checkpoint: bool,
+}
+
+fn conv_features(conv1: &Conv2d, conv2: &Conv2d, xs: &Tensor) -> Result<Tensor> {
+    xs.apply(conv1)?
+        .max_pool2d(2)?
+        .apply(conv2)?
+        .max_pool2d(2)?
+        .flatten_from(1)
 }
 
 impl ConvNet {
-    fn new(vs: VarBuilder) -> Result<Self> {
+    fn new(vs: VarBuilder, checkpoint: bool) -> Result<Self> {
         let conv1 = candle_nn::conv2d(1, 32, 5, Default::default(), vs.pp("c1"))?;
         let conv2 = candle_nn::conv2d(32, 64, 5, Default::default(), vs.pp("c2"))?;
         let fc1 = candle_nn::linear(1024, 1024, vs.pp("fc1"))?;
         let fc2 = candle_nn::linear(1024, LABELS, vs.pp("fc2"))?;
         let dropout = candle_nn::Dropout::new(0.5);
         Ok(Self {
-            conv1,
-            conv2,
+            conv1: Arc::new(conv1),
+            conv2: Arc::new(conv2),
             fc1,
             fc2,
             dropout,
+            checkpoint,
         })
     }
 
     fn forward(&self, xs: &Tensor, train: bool) -> Result<Tensor> {
         let (b_sz, _img_dim) = xs.dims2()?;
-        let xs = xs
-            .reshape((b_sz, 1, 28, 28))?
-            .apply(&self.conv1)?
-            .max_pool2d(2)?
-            .apply(&self.conv2)?
-            .max_pool2d(2)?
-            .flatten_from(1)?
-            .apply(&self.fc1)?
-            .relu()?;
+        let xs = xs.reshape((b_sz, 1, 28, 28))?;
+        let xs = if train && self.checkpoint {
+            // The convolution activations are recomputed in the backward pass.
+            let (conv1, conv2) = (self.conv1.clone(), self.conv2.clone());
+            candle::checkpoint(move |xs| conv_features(&conv1, &conv2, &xs[0]), &[&xs])?
+        } else {
+            conv_features(&self.conv1, &self.conv2, &xs)?
+        };
+        let xs = xs.apply(&self.fc1)?.relu()?;
         self.dropout.forward(&xs, train)?.apply(&self.fc2)
     }
 }
@@ -104,6 +115,7 @@ struct TrainingArgs {
     load: Option<String>,
     save: Option<String>,
     epochs: usize,
+    checkpoint: bool,
 }
 
 fn training_loop_cnn(
@@ -120,7 +132,7 @@ fn training_loop_cnn(
 
     let mut varmap = VarMap::new();
     let vs = VarBuilder::from_varmap(&varmap, DType::F32, &dev);
-    let model = ConvNet::new(vs.clone())?;
+    let model = ConvNet::new(vs.clone(), args.checkpoint)?;
 
     if let Some(load) = &args.load {
         println!("loading weights from {load}");
@@ -249,6 +261,11 @@ struct Args {
     /// The directory where to load the dataset from, in ubyte format.
     #[arg(long)]
     local_mnist: Option<String>,
+
+    /// Recompute the activations of the convolution layers during the backward pass rather than
+    /// keeping them in memory, this is only used by the cnn model.
+    #[arg(long)]
+    checkpoint: bool,
 }
 
 pub fn main() -> anyhow::Result<()> {
@@ -274,6 +291,7 @@ pub fn main() -> anyhow::Result<()> {
         learning_rate: args.learning_rate.unwrap_or(default_learning_rate),
         load: args.load,
         save: args.save,
+        checkpoint: args.checkpoint,
     };
     match args.model {
         WhichModel::Linear => training_loop::<LinearModel>(m, &training_args),
@@ -123,7 +123,7 @@ pub fn main() -> anyhow::Result<()> {
         println!("mask:\n{mask}");
         println!("iou_predictions: {iou_predictions:?}");
 
-        let mask = (mask.ge(args.threshold)? * 255.)?;
+        let mask = (mask.ge(args.threshold)?.to_dtype(DType::U8)? * 255.)?;
         let (_one, h, w) = mask.dims3()?;
         let mask = mask.expand((3, h, w))?;
 
@@ -476,9 +476,9 @@ fn main() -> Result<()> {
         .map(|v| *v as f32 / 32768.)
         .collect();
     println!("pcm data loaded {}", pcm_data.len());
-    let mel = audio::pcm_to_mel(&pcm_data, &mel_filters);
-    let mel_len = mel.len();
-    let mel = Tensor::from_vec(mel, (1, m::N_MELS, mel_len / m::N_MELS), &device)?;
+    let pcm_data = Tensor::new(pcm_data.as_slice(), &device)?;
+    let mel_filters = Tensor::new(mel_filters.as_slice(), &device)?;
+    let mel = audio::log_mel_spectrogram(&pcm_data, &mel_filters)?.unsqueeze(0)?;
     println!("loaded mel: {:?}", mel.dims());
 
     let weights = unsafe { candle::safetensors::MmapedFile::new(weights_filename)? };
@@ -41,3 +41,6 @@ AFFINE_OP(double, affine_f64)
 AFFINE_OP(uint8_t, affine_u8)
 AFFINE_OP(uint32_t, affine_u32)
 AFFINE_OP(int64_t, affine_i64)
+AFFINE_OP(int8_t, affine_i8)
+AFFINE_OP(int16_t, affine_i16)
+AFFINE_OP(int32_t, affine_i32)
@@ -8,6 +8,9 @@ BINARY_OP(__nv_bfloat16, bmul_bf16, x * y)
 BINARY_OP(__nv_bfloat16, bsub_bf16, x - y)
 BINARY_OP(__nv_bfloat16, bmaximum_bf16, maxg(x, y))
 BINARY_OP(__nv_bfloat16, bminimum_bf16, ming(x, y))
+BINARY_OP(__nv_bfloat16, bpow_bf16, powg(x, y))
+BINARY_OP(__nv_bfloat16, batan2_bf16, atan2g(x, y))
+BINARY_OP(__nv_bfloat16, bfmod_bf16, fmodg(x, y))
 BINARY_OP_OUT(__nv_bfloat16, uint8_t, eq_bf16, x == y)
 BINARY_OP_OUT(__nv_bfloat16, uint8_t, ne_bf16, x != y)
 BINARY_OP_OUT(__nv_bfloat16, uint8_t, lt_bf16, x < y)
@@ -23,6 +26,9 @@ BINARY_OP(__half, bmul_f16, x * y)
 BINARY_OP(__half, bsub_f16, x - y)
 BINARY_OP(__half, bmaximum_f16, maxg(x, y))
 BINARY_OP(__half, bminimum_f16, ming(x, y))
+BINARY_OP(__half, bpow_f16, powg(x, y))
+BINARY_OP(__half, batan2_f16, atan2g(x, y))
+BINARY_OP(__half, bfmod_f16, fmodg(x, y))
 BINARY_OP_OUT(__half, uint8_t, eq_f16, x == y)
 BINARY_OP_OUT(__half, uint8_t, ne_f16, x != y)
 BINARY_OP_OUT(__half, uint8_t, lt_f16, x < y)
@@ -36,64 +42,134 @@ BINARY_OP(double, badd_f64, x + y);
 BINARY_OP(uint8_t, badd_u8, x + y);
 BINARY_OP(uint32_t, badd_u32, x + y);
 BINARY_OP(int64_t, badd_i64, x + y);
+BINARY_OP(int8_t, badd_i8, x + y);
+BINARY_OP(int16_t, badd_i16, x + y);
+BINARY_OP(int32_t, badd_i32, x + y);
 BINARY_OP(float, bdiv_f32, x / y)
 BINARY_OP(double, bdiv_f64, x / y);
 BINARY_OP(uint8_t, bdiv_u8, x / y);
 BINARY_OP(uint32_t, bdiv_u32, x / y);
 BINARY_OP(int64_t, bdiv_i64, x / y);
+BINARY_OP(int8_t, bdiv_i8, x / y);
+BINARY_OP(int16_t, bdiv_i16, x / y);
+BINARY_OP(int32_t, bdiv_i32, x / y);
 BINARY_OP(float, bmul_f32, x * y)
 BINARY_OP(double, bmul_f64, x * y);
 BINARY_OP(uint8_t, bmul_u8, x * y);
 BINARY_OP(uint32_t, bmul_u32, x * y);
 BINARY_OP(int64_t, bmul_i64, x * y);
+BINARY_OP(int8_t, bmul_i8, x * y);
+BINARY_OP(int16_t, bmul_i16, x * y);
+BINARY_OP(int32_t, bmul_i32, x * y);
 BINARY_OP(float, bsub_f32, x - y)
 BINARY_OP(double, bsub_f64, x - y);
 BINARY_OP(uint8_t, bsub_u8, x - y);
 BINARY_OP(uint32_t, bsub_u32, x - y);
 BINARY_OP(int64_t, bsub_i64, x - y);
+BINARY_OP(int8_t, bsub_i8, x - y);
+BINARY_OP(int16_t, bsub_i16, x - y);
+BINARY_OP(int32_t, bsub_i32, x - y);
 BINARY_OP(float, bminimum_f32, ming(x, y));
 BINARY_OP(double, bminimum_f64, ming(x, y));
 BINARY_OP(uint8_t, bminimum_u8, ming(x, y));
 BINARY_OP(uint32_t, bminimum_u32, ming(x, y));
 BINARY_OP(int64_t, bminimum_i64, ming(x, y));
+BINARY_OP(int8_t, bminimum_i8, ming(x, y));
+BINARY_OP(int16_t, bminimum_i16, ming(x, y));
+BINARY_OP(int32_t, bminimum_i32, ming(x, y));
 BINARY_OP(float, bmaximum_f32, maxg(x, y));
 BINARY_OP(double, bmaximum_f64, maxg(x, y));
 BINARY_OP(uint8_t, bmaximum_u8, maxg(x, y));
 BINARY_OP(uint32_t, bmaximum_u32, maxg(x, y));
 BINARY_OP(int64_t, bmaximum_i64, maxg(x, y));
+BINARY_OP(int8_t, bmaximum_i8, maxg(x, y));
+BINARY_OP(int16_t, bmaximum_i16, maxg(x, y));
+BINARY_OP(int32_t, bmaximum_i32, maxg(x, y));
+BINARY_OP(float, bpow_f32, powg(x, y))
+BINARY_OP(double, bpow_f64, powg(x, y))
+BINARY_OP(float, batan2_f32, atan2g(x, y))
+BINARY_OP(double, batan2_f64, atan2g(x, y))
+BINARY_OP(float, bfmod_f32, fmodg(x, y))
+BINARY_OP(double, bfmod_f64, fmodg(x, y))
+
+// Integer remainders by zero are set to zero.
+BINARY_OP(uint8_t, bfmod_u8, y == 0 ? 0 : x % y)
+BINARY_OP(uint32_t, bfmod_u32, y == 0 ? 0 : x % y)
+BINARY_OP(int64_t, bfmod_i64, y == 0 ? 0 : x % y)
+BINARY_OP(int8_t, bfmod_i8, y == 0 ? 0 : x % y)
+BINARY_OP(int16_t, bfmod_i16, y == 0 ? 0 : x % y)
+BINARY_OP(int32_t, bfmod_i32, y == 0 ? 0 : x % y)
+
+// Booleans are stored as u8 and use the u8 kernels.
+BINARY_OP(uint8_t, bbitand_u8, x & y)
+BINARY_OP(uint32_t, bbitand_u32, x & y)
+BINARY_OP(int64_t, bbitand_i64, x & y)
+BINARY_OP(int8_t, bbitand_i8, x & y)
+BINARY_OP(int16_t, bbitand_i16, x & y)
+BINARY_OP(int32_t, bbitand_i32, x & y)
+BINARY_OP(uint8_t, bbitor_u8, x | y)
+BINARY_OP(uint32_t, bbitor_u32, x | y)
+BINARY_OP(int64_t, bbitor_i64, x | y)
+BINARY_OP(int8_t, bbitor_i8, x | y)
+BINARY_OP(int16_t, bbitor_i16, x | y)
+BINARY_OP(int32_t, bbitor_i32, x | y)
+BINARY_OP(uint8_t, bbitxor_u8, x ^ y)
+BINARY_OP(uint32_t, bbitxor_u32, x ^ y)
+BINARY_OP(int64_t, bbitxor_i64, x ^ y)
+BINARY_OP(int8_t, bbitxor_i8, x ^ y)
+BINARY_OP(int16_t, bbitxor_i16, x ^ y)
+BINARY_OP(int32_t, bbitxor_i32, x ^ y)
 
 BINARY_OP_OUT(float, uint8_t, eq_f32, x == y)
 BINARY_OP_OUT(double, uint8_t, eq_f64, x == y)
 BINARY_OP_OUT(uint8_t, uint8_t, eq_u8, x == y)
 BINARY_OP_OUT(uint32_t, uint8_t, eq_u32, x == y)
 BINARY_OP_OUT(int64_t, uint8_t, eq_i64, x == y)
+BINARY_OP_OUT(int8_t, uint8_t, eq_i8, x == y)
+BINARY_OP_OUT(int16_t, uint8_t, eq_i16, x == y)
+BINARY_OP_OUT(int32_t, uint8_t, eq_i32, x == y)
 
 BINARY_OP_OUT(float, uint8_t, ne_f32, x != y)
 BINARY_OP_OUT(double, uint8_t, ne_f64, x != y)
 BINARY_OP_OUT(uint8_t, uint8_t, ne_u8, x != y)
 BINARY_OP_OUT(uint32_t, uint8_t, ne_u32, x != y)
 BINARY_OP_OUT(int64_t, uint8_t, ne_i64, x != y)
+BINARY_OP_OUT(int8_t, uint8_t, ne_i8, x != y)
+BINARY_OP_OUT(int16_t, uint8_t, ne_i16, x != y)
+BINARY_OP_OUT(int32_t, uint8_t, ne_i32, x != y)
 
 BINARY_OP_OUT(float, uint8_t, lt_f32, x < y)
 BINARY_OP_OUT(double, uint8_t, lt_f64, x < y)
 BINARY_OP_OUT(uint8_t, uint8_t, lt_u8, x < y)
 BINARY_OP_OUT(uint32_t, uint8_t, lt_u32, x < y)
 BINARY_OP_OUT(int64_t, uint8_t, lt_i64, x < y)
+BINARY_OP_OUT(int8_t, uint8_t, lt_i8, x < y)
+BINARY_OP_OUT(int16_t, uint8_t, lt_i16, x < y)
+BINARY_OP_OUT(int32_t, uint8_t, lt_i32, x < y)
 
 BINARY_OP_OUT(float, uint8_t, le_f32, x <= y)
 BINARY_OP_OUT(double, uint8_t, le_f64, x <= y)
 BINARY_OP_OUT(uint8_t, uint8_t, le_u8, x <= y)
 BINARY_OP_OUT(uint32_t, uint8_t, le_u32, x <= y)
 BINARY_OP_OUT(int64_t, uint8_t, le_i64, x <= y)
+BINARY_OP_OUT(int8_t, uint8_t, le_i8, x <= y)
+BINARY_OP_OUT(int16_t, uint8_t, le_i16, x <= y)
+BINARY_OP_OUT(int32_t, uint8_t, le_i32, x <= y)
 
 BINARY_OP_OUT(float, uint8_t, gt_f32, x > y)
 BINARY_OP_OUT(double, uint8_t, gt_f64, x > y)
 BINARY_OP_OUT(uint8_t, uint8_t, gt_u8, x > y)
 BINARY_OP_OUT(uint32_t, uint8_t, gt_u32, x > y)
 BINARY_OP_OUT(int64_t, uint8_t, gt_i64, x > y)
+BINARY_OP_OUT(int8_t, uint8_t, gt_i8, x > y)
+BINARY_OP_OUT(int16_t, uint8_t, gt_i16, x > y)
+BINARY_OP_OUT(int32_t, uint8_t, gt_i32, x > y)
 
 BINARY_OP_OUT(float, uint8_t, ge_f32, x >= y)
 BINARY_OP_OUT(double, uint8_t, ge_f64, x >= y)
 BINARY_OP_OUT(uint8_t, uint8_t, ge_u8, x >= y)
 BINARY_OP_OUT(uint32_t, uint8_t, ge_u32, x >= y)
 BINARY_OP_OUT(int64_t, uint8_t, ge_i64, x >= y)
+BINARY_OP_OUT(int8_t, uint8_t, ge_i8, x >= y)
+BINARY_OP_OUT(int16_t, uint8_t, ge_i16, x >= y)
+BINARY_OP_OUT(int32_t, uint8_t, ge_i32, x >= y)
@@ -94,3 +94,43 @@ CAST_OP(double, uint8_t,  cast_f64_u8 )
 CAST_OP(double, uint32_t, cast_f64_u32)
 CAST_OP(double, float,    cast_f64_f32)
 CAST_OP(double, double,   cast_f64_f64)
+
+CAST_OP(int8_t, int8_t, cast_i8_i8)
+CAST_OP(int8_t, uint8_t, cast_i8_u8)
+CAST_OP(uint8_t, int8_t, cast_u8_i8)
+CAST_OP(int8_t, uint32_t, cast_i8_u32)
+CAST_OP(uint32_t, int8_t, cast_u32_i8)
+CAST_OP(int8_t, int64_t, cast_i8_i64)
+CAST_OP(int64_t, int8_t, cast_i64_i8)
+CAST_OP(int8_t, float, cast_i8_f32)
+CAST_OP(float, int8_t, cast_f32_i8)
+CAST_OP(int8_t, double, cast_i8_f64)
+CAST_OP(double, int8_t, cast_f64_i8)
+CAST_OP(int8_t, int16_t, cast_i8_i16)
+CAST_OP(int8_t, int32_t, cast_i8_i32)
+CAST_OP(int16_t, int16_t, cast_i16_i16)
+CAST_OP(int16_t, uint8_t, cast_i16_u8)
+CAST_OP(uint8_t, int16_t, cast_u8_i16)
+CAST_OP(int16_t, uint32_t, cast_i16_u32)
+CAST_OP(uint32_t, int16_t, cast_u32_i16)
+CAST_OP(int16_t, int64_t, cast_i16_i64)
+CAST_OP(int64_t, int16_t, cast_i64_i16)
+CAST_OP(int16_t, float, cast_i16_f32)
+CAST_OP(float, int16_t, cast_f32_i16)
+CAST_OP(int16_t, double, cast_i16_f64)
+CAST_OP(double, int16_t, cast_f64_i16)
+CAST_OP(int16_t, int8_t, cast_i16_i8)
+CAST_OP(int16_t, int32_t, cast_i16_i32)
+CAST_OP(int32_t, int32_t, cast_i32_i32)
+CAST_OP(int32_t, uint8_t, cast_i32_u8)
+CAST_OP(uint8_t, int32_t, cast_u8_i32)
+CAST_OP(int32_t, uint32_t, cast_i32_u32)
+CAST_OP(uint32_t, int32_t, cast_u32_i32)
+CAST_OP(int32_t, int64_t, cast_i32_i64)
+CAST_OP(int64_t, int32_t, cast_i64_i32)
+CAST_OP(int32_t, float, cast_i32_f32)
+CAST_OP(float, int32_t, cast_f32_i32)
+CAST_OP(int32_t, double, cast_i32_f64)
+CAST_OP(double, int32_t, cast_f64_i32)
+CAST_OP(int32_t, int8_t, cast_i32_i8)
+CAST_OP(int32_t, int16_t, cast_i32_i16)
@@ -61,3 +61,13 @@ WHERE_OP(double, uint8_t, where_u8_f64)
 WHERE_OP(uint8_t, uint8_t, where_u8_u8)
 WHERE_OP(uint32_t, uint8_t, where_u8_u32)
 WHERE_OP(int64_t, uint8_t, where_u8_i64)
+WHERE_OP(int32_t, uint8_t, where_u8_i32)
+WHERE_OP(int32_t, uint32_t, where_u32_i32)
+WHERE_OP(int32_t, int64_t, where_i64_i32)
+
+WHERE_OP(float, int32_t, where_i32_f32)
+WHERE_OP(double, int32_t, where_i32_f64)
+WHERE_OP(uint8_t, int32_t, where_i32_u8)
+WHERE_OP(uint32_t, int32_t, where_i32_u32)
+WHERE_OP(int32_t, int32_t, where_i32_i32)
+WHERE_OP(int64_t, int32_t, where_i32_i64)
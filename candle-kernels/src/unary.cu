@@ -50,6 +50,19 @@ __device__ __forceinline__ T relu_fwd(T x) {
     return maxg(x, zero);
 }
 
+template<typename T>
+__device__ __forceinline__ T sign_fwd(T x) {
+    T zero = 0.;
+    if (x > zero) {
+        return static_cast<T>(1);
+    }
+    if (x < zero) {
+        return static_cast<T>(-1);
+    }
+    // Zeros and NaNs are returned unchanged.
+    return x;
+}
+
 #define UNARY_OP1(TYPENAME, FN_NAME, FUNC) \
 extern "C" __global__ void FN_NAME( \
     const size_t numel, \
@@ -93,6 +106,11 @@ UNARY_OP(__nv_bfloat16, ugelu_bf16, gelu_fwd(x))
 UNARY_OP(__nv_bfloat16, urelu_bf16, relu_fwd(x))
 UNARY_OP1(__nv_bfloat16, uelu_bf16, elu_fwd(x, param))
 UNARY_OP1(__nv_bfloat16, upowf_bf16, powg(x, param))
+UNARY_OP(__nv_bfloat16, uerf_bf16, erfg(x))
+UNARY_OP(__nv_bfloat16, ufloor_bf16, floorg(x))
+UNARY_OP(__nv_bfloat16, uceil_bf16, ceilg(x))
+UNARY_OP(__nv_bfloat16, uround_bf16, roundg(x))
+UNARY_OP(__nv_bfloat16, usign_bf16, sign_fwd(x))
 #endif
 
 #if __CUDA_ARCH__ >= 530
@@ -111,10 +129,19 @@ UNARY_OP(__half, ugelu_f16, gelu_fwd(x))
 UNARY_OP(__half, urelu_f16, relu_fwd(x))
 UNARY_OP1(__half, uelu_f16, elu_fwd(x, param))
 UNARY_OP1(__half, upowf_f16, powg(x, param))
+UNARY_OP(__half, uerf_f16, erfg(x))
+UNARY_OP(__half, ufloor_f16, floorg(x))
+UNARY_OP(__half, uceil_f16, ceilg(x))
+UNARY_OP(__half, uround_f16, roundg(x))
+UNARY_OP(__half, usign_f16, sign_fwd(x))
 #endif
 
 UNARY_OP(uint8_t, ucopy_u8, x)
 UNARY_OP(uint32_t, ucopy_u32, x)
+UNARY_OP(int8_t, ucopy_i8, x)
+UNARY_OP(int16_t, ucopy_i16, x)
+UNARY_OP(int32_t, ucopy_i32, x)
+UNARY_OP(int64_t, ucopy_i64, x)
 UNARY_OP(float, ucopy_f32, x)
 UNARY_OP(double, ucopy_f64, x)
 UNARY_OP(float, uneg_f32, -x)
@@ -145,3 +172,51 @@ UNARY_OP1(float, uelu_f32, elu_fwd(x, param))
 UNARY_OP1(double, uelu_f64, elu_fwd(x, param))
 UNARY_OP1(float, upowf_f32, powg(x, param))
 UNARY_OP1(double, upowf_f64, powg(x, param))
+UNARY_OP(float, uerf_f32, erfg(x))
+UNARY_OP(double, uerf_f64, erfg(x))
+UNARY_OP(float, ufloor_f32, floorg(x))
+UNARY_OP(double, ufloor_f64, floorg(x))
+UNARY_OP(float, uceil_f32, ceilg(x))
+UNARY_OP(double, uceil_f64, ceilg(x))
+UNARY_OP(float, uround_f32, roundg(x))
+UNARY_OP(double, uround_f64, roundg(x))
+UNARY_OP(float, usign_f32, sign_fwd(x))
+UNARY_OP(double, usign_f64, sign_fwd(x))
+
+// Integers are already rounded.
+UNARY_OP(uint8_t, ufloor_u8, x)
+UNARY_OP(uint32_t, ufloor_u32, x)
+UNARY_OP(int8_t, ufloor_i8, x)
+UNARY_OP(int16_t, ufloor_i16, x)
+UNARY_OP(int32_t, ufloor_i32, x)
+UNARY_OP(int64_t, ufloor_i64, x)
+UNARY_OP(uint8_t, uceil_u8, x)
+UNARY_OP(uint32_t, uceil_u32, x)
+UNARY_OP(int8_t, uceil_i8, x)
+UNARY_OP(int16_t, uceil_i16, x)
+UNARY_OP(int32_t, uceil_i32, x)
+UNARY_OP(int64_t, uceil_i64, x)
+UNARY_OP(uint8_t, uround_u8, x)
+UNARY_OP(uint32_t, uround_u32, x)
+UNARY_OP(int8_t, uround_i8, x)
+UNARY_OP(int16_t, uround_i16, x)
+UNARY_OP(int32_t, uround_i32, x)
+UNARY_OP(int64_t, uround_i64, x)
+
+UNARY_OP(uint8_t, usign_u8, sign_fwd(x))
+UNARY_OP(uint32_t, usign_u32, sign_fwd(x))
+UNARY_OP(int8_t, usign_i8, sign_fwd(x))
+UNARY_OP(int16_t, usign_i16, sign_fwd(x))
+UNARY_OP(int32_t, usign_i32, sign_fwd(x))
+UNARY_OP(int64_t, usign_i64, sign_fwd(x))
+
+#define SIGNED_INT_OPS(TYPENAME, RUST_NAME) \
+UNARY_OP(TYPENAME, uneg_##RUST_NAME, -x) \
+UNARY_OP(TYPENAME, uabs_##RUST_NAME, x < 0 ? -x : x) \
+UNARY_OP(TYPENAME, usqr_##RUST_NAME, x*x) \
+UNARY_OP(TYPENAME, urelu_##RUST_NAME, x < 0 ? 0 : x)
+
+SIGNED_INT_OPS(int8_t, i8)
+SIGNED_INT_OPS(int16_t, i16)
+SIGNED_INT_OPS(int32_t, i32)
+SIGNED_INT_OPS(int64_t, i64)
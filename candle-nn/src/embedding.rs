@@ -5,6 +5,7 @@ use candle::{Result, Tensor};
 pub struct Embedding {
     embeddings: Tensor,
     hidden_size: usize,
+    sparse_grad: bool,
 }
 
 impl Embedding {
@@ -12,9 +13,18 @@ impl Embedding {
         Self {
             embeddings,
             hidden_size,
+            sparse_grad: false,
         }
     }
 
+    /// When set, the gradient of the embedding table only contains the rows that have been
+    /// looked up, see [`Tensor::index_select_sparse_grad`]. The optimizers from
+    /// [`crate::optim`] then only update these rows.
+    pub fn with_sparse_grad(mut self, sparse_grad: bool) -> Self {
+        self.sparse_grad = sparse_grad;
+        self
+    }
+
     pub fn embeddings(&self) -> &Tensor {
         &self.embeddings
     }
@@ -30,7 +40,11 @@ impl crate::Module for Embedding {
         let mut final_dims = indexes.dims().to_vec();
         final_dims.push(self.hidden_size);
         let indexes = indexes.flatten_all()?;
-        let values = self.embeddings.index_select(&indexes, 0)?;
+        let values = if self.sparse_grad {
+            self.embeddings.index_select_sparse_grad(&indexes)?
+        } else {
+            self.embeddings.index_select(&indexes, 0)?
+        };
         let values = values.reshape(final_dims)?;
         Ok(values)
     }
@@ -1,4 +1,5 @@
 //! Various optimization algorithms.
+use candle::backprop::GradStore;
 use candle::{Result, Tensor, Var};
 
 /// The interface optimizers should implement.
@@ -28,6 +29,43 @@ pub trait Optimizer: Sized {
     }
 }
 
+// The gradient of a variable as seen by the optimizers. Sparse gradients over the first
+// dimension, e.g. for embedding tables, are kept as rows so that only these rows get updated.
+enum VarGrad {
+    Dense(Tensor),
+    Rows { rows: Tensor, values: Tensor },
+}
+
+impl VarGrad {
+    // With `coalesce` set, the rows are unique which is required by the non-linear updates.
+    fn get(grads: &GradStore, var: &Var, coalesce: bool) -> Result<Option<Self>> {
+        let grad = match (grads.get(var), grads.get_sparse(var)) {
+            (None, None) => None,
+            (Some(grad), None) => Some(Self::Dense(grad.clone())),
+            (None, Some(grad)) if grad.sparse_dims() == 1 => {
+                let grad = if coalesce {
+                    grad.coalesce()?
+                } else {
+                    grad.clone()
+                };
+                let rows = grad.indices().flatten_all()?;
+                Some(Self::Rows {
+                    rows,
+                    values: grad.values().clone(),
+                })
+            }
+            (dense, Some(sparse)) => {
+                let sparse = sparse.to_dense()?;
+                match dense {
+                    None => Some(Self::Dense(sparse)),
+                    Some(dense) => Some(Self::Dense((dense + sparse)?)),
+                }
+            }
+        };
+        Ok(grad)
+    }
+}
+
 /// Optimizer for Stochastic Gradient Descent.
 ///
 /// Contrary to the PyTorch implementation of SGD, this version does not support momentum.
@@ -53,8 +91,14 @@ impl Optimizer for SGD {
 
     fn step(&mut self, grads: &candle::backprop::GradStore) -> Result<()> {
         for var in self.vars.iter() {
-            if let Some(grad) = grads.get(var) {
-                var.set(&var.sub(&(grad * self.learning_rate)?)?)?;
+            match VarGrad::get(grads, var, false)? {
+                None => {}
+                Some(VarGrad::Dense(grad)) => {
+                    var.set(&var.sub(&(grad * self.learning_rate)?)?)?;
+                }
+                Some(VarGrad::Rows { rows, values }) => {
+                    var.index_add_(&rows, &(values * -self.learning_rate)?, 0)?;
+                }
             }
         }
         Ok(())
@@ -157,21 +201,40 @@ impl Optimizer for AdamW {
             let theta = &var.var;
             let m = &var.first_moment;
             let v = &var.second_moment;
-            if let Some(g) = grads.get(theta) {
-                // This involves locking 3 RWLocks per params, if the parameters are large this
-                // should not be an issue but this may be problematic with models with lots of
-                // small parameters.
-                let next_m = ((m.as_tensor() * beta1)? + (g * (1.0 - beta1))?)?;
-                let next_v = ((v.as_tensor() * beta2)? + (g.sqr()? * (1.0 - beta2))?)?;
-                let m_hat = (&next_m * scale_m)?;
-                let v_hat = (&next_v * scale_v)?;
-                let next_theta = (theta.as_tensor() * (1f64 - lr_lambda))?;
-                let adjusted_grad = (m_hat / (v_hat.sqrt()? + self.params.eps)?)?;
-                let next_theta = (next_theta - (adjusted_grad * lr)?)?;
-                m.set(&next_m)?;
-                v.set(&next_v)?;
-                theta.set(&next_theta)?;
-            }
+            let g = match VarGrad::get(grads, theta, true)? {
+                None => continue,
+                Some(VarGrad::Dense(g)) => g,
+                Some(VarGrad::Rows { rows, values: g }) => {
+                    // Lazy update of the rows that have a gradient, the moments of the other
+                    // rows are left untouched.
+                    let m_rows = m.index_select(&rows, 0)?;
+                    let v_rows = v.index_select(&rows, 0)?;
+                    let theta_rows = theta.index_select(&rows, 0)?;
+                    let next_m = ((&m_rows * beta1)? + (&g * (1.0 - beta1))?)?;
+                    let next_v = ((&v_rows * beta2)? + (g.sqr()? * (1.0 - beta2))?)?;
+                    let m_hat = (&next_m * scale_m)?;
+                    let v_hat = (&next_v * scale_v)?;
+                    let adjusted_grad = (m_hat / (v_hat.sqrt()? + self.params.eps)?)?;
+                    let next_theta = ((&theta_rows * (1f64 - lr_lambda))? - (adjusted_grad * lr)?)?;
+                    m.index_add_(&rows, &(next_m - m_rows)?, 0)?;
+                    v.index_add_(&rows, &(next_v - v_rows)?, 0)?;
+                    theta.index_add_(&rows, &(next_theta - theta_rows)?, 0)?;
+                    continue;
+                }
+            };
+            // This involves locking 3 RWLocks per params, if the parameters are large this
+            // should not be an issue but this may be problematic with models with lots of
+            // small parameters.
+            let next_m = ((m.as_tensor() * beta1)? + (&g * (1.0 - beta1))?)?;
+            let next_v = ((v.as_tensor() * beta2)? + (g.sqr()? * (1.0 - beta2))?)?;
+            let m_hat = (&next_m * scale_m)?;
+            let v_hat = (&next_v * scale_v)?;
+            let next_theta = (theta.as_tensor() * (1f64 - lr_lambda))?;
+            let adjusted_grad = (m_hat / (v_hat.sqrt()? + self.params.eps)?)?;
+            let next_theta = (next_theta - (adjusted_grad * lr)?)?;
+            m.set(&next_m)?;
+            v.set(&next_v)?;
+            theta.set(&next_theta)?;
         }
         Ok(())
     }
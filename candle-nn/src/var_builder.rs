@@ -2,6 +2,7 @@
 //! from a pre-trained checkpoint, e.g. using `VarBuilder::from_safetensors`, or initialized for
 //! training, e.g. using `VarBuilder::from_varmap`.
 use crate::VarMap;
+use candle::quantized::gptq::{PackedWeight, QuantMethod};
 use candle::{safetensors::Load, DType, Device, Error, Result, Shape, Tensor};
 use safetensors::{slice::IndexOp, tensor::SafeTensors};
 use std::collections::HashMap;
@@ -292,6 +293,79 @@ impl<'a> SimpleBackend for SafeTensorWithRouting<'a> {
     }
 }
 
+impl<'a> SafeTensorWithRouting<'a> {
+    // Loads a tensor without any dtype conversion.
+    fn load_raw(&self, path: &str, dev: &Device) -> Result<Option<Tensor>> {
+        match self.routing.get(path) {
+            None => Ok(None),
+            Some(index) => Ok(Some(self.safetensors[*index].tensor(path)?.load(dev)?)),
+        }
+    }
+}
+
+// The GPTQ and AWQ checkpoints store the weight of the quantized linear layers as packed tensors,
+// these get dequantized when `prefix.weight` is requested.
+struct QuantizedSafeTensors<'a> {
+    inner: SafeTensorWithRouting<'a>,
+    method: QuantMethod,
+}
+
+impl<'a> QuantizedSafeTensors<'a> {
+    fn packed_prefix<'b>(&self, path: &'b str) -> Option<&'b str> {
+        let prefix = path.strip_suffix(".weight")?;
+        let is_packed = !self.inner.contains_tensor(path)
+            && self.inner.contains_tensor(&format!("{prefix}.qweight"));
+        is_packed.then_some(prefix)
+    }
+}
+
+impl<'a> SimpleBackend for QuantizedSafeTensors<'a> {
+    fn get(
+        &self,
+        s: Shape,
+        path: &str,
+        h: crate::Init,
+        dtype: DType,
+        dev: &Device,
+    ) -> Result<Tensor> {
+        let prefix = match self.packed_prefix(path) {
+            None => return self.inner.get(s, path, h, dtype, dev),
+            Some(prefix) => prefix,
+        };
+        let get = |name: &str| {
+            let path = format!("{prefix}.{name}");
+            self.inner.load_raw(&path, &Device::Cpu)?.ok_or_else(|| {
+                Error::CannotFindTensor {
+                    path: path.to_string(),
+                }
+                .bt()
+            })
+        };
+        let packed = PackedWeight {
+            qweight: get("qweight")?,
+            qzeros: get("qzeros")?,
+            scales: get("scales")?,
+            g_idx: self
+                .inner
+                .load_raw(&format!("{prefix}.g_idx"), &Device::Cpu)?,
+        };
+        let dims = packed.dims(self.method)?;
+        if dims != s.dims2()? {
+            Err(candle::Error::UnexpectedShape {
+                msg: format!("shape mismatch for {path}"),
+                expected: s,
+                got: dims.into(),
+            }
+            .bt())?
+        }
+        packed.dequantize(self.method, dev)?.to_dtype(dtype)
+    }
+
+    fn contains_tensor(&self, name: &str) -> bool {
+        self.inner.contains_tensor(name) || self.packed_prefix(name).is_some()
+    }
+}
+
 impl SimpleBackend for candle::npy::NpzTensors {
     fn get(
         &self,
@@ -376,6 +450,35 @@ impl<'a> VarBuilder<'a> {
         Self::new(Box::new(tensors), dtype, dev.clone())
     }
 
+    /// Initializes a `VarBuilder` that retrieves tensors stored in a collection of GPTQ or AWQ
+    /// safetensors files. The weights of the quantized linear layers are dequantized when loaded
+    /// so that models can use these checkpoints without any change, the other tensors are
+    /// retrieved as with `from_safetensors`.
+    ///
+    /// This is a fallback for the models that do not have a quantized version as the dequantized
+    /// weights use eight times more memory than the packed ones. Quantized models should load
+    /// these checkpoints with `candle_transformers::quantized_var_builder::VarBuilder` instead,
+    /// which keeps the weights as `QTensor`.
+    pub fn from_quantized_safetensors(
+        safetensors: Vec<SafeTensors<'a>>,
+        method: QuantMethod,
+        dtype: DType,
+        dev: &Device,
+    ) -> Self {
+        let mut routing = HashMap::new();
+        for (index, sf) in safetensors.iter().enumerate() {
+            for k in sf.names() {
+                routing.insert(k.to_string(), index);
+            }
+        }
+        let inner = SafeTensorWithRouting {
+            routing,
+            safetensors,
+        };
+        let tensors = QuantizedSafeTensors { inner, method };
+        Self::new(Box::new(tensors), dtype, dev.clone())
+    }
+
     /// Initializes a `VarBuilder` that retrieves tensors stored in a numpy npz file.
     pub fn from_npz<P: AsRef<std::path::Path>>(p: P, dtype: DType, dev: &Device) -> Result<Self> {
         let npz = candle::npy::NpzTensors::new(p)?;
@@ -0,0 +1,49 @@
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+use anyhow::Result;
+use candle::{Device, PaddingMode, Tensor};
+use candle_nn::{Conv2d, Conv2dConfig, Module};
+
+#[test]
+fn conv2d_padding() -> Result<()> {
+    let device = &Device::Cpu;
+    let xs = Tensor::arange(0f32, 2. * 5. * 6., device)?.reshape((1, 2, 5, 6))?;
+    let w = Tensor::arange(0f32, 3. * 2. * 3. * 2., device)?.reshape((3, 2, 3, 2))?;
+    let b = Tensor::new(&[1f32, -1., 0.5], device)?;
+    let cfg = Conv2dConfig {
+        padding: 1,
+        stride: 2,
+        ..Default::default()
+    };
+
+    // The scalar options apply to both axes.
+    let conv = Conv2d::new(w.clone(), Some(b.clone()), cfg);
+    let expected = xs
+        .conv2d(&w, 1, 2, 1, 1)?
+        .broadcast_add(&b.reshape((1, 3, 1, 1))?)?;
+    let ys = conv.forward(&xs)?;
+    assert_eq!(ys.dims(), [1, 3, 3, 4]);
+    assert_eq!(
+        ys.flatten_all()?.to_vec1::<f32>()?,
+        expected.flatten_all()?.to_vec1::<f32>()?
+    );
+
+    let conv = Conv2d::new(w.clone(), None, cfg)
+        .with_padding_per_side((2, 1, 0, 1))
+        .with_padding_mode(PaddingMode::Reflect)
+        .with_stride_per_axis((1, 2))
+        .with_dilation_per_axis((2, 1));
+    let expected =
+        xs.conv2d_with_padding(&w, (2, 1, 0, 1), PaddingMode::Reflect, (1, 2), (2, 1), 1)?;
+    let ys = conv.forward(&xs)?;
+    assert_eq!(ys.dims(), [1, 3, 4, 3]);
+    assert_eq!(
+        ys.flatten_all()?.to_vec1::<f32>()?,
+        expected.flatten_all()?.to_vec1::<f32>()?
+    );
+    Ok(())
+}
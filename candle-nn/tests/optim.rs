@@ -8,7 +8,7 @@ use candle::test_utils::{to_vec0_round, to_vec2_round};
 
 use anyhow::Result;
 use candle::{Device, Tensor, Var};
-use candle_nn::{AdamW, Linear, Module, Optimizer, ParamsAdamW, SGD};
+use candle_nn::{AdamW, Embedding, Linear, Module, Optimizer, ParamsAdamW, SGD};
 
 #[test]
 fn sgd_optim() -> Result<()> {
@@ -121,3 +121,40 @@ fn adamw_linear_regression() -> Result<()> {
     assert_eq!(to_vec0_round(b.as_tensor(), 4)?, 0.7873);
     Ok(())
 }
+
+// Runs a few optimizer steps on an embedding table, only looking up some of the rows.
+fn embedding_steps<O: Optimizer>(sparse_grad: bool, params: O::Config) -> Result<Vec<Vec<f32>>> {
+    let init = Tensor::arange(0f32, 12., &Device::Cpu)?.reshape((4, 3))?;
+    let table = Var::from_tensor(&init)?;
+    let mut opt = O::new(vec![table.clone()], params)?;
+    let emb = Embedding::new(table.as_tensor().clone(), 3).with_sparse_grad(sparse_grad);
+    let ids = Tensor::new(&[2u32, 0, 2], &Device::Cpu)?;
+    for _step in 0..3 {
+        let loss = emb.forward(&ids)?.sqr()?.sum_all()?;
+        opt.backward_step(&loss)?;
+    }
+    Ok(to_vec2_round(table.as_tensor(), 4)?)
+}
+
+#[test]
+fn sparse_embedding_optim() -> Result<()> {
+    let sgd = embedding_steps::<SGD>(true, 0.01)?;
+    assert_eq!(sgd, embedding_steps::<SGD>(false, 0.01)?);
+    assert_eq!(sgd[1], [3., 4., 5.]);
+    assert_eq!(sgd[3], [9., 10., 11.]);
+
+    // The sparse AdamW update is lazy: the rows that are not looked up are left untouched rather
+    // than being decayed, the other rows match the dense update.
+    let params = ParamsAdamW {
+        lr: 0.1,
+        weight_decay: 0.,
+        ..Default::default()
+    };
+    let sparse = embedding_steps::<AdamW>(true, params.clone())?;
+    let dense = embedding_steps::<AdamW>(false, params)?;
+    assert_eq!(sparse[0], dense[0]);
+    assert_eq!(sparse[2], dense[2]);
+    assert_eq!(sparse[1], [3., 4., 5.]);
+    assert_eq!(sparse[3], [9., 10., 11.]);
+    Ok(())
+}
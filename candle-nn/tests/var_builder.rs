@@ -0,0 +1,60 @@
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+use anyhow::Result;
+use candle::quantized::gptq::QuantMethod;
+use candle::{DType, Device, Module, Tensor};
+use candle_nn::VarBuilder;
+use std::collections::HashMap;
+
+#[test]
+fn quantized_safetensors() -> Result<()> {
+    let cpu = &Device::Cpu;
+    let (out_dim, in_dim) = (8, 64);
+    // A GPTQ layer with a single group, w = 0.5 * (q - 8) with the zero point stored as 7.
+    let q = |r: usize, c: usize| ((r + 3 * c) % 16) as u32;
+    let qweight = (0..in_dim / 8)
+        .map(|i| {
+            (0..out_dim)
+                .map(|r| (0..8).fold(0u32, |a, j| a | (q(r, i * 8 + j) << (4 * j))) as i32)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let qzeros = vec![vec![0x77777777i32]];
+    let w = (0..out_dim)
+        .flat_map(|r| (0..in_dim).map(move |c| 0.5 * (q(r, c) as f32 - 8.)))
+        .collect::<Vec<_>>();
+    let w = Tensor::from_vec(w, (out_dim, in_dim), cpu)?;
+    let bias = Tensor::arange(0f32, out_dim as f32, cpu)?;
+
+    let tensors = HashMap::from([
+        ("proj.qweight".to_string(), Tensor::new(qweight, cpu)?),
+        ("proj.qzeros".to_string(), Tensor::new(qzeros, cpu)?),
+        (
+            "proj.scales".to_string(),
+            (Tensor::ones((1, out_dim), DType::F16, cpu)? * 0.5)?,
+        ),
+        ("proj.bias".to_string(), bias.clone()),
+    ]);
+    let path = std::env::temp_dir().join(format!("candle-gptq-{}.st", std::process::id()));
+    candle::safetensors::save(&tensors, &path)?;
+    let data = std::fs::read(&path)?;
+    std::fs::remove_file(&path)?;
+    let st = safetensors::SafeTensors::deserialize(&data)?;
+    let vb = VarBuilder::from_quantized_safetensors(vec![st], QuantMethod::Gptq, DType::F32, cpu);
+
+    assert!(vb.contains_tensor("proj.weight"));
+    assert!(vb.contains_tensor("proj.bias"));
+    assert!(!vb.contains_tensor("other.weight"));
+    // The layer can be loaded as a regular linear layer.
+    let linear = candle_nn::linear(in_dim, out_dim, vb.pp("proj"))?;
+    let xs = Tensor::randn(0f32, 1., (2, in_dim), cpu)?;
+    let expected = xs.matmul(&w.t()?)?.broadcast_add(&bias)?;
+    let diff = (linear.forward(&xs)? - expected)?.abs()?.sum_all()?;
+    assert!(diff.to_scalar::<f32>()? < 1e-4);
+    assert!(candle_nn::linear(in_dim, 2 * out_dim, vb.pp("proj")).is_err());
+    Ok(())
+}
@@ -80,7 +80,7 @@ enum PyDevice {
 impl PyDevice {
     fn from_device(device: &Device) -> Self {
         match device {
-            Device::Cpu => Self::Cpu,
+            Device::Cpu | Device::CpuPool(_) => Self::Cpu,
             Device::Cuda(_) => Self::Cuda,
         }
     }
@@ -138,6 +138,9 @@ macro_rules! pydtype {
 }
 pydtype!(u8, |v| v);
 pydtype!(u32, |v| v);
+pydtype!(i8, |v| v);
+pydtype!(i16, |v| v);
+pydtype!(i32, |v| v);
 pydtype!(i64, |v| v);
 pydtype!(f16, f32::from);
 pydtype!(bf16, f32::from);
@@ -183,13 +186,21 @@ trait MapDType {
 
     fn map(&self, t: &Tensor) -> PyResult<Self::Output> {
         match t.dtype() {
+            // Booleans are exposed as u8 values.
+            DType::Bool => self.f::<u8>(&t.to_dtype(DType::U8).map_err(wrap_err)?),
             DType::U8 => self.f::<u8>(t),
             DType::U32 => self.f::<u32>(t),
+            DType::I8 => self.f::<i8>(t),
+            DType::I16 => self.f::<i16>(t),
+            DType::I32 => self.f::<i32>(t),
             DType::I64 => self.f::<i64>(t),
             DType::BF16 => self.f::<bf16>(t),
             DType::F16 => self.f::<f16>(t),
             DType::F32 => self.f::<f32>(t),
             DType::F64 => self.f::<f64>(t),
+            // Complex values are exposed as pairs of floats holding the real and imaginary parts.
+            DType::C64 => self.f::<f32>(&t.view_as_real().map_err(wrap_err)?),
+            DType::C128 => self.f::<f64>(&t.view_as_real().map_err(wrap_err)?),
         }
     }
 }
@@ -825,9 +836,15 @@ fn get_num_threads() -> usize {
     ::candle::utils::get_num_threads()
 }
 
+#[pyfunction]
+fn set_num_threads(num_threads: usize) -> PyResult<()> {
+    ::candle::set_num_threads(num_threads).map_err(wrap_err)
+}
+
 fn candle_utils(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(cuda_is_available, m)?)?;
     m.add_function(wrap_pyfunction!(get_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
     m.add_function(wrap_pyfunction!(has_accelerate, m)?)?;
     m.add_function(wrap_pyfunction!(has_mkl, m)?)?;
     Ok(())
@@ -863,13 +880,19 @@ fn candle(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyTensor>()?;
     m.add_class::<PyQTensor>()?;
     m.add_class::<PyDType>()?;
+    m.add("bool", PyDType(DType::Bool))?;
     m.add("u8", PyDType(DType::U8))?;
     m.add("u32", PyDType(DType::U32))?;
-    m.add("i16", PyDType(DType::I64))?;
+    m.add("i8", PyDType(DType::I8))?;
+    m.add("i16", PyDType(DType::I16))?;
+    m.add("i32", PyDType(DType::I32))?;
+    m.add("i64", PyDType(DType::I64))?;
     m.add("bf16", PyDType(DType::BF16))?;
     m.add("f16", PyDType(DType::F16))?;
     m.add("f32", PyDType(DType::F32))?;
     m.add("f64", PyDType(DType::F64))?;
+    m.add("c64", PyDType(DType::C64))?;
+    m.add("c128", PyDType(DType::C128))?;
     m.add_function(wrap_pyfunction!(cat, m)?)?;
     m.add_function(wrap_pyfunction!(load_ggml, m)?)?;
     m.add_function(wrap_pyfunction!(load_gguf, m)?)?;
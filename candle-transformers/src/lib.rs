@@ -2,4 +2,5 @@ pub mod generation;
 pub mod models;
 pub mod object_detection;
 pub mod pipelines;
+pub mod quantized_var_builder;
 pub mod utils;
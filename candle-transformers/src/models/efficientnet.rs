@@ -122,14 +122,19 @@ impl Module for Conv2DSame {
         let (_, _, ih, iw) = xs.dims4()?;
         let oh = (ih + s - 1) / s;
         let ow = (iw + s - 1) / s;
-        let pad_h = usize::max((oh - 1) * s + k - ih, 0);
-        let pad_w = usize::max((ow - 1) * s + k - iw, 0);
-        if pad_h > 0 || pad_w > 0 {
-            let xs = xs.pad_with_zeros(2, pad_h / 2, pad_h - pad_h / 2)?;
-            let xs = xs.pad_with_zeros(3, pad_w / 2, pad_w - pad_w / 2)?;
-            self.conv2d.forward(&xs)
-        } else {
-            self.conv2d.forward(xs)
+        let pad_h = ((oh - 1) * s + k).saturating_sub(ih);
+        let pad_w = ((ow - 1) * s + k).saturating_sub(iw);
+        let xs = xs.conv2d_with_padding(
+            self.conv2d.weight(),
+            (pad_h / 2, pad_h - pad_h / 2, pad_w / 2, pad_w - pad_w / 2),
+            nn::conv::PaddingMode::Zeros,
+            (s, s),
+            (1, 1),
+            self.conv2d.config().groups,
+        )?;
+        match self.conv2d.bias() {
+            None => Ok(xs),
+            Some(bias) => xs.broadcast_add(&bias.reshape((1, (), 1, 1))?),
         }
     }
 }
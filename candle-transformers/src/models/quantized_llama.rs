@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 
-use candle::quantized::QTensor;
+use std::sync::Arc;
+
 use candle::quantized::{ggml_file, gguf_file};
+use candle::quantized::{imatrix::Imatrix, QTensor};
 use candle::{DType, Device, IndexOp, Result, Tensor, D};
 use candle_nn::{Embedding, Module};
 
@@ -26,17 +28,39 @@ impl RmsNorm {
     }
 }
 
-// QMatMul wrapper adding some tracing.
+// QMatMul wrapper adding some tracing, the name of the weight is kept so that an importance
+// matrix can be recorded.
 struct QMatMul {
     inner: candle::quantized::QMatMul,
+    name: String,
     span: tracing::Span,
 }
 
 impl QMatMul {
-    fn from_qtensor(qtensor: QTensor) -> Self {
+    fn from_qtensor(qtensor: QTensor, name: &str) -> Self {
         let inner = candle::quantized::QMatMul::from_qtensor(qtensor);
         let span = tracing::span!(tracing::Level::TRACE, "qmatmul");
-        Self { inner, span }
+        Self {
+            inner,
+            name: name.to_string(),
+            span,
+        }
+    }
+
+    fn from_ggml(ct: &mut ggml_file::Content, name: &str) -> Result<Self> {
+        Ok(Self::from_qtensor(ct.remove(name)?, name))
+    }
+
+    fn from_gguf<R: std::io::Seek + std::io::Read>(
+        ct: &gguf_file::Content,
+        reader: &mut R,
+        name: &str,
+    ) -> Result<Self> {
+        Ok(Self::from_qtensor(ct.tensor(reader, name)?, name))
+    }
+
+    fn record_imatrix(&mut self, imatrix: &Arc<Imatrix>) {
+        self.inner = self.inner.clone().with_imatrix(&self.name, imatrix.clone())
     }
 
     fn forward(&self, xs: &Tensor) -> Result<Tensor> {
@@ -200,31 +224,38 @@ impl ModelWeights {
         let tok_embeddings = ct.remove("tok_embeddings.weight")?;
         let tok_embeddings = tok_embeddings.dequantize(cpu)?;
         let norm = RmsNorm::new(ct.remove("norm.weight")?, 1e-5)?;
-        let output = ct.remove("output.weight")?;
+        let output = QMatMul::from_ggml(&mut ct, "output.weight")?;
         let mut layers = Vec::with_capacity(ct.hparams.n_layer as usize);
         for layer_idx in 0..ct.hparams.n_layer {
             let prefix = format!("layers.{layer_idx}");
-            let attention_wq = ct.remove(&format!("{prefix}.attention.wq.weight"))?;
-            let attention_wk = ct.remove(&format!("{prefix}.attention.wk.weight"))?;
-            let attention_wv = ct.remove(&format!("{prefix}.attention.wv.weight"))?;
-            let attention_wo = ct.remove(&format!("{prefix}.attention.wo.weight"))?;
-            let feed_forward_w1 = ct.remove(&format!("{prefix}.feed_forward.w1.weight"))?;
-            let feed_forward_w2 = ct.remove(&format!("{prefix}.feed_forward.w2.weight"))?;
-            let feed_forward_w3 = ct.remove(&format!("{prefix}.feed_forward.w3.weight"))?;
+            let attention_wq =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.attention.wq.weight"))?;
+            let attention_wk =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.attention.wk.weight"))?;
+            let attention_wv =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.attention.wv.weight"))?;
+            let attention_wo =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.attention.wo.weight"))?;
+            let feed_forward_w1 =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.feed_forward.w1.weight"))?;
+            let feed_forward_w2 =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.feed_forward.w2.weight"))?;
+            let feed_forward_w3 =
+                QMatMul::from_ggml(&mut ct, &format!("{prefix}.feed_forward.w3.weight"))?;
             let attention_norm = ct.remove(&format!("{prefix}.attention_norm.weight"))?;
             let ffn_norm = ct.remove(&format!("{prefix}.ffn_norm.weight"))?;
             let span_attn = tracing::span!(tracing::Level::TRACE, "attn");
             let span_rot = tracing::span!(tracing::Level::TRACE, "attn-rot");
             let span_mlp = tracing::span!(tracing::Level::TRACE, "attn-mlp");
             layers.push(LayerWeights {
-                attention_wq: QMatMul::from_qtensor(attention_wq),
-                attention_wk: QMatMul::from_qtensor(attention_wk),
-                attention_wv: QMatMul::from_qtensor(attention_wv),
-                attention_wo: QMatMul::from_qtensor(attention_wo),
+                attention_wq,
+                attention_wk,
+                attention_wv,
+                attention_wo,
                 attention_norm: RmsNorm::new(attention_norm, 1e-5)?,
-                feed_forward_w1: QMatMul::from_qtensor(feed_forward_w1),
-                feed_forward_w2: QMatMul::from_qtensor(feed_forward_w2),
-                feed_forward_w3: QMatMul::from_qtensor(feed_forward_w3),
+                feed_forward_w1,
+                feed_forward_w2,
+                feed_forward_w3,
                 ffn_norm: RmsNorm::new(ffn_norm, 1e-5)?,
                 n_head: ct.hparams.n_head as usize,
                 n_kv_head: ct.hparams.n_head as usize / gqa,
@@ -243,7 +274,7 @@ impl ModelWeights {
             tok_embeddings: Embedding::new(tok_embeddings, ct.hparams.n_embd as usize),
             layers,
             norm,
-            output: QMatMul::from_qtensor(output),
+            output,
             masks: HashMap::new(),
             span,
             span_output,
@@ -277,31 +308,35 @@ impl ModelWeights {
         let tok_embeddings = ct.tensor(reader, "token_embd.weight")?;
         let tok_embeddings = tok_embeddings.dequantize(cpu)?;
         let norm = RmsNorm::new(ct.tensor(reader, "output_norm.weight")?, rms_norm_eps)?;
-        let output = ct.tensor(reader, "output.weight")?;
+        let output = QMatMul::from_gguf(&ct, reader, "output.weight")?;
         let mut layers = Vec::with_capacity(block_count);
         for layer_idx in 0..block_count {
             let prefix = format!("blk.{layer_idx}");
-            let attention_wq = ct.tensor(reader, &format!("{prefix}.attn_q.weight"))?;
-            let attention_wk = ct.tensor(reader, &format!("{prefix}.attn_k.weight"))?;
-            let attention_wv = ct.tensor(reader, &format!("{prefix}.attn_v.weight"))?;
-            let attention_wo = ct.tensor(reader, &format!("{prefix}.attn_output.weight"))?;
-            let feed_forward_w1 = ct.tensor(reader, &format!("{prefix}.ffn_gate.weight"))?;
-            let feed_forward_w2 = ct.tensor(reader, &format!("{prefix}.ffn_down.weight"))?;
-            let feed_forward_w3 = ct.tensor(reader, &format!("{prefix}.ffn_up.weight"))?;
+            let attention_wq = QMatMul::from_gguf(&ct, reader, &format!("{prefix}.attn_q.weight"))?;
+            let attention_wk = QMatMul::from_gguf(&ct, reader, &format!("{prefix}.attn_k.weight"))?;
+            let attention_wv = QMatMul::from_gguf(&ct, reader, &format!("{prefix}.attn_v.weight"))?;
+            let attention_wo =
+                QMatMul::from_gguf(&ct, reader, &format!("{prefix}.attn_output.weight"))?;
+            let feed_forward_w1 =
+                QMatMul::from_gguf(&ct, reader, &format!("{prefix}.ffn_gate.weight"))?;
+            let feed_forward_w2 =
+                QMatMul::from_gguf(&ct, reader, &format!("{prefix}.ffn_down.weight"))?;
+            let feed_forward_w3 =
+                QMatMul::from_gguf(&ct, reader, &format!("{prefix}.ffn_up.weight"))?;
             let attention_norm = ct.tensor(reader, &format!("{prefix}.attn_norm.weight"))?;
             let ffn_norm = ct.tensor(reader, &format!("{prefix}.ffn_norm.weight"))?;
             let span_attn = tracing::span!(tracing::Level::TRACE, "attn");
             let span_rot = tracing::span!(tracing::Level::TRACE, "attn-rot");
             let span_mlp = tracing::span!(tracing::Level::TRACE, "attn-mlp");
             layers.push(LayerWeights {
-                attention_wq: QMatMul::from_qtensor(attention_wq),
-                attention_wk: QMatMul::from_qtensor(attention_wk),
-                attention_wv: QMatMul::from_qtensor(attention_wv),
-                attention_wo: QMatMul::from_qtensor(attention_wo),
+                attention_wq,
+                attention_wk,
+                attention_wv,
+                attention_wo,
                 attention_norm: RmsNorm::new(attention_norm, rms_norm_eps)?,
-                feed_forward_w1: QMatMul::from_qtensor(feed_forward_w1),
-                feed_forward_w2: QMatMul::from_qtensor(feed_forward_w2),
-                feed_forward_w3: QMatMul::from_qtensor(feed_forward_w3),
+                feed_forward_w1,
+                feed_forward_w2,
+                feed_forward_w3,
                 ffn_norm: RmsNorm::new(ffn_norm, rms_norm_eps)?,
                 n_head: head_count,
                 n_kv_head: head_count_kv,
@@ -320,13 +355,32 @@ impl ModelWeights {
             tok_embeddings: Embedding::new(tok_embeddings, embedding_length),
             layers,
             norm,
-            output: QMatMul::from_qtensor(output),
+            output,
             masks: HashMap::new(),
             span,
             span_output,
         })
     }
 
+    /// Records the inputs of all the quantized matmuls in `imatrix` when running the model, the
+    /// entries are named after the weights as they appear in the model file.
+    pub fn record_imatrix(&mut self, imatrix: &Arc<Imatrix>) {
+        for layer in self.layers.iter_mut() {
+            for qmatmul in [
+                &mut layer.attention_wq,
+                &mut layer.attention_wk,
+                &mut layer.attention_wv,
+                &mut layer.attention_wo,
+                &mut layer.feed_forward_w1,
+                &mut layer.feed_forward_w2,
+                &mut layer.feed_forward_w3,
+            ] {
+                qmatmul.record_imatrix(imatrix)
+            }
+        }
+        self.output.record_imatrix(imatrix)
+    }
+
     fn mask(&mut self, t: usize) -> Result<Tensor> {
         if let Some(mask) = self.masks.get(&t) {
             Ok(mask.clone())
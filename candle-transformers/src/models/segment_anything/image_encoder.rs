@@ -75,24 +75,26 @@ impl candle::CustomOp3 for Add3 {
             Some((o1, o2)) => &s3[o1..o2],
         };
         let mut dst = vec![0f32; b * q_h * q_w * k_h * k_w];
-        dst.par_chunks_exact_mut(k_h * k_w)
-            .enumerate()
-            .for_each(|(b_idx, dst)| {
-                let s1_idx = b_idx * k_h * k_w;
-                let s2_idx = b_idx * k_h;
-                let s3_idx = b_idx * k_w;
-                for h_idx in 0..k_h {
-                    let s1_idx = s1_idx + h_idx * k_w;
-                    let s2_idx = s2_idx + h_idx;
-                    let dst_idx = h_idx * k_w;
-                    for w_idx in 0..k_w {
-                        let s1_idx = s1_idx + w_idx;
-                        let s3_idx = s3_idx + w_idx;
-                        let dst_idx = dst_idx + w_idx;
-                        dst[dst_idx] = s1[s1_idx] + s2[s2_idx] + s3[s3_idx]
+        candle::cpu_pool::install(|| {
+            dst.par_chunks_exact_mut(k_h * k_w)
+                .enumerate()
+                .for_each(|(b_idx, dst)| {
+                    let s1_idx = b_idx * k_h * k_w;
+                    let s2_idx = b_idx * k_h;
+                    let s3_idx = b_idx * k_w;
+                    for h_idx in 0..k_h {
+                        let s1_idx = s1_idx + h_idx * k_w;
+                        let s2_idx = s2_idx + h_idx;
+                        let dst_idx = h_idx * k_w;
+                        for w_idx in 0..k_w {
+                            let s1_idx = s1_idx + w_idx;
+                            let s3_idx = s3_idx + w_idx;
+                            let dst_idx = dst_idx + w_idx;
+                            dst[dst_idx] = s1[s1_idx] + s2[s2_idx] + s3[s3_idx]
+                        }
                     }
-                }
-            });
+                });
+        });
         let dst = candle::WithDType::to_cpu_storage_owned(dst);
         Ok((dst, (b, q_h * q_w, k_h * k_w).into()))
     }
@@ -6,167 +6,41 @@ pub trait Float: num_traits::Float + num_traits::FloatConst + num_traits::NumAss
 impl Float for f32 {}
 impl Float for f64 {}
 
-// https://github.com/ggerganov/whisper.cpp/blob/4774d2feb01a772a15de81ffc34b34a1f294f020/whisper.cpp#L2357
-fn fft<T: Float>(inp: &[T]) -> Vec<T> {
-    let n = inp.len();
-    let zero = T::zero();
-    if n == 1 {
-        return vec![inp[0], zero];
-    }
-    if n % 2 == 1 {
-        return dft(inp);
-    }
-    let mut out = vec![zero; n * 2];
-
-    let mut even = Vec::with_capacity(n / 2);
-    let mut odd = Vec::with_capacity(n / 2);
-
-    for (i, &inp) in inp.iter().enumerate() {
-        if i % 2 == 0 {
-            even.push(inp)
-        } else {
-            odd.push(inp);
-        }
-    }
-
-    let even_fft = fft(&even);
-    let odd_fft = fft(&odd);
-
-    let two_pi = T::PI() + T::PI();
-    let n_t = T::from(n).unwrap();
-    for k in 0..n / 2 {
-        let k_t = T::from(k).unwrap();
-        let theta = two_pi * k_t / n_t;
-        let re = theta.cos();
-        let im = -theta.sin();
-
-        let re_odd = odd_fft[2 * k];
-        let im_odd = odd_fft[2 * k + 1];
-
-        out[2 * k] = even_fft[2 * k] + re * re_odd - im * im_odd;
-        out[2 * k + 1] = even_fft[2 * k + 1] + re * im_odd + im * re_odd;
-
-        out[2 * (k + n / 2)] = even_fft[2 * k] - re * re_odd + im * im_odd;
-        out[2 * (k + n / 2) + 1] = even_fft[2 * k + 1] - re * im_odd - im * re_odd;
-    }
-    out
+/// Computes the log-mel spectrogram of `samples` on the cpu, see `log_mel_spectrogram`. The
+/// result is laid out as `N_MELS` rows of `n_frames` values.
+pub fn pcm_to_mel<T: Float>(samples: &[T], filters: &[T]) -> candle::Result<Vec<T>> {
+    use candle::{Device, Tensor};
+    let to_f64 = |vs: &[T]| vs.iter().map(|v| v.to_f64().unwrap()).collect::<Vec<_>>();
+    let samples = Tensor::new(to_f64(samples), &Device::Cpu)?;
+    let filters = Tensor::new(to_f64(filters), &Device::Cpu)?;
+    let mel = log_mel_spectrogram(&samples, &filters)?
+        .flatten_all()?
+        .to_vec1::<f64>()?;
+    Ok(mel.into_iter().map(|v| T::from(v).unwrap()).collect())
 }
 
-// https://github.com/ggerganov/whisper.cpp/blob/4774d2feb01a772a15de81ffc34b34a1f294f020/whisper.cpp#L2337
-fn dft<T: Float>(inp: &[T]) -> Vec<T> {
-    let zero = T::zero();
-    let n = inp.len();
-    let two_pi = T::PI() + T::PI();
-
-    let mut out = Vec::new();
-    out.reserve(2 * n);
-    let n_t = T::from(n).unwrap();
-    for k in 0..n {
-        let k_t = T::from(k).unwrap();
-        let mut re = zero;
-        let mut im = zero;
-
-        for (j, &inp) in inp.iter().enumerate() {
-            let j_t = T::from(j).unwrap();
-            let angle = two_pi * k_t * j_t / n_t;
-            re += inp * angle.cos();
-            im -= inp * angle.sin();
-        }
-
-        out.push(re);
-        out.push(im);
-    }
-    out
-}
-
-#[allow(clippy::too_many_arguments)]
-// https://github.com/ggerganov/whisper.cpp/blob/4774d2feb01a772a15de81ffc34b34a1f294f020/whisper.cpp#L2414
-fn log_mel_spectrogram_w<T: Float>(
-    ith: usize,
-    hann: &[T],
-    samples: &[T],
-    filters: &[T],
-    fft_size: usize,
-    fft_step: usize,
-    speed_up: bool,
-    n_len: usize,
-    n_mel: usize,
-    n_threads: usize,
-) -> Vec<T> {
-    let n_fft = if speed_up {
-        1 + fft_size / 4
-    } else {
-        1 + fft_size / 2
-    };
-
-    let zero = T::zero();
-    let half = T::from(0.5).unwrap();
-    let mut fft_in = vec![zero; fft_size];
-    let mut mel = vec![zero; n_len * n_mel];
-
-    for i in (ith..n_len).step_by(n_threads) {
-        let offset = i * fft_step;
-
-        // apply Hanning window
-        for j in 0..fft_size {
-            fft_in[j] = if offset + j < samples.len() {
-                hann[j] * samples[offset + j]
-            } else {
-                zero
-            }
-        }
-
-        // FFT -> mag^2
-        let mut fft_out: Vec<T> = fft(&fft_in);
-
-        for j in 0..fft_size {
-            fft_out[j] = fft_out[2 * j] * fft_out[2 * j] + fft_out[2 * j + 1] * fft_out[2 * j + 1];
-        }
-        for j in 1..fft_size / 2 {
-            let v = fft_out[fft_size - j];
-            fft_out[j] += v;
-        }
-
-        if speed_up {
-            // scale down in the frequency domain results in a speed up in the time domain
-            for j in 0..n_fft {
-                fft_out[j] = half * (fft_out[2 * j] + fft_out[2 * j + 1]);
-            }
-        }
-
-        // mel spectrogram
-        for j in 0..n_mel {
-            let mut sum = zero;
-            for k in 0..n_fft {
-                sum += fft_out[k] * filters[j * n_fft + k];
-            }
-            mel[j * n_len + i] = T::max(sum, T::from(1e-10).unwrap()).log10();
-        }
-    }
-    mel
-}
-
-fn log_mel_spectrogram_<T: Float + std::fmt::Display>(
-    samples: &[T],
-    filters: &[T],
-    fft_size: usize,
-    fft_step: usize,
-    n_mel: usize,
-    speed_up: bool,
-) -> Vec<T> {
-    let zero = T::zero();
-    let two_pi = T::PI() + T::PI();
-    let half = T::from(0.5).unwrap();
-    let one = T::from(1.0).unwrap();
-    let four = T::from(4.0).unwrap();
-    let fft_size_t = T::from(fft_size).unwrap();
-
-    let hann: Vec<T> = (0..fft_size)
-        .map(|i| half * (one - ((two_pi * T::from(i).unwrap()) / fft_size_t).cos()))
+/// Computes the log-mel spectrogram of `samples` as a tensor of shape `(N_MELS, n_frames)`, this
+/// follows whisper.cpp and supports backpropagation. The result is on the device of `samples`,
+/// however as complex tensors are only available on the cpu, the short-time fourier transform is
+/// always computed there and only its real power spectrum is moved to the device.
+/// `samples` is a one dimensional tensor and `filters` holds the `N_MELS` mel filters.
+pub fn log_mel_spectrogram(
+    samples: &candle::Tensor,
+    filters: &candle::Tensor,
+) -> candle::Result<candle::Tensor> {
+    use candle::{Tensor, D};
+    let (n_fft, hop_length) = (super::N_FFT, super::HOP_LENGTH);
+    let dtype = samples.dtype();
+    let device = samples.device();
+    let hann: Vec<f64> = (0..n_fft)
+        .map(|i| 0.5 * (1. - (2. * std::f64::consts::PI * i as f64 / n_fft as f64).cos()))
         .collect();
-    let n_len = samples.len() / fft_step;
+    let cpu = &candle::Device::Cpu;
+    let hann = Tensor::new(hann.as_slice(), cpu)?.to_dtype(dtype)?;
 
     // pad audio with at least one extra chunk of zeros
+    let n_samples = samples.dim(0)?;
+    let n_len = n_samples / hop_length;
     let pad = 100 * super::CHUNK_LENGTH / 2;
     let n_len = if n_len % pad != 0 {
         (n_len / pad + 1) * pad
@@ -174,37 +48,30 @@ fn log_mel_spectrogram_<T: Float + std::fmt::Display>(
         n_len
     };
     let n_len = n_len + pad;
-    let samples = {
-        let mut samples_padded = samples.to_vec();
-        let to_add = n_len * fft_step - samples.len();
-        samples_padded.extend(std::iter::repeat(zero).take(to_add));
-        samples_padded
-    };
-
-    // Use a single thread for now.
-    let mut mel = log_mel_spectrogram_w(
-        0, &hann, &samples, filters, fft_size, fft_step, speed_up, n_len, n_mel, 1,
-    );
-    let mmax = mel
-        .iter()
-        .max_by(|&u, &v| u.partial_cmp(v).unwrap_or(std::cmp::Ordering::Greater))
-        .copied()
-        .unwrap_or(zero)
-        - T::from(8).unwrap();
-    for m in mel.iter_mut() {
-        let v = T::max(*m, mmax);
-        *m = v / four + one
-    }
-    mel
-}
-
-pub fn pcm_to_mel<T: Float + std::fmt::Display>(samples: &[T], filters: &[T]) -> Vec<T> {
-    log_mel_spectrogram_(
-        samples,
-        filters,
-        super::N_FFT,
-        super::HOP_LENGTH,
-        super::N_MELS,
-        false,
-    )
+    let padding = Tensor::zeros((n_len - 1) * hop_length + n_fft - n_samples, dtype, cpu)?;
+    let samples = Tensor::cat(&[&samples.to_device(cpu)?, &padding], 0)?;
+
+    let power = samples
+        .stft(n_fft, hop_length, Some(&hann))?
+        .view_as_real()?
+        .sqr()?
+        .sum(D::Minus1)?
+        .to_device(device)?;
+    // Similar to whisper.cpp, the magnitudes of the negative frequencies are folded back on the
+    // positive ones, except for the last frequency.
+    let n_freqs = power.dim(0)?;
+    let weights: Vec<f64> = (0..n_freqs)
+        .map(|j| if j == 0 || j == n_freqs - 1 { 1. } else { 2. })
+        .collect();
+    let weights = Tensor::new(weights.as_slice(), device)?
+        .to_dtype(power.dtype())?
+        .unsqueeze(1)?;
+    let power = power.broadcast_mul(&weights)?;
+    let filters = filters
+        .reshape((super::N_MELS, n_freqs))?
+        .to_dtype(power.dtype())?;
+    let mel = (filters.matmul(&power)?.maximum(1e-10)?.log()? / std::f64::consts::LN_10)?;
+    let mmax = (mel.flatten_all()?.max(0)? - 8.)?;
+    let mel = ((mel.broadcast_maximum(&mmax)? / 4.)? + 1.)?;
+    mel.to_dtype(dtype)
 }
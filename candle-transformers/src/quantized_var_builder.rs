@@ -0,0 +1,109 @@
+//! A var-builder for quantized models, the weights are retrieved as [`QTensor`] so that they can
+//! be used with [`candle::quantized::QMatMul`] without being dequantized.
+use candle::quantized::gptq::{PackedWeight, QuantMethod};
+use candle::quantized::{gguf_file, QTensor};
+use candle::{Device, Result, Shape};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct VarBuilder {
+    data: Arc<HashMap<String, Arc<QTensor>>>,
+    path: Vec<String>,
+}
+
+impl VarBuilder {
+    /// Loads all the tensors of a gguf file.
+    pub fn from_gguf<P: AsRef<std::path::Path>>(p: P) -> Result<Self> {
+        let mut file = std::fs::File::open(p)?;
+        let content = gguf_file::Content::read(&mut file)?;
+        let mut data = HashMap::new();
+        for tensor_name in content.tensor_infos.keys() {
+            let tensor = content.tensor(&mut file, tensor_name)?;
+            data.insert(tensor_name.to_string(), Arc::new(tensor));
+        }
+        Ok(Self::from_data(data))
+    }
+
+    /// Loads the tensors of a GPTQ or AWQ checkpoint stored in a collection of safetensors files.
+    /// The packed `qweight`, `qzeros`, `scales` and `g_idx` tensors of each quantized linear layer
+    /// are converted with [`PackedWeight::to_qtensor`] and exposed as `prefix.weight`, the other
+    /// tensors are stored as `f32`.
+    pub fn from_quantized_safetensors<P: AsRef<std::path::Path>>(
+        paths: &[P],
+        method: QuantMethod,
+    ) -> Result<Self> {
+        let mut tensors = HashMap::new();
+        for path in paths.iter() {
+            tensors.extend(candle::safetensors::load(path, &Device::Cpu)?)
+        }
+        let prefixes = tensors
+            .keys()
+            .filter_map(|name| name.strip_suffix(".qweight"))
+            .map(|prefix| prefix.to_string())
+            .collect::<Vec<_>>();
+        let mut data = HashMap::new();
+        for prefix in prefixes.iter() {
+            let qtensor = PackedWeight::from_tensors(&tensors, prefix)?.to_qtensor(method)?;
+            data.insert(format!("{prefix}.weight"), Arc::new(qtensor));
+            for name in ["qweight", "qzeros", "scales", "g_idx"] {
+                tensors.remove(&format!("{prefix}.{name}"));
+            }
+        }
+        for (name, tensor) in tensors.into_iter() {
+            let qtensor = QTensor::quantize::<f32>(&tensor)?;
+            data.insert(name, Arc::new(qtensor));
+        }
+        Ok(Self::from_data(data))
+    }
+
+    fn from_data(data: HashMap<String, Arc<QTensor>>) -> Self {
+        Self {
+            data: Arc::new(data),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn pp<S: ToString>(&self, s: S) -> Self {
+        self.push_prefix(s)
+    }
+
+    pub fn push_prefix<S: ToString>(&self, s: S) -> Self {
+        let mut path = self.path.clone();
+        path.push(s.to_string());
+        Self {
+            data: self.data.clone(),
+            path,
+        }
+    }
+
+    fn path(&self, tensor_name: &str) -> String {
+        if self.path.is_empty() {
+            tensor_name.to_string()
+        } else {
+            [&self.path.join("."), tensor_name].join(".")
+        }
+    }
+
+    /// Retrieves the tensor `name` under the current prefix and checks that it has shape `s`.
+    pub fn get<S: Into<Shape>>(&self, s: S, name: &str) -> Result<Arc<QTensor>> {
+        let path = self.path(name);
+        match self.data.get(&path) {
+            None => candle::bail!("cannot find tensor {path}"),
+            Some(qtensor) => {
+                let shape = s.into();
+                if qtensor.shape() != &shape {
+                    candle::bail!(
+                        "shape mismatch for {path}, got {:?}, expected {shape:?}",
+                        qtensor.shape()
+                    )
+                }
+                Ok(qtensor.clone())
+            }
+        }
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.data.contains_key(&self.path(name))
+    }
+}
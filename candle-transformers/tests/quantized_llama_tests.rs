@@ -0,0 +1,70 @@
+use candle::quantized::gguf_file::{self, GgufWriter, Value};
+use candle::quantized::{imatrix::Imatrix, GgmlDType, QTensor};
+use candle::{Device, Result, Tensor};
+use candle_transformers::models::quantized_llama::ModelWeights;
+use std::sync::Arc;
+
+// A tiny llama model with random f32 weights, written as a gguf file.
+fn tiny_llama() -> Result<std::io::Cursor<Vec<u8>>> {
+    let (vocab, n_embd, n_ff) = (10, 32, 64);
+    let mut writer = GgufWriter::new(std::io::Cursor::new(vec![]));
+    for (key, value) in [
+        ("llama.attention.head_count", 2),
+        ("llama.attention.head_count_kv", 2),
+        ("llama.block_count", 1),
+        ("llama.embedding_length", n_embd as u32),
+        ("llama.rope.dimension_count", 16),
+    ] {
+        writer.add_metadata(key, Value::U32(value))
+    }
+    writer.add_metadata("llama.attention.layer_norm_rms_epsilon", Value::F32(1e-5));
+    let shapes = [
+        ("token_embd.weight", (vocab, n_embd)),
+        ("output_norm.weight", (1, n_embd)),
+        ("output.weight", (vocab, n_embd)),
+        ("blk.0.attn_q.weight", (n_embd, n_embd)),
+        ("blk.0.attn_k.weight", (n_embd, n_embd)),
+        ("blk.0.attn_v.weight", (n_embd, n_embd)),
+        ("blk.0.attn_output.weight", (n_embd, n_embd)),
+        ("blk.0.ffn_gate.weight", (n_ff, n_embd)),
+        ("blk.0.ffn_down.weight", (n_embd, n_ff)),
+        ("blk.0.ffn_up.weight", (n_ff, n_embd)),
+        ("blk.0.attn_norm.weight", (1, n_embd)),
+        ("blk.0.ffn_norm.weight", (1, n_embd)),
+    ];
+    let mut tensors = vec![];
+    for (name, (d0, d1)) in shapes {
+        let t = if name.ends_with("norm.weight") {
+            Tensor::ones(d1, candle::DType::F32, &Device::Cpu)?
+        } else {
+            (Tensor::randn(0f32, 1., (d0, d1), &Device::Cpu)? * 0.1)?
+        };
+        writer.add_tensor_info(name, GgmlDType::F32, t.shape().clone())?;
+        tensors.push(QTensor::quantize::<f32>(&t));
+    }
+    let mut file = writer.write_tensors(tensors)?;
+    file.set_position(0);
+    Ok(file)
+}
+
+#[test]
+fn record_imatrix() -> Result<()> {
+    let mut file = tiny_llama()?;
+    let content = gguf_file::Content::read(&mut file)?;
+    let mut model = ModelWeights::from_gguf(content, &mut file)?;
+    let imatrix = Arc::new(Imatrix::new());
+    model.record_imatrix(&imatrix);
+    let tokens = Tensor::new(&[[1u32, 4, 2, 7, 3]], &Device::Cpu)?;
+    let logits = model.forward(&tokens, 0)?;
+    assert_eq!(logits.dims(), [1, 10]);
+    model.forward(&tokens.narrow(1, 0, 2)?, 0)?;
+
+    let names = imatrix.names();
+    assert_eq!(names.len(), 8);
+    assert!(names.contains(&"blk.0.attn_q.weight".to_string()));
+    assert_eq!(imatrix.count("blk.0.attn_q.weight"), 7);
+    assert_eq!(imatrix.get("blk.0.ffn_down.weight").unwrap().len(), 64);
+    // Only the last position goes through the output layer.
+    assert_eq!(imatrix.count("output.weight"), 2);
+    Ok(())
+}
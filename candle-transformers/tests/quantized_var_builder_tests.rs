@@ -0,0 +1,61 @@
+use candle::quantized::{gptq::QuantMethod, GgmlDType, QMatMul};
+use candle::{DType, Device, Result, Tensor};
+use candle_transformers::quantized_var_builder::VarBuilder;
+use std::collections::HashMap;
+
+#[test]
+fn gptq_safetensors() -> Result<()> {
+    let cpu = &Device::Cpu;
+    let (out_dim, in_dim) = (8, 64);
+    // A GPTQ layer with a single group, w = 0.5 * (q - 8) with the zero point stored as 7.
+    let q = |r: usize, c: usize| ((r + 3 * c) % 16) as u32;
+    let qweight = (0..in_dim / 8)
+        .map(|i| {
+            (0..out_dim)
+                .map(|r| (0..8).fold(0u32, |a, j| a | (q(r, i * 8 + j) << (4 * j))) as i32)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let qzeros = vec![vec![0x77777777i32]];
+    let w = (0..out_dim)
+        .flat_map(|r| (0..in_dim).map(move |c| 0.5 * (q(r, c) as f32 - 8.)))
+        .collect::<Vec<_>>();
+    let w = Tensor::from_vec(w, (out_dim, in_dim), cpu)?;
+    let bias = Tensor::arange(0f32, out_dim as f32, cpu)?;
+
+    let tensors = HashMap::from([
+        ("proj.qweight".to_string(), Tensor::new(qweight, cpu)?),
+        ("proj.qzeros".to_string(), Tensor::new(qzeros, cpu)?),
+        (
+            "proj.scales".to_string(),
+            (Tensor::ones((1, out_dim), DType::F16, cpu)? * 0.5)?,
+        ),
+        ("proj.bias".to_string(), bias.clone()),
+    ]);
+    let path = std::env::temp_dir().join(format!("candle-qvb-{}.st", std::process::id()));
+    candle::safetensors::save(&tensors, &path)?;
+    let vb = VarBuilder::from_quantized_safetensors(&[&path], QuantMethod::Gptq);
+    std::fs::remove_file(&path)?;
+    let vb = vb?.pp("proj");
+
+    assert!(vb.contains_key("weight"));
+    assert!(!vb.contains_key("qweight"));
+    let weight = vb.get((out_dim, in_dim), "weight")?;
+    assert_eq!(weight.dtype(), GgmlDType::Q4_1);
+    assert!(vb.get((in_dim, out_dim), "weight").is_err());
+    let diff = (weight.dequantize(cpu)? - &w)?
+        .abs()?
+        .flatten_all()?
+        .max(0)?;
+    assert!(diff.to_scalar::<f32>()? < 1e-3);
+    let loaded_bias = vb.get(out_dim, "bias")?.dequantize(cpu)?;
+    assert_eq!(loaded_bias.to_vec1::<f32>()?, bias.to_vec1::<f32>()?);
+
+    let xs = Tensor::randn(0f32, 1., (2, in_dim), cpu)?;
+    let ys = QMatMul::from_arc(weight).forward(&xs)?;
+    let expected = xs.matmul(&w.t()?)?;
+    let diff = (ys - &expected)?.abs()?.flatten_all()?.max(0)?;
+    let scale = expected.abs()?.flatten_all()?.max(0)?;
+    assert!(diff.to_scalar::<f32>()? < 0.05 * scale.to_scalar::<f32>()?);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use candle::{test_device, Device, Result, Tensor};
+use candle_transformers::models::whisper::{self as m, audio};
+
+// Reference implementation using a naive dft, frames past the end of the samples only contain
+// padding and have a zero power spectrum.
+fn naive_log_mel(samples: &[f32], filters: &[f32], n_frames: usize) -> Vec<f32> {
+    let (n_fft, hop) = (m::N_FFT, m::HOP_LENGTH);
+    let n_freqs = n_fft / 2 + 1;
+    let two_pi = 2. * std::f64::consts::PI;
+    let mut mel = vec![-10f64; m::N_MELS * n_frames];
+    for i in 0..n_frames {
+        let offset = i * hop;
+        if offset >= samples.len() {
+            break;
+        }
+        let frame: Vec<f64> = (0..n_fft)
+            .map(|j| {
+                let hann = 0.5 * (1. - (two_pi * j as f64 / n_fft as f64).cos());
+                hann * samples.get(offset + j).map_or(0., |&v| v as f64)
+            })
+            .collect();
+        let power: Vec<f64> = (0..n_freqs)
+            .map(|k| {
+                let (mut re, mut im) = (0., 0.);
+                for (j, v) in frame.iter().enumerate() {
+                    let angle = two_pi * (k * j) as f64 / n_fft as f64;
+                    re += v * angle.cos();
+                    im -= v * angle.sin();
+                }
+                let w = if k == 0 || k == n_freqs - 1 { 1. } else { 2. };
+                w * (re * re + im * im)
+            })
+            .collect();
+        for j in 0..m::N_MELS {
+            let sum: f64 = (0..n_freqs)
+                .map(|k| power[k] * filters[j * n_freqs + k] as f64)
+                .sum();
+            mel[j * n_frames + i] = sum.max(1e-10).log10();
+        }
+    }
+    let mmax = mel.iter().copied().fold(f64::MIN, f64::max) - 8.;
+    mel.iter()
+        .map(|&v| (v.max(mmax) / 4. + 1.) as f32)
+        .collect()
+}
+
+fn log_mel_spectrogram(device: &Device) -> Result<()> {
+    let n_freqs = m::N_FFT / 2 + 1;
+    let filters = (0..m::N_MELS * n_freqs)
+        .map(|i| ((i * 7) % 13) as f32 / 100.)
+        .collect::<Vec<_>>();
+    let samples = (0..4000)
+        .map(|i| (i as f32 * 0.05).sin() * 0.5 + (i as f32 * 0.013).cos() * 0.1)
+        .collect::<Vec<_>>();
+
+    let mel = audio::log_mel_spectrogram(
+        &Tensor::new(samples.as_slice(), device)?,
+        &Tensor::new(filters.as_slice(), device)?,
+    )?;
+    assert!(mel.device().same_device(device));
+    // 25 frames of samples padded to a chunk, plus an extra chunk of zeros.
+    let n_frames = 3000;
+    assert_eq!(mel.dims(), [m::N_MELS, n_frames]);
+    let mel = mel.flatten_all()?.to_vec1::<f32>()?;
+    let expected = naive_log_mel(&samples, &filters, n_frames);
+    let max_diff = mel
+        .iter()
+        .zip(expected.iter())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0f32, f32::max);
+    assert!(max_diff < 1e-3, "{max_diff}");
+
+    let pcm_mel = audio::pcm_to_mel(&samples, &filters)?;
+    assert_eq!(pcm_mel.len(), expected.len());
+    let max_diff = pcm_mel
+        .iter()
+        .zip(expected.iter())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0f32, f32::max);
+    assert!(max_diff < 1e-3, "{max_diff}");
+    Ok(())
+}
+
+test_device!(
+    log_mel_spectrogram,
+    log_mel_spectrogram_cpu,
+    log_mel_spectrogram_gpu
+);